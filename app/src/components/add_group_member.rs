@@ -1,6 +1,6 @@
-use crate::{
-    components::select::{Select, SelectOption, SelectOptionProps},
-    infra::common_component::{CommonComponent, CommonComponentParts},
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    i18n::t,
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
@@ -28,19 +28,62 @@ pub struct AddUserToGroup;
 pub struct ListUserNames;
 pub type User = list_user_names::ListUserNamesUsers;
 
+use list_user_names::{EqualityConstraint, RequestFilter};
+
+/// The maximum number of users proposed for a search.
+const MAX_SEARCH_RESULTS: usize = 10;
+
+/// Matches the users whose ID, email or display name contains the search text.
+fn make_search_filter(search: &str) -> RequestFilter {
+    let contains = |field: &str| RequestFilter {
+        any: None,
+        all: None,
+        not: None,
+        eq: None,
+        not_equals: None,
+        contains: Some(EqualityConstraint {
+            field: field.to_owned(),
+            value: search.to_owned(),
+        }),
+        starts_with: None,
+        member_of: None,
+        member_of_id: None,
+        modified_since: None,
+    };
+    RequestFilter {
+        any: Some(
+            ["id", "email", "display_name"]
+                .iter()
+                .map(|field| contains(field))
+                .collect(),
+        ),
+        all: None,
+        not: None,
+        eq: None,
+        not_equals: None,
+        contains: None,
+        starts_with: None,
+        member_of: None,
+        member_of_id: None,
+        modified_since: None,
+    }
+}
+
+/// A search box proposing the users matching the typed text, to add them to the group.
 pub struct AddGroupMemberComponent {
     common: CommonComponentParts<Self>,
-    /// The list of existing users, initially not loaded.
-    user_list: Option<Vec<User>>,
-    /// The currently selected user.
+    search: String,
+    /// The users matching the search, if any search was typed.
+    search_results: Option<Vec<User>>,
+    /// The user being added.
     selected_user: Option<User>,
 }
 
 pub enum Msg {
-    UserListResponse(Result<list_user_names::ResponseData>),
-    SubmitAddMember,
+    SearchUpdate(String),
+    SearchResponse(Result<list_user_names::ResponseData>),
+    SubmitAddMember(User),
     AddMemberResponse(Result<add_user_to_group::ResponseData>),
-    SelectionChanged(Option<SelectOptionProps>),
 }
 
 #[derive(yew::Properties, Clone, PartialEq)]
@@ -54,30 +97,34 @@ pub struct Props {
 impl CommonComponent<AddGroupMemberComponent> for AddGroupMemberComponent {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
-            Msg::UserListResponse(response) => {
-                self.user_list = Some(response?.users);
+            Msg::SearchUpdate(search) => {
+                self.search = search;
+                self.search_users();
+            }
+            Msg::SearchResponse(response) => {
                 self.common.cancel_task();
+                self.search_results = Some(response?.users);
+            }
+            Msg::SubmitAddMember(user) => {
+                self.common.call_graphql::<AddUserToGroup, _>(
+                    add_user_to_group::Variables {
+                        user: user.id.clone(),
+                        group: self.common.group_id,
+                    },
+                    Msg::AddMemberResponse,
+                    "Error trying to initiate adding the user to a group",
+                );
+                self.selected_user = Some(user);
             }
-            Msg::SubmitAddMember => return self.submit_add_member(),
             Msg::AddMemberResponse(response) => {
-                response?;
                 self.common.cancel_task();
+                response?;
                 let user = self
                     .selected_user
-                    .as_ref()
-                    .expect("Could not get selected user")
-                    .clone();
-                // Remove the user from the dropdown.
+                    .take()
+                    .expect("Could not get selected user");
                 self.common.on_user_added_to_group.emit(user);
             }
-            Msg::SelectionChanged(option_props) => {
-                let was_some = self.selected_user.is_some();
-                self.selected_user = option_props.map(|u| User {
-                    id: u.value,
-                    display_name: u.text,
-                });
-                return Ok(self.selected_user.is_some() != was_some);
-            }
         }
         Ok(true)
     }
@@ -88,35 +135,29 @@ impl CommonComponent<AddGroupMemberComponent> for AddGroupMemberComponent {
 }
 
 impl AddGroupMemberComponent {
-    fn get_user_list(&mut self) {
+    fn search_users(&mut self) {
+        let search = self.search.trim();
+        if search.is_empty() {
+            self.common.cancel_task();
+            self.search_results = None;
+            return;
+        }
         self.common.call_graphql::<ListUserNames, _>(
-            list_user_names::Variables { filters: None },
-            Msg::UserListResponse,
-            "Error trying to fetch user list",
-        );
-    }
-
-    fn submit_add_member(&mut self) -> Result<bool> {
-        let user_id = match self.selected_user.clone() {
-            None => return Ok(false),
-            Some(user) => user.id,
-        };
-        self.common.call_graphql::<AddUserToGroup, _>(
-            add_user_to_group::Variables {
-                user: user_id,
-                group: self.common.group_id,
+            list_user_names::Variables {
+                filters: Some(make_search_filter(search)),
             },
-            Msg::AddMemberResponse,
-            "Error trying to initiate adding the user to a group",
+            Msg::SearchResponse,
+            "Error trying to search the users",
         );
-        Ok(true)
     }
 
+    /// The matching users that are not yet members of the group.
     fn get_selectable_user_list(&self, user_list: &[User]) -> Vec<User> {
-        let user_groups = self.common.users.iter().collect::<HashSet<_>>();
+        let members = self.common.users.iter().collect::<HashSet<_>>();
         user_list
             .iter()
-            .filter(|u| !user_groups.contains(u))
+            .filter(|u| !members.contains(u))
+            .take(MAX_SEARCH_RESULTS)
             .map(Clone::clone)
             .collect()
     }
@@ -127,13 +168,12 @@ impl Component for AddGroupMemberComponent {
     type Properties = Props;
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        let mut res = Self {
+        Self {
             common: CommonComponentParts::<Self>::create(props, link),
-            user_list: None,
+            search: String::new(),
+            search_results: None,
             selected_user: None,
-        };
-        res.get_user_list();
-        res
+        }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
@@ -149,41 +189,55 @@ impl Component for AddGroupMemberComponent {
     }
 
     fn view(&self) -> Html {
-        if let Some(user_list) = &self.user_list {
-            let to_add_user_list = self.get_selectable_user_list(user_list);
-            #[allow(unused_braces)]
-            let make_select_option = |user: User| {
-                html_nested! {
-                    <SelectOption value=user.id.clone() text=user.display_name.clone() key=user.id />
-                }
-            };
-            html! {
-            <div class="row">
-              <div class="col-sm-3">
-                <Select on_selection_change=self.common.callback(Msg::SelectionChanged)>
-                  {
-                    to_add_user_list
-                        .into_iter()
-                        .map(make_select_option)
-                        .collect::<Vec<_>>()
-                  }
-                </Select>
-              </div>
-              <div class="col-3">
-                <button
-                  class="btn btn-secondary"
-                  disabled=self.selected_user.is_none() || self.common.is_task_running()
-                  onclick=self.common.callback(|_| Msg::SubmitAddMember)>
-                   <i class="bi-person-plus me-2"></i>
-                  {"Add to group"}
-                </button>
+        html! {
+          <div class="row">
+            <div class="col-sm-6">
+              <div class="input-group">
+                <span class="input-group-text">
+                  <i class="bi-person-plus"/>
+                </span>
+                <input
+                  type="search"
+                  class="form-control"
+                  placeholder=t("Search a user to add to the group")
+                  value=self.search.clone()
+                  oninput=self.common.callback(|e: InputData| Msg::SearchUpdate(e.value)) />
               </div>
+              {self.view_search_results()}
             </div>
-            }
-        } else {
+          </div>
+        }
+    }
+}
+
+impl AddGroupMemberComponent {
+    fn view_search_results(&self) -> Html {
+        let user_list = match &self.search_results {
+            None => return html! {},
+            Some(user_list) => self.get_selectable_user_list(user_list),
+        };
+        if user_list.is_empty() {
+            return html! {
+              <div class="form-text">{t("No matching user outside of the group.")}</div>
+            };
+        }
+        let make_result = |user: User| {
+            let label = format!("{} ({})", user.display_name, user.id);
             html! {
-              {"Loading groups"}
+              <button
+                type="button"
+                class="list-group-item list-group-item-action"
+                key=user.id.clone()
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(move |_| Msg::SubmitAddMember(user.clone()))>
+                {label}
+              </button>
             }
+        };
+        html! {
+          <div class="list-group mt-1">
+            {user_list.into_iter().map(make_result).collect::<Vec<_>>()}
+          </div>
         }
     }
 }
@@ -3,7 +3,10 @@ use crate::{
         select::{Select, SelectOption, SelectOptionProps},
         user_details::Group,
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
@@ -188,14 +191,14 @@ impl Component for AddUserToGroupComponent {
                   disabled=self.selected_group.is_none() || self.common.is_task_running()
                   onclick=self.common.callback(|_| Msg::SubmitAddGroup)>
                   <i class="bi-person-plus me-2"></i>
-                  {"Add to group"}
+                  {t("Add to group")}
                 </button>
               </div>
             </div>
             }
         } else {
             html! {
-              {"Loading groups"}
+              {t("Loading groups")}
             }
         }
     }
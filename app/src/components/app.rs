@@ -1,5 +1,7 @@
 use crate::{
     components::{
+        attribute_schema::AttributeSchemaPage,
+        audit_log::AuditLog,
         change_password::ChangePasswordForm,
         create_group::CreateGroupForm,
         create_user::CreateUserForm,
@@ -7,16 +9,23 @@ use crate::{
         group_table::GroupTable,
         login::LoginForm,
         logout::LogoutButton,
+        profile::Profile,
         reset_password_step1::ResetPasswordStep1Form,
         reset_password_step2::ResetPasswordStep2Form,
         router::{AppRoute, Link, NavButton},
         user_details::UserDetails,
         user_table::UserTable,
     },
-    infra::cookies::get_cookie,
+    infra::{
+        api::HostService,
+        cookies::get_cookie,
+        i18n::{current_language, set_language, t, t_format, Language},
+    },
 };
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
 use yew::prelude::*;
-use yew::services::ConsoleService;
+use yew::services::{fetch::FetchTask, ConsoleService};
 use yew_router::{
     agent::{RouteAgentDispatcher, RouteRequest},
     route::Route,
@@ -24,16 +33,27 @@ use yew_router::{
     service::RouteService,
 };
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_locale.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserLocale;
+
 pub struct App {
     link: ComponentLink<Self>,
     user_info: Option<(String, bool)>,
     redirect_to: Option<AppRoute>,
     route_dispatcher: RouteAgentDispatcher,
+    locale_task: Option<FetchTask>,
 }
 
 pub enum Msg {
     Login((String, bool)),
     Logout,
+    UserLocaleResponse(Result<get_user_locale::ResponseData>),
 }
 
 impl Component for App {
@@ -58,8 +78,10 @@ impl Component for App {
                 }),
             redirect_to: Self::get_redirect_route(),
             route_dispatcher: RouteAgentDispatcher::new(),
+            locale_task: None,
         };
         app.apply_initial_redirections();
+        app.get_user_locale();
         app
     }
 
@@ -67,13 +89,14 @@ impl Component for App {
         match msg {
             Msg::Login((user_name, is_admin)) => {
                 self.user_info = Some((user_name.clone(), is_admin));
+                self.get_user_locale();
                 self.route_dispatcher
                     .send(RouteRequest::ChangeRoute(Route::from(
                         self.redirect_to.take().unwrap_or_else(|| {
                             if is_admin {
                                 AppRoute::ListUsers
                             } else {
-                                AppRoute::UserDetails(user_name.clone())
+                                AppRoute::Profile
                             }
                         }),
                     )));
@@ -82,6 +105,23 @@ impl Component for App {
                 self.user_info = None;
                 self.redirect_to = None;
             }
+            Msg::UserLocaleResponse(response) => {
+                self.locale_task = None;
+                let language = match response {
+                    Ok(data) => data.user.locale.as_deref().and_then(Language::from_tag),
+                    Err(e) => {
+                        ConsoleService::error(&e.to_string());
+                        None
+                    }
+                };
+                return match language {
+                    Some(language) if language != current_language() => {
+                        set_language(language);
+                        true
+                    }
+                    _ => false,
+                };
+            }
         }
         if self.user_info.is_none() {
             self.route_dispatcher
@@ -102,7 +142,8 @@ impl Component for App {
             {self.view_banner()}
             <div class="container py-3 bg-kug">
               <div class="row justify-content-center" style="padding-bottom: 80px;">
-                <div class="py-3" style="max-width: 1000px">
+                // The key re-creates the pages when the language changes.
+                <div class="py-3" style="max-width: 1000px" key=current_language().tag()>
                   <Router<AppRoute>
                     render = Router::render(move |s| Self::dispatch_route(s, &link, is_admin))
                   />
@@ -116,6 +157,21 @@ impl Component for App {
 }
 
 impl App {
+    /// Switches the interface to the language stored in the user's profile, if any.
+    fn get_user_locale(&mut self) {
+        let user_id = match &self.user_info {
+            None => return,
+            Some((user_id, _)) => user_id.clone(),
+        };
+        self.locale_task = HostService::graphql_query::<GetUserLocale>(
+            get_user_locale::Variables { id: user_id },
+            self.link.callback(Msg::UserLocaleResponse),
+            "Error trying to fetch the user's language",
+        )
+        .map_err(|e| ConsoleService::error(&e.to_string()))
+        .ok();
+    }
+
     fn get_redirect_route() -> Option<AppRoute> {
         let route_service = RouteService::<()>::new();
         let current_route = route_service.get_path();
@@ -123,6 +179,7 @@ impl App {
             || current_route == "/"
             || current_route.contains("login")
             || current_route.contains("reset-password")
+            || current_route.contains("password-expired")
         {
             None
         } else {
@@ -134,7 +191,7 @@ impl App {
     fn apply_initial_redirections(&mut self) {
         let route_service = RouteService::<()>::new();
         let current_route = route_service.get_path();
-        if current_route.contains("reset-password") {
+        if current_route.contains("reset-password") || current_route.contains("password-expired") {
             return;
         }
         match &self.user_info {
@@ -142,7 +199,7 @@ impl App {
                 self.route_dispatcher
                     .send(RouteRequest::ReplaceRoute(Route::from(AppRoute::Login)));
             }
-            Some((user_name, is_admin)) => match &self.redirect_to {
+            Some((_, is_admin)) => match &self.redirect_to {
                 Some(url) => {
                     self.route_dispatcher
                         .send(RouteRequest::ReplaceRoute(Route::from(url.clone())));
@@ -153,9 +210,7 @@ impl App {
                             .send(RouteRequest::ReplaceRoute(Route::from(AppRoute::ListUsers)));
                     } else {
                         self.route_dispatcher
-                            .send(RouteRequest::ReplaceRoute(Route::from(
-                                AppRoute::UserDetails(user_name.clone()),
-                            )));
+                            .send(RouteRequest::ReplaceRoute(Route::from(AppRoute::Profile)));
                     }
                 }
             },
@@ -175,7 +230,7 @@ impl App {
                   <UserTable />
                   <NavButton classes="btn btn-primary" route=AppRoute::CreateUser>
                     <i class="bi-person-plus me-2"></i>
-                    {"Create a user"}
+                    {t("Create a user")}
                   </NavButton>
                 </div>
             },
@@ -187,13 +242,22 @@ impl App {
                   <GroupTable />
                   <NavButton classes="btn btn-primary" route=AppRoute::CreateGroup>
                     <i class="bi-plus-circle me-2"></i>
-                    {"Create a group"}
+                    {t("Create a group")}
                   </NavButton>
                 </div>
             },
             AppRoute::GroupDetails(group_id) => html! {
                 <GroupDetails group_id=group_id />
             },
+            AppRoute::AuditLog => html! {
+                <AuditLog />
+            },
+            AppRoute::AttributeSchema => html! {
+                <AttributeSchemaPage />
+            },
+            AppRoute::Profile => html! {
+                <Profile />
+            },
             AppRoute::UserDetails(username) => html! {
                 <UserDetails username=username is_admin=is_admin />
             },
@@ -206,6 +270,9 @@ impl App {
             AppRoute::FinishResetPassword(token) => html! {
                 <ResetPasswordStep2Form token=token />
             },
+            AppRoute::ChangeExpiredPassword(username) => html! {
+                <ResetPasswordStep2Form username=username />
+            },
         }
     }
 
@@ -226,7 +293,7 @@ impl App {
                           classes="nav-link px-2 link-dark h6"
                           route=AppRoute::ListUsers>
                           <i class="bi-people me-2"></i>
-                          {"Users"}
+                          {t("Users")}
                         </Link>
                       </li>
                       <li>
@@ -234,7 +301,23 @@ impl App {
                           classes="nav-link px-2 link-dark h6"
                           route=AppRoute::ListGroups>
                           <i class="bi-collection me-2"></i>
-                          {"Groups"}
+                          {t("Groups")}
+                        </Link>
+                      </li>
+                      <li>
+                        <Link
+                          classes="nav-link px-2 link-dark h6"
+                          route=AppRoute::AuditLog>
+                          <i class="bi-journal-text me-2"></i>
+                          {t("Audit log")}
+                        </Link>
+                      </li>
+                      <li>
+                        <Link
+                          classes="nav-link px-2 link-dark h6"
+                          route=AppRoute::AttributeSchema>
+                          <i class="bi-list-ul me-2"></i>
+                          {t("Attributes")}
                         </Link>
                       </li>
                     </>
@@ -266,7 +349,7 @@ impl App {
                       } else { html!{} }
                     }
                   </a>
-                  {if let Some((user_id, _)) = &self.user_info { html! {
+                  {if self.user_info.is_some() { html! {
                     <ul
                       class="dropdown-menu text-small dropdown-menu-lg-end"
                       aria-labelledby="dropdownUser1"
@@ -274,8 +357,8 @@ impl App {
                       <li>
                         <Link
                           classes="dropdown-item"
-                          route=AppRoute::UserDetails(user_id.clone())>
-                          {"View details"}
+                          route=AppRoute::Profile>
+                          {t("My profile")}
                         </Link>
                       </li>
                       <li><hr class="dropdown-divider" /></li>
@@ -295,7 +378,7 @@ impl App {
         html! {
           <footer class="text-center text-muted fixed-bottom bg-light py-2">
             <div>
-              <span>{format!("LLDAP version {}", env!("CARGO_PKG_VERSION"))}</span>
+              <span>{t_format("LLDAP version {}", &[&env!("CARGO_PKG_VERSION")])}</span>
             </div>
             <div>
               <a href="https://github.com/nitnelave/lldap" class="me-4 text-reset">
@@ -309,7 +392,7 @@ impl App {
               </a>
             </div>
             <div>
-              <span>{"License "}<a href="https://github.com/nitnelave/lldap/blob/main/LICENSE" class="link-secondary">{"GNU GPL"}</a></span>
+              <span>{t("License ")}<a href="https://github.com/nitnelave/lldap/blob/main/LICENSE" class="link-secondary">{"GNU GPL"}</a></span>
             </div>
           </footer>
         }
@@ -0,0 +1,493 @@
+use crate::{
+    components::{
+        attribute_schema::{
+            get_attributes_schema, get_schema, AttributeKind, AttributeOwnerType, AttributeSchema,
+            GetAttributesSchema,
+        },
+        user_details_form::is_valid_jpeg,
+    },
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use graphql_client::GraphQLQuery;
+use std::collections::{BTreeMap, BTreeSet};
+use yew::{html::ChangeData, prelude::*, services::reader::FileData};
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct UpdateUserAttributes;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct UpdateGroupAttributes;
+
+/// The format of the "datetime-local" inputs. The dates are edited in UTC.
+const DATE_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// The user or group whose custom attributes are edited.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AttributeOwner {
+    User(String),
+    Group(i64),
+}
+
+impl AttributeOwner {
+    fn owner_type(&self) -> AttributeOwnerType {
+        match self {
+            AttributeOwner::User(_) => AttributeOwnerType::User,
+            AttributeOwner::Group(_) => AttributeOwnerType::Group,
+        }
+    }
+}
+
+/// The value(s) of a custom attribute.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AttributeValue {
+    pub name: String,
+    pub value: Vec<String>,
+}
+
+/// Converts a value from the server to the format of its input.
+fn to_input_value(kind: AttributeKind, value: &str) -> String {
+    match kind {
+        AttributeKind::DateTime => DateTime::parse_from_rfc3339(value)
+            .map(|date| {
+                date.with_timezone(&Utc)
+                    .format(DATE_INPUT_FORMAT)
+                    .to_string()
+            })
+            .unwrap_or_else(|_| value.to_owned()),
+        _ => value.to_owned(),
+    }
+}
+
+/// Converts the content of an input to the format expected by the server.
+fn from_input_value(kind: AttributeKind, value: &str) -> Result<String> {
+    match kind {
+        AttributeKind::DateTime => {
+            let date = NaiveDateTime::parse_from_str(value, DATE_INPUT_FORMAT)
+                .map_err(|e| anyhow!("Invalid date '{}': {}", value, e))?;
+            Ok(DateTime::<Utc>::from_utc(date, Utc).to_rfc3339())
+        }
+        AttributeKind::Integer => {
+            value
+                .parse::<i64>()
+                .map_err(|e| anyhow!("Invalid number '{}': {}", value, e))?;
+            Ok(value.to_owned())
+        }
+        _ => Ok(value.to_owned()),
+    }
+}
+
+/// The form editing the custom attributes of a user or group, with an input suited to the type
+/// of each attribute.
+pub struct AttributeEditor {
+    common: CommonComponentParts<Self>,
+    /// The attribute definitions, initially not loaded.
+    schema: Option<Vec<AttributeSchema>>,
+    /// The edited values by attribute name, in the format of the inputs.
+    values: BTreeMap<String, Vec<String>>,
+    /// The attributes with a value on the server, to remove when their inputs are emptied.
+    stored_names: BTreeSet<String>,
+    just_updated: bool,
+}
+
+#[derive(yew::Properties, Clone, PartialEq, Eq)]
+pub struct Props {
+    pub owner: AttributeOwner,
+    /// The current values of the attributes.
+    pub values: Vec<AttributeValue>,
+    /// Only displays the values, e.g. for the users who cannot edit them.
+    #[prop_or_default]
+    pub read_only: bool,
+}
+
+pub enum Msg {
+    SchemaResponse(Result<get_attributes_schema::ResponseData>),
+    /// The input of the value at the given index of the attribute changed.
+    Update(String, usize, String),
+    AddValue(String),
+    RemoveValue(String, usize),
+    FileSelected(String, usize, ChangeData),
+    FileLoaded(String, usize, FileData),
+    Submit,
+    UpdateResponse(Result<()>),
+}
+
+impl CommonComponent<AttributeEditor> for AttributeEditor {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        self.just_updated = false;
+        match msg {
+            Msg::SchemaResponse(response) => {
+                self.common.cancel_task();
+                self.schema = Some(get_schema(response?, self.common.owner.owner_type())?);
+                self.reset_values();
+            }
+            Msg::Update(name, index, value) => {
+                self.set_value(name, index, value);
+                return Ok(false);
+            }
+            Msg::AddValue(name) => self.values.entry(name).or_default().push(String::new()),
+            Msg::RemoveValue(name, index) => {
+                if let Some(values) = self.values.get_mut(&name) {
+                    if index < values.len() {
+                        values.remove(index);
+                    }
+                }
+            }
+            Msg::FileSelected(name, index, data) => {
+                let file = match data {
+                    ChangeData::Files(files) => files.get(0),
+                    _ => None,
+                };
+                if let Some(file) = file {
+                    self.common
+                        .read_file(file, move |data| Msg::FileLoaded(name, index, data))?;
+                }
+            }
+            Msg::FileLoaded(name, index, data) => {
+                self.common.cancel_task();
+                if !is_valid_jpeg(&data.content) {
+                    bail!("Chosen image is not a valid JPEG");
+                }
+                self.set_value(name, index, base64::encode(&data.content));
+            }
+            Msg::Submit => self.submit()?,
+            Msg::UpdateResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.stored_names = self.names_with_values();
+                self.just_updated = true;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl AttributeEditor {
+    fn get_schema(&mut self) {
+        self.common.call_graphql::<GetAttributesSchema, _>(
+            get_attributes_schema::Variables,
+            Msg::SchemaResponse,
+            "Error trying to fetch the attributes schema",
+        );
+    }
+
+    fn kind_of(&self, name: &str) -> Option<AttributeKind> {
+        self.schema
+            .iter()
+            .flatten()
+            .find(|a| a.name == name)
+            .map(|a| a.kind)
+    }
+
+    /// Starts the edition from the values of the properties.
+    fn reset_values(&mut self) {
+        self.values = self
+            .common
+            .values
+            .iter()
+            .map(|a| {
+                let kind = self.kind_of(&a.name).unwrap_or(AttributeKind::String);
+                let values = a.value.iter().map(|v| to_input_value(kind, v)).collect();
+                (a.name.clone(), values)
+            })
+            .collect();
+        self.stored_names = self.names_with_values();
+    }
+
+    fn set_value(&mut self, name: String, index: usize, value: String) {
+        let values = self.values.entry(name).or_default();
+        if values.len() <= index {
+            values.resize(index + 1, String::new());
+        }
+        values[index] = value;
+    }
+
+    /// The non-empty values of the attribute.
+    fn values_of(&self, name: &str) -> Vec<&str> {
+        self.values
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
+
+    fn names_with_values(&self) -> BTreeSet<String> {
+        self.values
+            .keys()
+            .filter(|name| !self.values_of(name).is_empty())
+            .cloned()
+            .collect()
+    }
+
+    fn submit(&mut self) -> Result<()> {
+        let schema = match &self.schema {
+            None => return Ok(()),
+            Some(schema) => schema,
+        };
+        let mut insert = Vec::new();
+        let mut remove = Vec::new();
+        for attribute in schema {
+            let values = self
+                .values_of(&attribute.name)
+                .into_iter()
+                .map(|v| from_input_value(attribute.kind, v))
+                .collect::<Result<Vec<_>>>()?;
+            if !values.is_empty() {
+                insert.push((attribute.name.clone(), values));
+            } else if self.stored_names.contains(&attribute.name) {
+                remove.push(attribute.name.clone());
+            }
+        }
+        match self.common.owner.clone() {
+            AttributeOwner::User(id) => self.common.call_graphql::<UpdateUserAttributes, _>(
+                update_user_attributes::Variables {
+                    id,
+                    insert: Some(
+                        insert
+                            .into_iter()
+                            .map(
+                                |(name, value)| update_user_attributes::AttributeValueInput {
+                                    name,
+                                    value,
+                                },
+                            )
+                            .collect(),
+                    ),
+                    remove: Some(remove),
+                },
+                |r| Msg::UpdateResponse(r.map(|_| ())),
+                "Error trying to update the attributes",
+            ),
+            AttributeOwner::Group(id) => self.common.call_graphql::<UpdateGroupAttributes, _>(
+                update_group_attributes::Variables {
+                    id,
+                    insert: Some(
+                        insert
+                            .into_iter()
+                            .map(
+                                |(name, value)| update_group_attributes::AttributeValueInput {
+                                    name,
+                                    value,
+                                },
+                            )
+                            .collect(),
+                    ),
+                    remove: Some(remove),
+                },
+                |r| Msg::UpdateResponse(r.map(|_| ())),
+                "Error trying to update the attributes",
+            ),
+        }
+        Ok(())
+    }
+}
+
+impl Component for AttributeEditor {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut editor = Self {
+            common: CommonComponentParts::<Self>::create(props, link),
+            schema: None,
+            values: BTreeMap::new(),
+            stored_names: BTreeSet::new(),
+            just_updated: false,
+        };
+        editor.get_schema();
+        editor
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let owner_changed = props.owner != self.common.owner;
+        if !self.common.change(props) {
+            return false;
+        }
+        if owner_changed {
+            self.schema = None;
+            self.get_schema();
+        } else {
+            self.reset_values();
+        }
+        true
+    }
+
+    fn view(&self) -> Html {
+        let schema = match &self.schema {
+            None => return html! {{t("Loading...")}},
+            Some(schema) => schema,
+        };
+        let attributes = schema
+            .iter()
+            .filter(|a| a.is_visible || !self.common.read_only)
+            .collect::<Vec<_>>();
+        if attributes.is_empty() {
+            return html! {};
+        }
+        html! {
+          <>
+            <h5 class="row m-3 fw-bold">{t("Custom attributes")}</h5>
+            <form class="form">
+              {attributes.into_iter().map(|a| self.view_attribute(a)).collect::<Vec<_>>()}
+              {self.view_submit()}
+            </form>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger">
+                    {e.to_string()}
+                  </div>
+                }
+              } else { html! {} }
+            }
+            <div hidden=!self.just_updated>
+              <div class="alert alert-success mt-4">{t("Attributes successfully updated!")}</div>
+            </div>
+          </>
+        }
+    }
+}
+
+impl AttributeEditor {
+    fn view_attribute(&self, attribute: &AttributeSchema) -> Html {
+        let mut values = self
+            .values
+            .get(&attribute.name)
+            .cloned()
+            .unwrap_or_default();
+        if values.is_empty() {
+            values.push(String::new());
+        }
+        let value_count = values.len();
+        let name = attribute.name.clone();
+        html! {
+          <div class="form-group row mb-3" key=attribute.name.clone()>
+            <label class="form-label col-4 col-form-label">
+              {&attribute.name}{": "}
+            </label>
+            <div class="col-8">
+              {values
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| self.view_value(attribute, index, value, value_count))
+                .collect::<Vec<_>>()}
+              {if attribute.is_list && !self.common.read_only { html! {
+                <button
+                  type="button"
+                  class="btn btn-secondary btn-sm"
+                  onclick=self.common.callback(move |_| Msg::AddValue(name.clone()))>
+                  <i class="bi-plus-circle me-2"></i>
+                  {t("Add a value")}
+                </button>
+              } } else { html! {} } }
+            </div>
+          </div>
+        }
+    }
+
+    fn view_value(
+        &self,
+        attribute: &AttributeSchema,
+        index: usize,
+        value: String,
+        value_count: usize,
+    ) -> Html {
+        let read_only = self.common.read_only;
+        let name = attribute.name.clone();
+        let input = match attribute.kind {
+            AttributeKind::JpegPhoto => html! {
+              <>
+                {if value.is_empty() { html! {} } else { html! {
+                  <img
+                    src=format!("data:image/jpeg;base64, {}", value)
+                    style="max-height:128px;max-width:128px;height:auto;width:auto;"
+                    alt=attribute.name.clone() />
+                } } }
+                {if read_only { html! {} } else { html! {
+                  <input
+                    class="form-control"
+                    type="file"
+                    accept="image/jpeg"
+                    onchange=self.common.callback(move |data| Msg::FileSelected(name.clone(), index, data)) />
+                } } }
+              </>
+            },
+            kind => {
+                let input_type = match kind {
+                    AttributeKind::Integer => "number",
+                    AttributeKind::DateTime => "datetime-local",
+                    _ => "text",
+                };
+                html! {
+                  <input
+                    class="form-control"
+                    type=input_type
+                    value=value
+                    disabled=read_only
+                    oninput=self.common.callback(move |e: InputData| Msg::Update(name.clone(), index, e.value)) />
+                }
+            }
+        };
+        let name = attribute.name.clone();
+        html! {
+          <div class="input-group mb-2">
+            {input}
+            {if attribute.kind == AttributeKind::DateTime { html! {
+              <span class="input-group-text">{"UTC"}</span>
+            } } else { html! {} } }
+            {if attribute.is_list && !read_only && value_count > 1 { html! {
+              <button
+                type="button"
+                class="btn btn-danger"
+                onclick=self.common.callback(move |_| Msg::RemoveValue(name.clone(), index))>
+                <i class="bi-x-circle-fill" aria-label=t("Remove the value") />
+              </button>
+            } } else { html! {} } }
+          </div>
+        }
+    }
+
+    fn view_submit(&self) -> Html {
+        if self.common.read_only {
+            return html! {};
+        }
+        html! {
+          <div class="form-group row justify-content-center mt-3">
+            <button
+              type="submit"
+              class="btn btn-primary col-auto col-form-label"
+              disabled=self.common.is_task_running()
+              onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Submit})>
+              <i class="bi-save me-2"></i>
+              {t("Save attributes")}
+            </button>
+          </div>
+        }
+    }
+}
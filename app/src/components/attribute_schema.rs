@@ -0,0 +1,491 @@
+use crate::{
+    components::select::{Select, SelectOption, SelectOptionProps},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
+};
+use anyhow::{bail, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetAttributesSchema;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct AddUserAttribute;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct AddGroupAttribute;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteUserAttribute;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/attributes.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteGroupAttribute;
+
+/// Whether the custom attributes are the ones of the users or of the groups.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttributeOwnerType {
+    User,
+    Group,
+}
+
+/// The type of the values of a custom attribute, which decides the input widget.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttributeKind {
+    String,
+    Integer,
+    JpegPhoto,
+    DateTime,
+}
+
+const ATTRIBUTE_KINDS: [AttributeKind; 4] = [
+    AttributeKind::String,
+    AttributeKind::Integer,
+    AttributeKind::DateTime,
+    AttributeKind::JpegPhoto,
+];
+
+impl AttributeKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            AttributeKind::String => "Text",
+            AttributeKind::Integer => "Number",
+            AttributeKind::JpegPhoto => "JPEG photo",
+            AttributeKind::DateTime => "Date",
+        }
+    }
+
+    fn value(self) -> &'static str {
+        match self {
+            AttributeKind::String => "STRING",
+            AttributeKind::Integer => "INTEGER",
+            AttributeKind::JpegPhoto => "JPEG_PHOTO",
+            AttributeKind::DateTime => "DATE_TIME",
+        }
+    }
+
+    fn from_value(value: &str) -> Option<Self> {
+        ATTRIBUTE_KINDS.iter().copied().find(|k| k.value() == value)
+    }
+}
+
+impl TryFrom<get_attributes_schema::AttributeType> for AttributeKind {
+    type Error = anyhow::Error;
+
+    fn try_from(attribute_type: get_attributes_schema::AttributeType) -> Result<Self> {
+        use get_attributes_schema::AttributeType;
+        Ok(match attribute_type {
+            AttributeType::STRING => AttributeKind::String,
+            AttributeType::INTEGER => AttributeKind::Integer,
+            AttributeType::JPEG_PHOTO => AttributeKind::JpegPhoto,
+            AttributeType::DATE_TIME => AttributeKind::DateTime,
+            AttributeType::Other(other) => bail!("Unknown attribute type: {}", other),
+        })
+    }
+}
+
+macro_rules! impl_from_attribute_kind {
+    ($module:ident) => {
+        impl From<AttributeKind> for $module::AttributeType {
+            fn from(kind: AttributeKind) -> Self {
+                match kind {
+                    AttributeKind::String => Self::STRING,
+                    AttributeKind::Integer => Self::INTEGER,
+                    AttributeKind::JpegPhoto => Self::JPEG_PHOTO,
+                    AttributeKind::DateTime => Self::DATE_TIME,
+                }
+            }
+        }
+    };
+}
+
+impl_from_attribute_kind!(add_user_attribute);
+impl_from_attribute_kind!(add_group_attribute);
+
+/// The definition of a custom attribute.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AttributeSchema {
+    pub name: String,
+    pub kind: AttributeKind,
+    pub is_list: bool,
+    pub is_visible: bool,
+}
+
+macro_rules! convert_attribute_schema {
+    ($schema:expr) => {
+        $schema
+            .into_iter()
+            .map(|a| {
+                Ok(AttributeSchema {
+                    name: a.name,
+                    kind: a.attribute_type.try_into()?,
+                    is_list: a.is_list,
+                    is_visible: a.is_visible,
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+    };
+}
+
+/// The definitions of the custom attributes of the users or groups, from the server response.
+pub fn get_schema(
+    response: get_attributes_schema::ResponseData,
+    owner_type: AttributeOwnerType,
+) -> Result<Vec<AttributeSchema>> {
+    match owner_type {
+        AttributeOwnerType::User => convert_attribute_schema!(response.user_attributes_schema),
+        AttributeOwnerType::Group => convert_attribute_schema!(response.group_attributes_schema),
+    }
+}
+
+/// The list of the custom attributes of the users or groups, with a form to add one.
+pub struct AttributeSchemaTable {
+    common: CommonComponentParts<Self>,
+    /// The attribute definitions, initially not loaded.
+    schema: Option<Vec<AttributeSchema>>,
+    new_name: String,
+    new_kind: AttributeKind,
+    new_is_list: bool,
+    new_is_visible: bool,
+    /// The attribute whose delete button was clicked once, waiting for the confirmation.
+    confirming_delete: Option<String>,
+}
+
+#[derive(yew::Properties, Clone, PartialEq, Eq)]
+pub struct Props {
+    pub owner_type: AttributeOwnerType,
+}
+
+pub enum Msg {
+    SchemaResponse(Result<get_attributes_schema::ResponseData>),
+    NameUpdate(String),
+    KindUpdate(Option<SelectOptionProps>),
+    ToggleList,
+    ToggleVisible,
+    Add,
+    Delete(String),
+    UpdateResponse(Result<()>),
+}
+
+impl CommonComponent<AttributeSchemaTable> for AttributeSchemaTable {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::SchemaResponse(response) => {
+                self.common.cancel_task();
+                self.schema = Some(get_schema(response?, self.common.owner_type)?);
+            }
+            Msg::NameUpdate(name) => {
+                self.new_name = name;
+                return Ok(false);
+            }
+            Msg::KindUpdate(option_props) => {
+                if let Some(kind) =
+                    option_props.and_then(|props| AttributeKind::from_value(&props.value))
+                {
+                    self.new_kind = kind;
+                }
+                return Ok(false);
+            }
+            Msg::ToggleList => self.new_is_list = !self.new_is_list,
+            Msg::ToggleVisible => self.new_is_visible = !self.new_is_visible,
+            Msg::Add => {
+                let name = self.new_name.trim().to_owned();
+                if name.is_empty() {
+                    bail!("The attribute name is required");
+                }
+                self.add_attribute(name);
+            }
+            Msg::Delete(name) => {
+                if self.confirming_delete.as_ref() != Some(&name) {
+                    self.confirming_delete = Some(name);
+                    return Ok(true);
+                }
+                self.confirming_delete = None;
+                self.delete_attribute(name);
+            }
+            Msg::UpdateResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.new_name.clear();
+                self.get_schema();
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl AttributeSchemaTable {
+    fn get_schema(&mut self) {
+        self.common.call_graphql::<GetAttributesSchema, _>(
+            get_attributes_schema::Variables,
+            Msg::SchemaResponse,
+            "Error trying to fetch the attributes schema",
+        );
+    }
+
+    fn add_attribute(&mut self, name: String) {
+        match self.common.owner_type {
+            AttributeOwnerType::User => self.common.call_graphql::<AddUserAttribute, _>(
+                add_user_attribute::Variables {
+                    name,
+                    attribute_type: self.new_kind.into(),
+                    is_list: self.new_is_list,
+                    is_visible: self.new_is_visible,
+                },
+                |r| Msg::UpdateResponse(r.map(|_| ())),
+                "Error trying to add the attribute",
+            ),
+            AttributeOwnerType::Group => self.common.call_graphql::<AddGroupAttribute, _>(
+                add_group_attribute::Variables {
+                    name,
+                    attribute_type: self.new_kind.into(),
+                    is_list: self.new_is_list,
+                    is_visible: self.new_is_visible,
+                },
+                |r| Msg::UpdateResponse(r.map(|_| ())),
+                "Error trying to add the attribute",
+            ),
+        }
+    }
+
+    fn delete_attribute(&mut self, name: String) {
+        match self.common.owner_type {
+            AttributeOwnerType::User => self.common.call_graphql::<DeleteUserAttribute, _>(
+                delete_user_attribute::Variables { name },
+                |r| Msg::UpdateResponse(r.map(|_| ())),
+                "Error trying to delete the attribute",
+            ),
+            AttributeOwnerType::Group => self.common.call_graphql::<DeleteGroupAttribute, _>(
+                delete_group_attribute::Variables { name },
+                |r| Msg::UpdateResponse(r.map(|_| ())),
+                "Error trying to delete the attribute",
+            ),
+        }
+    }
+}
+
+impl Component for AttributeSchemaTable {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut table = Self {
+            common: CommonComponentParts::<Self>::create(props, link),
+            schema: None,
+            new_name: String::new(),
+            new_kind: AttributeKind::String,
+            new_is_list: false,
+            new_is_visible: true,
+            confirming_delete: None,
+        };
+        table.get_schema();
+        table
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <div class="mb-5">
+            <h5 class="fw-bold">
+              {match self.common.owner_type {
+                AttributeOwnerType::User => t("User attributes"),
+                AttributeOwnerType::Group => t("Group attributes"),
+              }}
+            </h5>
+            {self.view_schema()}
+            {self.view_add_form()}
+            {self.view_errors()}
+          </div>
+        }
+    }
+}
+
+impl AttributeSchemaTable {
+    fn view_schema(&self) -> Html {
+        let schema = match &self.schema {
+            None => return html! {{t("Loading...")}},
+            Some(schema) => schema,
+        };
+        html! {
+          <div class="table-responsive">
+            <table class="table table-hover">
+              <thead>
+                <tr>
+                  <th>{t("Name")}</th>
+                  <th>{t("Type")}</th>
+                  <th>{t("Multiple values")}</th>
+                  <th>{t("Visible to the users")}</th>
+                  <th>{t("Delete")}</th>
+                </tr>
+              </thead>
+              <tbody>
+                {if schema.is_empty() {
+                  html! {
+                    <tr key="EmptyRow">
+                      <td>{t("There are no custom attributes.")}</td>
+                    </tr>
+                  }
+                } else {
+                  html! {<>{schema.iter().map(|a| self.view_attribute(a)).collect::<Vec<_>>()}</>}
+                }}
+              </tbody>
+            </table>
+          </div>
+        }
+    }
+
+    fn view_attribute(&self, attribute: &AttributeSchema) -> Html {
+        let yes_no = |value: bool| if value { t("Yes") } else { t("No") };
+        let name = attribute.name.clone();
+        let confirming = self.confirming_delete.as_ref() == Some(&attribute.name);
+        html! {
+          <tr key=attribute.name.clone()>
+            <td>{&attribute.name}</td>
+            <td>{t(attribute.kind.label())}</td>
+            <td>{yes_no(attribute.is_list)}</td>
+            <td>{yes_no(attribute.is_visible)}</td>
+            <td>
+              <button
+                class="btn btn-danger btn-sm"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(move |_| Msg::Delete(name.clone()))>
+                <i class="bi-x-circle-fill" aria-label=t("Delete attribute") />
+                {if confirming { html! {<span class="ms-2">{t("Confirm")}</span>} } else { html! {} }}
+              </button>
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_add_form(&self) -> Html {
+        #[allow(unused_braces)]
+        let make_select_option = |kind: &AttributeKind| {
+            html_nested! {
+                <SelectOption value=kind.value().to_owned() text=t(kind.label()) key=kind.value() />
+            }
+        };
+        html! {
+          <form class="row g-2 align-items-center">
+            <div class="col-sm-4">
+              <input
+                type="text"
+                class="form-control"
+                placeholder=t("Attribute name")
+                value=self.new_name.clone()
+                oninput=self.common.callback(|e: InputData| Msg::NameUpdate(e.value)) />
+            </div>
+            <div class="col-sm-3">
+              <Select on_selection_change=self.common.callback(Msg::KindUpdate)>
+                {ATTRIBUTE_KINDS.iter().map(make_select_option).collect::<Vec<_>>()}
+              </Select>
+            </div>
+            <div class="col-auto form-check">
+              <input
+                type="checkbox"
+                class="form-check-input"
+                checked=self.new_is_list
+                onclick=self.common.callback(|_| Msg::ToggleList) />
+              <label class="form-check-label">{t("Multiple values")}</label>
+            </div>
+            <div class="col-auto form-check">
+              <input
+                type="checkbox"
+                class="form-check-input"
+                checked=self.new_is_visible
+                onclick=self.common.callback(|_| Msg::ToggleVisible) />
+              <label class="form-check-label">{t("Visible to the users")}</label>
+            </div>
+            <div class="col-auto">
+              <button
+                class="btn btn-primary"
+                type="submit"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Add})>
+                <i class="bi-plus-circle me-2"></i>
+                {t("Add attribute")}
+              </button>
+            </div>
+          </form>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
+        }
+    }
+}
+
+/// The admin page defining the custom attributes of the users and groups.
+pub struct AttributeSchemaPage;
+
+impl Component for AttributeSchemaPage {
+    type Message = ();
+    type Properties = ();
+
+    fn create(_: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self
+    }
+
+    fn update(&mut self, _: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, _: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <div>
+            <h3>{t("Custom attributes")}</h3>
+            <AttributeSchemaTable owner_type=AttributeOwnerType::User />
+            <AttributeSchemaTable owner_type=AttributeOwnerType::Group />
+          </div>
+        }
+    }
+}
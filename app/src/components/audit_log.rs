@@ -0,0 +1,329 @@
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    i18n::{t, t_format},
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/list_audit_logs.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ListAuditLogs;
+
+use list_audit_logs::{AuditLogFilter, AuditSource, ResponseData};
+
+type AuditLogEntry = list_audit_logs::ListAuditLogsAuditLogsNodes;
+
+const PAGE_SIZE: i64 = 50;
+
+/// The fields of the filter form, as typed.
+#[derive(Default)]
+struct FilterInputs {
+    actor: String,
+    action: String,
+    target: String,
+    /// Dates in the "YYYY-MM-DD" format of the date inputs.
+    since: String,
+    until: String,
+}
+
+pub enum FilterField {
+    Actor,
+    Action,
+    Target,
+    Since,
+    Until,
+}
+
+/// The admin page listing the operations that modified the directory, newest first.
+pub struct AuditLog {
+    common: CommonComponentParts<Self>,
+    entries: Option<Vec<AuditLogEntry>>,
+    total_count: i64,
+    inputs: FilterInputs,
+    /// The filter of the displayed entries, set when submitting the form.
+    filter: Option<AuditLogFilter>,
+    /// The cursors of the pages visited so far, the last one being the current page.
+    page_cursors: Vec<Option<String>>,
+    next_page_cursor: Option<String>,
+}
+
+pub enum Msg {
+    ListAuditLogsResponse(Result<ResponseData>),
+    FilterUpdate(FilterField, String),
+    SubmitFilter,
+    NextPage,
+    PreviousPage,
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// The start of the day, in UTC.
+fn parse_date(value: &str) -> Result<Option<DateTime<Utc>>> {
+    non_empty(value)
+        .map(|value| {
+            let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                .with_context(|| format!("Invalid date: {}", value))?;
+            Ok(DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc))
+        })
+        .transpose()
+}
+
+impl FilterInputs {
+    fn to_filter(&self) -> Result<Option<AuditLogFilter>> {
+        let filter = AuditLogFilter {
+            actor: non_empty(&self.actor),
+            action: non_empty(&self.action),
+            target: non_empty(&self.target),
+            since: parse_date(&self.since)?,
+            // The "until" day is included.
+            until: parse_date(&self.until)?.map(|date| date + Duration::days(1)),
+        };
+        let is_empty = filter.actor.is_none()
+            && filter.action.is_none()
+            && filter.target.is_none()
+            && filter.since.is_none()
+            && filter.until.is_none();
+        Ok((!is_empty).then(|| filter))
+    }
+}
+
+impl CommonComponent<AuditLog> for AuditLog {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::ListAuditLogsResponse(response) => {
+                self.common.cancel_task();
+                let connection = response?.audit_logs;
+                self.entries = Some(connection.nodes);
+                self.total_count = connection.total_count;
+                self.next_page_cursor = if connection.page_info.has_next_page {
+                    connection.page_info.end_cursor
+                } else {
+                    None
+                };
+                Ok(true)
+            }
+            Msg::FilterUpdate(field, value) => {
+                let input = match field {
+                    FilterField::Actor => &mut self.inputs.actor,
+                    FilterField::Action => &mut self.inputs.action,
+                    FilterField::Target => &mut self.inputs.target,
+                    FilterField::Since => &mut self.inputs.since,
+                    FilterField::Until => &mut self.inputs.until,
+                };
+                *input = value;
+                Ok(false)
+            }
+            Msg::SubmitFilter => {
+                self.filter = self.inputs.to_filter()?;
+                self.page_cursors = vec![None];
+                self.next_page_cursor = None;
+                self.get_entries();
+                Ok(true)
+            }
+            Msg::NextPage => {
+                if let Some(cursor) = self.next_page_cursor.take() {
+                    self.page_cursors.push(Some(cursor));
+                    self.get_entries();
+                }
+                Ok(true)
+            }
+            Msg::PreviousPage => {
+                if self.page_cursors.len() > 1 {
+                    self.page_cursors.pop();
+                    self.get_entries();
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl AuditLog {
+    fn get_entries(&mut self) {
+        self.common.call_graphql::<ListAuditLogs, _>(
+            list_audit_logs::Variables {
+                filter: self.filter.as_ref().map(|filter| AuditLogFilter {
+                    actor: filter.actor.clone(),
+                    action: filter.action.clone(),
+                    target: filter.target.clone(),
+                    since: filter.since,
+                    until: filter.until,
+                }),
+                first: Some(PAGE_SIZE),
+                after: self.page_cursors.last().cloned().flatten(),
+            },
+            Msg::ListAuditLogsResponse,
+            "Error trying to fetch the audit log",
+        );
+    }
+}
+
+impl Component for AuditLog {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut page = AuditLog {
+            common: CommonComponentParts::<Self>::create(props, link),
+            entries: None,
+            total_count: 0,
+            inputs: FilterInputs::default(),
+            filter: None,
+            page_cursors: vec![None],
+            next_page_cursor: None,
+        };
+        page.get_entries();
+        page
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <div>
+              <h3>{t("Audit log")}</h3>
+              {self.view_filter()}
+              {self.view_entries()}
+              {self.view_pagination()}
+              {self.view_errors()}
+            </div>
+        }
+    }
+}
+
+impl AuditLog {
+    fn view_filter_input(&self, label: &str, input_type: &str, field: fn() -> FilterField) -> Html {
+        html! {
+          <div class="col-sm">
+            <label class="form-label">{t(label)}</label>
+            <input
+              type=input_type.to_owned()
+              class="form-control"
+              oninput=self.common.callback(move |e: InputData| Msg::FilterUpdate(field(), e.value)) />
+          </div>
+        }
+    }
+
+    fn view_filter(&self) -> Html {
+        html! {
+          <form class="row g-2 align-items-end mb-3">
+            {self.view_filter_input("Actor", "text", || FilterField::Actor)}
+            {self.view_filter_input("Action", "text", || FilterField::Action)}
+            {self.view_filter_input("Target", "text", || FilterField::Target)}
+            {self.view_filter_input("From", "date", || FilterField::Since)}
+            {self.view_filter_input("To", "date", || FilterField::Until)}
+            <div class="col-sm-auto">
+              <button
+                class="btn btn-primary"
+                type="submit"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitFilter})>
+                <i class="bi-funnel me-2"></i>
+                {t("Filter")}
+              </button>
+            </div>
+          </form>
+        }
+    }
+
+    fn view_entries(&self) -> Html {
+        let make_table = |entries: &Vec<AuditLogEntry>| {
+            html! {
+                <div class="table-responsive">
+                  <table class="table table-hover">
+                    <thead>
+                      <tr>
+                        <th>{t("Date")}</th>
+                        <th>{t("Actor")}</th>
+                        <th>{t("Source")}</th>
+                        <th>{t("Action")}</th>
+                        <th>{t("Target")}</th>
+                        <th>{t("Details")}</th>
+                      </tr>
+                    </thead>
+                    <tbody>
+                      {entries.iter().map(|e| self.view_entry(e)).collect::<Vec<_>>()}
+                    </tbody>
+                  </table>
+                </div>
+            }
+        };
+        match &self.entries {
+            None => html! {{t("Loading...")}},
+            Some(entries) => make_table(entries),
+        }
+    }
+
+    fn view_entry(&self, entry: &AuditLogEntry) -> Html {
+        let source = match &entry.source {
+            AuditSource::LDAP => "LDAP",
+            AuditSource::GRAPHQL => "GraphQL",
+            AuditSource::SCIM => "SCIM",
+            AuditSource::REST => "REST",
+            AuditSource::SERVER => "Server",
+            AuditSource::CLI => "CLI",
+            AuditSource::Other(source) => source.as_str(),
+        };
+        html! {
+          <tr key=entry.id>
+              <td>{entry.date.naive_local().format("%Y-%m-%d %H:%M:%S").to_string()}</td>
+              <td>{&entry.actor}</td>
+              <td>{source}</td>
+              <td>{&entry.action}</td>
+              <td>{&entry.target}</td>
+              <td>{entry.details.as_deref().unwrap_or_default()}</td>
+          </tr>
+        }
+    }
+
+    fn view_pagination(&self) -> Html {
+        let page = self.page_cursors.len() as i64;
+        let page_count = std::cmp::max(1, (self.total_count + PAGE_SIZE - 1) / PAGE_SIZE);
+        html! {
+          <div class="d-flex align-items-center mb-3">
+            <button
+              class="btn btn-secondary me-2"
+              disabled=self.page_cursors.len() <= 1 || self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::PreviousPage)>
+              <i class="bi-chevron-left"/>
+            </button>
+            <span class="me-2">
+              {t_format("Page {} of {} ({} entries)", &[&page, &page_count, &self.total_count])}
+            </span>
+            <button
+              class="btn btn-secondary"
+              disabled=self.next_page_cursor.is_none() || self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::NextPage)>
+              <i class="bi-chevron-right"/>
+            </button>
+          </div>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
+        }
+    }
+}
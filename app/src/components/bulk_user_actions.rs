@@ -0,0 +1,256 @@
+use crate::{
+    components::{
+        add_user_to_group::{get_group_list, GetGroupList},
+        select::{Select, SelectOption, SelectOptionProps},
+        user_details::Group,
+    },
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::{t, t_format},
+    },
+};
+use anyhow::{Error, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/bulk_user_actions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct AddUsersToGroup;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/bulk_user_actions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RemoveUsersFromGroup;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/bulk_user_actions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DisableUsers;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/bulk_user_actions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteUsers;
+
+/// The action bar for the users selected in the user table.
+pub struct BulkUserActions {
+    common: CommonComponentParts<Self>,
+    /// The list of existing groups, initially not loaded.
+    group_list: Option<Vec<Group>>,
+    selected_group: Option<Group>,
+    /// The delete button needs a second click to confirm.
+    confirming_delete: bool,
+}
+
+#[derive(yew::Properties, Clone, PartialEq)]
+pub struct Props {
+    pub user_ids: Vec<String>,
+    pub on_done: Callback<()>,
+    pub on_error: Callback<Error>,
+}
+
+pub enum Msg {
+    GroupListResponse(Result<get_group_list::ResponseData>),
+    SelectionChanged(Option<SelectOptionProps>),
+    AddToGroup,
+    RemoveFromGroup,
+    Disable,
+    Delete,
+    ActionResponse(Result<()>),
+}
+
+impl CommonComponent<BulkUserActions> for BulkUserActions {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::GroupListResponse(response) => {
+                self.group_list = Some(response?.groups.into_iter().map(Into::into).collect());
+                self.common.cancel_task();
+            }
+            Msg::SelectionChanged(option_props) => {
+                let was_some = self.selected_group.is_some();
+                self.selected_group = option_props.map(|props| Group {
+                    id: props.value.parse::<i64>().unwrap(),
+                    display_name: props.text,
+                });
+                return Ok(self.selected_group.is_some() != was_some);
+            }
+            Msg::AddToGroup => {
+                let group = match &self.selected_group {
+                    None => return Ok(false),
+                    Some(group) => group.id,
+                };
+                self.common.call_graphql::<AddUsersToGroup, _>(
+                    add_users_to_group::Variables {
+                        users: self.common.user_ids.clone(),
+                        group,
+                    },
+                    |r| Msg::ActionResponse(r.map(|_| ())),
+                    "Error trying to add the users to the group",
+                );
+            }
+            Msg::RemoveFromGroup => {
+                let group = match &self.selected_group {
+                    None => return Ok(false),
+                    Some(group) => group.id,
+                };
+                self.common.call_graphql::<RemoveUsersFromGroup, _>(
+                    remove_users_from_group::Variables {
+                        users: self.common.user_ids.clone(),
+                        group,
+                    },
+                    |r| Msg::ActionResponse(r.map(|_| ())),
+                    "Error trying to remove the users from the group",
+                );
+            }
+            Msg::Disable => {
+                self.common.call_graphql::<DisableUsers, _>(
+                    disable_users::Variables {
+                        users: self.common.user_ids.clone(),
+                    },
+                    |r| Msg::ActionResponse(r.map(|_| ())),
+                    "Error trying to disable the users",
+                );
+            }
+            Msg::Delete => {
+                if !self.confirming_delete {
+                    self.confirming_delete = true;
+                    return Ok(true);
+                }
+                self.confirming_delete = false;
+                self.common.call_graphql::<DeleteUsers, _>(
+                    delete_users::Variables {
+                        users: self.common.user_ids.clone(),
+                    },
+                    |r| Msg::ActionResponse(r.map(|_| ())),
+                    "Error trying to delete the users",
+                );
+            }
+            Msg::ActionResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.common.on_done.emit(());
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for BulkUserActions {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut res = Self {
+            common: CommonComponentParts::<Self>::create(props, link),
+            group_list: None,
+            selected_group: None,
+            confirming_delete: false,
+        };
+        res.common.call_graphql::<GetGroupList, _>(
+            get_group_list::Variables,
+            Msg::GroupListResponse,
+            "Error trying to fetch group list",
+        );
+        res
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update_and_report_error(
+            self,
+            msg,
+            self.common.on_error.clone(),
+        )
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if props.user_ids != self.common.user_ids {
+            self.confirming_delete = false;
+        }
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        let group_list = match &self.group_list {
+            None => return html! {{t("Loading groups")}},
+            Some(group_list) => group_list,
+        };
+        #[allow(unused_braces)]
+        let make_select_option = |group: &Group| {
+            html_nested! {
+                <SelectOption value=group.id.to_string() text=group.display_name.clone() key=group.id />
+            }
+        };
+        let busy = self.common.is_task_running();
+        let no_group = self.selected_group.is_none() || busy;
+        let user_count = self.common.user_ids.len();
+        html! {
+          <div class="row align-items-center mb-3">
+            <div class="col-auto">
+              {t_format("{} selected", &[&user_count])}
+            </div>
+            <div class="col-sm-3">
+              <Select on_selection_change=self.common.callback(Msg::SelectionChanged)>
+                {group_list.iter().map(make_select_option).collect::<Vec<_>>()}
+              </Select>
+            </div>
+            <div class="col-auto">
+              <button
+                class="btn btn-secondary me-2"
+                disabled=no_group
+                onclick=self.common.callback(|_| Msg::AddToGroup)>
+                <i class="bi-person-plus me-2"></i>
+                {t("Add to group")}
+              </button>
+              <button
+                class="btn btn-secondary me-2"
+                disabled=no_group
+                onclick=self.common.callback(|_| Msg::RemoveFromGroup)>
+                <i class="bi-person-dash me-2"></i>
+                {t("Remove from group")}
+              </button>
+              <button
+                class="btn btn-warning me-2"
+                disabled=busy
+                onclick=self.common.callback(|_| Msg::Disable)>
+                <i class="bi-pause-circle me-2"></i>
+                {t("Disable")}
+              </button>
+              <button
+                class="btn btn-danger"
+                disabled=busy
+                onclick=self.common.callback(|_| Msg::Delete)>
+                <i class="bi-x-circle-fill me-2"></i>
+                {
+                  if self.confirming_delete {
+                      t_format("Confirm deleting {} users", &[&user_count])
+                  } else {
+                      t("Delete")
+                  }
+                }
+              </button>
+            </div>
+          </div>
+        }
+    }
+}
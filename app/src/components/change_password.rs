@@ -3,6 +3,7 @@ use crate::{
     infra::{
         api::HostService,
         common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
     },
 };
 use anyhow::{anyhow, bail, Context, Result};
@@ -222,7 +223,7 @@ impl Component for ChangePasswordForm {
           <>
             <div class="mb-2 mt-2">
               <h5 class="fw-bold">
-                {"Change password"}
+                {t("Change password")}
               </h5>
             </div>
             {
@@ -240,7 +241,7 @@ impl Component for ChangePasswordForm {
                 <div class="form-group row">
                   <label for="old_password"
                     class="form-label col-sm-2 col-form-label">
-                    {"Current password*:"}
+                    {t("Current password*:")}
                   </label>
                   <div class="col-sm-10">
                     <Field
@@ -252,7 +253,7 @@ impl Component for ChangePasswordForm {
                       autocomplete="current-password"
                       oninput=self.common.callback(|_| Msg::FormUpdate) />
                     <div class="invalid-feedback">
-                      {&self.form.field_message("old_password")}
+                      {t(&self.form.field_message("old_password"))}
                     </div>
                   </div>
                 </div>
@@ -260,7 +261,7 @@ impl Component for ChangePasswordForm {
               <div class="form-group row mb-3">
                 <label for="new_password"
                   class="form-label col-sm-2 col-form-label">
-                   {"New Password"}
+                   {t("New Password")}
                    <span class="text-danger">{"*"}</span>
                    {":"}
                 </label>
@@ -275,14 +276,14 @@ impl Component for ChangePasswordForm {
                     autocomplete="new-password"
                     oninput=self.common.callback(|_| Msg::FormUpdate) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("password")}
+                    {t(&self.form.field_message("password"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="confirm_password"
                   class="form-label col-sm-2 col-form-label">
-                  {"Confirm Password"}
+                  {t("Confirm Password")}
                   <span class="text-danger">{"*"}</span>
                   {":"}
                 </label>
@@ -297,7 +298,7 @@ impl Component for ChangePasswordForm {
                     autocomplete="new-password"
                     oninput=self.common.callback(|_| Msg::FormUpdate) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("confirm_password")}
+                    {t(&self.form.field_message("confirm_password"))}
                   </div>
                 </div>
               </div>
@@ -308,13 +309,13 @@ impl Component for ChangePasswordForm {
                   disabled=self.common.is_task_running()
                   onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Submit})>
                   <i class="bi-save me-2"></i>
-                  {"Save changes"}
+                  {t("Save changes")}
                 </button>
                 <NavButton
                   classes="btn btn-secondary ms-2 col-auto col-form-label"
                   route=AppRoute::UserDetails(self.common.username.clone())>
                   <i class="bi-arrow-return-left me-2"></i>
-                  {"Back"}
+                  {t("Back")}
                 </NavButton>
               </div>
             </form>
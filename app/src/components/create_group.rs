@@ -1,6 +1,9 @@
 use crate::{
     components::router::AppRoute,
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
 };
 use anyhow::{bail, Result};
 use graphql_client::GraphQLQuery;
@@ -102,12 +105,12 @@ impl Component for CreateGroupForm {
           <div class="row justify-content-center">
             <form class="form py-3" style="max-width: 636px">
               <div class="row mb-3">
-                <h5 class="fw-bold">{"Create a group"}</h5>
+                <h5 class="fw-bold">{t("Create a group")}</h5>
               </div>
               <div class="form-group row mb-3">
                 <label for="groupname"
                   class="form-label col-4 col-form-label">
-                  {"Group name"}
+                  {t("Group name")}
                   <span class="text-danger">{"*"}</span>
                   {":"}
                 </label>
@@ -121,7 +124,7 @@ impl Component for CreateGroupForm {
                     autocomplete="groupname"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("groupname")}
+                    {t(&self.form.field_message("groupname"))}
                   </div>
                 </div>
               </div>
@@ -132,7 +135,7 @@ impl Component for CreateGroupForm {
                   disabled=self.common.is_task_running()
                   onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitForm})>
                   <i class="bi-save me-2"></i>
-                  {"Submit"}
+                  {t("Submit")}
                 </button>
               </div>
             </form>
@@ -3,6 +3,7 @@ use crate::{
     infra::{
         api::HostService,
         common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
     },
 };
 use anyhow::{bail, Context, Result};
@@ -90,6 +91,21 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
                         firstName: to_option(model.first_name),
                         lastName: to_option(model.last_name),
                         avatar: None,
+                        uidNumber: None,
+                        gidNumber: None,
+                        homeDirectory: None,
+                        loginShell: None,
+                        telephoneNumber: None,
+                        mobile: None,
+                        title: None,
+                        department: None,
+                        locality: None,
+                        postalAddress: None,
+                        locale: None,
+                        timezone: None,
+                        validFrom: None,
+                        validUntil: None,
+                        attributes: None,
                     },
                 };
                 self.common.call_graphql::<CreateUser, _>(
@@ -195,12 +211,12 @@ impl Component for CreateUserForm {
           <div class="row justify-content-center">
             <form class="form py-3" style="max-width: 636px">
               <div class="row mb-3">
-                <h5 class="fw-bold">{"Create a user"}</h5>
+                <h5 class="fw-bold">{t("Create a user")}</h5>
               </div>
               <div class="form-group row mb-3">
                 <label for="username"
                   class="form-label col-4 col-form-label">
-                  {"User name"}
+                  {t("User name")}
                   <span class="text-danger">{"*"}</span>
                   {":"}
                 </label>
@@ -214,14 +230,14 @@ impl Component for CreateUserForm {
                     autocomplete="username"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("username")}
+                    {t(&self.form.field_message("username"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="email"
                   class="form-label col-4 col-form-label">
-                  {"Email"}
+                  {t("Email")}
                   <span class="text-danger">{"*"}</span>
                   {":"}
                 </label>
@@ -236,14 +252,14 @@ impl Component for CreateUserForm {
                     autocomplete="email"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("email")}
+                    {t(&self.form.field_message("email"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="display-name"
                   class="form-label col-4 col-form-label">
-                  {"Display name:"}
+                  {t("Display name:")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -255,14 +271,14 @@ impl Component for CreateUserForm {
                     field_name="display_name"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("display_name")}
+                    {t(&self.form.field_message("display_name"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="first-name"
                   class="form-label col-4 col-form-label">
-                  {"First name:"}
+                  {t("First name:")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -274,14 +290,14 @@ impl Component for CreateUserForm {
                     field_name="first_name"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("first_name")}
+                    {t(&self.form.field_message("first_name"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="last-name"
                   class="form-label col-4 col-form-label">
-                  {"Last name:"}
+                  {t("Last name:")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -293,14 +309,14 @@ impl Component for CreateUserForm {
                     field_name="last_name"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("last_name")}
+                    {t(&self.form.field_message("last_name"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="password"
                   class="form-label col-4 col-form-label">
-                  {"Password:"}
+                  {t("Password:")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -313,14 +329,14 @@ impl Component for CreateUserForm {
                     autocomplete="new-password"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("password")}
+                    {t(&self.form.field_message("password"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="confirm_password"
                   class="form-label col-4 col-form-label">
-                  {"Confirm password:"}
+                  {t("Confirm password:")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -333,7 +349,7 @@ impl Component for CreateUserForm {
                     autocomplete="new-password"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("confirm_password")}
+                    {t(&self.form.field_message("confirm_password"))}
                   </div>
                 </div>
               </div>
@@ -344,7 +360,7 @@ impl Component for CreateUserForm {
                   type="submit"
                   onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitForm})>
                   <i class="bi-save me-2"></i>
-                  {"Submit"}
+                  {t("Submit")}
                 </button>
               </div>
             </form>
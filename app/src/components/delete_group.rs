@@ -2,6 +2,7 @@ use crate::{
     components::group_table::Group,
     infra::{
         common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
         modal::Modal,
     },
 };
@@ -115,7 +116,7 @@ impl Component for DeleteGroup {
             class="btn btn-danger"
             disabled=self.common.is_task_running()
             onclick=self.common.callback(|_| Msg::ClickedDeleteGroup)>
-            <i class="bi-x-circle-fill" aria-label="Delete group" />
+            <i class="bi-x-circle-fill" aria-label=t("Delete group") />
           </button>
           {self.show_modal()}
           </>
@@ -136,16 +137,16 @@ impl DeleteGroup {
             <div class="modal-dialog">
               <div class="modal-content">
                 <div class="modal-header">
-                  <h5 class="modal-title" id="deleteGroupModalLabel">{"Delete group?"}</h5>
+                  <h5 class="modal-title" id="deleteGroupModalLabel">{t("Delete group?")}</h5>
                   <button
                     type="button"
                     class="btn-close"
-                    aria-label="Close"
+                    aria-label=t("Close")
                     onclick=self.common.callback(|_| Msg::DismissModal) />
                 </div>
                 <div class="modal-body">
                 <span>
-                  {"Are you sure you want to delete group "}
+                  {t("Are you sure you want to delete group ")}
                   <b>{&self.common.group.display_name}</b>{"?"}
                 </span>
                 </div>
@@ -155,14 +156,14 @@ impl DeleteGroup {
                     class="btn btn-secondary"
                     onclick=self.common.callback(|_| Msg::DismissModal)>
                       <i class="bi-x-circle me-2"></i>
-                      {"Cancel"}
+                      {t("Cancel")}
                   </button>
                   <button
                     type="button"
                     onclick=self.common.callback(|_| Msg::ConfirmDeleteGroup)
                     class="btn btn-danger">
                     <i class="bi-check-circle me-2"></i>
-                    {"Yes, I'm sure"}
+                    {t("Yes, I'm sure")}
                  </button>
                 </div>
               </div>
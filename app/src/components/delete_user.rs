@@ -1,5 +1,6 @@
 use crate::infra::{
     common_component::{CommonComponent, CommonComponentParts},
+    i18n::t,
     modal::Modal,
 };
 use anyhow::{Error, Result};
@@ -112,7 +113,7 @@ impl Component for DeleteUser {
             class="btn btn-danger"
             disabled=self.common.is_task_running()
             onclick=self.common.callback(|_| Msg::ClickedDeleteUser)>
-            <i class="bi-x-circle-fill" aria-label="Delete user" />
+            <i class="bi-x-circle-fill" aria-label=t("Delete user") />
           </button>
           {self.show_modal()}
           </>
@@ -134,16 +135,16 @@ impl DeleteUser {
             <div class="modal-dialog" /*role="document"*/>
               <div class="modal-content">
                 <div class="modal-header">
-                  <h5 class="modal-title" id="deleteUserModalLabel">{"Delete user?"}</h5>
+                  <h5 class="modal-title" id="deleteUserModalLabel">{t("Delete user?")}</h5>
                   <button
                     type="button"
                     class="btn-close"
-                    aria-label="Close"
+                    aria-label=t("Close")
                     onclick=self.common.callback(|_| Msg::DismissModal) />
                 </div>
                 <div class="modal-body">
                 <span>
-                  {"Are you sure you want to delete user "}
+                  {t("Are you sure you want to delete user ")}
                   <b>{&self.common.username}</b>{"?"}
                 </span>
                 </div>
@@ -153,14 +154,14 @@ impl DeleteUser {
                     class="btn btn-secondary"
                     onclick=self.common.callback(|_| Msg::DismissModal)>
                     <i class="bi-x-circle me-2"></i>
-                    {"Cancel"}
+                    {t("Cancel")}
                   </button>
                   <button
                     type="button"
                     onclick=self.common.callback(|_| Msg::ConfirmDeleteUser)
                     class="btn btn-danger">
                     <i class="bi-check-circle me-2"></i>
-                    {"Yes, I'm sure"}
+                    {t("Yes, I'm sure")}
                   </button>
                 </div>
               </div>
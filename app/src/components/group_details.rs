@@ -1,10 +1,14 @@
 use crate::{
     components::{
         add_group_member::{self, AddGroupMemberComponent},
+        attribute_editor::{AttributeEditor, AttributeOwner, AttributeValue},
         remove_user_from_group::RemoveUserFromGroupComponent,
         router::{AppRoute, Link},
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
 };
 use anyhow::{bail, Error, Result};
 use graphql_client::GraphQLQuery;
@@ -60,7 +64,7 @@ impl GroupDetails {
         if let Some(e) = error {
             html! {
               <div class="alert alert-danger">
-                <span>{"Error: "}{e.to_string()}</span>
+                <span>{t("Error: ")}{e.to_string()}</span>
               </div>
             }
         } else {
@@ -77,7 +81,7 @@ impl GroupDetails {
                 <div class="form-group row mb-3">
                   <label for="displayName"
                     class="form-label col-4 col-form-label">
-                    {"Group: "}
+                    {t("Group: ")}
                   </label>
                   <div class="col-8">
                     <span id="groupId" class="form-constrol-static">{g.display_name.to_string()}</span>
@@ -86,7 +90,7 @@ impl GroupDetails {
                 <div class="form-group row mb-3">
                   <label for="creationDate"
                     class="form-label col-4 col-form-label">
-                    {"Creation date: "}
+                    {t("Creation date: ")}
                   </label>
                   <div class="col-8">
                     <span id="creationDate" class="form-constrol-static">{g.creation_date.naive_local().date()}</span>
@@ -95,18 +99,39 @@ impl GroupDetails {
                 <div class="form-group row mb-3">
                   <label for="uuid"
                     class="form-label col-4 col-form-label">
-                    {"UUID: "}
+                    {t("UUID: ")}
                   </label>
                   <div class="col-8">
                     <span id="uuid" class="form-constrol-static">{g.uuid.to_string()}</span>
                   </div>
                 </div>
+                {self.view_optional_detail("gidNumber", "GID number: ", g.gid_number.map(|gid| gid.to_string()))}
+                {self.view_optional_detail("description", "Description: ", g.description.clone())}
+                {self.view_optional_detail("mail", "Email: ", g.mail.clone())}
               </form>
             </div>
           </>
         }
     }
 
+    /// A row of the details, only displayed when the group has a value for it.
+    fn view_optional_detail(&self, id: &str, label: &str, value: Option<String>) -> Html {
+        match value {
+            None => html! {},
+            Some(value) => html! {
+              <div class="form-group row mb-3">
+                <label for=id.to_owned()
+                  class="form-label col-4 col-form-label">
+                  {t(label)}
+                </label>
+                <div class="col-8">
+                  <span id=id.to_owned() class="form-constrol-static">{value}</span>
+                </div>
+              </div>
+            },
+        }
+    }
+
     fn view_user_list(&self, g: &Group) -> Html {
         let make_user_row = |user: &User| {
             let user_id = user.id.clone();
@@ -131,13 +156,13 @@ impl GroupDetails {
         };
         html! {
           <>
-            <h5 class="fw-bold">{"Members"}</h5>
+            <h5 class="fw-bold">{t("Members")}</h5>
             <div class="table-responsive">
               <table class="table table-hover">
                 <thead>
                   <tr key="headerRow">
-                    <th>{"User Id"}</th>
-                    <th>{"Display name"}</th>
+                    <th>{t("User Id")}</th>
+                    <th>{t("Display name")}</th>
                     <th></th>
                   </tr>
                 </thead>
@@ -145,7 +170,7 @@ impl GroupDetails {
                   {if g.users.is_empty() {
                     html! {
                       <tr key="EmptyRow">
-                        <td>{"There are no users in this group."}</td>
+                        <td>{t("There are no users in this group.")}</td>
                         <td/>
                       </tr>
                     }
@@ -234,12 +259,18 @@ impl Component for GroupDetails {
 
     fn view(&self) -> Html {
         match (&self.group, &self.common.error) {
-            (None, None) => html! {{"Loading..."}},
-            (None, Some(e)) => html! {<div>{"Error: "}{e.to_string()}</div>},
+            (None, None) => html! {{t("Loading...")}},
+            (None, Some(e)) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
             (Some(u), error) => {
                 html! {
                     <div>
                       {self.view_details(u)}
+                      <AttributeEditor
+                        owner=AttributeOwner::Group(u.id)
+                        values=u.attributes.iter().map(|a| AttributeValue {
+                            name: a.name.clone(),
+                            value: a.value.clone(),
+                        }).collect::<Vec<_>>() />
                       {self.view_user_list(u)}
                       {self.view_add_user_button(u)}
                       {self.view_messages(error)}
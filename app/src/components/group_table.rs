@@ -3,7 +3,10 @@ use crate::{
         delete_group::DeleteGroup,
         router::{AppRoute, Link},
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
@@ -97,9 +100,9 @@ impl GroupTable {
                   <table class="table table-hover">
                     <thead>
                       <tr>
-                        <th>{"Group name"}</th>
-                        <th>{"Creation date"}</th>
-                        <th>{"Delete"}</th>
+                        <th>{t("Group name")}</th>
+                        <th>{t("Creation date")}</th>
+                        <th>{t("Delete")}</th>
                       </tr>
                     </thead>
                     <tbody>
@@ -110,7 +113,7 @@ impl GroupTable {
             }
         };
         match &self.groups {
-            None => html! {{"Loading..."}},
+            None => html! {{t("Loading...")}},
             Some(groups) => make_table(groups),
         }
     }
@@ -139,7 +142,7 @@ impl GroupTable {
     fn view_errors(&self) -> Html {
         match &self.common.error {
             None => html! {},
-            Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+            Some(e) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
         }
     }
 }
@@ -1,8 +1,9 @@
 use crate::{
     components::router::{AppRoute, NavButton},
     infra::{
-        api::HostService,
+        api::{HostService, LoginOutcome},
         common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
     },
 };
 use anyhow::{anyhow, bail, Context, Result};
@@ -11,11 +12,16 @@ use validator_derive::Validate;
 use yew::{prelude::*, services::ConsoleService};
 use yew_form::Form;
 use yew_form_derive::Model;
+use yew_router::{
+    agent::{RouteAgentDispatcher, RouteRequest},
+    route::Route,
+};
 
 pub struct LoginForm {
     common: CommonComponentParts<Self>,
     form: Form<FormModel>,
     refreshing: bool,
+    route_dispatcher: RouteAgentDispatcher,
 }
 
 /// The fields of the form, with the constraints.
@@ -42,7 +48,7 @@ pub enum Msg {
             Result<Box<login::ServerLoginStartResponse>>,
         ),
     ),
-    AuthenticationFinishResponse(Result<(String, bool)>),
+    AuthenticationFinishResponse(Result<LoginOutcome>),
 }
 
 impl CommonComponent<LoginForm> for LoginForm {
@@ -94,11 +100,19 @@ impl CommonComponent<LoginForm> for LoginForm {
                 )?;
                 Ok(false)
             }
-            Msg::AuthenticationFinishResponse(user_info) => {
+            Msg::AuthenticationFinishResponse(outcome) => {
                 self.common.cancel_task();
-                self.common
-                    .on_logged_in
-                    .emit(user_info.context("Could not log in")?);
+                match outcome.context("Could not log in")? {
+                    LoginOutcome::LoggedIn(user_id, is_admin) => {
+                        self.common.on_logged_in.emit((user_id, is_admin))
+                    }
+                    LoginOutcome::PasswordExpired(user_id) => {
+                        self.route_dispatcher
+                            .send(RouteRequest::ChangeRoute(Route::from(
+                                AppRoute::ChangeExpiredPassword(user_id),
+                            )))
+                    }
+                }
                 Ok(true)
             }
             Msg::AuthenticationRefreshResponse(user_info) => {
@@ -126,6 +140,7 @@ impl Component for LoginForm {
             common: CommonComponentParts::<Self>::create(props, link),
             form: Form::<FormModel>::new(FormModel::default()),
             refreshing: true,
+            route_dispatcher: RouteAgentDispatcher::new(),
         };
         if let Err(e) =
             app.common
@@ -150,7 +165,7 @@ impl Component for LoginForm {
         if self.refreshing {
             html! {
               <div>
-                <img src={"spinner.gif"} alt={"Loading"} />
+                <img src={"spinner.gif"} alt=t("Loading") />
               </div>
             }
         } else {
@@ -169,7 +184,7 @@ impl Component for LoginForm {
                       class_valid="has-success"
                       form=&self.form
                       field_name="username"
-                      placeholder="Username"
+                      placeholder=t("Username")
                       autocomplete="username"
                       oninput=self.common.callback(|_| Msg::Update) />
                   </div>
@@ -186,7 +201,7 @@ impl Component for LoginForm {
                       form=&self.form
                       field_name="password"
                       input_type="password"
-                      placeholder="Password"
+                      placeholder=t("Password")
                       autocomplete="current-password" />
                   </div>
                   <div class="form-group mt-3">
@@ -196,13 +211,13 @@ impl Component for LoginForm {
                       disabled=self.common.is_task_running()
                       onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Submit})>
                       <i class="bi-box-arrow-in-right me-2"/>
-                      {"Login"}
+                      {t("Login")}
                     </button>
                     <NavButton
                       classes="btn-link btn"
                       disabled=self.common.is_task_running()
                       route=AppRoute::StartResetPassword>
-                      {"Forgot your password?"}
+                      {t("Forgot your password?")}
                     </NavButton>
                   </div>
                   <div class="form-group">
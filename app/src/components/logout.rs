@@ -2,6 +2,7 @@ use crate::infra::{
     api::HostService,
     common_component::{CommonComponent, CommonComponentParts},
     cookies::delete_cookie,
+    i18n::t,
 };
 use anyhow::Result;
 use yew::prelude::*;
@@ -64,7 +65,7 @@ impl Component for LogoutButton {
             <button
               class="dropdown-item"
               onclick=self.common.callback(|_| Msg::LogoutRequested)>
-              {"Logout"}
+              {t("Logout")}
             </button>
         }
     }
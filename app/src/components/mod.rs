@@ -1,6 +1,10 @@
 pub mod add_group_member;
 pub mod add_user_to_group;
 pub mod app;
+pub mod attribute_editor;
+pub mod attribute_schema;
+pub mod audit_log;
+pub mod bulk_user_actions;
 pub mod change_password;
 pub mod create_group;
 pub mod create_user;
@@ -10,6 +14,7 @@ pub mod group_details;
 pub mod group_table;
 pub mod login;
 pub mod logout;
+pub mod profile;
 pub mod remove_user_from_group;
 pub mod reset_password_step1;
 pub mod reset_password_step2;
@@ -0,0 +1,252 @@
+use crate::{
+    components::user_details::UserDetails,
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/profile.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetProfile;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/profile.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RevokeSession;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/profile.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteWebauthnCredential;
+
+use get_profile::{PermissionLevel, ResponseData};
+
+type Session = get_profile::GetProfileSessions;
+type WebauthnCredential = get_profile::GetProfileWebauthnCredentials;
+
+/// The page of the logged-in user: their details, which they can edit, their login sessions
+/// and their second factors.
+pub struct Profile {
+    common: CommonComponentParts<Self>,
+    profile: Option<ResponseData>,
+}
+
+pub enum Msg {
+    ProfileResponse(Result<ResponseData>),
+    RevokeSession(String),
+    DeleteCredential(String),
+    UpdateResponse(Result<()>),
+}
+
+fn format_date(date: Option<&DateTime<Utc>>) -> String {
+    date.map(|d| d.naive_local().format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+impl CommonComponent<Profile> for Profile {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::ProfileResponse(response) => {
+                self.common.cancel_task();
+                self.profile = Some(response?);
+            }
+            Msg::RevokeSession(session_id) => {
+                self.common.call_graphql::<RevokeSession, _>(
+                    revoke_session::Variables { session_id },
+                    |r| Msg::UpdateResponse(r.map(|_| ())),
+                    "Error trying to revoke the session",
+                );
+            }
+            Msg::DeleteCredential(credential_id) => {
+                let user_id = match &self.profile {
+                    None => return Ok(false),
+                    Some(profile) => profile.me.user.id.clone(),
+                };
+                self.common.call_graphql::<DeleteWebauthnCredential, _>(
+                    delete_webauthn_credential::Variables {
+                        user_id,
+                        credential_id,
+                    },
+                    |r| Msg::UpdateResponse(r.map(|_| ())),
+                    "Error trying to delete the credential",
+                );
+            }
+            Msg::UpdateResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.get_profile();
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Profile {
+    fn get_profile(&mut self) {
+        self.common.call_graphql::<GetProfile, _>(
+            get_profile::Variables,
+            Msg::ProfileResponse,
+            "Error trying to fetch the profile",
+        );
+    }
+}
+
+impl Component for Profile {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut page = Self {
+            common: CommonComponentParts::<Self>::create(props, link),
+            profile: None,
+        };
+        page.get_profile();
+        page
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        match (&self.profile, &self.common.error) {
+            (None, None) => html! {{t("Loading...")}},
+            (None, Some(e)) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
+            (Some(profile), _) => html! {
+              <>
+                <UserDetails
+                  username=profile.me.user.id.clone()
+                  is_admin=matches!(profile.me.permission, PermissionLevel::ADMIN) />
+                {self.view_sessions(&profile.sessions)}
+                {self.view_credentials(&profile.webauthn_credentials)}
+                {self.view_errors()}
+              </>
+            },
+        }
+    }
+}
+
+impl Profile {
+    fn view_sessions(&self, sessions: &[Session]) -> Html {
+        let make_row = |session: &Session| {
+            let session_id = session.id.clone();
+            html! {
+              <tr key=session.id.clone()>
+                <td>{session.user_agent.as_deref().unwrap_or_default()}</td>
+                <td>{session.ip_address.as_deref().unwrap_or_default()}</td>
+                <td>{format_date(session.creation_date.as_ref())}</td>
+                <td>{format_date(session.last_used.as_ref())}</td>
+                <td>{format_date(Some(&session.expiry_date))}</td>
+                <td>
+                  <button
+                    class="btn btn-danger btn-sm"
+                    disabled=self.common.is_task_running()
+                    onclick=self.common.callback(move |_| Msg::RevokeSession(session_id.clone()))>
+                    <i class="bi-x-circle-fill" aria-label=t("Revoke the session") />
+                  </button>
+                </td>
+              </tr>
+            }
+        };
+        html! {
+          <>
+            <h5 class="row m-3 fw-bold">{t("Active sessions")}</h5>
+            <div class="table-responsive">
+              <table class="table table-hover">
+                <thead>
+                  <tr>
+                    <th>{t("Device")}</th>
+                    <th>{t("IP address")}</th>
+                    <th>{t("Opened")}</th>
+                    <th>{t("Last used")}</th>
+                    <th>{t("Expires")}</th>
+                    <th>{t("Revoke")}</th>
+                  </tr>
+                </thead>
+                <tbody>
+                  {sessions.iter().map(make_row).collect::<Vec<_>>()}
+                </tbody>
+              </table>
+            </div>
+          </>
+        }
+    }
+
+    fn view_credentials(&self, credentials: &[WebauthnCredential]) -> Html {
+        let make_row = |credential: &WebauthnCredential| {
+            let credential_id = credential.id.clone();
+            html! {
+              <tr key=credential.id.clone()>
+                <td>{&credential.display_name}</td>
+                <td>{format_date(Some(&credential.creation_date))}</td>
+                <td>{format_date(credential.last_used.as_ref())}</td>
+                <td>
+                  <button
+                    class="btn btn-danger btn-sm"
+                    disabled=self.common.is_task_running()
+                    onclick=self.common.callback(move |_| Msg::DeleteCredential(credential_id.clone()))>
+                    <i class="bi-x-circle-fill" aria-label=t("Delete the security key") />
+                  </button>
+                </td>
+              </tr>
+            }
+        };
+        html! {
+          <>
+            <h5 class="row m-3 fw-bold">{t("Two-factor authentication")}</h5>
+            {if credentials.is_empty() { html! {
+              <p>{t("No security key or passkey is registered.")}</p>
+            } } else { html! {
+              <div class="table-responsive">
+                <table class="table table-hover">
+                  <thead>
+                    <tr>
+                      <th>{t("Security key")}</th>
+                      <th>{t("Registered")}</th>
+                      <th>{t("Last used")}</th>
+                      <th>{t("Delete")}</th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {credentials.iter().map(make_row).collect::<Vec<_>>()}
+                  </tbody>
+                </table>
+              </div>
+            } } }
+          </>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    i18n::t,
+};
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
 use yew::prelude::*;
@@ -91,7 +94,7 @@ impl Component for RemoveUserFromGroupComponent {
             class="btn btn-danger"
             disabled=self.common.is_task_running()
             onclick=self.common.callback(|_| Msg::SubmitRemoveGroup)>
-            <i class="bi-x-circle-fill" aria-label="Remove user from group" />
+            <i class="bi-x-circle-fill" aria-label=t("Remove user from group") />
           </button>
         }
     }
@@ -3,6 +3,7 @@ use crate::{
     infra::{
         api::HostService,
         common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
     },
 };
 use anyhow::{bail, Result};
@@ -97,13 +98,13 @@ impl Component for ResetPasswordStep1Form {
                     class_valid="has-success"
                     form=&self.form
                     field_name="username"
-                    placeholder="Username or email"
+                    placeholder=t("Username or email")
                     autocomplete="username"
                     oninput=self.common.callback(|_| Msg::Update) />
                 </div>
                 { if self.just_succeeded {
                     html! {
-                      {"A reset token has been sent to your email."}
+                      {t("A reset token has been sent to your email.")}
                     }
                 } else {
                     html! {
@@ -114,13 +115,13 @@ impl Component for ResetPasswordStep1Form {
                             disabled=self.common.is_task_running()
                             onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Submit})>
                             <i class="bi-check-circle me-2"/>
-                            {"Reset password"}
+                            {t("Reset password")}
                           </button>
                           <NavButton
                             classes="btn-link btn"
                             disabled=self.common.is_task_running()
                             route=AppRoute::Login>
-                            {"Back"}
+                            {t("Back")}
                           </NavButton>
                         </div>
                     }
@@ -3,6 +3,7 @@ use crate::{
     infra::{
         api::HostService,
         common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
     },
 };
 use anyhow::{bail, Context, Result};
@@ -38,7 +39,12 @@ pub struct ResetPasswordStep2Form {
 
 #[derive(Clone, PartialEq, Eq, Properties)]
 pub struct Props {
+    #[prop_or_default]
     pub token: String,
+    /// Set instead of the token when the password has expired at login: the server already
+    /// issued the token to set the password.
+    #[prop_or_default]
+    pub username: Option<String>,
 }
 
 pub enum Msg {
@@ -129,6 +135,10 @@ impl Component for ResetPasswordStep2Form {
             route_dispatcher: RouteAgentDispatcher::new(),
             username: None,
         };
+        if let Some(username) = component.common.username.clone() {
+            component.username = Some(username);
+            return component;
+        }
         let token = component.common.token.clone();
         component
             .common
@@ -153,7 +163,7 @@ impl Component for ResetPasswordStep2Form {
         match (&self.username, &self.common.error) {
             (None, None) => {
                 return html! {
-                  {"Validating token"}
+                  {t("Validating token")}
                 }
             }
             (None, Some(e)) => {
@@ -168,13 +178,20 @@ impl Component for ResetPasswordStep2Form {
         type Field = yew_form::Field<FormModel>;
         html! {
           <>
-            <h2>{"Reset your password"}</h2>
+            <h2>
+              { if self.common.username.is_some() {
+                  t("Your password has expired, choose a new one")
+                } else {
+                  t("Reset your password")
+                }
+              }
+            </h2>
             <form
               class="form">
               <div class="form-group row">
                 <label for="new_password"
                   class="form-label col-sm-2 col-form-label">
-                  {"New password*:"}
+                  {t("New password*:")}
                 </label>
                 <div class="col-sm-10">
                   <Field
@@ -187,14 +204,14 @@ impl Component for ResetPasswordStep2Form {
                     input_type="password"
                     oninput=self.common.callback(|_| Msg::FormUpdate) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("password")}
+                    {t(&self.form.field_message("password"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row">
                 <label for="confirm_password"
                   class="form-label col-sm-2 col-form-label">
-                  {"Confirm password*:"}
+                  {t("Confirm password*:")}
                 </label>
                 <div class="col-sm-10">
                   <Field
@@ -207,7 +224,7 @@ impl Component for ResetPasswordStep2Form {
                     input_type="password"
                     oninput=self.common.callback(|_| Msg::FormUpdate) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("confirm_password")}
+                    {t(&self.form.field_message("confirm_password"))}
                   </div>
                 </div>
               </div>
@@ -217,7 +234,7 @@ impl Component for ResetPasswordStep2Form {
                   type="submit"
                   disabled=self.common.is_task_running()
                   onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Submit})>
-                  {"Submit"}
+                  {t("Submit")}
                 </button>
               </div>
             </form>
@@ -11,6 +11,8 @@ pub enum AppRoute {
     StartResetPassword,
     #[to = "/reset-password/step2/{token}"]
     FinishResetPassword(String),
+    #[to = "/password-expired/{user_id}"]
+    ChangeExpiredPassword(String),
     #[to = "/users/create"]
     CreateUser,
     #[to = "/users"]
@@ -19,12 +21,18 @@ pub enum AppRoute {
     ChangePassword(String),
     #[to = "/user/{user_id}"]
     UserDetails(String),
+    #[to = "/profile"]
+    Profile,
     #[to = "/groups/create"]
     CreateGroup,
     #[to = "/groups"]
     ListGroups,
     #[to = "/group/{group_id}"]
     GroupDetails(i64),
+    #[to = "/audit-log"]
+    AuditLog,
+    #[to = "/attributes"]
+    AttributeSchema,
     #[to = "/"]
     Index,
 }
@@ -1,11 +1,15 @@
 use crate::{
     components::{
         add_user_to_group::AddUserToGroupComponent,
+        attribute_editor::{AttributeEditor, AttributeOwner, AttributeValue},
         remove_user_from_group::RemoveUserFromGroupComponent,
         router::{AppRoute, Link, NavButton},
         user_details_form::UserDetailsForm,
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
 };
 use anyhow::{bail, Error, Result};
 use graphql_client::GraphQLQuery;
@@ -91,7 +95,7 @@ impl UserDetails {
         if let Some(e) = error {
             html! {
               <div class="alert alert-danger">
-                <span>{"Error: "}{e.to_string()}</span>
+                <span>{t("Error: ")}{e.to_string()}</span>
               </div>
             }
         } else {
@@ -127,12 +131,12 @@ impl UserDetails {
         };
         html! {
           <>
-            <h5 class="row m-3 fw-bold">{"Group memberships"}</h5>
+            <h5 class="row m-3 fw-bold">{t("Group memberships")}</h5>
             <div class="table-responsive">
               <table class="table table-hover">
                 <thead>
                   <tr key="headerRow">
-                    <th>{"Group"}</th>
+                    <th>{t("Group")}</th>
                     { if self.common.is_admin { html!{ <th></th> }} else { html!{} }}
                   </tr>
                 </thead>
@@ -140,7 +144,7 @@ impl UserDetails {
                   {if u.groups.is_empty() {
                     html! {
                       <tr key="EmptyRow">
-                        <td>{"This user is not a member of any groups."}</td>
+                        <td>{t("This user is not a member of any groups.")}</td>
                       </tr>
                     }
                   } else {
@@ -191,8 +195,8 @@ impl Component for UserDetails {
 
     fn view(&self) -> Html {
         match (&self.user, &self.common.error) {
-            (None, None) => html! {{"Loading..."}},
-            (None, Some(e)) => html! {<div>{"Error: "}{e.to_string()}</div>},
+            (None, None) => html! {{t("Loading...")}},
+            (None, Some(e)) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
             (Some(u), error) => {
                 html! {
                   <>
@@ -202,14 +206,21 @@ impl Component for UserDetails {
                         route=AppRoute::ChangePassword(u.id.clone())
                         classes="btn btn-secondary">
                         <i class="bi-key me-2"></i>
-                        {"Modify password"}
+                        {t("Modify password")}
                       </NavButton>
                     </div>
                     <div>
-                      <h5 class="row m-3 fw-bold">{"User details"}</h5>
+                      <h5 class="row m-3 fw-bold">{t("User details")}</h5>
                     </div>
                     <UserDetailsForm
                       user=u.clone() />
+                    <AttributeEditor
+                      owner=AttributeOwner::User(u.id.clone())
+                      values=u.attributes.iter().map(|a| AttributeValue {
+                          name: a.name.clone(),
+                          value: a.value.clone(),
+                      }).collect::<Vec<_>>()
+                      read_only=!self.common.is_admin />
                     {self.view_group_memberships(u)}
                     {self.view_add_group_button(u)}
                     {self.view_messages(error)}
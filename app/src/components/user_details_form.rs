@@ -2,7 +2,10 @@ use std::str::FromStr;
 
 use crate::{
     components::user_details::User,
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::t,
+    },
 };
 use anyhow::{bail, Error, Result};
 use graphql_client::GraphQLQuery;
@@ -46,6 +49,14 @@ pub struct UserModel {
     display_name: String,
     first_name: String,
     last_name: String,
+    telephone_number: String,
+    mobile: String,
+    title: String,
+    department: String,
+    locality: String,
+    postal_address: String,
+    locale: String,
+    timezone: String,
 }
 
 /// The GraphQL query sent to the server to update the user details.
@@ -153,6 +164,14 @@ impl Component for UserDetailsForm {
             display_name: props.user.display_name.clone(),
             first_name: props.user.first_name.clone(),
             last_name: props.user.last_name.clone(),
+            telephone_number: props.user.telephone_number.clone().unwrap_or_default(),
+            mobile: props.user.mobile.clone().unwrap_or_default(),
+            title: props.user.title.clone().unwrap_or_default(),
+            department: props.user.department.clone().unwrap_or_default(),
+            locality: props.user.locality.clone().unwrap_or_default(),
+            postal_address: props.user.postal_address.clone().unwrap_or_default(),
+            locale: props.user.locale.clone().unwrap_or_default(),
+            timezone: props.user.timezone.clone().unwrap_or_default(),
         };
         Self {
             common: CommonComponentParts::<Self>::create(props, link),
@@ -185,7 +204,7 @@ impl Component for UserDetailsForm {
               <div class="form-group row mb-3">
                 <label for="userId"
                   class="form-label col-4 col-form-label">
-                  {"User ID: "}
+                  {t("User ID: ")}
                 </label>
                 <div class="col-8">
                   <span id="userId" class="form-control-static"><i>{&self.common.user.id}</i></span>
@@ -194,7 +213,7 @@ impl Component for UserDetailsForm {
               <div class="form-group row mb-3">
                 <label for="creationDate"
                   class="form-label col-4 col-form-label">
-                  {"Creation date: "}
+                  {t("Creation date: ")}
                 </label>
                 <div class="col-8">
                   <span id="creationDate" class="form-control-static">{&self.common.user.creation_date.naive_local().date()}</span>
@@ -203,7 +222,7 @@ impl Component for UserDetailsForm {
               <div class="form-group row mb-3">
                 <label for="uuid"
                   class="form-label col-4 col-form-label">
-                  {"UUID: "}
+                  {t("UUID: ")}
                 </label>
                 <div class="col-8">
                   <span id="creationDate" class="form-control-static">{&self.common.user.uuid}</span>
@@ -212,7 +231,7 @@ impl Component for UserDetailsForm {
               <div class="form-group row mb-3">
                 <label for="email"
                   class="form-label col-4 col-form-label">
-                  {"Email"}
+                  {t("Email")}
                   <span class="text-danger">{"*"}</span>
                   {":"}
                 </label>
@@ -226,14 +245,14 @@ impl Component for UserDetailsForm {
                     autocomplete="email"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("email")}
+                    {t(&self.form.field_message("email"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="display_name"
                   class="form-label col-4 col-form-label">
-                  {"Display Name: "}
+                  {t("Display Name: ")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -245,14 +264,14 @@ impl Component for UserDetailsForm {
                     autocomplete="name"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("display_name")}
+                    {t(&self.form.field_message("display_name"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="first_name"
                   class="form-label col-4 col-form-label">
-                  {"First Name: "}
+                  {t("First Name: ")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -262,14 +281,14 @@ impl Component for UserDetailsForm {
                     autocomplete="given-name"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("first_name")}
+                    {t(&self.form.field_message("first_name"))}
                   </div>
                 </div>
               </div>
               <div class="form-group row mb-3">
                 <label for="last_name"
                   class="form-label col-4 col-form-label">
-                  {"Last Name: "}
+                  {t("Last Name: ")}
                 </label>
                 <div class="col-8">
                   <Field
@@ -279,14 +298,22 @@ impl Component for UserDetailsForm {
                     autocomplete="family-name"
                     oninput=self.common.callback(|_| Msg::Update) />
                   <div class="invalid-feedback">
-                    {&self.form.field_message("last_name")}
+                    {t(&self.form.field_message("last_name"))}
                   </div>
                 </div>
               </div>
+              {self.profile_field("telephone_number", "Telephone number: ", "tel")}
+              {self.profile_field("mobile", "Mobile: ", "tel")}
+              {self.profile_field("title", "Title: ", "organization-title")}
+              {self.profile_field("department", "Department: ", "off")}
+              {self.profile_field("locality", "Locality: ", "address-level2")}
+              {self.profile_field("postal_address", "Postal address: ", "street-address")}
+              {self.profile_field("locale", "Language: ", "language")}
+              {self.profile_field("timezone", "Time zone: ", "off")}
               <div class="form-group row align-items-center mb-3">
                 <label for="avatar"
                   class="form-label col-4 col-form-label">
-                  {"Avatar: "}
+                  {t("Avatar: ")}
                 </label>
                 <div class="col-8">
                   <div class="row align-items-center">
@@ -303,7 +330,7 @@ impl Component for UserDetailsForm {
                         id="avatarDisplay"
                         src={format!("data:image/jpeg;base64, {}", avatar_string)}
                         style="max-height:128px;max-width:128px;height:auto;width:auto;"
-                        alt="Avatar" />
+                        alt=t("Avatar") />
                     </div>
                   </div>
                 </div>
@@ -315,7 +342,7 @@ impl Component for UserDetailsForm {
                   disabled=self.common.is_task_running()
                   onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitClicked})>
                   <i class="bi-save me-2"></i>
-                  {"Save changes"}
+                  {t("Save changes")}
                 </button>
               </div>
             </form>
@@ -329,7 +356,7 @@ impl Component for UserDetailsForm {
               } else { html! {} }
             }
             <div hidden=!self.just_updated>
-              <div class="alert alert-success mt-4">{"User successfully updated!"}</div>
+              <div class="alert alert-success mt-4">{t("User successfully updated!")}</div>
             </div>
           </div>
         }
@@ -337,6 +364,30 @@ impl Component for UserDetailsForm {
 }
 
 impl UserDetailsForm {
+    /// A row of the form for one of the optional profile fields.
+    fn profile_field(&self, field_name: &str, label: &str, autocomplete: &str) -> Html {
+        type Field = yew_form::Field<UserModel>;
+        html! {
+          <div class="form-group row mb-3">
+            <label for=field_name.to_owned()
+              class="form-label col-4 col-form-label">
+              {t(label)}
+            </label>
+            <div class="col-8">
+              <Field
+                class="form-control"
+                form=&self.form
+                field_name=field_name.to_owned()
+                autocomplete=autocomplete.to_owned()
+                oninput=self.common.callback(|_| Msg::Update) />
+              <div class="invalid-feedback">
+                {t(&self.form.field_message(field_name))}
+              </div>
+            </div>
+          </div>
+        }
+    }
+
     fn submit_user_update_form(&mut self) -> Result<bool> {
         ConsoleService::log("Submit");
         if !self.form.validate() {
@@ -359,6 +410,20 @@ impl UserDetailsForm {
             firstName: None,
             lastName: None,
             avatar: None,
+            uidNumber: None,
+            gidNumber: None,
+            homeDirectory: None,
+            loginShell: None,
+            telephoneNumber: None,
+            mobile: None,
+            title: None,
+            department: None,
+            locality: None,
+            postalAddress: None,
+            locale: None,
+            timezone: None,
+            insertAttributes: None,
+            removeAttributes: None,
         };
         let default_user_input = user_input.clone();
         let model = self.form.model();
@@ -375,6 +440,30 @@ impl UserDetailsForm {
         if base_user.last_name != model.last_name {
             user_input.lastName = Some(model.last_name);
         }
+        if base_user.telephone_number.as_deref().unwrap_or_default() != model.telephone_number {
+            user_input.telephoneNumber = Some(model.telephone_number);
+        }
+        if base_user.mobile.as_deref().unwrap_or_default() != model.mobile {
+            user_input.mobile = Some(model.mobile);
+        }
+        if base_user.title.as_deref().unwrap_or_default() != model.title {
+            user_input.title = Some(model.title);
+        }
+        if base_user.department.as_deref().unwrap_or_default() != model.department {
+            user_input.department = Some(model.department);
+        }
+        if base_user.locality.as_deref().unwrap_or_default() != model.locality {
+            user_input.locality = Some(model.locality);
+        }
+        if base_user.postal_address.as_deref().unwrap_or_default() != model.postal_address {
+            user_input.postalAddress = Some(model.postal_address);
+        }
+        if base_user.locale.as_deref().unwrap_or_default() != model.locale {
+            user_input.locale = Some(model.locale);
+        }
+        if base_user.timezone.as_deref().unwrap_or_default() != model.timezone {
+            user_input.timezone = Some(model.timezone);
+        }
         user_input.avatar = maybe_to_base64(&self.avatar)?;
         // Nothing changed.
         if user_input == default_user_input {
@@ -401,6 +490,14 @@ impl UserDetailsForm {
                 self.common.user.display_name = model.display_name;
                 self.common.user.first_name = model.first_name;
                 self.common.user.last_name = model.last_name;
+                self.common.user.telephone_number = to_option(model.telephone_number);
+                self.common.user.mobile = to_option(model.mobile);
+                self.common.user.title = to_option(model.title);
+                self.common.user.department = to_option(model.department);
+                self.common.user.locality = to_option(model.locality);
+                self.common.user.postal_address = to_option(model.postal_address);
+                self.common.user.locale = to_option(model.locale);
+                self.common.user.timezone = to_option(model.timezone);
                 if let Some(avatar) = maybe_to_base64(&self.avatar)? {
                     self.common.user.avatar = Some(avatar);
                 }
@@ -411,7 +508,15 @@ impl UserDetailsForm {
     }
 }
 
-fn is_valid_jpeg(bytes: &[u8]) -> bool {
+fn to_option(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+pub fn is_valid_jpeg(bytes: &[u8]) -> bool {
     image::io::Reader::with_format(std::io::Cursor::new(bytes), image::ImageFormat::Jpeg)
         .decode()
         .is_ok()
@@ -1,12 +1,17 @@
 use crate::{
     components::{
+        bulk_user_actions::BulkUserActions,
         delete_user::DeleteUser,
         router::{AppRoute, Link},
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        i18n::{t, t_format},
+    },
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
+use std::collections::BTreeSet;
 use yew::prelude::*;
 
 #[derive(GraphQLQuery)]
@@ -18,32 +23,132 @@ use yew::prelude::*;
 )]
 pub struct ListUsersQuery;
 
-use list_users_query::{RequestFilter, ResponseData};
+use list_users_query::{
+    EqualityConstraint, OrderDirection, RequestFilter, ResponseData, UserOrderBy, UserOrderField,
+};
+
+type User = list_users_query::ListUsersQueryUsersConnectionNodes;
 
-type User = list_users_query::ListUsersQueryUsers;
+const PAGE_SIZE: i64 = 50;
+
+/// The columns by which the table can be sorted.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Id,
+    Email,
+    DisplayName,
+    FirstName,
+    LastName,
+    CreationDate,
+}
+
+impl SortColumn {
+    fn order_field(self) -> UserOrderField {
+        match self {
+            SortColumn::Id => UserOrderField::ID,
+            SortColumn::Email => UserOrderField::EMAIL,
+            SortColumn::DisplayName => UserOrderField::DISPLAY_NAME,
+            SortColumn::FirstName => UserOrderField::FIRST_NAME,
+            SortColumn::LastName => UserOrderField::LAST_NAME,
+            SortColumn::CreationDate => UserOrderField::CREATION_DATE,
+        }
+    }
+}
 
 pub struct UserTable {
     common: CommonComponentParts<Self>,
     users: Option<Vec<User>>,
+    total_count: i64,
+    search: String,
+    sort_column: SortColumn,
+    descending: bool,
+    /// The cursors of the pages visited so far, the last one being the current page.
+    page_cursors: Vec<Option<String>>,
+    next_page_cursor: Option<String>,
+    /// The users selected for a bulk action, kept across pages.
+    selected: BTreeSet<String>,
 }
 
 pub enum Msg {
     ListUsersResponse(Result<ResponseData>),
     OnUserDeleted(String),
     OnError(Error),
+    SearchUpdate(String),
+    SortBy(SortColumn),
+    NextPage,
+    PreviousPage,
+    ToggleSelection(String),
+    TogglePageSelection,
+    OnBulkActionDone,
 }
 
 impl CommonComponent<UserTable> for UserTable {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
-            Msg::ListUsersResponse(users) => {
-                self.users = Some(users?.users.into_iter().collect());
+            Msg::ListUsersResponse(response) => {
+                self.common.cancel_task();
+                let connection = response?.users_connection;
+                self.users = Some(connection.nodes);
+                self.total_count = connection.total_count;
+                self.next_page_cursor = if connection.page_info.has_next_page {
+                    connection.page_info.end_cursor
+                } else {
+                    None
+                };
                 Ok(true)
             }
             Msg::OnError(e) => Err(e),
             Msg::OnUserDeleted(user_id) => {
-                debug_assert!(self.users.is_some());
-                self.users.as_mut().unwrap().retain(|u| u.id != user_id);
+                self.selected.remove(&user_id);
+                // Reloads the page, to fill the gap.
+                self.get_users();
+                Ok(false)
+            }
+            Msg::SearchUpdate(search) => {
+                self.search = search;
+                self.get_first_page();
+                Ok(true)
+            }
+            Msg::SortBy(column) => {
+                self.descending = column == self.sort_column && !self.descending;
+                self.sort_column = column;
+                self.get_first_page();
+                Ok(true)
+            }
+            Msg::NextPage => {
+                if let Some(cursor) = self.next_page_cursor.take() {
+                    self.page_cursors.push(Some(cursor));
+                    self.get_users();
+                }
+                Ok(true)
+            }
+            Msg::PreviousPage => {
+                if self.page_cursors.len() > 1 {
+                    self.page_cursors.pop();
+                    self.get_users();
+                }
+                Ok(true)
+            }
+            Msg::ToggleSelection(user_id) => {
+                if !self.selected.remove(&user_id) {
+                    self.selected.insert(user_id);
+                }
+                Ok(true)
+            }
+            Msg::TogglePageSelection => {
+                let page_ids = self.page_user_ids();
+                if self.is_page_selected() {
+                    for id in &page_ids {
+                        self.selected.remove(id);
+                    }
+                } else {
+                    self.selected.extend(page_ids);
+                }
+                Ok(true)
+            }
+            Msg::OnBulkActionDone => {
+                self.selected.clear();
+                self.get_users();
                 Ok(true)
             }
         }
@@ -54,14 +159,86 @@ impl CommonComponent<UserTable> for UserTable {
     }
 }
 
+/// Matches the users with any of the displayed fields containing the search text.
+fn make_search_filter(search: &str) -> Option<RequestFilter> {
+    let search = search.trim();
+    if search.is_empty() {
+        return None;
+    }
+    let contains = |field: &str| RequestFilter {
+        any: None,
+        all: None,
+        not: None,
+        eq: None,
+        not_equals: None,
+        contains: Some(EqualityConstraint {
+            field: field.to_owned(),
+            value: search.to_owned(),
+        }),
+        starts_with: None,
+        member_of: None,
+        member_of_id: None,
+        modified_since: None,
+    };
+    Some(RequestFilter {
+        any: Some(
+            ["id", "email", "display_name", "first_name", "last_name"]
+                .iter()
+                .map(|field| contains(field))
+                .collect(),
+        ),
+        all: None,
+        not: None,
+        eq: None,
+        not_equals: None,
+        contains: None,
+        starts_with: None,
+        member_of: None,
+        member_of_id: None,
+        modified_since: None,
+    })
+}
+
 impl UserTable {
-    fn get_users(&mut self, req: Option<RequestFilter>) {
+    fn get_users(&mut self) {
+        let variables = list_users_query::Variables {
+            filters: make_search_filter(&self.search),
+            order_by: Some(UserOrderBy {
+                field: self.sort_column.order_field(),
+                direction: Some(if self.descending {
+                    OrderDirection::DESC
+                } else {
+                    OrderDirection::ASC
+                }),
+            }),
+            first: Some(PAGE_SIZE),
+            after: self.page_cursors.last().cloned().flatten(),
+        };
         self.common.call_graphql::<ListUsersQuery, _>(
-            list_users_query::Variables { filters: req },
+            variables,
             Msg::ListUsersResponse,
             "Error trying to fetch users",
         );
     }
+
+    fn page_user_ids(&self) -> Vec<String> {
+        self.users
+            .iter()
+            .flatten()
+            .map(|user| user.id.clone())
+            .collect()
+    }
+
+    fn is_page_selected(&self) -> bool {
+        let page_ids = self.page_user_ids();
+        !page_ids.is_empty() && page_ids.iter().all(|id| self.selected.contains(id))
+    }
+
+    fn get_first_page(&mut self) {
+        self.page_cursors = vec![None];
+        self.next_page_cursor = None;
+        self.get_users();
+    }
 }
 
 impl Component for UserTable {
@@ -72,8 +249,15 @@ impl Component for UserTable {
         let mut table = UserTable {
             common: CommonComponentParts::<Self>::create(props, link),
             users: None,
+            total_count: 0,
+            search: String::new(),
+            sort_column: SortColumn::Id,
+            descending: false,
+            page_cursors: vec![None],
+            next_page_cursor: None,
+            selected: BTreeSet::new(),
         };
-        table.get_users(None);
+        table.get_users();
         table
     }
 
@@ -88,7 +272,10 @@ impl Component for UserTable {
     fn view(&self) -> Html {
         html! {
             <div>
+              {self.view_search()}
+              {self.view_bulk_actions()}
               {self.view_users()}
+              {self.view_pagination()}
               {self.view_errors()}
             </div>
         }
@@ -96,6 +283,51 @@ impl Component for UserTable {
 }
 
 impl UserTable {
+    fn view_search(&self) -> Html {
+        html! {
+          <div class="input-group mb-3">
+            <span class="input-group-text">
+              <i class="bi-search"/>
+            </span>
+            <input
+              type="search"
+              class="form-control"
+              placeholder=t("Search by ID, email or name")
+              value=self.search.clone()
+              oninput=self.common.callback(|e: InputData| Msg::SearchUpdate(e.value)) />
+          </div>
+        }
+    }
+
+    fn view_bulk_actions(&self) -> Html {
+        if self.selected.is_empty() {
+            return html! {};
+        }
+        html! {
+          <BulkUserActions
+            user_ids=self.selected.iter().cloned().collect::<Vec<_>>()
+            on_done=self.common.callback(|_| Msg::OnBulkActionDone)
+            on_error=self.common.callback(Msg::OnError)/>
+        }
+    }
+
+    fn view_sortable_header(&self, title: &str, column: SortColumn) -> Html {
+        let indicator = if column != self.sort_column {
+            ""
+        } else if self.descending {
+            " ▼"
+        } else {
+            " ▲"
+        };
+        html! {
+          <th
+            style="cursor: pointer"
+            onclick=self.common.callback(move |_| Msg::SortBy(column))>
+            {t(title)}{indicator}
+          </th>
+        }
+    }
+
     fn view_users(&self) -> Html {
         let make_table = |users: &Vec<User>| {
             html! {
@@ -103,13 +335,21 @@ impl UserTable {
                   <table class="table table-hover">
                     <thead>
                       <tr>
-                        <th>{"User ID"}</th>
-                        <th>{"Email"}</th>
-                        <th>{"Display name"}</th>
-                        <th>{"First name"}</th>
-                        <th>{"Last name"}</th>
-                        <th>{"Creation date"}</th>
-                        <th>{"Delete"}</th>
+                        <th>
+                          <input
+                            type="checkbox"
+                            class="form-check-input"
+                            aria-label=t("Select the page")
+                            checked=self.is_page_selected()
+                            onclick=self.common.callback(|_| Msg::TogglePageSelection) />
+                        </th>
+                        {self.view_sortable_header("User ID", SortColumn::Id)}
+                        {self.view_sortable_header("Email", SortColumn::Email)}
+                        {self.view_sortable_header("Display name", SortColumn::DisplayName)}
+                        {self.view_sortable_header("First name", SortColumn::FirstName)}
+                        {self.view_sortable_header("Last name", SortColumn::LastName)}
+                        {self.view_sortable_header("Creation date", SortColumn::CreationDate)}
+                        <th>{t("Delete")}</th>
                       </tr>
                     </thead>
                     <tbody>
@@ -120,14 +360,47 @@ impl UserTable {
             }
         };
         match &self.users {
-            None => html! {{"Loading..."}},
+            None => html! {{t("Loading...")}},
             Some(users) => make_table(users),
         }
     }
 
+    fn view_pagination(&self) -> Html {
+        let page = self.page_cursors.len() as i64;
+        let page_count = std::cmp::max(1, (self.total_count + PAGE_SIZE - 1) / PAGE_SIZE);
+        html! {
+          <div class="d-flex align-items-center mb-3">
+            <button
+              class="btn btn-secondary me-2"
+              disabled=self.page_cursors.len() <= 1 || self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::PreviousPage)>
+              <i class="bi-chevron-left"/>
+            </button>
+            <span class="me-2">
+              {t_format("Page {} of {} ({} users)", &[&page, &page_count, &self.total_count])}
+            </span>
+            <button
+              class="btn btn-secondary"
+              disabled=self.next_page_cursor.is_none() || self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::NextPage)>
+              <i class="bi-chevron-right"/>
+            </button>
+          </div>
+        }
+    }
+
     fn view_user(&self, user: &User) -> Html {
+        let user_id = user.id.clone();
         html! {
           <tr key=user.id.clone()>
+              <td>
+                <input
+                  type="checkbox"
+                  class="form-check-input"
+                  aria-label=t("Select the user")
+                  checked=self.selected.contains(&user.id)
+                  onclick=self.common.callback(move |_| Msg::ToggleSelection(user_id.clone())) />
+              </td>
               <td><Link route=AppRoute::UserDetails(user.id.clone())>{&user.id}</Link></td>
               <td>{&user.email}</td>
               <td>{&user.display_name}</td>
@@ -147,7 +420,7 @@ impl UserTable {
     fn view_errors(&self) -> Html {
         match &self.common.error {
             None => html! {},
-            Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+            Some(e) => html! {<div>{t("Error: ")}{e.to_string()}</div>},
         }
     }
 }
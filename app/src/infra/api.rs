@@ -125,6 +125,14 @@ where
     )
 }
 
+/// The result of a successful password check.
+pub enum LoginOutcome {
+    /// The user ID, and whether the user is an admin.
+    LoggedIn(String, bool),
+    /// The password must be changed before logging in.
+    PasswordExpired(String),
+}
+
 impl HostService {
     pub fn graphql_query<QueryType>(
         variables: QueryType::Variables,
@@ -176,22 +184,25 @@ impl HostService {
 
     pub fn login_finish(
         request: login::ClientLoginFinishRequest,
-        callback: Callback<Result<(String, bool)>>,
+        callback: Callback<Result<LoginOutcome>>,
     ) -> Result<FetchTask> {
         let set_cookies = |jwt_claims: JWTClaims| {
             let is_admin = jwt_claims.groups.contains("lldap_admin");
             set_cookie("user_id", &jwt_claims.user, &jwt_claims.exp)
                 .map(|_| set_cookie("is_admin", &is_admin.to_string(), &jwt_claims.exp))
-                .map(|_| (jwt_claims.user.clone(), is_admin))
+                .map(|_| LoginOutcome::LoggedIn(jwt_claims.user.clone(), is_admin))
                 .context("Error clearing cookie")
         };
         let parse_token = move |data: String| {
             serde_json::from_str::<login::ServerLoginResponse>(&data)
                 .context("Could not parse response")
                 .and_then(|r| {
-                    get_claims_from_jwt(r.token.as_str())
-                        .context("Could not parse response")
-                        .and_then(set_cookies)
+                    let claims = get_claims_from_jwt(r.token.as_str())
+                        .context("Could not parse response")?;
+                    if r.password_expired {
+                        return Ok(LoginOutcome::PasswordExpired(claims.user));
+                    }
+                    set_cookies(claims)
                 })
         };
         call_server(
@@ -0,0 +1,98 @@
+//! Translation of the texts of the interface.
+//!
+//! The English texts are the keys of the message catalogs in `app/i18n/`, one JSON file per
+//! language. A text missing from a catalog is displayed in English.
+
+use std::{cell::Cell, collections::HashMap, fmt::Display};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Language {
+    English,
+    French,
+}
+
+impl Language {
+    /// The supported language of a BCP 47 tag such as "fr-CA", if any.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(|c| c == '-' || c == '_').next()?;
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Language::English),
+            "fr" => Some(Language::French),
+            _ => None,
+        }
+    }
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+        }
+    }
+
+    fn catalog(self) -> Option<&'static str> {
+        match self {
+            Language::English => None,
+            Language::French => Some(include_str!("../../i18n/fr.json")),
+        }
+    }
+}
+
+fn browser_language() -> Option<Language> {
+    Language::from_tag(&web_sys::window()?.navigator().language()?)
+}
+
+fn parse_catalog(language: Language) -> HashMap<String, String> {
+    language
+        .catalog()
+        .map(|catalog| {
+            serde_json::from_str(catalog)
+                .unwrap_or_else(|e| panic!("Invalid {} message catalog: {}", language.tag(), e))
+        })
+        .unwrap_or_default()
+}
+
+thread_local! {
+    static LANGUAGE: Cell<Language> =
+        Cell::new(browser_language().unwrap_or(Language::English));
+    static CATALOGS: HashMap<Language, HashMap<String, String>> =
+        [Language::English, Language::French]
+            .iter()
+            .map(|&language| (language, parse_catalog(language)))
+            .collect();
+}
+
+/// The language of the interface, initially the one of the browser.
+pub fn current_language() -> Language {
+    LANGUAGE.with(Cell::get)
+}
+
+/// Changes the language of the interface, e.g. to the one stored in the user's profile. Only
+/// the components rendered afterwards are affected.
+pub fn set_language(language: Language) {
+    LANGUAGE.with(|l| l.set(language))
+}
+
+/// The translation of an English text in the current language.
+pub fn t(text: &str) -> String {
+    CATALOGS.with(|catalogs| {
+        catalogs
+            .get(&current_language())
+            .and_then(|catalog| catalog.get(text))
+            .cloned()
+            .unwrap_or_else(|| text.to_owned())
+    })
+}
+
+/// Like `t`, replacing the successive "{}" of the translation with the arguments.
+pub fn t_format(text: &str, args: &[&dyn Display]) -> String {
+    let translation = t(text);
+    let mut parts = translation.split("{}");
+    let mut result = parts.next().unwrap_or_default().to_owned();
+    for (index, part) in parts.enumerate() {
+        if let Some(arg) = args.get(index) {
+            result.push_str(&arg.to_string());
+        }
+        result.push_str(part);
+    }
+    result
+}
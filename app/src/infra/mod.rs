@@ -2,4 +2,5 @@ pub mod api;
 pub mod common_component;
 pub mod cookies;
 pub mod graphql;
+pub mod i18n;
 pub mod modal;
@@ -57,6 +57,9 @@ pub mod login {
         pub token: String,
         #[serde(rename = "refreshToken", skip_serializing_if = "Option::is_none")]
         pub refresh_token: Option<String>,
+        /// The password has expired: the token can only be used to set a new one.
+        #[serde(rename = "passwordExpired", default)]
+        pub password_expired: bool,
     }
 }
 
@@ -104,6 +107,38 @@ pub mod password_reset {
     }
 }
 
+/// The messages to accept an invitation to create an account.
+pub mod invitation {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerInvitationResponse {
+        /// Email of the invitee, for the new account.
+        pub email: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientAcceptInvitationRequest {
+        /// The chosen user ID. The password is then set through an OPAQUE registration, with the
+        /// token from the response.
+        #[serde(rename = "userId")]
+        pub user_id: String,
+    }
+}
+
+pub mod sign_up {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientSignUpRequest {
+        #[serde(rename = "userId")]
+        pub user_id: String,
+        pub email: String,
+        #[serde(rename = "displayName")]
+        pub display_name: Option<String>,
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct JWTClaims {
     pub exp: DateTime<Utc>,
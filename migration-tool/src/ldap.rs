@@ -186,6 +186,8 @@ impl TryFrom<ResultEntry> for User {
             .and_then(|v| v.into_iter().next().filter(|s| !s.is_empty()));
         let password =
             get_optional_attribute("userPassword").or_else(|| get_optional_attribute("password"));
+        let get_number_attribute =
+            |attr: &str| get_optional_attribute(attr).and_then(|s| s.parse::<i64>().ok());
         Ok(User::new(
             crate::lldap::CreateUserInput {
                 id,
@@ -194,6 +196,22 @@ impl TryFrom<ResultEntry> for User {
                 first_name,
                 last_name,
                 avatar: avatar.map(base64::encode),
+                uid_number: get_number_attribute("uidNumber"),
+                gid_number: get_number_attribute("gidNumber"),
+                home_directory: get_optional_attribute("homeDirectory"),
+                login_shell: get_optional_attribute("loginShell"),
+                telephone_number: get_optional_attribute("telephoneNumber"),
+                mobile: get_optional_attribute("mobile"),
+                title: get_optional_attribute("title"),
+                department: get_optional_attribute("ou")
+                    .or_else(|| get_optional_attribute("department")),
+                locality: get_optional_attribute("l"),
+                postal_address: get_optional_attribute("postalAddress"),
+                locale: get_optional_attribute("preferredLanguage"),
+                timezone: None,
+                valid_from: None,
+                valid_until: None,
+                attributes: None,
             },
             password,
             entry.dn,
@@ -304,6 +322,18 @@ pub fn get_users(connection: &mut LdapClient) -> Result<Vec<User>, anyhow::Error
                 "displayName",
                 "name",
                 "userPassword",
+                "uidNumber",
+                "gidNumber",
+                "homeDirectory",
+                "loginShell",
+                "telephoneNumber",
+                "mobile",
+                "title",
+                "ou",
+                "department",
+                "l",
+                "postalAddress",
+                "preferredLanguage",
             ],
         )?
         .success()?
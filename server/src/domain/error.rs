@@ -7,6 +7,8 @@ pub enum DomainError {
     AuthenticationError(String),
     #[error("Database error: `{0}`")]
     DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Account locked: `{0}`")]
+    AccountLocked(String),
     #[error("Authentication protocol error for `{0}`")]
     AuthenticationProtocolError(#[from] lldap_auth::opaque::AuthenticationError),
     #[error("Unknown crypto error: `{0}`")]
@@ -17,6 +19,18 @@ pub enum DomainError {
     Base64DecodeError(#[from] base64::DecodeError),
     #[error("Entity not found: `{0}`")]
     EntityNotFound(String),
+    #[error("Invalid request: `{0}`")]
+    InvalidRequest(String),
+    /// A password refused because its estimated strength is below the minimum score.
+    #[error("Invalid request: `{message}`")]
+    WeakPassword {
+        message: String,
+        score: u8,
+        min_score: u8,
+    },
+    /// Refused because it would leave `lldap_admin` without members.
+    #[error("Cannot remove the last admin: `{0}`")]
+    LastAdmin(String),
     #[error("Internal error: `{0}`")]
     InternalError(String),
 }
@@ -0,0 +1,143 @@
+use crate::{
+    domain::{
+        error::{DomainError, Result},
+        types::UserId,
+    },
+    infra::configuration::{ExternalAuthOptions, ExternalAuthType},
+};
+use serde::Serialize;
+use std::{process::Stdio, time::Duration};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, instrument};
+
+/// What the hook receives: a JSON object on the standard input of the command, or the body of
+/// the HTTP request.
+#[derive(Serialize)]
+struct Credentials<'a> {
+    user_id: &'a str,
+    password: &'a str,
+}
+
+fn to_domain_error(e: impl std::fmt::Display) -> DomainError {
+    DomainError::InternalError(format!("External authentication error: {}", e))
+}
+
+async fn run_command(command: &str, credentials: &[u8], timeout: Duration) -> Result<bool> {
+    let mut child = tokio::process::Command::new(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| to_domain_error(format!("while running {}: {}", command, e)))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let run = async {
+        // The command may exit without reading its input.
+        let _ = stdin.write_all(credentials).await;
+        drop(stdin);
+        child.wait_with_output().await
+    };
+    let output = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| to_domain_error(format!("{} timed out", command)))?
+        .map_err(to_domain_error)?;
+    if !output.status.success() {
+        debug!(
+            "{} denied the bind with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.status.success())
+}
+
+async fn call_endpoint(
+    url: &str,
+    credentials: &Credentials<'_>,
+    timeout: Duration,
+) -> Result<bool> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(credentials)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(to_domain_error)?;
+    match response.status() {
+        status if status.is_success() => Ok(true),
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Ok(false),
+        status => Err(to_domain_error(format!("{} answered {}", url, status))),
+    }
+}
+
+/// Asks the configured hook whether the password of the user is valid.
+#[instrument(skip_all, level = "debug", err)]
+pub async fn authenticate(
+    options: &ExternalAuthOptions,
+    user_id: &UserId,
+    password: &str,
+) -> Result<bool> {
+    debug!(?user_id);
+    let credentials = Credentials {
+        user_id: user_id.as_str(),
+        password,
+    };
+    let timeout = Duration::from_secs(options.timeout_seconds);
+    match (options.hook, &options.command, &options.url) {
+        (ExternalAuthType::None, _, _) => Ok(false),
+        (ExternalAuthType::Exec, Some(command), _) => {
+            let mut input = serde_json::to_vec(&credentials).map_err(to_domain_error)?;
+            input.push(b'\n');
+            run_command(command, &input, timeout).await
+        }
+        (ExternalAuthType::Http, _, Some(url)) => call_endpoint(url, &credentials, timeout).await,
+        (ExternalAuthType::Exec, None, _) => {
+            Err(to_domain_error("external_auth.command is not set"))
+        }
+        (ExternalAuthType::Http, _, None) => Err(to_domain_error("external_auth.url is not set")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec_options(command: &str) -> ExternalAuthOptions {
+        ExternalAuthOptions {
+            hook: ExternalAuthType::Exec,
+            command: Some(command.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exec_hook() {
+        let user_id = UserId::new("bob");
+        assert!(authenticate(&exec_options("true"), &user_id, "pass")
+            .await
+            .unwrap());
+        assert!(!authenticate(&exec_options("false"), &user_id, "pass")
+            .await
+            .unwrap());
+        authenticate(&exec_options("/nonexistent/hook"), &user_id, "pass")
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_hook_not_configured() {
+        let options = ExternalAuthOptions {
+            hook: ExternalAuthType::Http,
+            ..Default::default()
+        };
+        authenticate(&options, &UserId::new("bob"), "pass")
+            .await
+            .unwrap_err();
+        assert!(
+            !authenticate(&ExternalAuthOptions::default(), &UserId::new("bob"), "pass")
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -1,12 +1,21 @@
 use super::{
-    error::Result,
+    error::{DomainError, Result},
     types::{
-        Group, GroupDetails, GroupId, JpegPhoto, User, UserAndGroups, UserColumn, UserId, Uuid,
+        ApiToken, ApiTokenScope, AttributeSchema, AttributeValue, AuditLogEntry, AuditSource,
+        Capabilities, Change, DateTime, DeletedGroup, DeletedUser, DirectoryStatistics, Group,
+        GroupColumn, GroupDetails, GroupId, Invitation, JpegPhoto, OidcAuthorizationCode,
+        OidcClient, OidcGroupClaim, PendingUser, Role, ServiceAccount, Session, SshPublicKeys,
+        User, UserAndGroups, UserColumn, UserId, Uuid, WebauthnCredential,
     },
 };
+use crate::infra::configuration::PasswordPolicyOptions;
 use async_trait::async_trait;
+use futures_util::{
+    stream::{self, LocalBoxStream},
+    StreamExt, TryStreamExt,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct BindRequest {
@@ -14,6 +23,14 @@ pub struct BindRequest {
     pub password: String,
 }
 
+/// A substring match, as in the LDAP filter `(attr=initial*any1*any2*final)`.
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SubStringFilter {
+    pub initial: Option<String>,
+    pub any: Vec<String>,
+    pub final_: Option<String>,
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub enum UserRequestFilter {
     And(Vec<UserRequestFilter>),
@@ -21,22 +38,48 @@ pub enum UserRequestFilter {
     Not(Box<UserRequestFilter>),
     UserId(UserId),
     Equality(UserColumn, String),
-    // Check if a user belongs to a group identified by name.
+    SubString(UserColumn, SubStringFilter),
+    // Check that the attribute has a value.
+    Present(UserColumn),
+    // Check if a user belongs to a group identified by name, directly or through a subgroup.
     MemberOf(String),
     // Same, by id.
     MemberOfId(GroupId),
+    // Same, by UUID.
+    MemberOfUuid(Uuid),
+    // Check that the user was modified at or after the given date.
+    ModifiedSince(DateTime),
+    // Check that the user existed before the given date, and hasn't logged in since.
+    InactiveSince(DateTime),
+    // Check that the address is one of the secondary email addresses of the user.
+    SecondaryEmail(String),
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone)]
 pub enum GroupRequestFilter {
     And(Vec<GroupRequestFilter>),
     Or(Vec<GroupRequestFilter>),
     Not(Box<GroupRequestFilter>),
     DisplayName(String),
+    DisplayNameSubString(SubStringFilter),
     Uuid(Uuid),
     GroupId(GroupId),
-    // Check if the group contains a user identified by uid.
+    GidNumber(i32),
+    // The address of the group, ignoring case.
+    Mail(String),
+    // Check if the group contains a user identified by uid, directly or through a subgroup.
     Member(UserId),
+    // Check if the group is a direct subgroup of the given group.
+    ParentGroup(GroupId),
+    // Check that the group was modified at or after the given date.
+    ModifiedSince(DateTime),
+}
+
+/// How a user logged in, for their last login dates.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum LoginKind {
+    Ldap,
+    Web,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -48,6 +91,26 @@ pub struct CreateUserRequest {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub telephone_number: Option<String>,
+    pub mobile: Option<String>,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub locality: Option<String>,
+    pub postal_address: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub valid_from: Option<DateTime>,
+    pub valid_until: Option<DateTime>,
+    pub attributes: Vec<AttributeValue>,
+    /// A password hash imported from another directory (bcrypt, argon2, SSHA...), replaced by an
+    /// OPAQUE password on the first successful bind.
+    pub legacy_password_hash: Option<String>,
+    /// Name of the user template of the configuration whose defaults apply.
+    pub template: Option<String>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -59,12 +122,92 @@ pub struct UpdateUserRequest {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub telephone_number: Option<String>,
+    pub mobile: Option<String>,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub locality: Option<String>,
+    pub postal_address: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub ssh_public_keys: Option<SshPublicKeys>,
+    /// Replaces the secondary email addresses.
+    pub secondary_emails: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+    /// `Some(None)` removes the start of the validity period.
+    pub valid_from: Option<Option<DateTime>>,
+    /// `Some(None)` removes the end of the validity period.
+    pub valid_until: Option<Option<DateTime>>,
+    /// Attributes to set, replacing any existing value.
+    pub insert_attributes: Vec<AttributeValue>,
+    /// Names of the attributes to remove.
+    pub delete_attributes: Vec<String>,
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
 pub struct UpdateGroupRequest {
     pub group_id: GroupId,
     pub display_name: Option<String>,
+    pub gid_number: Option<i32>,
+    /// An empty value removes the description.
+    pub description: Option<String>,
+    /// An empty value removes the address.
+    pub mail: Option<String>,
+    /// Attributes to set, replacing any existing value.
+    pub insert_attributes: Vec<AttributeValue>,
+    /// Names of the attributes to remove.
+    pub delete_attributes: Vec<String>,
+}
+
+/// The direction in which a list is sorted.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// The order in which users are listed. Users with the same value are sorted by user ID.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct UserOrder {
+    pub column: UserColumn,
+    pub direction: SortDirection,
+}
+
+impl Default for UserOrder {
+    fn default() -> Self {
+        Self {
+            column: UserColumn::UserId,
+            direction: SortDirection::Ascending,
+        }
+    }
+}
+
+/// The order in which groups are listed. Groups with the same value are sorted by group ID.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct GroupOrder {
+    pub column: GroupColumn,
+    pub direction: SortDirection,
+}
+
+impl Default for GroupOrder {
+    fn default() -> Self {
+        Self {
+            column: GroupColumn::DisplayName,
+            direction: SortDirection::Ascending,
+        }
+    }
+}
+
+/// A window of the users matching a filter, see `UserBackendHandler::list_users_page`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UserPage {
+    pub users: Vec<UserAndGroups>,
+    /// Number of users matching the filter, in all the pages.
+    pub total_count: u64,
 }
 
 #[async_trait]
@@ -75,10 +218,18 @@ pub trait LoginHandler: Clone + Send {
 #[async_trait]
 pub trait GroupBackendHandler {
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+    /// Same as `list_groups`, in the given order instead of by display name.
+    async fn list_groups_sorted(
+        &self,
+        filters: Option<GroupRequestFilter>,
+        order: GroupOrder,
+    ) -> Result<Vec<Group>>;
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, group_name: &str) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+    async fn add_group_to_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
+    async fn remove_group_from_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
 }
 
 #[async_trait]
@@ -88,17 +239,447 @@ pub trait UserBackendHandler {
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>>;
+    /// Same as `list_users`, but the users are produced one by one, so that implementations can
+    /// avoid holding all of them in memory at once.
+    fn stream_users<'a>(
+        &'a self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+    ) -> LocalBoxStream<'a, Result<UserAndGroups>> {
+        stream::once(self.list_users(filters, get_groups))
+            .map_ok(|users| stream::iter(users.into_iter().map(Ok::<_, DomainError>)))
+            .try_flatten()
+            .boxed_local()
+    }
+    /// Same as `list_users`, in the given order, but skips the first `offset` users and returns
+    /// at most `limit` of them, along with the total number of users matching the filter.
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        order: UserOrder,
+        offset: u64,
+        limit: Option<u64>,
+    ) -> Result<UserPage>;
     async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
+    /// Same as `get_user_details`, but never from a read replica, which can lag behind: for the
+    /// login and permission checks.
+    async fn get_user_details_from_primary(&self, user_id: &UserId) -> Result<User> {
+        self.get_user_details(user_id).await
+    }
     async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+    /// Creates the users in a single transaction, and returns the result of each creation, in
+    /// order: a failure only prevents the creation of the corresponding user.
+    async fn create_users(&self, requests: Vec<CreateUserRequest>) -> Result<Vec<Result<()>>>;
+    /// Checks a new user ID, as given by the client before its conversion to lowercase, against
+    /// the username policy. The creations and renames check the converted ID again.
+    fn check_user_id(&self, user_id: &str) -> Result<()>;
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+    /// Adds the SSH public key to the user, unless they already have it.
+    async fn add_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+    /// Removes the SSH public key from the user, failing if they don't have it.
+    async fn remove_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+    /// Renames the user, keeping their password, attributes, memberships and sessions.
+    async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<()>;
     async fn delete_user(&self, user_id: &UserId) -> Result<()>;
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    /// Same as `add_user_to_group`, but the membership is removed after the expiry date.
+    async fn add_user_to_group_until(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: DateTime,
+    ) -> Result<()>;
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    /// Same as `add_user_to_group` for several users at once, in a single transaction: if one of
+    /// the memberships cannot be added, none of them is.
+    async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+    /// Same as `remove_user_from_group` for several users at once, in a single transaction.
+    async fn remove_users_from_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+    /// Same as `get_user_groups`, but never from a read replica: for the login and permission
+    /// checks.
+    async fn get_user_groups_from_primary(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashSet<GroupDetails>> {
+        self.get_user_groups(user_id).await
+    }
+    /// Same as `get_user_groups` for several users at once, in a single query. The users without
+    /// any group, or that don't exist, are missing from the result.
+    async fn get_users_groups(
+        &self,
+        user_ids: &[UserId],
+    ) -> Result<HashMap<UserId, HashSet<GroupDetails>>>;
+    /// Records a successful login of the user. The date is only written if the previous one is a
+    /// few minutes old, to avoid a write on every bind.
+    async fn record_login(&self, user_id: &UserId, kind: LoginKind) -> Result<()>;
+}
+
+#[async_trait]
+pub trait SchemaBackendHandler {
+    async fn get_user_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+    async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+    async fn delete_user_attribute(&self, name: &str) -> Result<()>;
+    async fn get_group_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+    async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+    async fn delete_group_attribute(&self, name: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait ChangeLogBackendHandler {
+    /// Lists the changes recorded after the given change, oldest first.
+    async fn list_changes_since(&self, change_id: i32) -> Result<Vec<Change>>;
+    /// Same as `list_changes_since`, returning at most `limit` changes.
+    async fn list_changes_page(&self, change_id: i32, limit: u64) -> Result<Vec<Change>>;
+    /// Returns the ID of the latest change, or 0 if nothing was recorded yet.
+    async fn get_last_change_id(&self) -> Result<i32>;
+}
+
+/// Who performs the mutating operations of a handler, recorded in the audit log.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AuditActor {
+    pub user: UserId,
+    pub source: AuditSource,
+}
+
+impl AuditActor {
+    pub fn new(user: UserId, source: AuditSource) -> Self {
+        Self { user, source }
+    }
+
+    /// The operations not requested by a user, e.g. the scheduled jobs.
+    pub fn system(source: AuditSource) -> Self {
+        Self::new(UserId::new("system"), source)
+    }
+}
+
+/// A mutating operation to record in the audit log.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AuditEvent {
+    pub actor: UserId,
+    pub source: AuditSource,
+    pub action: String,
+    pub target: String,
+    pub details: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn user_target(user_id: &UserId) -> String {
+        format!("user:{}", user_id)
+    }
+
+    pub fn group_target(group_id: GroupId) -> String {
+        format!("group:{}", group_id.0)
+    }
+}
+
+/// Restricts the audit log entries to list: all the set fields must match.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub actor: Option<UserId>,
+    /// The name of the operation, e.g. "create_user".
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// Only the entries recorded at or after this date.
+    pub since: Option<DateTime>,
+    /// Only the entries recorded before this date.
+    pub until: Option<DateTime>,
+}
+
+/// A window of the audit log, see `AuditLogBackendHandler::list_audit_logs`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AuditLogPage {
+    pub entries: Vec<AuditLogEntry>,
+    /// Number of entries matching the filter, in all the pages.
+    pub total_count: u64,
+}
+
+#[async_trait]
+pub trait AuditLogBackendHandler {
+    /// Records an operation that doesn't go through the other handlers, e.g. a configuration
+    /// change. The handlers record their own mutating operations on behalf of the audit actor.
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+    /// Lists the entries matching the filter, newest first, skipping the first `offset` ones.
+    async fn list_audit_logs(
+        &self,
+        filter: AuditLogFilter,
+        offset: u64,
+        limit: Option<u64>,
+    ) -> Result<AuditLogPage>;
+    /// Sets who performs the next mutating operations, see `AuditActor`.
+    fn set_audit_actor(&mut self, _actor: AuditActor) {}
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scope: ApiTokenScope,
+    pub created_by: UserId,
+}
+
+/// Prefix of the clear-text API tokens, to tell them apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "lldap_";
+
+#[async_trait]
+pub trait ApiTokenBackendHandler {
+    /// Creates a token, and returns it along with its clear-text value, which can't be retrieved
+    /// later.
+    async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+    async fn delete_api_token(&self, token_id: i32) -> Result<()>;
+    /// Returns the token with the given clear-text value, or an `AuthenticationError`.
+    async fn validate_api_token(&self, token: &str) -> Result<ApiToken>;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct CreateServiceAccountRequest {
+    pub service_id: String,
+    pub description: Option<String>,
+    pub read_all: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct UpdateServiceAccountRequest {
+    pub service_id: String,
+    /// An empty description removes it.
+    pub description: Option<String>,
+    pub read_all: Option<bool>,
+}
+
+/// The accounts of the applications binding to the LDAP server, kept apart from the users.
+#[async_trait]
+pub trait ServiceAccountBackendHandler {
+    /// Creates the account, and returns it along with its generated password, which can't be
+    /// retrieved later.
+    async fn create_service_account(
+        &self,
+        request: CreateServiceAccountRequest,
+    ) -> Result<(ServiceAccount, String)>;
+    async fn list_service_accounts(&self) -> Result<Vec<ServiceAccount>>;
+    async fn update_service_account(&self, request: UpdateServiceAccountRequest) -> Result<()>;
+    /// Replaces the password with a newly generated one, and returns it.
+    async fn reset_service_account_password(&self, service_id: &str) -> Result<String>;
+    async fn delete_service_account(&self, service_id: &str) -> Result<()>;
+    /// Returns the account if the password matches, or an `AuthenticationError`.
+    async fn bind_service_account(
+        &self,
+        service_id: &str,
+        password: &str,
+    ) -> Result<ServiceAccount>;
+}
+
+#[async_trait]
+pub trait SessionBackendHandler {
+    /// Lists the sessions of the user that haven't expired, most recently used first.
+    async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+    /// Deletes the session, if it belongs to the user. The JWTs already obtained from it stay
+    /// valid until they expire.
+    async fn delete_user_session(&self, user_id: &UserId, session_id: i64) -> Result<()>;
+    async fn delete_all_user_sessions(&self, user_id: &UserId) -> Result<()>;
+}
+
+#[async_trait]
+pub trait PasswordPolicyBackendHandler {
+    /// The requirements for the new passwords.
+    fn get_password_policy(&self) -> PasswordPolicyOptions;
+    /// Checks a new password of the user against the policy, including their previous passwords.
+    /// Returns an `InvalidRequest` listing the unmet requirements.
+    async fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result<()>;
+    /// Adds the new password of the user to their history, forgetting the ones beyond the history
+    /// depth.
+    async fn add_password_to_history(&self, user_id: &UserId, password: &str) -> Result<()>;
+}
+
+#[async_trait]
+pub trait AccountLockoutBackendHandler {
+    /// Returns the end of the lockout of the account, if it is currently locked after too many
+    /// failed logins.
+    async fn get_account_lockout(&self, user_id: &UserId) -> Result<Option<DateTime>>;
+    /// Lifts the lockout of the account, and forgets its failed logins.
+    async fn unlock_account(&self, user_id: &UserId) -> Result<()>;
+}
+
+#[async_trait]
+pub trait PasswordResetBackendHandler {
+    /// Creates a token to reset the password of the user, valid for the given duration, and returns
+    /// it. Only the last token of the user is valid. Returns `EntityNotFound` if the user doesn't
+    /// exist.
+    async fn create_password_reset_token(
+        &self,
+        user_id: &UserId,
+        validity: chrono::Duration,
+    ) -> Result<String>;
+}
+
+#[async_trait]
+pub trait WebauthnCredentialBackendHandler {
+    /// Lists the WebAuthn credentials registered by the user.
+    async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+    /// Deletes the credential, if it belongs to the user.
+    async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str)
+        -> Result<()>;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    /// The groups the invitee joins when accepting.
+    pub group_ids: Vec<GroupId>,
+    pub created_by: UserId,
+    /// The user template applied to the account of the invitee.
+    pub template: Option<String>,
+}
+
+#[async_trait]
+pub trait InvitationBackendHandler {
+    /// Creates an invitation to create an account, and returns its secret token.
+    async fn create_invitation(&self, request: CreateInvitationRequest) -> Result<String>;
+    /// Returns the invitation with the token, or `EntityNotFound` if it doesn't exist or expired.
+    async fn get_invitation(&self, token: &str) -> Result<Invitation>;
+    /// Creates the account of the invitee, with the chosen ID and without a password, in the
+    /// invited groups. The invitation cannot be used again.
+    async fn accept_invitation(&self, token: &str, user_id: &UserId) -> Result<()>;
+    async fn delete_invitation(&self, token: &str) -> Result<()>;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct CreatePendingUserRequest {
+    pub user_id: UserId,
+    pub email: String,
+    pub display_name: Option<String>,
+}
+
+#[async_trait]
+pub trait PendingUserBackendHandler {
+    /// Records a sign-up request. Fails if the user ID or the email is already taken.
+    async fn create_pending_user(&self, request: CreatePendingUserRequest) -> Result<()>;
+    async fn list_pending_users(&self) -> Result<Vec<PendingUser>>;
+    /// Creates the account of the pending user, without a password, and removes the request.
+    async fn approve_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+    /// Removes the request, returning it to notify the user.
+    async fn reject_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+}
+
+#[async_trait]
+pub trait RecycleBinBackendHandler {
+    async fn list_deleted_users(&self) -> Result<Vec<DeletedUser>>;
+    /// Recreates the user with its attributes and its memberships of the groups that still exist.
+    /// Fails if the user ID was taken since the deletion.
+    async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+    /// Removes the user from the recycle bin, for good.
+    async fn purge_user(&self, user_id: &UserId) -> Result<()>;
+    async fn list_deleted_groups(&self) -> Result<Vec<DeletedGroup>>;
+    /// Recreates the group with its attributes and its members that still exist.
+    async fn restore_group(&self, group_id: GroupId) -> Result<()>;
+    async fn purge_group(&self, group_id: GroupId) -> Result<()>;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct CreateRoleRequest {
+    pub display_name: String,
+    pub read_all: bool,
+    pub reset_passwords: bool,
+    pub managed_group_id: Option<GroupId>,
+}
+
+#[async_trait]
+pub trait RoleBackendHandler {
+    async fn list_roles(&self) -> Result<Vec<Role>>;
+    async fn create_role(&self, request: CreateRoleRequest) -> Result<i32>;
+    async fn delete_role(&self, role_id: i32) -> Result<()>;
+    /// Grants the role to the members of the group.
+    async fn add_role_to_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+    async fn remove_role_from_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+    /// The combined capabilities of the roles held by the user through their groups, and of the
+    /// groups they manage.
+    async fn get_user_capabilities(&self, user_id: &UserId) -> Result<Capabilities>;
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct CreateOidcClientRequest {
+    pub client_id: String,
+    pub display_name: String,
+    pub redirect_uris: Vec<String>,
+}
+
+/// The clients of the OpenID Connect provider, and the authorization codes they exchange for the
+/// tokens of the users.
+#[async_trait]
+pub trait OidcBackendHandler {
+    /// Registers the client, returning its generated secret.
+    async fn create_oidc_client(&self, request: CreateOidcClientRequest) -> Result<String>;
+    async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>>;
+    async fn get_oidc_client(&self, client_id: &str) -> Result<OidcClient>;
+    async fn delete_oidc_client(&self, client_id: &str) -> Result<()>;
+    async fn list_oidc_group_claims(&self, client_id: &str) -> Result<Vec<OidcGroupClaim>>;
+    async fn add_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+    async fn remove_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+    /// Stores what the user authorized, returning the single-use code given to the client.
+    async fn create_oidc_authorization_code(&self, code: OidcAuthorizationCode) -> Result<String>;
+    /// Returns and deletes the code, if it is not expired.
+    async fn consume_oidc_authorization_code(&self, code: &str) -> Result<OidcAuthorizationCode>;
+}
+
+/// The group managers can add and remove the members of their groups, without being admins.
+#[async_trait]
+pub trait GroupManagerBackendHandler {
+    async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+    async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+}
+
+#[async_trait]
+pub trait StatisticsBackendHandler {
+    /// Counts of the users and groups, computed by the database.
+    async fn get_statistics(&self) -> Result<DirectoryStatistics>;
+}
+
+/// A change of a batch applied by `apply_changes`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum DirectoryOperation {
+    CreateUser(CreateUserRequest),
+    UpdateUser(UpdateUserRequest),
+    CreateGroup(String),
+    UpdateGroup(UpdateGroupRequest),
+    AddUserToGroup { user_id: UserId, group_id: GroupId },
+    RemoveUserFromGroup { user_id: UserId, group_id: GroupId },
+}
+
+#[async_trait]
+pub trait DirectoryChangesBackendHandler {
+    /// Applies the operations in order, in a single transaction: if one of them fails, none is
+    /// applied and its error is returned, prefixed with its index. Returns the IDs of the
+    /// created groups, in order.
+    async fn apply_changes(&self, operations: Vec<DirectoryOperation>) -> Result<Vec<GroupId>>;
 }
 
 #[async_trait]
-pub trait BackendHandler: Clone + Send + GroupBackendHandler + UserBackendHandler {}
+pub trait BackendHandler:
+    Clone
+    + Send
+    + GroupBackendHandler
+    + UserBackendHandler
+    + SchemaBackendHandler
+    + ChangeLogBackendHandler
+    + AuditLogBackendHandler
+    + ApiTokenBackendHandler
+    + ServiceAccountBackendHandler
+    + SessionBackendHandler
+    + PasswordPolicyBackendHandler
+    + AccountLockoutBackendHandler
+    + PasswordResetBackendHandler
+    + WebauthnCredentialBackendHandler
+    + InvitationBackendHandler
+    + PendingUserBackendHandler
+    + RecycleBinBackendHandler
+    + RoleBackendHandler
+    + GroupManagerBackendHandler
+    + OidcBackendHandler
+    + StatisticsBackendHandler
+    + DirectoryChangesBackendHandler
+{
+}
 
 #[cfg(test)]
 mockall::mock! {
@@ -109,21 +690,155 @@ mockall::mock! {
     #[async_trait]
     impl GroupBackendHandler for TestBackendHandler {
         async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+        async fn list_groups_sorted(&self, filters: Option<GroupRequestFilter>, order: GroupOrder) -> Result<Vec<Group>>;
         async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, group_name: &str) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        async fn add_group_to_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
     }
     #[async_trait]
     impl UserBackendHandler for TestBackendHandler {
         async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_page(&self, filters: Option<UserRequestFilter>, get_groups: bool, order: UserOrder, offset: u64, limit: Option<u64>) -> Result<UserPage>;
         async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
         async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+        async fn create_users(&self, requests: Vec<CreateUserRequest>) -> Result<Vec<Result<()>>>;
+        fn check_user_id(&self, user_id: &str) -> Result<()>;
         async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+        async fn add_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+        async fn remove_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+        async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<()>;
         async fn delete_user(&self, user_id: &UserId) -> Result<()>;
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+        async fn get_users_groups(&self, user_ids: &[UserId]) -> Result<HashMap<UserId, HashSet<GroupDetails>>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn add_user_to_group_until(&self, user_id: &UserId, group_id: GroupId, expiry_date: DateTime) -> Result<()>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+        async fn remove_users_from_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+        async fn record_login(&self, user_id: &UserId, kind: LoginKind) -> Result<()>;
+    }
+    #[async_trait]
+    impl SchemaBackendHandler for TestBackendHandler {
+        async fn get_user_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_user_attribute(&self, name: &str) -> Result<()>;
+        async fn get_group_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_group_attribute(&self, name: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl ChangeLogBackendHandler for TestBackendHandler {
+        async fn list_changes_since(&self, change_id: i32) -> Result<Vec<Change>>;
+        async fn list_changes_page(&self, change_id: i32, limit: u64) -> Result<Vec<Change>>;
+        async fn get_last_change_id(&self) -> Result<i32>;
+    }
+    #[async_trait]
+    impl AuditLogBackendHandler for TestBackendHandler {
+        async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+        async fn list_audit_logs(&self, filter: AuditLogFilter, offset: u64, limit: Option<u64>) -> Result<AuditLogPage>;
+    }
+    #[async_trait]
+    impl ApiTokenBackendHandler for TestBackendHandler {
+        async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+        async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+        async fn delete_api_token(&self, token_id: i32) -> Result<()>;
+        async fn validate_api_token(&self, token: &str) -> Result<ApiToken>;
+    }
+    #[async_trait]
+    impl ServiceAccountBackendHandler for TestBackendHandler {
+        async fn create_service_account(&self, request: CreateServiceAccountRequest) -> Result<(ServiceAccount, String)>;
+        async fn list_service_accounts(&self) -> Result<Vec<ServiceAccount>>;
+        async fn update_service_account(&self, request: UpdateServiceAccountRequest) -> Result<()>;
+        async fn reset_service_account_password(&self, service_id: &str) -> Result<String>;
+        async fn delete_service_account(&self, service_id: &str) -> Result<()>;
+        async fn bind_service_account(&self, service_id: &str, password: &str) -> Result<ServiceAccount>;
+    }
+    #[async_trait]
+    impl SessionBackendHandler for TestBackendHandler {
+        async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+        async fn delete_user_session(&self, user_id: &UserId, session_id: i64) -> Result<()>;
+        async fn delete_all_user_sessions(&self, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl PasswordPolicyBackendHandler for TestBackendHandler {
+        fn get_password_policy(&self) -> PasswordPolicyOptions;
+        async fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result<()>;
+        async fn add_password_to_history(&self, user_id: &UserId, password: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl AccountLockoutBackendHandler for TestBackendHandler {
+        async fn get_account_lockout(&self, user_id: &UserId) -> Result<Option<DateTime>>;
+        async fn unlock_account(&self, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl PasswordResetBackendHandler for TestBackendHandler {
+        async fn create_password_reset_token(&self, user_id: &UserId, validity: chrono::Duration) -> Result<String>;
+    }
+    #[async_trait]
+    impl WebauthnCredentialBackendHandler for TestBackendHandler {
+        async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+        async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl InvitationBackendHandler for TestBackendHandler {
+        async fn create_invitation(&self, request: CreateInvitationRequest) -> Result<String>;
+        async fn get_invitation(&self, token: &str) -> Result<Invitation>;
+        async fn accept_invitation(&self, token: &str, user_id: &UserId) -> Result<()>;
+        async fn delete_invitation(&self, token: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl PendingUserBackendHandler for TestBackendHandler {
+        async fn create_pending_user(&self, request: CreatePendingUserRequest) -> Result<()>;
+        async fn list_pending_users(&self) -> Result<Vec<PendingUser>>;
+        async fn approve_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+        async fn reject_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+    }
+    #[async_trait]
+    impl RecycleBinBackendHandler for TestBackendHandler {
+        async fn list_deleted_users(&self) -> Result<Vec<DeletedUser>>;
+        async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+        async fn purge_user(&self, user_id: &UserId) -> Result<()>;
+        async fn list_deleted_groups(&self) -> Result<Vec<DeletedGroup>>;
+        async fn restore_group(&self, group_id: GroupId) -> Result<()>;
+        async fn purge_group(&self, group_id: GroupId) -> Result<()>;
+    }
+    #[async_trait]
+    impl RoleBackendHandler for TestBackendHandler {
+        async fn list_roles(&self) -> Result<Vec<Role>>;
+        async fn create_role(&self, request: CreateRoleRequest) -> Result<i32>;
+        async fn delete_role(&self, role_id: i32) -> Result<()>;
+        async fn add_role_to_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+        async fn remove_role_from_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+        async fn get_user_capabilities(&self, user_id: &UserId) -> Result<Capabilities>;
+    }
+    #[async_trait]
+    impl GroupManagerBackendHandler for TestBackendHandler {
+        async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+        async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl OidcBackendHandler for TestBackendHandler {
+        async fn create_oidc_client(&self, request: CreateOidcClientRequest) -> Result<String>;
+        async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>>;
+        async fn get_oidc_client(&self, client_id: &str) -> Result<OidcClient>;
+        async fn delete_oidc_client(&self, client_id: &str) -> Result<()>;
+        async fn list_oidc_group_claims(&self, client_id: &str) -> Result<Vec<OidcGroupClaim>>;
+        async fn add_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+        async fn remove_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+        async fn create_oidc_authorization_code(&self, code: OidcAuthorizationCode) -> Result<String>;
+        async fn consume_oidc_authorization_code(&self, code: &str) -> Result<OidcAuthorizationCode>;
+    }
+    #[async_trait]
+    impl StatisticsBackendHandler for TestBackendHandler {
+        async fn get_statistics(&self) -> Result<DirectoryStatistics>;
+    }
+    #[async_trait]
+    impl DirectoryChangesBackendHandler for TestBackendHandler {
+        async fn apply_changes(&self, operations: Vec<DirectoryOperation>) -> Result<Vec<GroupId>>;
     }
     #[async_trait]
     impl BackendHandler for TestBackendHandler {}
@@ -6,36 +6,70 @@ use tracing::{debug, info, instrument, warn};
 use crate::domain::{
     handler::{BackendHandler, GroupRequestFilter},
     ldap::error::LdapError,
-    types::{Group, GroupColumn, UserId, Uuid},
+    types::{AttributeSchema, Group, GroupColumn, UserId},
 };
 
 use super::{
     error::LdapResult,
     utils::{
-        expand_attribute_wildcards, get_user_id_from_distinguished_name, map_group_field, LdapInfo,
+        convert_extensible_match, expand_attribute_wildcards, get_custom_attribute,
+        map_group_field, parse_ldap_timestamp, parse_uuid, LdapInfo,
     },
 };
 
+// In the Active Directory compatibility mode, sAMAccountName is the name of the group.
+fn map_active_directory_field(ldap_info: &LdapInfo, field: String) -> String {
+    if ldap_info.active_directory.is_some() && field == "samaccountname" {
+        "cn".to_owned()
+    } else {
+        field
+    }
+}
+
 fn get_group_attribute(
     group: &Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attribute: &str,
     user_filter: &Option<&UserId>,
-    ignored_group_attributes: &[String],
+    schema: &[AttributeSchema],
 ) -> Option<Vec<Vec<u8>>> {
     let attribute = attribute.to_ascii_lowercase();
+    if ldap_info.membership_options.is_member_attribute(&attribute) {
+        return Some(
+            group
+                .users
+                .iter()
+                .filter(|u| user_filter.map(|f| *u == f).unwrap_or(true))
+                .map(|u| ldap_info.member_value(u.as_str()))
+                .collect(),
+        );
+    }
     let attribute_values = match attribute.as_str() {
-        "objectclass" => vec![b"groupOfUniqueNames".to_vec()],
+        "objectclass" => {
+            let mut classes = vec![b"groupOfUniqueNames".to_vec()];
+            if group.gid_number.is_some() {
+                classes.push(b"posixGroup".to_vec());
+            }
+            if ldap_info.active_directory.is_some() {
+                classes.extend([b"top".to_vec(), b"group".to_vec()]);
+            }
+            classes
+        }
         // Always returned as part of the base response.
         "dn" | "distinguishedname" => return None,
         "cn" | "uid" => vec![group.display_name.clone().into_bytes()],
+        "samaccountname" if ldap_info.active_directory.is_some() => {
+            vec![group.display_name.clone().into_bytes()]
+        }
+        "objectcategory" if ldap_info.active_directory.is_some() => {
+            vec![ldap_info.object_category("group").into_bytes()]
+        }
         "entryuuid" => vec![group.uuid.to_string().into_bytes()],
-        "member" | "uniquemember" => group
-            .users
-            .iter()
-            .filter(|u| user_filter.map(|f| *u == f).unwrap_or(true))
-            .map(|u| format!("uid={},ou=people,{}", u, base_dn_str).into_bytes())
-            .collect(),
+        "gidnumber" => vec![group.gid_number?.to_string().into_bytes()],
+        "description" => vec![group.description.clone()?.into_bytes()],
+        "mail" => vec![group.mail.clone()?.into_bytes()],
+        "createtimestamp" => vec![group.creation_date.to_rfc3339().into_bytes()],
+        "modifytimestamp" => vec![group.modified_date.to_rfc3339().into_bytes()],
         "1.1" => return None,
         // We ignore the operational attribute wildcard
         "+" => return None,
@@ -46,7 +80,10 @@ fn get_group_attribute(
             )
         }
         _ => {
-            if !ignored_group_attributes.contains(&attribute) {
+            if let Some(attribute_schema) = schema.iter().find(|s| s.name == attribute) {
+                return get_custom_attribute(&group.attributes, attribute_schema);
+            }
+            if !ldap_info.ignored_group_attributes.contains(&attribute) {
                 warn!(
                     r#"Ignoring unrecognized group attribute: {}\n\
                       To disable this warning, add it to "ignored_group_attributes" in the config."#,
@@ -63,36 +100,40 @@ fn get_group_attribute(
     }
 }
 
-const ALL_GROUP_ATTRIBUTE_KEYS: &[&str] = &[
-    "objectclass",
-    "uid",
-    "cn",
-    "member",
-    "uniquemember",
-    "entryuuid",
-];
-
 fn make_ldap_search_group_result_entry(
     group: Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attributes: &[String],
     user_filter: &Option<&UserId>,
-    ignored_group_attributes: &[String],
+    schema: &[AttributeSchema],
 ) -> LdapSearchResultEntry {
-    let expanded_attributes = expand_attribute_wildcards(attributes, ALL_GROUP_ATTRIBUTE_KEYS);
+    // The member attributes are configurable, so the list of all attributes is built here.
+    let member_attributes = ldap_info
+        .membership_options
+        .member_attributes
+        .iter()
+        .map(|a| a.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let all_attribute_keys = ["objectclass", "uid", "cn"]
+        .into_iter()
+        .chain(member_attributes.iter().map(String::as_str))
+        .chain(["entryuuid", "gidnumber", "description", "mail"])
+        .chain(
+            ldap_info
+                .active_directory
+                .iter()
+                .flat_map(|_| ["samaccountname", "objectcategory"]),
+        )
+        .chain(schema.iter().map(|s| s.name.as_str()))
+        .collect::<Vec<_>>();
+    let expanded_attributes = expand_attribute_wildcards(attributes, &all_attribute_keys);
 
     LdapSearchResultEntry {
-        dn: format!("cn={},ou=groups,{}", group.display_name, base_dn_str),
+        dn: ldap_info.group_dn(&group.display_name, &group.uuid),
         attributes: expanded_attributes
             .iter()
             .filter_map(|a| {
-                let values = get_group_attribute(
-                    &group,
-                    base_dn_str,
-                    a,
-                    user_filter,
-                    ignored_group_attributes,
-                )?;
+                let values = get_group_attribute(&group, ldap_info, a, user_filter, schema)?;
                 Some(LdapPartialAttribute {
                     atype: a.to_string(),
                     vals: values,
@@ -109,33 +150,43 @@ fn convert_group_filter(
     let rec = |f| convert_group_filter(ldap_info, f);
     match filter {
         LdapFilter::Equality(field, value) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &map_active_directory_field(ldap_info, field.to_ascii_lowercase());
             let value = &value.to_ascii_lowercase();
+            if ldap_info.membership_options.is_member_attribute(field) {
+                let user_name = ldap_info.parse_member_value(value)?;
+                return Ok(GroupRequestFilter::Member(user_name));
+            }
             match field.as_str() {
-                "member" | "uniquemember" => {
-                    let user_name = get_user_id_from_distinguished_name(
-                        value,
-                        &ldap_info.base_dn,
-                        &ldap_info.base_dn_str,
-                    )?;
-                    Ok(GroupRequestFilter::Member(user_name))
-                }
                 "objectclass" => match value.as_str() {
-                    "groupofuniquenames" | "groupofnames" => Ok(GroupRequestFilter::And(vec![])),
+                    "groupofuniquenames" | "groupofnames" | "posixgroup" => {
+                        Ok(GroupRequestFilter::And(vec![]))
+                    }
+                    "group" | "top" if ldap_info.active_directory.is_some() => {
+                        Ok(GroupRequestFilter::And(vec![]))
+                    }
                     _ => Ok(GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(
                         vec![],
                     )))),
                 },
+                "objectcategory" if ldap_info.active_directory.is_some() => {
+                    Ok(if ldap_info.is_object_category(value, "group") {
+                        GroupRequestFilter::And(vec![])
+                    } else {
+                        GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![])))
+                    })
+                }
                 _ => match map_group_field(field) {
                     Some(GroupColumn::DisplayName) => {
                         Ok(GroupRequestFilter::DisplayName(value.to_string()))
                     }
-                    Some(GroupColumn::Uuid) => Ok(GroupRequestFilter::Uuid(
-                        Uuid::try_from(value.as_str()).map_err(|e| LdapError {
-                            code: LdapResultCode::InappropriateMatching,
-                            message: format!("Invalid UUID: {:#}", e),
-                        })?,
-                    )),
+                    Some(GroupColumn::Mail) => Ok(GroupRequestFilter::Mail(value.to_string())),
+                    Some(GroupColumn::GidNumber) => Ok(value
+                        .parse()
+                        .map(GroupRequestFilter::GidNumber)
+                        .unwrap_or_else(|_| {
+                            GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![])))
+                        })),
+                    Some(GroupColumn::Uuid) => Ok(GroupRequestFilter::Uuid(parse_uuid(value)?)),
                     _ => {
                         if !ldap_info.ignored_group_attributes.contains(field) {
                             warn!(
@@ -158,11 +209,39 @@ fn convert_group_filter(
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
         )),
         LdapFilter::Not(filter) => Ok(GroupRequestFilter::Not(Box::new(rec(filter)?))),
+        LdapFilter::Substring(field, substring_filter) => {
+            let field = &map_active_directory_field(ldap_info, field.to_ascii_lowercase());
+            match map_group_field(field) {
+                Some(GroupColumn::DisplayName) => Ok(GroupRequestFilter::DisplayNameSubString(
+                    substring_filter.into(),
+                )),
+                Some(_) => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported group attribute for substring filter: {:?}",
+                        field
+                    ),
+                }),
+                None => {
+                    if !ldap_info.ignored_group_attributes.contains(field) {
+                        warn!(
+                            r#"Ignoring unknown group attribute "{:?}" in filter.\n\
+                                To disable this warning, add it to "ignored_group_attributes" in the config."#,
+                            field
+                        );
+                    }
+                    Ok(GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(
+                        vec![],
+                    ))))
+                }
+            }
+        }
         LdapFilter::Present(field) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &map_active_directory_field(ldap_info, field.to_ascii_lowercase());
             if field == "objectclass"
                 || field == "dn"
                 || field == "distinguishedname"
+                || (field == "objectcategory" && ldap_info.active_directory.is_some())
                 || map_group_field(field).is_some()
             {
                 Ok(GroupRequestFilter::And(vec![]))
@@ -172,6 +251,28 @@ fn convert_group_filter(
                 ))))
             }
         }
+        LdapFilter::Approx(field, value) => convert_group_filter(
+            ldap_info,
+            &LdapFilter::Equality(field.clone(), value.clone()),
+        ),
+        LdapFilter::Extensible(assertion) => convert_group_filter(
+            ldap_info,
+            &convert_extensible_match(assertion, &ldap_info.group_dn_suffix())?,
+        ),
+        LdapFilter::GreaterOrEqual(field, value) => {
+            match map_group_field(&field.to_ascii_lowercase()) {
+                Some(GroupColumn::ModifiedDate) => Ok(GroupRequestFilter::ModifiedSince(
+                    parse_ldap_timestamp(value)?,
+                )),
+                _ => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported group attribute for greater-or-equal filter: {:?}",
+                        field
+                    ),
+                }),
+            }
+        }
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
             message: format!("Unsupported group filter: {:?}", filter),
@@ -186,7 +287,7 @@ pub async fn get_groups_list<Backend: BackendHandler>(
     attributes: &[String],
     base: &str,
     user_filter: &Option<&UserId>,
-    backend: &mut Backend,
+    backend: &Backend,
 ) -> LdapResult<Vec<LdapOp>> {
     debug!(?ldap_filter);
     let filter = convert_group_filter(ldap_info, ldap_filter)?;
@@ -198,6 +299,17 @@ pub async fn get_groups_list<Backend: BackendHandler>(
         }
     };
     debug!(?parsed_filters);
+    let mut schema = backend
+        .get_group_attributes_schema()
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::Other,
+            message: format!("Error while fetching the attributes schema: {:#}", e),
+        })?;
+    if user_filter.is_some() {
+        // Regular users don't get to see the hidden attributes.
+        schema.retain(|s| s.is_visible);
+    }
     let groups = backend
         .list_groups(Some(parsed_filters))
         .await
@@ -211,10 +323,10 @@ pub async fn get_groups_list<Backend: BackendHandler>(
         .map(|u| {
             LdapOp::SearchResultEntry(make_ldap_search_group_result_entry(
                 u,
-                &ldap_info.base_dn_str,
+                ldap_info,
                 attributes,
                 user_filter,
-                &ldap_info.ignored_group_attributes,
+                &schema,
             ))
         })
         .collect::<Vec<_>>())
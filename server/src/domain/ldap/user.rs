@@ -1,55 +1,181 @@
+use futures_util::{
+    stream::{self, LocalBoxStream},
+    StreamExt, TryStreamExt,
+};
 use ldap3_proto::{
     proto::LdapOp, LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
 };
-use tracing::{debug, info, instrument, warn};
+use tracing::{debug, info, warn};
 
 use crate::domain::{
     handler::{BackendHandler, UserRequestFilter},
     ldap::{error::LdapError, utils::expand_attribute_wildcards},
-    types::{GroupDetails, User, UserColumn, UserId},
+    types::{AttributeSchema, DateTime, GroupDetails, User, UserColumn, UserId},
 };
+use crate::infra::configuration::PasswordPolicyOptions;
 
 use super::{
     error::LdapResult,
-    utils::{get_group_id_from_distinguished_name, map_user_field, LdapInfo},
+    utils::{
+        convert_extensible_match, get_custom_attribute, map_user_field, parse_ldap_timestamp,
+        parse_uuid, LdapInfo,
+    },
 };
 
+// The dates of the shadowAccount attributes are in days since the epoch.
+fn shadow_days(date: DateTime) -> Vec<u8> {
+    date.timestamp()
+        .div_euclid(24 * 60 * 60)
+        .to_string()
+        .into_bytes()
+}
+
+/// The attributes of the Active Directory compatibility mode.
+fn get_active_directory_attribute(
+    user: &User,
+    attribute: &str,
+    ldap_info: &LdapInfo,
+    groups: Option<&[GroupDetails]>,
+) -> Option<Vec<Vec<u8>>> {
+    ldap_info.active_directory.as_ref()?;
+    Some(match attribute {
+        "samaccountname" => vec![user.user_id.to_string().into_bytes()],
+        "userprincipalname" => vec![ldap_info
+            .user_principal_name(user.user_id.as_str())?
+            .into_bytes()],
+        "objectcategory" => vec![ldap_info.object_category("person").into_bytes()],
+        // Always the DNs of the groups, whatever the format of the membership values.
+        "memberof" => groups
+            .into_iter()
+            .flatten()
+            .map(|group| {
+                ldap_info
+                    .group_dn(&group.display_name, &group.uuid)
+                    .into_bytes()
+            })
+            .collect(),
+        _ => return None,
+    })
+}
+
 fn get_user_attribute(
     user: &User,
     attribute: &str,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[String],
+    schema: &[AttributeSchema],
+    password_policy: &PasswordPolicyOptions,
 ) -> Option<Vec<Vec<u8>>> {
     let attribute = attribute.to_ascii_lowercase();
+    if let Some(values) = get_active_directory_attribute(user, &attribute, ldap_info, groups) {
+        return Some(values);
+    }
+    if ldap_info
+        .membership_options
+        .is_member_of_attribute(&attribute)
+    {
+        return Some(
+            groups
+                .into_iter()
+                .flatten()
+                .map(|group| ldap_info.member_of_value(group))
+                .collect(),
+        );
+    }
     let attribute_values = match attribute.as_str() {
-        "objectclass" => vec![
-            b"inetOrgPerson".to_vec(),
-            b"posixAccount".to_vec(),
-            b"mailAccount".to_vec(),
-            b"person".to_vec(),
-        ],
+        "objectclass" => {
+            let mut classes = vec![
+                b"inetOrgPerson".to_vec(),
+                b"posixAccount".to_vec(),
+                b"mailAccount".to_vec(),
+                b"person".to_vec(),
+            ];
+            if !user.ssh_public_keys.is_empty() {
+                classes.push(b"ldapPublicKey".to_vec());
+            }
+            if user.password_modified_date.is_some() {
+                classes.push(b"shadowAccount".to_vec());
+            }
+            if ldap_info.active_directory.is_some() {
+                classes.extend([
+                    b"top".to_vec(),
+                    b"organizationalPerson".to_vec(),
+                    b"user".to_vec(),
+                ]);
+            }
+            classes
+        }
         // dn is always returned as part of the base response.
         "dn" | "distinguishedname" => return None,
         "uid" => vec![user.user_id.to_string().into_bytes()],
         "entryuuid" => vec![user.uuid.to_string().into_bytes()],
         "mail" => vec![user.email.clone().into_bytes()],
+        // The secondary addresses, under the names used by Active Directory and by the mail
+        // servers' schemas.
+        "othermailbox" | "mailalias" => {
+            if user.secondary_emails.is_empty() {
+                return None;
+            }
+            user.secondary_emails
+                .iter()
+                .map(|e| e.clone().into_bytes())
+                .collect()
+        }
         "givenname" => vec![user.first_name.clone()?.into_bytes()],
         "sn" => vec![user.last_name.clone()?.into_bytes()],
         "jpegphoto" => vec![user.avatar.clone()?.into_bytes()],
-        "memberof" => groups
-            .into_iter()
-            .flatten()
-            .map(|id_and_name| {
-                format!(
-                    "uid={},ou=groups,{}",
-                    &id_and_name.display_name, base_dn_str
-                )
-                .into_bytes()
-            })
-            .collect(),
         "cn" | "displayname" => vec![user.display_name.clone()?.into_bytes()],
-        "createtimestamp" | "modifytimestamp" => vec![user.creation_date.to_rfc3339().into_bytes()],
+        "createtimestamp" => vec![user.creation_date.to_rfc3339().into_bytes()],
+        "modifytimestamp" => vec![user.modified_date.to_rfc3339().into_bytes()],
+        // Same as the lastbind overlay of OpenLDAP, but also counting the web logins.
+        "authtimestamp" => vec![user
+            .last_ldap_login
+            .max(user.last_web_login)?
+            .to_rfc3339()
+            .into_bytes()],
+        "uidnumber" => vec![user.uid_number?.to_string().into_bytes()],
+        "gidnumber" => vec![user.gid_number?.to_string().into_bytes()],
+        "homedirectory" => vec![user.home_directory.clone()?.into_bytes()],
+        "loginshell" => vec![user.login_shell.clone()?.into_bytes()],
+        "telephonenumber" => vec![user.telephone_number.clone()?.into_bytes()],
+        "mobile" => vec![user.mobile.clone()?.into_bytes()],
+        "title" => vec![user.title.clone()?.into_bytes()],
+        "ou" | "department" => vec![user.department.clone()?.into_bytes()],
+        "l" => vec![user.locality.clone()?.into_bytes()],
+        "postaladdress" => vec![user.postal_address.clone()?.into_bytes()],
+        "preferredlanguage" => vec![user.locale.clone()?.into_bytes()],
+        // Same as 389-ds: only present on disabled accounts.
+        "nsaccountlock" => {
+            if user.enabled {
+                return None;
+            }
+            vec![b"TRUE".to_vec()]
+        }
+        "shadowlastchange" => vec![shadow_days(user.password_modified_date?)],
+        // The password expiry only applies to the users with a password.
+        "shadowmax"
+            if user.password_modified_date.is_some() && password_policy.max_age_days > 0 =>
+        {
+            vec![password_policy.max_age_days.to_string().into_bytes()]
+        }
+        "shadowwarning"
+            if user.password_modified_date.is_some()
+                && password_policy.max_age_days > 0
+                && password_policy.expiry_warning_days > 0 =>
+        {
+            vec![password_policy.expiry_warning_days.to_string().into_bytes()]
+        }
+        "shadowmax" | "shadowwarning" => return None,
+        "shadowexpire" => vec![shadow_days(user.valid_until?)],
+        "sshpublickey" => {
+            if user.ssh_public_keys.is_empty() {
+                return None;
+            }
+            user.ssh_public_keys
+                .iter()
+                .map(|k| k.clone().into_bytes())
+                .collect()
+        }
         "1.1" => return None,
         // We ignore the operational attribute wildcard.
         "+" => return None,
@@ -60,7 +186,10 @@ fn get_user_attribute(
             )
         }
         _ => {
-            if !ignored_user_attributes.contains(&attribute) {
+            if let Some(attribute_schema) = schema.iter().find(|s| s.name == attribute) {
+                return get_custom_attribute(&user.attributes, attribute_schema);
+            }
+            if !ldap_info.ignored_user_attributes.contains(&attribute) {
                 warn!(
                     r#"Ignoring unrecognized group attribute: {}\n\
                       To disable this warning, add it to "ignored_user_attributes" in the config."#,
@@ -81,30 +210,49 @@ const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "objectclass",
     "uid",
     "mail",
+    "mailalias",
     "givenname",
     "sn",
     "cn",
     "jpegPhoto",
     "createtimestamp",
     "entryuuid",
+    "uidnumber",
+    "gidnumber",
+    "homedirectory",
+    "loginshell",
+    "telephonenumber",
+    "mobile",
+    "title",
+    "ou",
+    "l",
+    "postaladdress",
+    "preferredlanguage",
+    "shadowlastchange",
+    "shadowmax",
+    "shadowwarning",
+    "shadowexpire",
+    "sshpublickey",
 ];
 
+const ACTIVE_DIRECTORY_USER_ATTRIBUTE_KEYS: &[&str] =
+    &["samaccountname", "userprincipalname", "objectcategory"];
+
 fn make_ldap_search_user_result_entry(
     user: User,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attributes: &[&str],
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[String],
+    schema: &[AttributeSchema],
+    password_policy: &PasswordPolicyOptions,
 ) -> LdapSearchResultEntry {
-    let dn = format!("uid={},ou=people,{}", user.user_id.as_str(), base_dn_str);
-
     LdapSearchResultEntry {
-        dn,
+        dn: ldap_info.user_dn(user.user_id.as_str()),
         attributes: attributes
             .iter()
             .filter_map(|a| {
                 let values =
-                    get_user_attribute(&user, a, base_dn_str, groups, ignored_user_attributes)?;
+                    get_user_attribute(&user, a, ldap_info, groups, schema, password_policy)?;
                 Some(LdapPartialAttribute {
                     atype: a.to_string(),
                     vals: values,
@@ -126,25 +274,53 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
         LdapFilter::Not(filter) => Ok(UserRequestFilter::Not(Box::new(rec(filter)?))),
         LdapFilter::Equality(field, value) => {
             let field = &field.to_ascii_lowercase();
-            match field.as_str() {
-                "memberof" => {
-                    let group_name = get_group_id_from_distinguished_name(
-                        &value.to_ascii_lowercase(),
-                        &ldap_info.base_dn,
-                        &ldap_info.base_dn_str,
-                    )?;
-                    Ok(UserRequestFilter::MemberOf(group_name))
+            if ldap_info.active_directory.is_some() {
+                match field.as_str() {
+                    "samaccountname" => return Ok(UserRequestFilter::UserId(UserId::new(value))),
+                    "userprincipalname" => {
+                        return Ok(match ldap_info.parse_user_principal_name(value) {
+                            Some(user_id) => UserRequestFilter::UserId(user_id),
+                            None => {
+                                UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![])))
+                            }
+                        })
+                    }
+                    "objectcategory" => {
+                        return Ok(if ldap_info.is_object_category(value, "person") {
+                            UserRequestFilter::And(vec![])
+                        } else {
+                            UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![])))
+                        })
+                    }
+                    "memberof" => return ldap_info.parse_member_of_dn(&value.to_ascii_lowercase()),
+                    _ => {}
                 }
+            }
+            if ldap_info.membership_options.is_member_of_attribute(field) {
+                return ldap_info.parse_member_of_value(&value.to_ascii_lowercase());
+            }
+            match field.as_str() {
                 "objectclass" => match value.to_ascii_lowercase().as_str() {
-                    "person" | "inetorgperson" | "posixaccount" | "mailaccount" => {
+                    "person" | "inetorgperson" | "posixaccount" | "mailaccount"
+                    | "ldappublickey" => Ok(UserRequestFilter::And(vec![])),
+                    "user" | "organizationalperson" | "top"
+                        if ldap_info.active_directory.is_some() =>
+                    {
                         Ok(UserRequestFilter::And(vec![]))
                     }
                     _ => Ok(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
                         vec![],
                     )))),
                 },
+                "othermailbox" | "mailalias" => {
+                    Ok(UserRequestFilter::SecondaryEmail(value.clone()))
+                }
                 _ => match map_user_field(field) {
                     Some(UserColumn::UserId) => Ok(UserRequestFilter::UserId(UserId::new(value))),
+                    Some(UserColumn::Uuid) => Ok(UserRequestFilter::Equality(
+                        UserColumn::Uuid,
+                        parse_uuid(value)?.into_string(),
+                    )),
                     Some(field) => Ok(UserRequestFilter::Equality(field, value.clone())),
                     None => {
                         if !ldap_info.ignored_user_attributes.contains(field) {
@@ -161,21 +337,85 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
                 },
             }
         }
+        LdapFilter::Substring(field, substring_filter) => {
+            let mut field = field.to_ascii_lowercase();
+            if ldap_info.active_directory.is_some() && field == "samaccountname" {
+                field = "uid".to_owned();
+            }
+            let field = &field;
+            match map_user_field(field) {
+                Some(
+                    column @ (UserColumn::UserId
+                    | UserColumn::Email
+                    | UserColumn::DisplayName
+                    | UserColumn::FirstName
+                    | UserColumn::LastName
+                    | UserColumn::HomeDirectory
+                    | UserColumn::LoginShell),
+                ) => Ok(UserRequestFilter::SubString(
+                    column,
+                    substring_filter.into(),
+                )),
+                Some(_) => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported user attribute for substring filter: {:?}",
+                        field
+                    ),
+                }),
+                None => {
+                    if !ldap_info.ignored_user_attributes.contains(field) {
+                        warn!(
+                            r#"Ignoring unknown user attribute "{}" in filter.\n\
+                                  To disable this warning, add it to "ignored_user_attributes" in the config"#,
+                            field
+                        );
+                    }
+                    Ok(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
+                        vec![],
+                    ))))
+                }
+            }
+        }
         LdapFilter::Present(field) => {
             let field = &field.to_ascii_lowercase();
             // Check that it's a field we support.
-            if field == "objectclass"
-                || field == "dn"
-                || field == "distinguishedname"
-                || map_user_field(field).is_some()
+            if field == "objectclass" || field == "dn" || field == "distinguishedname" {
+                Ok(UserRequestFilter::And(vec![]))
+            } else if ldap_info.active_directory.is_some()
+                && ACTIVE_DIRECTORY_USER_ATTRIBUTE_KEYS.contains(&field.as_str())
             {
                 Ok(UserRequestFilter::And(vec![]))
+            } else if let Some(column) = map_user_field(field) {
+                Ok(UserRequestFilter::Present(column))
             } else {
                 Ok(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
                     vec![],
                 ))))
             }
         }
+        LdapFilter::Approx(field, value) => convert_user_filter(
+            ldap_info,
+            &LdapFilter::Equality(field.clone(), value.clone()),
+        ),
+        LdapFilter::Extensible(assertion) => convert_user_filter(
+            ldap_info,
+            &convert_extensible_match(assertion, &ldap_info.user_dn_suffix())?,
+        ),
+        LdapFilter::GreaterOrEqual(field, value) => {
+            match map_user_field(&field.to_ascii_lowercase()) {
+                Some(UserColumn::ModifiedDate) => Ok(UserRequestFilter::ModifiedSince(
+                    parse_ldap_timestamp(value)?,
+                )),
+                _ => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported user attribute for greater-or-equal filter: {:?}",
+                        field
+                    ),
+                }),
+            }
+        }
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
             message: format!("Unsupported user filter: {:?}", filter),
@@ -183,47 +423,79 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
     }
 }
 
-#[instrument(skip_all, level = "debug")]
-pub async fn get_user_list<Backend: BackendHandler>(
-    ldap_info: &LdapInfo,
-    ldap_filter: &LdapFilter,
-    attributes: &[String],
-    base: &str,
-    user_filter: &Option<&UserId>,
-    backend: &mut Backend,
-) -> LdapResult<Vec<LdapOp>> {
-    debug!(?ldap_filter);
-    let filters = convert_user_filter(ldap_info, ldap_filter)?;
-    let parsed_filters = match user_filter {
-        None => filters,
-        Some(u) => {
-            info!("Unprivileged search, limiting results");
-            UserRequestFilter::And(vec![filters, UserRequestFilter::UserId((*u).clone())])
+/// Searches the users matching the filter. The entries are converted as the users are streamed
+/// from the backend, so large directories are never held in memory all at once.
+pub fn get_user_list<'a, Backend: BackendHandler>(
+    ldap_info: &'a LdapInfo,
+    ldap_filter: LdapFilter,
+    attributes: &'a [String],
+    base: &'a str,
+    user_filter: Option<UserId>,
+    backend: &'a Backend,
+) -> LocalBoxStream<'a, LdapResult<LdapOp>> {
+    stream::once(async move {
+        debug!(?ldap_filter);
+        let filters = convert_user_filter(ldap_info, &ldap_filter)?;
+        let parsed_filters = match &user_filter {
+            None => filters,
+            Some(u) => {
+                info!("Unprivileged search, limiting results");
+                UserRequestFilter::And(vec![filters, UserRequestFilter::UserId(u.clone())])
+            }
+        };
+        debug!(?parsed_filters);
+        let mut schema = backend
+            .get_user_attributes_schema()
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::Other,
+                message: format!("Error while fetching the attributes schema: {:#}", e),
+            })?;
+        if user_filter.is_some() {
+            // Regular users don't get to see the hidden attributes.
+            schema.retain(|s| s.is_visible);
         }
-    };
-    debug!(?parsed_filters);
-    let expanded_attributes = expand_attribute_wildcards(attributes, ALL_USER_ATTRIBUTE_KEYS);
-    let need_groups = expanded_attributes
-        .iter()
-        .any(|s| s.to_ascii_lowercase() == "memberof");
-    let users = backend
-        .list_users(Some(parsed_filters), need_groups)
-        .await
-        .map_err(|e| LdapError {
-            code: LdapResultCode::Other,
-            message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
-        })?;
-
-    Ok(users
-        .into_iter()
-        .map(|u| {
-            LdapOp::SearchResultEntry(make_ldap_search_user_result_entry(
-                u.user,
-                &ldap_info.base_dn_str,
-                &expanded_attributes,
-                u.groups.as_deref(),
-                &ldap_info.ignored_user_attributes,
-            ))
-        })
-        .collect::<Vec<_>>())
+        let active_directory_keys = match ldap_info.active_directory {
+            Some(_) => ACTIVE_DIRECTORY_USER_ATTRIBUTE_KEYS,
+            None => &[],
+        };
+        let all_attribute_keys: Vec<&str> = ALL_USER_ATTRIBUTE_KEYS
+            .iter()
+            .chain(active_directory_keys)
+            .copied()
+            .chain(schema.iter().map(|s| s.name.as_str()))
+            .collect();
+        let expanded_attributes: Vec<String> =
+            expand_attribute_wildcards(attributes, &all_attribute_keys)
+                .into_iter()
+                .map(str::to_owned)
+                .collect();
+        let password_policy = backend.get_password_policy();
+        let need_groups = expanded_attributes.iter().any(|s| {
+            ldap_info.membership_options.is_member_of_attribute(s)
+                || (ldap_info.active_directory.is_some() && s.eq_ignore_ascii_case("memberof"))
+        });
+        Ok::<_, LdapError>(backend.stream_users(Some(parsed_filters), need_groups).map(
+            move |user| -> LdapResult<LdapOp> {
+                let user = user.map_err(|e| LdapError {
+                    code: LdapResultCode::Other,
+                    message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
+                })?;
+                let attributes: Vec<&str> =
+                    expanded_attributes.iter().map(String::as_str).collect();
+                Ok(LdapOp::SearchResultEntry(
+                    make_ldap_search_user_result_entry(
+                        user.user,
+                        ldap_info,
+                        &attributes,
+                        user.groups.as_deref(),
+                        &schema,
+                        &password_policy,
+                    ),
+                ))
+            },
+        ))
+    })
+    .try_flatten()
+    .boxed_local()
 }
@@ -1,10 +1,21 @@
+use chrono::TimeZone;
 use itertools::Itertools;
-use ldap3_proto::LdapResultCode;
+use ldap3_proto::{
+    proto::{LdapMatchingRuleAssertion, LdapSubstringFilter},
+    LdapFilter, LdapResultCode,
+};
 use tracing::{debug, instrument, warn};
 
-use crate::domain::{
-    ldap::error::{LdapError, LdapResult},
-    types::{GroupColumn, UserColumn, UserId},
+use crate::{
+    domain::{
+        handler::{GroupRequestFilter, SubStringFilter, UserRequestFilter},
+        ldap::error::{LdapError, LdapResult},
+        types::{
+            AttributeSchema, AttributeType, AttributeValue, DateTime, GroupColumn, GroupDetails,
+            UserColumn, UserId, Uuid,
+        },
+    },
+    infra::configuration::{ActiveDirectoryOptions, MembershipOptions, MembershipValueFormat},
 };
 
 fn make_dn_pair<I>(mut iter: I) -> LdapResult<(String, String)>
@@ -38,31 +49,35 @@ pub fn parse_distinguished_name(dn: &str) -> LdapResult<Vec<(String, String)>> {
         .collect()
 }
 
+// `naming_attributes` are the accepted attributes of the first DN component, the first one being
+// the expected one.
 fn get_id_from_distinguished_name(
     dn: &str,
     base_tree: &[(String, String)],
     base_dn_str: &str,
-    is_group: bool,
+    ou: &str,
+    naming_attributes: &[&str],
 ) -> LdapResult<String> {
     let parts = parse_distinguished_name(dn)?;
     {
-        let ou = if is_group { "groups" } else { "people" };
         if !is_subtree(&parts, base_tree) {
             Err("Not a subtree of the base tree".to_string())
         } else if parts.len() == base_tree.len() + 2 {
-            if parts[1].0 != "ou" || parts[1].1 != ou || (parts[0].0 != "cn" && parts[0].0 != "uid")
+            if parts[1].0 != "ou"
+                || parts[1].1 != ou
+                || !naming_attributes.contains(&parts[0].0.as_str())
             {
                 Err(format!(
-                    r#"Unexpected DN format. Got "{}", expected: "uid=id,ou={},{}""#,
-                    dn, ou, base_dn_str
+                    r#"Unexpected DN format. Got "{}", expected: "{}=id,ou={},{}""#,
+                    dn, naming_attributes[0], ou, base_dn_str
                 ))
             } else {
                 Ok(parts[0].1.to_string())
             }
         } else {
             Err(format!(
-                r#"Unexpected DN format. Got "{}", expected: "uid=id,ou={},{}""#,
-                dn, ou, base_dn_str
+                r#"Unexpected DN format. Got "{}", expected: "{}=id,ou={},{}""#,
+                dn, naming_attributes[0], ou, base_dn_str
             ))
         }
     }
@@ -72,26 +87,19 @@ fn get_id_from_distinguished_name(
     })
 }
 
-pub fn get_user_id_from_distinguished_name(
-    dn: &str,
-    base_tree: &[(String, String)],
-    base_dn_str: &str,
-) -> LdapResult<UserId> {
-    get_id_from_distinguished_name(dn, base_tree, base_dn_str, false).map(UserId::from)
-}
-
-pub fn get_group_id_from_distinguished_name(
+/// The ID of a service account, from a DN like "uid=nextcloud,ou=services,dc=example,dc=com".
+pub fn get_service_account_id_from_distinguished_name(
     dn: &str,
     base_tree: &[(String, String)],
     base_dn_str: &str,
 ) -> LdapResult<String> {
-    get_id_from_distinguished_name(dn, base_tree, base_dn_str, true)
+    get_id_from_distinguished_name(dn, base_tree, base_dn_str, "services", &["uid", "cn"])
 }
 
 #[instrument(skip_all, level = "debug")]
 pub fn expand_attribute_wildcards<'a>(
     ldap_attributes: &'a [String],
-    all_attribute_keys: &'a [&'static str],
+    all_attribute_keys: &'a [&'a str],
 ) -> Vec<&'a str> {
     let mut attributes_out = ldap_attributes
         .iter()
@@ -135,6 +143,102 @@ pub fn is_subtree(subtree: &[(String, String)], base_tree: &[(String, String)])
     true
 }
 
+impl From<&LdapSubstringFilter> for SubStringFilter {
+    fn from(filter: &LdapSubstringFilter) -> Self {
+        Self {
+            initial: filter.initial.clone(),
+            any: filter.any.clone(),
+            final_: filter.final_.clone(),
+        }
+    }
+}
+
+/// Matching rules of extensible match filters that are evaluated as a plain equality match.
+/// String comparisons are already case-insensitive, and group memberships are resolved
+/// transitively, so Active Directory's LDAP_MATCHING_RULE_IN_CHAIN is equivalent to an
+/// equality on memberOf.
+const EQUALITY_MATCHING_RULES: &[&str] = &[
+    "2.5.13.2",
+    "caseignorematch",
+    "2.5.13.5",
+    "caseexactmatch",
+    "2.5.13.14",
+    "integermatch",
+    "1.2.840.113556.1.4.1941",
+];
+
+/// Rewrites an extensible match filter into the equivalent equality filter.
+///
+/// With `:dn:`, the assertion also matches every entry whose DN contains the asserted
+/// attribute, i.e. the entry's own RDN (covered by the equality) or one of the `dn_suffix`
+/// components shared by all the entries of that kind. Unknown matching rules evaluate to
+/// undefined, which we treat as not matching.
+pub fn convert_extensible_match(
+    assertion: &LdapMatchingRuleAssertion,
+    dn_suffix: &[(String, String)],
+) -> LdapResult<LdapFilter> {
+    let field = assertion.type_.as_ref().ok_or_else(|| LdapError {
+        code: LdapResultCode::UnwillingToPerform,
+        message: "Extensible match filters without an attribute are not supported".to_string(),
+    })?;
+    if let Some(rule) = &assertion.matching_rule {
+        if !EQUALITY_MATCHING_RULES.contains(&rule.to_ascii_lowercase().as_str()) {
+            warn!(r#"Unsupported matching rule "{}" in filter"#, rule);
+            return Ok(LdapFilter::Not(Box::new(LdapFilter::And(vec![]))));
+        }
+    }
+    let equality = LdapFilter::Equality(field.clone(), assertion.match_value.clone());
+    if assertion.dn_attributes
+        && dn_suffix.iter().any(|(k, v)| {
+            k.eq_ignore_ascii_case(field) && v.eq_ignore_ascii_case(&assertion.match_value)
+        })
+    {
+        Ok(LdapFilter::And(vec![]))
+    } else {
+        Ok(equality)
+    }
+}
+
+/// Returns the values of a custom attribute, if set, in their LDAP form.
+pub fn get_custom_attribute(
+    attributes: &[AttributeValue],
+    attribute_schema: &AttributeSchema,
+) -> Option<Vec<Vec<u8>>> {
+    let values = &attributes
+        .iter()
+        .find(|a| a.name == attribute_schema.name)?
+        .value;
+    Some(
+        values
+            .iter()
+            .filter_map(|v| match attribute_schema.attribute_type {
+                // Photos are stored in base64, but returned as raw bytes.
+                AttributeType::JpegPhoto => base64::decode(v).ok(),
+                _ => Some(v.clone().into_bytes()),
+            })
+            .collect(),
+    )
+}
+
+/// Parses a timestamp from a filter, either in the LDAP generalized time format (e.g.
+/// "20230102030405Z") or in RFC 3339 format, as the timestamps are returned.
+pub fn parse_uuid(value: &str) -> LdapResult<Uuid> {
+    Uuid::try_from(value).map_err(|e| LdapError {
+        code: LdapResultCode::InappropriateMatching,
+        message: format!("Invalid UUID: {:#}", e),
+    })
+}
+
+pub fn parse_ldap_timestamp(value: &str) -> LdapResult<DateTime> {
+    chrono::NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%SZ")
+        .map(|date| chrono::Utc.from_utc_datetime(&date))
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(value).map(|date| date.into()))
+        .map_err(|_| LdapError {
+            code: LdapResultCode::InvalidAttributeSyntax,
+            message: format!("Invalid timestamp: {:?}", value),
+        })
+}
+
 pub fn map_user_field(field: &str) -> Option<UserColumn> {
     assert!(field == field.to_ascii_lowercase());
     Some(match field {
@@ -144,10 +248,21 @@ pub fn map_user_field(field: &str) -> Option<UserColumn> {
         "givenname" | "first_name" => UserColumn::FirstName,
         "sn" | "last_name" => UserColumn::LastName,
         "avatar" => UserColumn::Avatar,
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
-            UserColumn::CreationDate
-        }
+        "creationdate" | "createtimestamp" | "creation_date" => UserColumn::CreationDate,
+        "modifieddate" | "modifytimestamp" | "modified_date" => UserColumn::ModifiedDate,
         "entryuuid" | "uuid" => UserColumn::Uuid,
+        "uidnumber" | "uid_number" => UserColumn::UidNumber,
+        "gidnumber" | "gid_number" => UserColumn::GidNumber,
+        "homedirectory" | "home_directory" => UserColumn::HomeDirectory,
+        "loginshell" | "login_shell" => UserColumn::LoginShell,
+        "telephonenumber" | "telephone_number" => UserColumn::TelephoneNumber,
+        "mobile" => UserColumn::Mobile,
+        "title" => UserColumn::Title,
+        "ou" | "department" => UserColumn::Department,
+        "l" | "locality" => UserColumn::Locality,
+        "postaladdress" | "postal_address" => UserColumn::PostalAddress,
+        "preferredlanguage" | "locale" => UserColumn::Locale,
+        "timezone" => UserColumn::Timezone,
         _ => return None,
     })
 }
@@ -156,10 +271,12 @@ pub fn map_group_field(field: &str) -> Option<GroupColumn> {
     assert!(field == field.to_ascii_lowercase());
     Some(match field {
         "cn" | "displayname" | "uid" | "display_name" => GroupColumn::DisplayName,
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
-            GroupColumn::CreationDate
-        }
+        "creationdate" | "createtimestamp" | "creation_date" => GroupColumn::CreationDate,
+        "modifieddate" | "modifytimestamp" | "modified_date" => GroupColumn::ModifiedDate,
         "entryuuid" | "uuid" => GroupColumn::Uuid,
+        "gidnumber" | "gid_number" => GroupColumn::GidNumber,
+        "description" => GroupColumn::Description,
+        "mail" => GroupColumn::Mail,
         _ => return None,
     })
 }
@@ -167,6 +284,305 @@ pub fn map_group_field(field: &str) -> Option<GroupColumn> {
 pub struct LdapInfo {
     pub base_dn: Vec<(String, String)>,
     pub base_dn_str: String,
+    /// Alternative base DNs under which the same tree is served.
+    pub base_dn_aliases: Vec<Vec<(String, String)>>,
     pub ignored_user_attributes: Vec<String>,
     pub ignored_group_attributes: Vec<String>,
+    pub membership_options: MembershipOptions,
+    /// Set in the Active Directory compatibility mode.
+    pub active_directory: Option<ActiveDirectoryInfo>,
+}
+
+/// The Active Directory domain, in lowercase.
+pub struct ActiveDirectoryInfo {
+    pub domain: String,
+    pub netbios_domain: Option<String>,
+}
+
+impl LdapInfo {
+    pub fn new(
+        mut base_dn: String,
+        base_dn_aliases: &[String],
+        ignored_user_attributes: Vec<String>,
+        ignored_group_attributes: Vec<String>,
+        mut membership_options: MembershipOptions,
+    ) -> Self {
+        base_dn.make_ascii_lowercase();
+        // Like the base DN, the DNs are served and compared in lowercase.
+        membership_options.user_dn_attribute.make_ascii_lowercase();
+        membership_options.user_ou.make_ascii_lowercase();
+        membership_options.group_ou.make_ascii_lowercase();
+        Self {
+            base_dn: parse_distinguished_name(&base_dn).unwrap_or_else(|_| {
+                panic!(
+                    "Invalid value for ldap_base_dn in configuration: {}",
+                    base_dn
+                )
+            }),
+            base_dn_str: base_dn,
+            base_dn_aliases: base_dn_aliases
+                .iter()
+                .map(|alias| {
+                    parse_distinguished_name(&alias.to_ascii_lowercase()).unwrap_or_else(|_| {
+                        panic!(
+                            "Invalid value for ldap_base_dn_aliases in configuration: {}",
+                            alias
+                        )
+                    })
+                })
+                .collect(),
+            ignored_user_attributes,
+            ignored_group_attributes,
+            membership_options,
+            active_directory: None,
+        }
+    }
+
+    pub fn with_active_directory(self, options: &ActiveDirectoryOptions) -> Self {
+        if !options.enabled {
+            return self;
+        }
+        let domain = match &options.domain {
+            Some(domain) => domain.to_ascii_lowercase(),
+            None => self
+                .base_dn
+                .iter()
+                .filter(|(k, _)| k == "dc")
+                .map(|(_, v)| v.as_str())
+                .join("."),
+        };
+        Self {
+            active_directory: Some(ActiveDirectoryInfo {
+                domain,
+                netbios_domain: options
+                    .netbios_domain
+                    .as_ref()
+                    .map(|d| d.to_ascii_lowercase()),
+            }),
+            ..self
+        }
+    }
+
+    /// Lowercases the DN and rewrites it to be under the canonical base DN if it is under one
+    /// of the base DN aliases. Invalid DNs are only lowercased.
+    pub fn normalize_dn(&self, dn: &str) -> String {
+        let dn = dn.to_ascii_lowercase();
+        let parts = match parse_distinguished_name(&dn) {
+            Ok(parts) => parts,
+            Err(_) => return dn,
+        };
+        if is_subtree(&parts, &self.base_dn) {
+            return dn;
+        }
+        match self
+            .base_dn_aliases
+            .iter()
+            .find(|alias| is_subtree(&parts, alias))
+        {
+            Some(alias) => parts[..parts.len() - alias.len()]
+                .iter()
+                .chain(self.base_dn.iter())
+                .map(|(k, v)| format!("{}={}", k, v))
+                .join(","),
+            None => dn,
+        }
+    }
+
+    /// DN components shared by all the entries under the given organizational unit.
+    fn dn_suffix(&self, ou: &str) -> Vec<(String, String)> {
+        std::iter::once(("ou".to_owned(), ou.to_owned()))
+            .chain(self.base_dn.iter().cloned())
+            .collect()
+    }
+
+    /// DN components shared by all the user entries.
+    pub fn user_dn_suffix(&self) -> Vec<(String, String)> {
+        self.dn_suffix(&self.membership_options.user_ou)
+    }
+
+    /// DN components shared by all the group entries.
+    pub fn group_dn_suffix(&self) -> Vec<(String, String)> {
+        self.dn_suffix(&self.membership_options.group_ou)
+    }
+
+    pub fn user_dn(&self, user_id: &str) -> String {
+        format!(
+            "{}={},ou={},{}",
+            self.membership_options.user_dn_attribute,
+            user_id,
+            self.membership_options.user_ou,
+            self.base_dn_str
+        )
+    }
+
+    /// Parses the DN of a user, already normalized, into their ID. Both "uid" and "cn" are
+    /// accepted as the naming attribute.
+    pub fn get_user_id_from_dn(&self, dn: &str) -> LdapResult<UserId> {
+        let naming_attributes: &[&str] = if self.membership_options.user_dn_attribute == "cn" {
+            &["cn", "uid"]
+        } else {
+            &["uid", "cn"]
+        };
+        get_id_from_distinguished_name(
+            dn,
+            &self.base_dn,
+            &self.base_dn_str,
+            &self.membership_options.user_ou,
+            naming_attributes,
+        )
+        .map(UserId::from)
+    }
+
+    /// Value identifying the group in its DN: its UUID if the groups are named by "entryUUID",
+    /// which doesn't change when the group is renamed, otherwise its name.
+    fn group_rdn_value<'a>(&self, display_name: &'a str, uuid: &'a Uuid) -> &'a str {
+        if self.membership_options.uses_group_uuids() {
+            uuid.as_str()
+        } else {
+            display_name
+        }
+    }
+
+    pub fn group_dn(&self, display_name: &str, uuid: &Uuid) -> String {
+        format!(
+            "{}={},ou={},{}",
+            self.membership_options.group_dn_attribute,
+            self.group_rdn_value(display_name, uuid),
+            self.membership_options.group_ou,
+            self.base_dn_str
+        )
+    }
+
+    /// Parses the DN of a group into the value of its naming attribute. The configured attribute
+    /// is expected, and "cn" and "uid" are accepted too unless the groups are named by UUID.
+    fn get_group_rdn_value(&self, dn: &str) -> LdapResult<String> {
+        let group_dn_attribute = self
+            .membership_options
+            .group_dn_attribute
+            .to_ascii_lowercase();
+        let mut naming_attributes = vec![group_dn_attribute.as_str()];
+        if !self.membership_options.uses_group_uuids() {
+            naming_attributes.extend(
+                ["cn", "uid"]
+                    .into_iter()
+                    .filter(|a| *a != group_dn_attribute),
+            );
+        }
+        get_id_from_distinguished_name(
+            &self.normalize_dn(dn),
+            &self.base_dn,
+            &self.base_dn_str,
+            &self.membership_options.group_ou,
+            &naming_attributes,
+        )
+    }
+
+    /// Parses the DN of a group into a filter matching it.
+    pub fn parse_group_dn(&self, dn: &str) -> LdapResult<GroupRequestFilter> {
+        let value = self.get_group_rdn_value(dn)?;
+        Ok(if self.membership_options.uses_group_uuids() {
+            GroupRequestFilter::Uuid(parse_uuid(&value)?)
+        } else {
+            GroupRequestFilter::DisplayName(value)
+        })
+    }
+
+    /// Value of a group's member attribute for the given user, according to the configured format.
+    pub fn member_value(&self, user_id: &str) -> Vec<u8> {
+        match self.membership_options.value_format {
+            MembershipValueFormat::Dn => self.user_dn(user_id),
+            MembershipValueFormat::Id => user_id.to_owned(),
+        }
+        .into_bytes()
+    }
+
+    /// Value of a user's memberOf attribute for the given group, according to the configured
+    /// format.
+    pub fn member_of_value(&self, group: &GroupDetails) -> Vec<u8> {
+        match self.membership_options.value_format {
+            MembershipValueFormat::Dn => self.group_dn(&group.display_name, &group.uuid),
+            MembershipValueFormat::Id => self
+                .group_rdn_value(&group.display_name, &group.uuid)
+                .to_owned(),
+        }
+        .into_bytes()
+    }
+
+    /// Parses the value of a member attribute in a filter into a user ID.
+    pub fn parse_member_value(&self, value: &str) -> LdapResult<UserId> {
+        match self.membership_options.value_format {
+            MembershipValueFormat::Dn => self.get_user_id_from_dn(&self.normalize_dn(value)),
+            MembershipValueFormat::Id => Ok(UserId::new(value)),
+        }
+    }
+
+    /// Parses the value of a memberOf attribute in a filter into a filter on the users of the
+    /// group.
+    pub fn parse_member_of_value(&self, value: &str) -> LdapResult<UserRequestFilter> {
+        match self.membership_options.value_format {
+            MembershipValueFormat::Dn => self.parse_member_of_dn(value),
+            MembershipValueFormat::Id => self.member_of_filter(value.to_owned()),
+        }
+    }
+
+    /// Parses the DN of a group in a memberOf filter, whatever the configured format.
+    pub fn parse_member_of_dn(&self, dn: &str) -> LdapResult<UserRequestFilter> {
+        self.member_of_filter(self.get_group_rdn_value(dn)?)
+    }
+
+    fn member_of_filter(&self, value: String) -> LdapResult<UserRequestFilter> {
+        Ok(if self.membership_options.uses_group_uuids() {
+            UserRequestFilter::MemberOfUuid(parse_uuid(&value)?)
+        } else {
+            UserRequestFilter::MemberOf(value)
+        })
+    }
+
+    /// The user ID of an Active Directory bind name, "DOMAIN\user" or "user@domain", in the
+    /// Active Directory compatibility mode.
+    pub fn parse_active_directory_name(&self, name: &str) -> Option<UserId> {
+        let options = self.active_directory.as_ref()?;
+        if let Some((domain, user)) = name.split_once('\\') {
+            let domain_matches = options
+                .netbios_domain
+                .as_ref()
+                .map(|d| d.eq_ignore_ascii_case(domain))
+                .unwrap_or(true);
+            return (domain_matches && !user.is_empty()).then(|| UserId::new(user));
+        }
+        self.parse_user_principal_name(name)
+    }
+
+    /// The user ID of a "user@domain" user principal name, if the domain is the configured one.
+    pub fn parse_user_principal_name(&self, name: &str) -> Option<UserId> {
+        let options = self.active_directory.as_ref()?;
+        match name.rsplit_once('@') {
+            Some((user, domain))
+                if !user.is_empty() && domain.eq_ignore_ascii_case(&options.domain) =>
+            {
+                Some(UserId::new(user))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn user_principal_name(&self, user_id: &str) -> Option<String> {
+        let options = self.active_directory.as_ref()?;
+        Some(format!("{}@{}", user_id, options.domain))
+    }
+
+    /// The objectCategory of the entries of the given class, "person" or "group".
+    pub fn object_category(&self, class: &str) -> String {
+        format!(
+            "cn={},cn=schema,cn=configuration,{}",
+            class, self.base_dn_str
+        )
+    }
+
+    /// Whether the value of an objectCategory filter designates the given class, by its name or
+    /// its DN.
+    pub fn is_object_category(&self, value: &str, class: &str) -> bool {
+        value.eq_ignore_ascii_case(class)
+            || value.eq_ignore_ascii_case(&self.object_category(class))
+    }
 }
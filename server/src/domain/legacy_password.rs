@@ -0,0 +1,106 @@
+//! Verification of the password hashes imported from other directories, in the formats of the
+//! `userPassword` attribute of OpenLDAP and 389-ds: bcrypt, argon2 and salted SHA.
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    Bcrypt,
+    Argon2,
+    Sha1 { salted: bool },
+    Ssha256,
+    Ssha512,
+}
+
+// Splits the "{SCHEME}" prefix from the hash, if the scheme is supported.
+fn parse(hash: &str) -> Option<(Scheme, &str)> {
+    let (prefix, value) = match hash.strip_prefix('{').and_then(|h| h.split_once('}')) {
+        Some((prefix, value)) => (prefix.to_ascii_uppercase(), value),
+        None => (String::new(), hash),
+    };
+    let scheme = match prefix.as_str() {
+        "" | "CRYPT" | "BCRYPT" | "ARGON2" if value.starts_with("$2") => Scheme::Bcrypt,
+        "" | "CRYPT" | "ARGON2" if value.starts_with("$argon2") => Scheme::Argon2,
+        "SHA" => Scheme::Sha1 { salted: false },
+        "SSHA" => Scheme::Sha1 { salted: true },
+        "SSHA256" => Scheme::Ssha256,
+        "SSHA512" => Scheme::Ssha512,
+        _ => return None,
+    };
+    Some((scheme, value))
+}
+
+pub fn is_supported(hash: &str) -> bool {
+    parse(hash).is_some()
+}
+
+// The salted SHA hashes are the base64 encoding of the digest of the password followed by the
+// salt, and of the salt.
+fn verify_salted<D: Digest>(encoded: &str, password: &str, salted: bool) -> bool {
+    let decoded = match base64::decode(encoded.trim()) {
+        Ok(decoded) => decoded,
+        Err(_) => return false,
+    };
+    let digest_size = <D as Digest>::output_size();
+    if decoded.len() < digest_size || (!salted && decoded.len() != digest_size) {
+        return false;
+    }
+    let (expected, salt) = decoded.split_at(digest_size);
+    let mut hasher = D::new();
+    hasher.update(password.as_bytes());
+    hasher.update(salt);
+    orion::util::secure_cmp(&hasher.finalize(), expected).is_ok()
+}
+
+/// Checks the password against an imported hash. Unsupported or malformed hashes never match.
+pub fn verify(hash: &str, password: &str) -> bool {
+    match parse(hash) {
+        None => false,
+        Some((Scheme::Bcrypt, value)) => bcrypt::verify(password, value).unwrap_or(false),
+        Some((Scheme::Argon2, value)) => {
+            argon2::verify_encoded(value, password.as_bytes()).unwrap_or(false)
+        }
+        Some((Scheme::Sha1 { salted }, value)) => verify_salted::<Sha1>(value, password, salted),
+        Some((Scheme::Ssha256, value)) => verify_salted::<Sha256>(value, password, true),
+        Some((Scheme::Ssha512, value)) => verify_salted::<Sha512>(value, password, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssha(password: &str, salt: &[u8]) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(password.as_bytes());
+        hasher.update(salt);
+        let mut value = hasher.finalize().to_vec();
+        value.extend_from_slice(salt);
+        format!("{{SSHA}}{}", base64::encode(value))
+    }
+
+    #[test]
+    fn test_verify() {
+        let hash = ssha("secret", b"salt");
+        assert!(verify(&hash, "secret"));
+        assert!(!verify(&hash, "wrong"));
+        let bcrypt_hash = bcrypt::hash("secret", 4).unwrap();
+        assert!(verify(&bcrypt_hash, "secret"));
+        assert!(verify(&format!("{{CRYPT}}{}", bcrypt_hash), "secret"));
+        assert!(!verify(&bcrypt_hash, "wrong"));
+        let argon2_hash =
+            argon2::hash_encoded(b"secret", b"saltsalt", &argon2::Config::default()).unwrap();
+        assert!(verify(&argon2_hash, "secret"));
+        assert!(!verify(&argon2_hash, "wrong"));
+    }
+
+    #[test]
+    fn test_unsupported_hashes() {
+        assert!(!is_supported("{MD5}Xr4ilOzQ4PCOq3aQ0qbuaQ=="));
+        assert!(!is_supported("secret"));
+        assert!(!verify("secret", "secret"));
+        assert!(!verify("{SSHA}not base64", "secret"));
+        assert!(is_supported("{SSHA512}abcd"));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    domain::{
+        error::Result,
+        handler::GroupRequestFilter,
+        types::{Group, GroupDetails, User, UserId},
+    },
+    infra::configuration::CacheOptions,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+struct Entry<V> {
+    value: V,
+    expiry: Instant,
+}
+
+type EntryMap<K, V> = HashMap<K, Entry<V>>;
+
+#[derive(Default)]
+struct State {
+    /// Incremented on every invalidation, so that the lookups started before a change don't
+    /// store their stale result.
+    generation: u64,
+    user_details: EntryMap<UserId, User>,
+    user_groups: EntryMap<UserId, HashSet<GroupDetails>>,
+    group_lists: EntryMap<Option<GroupRequestFilter>, Vec<Group>>,
+}
+
+/// Caches the results of the hot user and group lookups for a short time, shared between all the
+/// clones of the backend handler. Any change to the users or groups clears it.
+#[derive(Clone)]
+pub struct LookupCache {
+    ttl: Duration,
+    max_entries: usize,
+    state: Arc<Mutex<State>>,
+}
+
+impl LookupCache {
+    pub fn new(options: &CacheOptions) -> Self {
+        Self {
+            ttl: Duration::from_secs(options.ttl_seconds),
+            max_entries: options.max_entries,
+            state: Arc::default(),
+        }
+    }
+
+    /// Returns the cached value if it is still fresh, otherwise runs `fetch` and caches its
+    /// result. Errors are not cached.
+    async fn get_or_fetch<K, V, Fut>(
+        &self,
+        entries: fn(&mut State) -> &mut EntryMap<K, V>,
+        key: K,
+        fetch: Fut,
+    ) -> Result<V>
+    where
+        K: Hash + Eq,
+        V: Clone,
+        Fut: Future<Output = Result<V>>,
+    {
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(entry) = entries(&mut state).get(&key) {
+                if entry.expiry > Instant::now() {
+                    return Ok(entry.value.clone());
+                }
+            }
+            state.generation
+        };
+        let value = fetch.await?;
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            let now = Instant::now();
+            let entries = entries(&mut state);
+            if entries.len() >= self.max_entries {
+                entries.retain(|_, entry| entry.expiry > now);
+                if entries.len() >= self.max_entries {
+                    entries.clear();
+                }
+            }
+            entries.insert(
+                key,
+                Entry {
+                    value: value.clone(),
+                    expiry: now + self.ttl,
+                },
+            );
+        }
+        Ok(value)
+    }
+
+    pub async fn user_details(
+        &self,
+        user_id: &UserId,
+        fetch: impl Future<Output = Result<User>>,
+    ) -> Result<User> {
+        self.get_or_fetch(|state| &mut state.user_details, user_id.clone(), fetch)
+            .await
+    }
+
+    pub async fn user_groups(
+        &self,
+        user_id: &UserId,
+        fetch: impl Future<Output = Result<HashSet<GroupDetails>>>,
+    ) -> Result<HashSet<GroupDetails>> {
+        self.get_or_fetch(|state| &mut state.user_groups, user_id.clone(), fetch)
+            .await
+    }
+
+    pub async fn group_list(
+        &self,
+        filters: &Option<GroupRequestFilter>,
+        fetch: impl Future<Output = Result<Vec<Group>>>,
+    ) -> Result<Vec<Group>> {
+        self.get_or_fetch(|state| &mut state.group_lists, filters.clone(), fetch)
+            .await
+    }
+
+    /// Drops all the entries. A change to a user or a group can affect the entries of the
+    /// others, e.g. a group rename changes the groups of all its members.
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.user_details.clear();
+        state.user_groups.clear();
+        state.group_lists.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::types::GroupId, infra::configuration::CacheOptionsBuilder, uuid};
+
+    fn make_cache(max_entries: usize) -> LookupCache {
+        LookupCache::new(
+            &CacheOptionsBuilder::default()
+                .enabled(true)
+                .ttl_seconds(60)
+                .max_entries(max_entries)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    async fn lookup(cache: &LookupCache, user_id: &str, value: &str) -> HashSet<GroupDetails> {
+        let value = value.to_owned();
+        cache
+            .user_groups(&UserId::new(user_id), async move {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(1),
+                    display_name: value,
+                    creation_date: chrono::Utc::now(),
+                    uuid: uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                    gid_number: None,
+                }]))
+            })
+            .await
+            .unwrap()
+    }
+
+    fn name(groups: HashSet<GroupDetails>) -> String {
+        groups.into_iter().next().unwrap().display_name
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_and_invalidation() {
+        let cache = make_cache(10);
+        assert_eq!(name(lookup(&cache, "bob", "first").await), "first");
+        assert_eq!(name(lookup(&cache, "bob", "second").await), "first");
+        cache.invalidate();
+        assert_eq!(name(lookup(&cache, "bob", "third").await), "third");
+    }
+
+    #[tokio::test]
+    async fn test_cache_max_entries() {
+        let cache = make_cache(1);
+        lookup(&cache, "bob", "first").await;
+        lookup(&cache, "patrick", "first").await;
+        assert_eq!(name(lookup(&cache, "bob", "second").await), "second");
+    }
+}
@@ -1,12 +1,37 @@
 pub mod error;
+pub mod external_auth;
 pub mod handler;
 pub mod ldap;
+pub mod legacy_password;
+pub mod lookup_cache;
 pub mod model;
 pub mod opaque_handler;
+pub mod security_events;
+pub mod sql_account_lockout_backend_handler;
+pub mod sql_api_token_backend_handler;
+pub mod sql_audit_log_backend_handler;
 pub mod sql_backend_handler;
+pub mod sql_backup;
+pub mod sql_change_log_backend_handler;
+pub mod sql_directory_changes_backend_handler;
 pub mod sql_group_backend_handler;
+pub mod sql_group_manager_backend_handler;
+pub mod sql_invitation_backend_handler;
 pub mod sql_migrations;
+pub mod sql_oidc_backend_handler;
 pub mod sql_opaque_handler;
+pub mod sql_password_policy_backend_handler;
+pub mod sql_password_reset_backend_handler;
+pub mod sql_pending_user_backend_handler;
+pub mod sql_recycle_bin_backend_handler;
+pub mod sql_role_backend_handler;
+pub mod sql_schema_backend_handler;
+pub mod sql_service_account_backend_handler;
+pub mod sql_session_backend_handler;
+pub mod sql_statistics_backend_handler;
 pub mod sql_tables;
 pub mod sql_user_backend_handler;
+pub mod sql_webauthn_handler;
 pub mod types;
+pub mod upstream_ldap;
+pub mod webauthn_handler;
@@ -13,12 +13,18 @@ pub struct Model {
     pub display_name: String,
     pub creation_date: chrono::DateTime<chrono::Utc>,
     pub uuid: Uuid,
+    pub gid_number: Option<i32>,
+    pub modified_date: chrono::DateTime<chrono::Utc>,
+    pub description: Option<String>,
+    pub mail: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::memberships::Entity")]
     Memberships,
+    #[sea_orm(has_many = "super::group_attributes::Entity")]
+    GroupAttributes,
 }
 
 impl Related<super::memberships::Entity> for Entity {
@@ -27,6 +33,12 @@ impl Related<super::memberships::Entity> for Entity {
     }
 }
 
+impl Related<super::group_attributes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GroupAttributes.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl From<Model> for crate::domain::types::Group {
@@ -36,7 +48,12 @@ impl From<Model> for crate::domain::types::Group {
             display_name: group.display_name,
             creation_date: group.creation_date,
             uuid: group.uuid,
+            gid_number: group.gid_number,
+            modified_date: group.modified_date,
+            description: group.description,
+            mail: group.mail,
             users: vec![],
+            attributes: vec![],
         }
     }
 }
@@ -48,6 +65,7 @@ impl From<Model> for crate::domain::types::GroupDetails {
             display_name: group.display_name,
             creation_date: group.creation_date,
             uuid: group.uuid,
+            gid_number: group.gid_number,
         }
     }
 }
@@ -3,7 +3,7 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::domain::types::UserId;
+use crate::domain::types::{Session, UserId};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "jwt_refresh_storage")]
@@ -12,6 +12,10 @@ pub struct Model {
     pub refresh_token_hash: i64,
     pub user_id: UserId,
     pub expiry_date: chrono::DateTime<chrono::Utc>,
+    pub creation_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used: Option<chrono::DateTime<chrono::Utc>>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -33,3 +37,17 @@ impl Related<super::users::Entity> for Entity {
 }
 
 impl ActiveModelBehavior for ActiveModel {}
+
+impl From<Model> for Session {
+    fn from(token: Model) -> Self {
+        Self {
+            session_id: token.refresh_token_hash,
+            user_id: token.user_id,
+            creation_date: token.creation_date,
+            last_used: token.last_used,
+            expiry_date: token.expiry_date,
+            user_agent: token.user_agent,
+            ip_address: token.ip_address,
+        }
+    }
+}
@@ -12,6 +12,8 @@ pub struct Model {
     pub user_id: UserId,
     #[sea_orm(primary_key)]
     pub group_id: GroupId,
+    /// Temporary memberships are removed by the DB cleaner after this date.
+    pub expiry_date: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
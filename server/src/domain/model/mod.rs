@@ -2,11 +2,37 @@
 
 pub mod prelude;
 
+pub mod api_tokens;
+pub mod audit_log;
+pub mod change_log;
+pub mod deleted_groups;
+pub mod deleted_users;
+pub mod failed_logins;
+pub mod group_attribute_schema;
+pub mod group_attributes;
+pub mod group_managers;
+pub mod group_memberships;
 pub mod groups;
+pub mod invitation_groups;
+pub mod invitations;
 pub mod jwt_refresh_storage;
 pub mod jwt_storage;
+pub mod legacy_password_hashes;
 pub mod memberships;
+pub mod oidc_authorization_codes;
+pub mod oidc_clients;
+pub mod oidc_group_claims;
+pub mod password_history;
 pub mod password_reset_tokens;
+pub mod pending_users;
+pub mod remote_avatars;
+pub mod role_groups;
+pub mod roles;
+pub mod service_accounts;
+pub mod user_attribute_schema;
+pub mod user_attributes;
+pub mod user_emails;
 pub mod users;
+pub mod webauthn_credentials;
 
 pub use prelude::*;
@@ -1,14 +1,66 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.3
 
+pub use super::api_tokens::Column as ApiTokensColumn;
+pub use super::api_tokens::Entity as ApiTokens;
+pub use super::audit_log::Column as AuditLogColumn;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::change_log::Column as ChangeLogColumn;
+pub use super::change_log::Entity as ChangeLog;
+pub use super::deleted_groups::Column as DeletedGroupsColumn;
+pub use super::deleted_groups::Entity as DeletedGroups;
+pub use super::deleted_users::Column as DeletedUsersColumn;
+pub use super::deleted_users::Entity as DeletedUsers;
+pub use super::failed_logins::Column as FailedLoginsColumn;
+pub use super::failed_logins::Entity as FailedLogins;
+pub use super::group_attribute_schema::Column as GroupAttributeSchemaColumn;
+pub use super::group_attribute_schema::Entity as GroupAttributeSchema;
+pub use super::group_attributes::Column as GroupAttributesColumn;
+pub use super::group_attributes::Entity as GroupAttributes;
+pub use super::group_managers::Column as GroupManagersColumn;
+pub use super::group_managers::Entity as GroupManagers;
+pub use super::group_memberships::Column as GroupMembershipColumn;
+pub use super::group_memberships::Entity as GroupMembership;
 pub use super::groups::Column as GroupColumn;
 pub use super::groups::Entity as Group;
+pub use super::invitation_groups::Column as InvitationGroupsColumn;
+pub use super::invitation_groups::Entity as InvitationGroups;
+pub use super::invitations::Column as InvitationsColumn;
+pub use super::invitations::Entity as Invitations;
 pub use super::jwt_refresh_storage::Column as JwtRefreshStorageColumn;
 pub use super::jwt_refresh_storage::Entity as JwtRefreshStorage;
 pub use super::jwt_storage::Column as JwtStorageColumn;
 pub use super::jwt_storage::Entity as JwtStorage;
+pub use super::legacy_password_hashes::Column as LegacyPasswordHashesColumn;
+pub use super::legacy_password_hashes::Entity as LegacyPasswordHashes;
 pub use super::memberships::Column as MembershipColumn;
 pub use super::memberships::Entity as Membership;
+pub use super::oidc_authorization_codes::Column as OidcAuthorizationCodesColumn;
+pub use super::oidc_authorization_codes::Entity as OidcAuthorizationCodes;
+pub use super::oidc_clients::Column as OidcClientsColumn;
+pub use super::oidc_clients::Entity as OidcClients;
+pub use super::oidc_group_claims::Column as OidcGroupClaimsColumn;
+pub use super::oidc_group_claims::Entity as OidcGroupClaims;
+pub use super::password_history::Column as PasswordHistoryColumn;
+pub use super::password_history::Entity as PasswordHistory;
 pub use super::password_reset_tokens::Column as PasswordResetTokensColumn;
 pub use super::password_reset_tokens::Entity as PasswordResetTokens;
+pub use super::pending_users::Column as PendingUsersColumn;
+pub use super::pending_users::Entity as PendingUsers;
+pub use super::remote_avatars::Column as RemoteAvatarsColumn;
+pub use super::remote_avatars::Entity as RemoteAvatars;
+pub use super::role_groups::Column as RoleGroupsColumn;
+pub use super::role_groups::Entity as RoleGroups;
+pub use super::roles::Column as RolesColumn;
+pub use super::roles::Entity as Roles;
+pub use super::service_accounts::Column as ServiceAccountsColumn;
+pub use super::service_accounts::Entity as ServiceAccounts;
+pub use super::user_attribute_schema::Column as UserAttributeSchemaColumn;
+pub use super::user_attribute_schema::Entity as UserAttributeSchema;
+pub use super::user_attributes::Column as UserAttributesColumn;
+pub use super::user_attributes::Entity as UserAttributes;
+pub use super::user_emails::Column as UserEmailsColumn;
+pub use super::user_emails::Entity as UserEmails;
 pub use super::users::Column as UserColumn;
 pub use super::users::Entity as User;
+pub use super::webauthn_credentials::Column as WebauthnCredentialsColumn;
+pub use super::webauthn_credentials::Entity as WebauthnCredentials;
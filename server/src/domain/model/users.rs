@@ -3,7 +3,7 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::domain::types::{JpegPhoto, UserId, Uuid};
+use crate::domain::types::{JpegPhoto, SshPublicKeys, UserId, Uuid};
 
 #[derive(Copy, Clone, Default, Debug, DeriveEntity)]
 pub struct Entity;
@@ -23,6 +23,32 @@ pub struct Model {
     pub totp_secret: Option<String>,
     pub mfa_type: Option<String>,
     pub uuid: Uuid,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub ssh_public_keys: SshPublicKeys,
+    pub modified_date: chrono::DateTime<chrono::Utc>,
+    pub enabled: bool,
+    pub valid_from: Option<chrono::DateTime<chrono::Utc>>,
+    pub valid_until: Option<chrono::DateTime<chrono::Utc>>,
+    pub telephone_number: Option<String>,
+    pub mobile: Option<String>,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub locality: Option<String>,
+    pub postal_address: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub password_modified_date: Option<chrono::DateTime<chrono::Utc>>,
+    // Missing from the users deleted or backed up by the older versions.
+    #[serde(default)]
+    pub password_expiry_warned: bool,
+    pub last_ldap_login: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_web_login: Option<chrono::DateTime<chrono::Utc>>,
+    // Set by the stale accounts maintenance job.
+    pub stale_warning_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub stale_disabled_date: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl EntityName for Entity {
@@ -44,6 +70,29 @@ pub enum Column {
     TotpSecret,
     MfaType,
     Uuid,
+    UidNumber,
+    GidNumber,
+    HomeDirectory,
+    LoginShell,
+    SshPublicKeys,
+    ModifiedDate,
+    Enabled,
+    ValidFrom,
+    ValidUntil,
+    TelephoneNumber,
+    Mobile,
+    Title,
+    Department,
+    Locality,
+    PostalAddress,
+    Locale,
+    Timezone,
+    PasswordModifiedDate,
+    PasswordExpiryWarned,
+    LastLdapLogin,
+    LastWebLogin,
+    StaleWarningDate,
+    StaleDisabledDate,
 }
 
 impl ColumnTrait for Column {
@@ -62,6 +111,29 @@ impl ColumnTrait for Column {
             Column::TotpSecret => ColumnType::String(Some(64)),
             Column::MfaType => ColumnType::String(Some(64)),
             Column::Uuid => ColumnType::String(Some(36)),
+            Column::UidNumber => ColumnType::Integer,
+            Column::GidNumber => ColumnType::Integer,
+            Column::HomeDirectory => ColumnType::String(Some(255)),
+            Column::LoginShell => ColumnType::String(Some(255)),
+            Column::SshPublicKeys => ColumnType::Text,
+            Column::ModifiedDate => ColumnType::DateTime,
+            Column::Enabled => ColumnType::Boolean,
+            Column::ValidFrom => ColumnType::DateTime,
+            Column::ValidUntil => ColumnType::DateTime,
+            Column::TelephoneNumber => ColumnType::String(Some(255)),
+            Column::Mobile => ColumnType::String(Some(255)),
+            Column::Title => ColumnType::String(Some(255)),
+            Column::Department => ColumnType::String(Some(255)),
+            Column::Locality => ColumnType::String(Some(255)),
+            Column::PostalAddress => ColumnType::String(Some(255)),
+            Column::Locale => ColumnType::String(Some(35)),
+            Column::Timezone => ColumnType::String(Some(64)),
+            Column::PasswordModifiedDate => ColumnType::DateTime,
+            Column::PasswordExpiryWarned => ColumnType::Boolean,
+            Column::LastLdapLogin => ColumnType::DateTime,
+            Column::LastWebLogin => ColumnType::DateTime,
+            Column::StaleWarningDate => ColumnType::DateTime,
+            Column::StaleDisabledDate => ColumnType::DateTime,
         }
         .def()
     }
@@ -77,6 +149,8 @@ pub enum Relation {
     JwtStorage,
     #[sea_orm(has_many = "super::password_reset_tokens::Entity")]
     PasswordResetTokens,
+    #[sea_orm(has_many = "super::user_attributes::Entity")]
+    UserAttributes,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DerivePrimaryKey)]
@@ -116,6 +190,12 @@ impl Related<super::password_reset_tokens::Entity> for Entity {
     }
 }
 
+impl Related<super::user_attributes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserAttributes.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl From<Model> for crate::domain::types::User {
@@ -123,12 +203,34 @@ impl From<Model> for crate::domain::types::User {
         Self {
             user_id: user.user_id,
             email: user.email,
+            secondary_emails: Vec::new(),
             display_name: user.display_name,
             first_name: user.first_name,
             last_name: user.last_name,
             creation_date: user.creation_date,
+            modified_date: user.modified_date,
             uuid: user.uuid,
             avatar: user.avatar,
+            uid_number: user.uid_number,
+            gid_number: user.gid_number,
+            home_directory: user.home_directory,
+            login_shell: user.login_shell,
+            ssh_public_keys: user.ssh_public_keys,
+            enabled: user.enabled,
+            valid_from: user.valid_from,
+            valid_until: user.valid_until,
+            telephone_number: user.telephone_number,
+            mobile: user.mobile,
+            title: user.title,
+            department: user.department,
+            locality: user.locality,
+            postal_address: user.postal_address,
+            locale: user.locale,
+            timezone: user.timezone,
+            password_modified_date: user.password_modified_date,
+            last_ldap_login: user.last_ldap_login,
+            last_web_login: user.last_web_login,
+            attributes: Vec::new(),
         }
     }
 }
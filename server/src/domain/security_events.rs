@@ -0,0 +1,24 @@
+use crate::domain::types::{GroupId, UserId};
+use tokio::sync::broadcast;
+
+// Number of events kept for the slowest consumer before it starts missing some.
+const BUS_CAPACITY: usize = 256;
+
+/// A change to an account that its owner should know about, to notice a takeover.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecurityEvent {
+    PasswordChanged(UserId),
+    WebauthnCredentialRemoved(UserId),
+    GroupMembershipChanged {
+        user_id: UserId,
+        group_id: GroupId,
+        added: bool,
+    },
+}
+
+/// Published by the backend handler, after the change is committed.
+pub type SecurityEventBus = broadcast::Sender<SecurityEvent>;
+
+pub fn new_bus() -> SecurityEventBus {
+    broadcast::channel(BUS_CAPACITY).0
+}
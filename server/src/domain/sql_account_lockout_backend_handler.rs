@@ -0,0 +1,169 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AccountLockoutBackendHandler, AuditEvent},
+    model::{self, FailedLoginsColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{DateTime, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
+use tracing::{debug, instrument, warn};
+
+impl SqlBackendHandler {
+    fn lockout_enabled(&self) -> bool {
+        self.config.account_lockout.max_failed_attempts > 0
+    }
+
+    fn lockout_duration(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.config.account_lockout.lockout_duration_seconds as i64)
+    }
+
+    /// Fails with `AccountLocked` if the user cannot try to log in for now.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn check_account_not_locked(&self, user_id: &UserId) -> Result<()> {
+        if !self.lockout_enabled() {
+            return Ok(());
+        }
+        match self.get_account_lockout(user_id).await? {
+            Some(locked_until) => Err(DomainError::AccountLocked(format!(
+                "Too many failed logins for user '{}', try again after {}",
+                user_id, locked_until
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a failed login of the user, locking the account once there are too many.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn record_login_failure(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        if !self.lockout_enabled() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now();
+        let previous = model::FailedLogins::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?;
+        let failed_attempts = match &previous {
+            Some(failures) if failures.last_failure + self.lockout_duration() > now => {
+                failures.failed_attempts + 1
+            }
+            _ => 1,
+        };
+        let max_failed_attempts = self.config.account_lockout.max_failed_attempts;
+        let locked_until = if failed_attempts as u32 >= max_failed_attempts {
+            warn!(
+                "Locking the account of '{}' after {} failed logins",
+                user_id, failed_attempts
+            );
+            Some(now + self.lockout_duration())
+        } else {
+            None
+        };
+        let failures = model::failed_logins::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            // The count starts over after the lockout.
+            failed_attempts: ActiveValue::Set(if locked_until.is_some() {
+                0
+            } else {
+                failed_attempts
+            }),
+            last_failure: ActiveValue::Set(now),
+            locked_until: ActiveValue::Set(locked_until),
+        };
+        if previous.is_some() {
+            failures.update(&self.sql_pool).await?;
+        } else if model::User::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            failures.insert(&self.sql_pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Forgets the failed logins of the user, after a successful one.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn record_login_success(&self, user_id: &UserId) -> Result<()> {
+        if !self.lockout_enabled() {
+            return Ok(());
+        }
+        model::FailedLogins::delete_many()
+            .filter(FailedLoginsColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountLockoutBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_account_lockout(&self, user_id: &UserId) -> Result<Option<DateTime>> {
+        debug!(?user_id);
+        let now = chrono::Utc::now();
+        Ok(model::FailedLogins::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .and_then(|failures| failures.locked_until)
+            .filter(|locked_until| *locked_until > now))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn unlock_account(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        model::FailedLogins::delete_many()
+            .filter(FailedLoginsColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        self.audit("unlock_user", AuditEvent::user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{BindRequest, LoginHandler},
+        sql_backend_handler::tests::*,
+    };
+
+    async fn bind(handler: &SqlBackendHandler, name: &str, password: &str) -> Result<()> {
+        handler
+            .bind(BindRequest {
+                name: UserId::new(name),
+                password: password.to_owned(),
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_account_lockout() {
+        let mut handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        handler.config.account_lockout.max_failed_attempts = 2;
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+
+        bind(&handler, "bob", "wrong").await.unwrap_err();
+        // A successful login resets the count.
+        bind(&handler, "bob", "bob00").await.unwrap();
+        bind(&handler, "bob", "wrong").await.unwrap_err();
+        assert_eq!(handler.get_account_lockout(&bob).await.unwrap(), None);
+        bind(&handler, "bob", "wrong").await.unwrap_err();
+        assert!(handler.get_account_lockout(&bob).await.unwrap().is_some());
+        // Even the right password is refused while the account is locked.
+        assert!(matches!(
+            bind(&handler, "bob", "bob00").await,
+            Err(DomainError::AccountLocked(_))
+        ));
+
+        handler.unlock_account(&bob).await.unwrap();
+        assert_eq!(handler.get_account_lockout(&bob).await.unwrap(), None);
+        bind(&handler, "bob", "bob00").await.unwrap();
+        // The failed logins of unknown users are not tracked.
+        bind(&handler, "unknown", "wrong").await.unwrap_err();
+    }
+}
@@ -0,0 +1,152 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{ApiTokenBackendHandler, CreateApiTokenRequest, API_TOKEN_PREFIX},
+    model::{self, ApiTokensColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::ApiToken,
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use tracing::{debug, instrument};
+
+fn generate_token() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    let random: String = rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect();
+    format!("{}{}", API_TOKEN_PREFIX, random)
+}
+
+// The tokens are long random strings, a fast hash is enough to protect them.
+pub(crate) fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[async_trait]
+impl ApiTokenBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)> {
+        debug!(name = ?request.name, scope = ?request.scope, created_by = ?request.created_by);
+        let token = generate_token();
+        let new_token = model::api_tokens::ActiveModel {
+            name: ActiveValue::Set(request.name),
+            scope: ActiveValue::Set(request.scope),
+            token_hash: ActiveValue::Set(hash_token(&token)),
+            created_by: ActiveValue::Set(request.created_by),
+            creation_date: ActiveValue::Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit(
+            "create_api_token",
+            format!("api_token:{}", new_token.token_id),
+            Some(new_token.name.clone()),
+        )
+        .await;
+        Ok((new_token.into(), token))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        Ok(model::ApiTokens::find()
+            .order_by_asc(ApiTokensColumn::TokenId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_api_token(&self, token_id: i32) -> Result<()> {
+        debug!(?token_id);
+        let res = model::ApiTokens::delete_by_id(token_id)
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such API token: '{}'",
+                token_id
+            )));
+        }
+        self.audit("revoke_api_token", format!("api_token:{}", token_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn validate_api_token(&self, token: &str) -> Result<ApiToken> {
+        model::ApiTokens::find()
+            .filter(ApiTokensColumn::TokenHash.eq(hash_token(token)))
+            .one(&self.sql_pool)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| DomainError::AuthenticationError("Invalid API token".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        sql_backend_handler::tests::*,
+        types::{ApiTokenScope, UserId},
+    };
+
+    #[tokio::test]
+    async fn test_api_tokens() {
+        let fixture = TestFixture::new().await;
+        let (token, secret) = fixture
+            .handler
+            .create_api_token(CreateApiTokenRequest {
+                name: "CI".to_owned(),
+                scope: ApiTokenScope::ReadOnly,
+                created_by: UserId::new("bob"),
+            })
+            .await
+            .unwrap();
+        assert!(secret.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(token.name, "CI");
+        assert_eq!(
+            fixture
+                .handler
+                .validate_api_token(&secret)
+                .await
+                .unwrap()
+                .token_id,
+            token.token_id
+        );
+        assert!(fixture
+            .handler
+            .validate_api_token(&format!("{}x", secret))
+            .await
+            .is_err());
+        assert_eq!(
+            fixture
+                .handler
+                .list_api_tokens()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.name, t.scope))
+                .collect::<Vec<_>>(),
+            vec![("CI".to_owned(), ApiTokenScope::ReadOnly)]
+        );
+
+        fixture
+            .handler
+            .delete_api_token(token.token_id)
+            .await
+            .unwrap();
+        assert!(fixture.handler.validate_api_token(&secret).await.is_err());
+        assert!(fixture
+            .handler
+            .delete_api_token(token.token_id)
+            .await
+            .is_err());
+    }
+}
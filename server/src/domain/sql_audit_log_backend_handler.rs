@@ -0,0 +1,290 @@
+use crate::domain::{
+    error::Result,
+    handler::{AuditActor, AuditEvent, AuditLogBackendHandler, AuditLogFilter, AuditLogPage},
+    model::{self, AuditLogColumn},
+    sql_backend_handler::SqlBackendHandler,
+    sql_tables::DbConnection,
+};
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::Cond, ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+use tracing::{debug, error, instrument};
+
+fn get_audit_log_filter_expr(filter: AuditLogFilter) -> Cond {
+    let mut condition = Cond::all();
+    if let Some(actor) = filter.actor {
+        condition = condition.add(AuditLogColumn::Actor.eq(actor));
+    }
+    if let Some(action) = filter.action {
+        condition = condition.add(AuditLogColumn::Action.eq(action));
+    }
+    if let Some(target) = filter.target {
+        condition = condition.add(AuditLogColumn::Target.eq(target));
+    }
+    if let Some(since) = filter.since {
+        condition = condition.add(AuditLogColumn::Date.gte(since));
+    }
+    if let Some(until) = filter.until {
+        condition = condition.add(AuditLogColumn::Date.lt(until));
+    }
+    condition
+}
+
+#[instrument(skip_all, level = "debug", err)]
+async fn insert_audit_event(pool: &DbConnection, event: AuditEvent) -> Result<()> {
+    debug!(?event);
+    model::audit_log::ActiveModel {
+        actor: ActiveValue::Set(event.actor),
+        source: ActiveValue::Set(event.source),
+        action: ActiveValue::Set(event.action),
+        target: ActiveValue::Set(event.target),
+        details: ActiveValue::Set(event.details),
+        date: ActiveValue::Set(chrono::Utc::now()),
+        ..Default::default()
+    }
+    .insert(pool)
+    .await?;
+    Ok(())
+}
+
+/// Records a mutating operation on behalf of the actor. It already happened, so failing to record
+/// it is only logged. Also used by the scheduled jobs, which work on the tables directly.
+pub(crate) async fn audit(
+    pool: &DbConnection,
+    actor: &AuditActor,
+    action: &str,
+    target: String,
+    details: Option<String>,
+) {
+    let event = AuditEvent {
+        actor: actor.user.clone(),
+        source: actor.source,
+        action: action.to_owned(),
+        target,
+        details,
+    };
+    if let Err(e) = insert_audit_event(pool, event).await {
+        error!("Could not record {} in the audit log: {}", action, e);
+    }
+}
+
+impl SqlBackendHandler {
+    pub(crate) async fn audit(&self, action: &str, target: String, details: Option<String>) {
+        audit(&self.sql_pool, &self.audit_actor, action, target, details).await
+    }
+}
+
+#[async_trait]
+impl AuditLogBackendHandler for SqlBackendHandler {
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<()> {
+        insert_audit_event(&self.sql_pool, event).await
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_audit_logs(
+        &self,
+        filter: AuditLogFilter,
+        offset: u64,
+        limit: Option<u64>,
+    ) -> Result<AuditLogPage> {
+        debug!(?filter, offset, ?limit);
+        let query = model::AuditLog::find().filter(get_audit_log_filter_expr(filter));
+        let total_count = query.clone().count(&self.sql_pool).await? as u64;
+        let mut query = query.order_by_desc(AuditLogColumn::AuditId).offset(offset);
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        Ok(AuditLogPage {
+            entries: query
+                .all(&self.sql_pool)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            total_count,
+        })
+    }
+
+    fn set_audit_actor(&mut self, actor: AuditActor) {
+        self.audit_actor = actor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UserBackendHandler},
+        sql_backend_handler::tests::*,
+        types::{AuditSource, UserId},
+    };
+
+    fn make_event(actor: &str, action: &str, target: &str) -> AuditEvent {
+        AuditEvent {
+            actor: UserId::new(actor),
+            source: AuditSource::Graphql,
+            action: action.to_owned(),
+            target: target.to_owned(),
+            details: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_audit_logs() {
+        // The fixture would record the creation of its users and groups.
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        for event in [
+            make_event("admin", "create_user", "user:alice"),
+            make_event("admin", "add_user_to_group", "user:alice"),
+            make_event("bob", "update_user", "user:bob"),
+        ] {
+            handler.record_audit_event(event).await.unwrap();
+        }
+        let summarize = |page: AuditLogPage| {
+            page.entries
+                .into_iter()
+                .map(|e| (e.actor.into_string(), e.action))
+                .collect::<Vec<_>>()
+        };
+
+        let page = handler
+            .list_audit_logs(AuditLogFilter::default(), 1, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(page.total_count, 3);
+        assert_eq!(
+            summarize(page),
+            vec![("admin".to_owned(), "add_user_to_group".to_owned())]
+        );
+
+        let page = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    target: Some("user:alice".to_owned()),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            summarize(page),
+            vec![
+                ("admin".to_owned(), "add_user_to_group".to_owned()),
+                ("admin".to_owned(), "create_user".to_owned()),
+            ]
+        );
+
+        let page = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    actor: Some(UserId::new("bob")),
+                    until: Some(chrono::Utc::now() + chrono::Duration::days(1)),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            summarize(page),
+            vec![("bob".to_owned(), "update_user".to_owned())]
+        );
+
+        let page = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    action: Some("create_user".to_owned()),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            summarize(page),
+            vec![("admin".to_owned(), "create_user".to_owned())]
+        );
+
+        let page = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    since: Some(chrono::Utc::now() + chrono::Duration::days(1)),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_mutations_are_recorded_with_the_actor() {
+        let fixture = TestFixture::new().await;
+        let handler = fixture
+            .handler
+            .clone()
+            .with_audit_actor(AuditActor::new(UserId::new("admin"), AuditSource::Graphql));
+        let group_id = handler.create_group("Audited Group").await.unwrap();
+        handler
+            .add_user_to_group(&UserId::new("bob"), group_id)
+            .await
+            .unwrap();
+        let entries = fixture
+            .handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    actor: Some(UserId::new("admin")),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|e| (e.source, e.action, e.target, e.details))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    AuditSource::Graphql,
+                    "add_user_to_group".to_owned(),
+                    "user:bob".to_owned(),
+                    Some(AuditEvent::group_target(group_id)),
+                ),
+                (
+                    AuditSource::Graphql,
+                    "create_group".to_owned(),
+                    AuditEvent::group_target(group_id),
+                    Some("Audited Group".to_owned()),
+                ),
+            ]
+        );
+        // Without an actor, the handler acts as the server, e.g. for the fixture.
+        let page = fixture
+            .handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    actor: Some(UserId::new("system")),
+                    action: Some("create_user".to_owned()),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total_count, 4);
+        assert!(page.entries.iter().all(|e| e.source == AuditSource::Server));
+    }
+}
@@ -1,22 +1,206 @@
-use super::{handler::BackendHandler, sql_tables::DbConnection};
-use crate::infra::configuration::Configuration;
+use super::{
+    handler::{AuditActor, BackendHandler, SortDirection, SubStringFilter},
+    lookup_cache::LookupCache,
+    security_events::{SecurityEvent, SecurityEventBus},
+    sql_tables::DbConnection,
+    types::AuditSource,
+};
+use crate::infra::configuration::{CacheOptions, Configuration};
 use async_trait::async_trait;
+use sea_orm::{ConnectionTrait, Order, Statement};
+use sea_query::{Expr, Func, IntoColumnRef, LikeExpr, SimpleExpr};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// How often the read replica is checked, to stop using it while it is down.
+const READ_REPLICA_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A read-only copy of the database, for the queries that can be served slightly out of date.
+#[derive(Clone)]
+struct ReadReplica {
+    pool: DbConnection,
+    available: Arc<AtomicBool>,
+}
 
 #[derive(Clone)]
 pub struct SqlBackendHandler {
     pub(crate) config: Configuration,
     pub(crate) sql_pool: DbConnection,
+    read_replica: Option<ReadReplica>,
+    pub(crate) cache: Option<LookupCache>,
+    security_events: Option<SecurityEventBus>,
+    pub(crate) audit_actor: AuditActor,
 }
 
 impl SqlBackendHandler {
     pub fn new(config: Configuration, sql_pool: DbConnection) -> Self {
-        SqlBackendHandler { config, sql_pool }
+        SqlBackendHandler {
+            config,
+            sql_pool,
+            read_replica: None,
+            cache: None,
+            security_events: None,
+            audit_actor: AuditActor::system(AuditSource::Server),
+        }
+    }
+
+    /// Records the mutating operations on behalf of the actor, instead of the server.
+    pub fn with_audit_actor(self, actor: AuditActor) -> Self {
+        SqlBackendHandler {
+            audit_actor: actor,
+            ..self
+        }
+    }
+
+    /// Caches the user details, user groups and group lists, if enabled in the options.
+    pub fn with_cache(self, options: &CacheOptions) -> Self {
+        SqlBackendHandler {
+            cache: options.enabled.then(|| LookupCache::new(options)),
+            ..self
+        }
+    }
+
+    pub fn lookup_cache(&self) -> Option<&LookupCache> {
+        self.cache.as_ref()
+    }
+
+    /// Clears the lookup cache, after a change to the users, groups or memberships.
+    pub(crate) fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate();
+        }
+    }
+
+    /// Publishes the password, WebAuthn credential and membership changes on the bus.
+    pub fn with_security_events(self, bus: SecurityEventBus) -> Self {
+        SqlBackendHandler {
+            security_events: Some(bus),
+            ..self
+        }
+    }
+
+    pub(crate) fn publish_security_event(&self, event: SecurityEvent) {
+        if let Some(bus) = &self.security_events {
+            // Fails only when nobody is listening, which is fine.
+            drop(bus.send(event));
+        }
+    }
+
+    /// Sends the read-only queries of the listings and searches to the replica, while it is up.
+    pub fn with_read_replica(self, pool: DbConnection) -> Self {
+        SqlBackendHandler {
+            read_replica: Some(ReadReplica {
+                pool,
+                available: Arc::new(AtomicBool::new(true)),
+            }),
+            ..self
+        }
+    }
+
+    /// The connection for the read-only queries: the replica if there is one and it is up,
+    /// otherwise the primary. The queries that a write, a login or a permission check depends on
+    /// must use `sql_pool`, since the replica can lag behind.
+    pub(crate) fn read_pool(&self) -> &DbConnection {
+        match &self.read_replica {
+            Some(replica) if replica.available.load(Ordering::Relaxed) => &replica.pool,
+            _ => &self.sql_pool,
+        }
+    }
+
+    /// Checks the read replica periodically, falling back to the primary while it is down. Never
+    /// returns.
+    pub async fn monitor_read_replica(self) {
+        let replica = match self.read_replica {
+            Some(replica) => replica,
+            None => return,
+        };
+        let mut interval = tokio::time::interval(READ_REPLICA_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let result = replica
+                .pool
+                .execute(Statement::from_string(
+                    replica.pool.get_database_backend(),
+                    "SELECT 1".to_owned(),
+                ))
+                .await;
+            let was_available = replica.available.swap(result.is_ok(), Ordering::Relaxed);
+            match result {
+                Err(e) if was_available => {
+                    warn!(
+                        "The read replica is down, using the primary database: {:#}",
+                        e
+                    )
+                }
+                Ok(_) if !was_available => info!("The read replica is back up"),
+                _ => (),
+            }
+        }
     }
 }
 
 #[async_trait]
 impl BackendHandler for SqlBackendHandler {}
 
+pub(crate) fn to_sql_order(direction: SortDirection) -> Order {
+    match direction {
+        SortDirection::Ascending => Order::Asc,
+        SortDirection::Descending => Order::Desc,
+    }
+}
+
+/// Normalizes a value following the LDAP caseIgnoreMatch rules: leading and trailing spaces are
+/// insignificant, inner runs of spaces count as one, and the case is ignored.
+pub(crate) fn normalize_case_ignore(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Matches the rows where the column is equal to the value according to caseIgnoreMatch.
+///
+/// The comparison is done on `LOWER(column)` rather than relying on the column collation, since
+/// only MySQL uses a case-insensitive collation by default; `LOWER` behaves the same on SQLite,
+/// MySQL and Postgres (SQLite only folds ASCII characters).
+pub(crate) fn case_ignore_eq(column: impl IntoColumnRef, value: &str) -> SimpleExpr {
+    Expr::expr(Func::lower(Expr::col(column))).eq(normalize_case_ignore(value))
+}
+
+fn escape_like_pattern(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .to_lowercase()
+}
+
+/// Matches the rows where the column matches the substring filter according to
+/// caseIgnoreSubstringsMatch, with a `LIKE` condition.
+pub(crate) fn case_ignore_like(column: impl IntoColumnRef, filter: &SubStringFilter) -> SimpleExpr {
+    let mut pattern = filter
+        .initial
+        .as_deref()
+        .map(escape_like_pattern)
+        .unwrap_or_default();
+    pattern.push('%');
+    for any in &filter.any {
+        pattern.push_str(&escape_like_pattern(any));
+        pattern.push('%');
+    }
+    if let Some(final_) = &filter.final_ {
+        pattern.push_str(&escape_like_pattern(final_));
+    }
+    Expr::expr(Func::lower(Expr::col(column))).like(LikeExpr::str(&pattern).escape('\\'))
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -33,6 +217,11 @@ pub mod tests {
     use lldap_auth::{opaque, registration};
     use sea_orm::Database;
 
+    /// Real SSH public keys, generated by `ssh-keygen`.
+    pub const ED25519_PUBLIC_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGObBDWLPLeWnsceSN9h1WfmqVjr9zX6xc4BnoVSjXPX bob@laptop";
+    pub const ECDSA_PUBLIC_KEY: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBJpR2T/ihBJ37+LGhUJN0nZzIB8JIM4pfRMCBp9MIpjQx+CvhovNMHT3ICHnNGDG3xT4W20+v40PNBMVBq74Ehc= bob@desktop";
+    pub const RSA_PUBLIC_KEY: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQC0Y22wP7Szm/kv2Z8Ed/O2kz1lG2+O/kHwaQkk3g/MFcPtB2akzfz/Hex8vPS8tiLq0OZIy5OVQfLoYCrys0KO9pNRd7D2bo4buenCy1jLv89ULVEQVsEB9YNBRg2L/btfxfZ27gPqSRukpQFb6nbCKD0iNKZiGNuy/GSmPvlU+w== bob@server";
+
     pub fn get_default_config() -> Configuration {
         ConfigurationBuilder::for_tests()
     }
@@ -50,6 +239,9 @@ pub mod tests {
     pub async fn get_initialized_db() -> DbConnection {
         let sql_pool = get_in_memory_db().await;
         init_table(&sql_pool).await.unwrap();
+        crate::infra::jwt_sql_tables::init_table(&sql_pool)
+            .await
+            .unwrap();
         sql_pool
     }
 
@@ -166,4 +358,34 @@ pub mod tests {
             assert_eq!(user.user_id, user_name);
         }
     }
+
+    #[tokio::test]
+    async fn test_read_replica_lag() {
+        let fixture = TestFixture::new().await;
+        // A replica that lags behind the primary, so much that it doesn't have the users yet.
+        let handler = fixture
+            .handler
+            .with_read_replica(get_initialized_db().await);
+        let bob = UserId::new("bob");
+        assert!(handler.list_users(None, false).await.unwrap().is_empty());
+        assert!(handler.get_user_details(&bob).await.is_err());
+        assert_eq!(
+            handler
+                .get_user_details_from_primary(&bob)
+                .await
+                .unwrap()
+                .user_id,
+            bob
+        );
+        assert_eq!(
+            handler
+                .get_user_groups_from_primary(&bob)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![fixture.groups[0]]
+        );
+    }
 }
@@ -0,0 +1,214 @@
+use crate::domain::{
+    model,
+    sql_migrations::{get_schema_version, LAST_SCHEMA_VERSION},
+    sql_tables::DbConnection,
+};
+use anyhow::{Context, Result};
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseTransaction, DbBackend, EntityTrait,
+    IntoActiveModel, PaginatorTrait, Statement, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Version of the backup format, bumped on incompatible changes.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+// Number of rows per INSERT, below the limits on the number of bound parameters.
+const INSERT_BATCH_SIZE: usize = 100;
+
+/// The whole content of the directory: users with their password records, groups, memberships and
+/// custom attributes. The sessions, tokens and logs are not part of it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub format_version: u32,
+    pub lldap_version: String,
+    pub creation_date: chrono::DateTime<chrono::Utc>,
+    pub users: Vec<model::users::Model>,
+    pub groups: Vec<model::groups::Model>,
+    pub memberships: Vec<model::memberships::Model>,
+    pub group_memberships: Vec<model::group_memberships::Model>,
+    pub user_attribute_schema: Vec<model::user_attribute_schema::Model>,
+    pub group_attribute_schema: Vec<model::group_attribute_schema::Model>,
+    pub user_attributes: Vec<model::user_attributes::Model>,
+    pub group_attributes: Vec<model::group_attributes::Model>,
+    /// Missing from the backups of the older versions.
+    #[serde(default)]
+    pub legacy_password_hashes: Vec<model::legacy_password_hashes::Model>,
+    #[serde(default)]
+    pub user_emails: Vec<model::user_emails::Model>,
+}
+
+/// Reads the directory, within a transaction so that the backup is consistent.
+pub async fn create_backup(pool: &DbConnection) -> Result<Backup> {
+    let transaction = pool.begin().await?;
+    let backup = Backup {
+        format_version: BACKUP_FORMAT_VERSION,
+        lldap_version: env!("CARGO_PKG_VERSION").to_owned(),
+        creation_date: chrono::Utc::now(),
+        users: model::User::find().all(&transaction).await?,
+        groups: model::Group::find().all(&transaction).await?,
+        memberships: model::Membership::find().all(&transaction).await?,
+        group_memberships: model::GroupMembership::find().all(&transaction).await?,
+        user_attribute_schema: model::UserAttributeSchema::find().all(&transaction).await?,
+        group_attribute_schema: model::GroupAttributeSchema::find()
+            .all(&transaction)
+            .await?,
+        user_attributes: model::UserAttributes::find().all(&transaction).await?,
+        group_attributes: model::GroupAttributes::find().all(&transaction).await?,
+        legacy_password_hashes: model::LegacyPasswordHashes::find()
+            .all(&transaction)
+            .await?,
+        user_emails: model::UserEmails::find().all(&transaction).await?,
+    };
+    transaction.commit().await?;
+    Ok(backup)
+}
+
+async fn insert_all<A>(
+    transaction: &DatabaseTransaction,
+    rows: Vec<<A::Entity as EntityTrait>::Model>,
+) -> Result<()>
+where
+    A: ActiveModelTrait + Send,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+{
+    let mut rows = rows
+        .into_iter()
+        .map(IntoActiveModel::into_active_model)
+        .peekable();
+    while rows.peek().is_some() {
+        <A::Entity as EntityTrait>::insert_many(
+            rows.by_ref().take(INSERT_BATCH_SIZE).collect::<Vec<A>>(),
+        )
+        .exec(transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+// The restored groups keep their IDs: the Postgres sequence has to continue after them.
+async fn reset_group_id_sequence(transaction: &DatabaseTransaction) -> Result<()> {
+    if transaction.get_database_backend() == DbBackend::Postgres {
+        transaction
+            .execute(Statement::from_string(
+                DbBackend::Postgres,
+                "SELECT setval(pg_get_serial_sequence('groups', 'group_id'), \
+                 COALESCE((SELECT MAX(group_id) FROM groups), 0) + 1, false)"
+                    .to_owned(),
+            ))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Replaces the content of the directory with the backup. Unless `replace` is set, the directory
+/// must be empty.
+pub async fn restore_backup(pool: &DbConnection, backup: Backup, replace: bool) -> Result<()> {
+    if backup.format_version != BACKUP_FORMAT_VERSION {
+        anyhow::bail!(
+            "Unsupported backup format version {}, expected {}",
+            backup.format_version,
+            BACKUP_FORMAT_VERSION
+        );
+    }
+    if get_schema_version(pool).await != Some(LAST_SCHEMA_VERSION) {
+        anyhow::bail!("The database schema is not up to date, start the server once to create it");
+    }
+    let transaction = pool.begin().await?;
+    let existing = model::User::find().count(&transaction).await?
+        + model::Group::find().count(&transaction).await?;
+    if existing != 0 {
+        if !replace {
+            anyhow::bail!("The database is not empty");
+        }
+        // The memberships and attributes are deleted in cascade.
+        model::User::delete_many().exec(&transaction).await?;
+        model::Group::delete_many().exec(&transaction).await?;
+        model::UserAttributeSchema::delete_many()
+            .exec(&transaction)
+            .await?;
+        model::GroupAttributeSchema::delete_many()
+            .exec(&transaction)
+            .await?;
+    }
+    info!(
+        "Restoring {} users and {} groups, backed up by LLDAP {} on {}",
+        backup.users.len(),
+        backup.groups.len(),
+        backup.lldap_version,
+        backup.creation_date
+    );
+    insert_all::<model::user_attribute_schema::ActiveModel>(
+        &transaction,
+        backup.user_attribute_schema,
+    )
+    .await?;
+    insert_all::<model::group_attribute_schema::ActiveModel>(
+        &transaction,
+        backup.group_attribute_schema,
+    )
+    .await?;
+    insert_all::<model::users::ActiveModel>(&transaction, backup.users).await?;
+    insert_all::<model::groups::ActiveModel>(&transaction, backup.groups).await?;
+    insert_all::<model::memberships::ActiveModel>(&transaction, backup.memberships).await?;
+    insert_all::<model::group_memberships::ActiveModel>(&transaction, backup.group_memberships)
+        .await?;
+    insert_all::<model::user_attributes::ActiveModel>(&transaction, backup.user_attributes).await?;
+    insert_all::<model::group_attributes::ActiveModel>(&transaction, backup.group_attributes)
+        .await?;
+    insert_all::<model::legacy_password_hashes::ActiveModel>(
+        &transaction,
+        backup.legacy_password_hashes,
+    )
+    .await?;
+    insert_all::<model::user_emails::ActiveModel>(&transaction, backup.user_emails).await?;
+    reset_group_id_sequence(&transaction).await?;
+    transaction
+        .commit()
+        .await
+        .context("while committing the restored data")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UserBackendHandler},
+        sql_backend_handler::{tests::*, SqlBackendHandler},
+        types::UserId,
+    };
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let fixture = TestFixture::new().await;
+        let backup = create_backup(&fixture.handler.sql_pool).await.unwrap();
+        assert_eq!(backup.users.len(), 4);
+        let serialized = serde_json::to_vec(&backup).unwrap();
+        let backup: Backup = serde_json::from_slice(&serialized).unwrap();
+
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool.clone());
+        restore_backup(&sql_pool, backup, false).await.unwrap();
+        let users = handler.list_users(None, true).await.unwrap();
+        assert_eq!(users.len(), 4);
+        assert_eq!(
+            handler
+                .get_user_groups(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        // New groups don't reuse the restored IDs.
+        handler.create_group("NewGroup").await.unwrap();
+
+        // The directory is not empty anymore.
+        let backup = create_backup(&fixture.handler.sql_pool).await.unwrap();
+        assert!(restore_backup(&sql_pool, backup, false).await.is_err());
+        let backup = create_backup(&fixture.handler.sql_pool).await.unwrap();
+        restore_backup(&sql_pool, backup, true).await.unwrap();
+        assert_eq!(handler.list_groups(None).await.unwrap().len(), 3);
+    }
+}
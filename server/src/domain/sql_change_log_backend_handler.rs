@@ -0,0 +1,307 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::ChangeLogBackendHandler,
+    model::{self, ChangeLogColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{Change, ChangeDiff, ChangeEntryType, ChangeType, GroupId, UserId, Uuid},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+use tracing::{debug, instrument};
+
+impl SqlBackendHandler {
+    /// Records a change in the change log, using the given connection (possibly a transaction).
+    pub(crate) async fn log_change<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        entry_type: ChangeEntryType,
+        entry_id: String,
+        entry_uuid: Uuid,
+        change_type: ChangeType,
+        diff: Option<ChangeDiff>,
+    ) -> Result<()> {
+        let diff = diff
+            .map(|diff| serde_json::to_string(&diff))
+            .transpose()
+            .map_err(|e| DomainError::InternalError(format!("Could not serialize diff: {}", e)))?;
+        model::change_log::ActiveModel {
+            entry_type: ActiveValue::Set(entry_type),
+            entry_id: ActiveValue::Set(entry_id),
+            entry_uuid: ActiveValue::Set(entry_uuid),
+            change_type: ActiveValue::Set(change_type),
+            change_date: ActiveValue::Set(chrono::Utc::now()),
+            diff: ActiveValue::Set(diff),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a change of the user in the change log, if the user exists. Deletions must be
+    /// recorded before deleting the user.
+    pub(crate) async fn log_user_change<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        change_type: ChangeType,
+    ) -> Result<()> {
+        self.log_user_entry(conn, user_id, change_type, None).await
+    }
+
+    /// Records a modification of the user, with what it changed.
+    pub(crate) async fn log_user_modification<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        diff: ChangeDiff,
+    ) -> Result<()> {
+        self.log_user_entry(conn, user_id, ChangeType::Modify, Some(diff))
+            .await
+    }
+
+    async fn log_user_entry<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        change_type: ChangeType,
+        diff: Option<ChangeDiff>,
+    ) -> Result<()> {
+        if let Some(user) = model::User::find_by_id(user_id.clone()).one(conn).await? {
+            self.log_change(
+                conn,
+                ChangeEntryType::User,
+                user.user_id.into_string(),
+                user.uuid,
+                change_type,
+                diff,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Same as `log_user_change`, for groups.
+    pub(crate) async fn log_group_change<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        group_id: GroupId,
+        change_type: ChangeType,
+    ) -> Result<()> {
+        self.log_group_entry(conn, group_id, change_type, None)
+            .await
+    }
+
+    /// Same as `log_user_modification`, for groups.
+    pub(crate) async fn log_group_modification<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        group_id: GroupId,
+        diff: ChangeDiff,
+    ) -> Result<()> {
+        self.log_group_entry(conn, group_id, ChangeType::Modify, Some(diff))
+            .await
+    }
+
+    async fn log_group_entry<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        group_id: GroupId,
+        change_type: ChangeType,
+        diff: Option<ChangeDiff>,
+    ) -> Result<()> {
+        if let Some(group) = model::Group::find_by_id(group_id).one(conn).await? {
+            self.log_change(
+                conn,
+                ChangeEntryType::Group,
+                group.display_name,
+                group.uuid,
+                change_type,
+                diff,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChangeLogBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_changes_since(&self, change_id: i32) -> Result<Vec<Change>> {
+        debug!(?change_id);
+        Ok(model::ChangeLog::find()
+            .filter(ChangeLogColumn::ChangeId.gt(change_id))
+            .order_by_asc(ChangeLogColumn::ChangeId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_changes_page(&self, change_id: i32, limit: u64) -> Result<Vec<Change>> {
+        debug!(?change_id, ?limit);
+        Ok(model::ChangeLog::find()
+            .filter(ChangeLogColumn::ChangeId.gt(change_id))
+            .order_by_asc(ChangeLogColumn::ChangeId)
+            .limit(limit)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_last_change_id(&self) -> Result<i32> {
+        Ok(model::ChangeLog::find()
+            .order_by_desc(ChangeLogColumn::ChangeId)
+            .one(&self.sql_pool)
+            .await?
+            .map(|change| change.change_id)
+            .unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UpdateUserRequest, UserBackendHandler},
+        sql_backend_handler::tests::*,
+    };
+
+    fn summarize(changes: Vec<Change>) -> Vec<(ChangeEntryType, String, ChangeType)> {
+        changes
+            .into_iter()
+            .map(|c| (c.entry_type, c.entry_id, c.change_type))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_changes_since() {
+        let fixture = TestFixture::new().await;
+        let last_change_id = fixture.handler.get_last_change_id().await.unwrap();
+        assert_eq!(
+            fixture.handler.list_changes_since(0).await.unwrap().len(),
+            last_change_id as usize
+        );
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                first_name: Some("first_name".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[1])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .delete_group(fixture.groups[2])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            summarize(
+                fixture
+                    .handler
+                    .list_changes_since(last_change_id)
+                    .await
+                    .unwrap()
+            ),
+            vec![
+                (ChangeEntryType::User, "bob".to_owned(), ChangeType::Modify),
+                (
+                    ChangeEntryType::User,
+                    "patrick".to_owned(),
+                    ChangeType::Modify
+                ),
+                (
+                    ChangeEntryType::Group,
+                    "Worst Group".to_owned(),
+                    ChangeType::Modify
+                ),
+                (
+                    ChangeEntryType::Group,
+                    "Empty Group".to_owned(),
+                    ChangeType::Delete
+                ),
+            ]
+        );
+        assert_eq!(
+            fixture.handler.get_last_change_id().await.unwrap(),
+            last_change_id + 4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_diffs() {
+        let fixture = TestFixture::new().await;
+        let last_change_id = fixture.handler.get_last_change_id().await.unwrap();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                first_name: Some("first_name".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[1])
+            .await
+            .unwrap();
+
+        let diffs: Vec<_> = fixture
+            .handler
+            .list_changes_since(last_change_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.diff)
+            .collect();
+        assert_eq!(
+            diffs,
+            vec![
+                Some(ChangeDiff {
+                    fields: vec!["first_name".to_owned()],
+                    ..Default::default()
+                }),
+                Some(ChangeDiff {
+                    removed_memberships: vec!["Worst Group".to_owned()],
+                    ..Default::default()
+                }),
+                Some(ChangeDiff {
+                    removed_memberships: vec!["patrick".to_owned()],
+                    ..Default::default()
+                }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_changes_page() {
+        let fixture = TestFixture::new().await;
+        let changes = fixture.handler.list_changes_since(0).await.unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_changes_page(changes[0].change_id, 2)
+                .await
+                .unwrap(),
+            changes[1..3].to_vec()
+        );
+    }
+}
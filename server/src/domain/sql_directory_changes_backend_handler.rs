@@ -0,0 +1,228 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, DirectoryChangesBackendHandler, DirectoryOperation},
+    sql_backend_handler::SqlBackendHandler,
+    sql_schema_backend_handler::normalize_attribute_names,
+    sql_user_backend_handler::updated_user_fields,
+    types::{AttributeValue, GroupId},
+};
+use async_trait::async_trait;
+use sea_orm::TransactionTrait;
+use tracing::{debug, instrument};
+
+// Points to the operation of the batch that failed, keeping the kind of error.
+fn in_operation(index: usize, error: DomainError) -> DomainError {
+    let prefix = |message: String| format!("Operation {}: {}", index, message);
+    match error {
+        DomainError::EntityNotFound(message) => DomainError::EntityNotFound(prefix(message)),
+        DomainError::InvalidRequest(message) => DomainError::InvalidRequest(prefix(message)),
+        DomainError::LastAdmin(message) => DomainError::LastAdmin(prefix(message)),
+        DomainError::InternalError(message) => DomainError::InternalError(prefix(message)),
+        e => e,
+    }
+}
+
+// Takes the custom attributes set by the operation, to validate them.
+fn take_attributes(operation: &mut DirectoryOperation) -> Vec<AttributeValue> {
+    normalize_attribute_names(match operation {
+        DirectoryOperation::CreateUser(request) => std::mem::take(&mut request.attributes),
+        DirectoryOperation::UpdateUser(request) => std::mem::take(&mut request.insert_attributes),
+        DirectoryOperation::UpdateGroup(request) => std::mem::take(&mut request.insert_attributes),
+        _ => Vec::new(),
+    })
+}
+
+// The action, target and details of the audit log entry of the operation. The target of a new
+// group is only known once created, it is left empty.
+fn audit_entry(operation: &DirectoryOperation) -> (&'static str, String, Option<String>) {
+    match operation {
+        DirectoryOperation::CreateUser(request) => (
+            "create_user",
+            AuditEvent::user_target(&request.user_id),
+            None,
+        ),
+        DirectoryOperation::UpdateUser(request) => (
+            "update_user",
+            AuditEvent::user_target(&request.user_id),
+            updated_user_fields(request),
+        ),
+        DirectoryOperation::CreateGroup(name) => {
+            ("create_group", String::new(), Some(name.clone()))
+        }
+        DirectoryOperation::UpdateGroup(request) => (
+            "update_group",
+            AuditEvent::group_target(request.group_id),
+            request.display_name.clone(),
+        ),
+        DirectoryOperation::AddUserToGroup { user_id, group_id } => (
+            "add_user_to_group",
+            AuditEvent::user_target(user_id),
+            Some(AuditEvent::group_target(*group_id)),
+        ),
+        DirectoryOperation::RemoveUserFromGroup { user_id, group_id } => (
+            "remove_user_from_group",
+            AuditEvent::user_target(user_id),
+            Some(AuditEvent::group_target(*group_id)),
+        ),
+    }
+}
+
+#[async_trait]
+impl DirectoryChangesBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn apply_changes(&self, operations: Vec<DirectoryOperation>) -> Result<Vec<GroupId>> {
+        debug!(count = operations.len());
+        // The attributes are validated before starting the transaction, which holds a connection.
+        let mut changes = Vec::with_capacity(operations.len());
+        for (index, mut operation) in operations.into_iter().enumerate() {
+            let entry = audit_entry(&operation);
+            let attributes = take_attributes(&mut operation);
+            match operation {
+                DirectoryOperation::UpdateGroup(_) => {
+                    self.validate_group_attributes(&attributes).await
+                }
+                _ => self.validate_user_attributes(&attributes).await,
+            }
+            .map_err(|e| in_operation(index, e))?;
+            changes.push((operation, attributes, entry));
+        }
+        let transaction = self.sql_pool.begin().await?;
+        let mut group_ids = Vec::new();
+        let mut membership_changes = Vec::new();
+        let mut audit_entries = Vec::with_capacity(changes.len());
+        for (index, (operation, attributes, (action, mut target, details))) in
+            changes.into_iter().enumerate()
+        {
+            // On error, the transaction is rolled back when dropped.
+            match operation {
+                DirectoryOperation::CreateUser(request) => self
+                    .insert_new_user(&transaction, request, attributes)
+                    .await
+                    .map(|_| ()),
+                DirectoryOperation::UpdateUser(request) => {
+                    self.apply_user_update(&transaction, request, attributes)
+                        .await
+                }
+                DirectoryOperation::CreateGroup(name) => self
+                    .insert_group(&transaction, &name)
+                    .await
+                    .map(|group_id| {
+                        target = AuditEvent::group_target(group_id);
+                        group_ids.push(group_id)
+                    }),
+                DirectoryOperation::UpdateGroup(request) => {
+                    self.apply_group_update(&transaction, request, attributes)
+                        .await
+                }
+                DirectoryOperation::AddUserToGroup { user_id, group_id } => {
+                    let result = self
+                        .insert_membership(&transaction, &user_id, group_id, None)
+                        .await;
+                    membership_changes.push((user_id, group_id, true));
+                    result
+                }
+                DirectoryOperation::RemoveUserFromGroup { user_id, group_id } => {
+                    let result = async {
+                        self.check_admins_remain(
+                            &transaction,
+                            Some(group_id),
+                            std::slice::from_ref(&user_id),
+                        )
+                        .await?;
+                        self.delete_membership(&transaction, &user_id, group_id)
+                            .await
+                    }
+                    .await;
+                    membership_changes.push((user_id, group_id, false));
+                    result
+                }
+            }
+            .map_err(|e| in_operation(index, e))?;
+            audit_entries.push((action, target, details));
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        for (user_id, group_id, added) in membership_changes {
+            self.publish_membership_changes(std::slice::from_ref(&user_id), group_id, added);
+        }
+        for (action, target, details) in audit_entries {
+            self.audit(action, target, details).await;
+        }
+        Ok(group_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{CreateUserRequest, UpdateGroupRequest, UserBackendHandler},
+        sql_backend_handler::tests::*,
+        types::UserId,
+    };
+
+    #[tokio::test]
+    async fn test_apply_changes() {
+        let fixture = TestFixture::new().await;
+        let alice = UserId::new("alice");
+        let create_alice = DirectoryOperation::CreateUser(CreateUserRequest {
+            user_id: alice.clone(),
+            email: "alice@example.com".to_owned(),
+            ..Default::default()
+        });
+        // Bob is not in the third group: nothing is applied.
+        let error = fixture
+            .handler
+            .apply_changes(vec![
+                create_alice.clone(),
+                DirectoryOperation::RemoveUserFromGroup {
+                    user_id: UserId::new("bob"),
+                    group_id: fixture.groups[2],
+                },
+            ])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Operation 1: "), "{}", error);
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+
+        let group_ids = fixture
+            .handler
+            .apply_changes(vec![
+                create_alice,
+                DirectoryOperation::CreateGroup("Interns".to_owned()),
+                DirectoryOperation::UpdateGroup(UpdateGroupRequest {
+                    group_id: fixture.groups[2],
+                    display_name: Some("Full Group".to_owned()),
+                    ..Default::default()
+                }),
+                DirectoryOperation::AddUserToGroup {
+                    user_id: alice.clone(),
+                    group_id: fixture.groups[2],
+                },
+                DirectoryOperation::RemoveUserFromGroup {
+                    user_id: UserId::new("bob"),
+                    group_id: fixture.groups[0],
+                },
+            ])
+            .await
+            .unwrap();
+        assert_eq!(group_ids.len(), 1);
+        let groups = fixture.handler.get_user_groups(&alice).await.unwrap();
+        assert_eq!(
+            groups
+                .into_iter()
+                .map(|g| g.display_name)
+                .collect::<Vec<_>>(),
+            vec!["Full Group"]
+        );
+        assert!(fixture
+            .handler
+            .get_user_groups(&UserId::new("bob"))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,18 +1,107 @@
 use crate::domain::{
     error::{DomainError, Result},
-    handler::{GroupBackendHandler, GroupRequestFilter, UpdateGroupRequest},
-    model::{self, GroupColumn, MembershipColumn},
-    sql_backend_handler::SqlBackendHandler,
-    types::{Group, GroupDetails, GroupId, Uuid},
+    handler::{
+        AuditEvent, GroupBackendHandler, GroupOrder, GroupRequestFilter, UpdateGroupRequest,
+    },
+    model::{self, GroupAttributesColumn, GroupColumn, GroupMembershipColumn, MembershipColumn},
+    sql_backend_handler::{case_ignore_eq, case_ignore_like, to_sql_order, SqlBackendHandler},
+    sql_schema_backend_handler::{
+        deserialize_attribute_value, normalize_attribute_names, serialize_attribute_value,
+    },
+    sql_user_backend_handler::to_value,
+    types::{AttributeValue, ChangeDiff, ChangeType, Group, GroupDetails, GroupId, UserId, Uuid},
 };
 use async_trait::async_trait;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
-    QueryTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, IdenStatic, Iterable,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, TransactionTrait,
 };
-use sea_query::{Cond, IntoCondition, SimpleExpr};
+use sea_query::{Cond, Expr, IntoCondition, SelectStatement, SimpleExpr};
+use std::collections::{HashMap, HashSet};
 use tracing::{debug, instrument};
 
+/// Maps each group to the groups directly nested in it (or, once inverted, to its direct parents).
+pub(crate) type GroupNesting = HashMap<GroupId, Vec<GroupId>>;
+
+/// Returns the given groups along with all the groups reachable from them through `nesting`.
+pub(crate) fn get_reachable_groups(
+    nesting: &GroupNesting,
+    groups: impl IntoIterator<Item = GroupId>,
+) -> HashSet<GroupId> {
+    let mut reachable = HashSet::new();
+    let mut to_visit: Vec<GroupId> = groups.into_iter().collect();
+    while let Some(group) = to_visit.pop() {
+        if reachable.insert(group) {
+            if let Some(next) = nesting.get(&group) {
+                to_visit.extend(next.iter().copied());
+            }
+        }
+    }
+    reachable
+}
+
+pub(crate) fn invert_nesting(nesting: &GroupNesting) -> GroupNesting {
+    let mut inverted = GroupNesting::new();
+    for (parent, children) in nesting {
+        for child in children {
+            inverted.entry(*child).or_default().push(*parent);
+        }
+    }
+    inverted
+}
+
+fn collect_filter_members(filter: &GroupRequestFilter, members: &mut Vec<UserId>) {
+    use GroupRequestFilter::*;
+    match filter {
+        And(fs) | Or(fs) => fs.iter().for_each(|f| collect_filter_members(f, members)),
+        Not(f) => collect_filter_members(f, members),
+        Member(user) => members.push(user.clone()),
+        DisplayName(_)
+        | DisplayNameSubString(_)
+        | Uuid(_)
+        | GroupId(_)
+        | GidNumber(_)
+        | ParentGroup(_)
+        | ModifiedSince(_) => {}
+    }
+}
+
+// Replaces each `Member(user)` with a check that also matches the groups that contain the user's
+// groups, transitively.
+fn expand_member_filter(
+    filter: GroupRequestFilter,
+    parents: &GroupNesting,
+    user_groups: &HashMap<UserId, Vec<GroupId>>,
+) -> GroupRequestFilter {
+    use GroupRequestFilter::*;
+    let expand = |fs: Vec<GroupRequestFilter>| {
+        fs.into_iter()
+            .map(|f| expand_member_filter(f, parents, user_groups))
+            .collect()
+    };
+    match filter {
+        And(fs) => And(expand(fs)),
+        Or(fs) => Or(expand(fs)),
+        Not(f) => Not(Box::new(expand_member_filter(*f, parents, user_groups))),
+        Member(user) => {
+            let mut ancestors: Vec<_> = get_reachable_groups(
+                parents,
+                user_groups.get(&user).into_iter().flatten().copied(),
+            )
+            .into_iter()
+            .collect();
+            if ancestors.is_empty() {
+                return Member(user);
+            }
+            ancestors.sort();
+            Or(std::iter::once(Member(user))
+                .chain(ancestors.into_iter().map(GroupId))
+                .collect())
+        }
+        f => f,
+    }
+}
+
 fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
     use GroupRequestFilter::*;
     match filter {
@@ -33,9 +122,25 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
             }
         }
         Not(f) => get_group_filter_expr(*f).not(),
-        DisplayName(name) => GroupColumn::DisplayName.eq(name).into_condition(),
+        DisplayName(name) => case_ignore_eq(GroupColumn::DisplayName, &name).into_condition(),
+        DisplayNameSubString(filter) => {
+            case_ignore_like(GroupColumn::DisplayName, &filter).into_condition()
+        }
         GroupId(id) => GroupColumn::GroupId.eq(id.0).into_condition(),
         Uuid(uuid) => GroupColumn::Uuid.eq(uuid.to_string()).into_condition(),
+        GidNumber(number) => GroupColumn::GidNumber.eq(number).into_condition(),
+        Mail(mail) => case_ignore_eq(GroupColumn::Mail, &mail).into_condition(),
+        ModifiedSince(date) => GroupColumn::ModifiedDate.gte(date).into_condition(),
+        // WHERE (group_id in (SELECT child_group_id FROM group_memberships WHERE parent_group_id = id))
+        ParentGroup(id) => GroupColumn::GroupId
+            .in_subquery(
+                model::GroupMembership::find()
+                    .select_only()
+                    .column(GroupMembershipColumn::ChildGroupId)
+                    .filter(GroupMembershipColumn::ParentGroupId.eq(id))
+                    .into_query(),
+            )
+            .into_condition(),
         // WHERE (group_id in (SELECT group_id FROM memberships WHERE user_id = user))
         Member(user) => GroupColumn::GroupId
             .in_subquery(
@@ -49,39 +154,264 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
     }
 }
 
+// Selects the IDs of the groups matching the filter.
+fn get_group_id_subquery(filter: GroupRequestFilter) -> SelectStatement {
+    model::Group::find()
+        .find_also_linked(model::memberships::GroupToUser)
+        .select_only()
+        .column(GroupColumn::GroupId)
+        .filter(get_group_filter_expr(filter))
+        .into_query()
+}
+
+impl SqlBackendHandler {
+    // Fetches the custom attributes of the groups matching the condition, sorted by name.
+    async fn get_group_attributes(
+        &self,
+        condition: Cond,
+    ) -> Result<HashMap<GroupId, Vec<AttributeValue>>> {
+        let mut attributes: HashMap<GroupId, Vec<AttributeValue>> = HashMap::new();
+        for attribute in model::GroupAttributes::find()
+            .filter(condition)
+            .order_by_asc(GroupAttributesColumn::AttributeName)
+            .all(self.read_pool())
+            .await?
+        {
+            let value = deserialize_attribute_value(&attribute.attribute_name, &attribute.value)?;
+            attributes
+                .entry(attribute.group_id)
+                .or_default()
+                .push(AttributeValue {
+                    name: attribute.attribute_name,
+                    value,
+                });
+        }
+        Ok(attributes)
+    }
+
+    pub(crate) async fn get_group_nesting<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+    ) -> Result<GroupNesting> {
+        let mut nesting = GroupNesting::new();
+        for membership in model::GroupMembership::find().all(conn).await? {
+            nesting
+                .entry(membership.parent_group_id)
+                .or_default()
+                .push(membership.child_group_id);
+        }
+        Ok(nesting)
+    }
+
+    // Bumps the modification date of the group, e.g. when its members change.
+    pub(crate) async fn touch_group<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        group_id: GroupId,
+    ) -> Result<()> {
+        model::Group::update_many()
+            .col_expr(GroupColumn::ModifiedDate, Expr::value(chrono::Utc::now()))
+            .filter(GroupColumn::GroupId.eq(group_id))
+            .exec(conn)
+            .await?;
+        Ok(())
+    }
+
+    // Updates a group whose new attributes were already validated.
+    pub(crate) async fn apply_group_update<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        request: UpdateGroupRequest,
+        insert_attributes: Vec<AttributeValue>,
+    ) -> Result<()> {
+        let mail = request.mail.map(|mail| mail.trim().to_owned());
+        if let Some(mail) = mail.as_deref().filter(|m| !m.is_empty()) {
+            if mail.len() > 255 || mail.contains(char::is_whitespace) || !mail.contains('@') {
+                return Err(DomainError::InvalidRequest(format!(
+                    "Invalid email address: '{}'",
+                    mail
+                )));
+            }
+        }
+        let group_id = request.group_id;
+        let attributes_changed =
+            !insert_attributes.is_empty() || !request.delete_attributes.is_empty();
+        let mut update_group = model::groups::ActiveModel {
+            group_id: ActiveValue::Set(group_id),
+            display_name: request
+                .display_name
+                .map(ActiveValue::Set)
+                .unwrap_or_default(),
+            gid_number: request
+                .gid_number
+                .map(|n| ActiveValue::Set(Some(n)))
+                .unwrap_or_default(),
+            description: to_value(&request.description),
+            mail: to_value(&mail),
+            ..Default::default()
+        };
+        let mut changed_fields: Vec<String> = GroupColumn::iter()
+            .filter(|c| !matches!(c, GroupColumn::GroupId))
+            .filter(|c| update_group.get(*c).is_set())
+            .map(|c| c.as_str().to_owned())
+            .collect();
+        let changed = attributes_changed || !changed_fields.is_empty();
+        if changed {
+            update_group.modified_date = ActiveValue::Set(chrono::Utc::now());
+            update_group.update(conn).await?;
+        }
+        let replaced_attributes: Vec<_> = insert_attributes
+            .iter()
+            .map(|a| a.name.clone())
+            .chain(
+                request
+                    .delete_attributes
+                    .iter()
+                    .map(|name| name.to_ascii_lowercase()),
+            )
+            .collect();
+        if !replaced_attributes.is_empty() {
+            model::GroupAttributes::delete_many()
+                .filter(GroupAttributesColumn::GroupId.eq(group_id))
+                .filter(GroupAttributesColumn::AttributeName.is_in(replaced_attributes.clone()))
+                .exec(conn)
+                .await?;
+        }
+        if !insert_attributes.is_empty() {
+            model::GroupAttributes::insert_many(insert_attributes.into_iter().map(|a| {
+                model::group_attributes::ActiveModel {
+                    group_id: ActiveValue::Set(group_id),
+                    attribute_name: ActiveValue::Set(a.name),
+                    value: ActiveValue::Set(serialize_attribute_value(&a.value)),
+                }
+            }))
+            .exec(conn)
+            .await?;
+        }
+        if changed {
+            changed_fields.extend(replaced_attributes);
+            self.log_group_modification(
+                conn,
+                group_id,
+                ChangeDiff {
+                    fields: changed_fields,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn insert_group<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        group_name: &str,
+    ) -> Result<GroupId> {
+        let now = chrono::Utc::now();
+        let uuid = Uuid::from_name_and_date(group_name, &now);
+        let new_group = model::groups::ActiveModel {
+            display_name: ActiveValue::Set(group_name.to_owned()),
+            creation_date: ActiveValue::Set(now),
+            modified_date: ActiveValue::Set(now),
+            uuid: ActiveValue::Set(uuid),
+            ..Default::default()
+        };
+        let group_id = new_group.insert(conn).await?.group_id;
+        self.log_group_change(conn, group_id, ChangeType::Add)
+            .await?;
+        Ok(group_id)
+    }
+
+    async fn expand_nested_groups_filter(
+        &self,
+        filter: GroupRequestFilter,
+    ) -> Result<GroupRequestFilter> {
+        let parents = invert_nesting(&self.get_group_nesting(self.read_pool()).await?);
+        let mut members = Vec::new();
+        collect_filter_members(&filter, &mut members);
+        if parents.is_empty() || members.is_empty() {
+            return Ok(filter);
+        }
+        let mut user_groups = HashMap::<UserId, Vec<GroupId>>::new();
+        for membership in model::Membership::find()
+            .filter(MembershipColumn::UserId.is_in(members))
+            .all(self.read_pool())
+            .await?
+        {
+            user_groups
+                .entry(membership.user_id)
+                .or_default()
+                .push(membership.group_id);
+        }
+        Ok(expand_member_filter(filter, &parents, &user_groups))
+    }
+}
+
 #[async_trait]
 impl GroupBackendHandler for SqlBackendHandler {
-    #[instrument(skip_all, level = "debug", ret, err)]
+    #[instrument(skip_all, level = "debug")]
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
-        debug!(?filters);
+        match &self.cache {
+            Some(cache) => {
+                cache
+                    .group_list(
+                        &filters,
+                        self.list_groups_sorted(filters.clone(), GroupOrder::default()),
+                    )
+                    .await
+            }
+            None => {
+                self.list_groups_sorted(filters, GroupOrder::default())
+                    .await
+            }
+        }
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_groups_sorted(
+        &self,
+        filters: Option<GroupRequestFilter>,
+        order: GroupOrder,
+    ) -> Result<Vec<Group>> {
+        debug!(?filters, ?order);
+        let filters = match filters {
+            Some(f) => Some(self.expand_nested_groups_filter(f).await?),
+            None => None,
+        };
+        let group_ids = filters.map(get_group_id_subquery);
+        let mut attributes = self
+            .get_group_attributes(
+                group_ids
+                    .clone()
+                    .map(|q| {
+                        GroupAttributesColumn::GroupId
+                            .in_subquery(q)
+                            .into_condition()
+                    })
+                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
+            )
+            .await?;
         let results = model::Group::find()
             // The order_by must be before find_with_related otherwise the primary order is by group_id.
-            .order_by_asc(GroupColumn::DisplayName)
+            .order_by(order.column, to_sql_order(order.direction))
+            .order_by_asc(GroupColumn::GroupId)
             .find_with_related(model::Membership)
             .filter(
-                filters
-                    .map(|f| {
-                        GroupColumn::GroupId
-                            .in_subquery(
-                                model::Group::find()
-                                    .find_also_linked(model::memberships::GroupToUser)
-                                    .select_only()
-                                    .column(GroupColumn::GroupId)
-                                    .filter(get_group_filter_expr(f))
-                                    .into_query(),
-                            )
-                            .into_condition()
-                    })
+                group_ids
+                    .map(|q| GroupColumn::GroupId.in_subquery(q).into_condition())
                     .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
             )
-            .all(&self.sql_pool)
+            .all(self.read_pool())
             .await?;
         Ok(results
             .into_iter()
             .map(|(group, users)| {
                 let users: Vec<_> = users.into_iter().map(|u| u.user_id).collect();
+                let attributes = attributes.remove(&group.group_id).unwrap_or_default();
                 Group {
                     users,
+                    attributes,
                     ..group.into()
                 }
             })
@@ -93,52 +423,125 @@ impl GroupBackendHandler for SqlBackendHandler {
         debug!(?group_id);
         model::Group::find_by_id(group_id)
             .into_model::<GroupDetails>()
-            .one(&self.sql_pool)
+            .one(self.read_pool())
             .await?
             .ok_or_else(|| DomainError::EntityNotFound(format!("{:?}", group_id)))
     }
 
     #[instrument(skip_all, level = "debug", err)]
-    async fn update_group(&self, request: UpdateGroupRequest) -> Result<()> {
+    async fn update_group(&self, mut request: UpdateGroupRequest) -> Result<()> {
         debug!(?request.group_id);
-        let update_group = model::groups::ActiveModel {
-            group_id: ActiveValue::Set(request.group_id),
-            display_name: request
-                .display_name
-                .map(ActiveValue::Set)
-                .unwrap_or_default(),
-            ..Default::default()
-        };
-        update_group.update(&self.sql_pool).await?;
+        let target = AuditEvent::group_target(request.group_id);
+        // The new name, if any.
+        let details = request.display_name.clone();
+        let insert_attributes =
+            normalize_attribute_names(std::mem::take(&mut request.insert_attributes));
+        self.validate_group_attributes(&insert_attributes).await?;
+        self.apply_group_update(&self.sql_pool, request, insert_attributes)
+            .await?;
+        self.invalidate_cache();
+        self.audit("update_group", target, details).await;
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug", ret, err)]
     async fn create_group(&self, group_name: &str) -> Result<GroupId> {
         debug!(?group_name);
-        let now = chrono::Utc::now();
-        let uuid = Uuid::from_name_and_date(group_name, &now);
-        let new_group = model::groups::ActiveModel {
-            display_name: ActiveValue::Set(group_name.to_owned()),
-            creation_date: ActiveValue::Set(now),
-            uuid: ActiveValue::Set(uuid),
-            ..Default::default()
-        };
-        Ok(new_group.insert(&self.sql_pool).await?.group_id)
+        let group_id = self.insert_group(&self.sql_pool, group_name).await?;
+        self.invalidate_cache();
+        self.audit(
+            "create_group",
+            AuditEvent::group_target(group_id),
+            Some(group_name.to_owned()),
+        )
+        .await;
+        Ok(group_id)
     }
 
     #[instrument(skip_all, level = "debug", err)]
     async fn delete_group(&self, group_id: GroupId) -> Result<()> {
         debug!(?group_id);
-        let res = model::Group::delete_by_id(group_id)
+        let transaction = self.sql_pool.begin().await?;
+        let user_ids: Vec<UserId> = model::Membership::find()
+            .filter(MembershipColumn::GroupId.eq(group_id))
+            .all(&transaction)
+            .await?
+            .into_iter()
+            .map(|m| m.user_id)
+            .collect();
+        self.check_admins_remain(&transaction, Some(group_id), &user_ids)
+            .await?;
+        // Fails if the group doesn't exist.
+        self.copy_group_to_recycle_bin(&transaction, group_id)
+            .await?;
+        self.log_group_change(&transaction, group_id, ChangeType::Delete)
+            .await?;
+        model::Group::delete_by_id(group_id)
+            .exec(&transaction)
+            .await?;
+        // The members lost a group.
+        for user_id in user_ids {
+            self.log_user_change(&transaction, &user_id, ChangeType::Modify)
+                .await?;
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.audit("delete_group", AuditEvent::group_target(group_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_to_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()> {
+        debug!(?parent_id, ?child_id);
+        if get_reachable_groups(&self.get_group_nesting(&self.sql_pool).await?, [child_id])
+            .contains(&parent_id)
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "Adding group {:?} to group {:?} would create a cycle",
+                child_id, parent_id
+            )));
+        }
+        let new_membership = model::group_memberships::ActiveModel {
+            parent_group_id: ActiveValue::Set(parent_id),
+            child_group_id: ActiveValue::Set(child_id),
+        };
+        new_membership.insert(&self.sql_pool).await?;
+        self.touch_group(&self.sql_pool, parent_id).await?;
+        self.log_group_change(&self.sql_pool, parent_id, ChangeType::Modify)
+            .await?;
+        self.invalidate_cache();
+        self.audit(
+            "add_group_to_group",
+            AuditEvent::group_target(parent_id),
+            Some(AuditEvent::group_target(child_id)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_group_from_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()> {
+        debug!(?parent_id, ?child_id);
+        let res = model::GroupMembership::delete_by_id((parent_id, child_id))
             .exec(&self.sql_pool)
             .await?;
         if res.rows_affected == 0 {
             return Err(DomainError::EntityNotFound(format!(
-                "No such group: '{:?}'",
-                group_id
+                "No such group membership: {:?} -> {:?}",
+                child_id, parent_id
             )));
         }
+        self.touch_group(&self.sql_pool, parent_id).await?;
+        self.log_group_change(&self.sql_pool, parent_id, ChangeType::Modify)
+            .await?;
+        self.invalidate_cache();
+        self.audit(
+            "remove_group_from_group",
+            AuditEvent::group_target(parent_id),
+            Some(AuditEvent::group_target(child_id)),
+        )
+        .await;
         Ok(())
     }
 }
@@ -146,7 +549,7 @@ impl GroupBackendHandler for SqlBackendHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{sql_backend_handler::tests::*, types::UserId};
+    use crate::domain::{handler::SortDirection, sql_backend_handler::tests::*, types::UserId};
 
     async fn get_group_ids(
         handler: &SqlBackendHandler,
@@ -187,6 +590,32 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_groups_sorted() {
+        let fixture = TestFixture::new().await;
+        assert_eq!(
+            fixture
+                .handler
+                .list_groups_sorted(
+                    None,
+                    GroupOrder {
+                        column: GroupColumn::GroupId,
+                        direction: SortDirection::Descending,
+                    }
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.display_name)
+                .collect::<Vec<_>>(),
+            vec![
+                "Empty Group".to_owned(),
+                "Worst Group".to_owned(),
+                "Best Group".to_owned()
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_list_groups_simple_filter() {
         let fixture = TestFixture::new().await;
@@ -203,6 +632,19 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_groups_case_ignore_filter() {
+        let fixture = TestFixture::new().await;
+        assert_eq!(
+            get_group_names(
+                &fixture.handler,
+                Some(GroupRequestFilter::DisplayName(" EMPTY  group".to_owned()))
+            )
+            .await,
+            vec!["Empty Group".to_owned()]
+        );
+    }
+
     #[tokio::test]
     async fn test_list_groups_negation() {
         let fixture = TestFixture::new().await;
@@ -249,6 +691,8 @@ mod tests {
             .update_group(UpdateGroupRequest {
                 group_id: fixture.groups[0],
                 display_name: Some("Awesomest Group".to_owned()),
+                gid_number: Some(2000),
+                ..Default::default()
             })
             .await
             .unwrap();
@@ -258,6 +702,86 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(details.display_name, "Awesomest Group");
+        assert_eq!(details.gid_number, Some(2000));
+        assert_eq!(
+            get_group_ids(&fixture.handler, Some(GroupRequestFilter::GidNumber(2000))).await,
+            vec![fixture.groups[0]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_group_description_and_mail() {
+        let fixture = TestFixture::new().await;
+        let get_group = || async {
+            fixture
+                .handler
+                .list_groups(Some(GroupRequestFilter::GroupId(fixture.groups[0])))
+                .await
+                .unwrap()
+                .remove(0)
+        };
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                description: Some("The best".to_owned()),
+                mail: Some(" best@example.com ".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let group = get_group().await;
+        assert_eq!(group.description.as_deref(), Some("The best"));
+        assert_eq!(group.mail.as_deref(), Some("best@example.com"));
+        assert!(fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                mail: Some("not an address".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .is_err());
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                mail: Some(String::new()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let group = get_group().await;
+        assert_eq!(group.description.as_deref(), Some("The best"));
+        assert_eq!(group.mail, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_groups_modified_since() {
+        let fixture = TestFixture::new().await;
+        let before_update = chrono::Utc::now();
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                display_name: Some("Awesomest Group".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[2], fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(
+                &fixture.handler,
+                Some(GroupRequestFilter::ModifiedSince(before_update))
+            )
+            .await,
+            vec![fixture.groups[0], fixture.groups[2]]
+        );
     }
 
     #[tokio::test]
@@ -277,4 +801,69 @@ mod tests {
             vec![fixture.groups[2], fixture.groups[1]]
         );
     }
+
+    #[tokio::test]
+    async fn test_nested_groups() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        // "Empty Group" contains "Best Group", which contains bob.
+        handler
+            .add_group_to_group(fixture.groups[2], fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::Member(UserId::new("bob")))
+            )
+            .await,
+            vec![fixture.groups[0], fixture.groups[2]]
+        );
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::ParentGroup(fixture.groups[2]))
+            )
+            .await,
+            vec![fixture.groups[0]]
+        );
+        handler
+            .remove_group_from_group(fixture.groups[2], fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::Member(UserId::new("bob")))
+            )
+            .await,
+            vec![fixture.groups[0]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nested_groups_cycle() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        handler
+            .add_group_to_group(fixture.groups[0], fixture.groups[1])
+            .await
+            .unwrap();
+        handler
+            .add_group_to_group(fixture.groups[1], fixture.groups[2])
+            .await
+            .unwrap();
+        assert!(matches!(
+            handler
+                .add_group_to_group(fixture.groups[2], fixture.groups[0])
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            handler
+                .add_group_to_group(fixture.groups[1], fixture.groups[1])
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+    }
 }
@@ -0,0 +1,138 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, GroupManagerBackendHandler},
+    model::{self, GroupManagersColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{GroupId, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use tracing::{debug, instrument};
+
+#[async_trait]
+impl GroupManagerBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>> {
+        debug!(?group_id);
+        Ok(model::GroupManagers::find()
+            .filter(GroupManagersColumn::GroupId.eq(group_id))
+            .order_by_asc(GroupManagersColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|m| m.user_id)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        debug!(?group_id, ?user_id);
+        model::group_managers::ActiveModel {
+            group_id: ActiveValue::Set(group_id),
+            user_id: ActiveValue::Set(user_id.clone()),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit(
+            "add_group_manager",
+            AuditEvent::group_target(group_id),
+            Some(AuditEvent::user_target(user_id)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        debug!(?group_id, ?user_id);
+        let res = model::GroupManagers::delete_by_id((group_id, user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such group manager: '{}' for group '{}'",
+                user_id, group_id.0
+            )));
+        }
+        self.audit(
+            "remove_group_manager",
+            AuditEvent::group_target(group_id),
+            Some(AuditEvent::user_target(user_id)),
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{RoleBackendHandler, UserBackendHandler},
+        sql_backend_handler::tests::*,
+        types::Capabilities,
+    };
+
+    #[tokio::test]
+    async fn test_group_managers() {
+        let fixture = TestFixture::new().await;
+        let john = UserId::new("john");
+        fixture
+            .handler
+            .add_group_manager(fixture.groups[0], &john)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_manager(fixture.groups[2], &john)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_manager(fixture.groups[0], &UserId::new("bob"))
+            .await
+            .unwrap();
+        // Already a manager.
+        assert!(fixture
+            .handler
+            .add_group_manager(fixture.groups[0], &john)
+            .await
+            .is_err());
+        assert_eq!(
+            fixture
+                .handler
+                .list_group_managers(fixture.groups[0])
+                .await
+                .unwrap(),
+            vec![UserId::new("bob"), john.clone()]
+        );
+        assert_eq!(
+            fixture.handler.get_user_capabilities(&john).await.unwrap(),
+            Capabilities {
+                managed_memberships: vec![fixture.groups[0], fixture.groups[2]],
+                ..Default::default()
+            }
+        );
+
+        fixture
+            .handler
+            .remove_group_manager(fixture.groups[0], &john)
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .remove_group_manager(fixture.groups[0], &john)
+            .await
+            .is_err());
+        // Deleting the user removes them from the managers.
+        fixture.handler.delete_user(&john).await.unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_group_managers(fixture.groups[2])
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, CreateInvitationRequest, CreateUserRequest, InvitationBackendHandler},
+    model::{self, InvitationGroupsColumn, InvitationsColumn, UserColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{Invitation, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
+use tracing::{debug, instrument};
+
+/// How long the invitees have to create their account.
+const INVITATION_VALIDITY_DAYS: i64 = 7;
+
+fn generate_token() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+#[async_trait]
+impl InvitationBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_invitation(&self, request: CreateInvitationRequest) -> Result<String> {
+        debug!(email = ?request.email, group_ids = ?request.group_ids, created_by = ?request.created_by);
+        if model::User::find()
+            .filter(UserColumn::Email.eq(request.email.as_str()))
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "A user already has the email '{}'",
+                request.email
+            )));
+        }
+        if let Some(template) = &request.template {
+            if !self
+                .config
+                .user_templates
+                .iter()
+                .any(|t| &t.name == template)
+            {
+                return Err(DomainError::InvalidRequest(format!(
+                    "No user template named '{}'",
+                    template
+                )));
+            }
+        }
+        let token = generate_token();
+        let now = chrono::Utc::now();
+        let target = format!("invitation:{}", request.email);
+        let transaction = self.sql_pool.begin().await?;
+        model::invitations::ActiveModel {
+            token: ActiveValue::Set(token.clone()),
+            email: ActiveValue::Set(request.email),
+            created_by: ActiveValue::Set(request.created_by),
+            creation_date: ActiveValue::Set(now),
+            expiry_date: ActiveValue::Set(now + chrono::Duration::days(INVITATION_VALIDITY_DAYS)),
+            template: ActiveValue::Set(request.template),
+        }
+        .insert(&transaction)
+        .await?;
+        for group_id in request.group_ids {
+            model::invitation_groups::ActiveModel {
+                token: ActiveValue::Set(token.clone()),
+                group_id: ActiveValue::Set(group_id),
+            }
+            .insert(&transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        self.audit("invite_user", target, None).await;
+        Ok(token)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_invitation(&self, token: &str) -> Result<Invitation> {
+        let invitation = model::Invitations::find_by_id(token.to_owned())
+            .filter(InvitationsColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound("Invalid invitation".to_owned()))?;
+        let group_ids = model::InvitationGroups::find()
+            .filter(InvitationGroupsColumn::Token.eq(token))
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect();
+        Ok(Invitation {
+            email: invitation.email,
+            group_ids,
+            created_by: invitation.created_by,
+            creation_date: invitation.creation_date,
+            expiry_date: invitation.expiry_date,
+            template: invitation.template,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn accept_invitation(&self, token: &str, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        let invitation = self.get_invitation(token).await?;
+        let target = format!("invitation:{}", invitation.email);
+        let transaction = self.sql_pool.begin().await?;
+        if model::User::find_by_id(user_id.clone())
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "User '{}' already exists",
+                user_id
+            )));
+        }
+        let template_group_ids = self
+            .insert_new_user(
+                &transaction,
+                CreateUserRequest {
+                    user_id: user_id.clone(),
+                    email: invitation.email,
+                    template: invitation.template,
+                    ..Default::default()
+                },
+                Vec::new(),
+            )
+            .await?;
+        for group_id in invitation
+            .group_ids
+            .into_iter()
+            .filter(|g| !template_group_ids.contains(g))
+        {
+            self.insert_membership(&transaction, user_id, group_id, None)
+                .await?;
+        }
+        // Also deletes the groups of the invitation.
+        let res = model::Invitations::delete_by_id(token.to_owned())
+            .exec(&transaction)
+            .await?;
+        if res.rows_affected == 0 {
+            // Accepted concurrently.
+            return Err(DomainError::EntityNotFound("Invalid invitation".to_owned()));
+        }
+        transaction.commit().await?;
+        self.audit(
+            "accept_invitation",
+            target,
+            Some(AuditEvent::user_target(user_id)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_invitation(&self, token: &str) -> Result<()> {
+        // The expired invitations can be deleted too.
+        let invitation = model::Invitations::find_by_id(token.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound("No such invitation".to_owned()))?;
+        model::Invitations::delete_by_id(token.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        self.audit(
+            "delete_invitation",
+            format!("invitation:{}", invitation.email),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{UserBackendHandler, UserRequestFilter},
+        sql_backend_handler::tests::*,
+    };
+
+    #[tokio::test]
+    async fn test_invitation() {
+        let fixture = TestFixture::new().await;
+        let admin = UserId::new("bob");
+        let token = fixture
+            .handler
+            .create_invitation(CreateInvitationRequest {
+                email: "alice@example.com".to_owned(),
+                group_ids: vec![fixture.groups[0], fixture.groups[1]],
+                created_by: admin.clone(),
+                template: None,
+            })
+            .await
+            .unwrap();
+        let invitation = fixture.handler.get_invitation(&token).await.unwrap();
+        assert_eq!(invitation.email, "alice@example.com");
+        assert_eq!(invitation.created_by, admin);
+
+        // The chosen ID must be free.
+        assert!(fixture
+            .handler
+            .accept_invitation(&token, &UserId::new("patrick"))
+            .await
+            .is_err());
+        let alice = UserId::new("alice");
+        fixture
+            .handler
+            .accept_invitation(&token, &alice)
+            .await
+            .unwrap();
+        let user = fixture.handler.get_user_details(&alice).await.unwrap();
+        assert_eq!(user.email, "alice@example.com");
+        let mut groups: Vec<_> = fixture
+            .handler
+            .get_user_groups(&alice)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect();
+        groups.sort();
+        assert_eq!(groups, vec![fixture.groups[0], fixture.groups[1]]);
+
+        // The invitation can only be used once.
+        assert!(fixture.handler.get_invitation(&token).await.is_err());
+        assert!(fixture
+            .handler
+            .accept_invitation(&token, &UserId::new("eve"))
+            .await
+            .is_err());
+        assert_eq!(
+            fixture
+                .handler
+                .list_users(Some(UserRequestFilter::UserId(UserId::new("eve"))), false)
+                .await
+                .unwrap(),
+            vec![]
+        );
+        // The email now belongs to a user.
+        assert!(fixture
+            .handler
+            .create_invitation(CreateInvitationRequest {
+                email: "alice@example.com".to_owned(),
+                group_ids: vec![],
+                created_by: admin,
+                template: None,
+            })
+            .await
+            .is_err());
+    }
+}
@@ -3,8 +3,11 @@ use crate::domain::{
     types::{GroupId, UserId, Uuid},
 };
 use sea_orm::{ConnectionTrait, FromQueryResult, Statement};
-use sea_query::{ColumnDef, Expr, ForeignKey, ForeignKeyAction, Iden, Query, Table, Value};
+use sea_query::{
+    ColumnDef, Expr, ForeignKey, ForeignKeyAction, Iden, Index, Order, Query, Table, Value,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use tracing::{instrument, warn};
 
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -21,6 +24,29 @@ pub enum Users {
     TotpSecret,
     MfaType,
     Uuid,
+    UidNumber,
+    GidNumber,
+    HomeDirectory,
+    LoginShell,
+    SshPublicKeys,
+    ModifiedDate,
+    Enabled,
+    ValidFrom,
+    ValidUntil,
+    TelephoneNumber,
+    Mobile,
+    Title,
+    Department,
+    Locality,
+    PostalAddress,
+    Locale,
+    Timezone,
+    PasswordModifiedDate,
+    PasswordExpiryWarned,
+    LastLdapLogin,
+    LastWebLogin,
+    StaleWarningDate,
+    StaleDisabledDate,
 }
 
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -30,6 +56,10 @@ pub enum Groups {
     DisplayName,
     CreationDate,
     Uuid,
+    GidNumber,
+    ModifiedDate,
+    Description,
+    Mail,
 }
 
 #[derive(Iden)]
@@ -37,6 +67,275 @@ pub enum Memberships {
     Table,
     UserId,
     GroupId,
+    ExpiryDate,
+}
+
+#[derive(Iden)]
+pub enum GroupMemberships {
+    Table,
+    ParentGroupId,
+    ChildGroupId,
+}
+
+#[derive(Iden)]
+pub enum UserAttributeSchema {
+    Table,
+    AttributeName,
+    AttributeType,
+    IsList,
+    IsVisible,
+}
+
+#[derive(Iden)]
+pub enum UserAttributes {
+    Table,
+    UserId,
+    AttributeName,
+    Value,
+}
+
+#[derive(Iden)]
+pub enum GroupAttributeSchema {
+    Table,
+    AttributeName,
+    AttributeType,
+    IsList,
+    IsVisible,
+}
+
+#[derive(Iden)]
+pub enum GroupAttributes {
+    Table,
+    GroupId,
+    AttributeName,
+    Value,
+}
+
+#[derive(Iden)]
+pub enum ChangeLog {
+    Table,
+    ChangeId,
+    EntryType,
+    EntryId,
+    EntryUuid,
+    ChangeType,
+    ChangeDate,
+    Diff,
+}
+
+#[derive(Iden)]
+pub enum AuditLog {
+    Table,
+    AuditId,
+    Actor,
+    Source,
+    Action,
+    Target,
+    Details,
+    Date,
+}
+
+#[derive(Iden)]
+pub enum ApiTokens {
+    Table,
+    TokenId,
+    Name,
+    Scope,
+    TokenHash,
+    CreatedBy,
+    CreationDate,
+}
+
+#[derive(Iden)]
+pub enum PasswordHistory {
+    Table,
+    UserId,
+    PasswordHash,
+    CreationDate,
+}
+
+#[derive(Iden)]
+pub enum FailedLogins {
+    Table,
+    UserId,
+    FailedAttempts,
+    LastFailure,
+    LockedUntil,
+}
+
+#[derive(Iden)]
+pub enum WebauthnCredentials {
+    Table,
+    CredentialId,
+    UserId,
+    DisplayName,
+    Passkey,
+    CreationDate,
+    LastUsed,
+}
+
+#[derive(Iden)]
+pub enum Invitations {
+    Table,
+    Token,
+    Email,
+    CreatedBy,
+    CreationDate,
+    ExpiryDate,
+    Template,
+}
+
+#[derive(Iden)]
+pub enum InvitationGroups {
+    Table,
+    Token,
+    GroupId,
+}
+
+#[derive(Iden)]
+pub enum PendingUsers {
+    Table,
+    UserId,
+    Email,
+    DisplayName,
+    CreationDate,
+}
+
+#[derive(Iden)]
+pub enum Roles {
+    Table,
+    RoleId,
+    DisplayName,
+    ReadAll,
+    ResetPasswords,
+    ManagedGroupId,
+}
+
+/// The groups whose members hold a role.
+#[derive(Iden)]
+pub enum RoleGroups {
+    Table,
+    RoleId,
+    GroupId,
+}
+
+/// The users allowed to add and remove the members of a group, without being admins.
+#[derive(Iden)]
+pub enum GroupManagers {
+    Table,
+    GroupId,
+    UserId,
+}
+
+/// The applications that authenticate their users through the OpenID Connect provider.
+#[derive(Iden)]
+pub enum OidcClients {
+    Table,
+    ClientId,
+    ClientSecret,
+    DisplayName,
+    /// One per line.
+    RedirectUris,
+    CreationDate,
+}
+
+/// Extra claims given to the members of a group, for a client.
+#[derive(Iden)]
+pub enum OidcGroupClaims {
+    Table,
+    ClientId,
+    GroupId,
+    Claim,
+    Value,
+}
+
+#[derive(Iden)]
+pub enum OidcAuthorizationCodes {
+    Table,
+    Code,
+    ClientId,
+    UserId,
+    RedirectUri,
+    Scope,
+    Nonce,
+    CodeChallenge,
+    CodeChallengeMethod,
+    ExpiryDate,
+}
+
+/// Password hashes imported from another directory, checked on the first bind and replaced by
+/// an OPAQUE password file.
+#[derive(Iden)]
+pub enum LegacyPasswordHashes {
+    Table,
+    UserId,
+    PasswordHash,
+}
+
+/// Contains the refresh tokens for a given user, i.e. the login sessions. The table itself is
+/// created by the web server, after the migrations.
+#[derive(Iden)]
+pub enum JwtRefreshStorage {
+    Table,
+    RefreshTokenHash,
+    UserId,
+    ExpiryDate,
+    CreationDate,
+    LastUsed,
+    UserAgent,
+    IpAddress,
+}
+
+/// The recycle bin: the deleted users, with their memberships and attributes serialized in `data`
+/// so that they can be restored.
+#[derive(Iden)]
+pub enum DeletedUsers {
+    Table,
+    UserId,
+    Email,
+    DisplayName,
+    DeletionDate,
+    Data,
+}
+
+/// The deleted groups, with their memberships and attributes serialized in `data`.
+#[derive(Iden)]
+pub enum DeletedGroups {
+    Table,
+    GroupId,
+    DisplayName,
+    DeletionDate,
+    Data,
+}
+
+/// The avatars fetched from Gravatar or the configured URL template, stored in `users.avatar`.
+/// Refreshed by the scheduler, and removed when the user sets their own avatar.
+#[derive(Iden)]
+pub enum RemoteAvatars {
+    Table,
+    UserId,
+    EmailHash,
+    FetchDate,
+}
+
+/// The secondary email addresses of the users, e.g. the aliases of their mailbox. Stored in lower
+/// case, and unique across the users.
+#[derive(Iden)]
+pub enum UserEmails {
+    Table,
+    Email,
+    UserId,
+}
+
+/// The accounts of the applications binding to the LDAP server, under `ou=services`.
+#[derive(Iden)]
+pub enum ServiceAccounts {
+    Table,
+    ServiceId,
+    Description,
+    ReadAll,
+    PasswordHash,
+    CreationDate,
 }
 
 // Metadata about the SQL DB.
@@ -47,6 +346,8 @@ pub enum Metadata {
     Version,
 }
 
+pub const LAST_SCHEMA_VERSION: SchemaVersion = SchemaVersion(36);
+
 #[derive(FromQueryResult, PartialEq, Eq, Debug)]
 pub struct JustSchemaVersion {
     pub version: SchemaVersion,
@@ -329,12 +630,1698 @@ pub async fn upgrade_to_v1(pool: &DbConnection) -> std::result::Result<(), sea_o
     Ok(())
 }
 
+async fn upgrade_to_v2(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupMemberships::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(GroupMemberships::ParentGroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupMemberships::ChildGroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(GroupMemberships::ParentGroupId)
+                        .col(GroupMemberships::ChildGroupId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupMembershipParentForeignKey")
+                        .from(GroupMemberships::Table, GroupMemberships::ParentGroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupMembershipChildForeignKey")
+                        .from(GroupMemberships::Table, GroupMemberships::ChildGroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+
+    set_schema_version(pool, SchemaVersion(2)).await
+}
+
+async fn upgrade_to_v3(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // SQLite only supports adding one column per statement.
+    for column in [
+        ColumnDef::new(Users::UidNumber).integer(),
+        ColumnDef::new(Users::GidNumber).integer(),
+        ColumnDef::new(Users::HomeDirectory).string_len(255),
+        ColumnDef::new(Users::LoginShell).string_len(255),
+    ] {
+        pool.execute(builder.build(Table::alter().table(Users::Table).add_column(column)))
+            .await?;
+    }
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Groups::Table)
+                .add_column(ColumnDef::new(Groups::GidNumber).integer()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(3)).await
+}
+
+async fn upgrade_to_v4(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::SshPublicKeys).text()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(4)).await
+}
+
+async fn upgrade_to_v5(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserAttributeSchema::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserAttributeSchema::AttributeName)
+                        .string_len(64)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::AttributeType)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::IsList)
+                        .boolean()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::IsVisible)
+                        .boolean()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserAttributes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserAttributes::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributes::AttributeName)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(UserAttributes::Value).text().not_null())
+                .primary_key(
+                    Index::create()
+                        .col(UserAttributes::UserId)
+                        .col(UserAttributes::AttributeName),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserAttributeUserForeignKey")
+                        .from(UserAttributes::Table, UserAttributes::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserAttributeSchemaForeignKey")
+                        .from(UserAttributes::Table, UserAttributes::AttributeName)
+                        .to(
+                            UserAttributeSchema::Table,
+                            UserAttributeSchema::AttributeName,
+                        )
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(5)).await
+}
+
+async fn upgrade_to_v6(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupAttributeSchema::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::AttributeName)
+                        .string_len(64)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::AttributeType)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::IsList)
+                        .boolean()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::IsVisible)
+                        .boolean()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupAttributes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(GroupAttributes::GroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributes::AttributeName)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(GroupAttributes::Value).text().not_null())
+                .primary_key(
+                    Index::create()
+                        .col(GroupAttributes::GroupId)
+                        .col(GroupAttributes::AttributeName),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupAttributeGroupForeignKey")
+                        .from(GroupAttributes::Table, GroupAttributes::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupAttributeSchemaForeignKey")
+                        .from(GroupAttributes::Table, GroupAttributes::AttributeName)
+                        .to(
+                            GroupAttributeSchema::Table,
+                            GroupAttributeSchema::AttributeName,
+                        )
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(6)).await
+}
+
+async fn upgrade_to_v7(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::ModifiedDate).date_time()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Users::Table)
+                .value(Users::ModifiedDate, Expr::col(Users::CreationDate)),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Groups::Table)
+                .add_column(ColumnDef::new(Groups::ModifiedDate).date_time()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Groups::Table)
+                .value(Groups::ModifiedDate, Expr::col(Groups::CreationDate)),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(7)).await
+}
+
+async fn upgrade_to_v8(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(ChangeLog::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(ChangeLog::ChangeId)
+                        .integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(ChangeLog::EntryType)
+                        .string_len(16)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ChangeLog::EntryId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ChangeLog::EntryUuid)
+                        .string_len(36)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ChangeLog::ChangeType)
+                        .string_len(16)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(ChangeLog::ChangeDate).date_time().not_null()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(8)).await
+}
+
+async fn upgrade_to_v9(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Users::Table).add_column(
+                ColumnDef::new(Users::Enabled)
+                    .boolean()
+                    .not_null()
+                    .default(true),
+            ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(9)).await
+}
+
+async fn upgrade_to_v10(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::ValidFrom).date_time()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::ValidUntil).date_time()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(10)).await
+}
+
+async fn upgrade_to_v11(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Memberships::Table)
+                .add_column(ColumnDef::new(Memberships::ExpiryDate).date_time()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(11)).await
+}
+
+async fn upgrade_to_v12(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(AuditLog::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(AuditLog::AuditId)
+                        .integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(AuditLog::Actor).string_len(255).not_null())
+                .col(ColumnDef::new(AuditLog::Source).string_len(16).not_null())
+                .col(ColumnDef::new(AuditLog::Action).string_len(64).not_null())
+                .col(ColumnDef::new(AuditLog::Target).string_len(255).not_null())
+                .col(ColumnDef::new(AuditLog::Details).string_len(255))
+                .col(ColumnDef::new(AuditLog::Date).date_time().not_null()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(12)).await
+}
+
+async fn upgrade_to_v13(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(ApiTokens::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(ApiTokens::TokenId)
+                        .integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(ApiTokens::Name).string_len(255).not_null())
+                .col(ColumnDef::new(ApiTokens::Scope).string_len(16).not_null())
+                .col(
+                    ColumnDef::new(ApiTokens::TokenHash)
+                        .string_len(64)
+                        .not_null()
+                        .unique_key(),
+                )
+                .col(
+                    ColumnDef::new(ApiTokens::CreatedBy)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ApiTokens::CreationDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(13)).await
+}
+
+async fn upgrade_to_v14(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // On new installations, the table doesn't exist yet and gets created with all the columns.
+    let table_exists = pool
+        .query_one(
+            builder.build(
+                Query::select()
+                    .expr(Expr::val(1))
+                    .from(JwtRefreshStorage::Table)
+                    .limit(1),
+            ),
+        )
+        .await
+        .is_ok();
+    if table_exists {
+        // SQLite only supports adding one column per statement.
+        for column in [
+            ColumnDef::new(JwtRefreshStorage::CreationDate).date_time(),
+            ColumnDef::new(JwtRefreshStorage::LastUsed).date_time(),
+            ColumnDef::new(JwtRefreshStorage::UserAgent).string_len(255),
+            ColumnDef::new(JwtRefreshStorage::IpAddress).string_len(64),
+        ] {
+            pool.execute(
+                builder.build(
+                    Table::alter()
+                        .table(JwtRefreshStorage::Table)
+                        .add_column(column),
+                ),
+            )
+            .await?;
+        }
+    }
+    set_schema_version(pool, SchemaVersion(14)).await
+}
+
+async fn upgrade_to_v15(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(PasswordHistory::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(PasswordHistory::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(PasswordHistory::PasswordHash)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(PasswordHistory::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(PasswordHistory::UserId)
+                        .col(PasswordHistory::PasswordHash),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("PasswordHistoryUserForeignKey")
+                        .from(PasswordHistory::Table, PasswordHistory::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(15)).await
+}
+
+async fn upgrade_to_v16(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(FailedLogins::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(FailedLogins::UserId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(FailedLogins::FailedAttempts)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(FailedLogins::LastFailure)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(FailedLogins::LockedUntil).date_time())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("FailedLoginsUserForeignKey")
+                        .from(FailedLogins::Table, FailedLogins::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(16)).await
+}
+
+async fn upgrade_to_v17(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(WebauthnCredentials::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(WebauthnCredentials::CredentialId)
+                        .string_len(1023)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::DisplayName)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::Passkey)
+                        .text()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(WebauthnCredentials::LastUsed).date_time())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("WebauthnCredentialsUserForeignKey")
+                        .from(WebauthnCredentials::Table, WebauthnCredentials::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(17)).await
+}
+
+async fn upgrade_to_v18(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(Invitations::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(Invitations::Token)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(Invitations::Email)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(Invitations::CreatedBy)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(Invitations::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(Invitations::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(InvitationGroups::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(InvitationGroups::Token)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(InvitationGroups::GroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(InvitationGroups::Token)
+                        .col(InvitationGroups::GroupId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("InvitationGroupsInvitationForeignKey")
+                        .from(InvitationGroups::Table, InvitationGroups::Token)
+                        .to(Invitations::Table, Invitations::Token)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("InvitationGroupsGroupForeignKey")
+                        .from(InvitationGroups::Table, InvitationGroups::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(18)).await
+}
+
+async fn upgrade_to_v19(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(PendingUsers::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(PendingUsers::UserId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(PendingUsers::Email)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(PendingUsers::DisplayName).string_len(255))
+                .col(
+                    ColumnDef::new(PendingUsers::CreationDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(19)).await
+}
+
+async fn upgrade_to_v20(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(Roles::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(Roles::RoleId)
+                        .integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(Roles::DisplayName)
+                        .string_len(255)
+                        .not_null()
+                        .unique_key(),
+                )
+                .col(
+                    ColumnDef::new(Roles::ReadAll)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(
+                    ColumnDef::new(Roles::ResetPasswords)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(ColumnDef::new(Roles::ManagedGroupId).integer())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("RolesManagedGroupForeignKey")
+                        .from(Roles::Table, Roles::ManagedGroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::SetNull)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(RoleGroups::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(RoleGroups::RoleId).integer().not_null())
+                .col(ColumnDef::new(RoleGroups::GroupId).integer().not_null())
+                .primary_key(
+                    Index::create()
+                        .col(RoleGroups::RoleId)
+                        .col(RoleGroups::GroupId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("RoleGroupsRoleForeignKey")
+                        .from(RoleGroups::Table, RoleGroups::RoleId)
+                        .to(Roles::Table, Roles::RoleId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("RoleGroupsGroupForeignKey")
+                        .from(RoleGroups::Table, RoleGroups::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(20)).await
+}
+
+async fn upgrade_to_v21(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupManagers::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(GroupManagers::GroupId).integer().not_null())
+                .col(
+                    ColumnDef::new(GroupManagers::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(GroupManagers::GroupId)
+                        .col(GroupManagers::UserId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupManagersGroupForeignKey")
+                        .from(GroupManagers::Table, GroupManagers::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupManagersUserForeignKey")
+                        .from(GroupManagers::Table, GroupManagers::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(21)).await
+}
+
+async fn upgrade_to_v22(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcClients::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcClients::ClientId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(OidcClients::ClientSecret)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcClients::DisplayName)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(OidcClients::RedirectUris).text().not_null())
+                .col(
+                    ColumnDef::new(OidcClients::CreationDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcGroupClaims::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcGroupClaims::ClientId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcGroupClaims::GroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcGroupClaims::Claim)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcGroupClaims::Value)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(OidcGroupClaims::ClientId)
+                        .col(OidcGroupClaims::GroupId)
+                        .col(OidcGroupClaims::Claim)
+                        .col(OidcGroupClaims::Value),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcGroupClaimsClientForeignKey")
+                        .from(OidcGroupClaims::Table, OidcGroupClaims::ClientId)
+                        .to(OidcClients::Table, OidcClients::ClientId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcGroupClaimsGroupForeignKey")
+                        .from(OidcGroupClaims::Table, OidcGroupClaims::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcAuthorizationCodes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcAuthorizationCodes::Code)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizationCodes::ClientId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizationCodes::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizationCodes::RedirectUri)
+                        .text()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizationCodes::Scope)
+                        .string_len(1023)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(OidcAuthorizationCodes::Nonce).string_len(1023))
+                .col(ColumnDef::new(OidcAuthorizationCodes::CodeChallenge).string_len(255))
+                .col(ColumnDef::new(OidcAuthorizationCodes::CodeChallengeMethod).string_len(255))
+                .col(
+                    ColumnDef::new(OidcAuthorizationCodes::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcAuthorizationCodesClientForeignKey")
+                        .from(
+                            OidcAuthorizationCodes::Table,
+                            OidcAuthorizationCodes::ClientId,
+                        )
+                        .to(OidcClients::Table, OidcClients::ClientId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcAuthorizationCodesUserForeignKey")
+                        .from(
+                            OidcAuthorizationCodes::Table,
+                            OidcAuthorizationCodes::UserId,
+                        )
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(22)).await
+}
+
+async fn upgrade_to_v23(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(LegacyPasswordHashes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(LegacyPasswordHashes::UserId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(LegacyPasswordHashes::PasswordHash)
+                        .text()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("LegacyPasswordHashesUserForeignKey")
+                        .from(LegacyPasswordHashes::Table, LegacyPasswordHashes::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(23)).await
+}
+
+async fn upgrade_to_v24(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // No foreign keys: the deleted entries outlive the users and groups they reference.
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(DeletedUsers::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(DeletedUsers::UserId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(DeletedUsers::Email)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(DeletedUsers::DisplayName).string_len(255))
+                .col(
+                    ColumnDef::new(DeletedUsers::DeletionDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(DeletedUsers::Data).text().not_null()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(DeletedGroups::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(DeletedGroups::GroupId)
+                        .integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(DeletedGroups::DisplayName)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(DeletedGroups::DeletionDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(DeletedGroups::Data).text().not_null()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(24)).await
+}
+
+async fn upgrade_to_v25(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(RemoteAvatars::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(RemoteAvatars::UserId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(RemoteAvatars::EmailHash)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(RemoteAvatars::FetchDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("RemoteAvatarsUserForeignKey")
+                        .from(RemoteAvatars::Table, RemoteAvatars::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(25)).await
+}
+
+async fn upgrade_to_v26(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserEmails::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserEmails::Email)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(UserEmails::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserEmailsUserForeignKey")
+                        .from(UserEmails::Table, UserEmails::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(26)).await
+}
+
+const PROFILE_COLUMNS: [Users; 6] = [
+    Users::TelephoneNumber,
+    Users::Mobile,
+    Users::Title,
+    Users::Department,
+    Users::Locality,
+    Users::PostalAddress,
+];
+
+async fn upgrade_to_v27(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // SQLite only supports adding one column per statement.
+    for column in PROFILE_COLUMNS {
+        pool.execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(column).string_len(255)),
+            ),
+        )
+        .await?;
+    }
+    set_schema_version(pool, SchemaVersion(27)).await
+}
+
+async fn upgrade_to_v28(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    for column in [
+        ColumnDef::new(Users::Locale).string_len(35),
+        ColumnDef::new(Users::Timezone).string_len(64),
+    ] {
+        pool.execute(builder.build(Table::alter().table(Users::Table).add_column(column)))
+            .await?;
+    }
+    set_schema_version(pool, SchemaVersion(28)).await
+}
+
+// The user IDs stored by the old versions can have uppercase letters, which made these users
+// unreachable since the IDs are always lowercased. The users whose ID only differs by case from
+// another one get a numbered suffix rather than being merged.
+async fn upgrade_to_v29(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    #[derive(FromQueryResult)]
+    struct RawUserId {
+        user_id: String,
+    }
+    let user_ids = RawUserId::find_by_statement(
+        builder.build(
+            Query::select()
+                .from(Users::Table)
+                .column(Users::UserId)
+                .order_by(Users::CreationDate, Order::Asc),
+        ),
+    )
+    .all(pool)
+    .await?
+    .into_iter()
+    .map(|u| u.user_id)
+    .collect::<Vec<_>>();
+    let mut taken = user_ids
+        .iter()
+        .filter(|user_id| **user_id == user_id.to_lowercase())
+        .cloned()
+        .collect::<HashSet<_>>();
+    for user_id in user_ids {
+        let lowercase = user_id.to_lowercase();
+        if lowercase == user_id {
+            continue;
+        }
+        let new_user_id = if taken.contains(&lowercase) {
+            let new_user_id = (2..)
+                .map(|n| format!("{}-{}", lowercase, n))
+                .find(|candidate| !taken.contains(candidate))
+                .unwrap();
+            warn!(
+                "The user `{}` only differs by case from another user, renaming it to `{}`",
+                user_id, new_user_id
+            );
+            new_user_id
+        } else {
+            lowercase
+        };
+        // The references to the user follow through the cascading foreign keys, except in the
+        // tables without one.
+        for statement in [
+            Query::update()
+                .table(Users::Table)
+                .value(Users::UserId, Value::from(new_user_id.clone()))
+                .and_where(Expr::col(Users::UserId).eq(user_id.clone()))
+                .to_owned(),
+            Query::update()
+                .table(ApiTokens::Table)
+                .value(ApiTokens::CreatedBy, Value::from(new_user_id.clone()))
+                .and_where(Expr::col(ApiTokens::CreatedBy).eq(user_id.clone()))
+                .to_owned(),
+            Query::update()
+                .table(Invitations::Table)
+                .value(Invitations::CreatedBy, Value::from(new_user_id.clone()))
+                .and_where(Expr::col(Invitations::CreatedBy).eq(user_id.clone()))
+                .to_owned(),
+            Query::update()
+                .table(AuditLog::Table)
+                .value(AuditLog::Actor, Value::from(new_user_id.clone()))
+                .and_where(Expr::col(AuditLog::Actor).eq(user_id.clone()))
+                .to_owned(),
+            Query::update()
+                .table(AuditLog::Table)
+                .value(
+                    AuditLog::Target,
+                    Value::from(format!("user:{}", new_user_id)),
+                )
+                .and_where(Expr::col(AuditLog::Target).eq(format!("user:{}", user_id)))
+                .to_owned(),
+            Query::update()
+                .table(ChangeLog::Table)
+                .value(ChangeLog::EntryId, Value::from(new_user_id.clone()))
+                .and_where(Expr::col(ChangeLog::EntryType).eq("User"))
+                .and_where(Expr::col(ChangeLog::EntryId).eq(user_id.clone()))
+                .to_owned(),
+        ] {
+            pool.execute(builder.build(&statement)).await?;
+        }
+        taken.insert(new_user_id);
+    }
+    set_schema_version(pool, SchemaVersion(29)).await
+}
+
+async fn upgrade_to_v30(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(ServiceAccounts::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(ServiceAccounts::ServiceId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(ServiceAccounts::Description).string_len(255))
+                .col(
+                    ColumnDef::new(ServiceAccounts::ReadAll)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(
+                    ColumnDef::new(ServiceAccounts::PasswordHash)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(ServiceAccounts::CreationDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(30)).await
+}
+
+async fn upgrade_to_v31(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    for column in [
+        ColumnDef::new(Groups::Description).string_len(1024),
+        ColumnDef::new(Groups::Mail).string_len(255),
+    ] {
+        pool.execute(builder.build(Table::alter().table(Groups::Table).add_column(column)))
+            .await?;
+    }
+    // The custom attributes with the same names are replaced by the new columns, which take their
+    // first value.
+    #[derive(FromQueryResult)]
+    struct GroupAttributeValue {
+        group_id: GroupId,
+        attribute_name: String,
+        value: String,
+    }
+    for attribute in GroupAttributeValue::find_by_statement(
+        builder.build(
+            Query::select()
+                .from(GroupAttributes::Table)
+                .column(GroupAttributes::GroupId)
+                .column(GroupAttributes::AttributeName)
+                .column(GroupAttributes::Value)
+                .and_where(
+                    Expr::col(GroupAttributes::AttributeName).is_in(["description", "mail"]),
+                ),
+        ),
+    )
+    .all(pool)
+    .await?
+    {
+        let value = match serde_json::from_str::<Vec<String>>(&attribute.value)
+            .ok()
+            .and_then(|values| values.into_iter().next())
+        {
+            Some(value) => value,
+            None => {
+                warn!(
+                    "Dropping the invalid value of the attribute `{}` of the group {:?}",
+                    attribute.attribute_name, attribute.group_id
+                );
+                continue;
+            }
+        };
+        let column = if attribute.attribute_name == "mail" {
+            Groups::Mail
+        } else {
+            Groups::Description
+        };
+        pool.execute(
+            builder.build(
+                Query::update()
+                    .table(Groups::Table)
+                    .value(column, Value::from(value))
+                    .and_where(Expr::col(Groups::GroupId).eq(attribute.group_id)),
+            ),
+        )
+        .await?;
+    }
+    pool.execute(
+        builder.build(
+            Query::delete()
+                .from_table(GroupAttributes::Table)
+                .and_where(
+                    Expr::col(GroupAttributes::AttributeName).is_in(["description", "mail"]),
+                ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Query::delete()
+                .from_table(GroupAttributeSchema::Table)
+                .and_where(
+                    Expr::col(GroupAttributeSchema::AttributeName).is_in(["description", "mail"]),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(31)).await
+}
+
+async fn upgrade_to_v32(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    for column in [
+        ColumnDef::new(Users::PasswordModifiedDate).date_time(),
+        ColumnDef::new(Users::PasswordExpiryWarned)
+            .boolean()
+            .not_null()
+            .default(false),
+    ] {
+        pool.execute(builder.build(Table::alter().table(Users::Table).add_column(column)))
+            .await?;
+    }
+    // The existing passwords count as set now: with a maximum age, they would otherwise all
+    // expire at once.
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Users::Table)
+                .value(
+                    Users::PasswordModifiedDate,
+                    Value::from(chrono::Utc::now().naive_utc()),
+                )
+                .and_where(Expr::col(Users::PasswordHash).is_not_null()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(32)).await
+}
+
+async fn upgrade_to_v33(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(ChangeLog::Table)
+                .add_column(ColumnDef::new(ChangeLog::Diff).text()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(33)).await
+}
+
+async fn upgrade_to_v34(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    for column in [
+        ColumnDef::new(Users::LastLdapLogin).date_time(),
+        ColumnDef::new(Users::LastWebLogin).date_time(),
+    ] {
+        pool.execute(builder.build(Table::alter().table(Users::Table).add_column(column)))
+            .await?;
+    }
+    set_schema_version(pool, SchemaVersion(34)).await
+}
+
+async fn upgrade_to_v35(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    for column in [
+        ColumnDef::new(Users::StaleWarningDate).date_time(),
+        ColumnDef::new(Users::StaleDisabledDate).date_time(),
+    ] {
+        pool.execute(builder.build(Table::alter().table(Users::Table).add_column(column)))
+            .await?;
+    }
+    set_schema_version(pool, SchemaVersion(35)).await
+}
+
+async fn upgrade_to_v36(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    pool.execute(
+        pool.get_database_backend().build(
+            Table::alter()
+                .table(Invitations::Table)
+                .add_column(ColumnDef::new(Invitations::Template).string()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(36)).await
+}
+
+async fn set_schema_version(
+    pool: &DbConnection,
+    version: SchemaVersion,
+) -> std::result::Result<(), sea_orm::DbErr> {
+    pool.execute(
+        pool.get_database_backend().build(
+            Query::update()
+                .table(Metadata::Table)
+                .value(Metadata::Version, Value::from(version)),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+// Applies the migration from the previous version to `version`.
+async fn upgrade_to(
+    pool: &DbConnection,
+    version: SchemaVersion,
+) -> std::result::Result<(), sea_orm::DbErr> {
+    match version.0 {
+        2 => upgrade_to_v2(pool).await,
+        3 => upgrade_to_v3(pool).await,
+        4 => upgrade_to_v4(pool).await,
+        5 => upgrade_to_v5(pool).await,
+        6 => upgrade_to_v6(pool).await,
+        7 => upgrade_to_v7(pool).await,
+        8 => upgrade_to_v8(pool).await,
+        9 => upgrade_to_v9(pool).await,
+        10 => upgrade_to_v10(pool).await,
+        11 => upgrade_to_v11(pool).await,
+        12 => upgrade_to_v12(pool).await,
+        13 => upgrade_to_v13(pool).await,
+        14 => upgrade_to_v14(pool).await,
+        15 => upgrade_to_v15(pool).await,
+        16 => upgrade_to_v16(pool).await,
+        17 => upgrade_to_v17(pool).await,
+        18 => upgrade_to_v18(pool).await,
+        19 => upgrade_to_v19(pool).await,
+        20 => upgrade_to_v20(pool).await,
+        21 => upgrade_to_v21(pool).await,
+        22 => upgrade_to_v22(pool).await,
+        23 => upgrade_to_v23(pool).await,
+        24 => upgrade_to_v24(pool).await,
+        25 => upgrade_to_v25(pool).await,
+        26 => upgrade_to_v26(pool).await,
+        27 => upgrade_to_v27(pool).await,
+        28 => upgrade_to_v28(pool).await,
+        29 => upgrade_to_v29(pool).await,
+        30 => upgrade_to_v30(pool).await,
+        31 => upgrade_to_v31(pool).await,
+        32 => upgrade_to_v32(pool).await,
+        33 => upgrade_to_v33(pool).await,
+        34 => upgrade_to_v34(pool).await,
+        35 => upgrade_to_v35(pool).await,
+        36 => upgrade_to_v36(pool).await,
+        _ => Err(sea_orm::DbErr::Custom(format!(
+            "No migration to version {}",
+            version.0
+        ))),
+    }
+}
+
+async fn drop_table(
+    pool: &DbConnection,
+    table: impl Iden + 'static,
+) -> std::result::Result<(), sea_orm::DbErr> {
+    pool.execute(
+        pool.get_database_backend()
+            .build(Table::drop().table(table).if_exists()),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn drop_columns<T: Iden + Clone + 'static, const N: usize>(
+    pool: &DbConnection,
+    table: T,
+    columns: [T; N],
+) -> std::result::Result<(), sea_orm::DbErr> {
+    // SQLite only supports dropping one column per statement.
+    for column in columns {
+        pool.execute(
+            pool.get_database_backend()
+                .build(Table::alter().table(table.clone()).drop_column(column)),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+// Undoes the migration to `version`, going back to the previous version. Only the migrations that
+// add tables or columns can be undone: the older ones transform the existing data.
+async fn downgrade_from(
+    pool: &DbConnection,
+    version: SchemaVersion,
+) -> std::result::Result<(), sea_orm::DbErr> {
+    match version.0 {
+        36 => drop_columns(pool, Invitations::Table, [Invitations::Template]).await?,
+        35 => {
+            drop_columns(
+                pool,
+                Users::Table,
+                [Users::StaleWarningDate, Users::StaleDisabledDate],
+            )
+            .await?
+        }
+        34 => {
+            drop_columns(
+                pool,
+                Users::Table,
+                [Users::LastLdapLogin, Users::LastWebLogin],
+            )
+            .await?
+        }
+        33 => drop_columns(pool, ChangeLog::Table, [ChangeLog::Diff]).await?,
+        32 => {
+            drop_columns(
+                pool,
+                Users::Table,
+                [Users::PasswordModifiedDate, Users::PasswordExpiryWarned],
+            )
+            .await?
+        }
+        31 => drop_columns(pool, Groups::Table, [Groups::Description, Groups::Mail]).await?,
+        30 => drop_table(pool, ServiceAccounts::Table).await?,
+        // The user IDs stay in lowercase, which the older versions handle as well.
+        29 => {}
+        28 => drop_columns(pool, Users::Table, [Users::Locale, Users::Timezone]).await?,
+        27 => drop_columns(pool, Users::Table, PROFILE_COLUMNS).await?,
+        26 => drop_table(pool, UserEmails::Table).await?,
+        25 => drop_table(pool, RemoteAvatars::Table).await?,
+        24 => {
+            drop_table(pool, DeletedGroups::Table).await?;
+            drop_table(pool, DeletedUsers::Table).await?;
+        }
+        23 => drop_table(pool, LegacyPasswordHashes::Table).await?,
+        22 => {
+            drop_table(pool, OidcAuthorizationCodes::Table).await?;
+            drop_table(pool, OidcGroupClaims::Table).await?;
+            drop_table(pool, OidcClients::Table).await?;
+        }
+        21 => drop_table(pool, GroupManagers::Table).await?,
+        20 => {
+            drop_table(pool, RoleGroups::Table).await?;
+            drop_table(pool, Roles::Table).await?;
+        }
+        19 => drop_table(pool, PendingUsers::Table).await?,
+        18 => {
+            drop_table(pool, InvitationGroups::Table).await?;
+            drop_table(pool, Invitations::Table).await?;
+        }
+        17 => drop_table(pool, WebauthnCredentials::Table).await?,
+        16 => drop_table(pool, FailedLogins::Table).await?,
+        15 => drop_table(pool, PasswordHistory::Table).await?,
+        _ => {
+            return Err(sea_orm::DbErr::Custom(format!(
+                "The migration to version {} cannot be undone",
+                version.0
+            )))
+        }
+    }
+    set_schema_version(pool, SchemaVersion(version.0 - 1)).await
+}
+
+/// The oldest version the schema can be rolled back to.
+pub const OLDEST_REVERSIBLE_SCHEMA_VERSION: SchemaVersion = SchemaVersion(14);
+
+/// A migration of the schema, by one version.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MigrationStep {
+    /// Migrates from the previous version to this one.
+    Upgrade(SchemaVersion),
+    /// Migrates from this version to the previous one.
+    Downgrade(SchemaVersion),
+}
+
+impl std::fmt::Display for MigrationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationStep::Upgrade(version) => {
+                write!(f, "upgrade from v{} to v{}", version.0 - 1, version.0)
+            }
+            MigrationStep::Downgrade(version) => {
+                write!(f, "downgrade from v{} to v{}", version.0, version.0 - 1)
+            }
+        }
+    }
+}
+
+fn check_not_newer(version: SchemaVersion) -> anyhow::Result<()> {
+    if version > LAST_SCHEMA_VERSION {
+        anyhow::bail!(
+            "The database schema is at version {}, newer than the latest one known by this version \
+             of LLDAP ({}). Upgrade LLDAP, or roll back the schema with the `migrate` command of \
+             the newer version",
+            version.0,
+            LAST_SCHEMA_VERSION.0
+        );
+    }
+    Ok(())
+}
+
+/// The steps to migrate the schema from `current` to `target`.
+pub fn migration_plan(
+    current: SchemaVersion,
+    target: SchemaVersion,
+) -> anyhow::Result<Vec<MigrationStep>> {
+    check_not_newer(current)?;
+    if target > LAST_SCHEMA_VERSION {
+        anyhow::bail!(
+            "Unknown schema version {}, the latest one is {}",
+            target.0,
+            LAST_SCHEMA_VERSION.0
+        );
+    }
+    if target >= current {
+        return Ok((current.0 + 1..=target.0)
+            .map(|version| MigrationStep::Upgrade(SchemaVersion(version)))
+            .collect());
+    }
+    if target < OLDEST_REVERSIBLE_SCHEMA_VERSION {
+        anyhow::bail!(
+            "The schema can't be rolled back further than version {}",
+            OLDEST_REVERSIBLE_SCHEMA_VERSION.0
+        );
+    }
+    Ok((target.0 + 1..=current.0)
+        .rev()
+        .map(|version| MigrationStep::Downgrade(SchemaVersion(version)))
+        .collect())
+}
+
+pub async fn apply_migration_step(pool: &DbConnection, step: MigrationStep) -> anyhow::Result<()> {
+    match step {
+        MigrationStep::Upgrade(version) => upgrade_to(pool, version).await?,
+        MigrationStep::Downgrade(version) => downgrade_from(pool, version).await?,
+    }
+    Ok(())
+}
+
 pub async fn migrate_from_version(
-    _pool: &DbConnection,
+    pool: &DbConnection,
     version: SchemaVersion,
 ) -> anyhow::Result<()> {
-    if version.0 > 1 {
-        anyhow::bail!("DB version downgrading is not supported");
+    for step in migration_plan(version, LAST_SCHEMA_VERSION)? {
+        apply_migration_step(pool, step).await?;
     }
     Ok(())
 }
@@ -0,0 +1,334 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, CreateOidcClientRequest, OidcBackendHandler},
+    model::{self, OidcAuthorizationCodesColumn, OidcClientsColumn, OidcGroupClaimsColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{OidcAuthorizationCode, OidcClient, OidcGroupClaim},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use tracing::{debug, instrument};
+
+fn generate_secret() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+// The target and the details of the audit log entry of a claim change.
+fn claim_audit_entry(claim: &OidcGroupClaim) -> (String, String) {
+    (
+        format!("oidc_client:{}", claim.client_id),
+        format!(
+            "{} {}: {}",
+            AuditEvent::group_target(claim.group_id),
+            claim.claim,
+            claim.value
+        ),
+    )
+}
+
+#[async_trait]
+impl OidcBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_oidc_client(&self, request: CreateOidcClientRequest) -> Result<String> {
+        debug!(client_id = ?request.client_id, redirect_uris = ?request.redirect_uris);
+        if request.client_id.is_empty() {
+            return Err(DomainError::InvalidRequest(
+                "The client ID cannot be empty".to_owned(),
+            ));
+        }
+        if request.redirect_uris.is_empty()
+            || request
+                .redirect_uris
+                .iter()
+                .any(|uri| uri.is_empty() || uri.contains('\n'))
+        {
+            return Err(DomainError::InvalidRequest(
+                "The client needs at least one redirect URI, on a single line".to_owned(),
+            ));
+        }
+        let client_secret = generate_secret();
+        let target = format!("oidc_client:{}", request.client_id);
+        model::oidc_clients::ActiveModel {
+            client_id: ActiveValue::Set(request.client_id),
+            client_secret: ActiveValue::Set(client_secret.clone()),
+            display_name: ActiveValue::Set(request.display_name),
+            redirect_uris: ActiveValue::Set(request.redirect_uris.join("\n")),
+            creation_date: ActiveValue::Set(chrono::Utc::now()),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit("create_oidc_client", target, None).await;
+        Ok(client_secret)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>> {
+        Ok(model::OidcClients::find()
+            .order_by_asc(OidcClientsColumn::ClientId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_oidc_client(&self, client_id: &str) -> Result<OidcClient> {
+        debug!(?client_id);
+        model::OidcClients::find_by_id(client_id.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| DomainError::EntityNotFound(format!("No such client: '{}'", client_id)))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_oidc_client(&self, client_id: &str) -> Result<()> {
+        debug!(?client_id);
+        // Also deletes the claims and the pending authorization codes.
+        let res = model::OidcClients::delete_by_id(client_id.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such client: '{}'",
+                client_id
+            )));
+        }
+        self.audit(
+            "delete_oidc_client",
+            format!("oidc_client:{}", client_id),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_oidc_group_claims(&self, client_id: &str) -> Result<Vec<OidcGroupClaim>> {
+        debug!(?client_id);
+        Ok(model::OidcGroupClaims::find()
+            .filter(OidcGroupClaimsColumn::ClientId.eq(client_id))
+            .order_by_asc(OidcGroupClaimsColumn::GroupId)
+            .order_by_asc(OidcGroupClaimsColumn::Claim)
+            .order_by_asc(OidcGroupClaimsColumn::Value)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()> {
+        debug!(?claim);
+        if claim.claim.is_empty() {
+            return Err(DomainError::InvalidRequest(
+                "The claim name cannot be empty".to_owned(),
+            ));
+        }
+        let (target, details) = claim_audit_entry(&claim);
+        model::oidc_group_claims::ActiveModel {
+            client_id: ActiveValue::Set(claim.client_id),
+            group_id: ActiveValue::Set(claim.group_id),
+            claim: ActiveValue::Set(claim.claim),
+            value: ActiveValue::Set(claim.value),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit("add_oidc_group_claim", target, Some(details))
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()> {
+        debug!(?claim);
+        let (target, details) = claim_audit_entry(&claim);
+        let res = model::OidcGroupClaims::delete_by_id((
+            claim.client_id,
+            claim.group_id,
+            claim.claim,
+            claim.value,
+        ))
+        .exec(&self.sql_pool)
+        .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound("No such claim".to_owned()));
+        }
+        self.audit("remove_oidc_group_claim", target, Some(details))
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_oidc_authorization_code(&self, code: OidcAuthorizationCode) -> Result<String> {
+        debug!(client_id = ?code.client_id, user_id = ?code.user_id);
+        let token = generate_secret();
+        model::oidc_authorization_codes::ActiveModel {
+            code: ActiveValue::Set(token.clone()),
+            client_id: ActiveValue::Set(code.client_id),
+            user_id: ActiveValue::Set(code.user_id),
+            redirect_uri: ActiveValue::Set(code.redirect_uri),
+            scope: ActiveValue::Set(code.scope),
+            nonce: ActiveValue::Set(code.nonce),
+            code_challenge: ActiveValue::Set(code.code_challenge),
+            code_challenge_method: ActiveValue::Set(code.code_challenge_method),
+            expiry_date: ActiveValue::Set(code.expiry_date),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(token)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn consume_oidc_authorization_code(&self, code: &str) -> Result<OidcAuthorizationCode> {
+        let transaction = self.sql_pool.begin().await?;
+        // Cleans up the expired codes along the way.
+        model::OidcAuthorizationCodes::delete_many()
+            .filter(OidcAuthorizationCodesColumn::ExpiryDate.lte(chrono::Utc::now().naive_utc()))
+            .exec(&transaction)
+            .await?;
+        let authorization = model::OidcAuthorizationCodes::find_by_id(code.to_owned())
+            .one(&transaction)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound("Invalid authorization code".to_owned()))?;
+        model::OidcAuthorizationCodes::delete_by_id(code.to_owned())
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(authorization.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{sql_backend_handler::tests::*, types::UserId};
+
+    #[tokio::test]
+    async fn test_oidc_clients() {
+        let fixture = TestFixture::new().await;
+        let secret = fixture
+            .handler
+            .create_oidc_client(CreateOidcClientRequest {
+                client_id: "grafana".to_owned(),
+                display_name: "Grafana".to_owned(),
+                redirect_uris: vec![
+                    "https://grafana.example.com/login/generic_oauth".to_owned(),
+                    "http://localhost:3000/login/generic_oauth".to_owned(),
+                ],
+            })
+            .await
+            .unwrap();
+        let client = fixture.handler.get_oidc_client("grafana").await.unwrap();
+        assert_eq!(client.client_secret, secret);
+        assert_eq!(client.redirect_uris.len(), 2);
+        assert!(fixture
+            .handler
+            .create_oidc_client(CreateOidcClientRequest {
+                client_id: "no_redirect".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .is_err());
+
+        let claim = OidcGroupClaim {
+            client_id: "grafana".to_owned(),
+            group_id: fixture.groups[0],
+            claim: "role".to_owned(),
+            value: "Admin".to_owned(),
+        };
+        fixture
+            .handler
+            .add_oidc_group_claim(claim.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_oidc_group_claims("grafana")
+                .await
+                .unwrap(),
+            vec![claim.clone()]
+        );
+        fixture
+            .handler
+            .remove_oidc_group_claim(claim.clone())
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .remove_oidc_group_claim(claim)
+            .await
+            .is_err());
+
+        fixture.handler.delete_oidc_client("grafana").await.unwrap();
+        assert!(fixture.handler.get_oidc_client("grafana").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oidc_authorization_codes() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .create_oidc_client(CreateOidcClientRequest {
+                client_id: "grafana".to_owned(),
+                display_name: "Grafana".to_owned(),
+                redirect_uris: vec!["https://grafana.example.com/login".to_owned()],
+            })
+            .await
+            .unwrap();
+        let authorization = OidcAuthorizationCode {
+            client_id: "grafana".to_owned(),
+            user_id: UserId::new("bob"),
+            redirect_uri: "https://grafana.example.com/login".to_owned(),
+            scope: "openid profile".to_owned(),
+            nonce: Some("nonce".to_owned()),
+            code_challenge: None,
+            code_challenge_method: None,
+            expiry_date: chrono::Utc::now() + chrono::Duration::minutes(1),
+        };
+        let code = fixture
+            .handler
+            .create_oidc_authorization_code(authorization.clone())
+            .await
+            .unwrap();
+        let consumed = fixture
+            .handler
+            .consume_oidc_authorization_code(&code)
+            .await
+            .unwrap();
+        assert_eq!(consumed.user_id, authorization.user_id);
+        assert_eq!(consumed.scope, authorization.scope);
+        assert_eq!(consumed.nonce, authorization.nonce);
+        // The codes can only be used once.
+        assert!(fixture
+            .handler
+            .consume_oidc_authorization_code(&code)
+            .await
+            .is_err());
+
+        let expired_code = fixture
+            .handler
+            .create_oidc_authorization_code(OidcAuthorizationCode {
+                expiry_date: chrono::Utc::now() - chrono::Duration::minutes(1),
+                ..authorization
+            })
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .consume_oidc_authorization_code(&expired_code)
+            .await
+            .is_err());
+    }
+}
@@ -1,16 +1,23 @@
 use super::{
     error::{DomainError, Result},
-    handler::{BindRequest, LoginHandler},
+    external_auth,
+    handler::{
+        AuditActor, AuditEvent, BindRequest, CreateUserRequest, LoginHandler, UserBackendHandler,
+    },
+    legacy_password,
     model::{self, UserColumn},
     opaque_handler::{login, registration, OpaqueHandler},
+    security_events::SecurityEvent,
     sql_backend_handler::SqlBackendHandler,
-    types::UserId,
+    types::{AuditSource, UserId},
+    upstream_ldap,
 };
+use crate::infra::configuration::ExternalAuthType;
 use async_trait::async_trait;
 use lldap_auth::opaque;
 use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, FromQueryResult, QuerySelect};
 use secstr::SecUtf8;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
 type SqlOpaqueHandler = SqlBackendHandler;
 
@@ -42,7 +49,7 @@ fn passwords_match(
 }
 
 impl SqlBackendHandler {
-    fn get_orion_secret_key(&self) -> Result<orion::aead::SecretKey> {
+    pub(crate) fn get_orion_secret_key(&self) -> Result<orion::aead::SecretKey> {
         Ok(orion::aead::SecretKey::from_slice(
             self.config.get_server_keys().private(),
         )?)
@@ -63,16 +70,166 @@ impl SqlBackendHandler {
             .await?
             .and_then(|u| u.password_hash))
     }
+
+    /// Whether the user is in the group, e.g. of the users that always authenticate upstream.
+    async fn is_in_group(&self, user_id: &UserId, group_name: &str) -> Result<bool> {
+        match self.get_user_groups_from_primary(user_id).await {
+            Ok(groups) => Ok(groups.iter().any(|g| g.display_name == group_name)),
+            Err(DomainError::EntityNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The handler recording the changes made while binding, e.g. the password saved from the
+    /// upstream server, on behalf of the server rather than of the connection's user.
+    fn as_server(&self) -> Self {
+        self.clone()
+            .with_audit_actor(AuditActor::system(AuditSource::Server))
+    }
+
+    /// Checks the password against the hash imported from another directory, if any, and
+    /// replaces the hash with an OPAQUE password file when it matches. Returns `None` if the user
+    /// has no imported hash.
+    #[instrument(skip_all, level = "debug", err)]
+    async fn bind_legacy_hash(&self, request: &BindRequest) -> Result<Option<bool>> {
+        let legacy_hash = match model::LegacyPasswordHashes::find_by_id(request.name.clone())
+            .one(&self.sql_pool)
+            .await?
+        {
+            Some(legacy_hash) => legacy_hash,
+            None => return Ok(None),
+        };
+        if !legacy_password::verify(&legacy_hash.password_hash, &request.password) {
+            return Ok(Some(false));
+        }
+        debug!(r#"Upgrading the imported password of "{}""#, &request.name);
+        register_password(
+            &self.as_server(),
+            &request.name,
+            &SecUtf8::from(request.password.as_str()),
+        )
+        .await?;
+        Ok(Some(true))
+    }
+
+    /// Checks the password with the external authentication hook. The user must exist locally.
+    #[instrument(skip_all, level = "debug")]
+    async fn bind_external(&self, request: &BindRequest) -> bool {
+        match external_auth::authenticate(
+            &self.config.external_auth,
+            &request.name,
+            &request.password,
+        )
+        .await
+        {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                warn!(
+                    r#"Could not check the password of "{}" with the external hook: {}"#,
+                    &request.name, e
+                );
+                false
+            }
+        }
+    }
+
+    /// Checks the password with the upstream LDAP server, creating the local account if needed.
+    /// The password is saved locally if `save_password`, so that the next binds don't need the
+    /// upstream server anymore.
+    #[instrument(skip_all, level = "debug", err)]
+    async fn bind_upstream(&self, request: &BindRequest, save_password: bool) -> Result<bool> {
+        let options = &self.config.upstream_ldap_options;
+        let upstream_user =
+            match upstream_ldap::authenticate(options, &request.name, &request.password).await {
+                Ok(Some(upstream_user)) => upstream_user,
+                Ok(None) => return Ok(false),
+                Err(e) => {
+                    warn!(
+                        r#"Could not check the password of "{}" upstream: {}"#,
+                        &request.name, e
+                    );
+                    return Ok(false);
+                }
+            };
+        if model::User::find_by_id(request.name.clone())
+            .one(&self.sql_pool)
+            .await?
+            .is_none()
+        {
+            if !options.create_users {
+                debug!(r#"User "{}" only exists upstream"#, &request.name);
+                return Ok(false);
+            }
+            let email = match upstream_user.email {
+                Some(email) => email,
+                None => {
+                    warn!(
+                        r#"Cannot create the user "{}": no email upstream"#,
+                        &request.name
+                    );
+                    return Ok(false);
+                }
+            };
+            self.as_server()
+                .create_user(CreateUserRequest {
+                    user_id: request.name.clone(),
+                    email,
+                    display_name: upstream_user.display_name,
+                    first_name: upstream_user.first_name,
+                    last_name: upstream_user.last_name,
+                    ..Default::default()
+                })
+                .await?;
+        }
+        if save_password {
+            register_password(
+                &self.as_server(),
+                &request.name,
+                &SecUtf8::from(request.password.as_str()),
+            )
+            .await?;
+        }
+        Ok(true)
+    }
 }
 
 #[async_trait]
 impl LoginHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn bind(&self, request: BindRequest) -> Result<()> {
-        if let Some(password_hash) = self
+        self.check_account_not_locked(&request.name).await?;
+        if self.config.external_auth.hook != ExternalAuthType::None
+            && self
+                .is_in_group(&request.name, &self.config.external_auth.group)
+                .await?
+        {
+            debug!(
+                r#"User "{}" authenticates with the external hook"#,
+                &request.name
+            );
+            if self.bind_external(&request).await {
+                self.record_login_success(&request.name).await?;
+                return Ok(());
+            }
+            self.record_login_failure(&request.name).await?;
+            return Err(DomainError::AuthenticationError(format!(
+                " for user '{}'",
+                request.name
+            )));
+        }
+        let upstream_enabled = self.config.upstream_ldap_options.enabled;
+        let upstream_only = upstream_enabled
+            && self
+                .is_in_group(&request.name, &self.config.upstream_ldap_options.group)
+                .await?;
+        let mut known_user = upstream_only;
+        if upstream_only {
+            debug!(r#"User "{}" authenticates upstream"#, &request.name);
+        } else if let Some(password_hash) = self
             .get_password_file_for_user(request.name.clone())
             .await?
         {
+            known_user = true;
             if let Err(e) = passwords_match(
                 &password_hash,
                 &request.password,
@@ -81,14 +238,29 @@ impl LoginHandler for SqlBackendHandler {
             ) {
                 debug!(r#"Invalid password for "{}": {}"#, &request.name, e);
             } else {
+                self.record_login_success(&request.name).await?;
+                return Ok(());
+            }
+        } else if let Some(matches) = self.bind_legacy_hash(&request).await? {
+            known_user = true;
+            if matches {
+                self.record_login_success(&request.name).await?;
                 return Ok(());
             }
+            debug!(r#"Invalid password for "{}""#, &request.name);
         } else {
             debug!(
                 r#"User "{}" doesn't exist or has no password"#,
                 &request.name
             );
         }
+        if upstream_enabled && self.bind_upstream(&request, !upstream_only).await? {
+            self.record_login_success(&request.name).await?;
+            return Ok(());
+        }
+        if known_user {
+            self.record_login_failure(&request.name).await?;
+        }
         Err(DomainError::AuthenticationError(format!(
             " for user '{}'",
             request.name
@@ -148,13 +320,19 @@ impl OpaqueHandler for SqlOpaqueHandler {
             &secret_key,
             &base64::decode(&request.server_data)?,
         )?)?;
+        let user_id = UserId::new(&username);
+        self.check_account_not_locked(&user_id).await?;
         // Finish the login: this makes sure the client data is correct, and gives a session key we
         // don't need.
-        let _session_key =
-            opaque::server::login::finish_login(server_login, request.credential_finalization)?
-                .session_key;
+        match opaque::server::login::finish_login(server_login, request.credential_finalization) {
+            Ok(_) => self.record_login_success(&user_id).await?,
+            Err(e) => {
+                self.record_login_failure(&user_id).await?;
+                return Err(e.into());
+            }
+        }
 
-        Ok(UserId::new(&username))
+        Ok(user_id)
     }
 
     #[instrument(skip_all, level = "debug", err)]
@@ -192,13 +370,36 @@ impl OpaqueHandler for SqlOpaqueHandler {
 
         let password_file =
             opaque::server::registration::get_password_file(request.registration_upload);
+        // Setting the first password, e.g. for a new account or when migrating a legacy or
+        // upstream password, is not worth a notification.
+        let had_password = self
+            .get_password_file_for_user(UserId::new(&username))
+            .await?
+            .is_some();
         // Set the user password to the new password.
         let user_update = model::users::ActiveModel {
             user_id: ActiveValue::Set(UserId::new(&username)),
             password_hash: ActiveValue::Set(Some(password_file.serialize())),
+            password_modified_date: ActiveValue::Set(Some(chrono::Utc::now())),
+            password_expiry_warned: ActiveValue::Set(false),
             ..Default::default()
         };
         user_update.update(&self.sql_pool).await?;
+        // The imported hash, if any, is superseded.
+        model::LegacyPasswordHashes::delete_by_id(UserId::new(&username))
+            .exec(&self.sql_pool)
+            .await?;
+        if had_password {
+            self.publish_security_event(SecurityEvent::PasswordChanged(UserId::new(&username)));
+        }
+        let user_id = UserId::new(&username);
+        let action = if self.audit_actor.user == user_id {
+            "change_password"
+        } else {
+            "reset_user_password"
+        };
+        self.audit(action, AuditEvent::user_target(&user_id), None)
+            .await;
         Ok(())
     }
 }
@@ -206,7 +407,7 @@ impl OpaqueHandler for SqlOpaqueHandler {
 /// Convenience function to set a user's password.
 #[instrument(skip_all, level = "debug", err)]
 pub(crate) async fn register_password(
-    opaque_handler: &SqlOpaqueHandler,
+    opaque_handler: &impl OpaqueHandler,
     username: &UserId,
     password: &SecUtf8,
 ) -> Result<()> {
@@ -233,6 +434,36 @@ pub(crate) async fn register_password(
         .await
 }
 
+/// Convenience function to check a user's password, running both sides of the OPAQUE login.
+/// The login counts towards the lockout of the account only when the password is correct.
+#[instrument(skip_all, level = "debug", err)]
+pub(crate) async fn verify_password(
+    opaque_handler: &impl OpaqueHandler,
+    username: &UserId,
+    password: &SecUtf8,
+) -> Result<()> {
+    let mut rng = rand::rngs::OsRng;
+    use login::*;
+    let login_start = opaque::client::login::start_login(password.unsecure(), &mut rng)?;
+    let start_response = opaque_handler
+        .login_start(ClientLoginStartRequest {
+            username: username.to_string(),
+            login_start_request: login_start.message,
+        })
+        .await?;
+    // The client side of the login fails when the password is wrong.
+    let login_finish =
+        opaque::client::login::finish_login(login_start.state, start_response.credential_response)
+            .map_err(|_| DomainError::AuthenticationError(format!(" for user '{}'", username)))?;
+    opaque_handler
+        .login_finish(ClientLoginFinishRequest {
+            server_data: start_response.server_data,
+            credential_finalization: login_finish.message,
+        })
+        .await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,6 +521,24 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_verify_password() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        verify_password(&handler, &bob, &SecUtf8::from("bob00")).await?;
+        assert!(matches!(
+            verify_password(&handler, &bob, &SecUtf8::from("wrong_password")).await,
+            Err(DomainError::AuthenticationError(_))
+        ));
+        verify_password(&handler, &UserId::new("andrew"), &SecUtf8::from("bob00"))
+            .await
+            .unwrap_err();
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_bind_user() {
         let sql_pool = get_initialized_db().await;
@@ -320,6 +569,82 @@ mod tests {
             .unwrap_err();
     }
 
+    #[tokio::test]
+    async fn test_bind_upstream_unreachable() {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.upstream_ldap_options = crate::infra::configuration::UpstreamLdapOptions {
+            enabled: true,
+            url: "ldap://127.0.0.1:1".to_owned(),
+            timeout_seconds: 1,
+            ..Default::default()
+        };
+        let handler = SqlOpaqueHandler::new(config, sql_pool.clone());
+        insert_user(&handler, "bob", "bob00").await;
+
+        // The local password still works, and the upstream errors are only failed binds.
+        handler
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "bob00".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            handler
+                .bind(BindRequest {
+                    name: UserId::new("bob"),
+                    password: "wrong_password".to_string(),
+                })
+                .await,
+            Err(DomainError::AuthenticationError(_))
+        ));
+        assert!(matches!(
+            handler
+                .bind(BindRequest {
+                    name: UserId::new("andrew"),
+                    password: "bob00".to_string(),
+                })
+                .await,
+            Err(DomainError::AuthenticationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bind_external_hook() {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.external_auth = crate::infra::configuration::ExternalAuthOptions {
+            hook: ExternalAuthType::Exec,
+            command: Some("false".to_owned()),
+            ..Default::default()
+        };
+        let handler = SqlOpaqueHandler::new(config.clone(), sql_pool.clone());
+        insert_user(&handler, "bob", "bob00").await;
+        let bob_bind = || BindRequest {
+            name: UserId::new("bob"),
+            password: "bob00".to_string(),
+        };
+        // Outside of the group, the local password is used.
+        handler.bind(bob_bind()).await.unwrap();
+        let group = insert_group(&handler, "lldap_external_auth").await;
+        insert_membership(&handler, group, "bob").await;
+        // The hook denies the bind, even with the right local password.
+        assert!(matches!(
+            handler.bind(bob_bind()).await,
+            Err(DomainError::AuthenticationError(_))
+        ));
+        config.external_auth.command = Some("true".to_owned());
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        handler
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "any_password".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_user_no_password() {
         let sql_pool = get_initialized_db().await;
@@ -335,4 +660,42 @@ mod tests {
             .await
             .unwrap_err();
     }
+
+    #[tokio::test]
+    async fn test_bind_legacy_password_hash() {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlBackendHandler::new(config, sql_pool.clone());
+        handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@bob.bob".to_string(),
+                legacy_password_hash: Some(bcrypt::hash("bob00", 4).unwrap()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let bind = |password: &str| {
+            handler.bind(BindRequest {
+                name: UserId::new("bob"),
+                password: password.to_string(),
+            })
+        };
+
+        bind("wrong_password").await.unwrap_err();
+        bind("bob00").await.unwrap();
+        // The imported hash was replaced by a regular password.
+        assert!(model::LegacyPasswordHashes::find_by_id(UserId::new("bob"))
+            .one(&sql_pool)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(handler
+            .get_password_file_for_user(UserId::new("bob"))
+            .await
+            .unwrap()
+            .is_some());
+        bind("bob00").await.unwrap();
+        bind("wrong_password").await.unwrap_err();
+    }
 }
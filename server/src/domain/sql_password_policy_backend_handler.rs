@@ -0,0 +1,320 @@
+use crate::{
+    domain::{
+        error::{DomainError, Result},
+        handler::{PasswordPolicyBackendHandler, UserBackendHandler},
+        model::{self, PasswordHistoryColumn},
+        sql_backend_handler::SqlBackendHandler,
+        types::{User, UserId},
+    },
+    infra::{configuration::PasswordPolicyOptions, pwned_passwords},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, FromQueryResult, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+use tracing::{debug, instrument, warn};
+
+// Lists the requirements of the policy that the password doesn't meet, as completions of "The
+// password must".
+fn unmet_requirements(policy: &PasswordPolicyOptions, password: &str) -> Vec<String> {
+    let mut unmet = Vec::new();
+    if password.chars().count() < policy.min_length {
+        unmet.push(format!("be at least {} characters long", policy.min_length));
+    }
+    let contains = |predicate: fn(char) -> bool| password.chars().any(predicate);
+    if policy.require_lowercase && !contains(char::is_lowercase) {
+        unmet.push("contain a lowercase letter".to_owned());
+    }
+    if policy.require_uppercase && !contains(char::is_uppercase) {
+        unmet.push("contain an uppercase letter".to_owned());
+    }
+    if policy.require_digit && !contains(|c| c.is_ascii_digit()) {
+        unmet.push("contain a digit".to_owned());
+    }
+    if policy.require_special && !contains(|c| !c.is_alphanumeric()) {
+        unmet.push("contain a character that is neither a letter nor a digit".to_owned());
+    }
+    let lowercase_password = password.to_lowercase();
+    if policy
+        .banned_passwords
+        .iter()
+        .any(|banned| banned.to_lowercase() == lowercase_password)
+    {
+        unmet.push("not be a commonly used password".to_owned());
+    }
+    unmet
+}
+
+// The zxcvbn score of the password, from 0 to 4, and the explanation of a low score if there is
+// one.
+fn password_strength(password: &str, user_inputs: &[&str]) -> (u8, Option<String>) {
+    match zxcvbn::zxcvbn(password, user_inputs) {
+        Ok(entropy) => {
+            let feedback = entropy.feedback().as_ref().and_then(|feedback| {
+                feedback
+                    .warning()
+                    .map(|warning| warning.to_string())
+                    .or_else(|| feedback.suggestions().first().map(ToString::to_string))
+            });
+            (entropy.score(), feedback)
+        }
+        // Only the empty passwords are refused.
+        Err(_) => (0, None),
+    }
+}
+
+// The personal information that makes a password of the user easier to guess.
+fn user_inputs(user_id: &UserId, user: Option<&User>) -> Vec<String> {
+    let mut inputs = vec![user_id.to_string()];
+    if let Some(user) = user {
+        inputs.push(user.email.clone());
+        if let Some((local_part, _)) = user.email.split_once('@') {
+            inputs.push(local_part.to_owned());
+        }
+        inputs.extend(
+            [&user.display_name, &user.first_name, &user.last_name]
+                .into_iter()
+                .flatten()
+                .cloned(),
+        );
+    }
+    inputs
+}
+
+// The previous passwords are kept as salted Argon2 hashes, like a regular password hash would be.
+fn hash_password(password: &str) -> Result<String> {
+    use rand::RngCore;
+    let mut salt = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+        .map_err(|e| DomainError::InternalError(format!("Could not hash the password: {}", e)))
+}
+
+#[derive(FromQueryResult)]
+struct OnlyPasswordHash {
+    password_hash: String,
+}
+
+impl SqlBackendHandler {
+    // The previous passwords of the user, most recent first.
+    async fn get_password_history(&self, user_id: &UserId) -> Result<Vec<String>> {
+        Ok(model::PasswordHistory::find()
+            .select_only()
+            .column(PasswordHistoryColumn::PasswordHash)
+            .filter(PasswordHistoryColumn::UserId.eq(user_id))
+            .order_by_desc(PasswordHistoryColumn::CreationDate)
+            .into_model::<OnlyPasswordHash>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|h| h.password_hash)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl PasswordPolicyBackendHandler for SqlBackendHandler {
+    fn get_password_policy(&self) -> PasswordPolicyOptions {
+        self.config.password_policy.clone()
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result<()> {
+        debug!(?user_id);
+        let policy = &self.config.password_policy;
+        let mut unmet = unmet_requirements(policy, password);
+        let mut weak_score = None;
+        if policy.min_strength_score > 0 {
+            // The user doesn't exist yet when the password is set at creation.
+            let user = match self.get_user_details(user_id).await {
+                Ok(user) => Some(user),
+                Err(DomainError::EntityNotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            let inputs = user_inputs(user_id, user.as_ref());
+            let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+            let (score, feedback) = password_strength(password, &inputs);
+            if score < policy.min_strength_score {
+                unmet.push(match feedback {
+                    Some(feedback) => {
+                        format!("be harder to guess ({})", feedback.trim_end_matches('.'))
+                    }
+                    None => "be harder to guess".to_owned(),
+                });
+                weak_score = Some(score);
+            }
+        }
+        if policy.history_depth > 0
+            && self
+                .get_password_history(user_id)
+                .await?
+                .iter()
+                .take(policy.history_depth)
+                .any(|hash| argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false))
+        {
+            unmet.push(format!(
+                "not be one of the last {} passwords",
+                policy.history_depth
+            ));
+        }
+        if policy.check_pwned_passwords {
+            match pwned_passwords::is_pwned(&policy.pwned_passwords_url, password).await {
+                Ok(true) => unmet.push("not appear in a known data breach".to_owned()),
+                Ok(false) => (),
+                // The password changes shouldn't depend on the availability of the API.
+                Err(e) => warn!("Could not check the password against the breaches: {:#}", e),
+            }
+        }
+        if unmet.is_empty() {
+            return Ok(());
+        }
+        let message = format!("The password must {}", unmet.join(", "));
+        Err(match weak_score {
+            Some(score) => DomainError::WeakPassword {
+                message,
+                score,
+                min_score: policy.min_strength_score,
+            },
+            None => DomainError::InvalidRequest(message),
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_password_to_history(&self, user_id: &UserId, password: &str) -> Result<()> {
+        debug!(?user_id);
+        let depth = self.config.password_policy.history_depth;
+        if depth == 0 {
+            return Ok(());
+        }
+        model::password_history::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            password_hash: ActiveValue::Set(hash_password(password)?),
+            creation_date: ActiveValue::Set(chrono::Utc::now()),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        let forgotten: Vec<String> = self
+            .get_password_history(user_id)
+            .await?
+            .into_iter()
+            .skip(depth)
+            .collect();
+        if !forgotten.is_empty() {
+            model::PasswordHistory::delete_many()
+                .filter(PasswordHistoryColumn::UserId.eq(user_id))
+                .filter(PasswordHistoryColumn::PasswordHash.is_in(forgotten))
+                .exec(&self.sql_pool)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::sql_backend_handler::tests::*, infra::configuration::PasswordPolicyOptionsBuilder,
+    };
+
+    #[test]
+    fn test_unmet_requirements() {
+        let policy = PasswordPolicyOptionsBuilder::default()
+            .min_length(10)
+            .require_lowercase(true)
+            .require_uppercase(true)
+            .require_digit(true)
+            .require_special(true)
+            .banned_passwords(vec!["Password123!".to_owned()])
+            .build()
+            .unwrap();
+        assert_eq!(
+            unmet_requirements(&policy, "pass"),
+            vec![
+                "be at least 10 characters long",
+                "contain an uppercase letter",
+                "contain a digit",
+                "contain a character that is neither a letter nor a digit",
+            ]
+        );
+        assert_eq!(
+            unmet_requirements(&policy, "PASSWORD123!"),
+            vec![
+                "contain a lowercase letter",
+                "not be a commonly used password"
+            ]
+        );
+        assert!(unmet_requirements(&policy, "Correct-Horse-42").is_empty());
+        assert!(unmet_requirements(&PasswordPolicyOptions::default(), "password").is_empty());
+    }
+
+    #[test]
+    fn test_password_strength() {
+        assert!(password_strength("patrick123", &["patrick"]).0 < 2);
+        assert_eq!(
+            password_strength("correct-horse-battery-staple-42", &[]).0,
+            4
+        );
+        assert_eq!(password_strength("", &[]), (0, None));
+    }
+
+    #[tokio::test]
+    async fn test_weak_password() {
+        let mut fixture = TestFixture::new().await;
+        fixture.handler.config.password_policy.min_strength_score = 3;
+        let bob = UserId::new("bob");
+        assert!(matches!(
+            fixture
+                .handler
+                .check_password_policy(&bob, "bob12345")
+                .await,
+            Err(DomainError::WeakPassword { min_score: 3, .. })
+        ));
+        fixture
+            .handler
+            .check_password_policy(&bob, "correct-horse-battery-staple-42")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_password_history() {
+        let mut fixture = TestFixture::new().await;
+        fixture.handler.config.password_policy.history_depth = 2;
+        let bob = UserId::new("bob");
+        for password in ["password1", "password2", "password3"] {
+            fixture
+                .handler
+                .check_password_policy(&bob, password)
+                .await
+                .unwrap();
+            fixture
+                .handler
+                .add_password_to_history(&bob, password)
+                .await
+                .unwrap();
+        }
+        // Only the last 2 passwords are remembered.
+        fixture
+            .handler
+            .check_password_policy(&bob, "password1")
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .check_password_policy(&bob, "password2")
+            .await
+            .is_err());
+        assert!(fixture
+            .handler
+            .check_password_policy(&bob, "password3")
+            .await
+            .is_err());
+        fixture
+            .handler
+            .check_password_policy(&UserId::new("patrick"), "password3")
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, PasswordResetBackendHandler},
+    model::{self, PasswordResetTokensColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter};
+use tracing::{debug, instrument};
+
+fn generate_token() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(100)
+        .map(char::from)
+        .collect()
+}
+
+#[async_trait]
+impl PasswordResetBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_password_reset_token(
+        &self,
+        user_id: &UserId,
+        validity: chrono::Duration,
+    ) -> Result<String> {
+        debug!(?user_id);
+        if model::User::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        // Only the last emailed link is valid.
+        model::PasswordResetTokens::delete_many()
+            .filter(PasswordResetTokensColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        let token = generate_token();
+        model::password_reset_tokens::Model {
+            token: token.clone(),
+            user_id: user_id.clone(),
+            expiry_date: chrono::Utc::now() + validity,
+        }
+        .into_active_model()
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit(
+            "create_password_reset_token",
+            AuditEvent::user_target(user_id),
+            None,
+        )
+        .await;
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::sql_backend_handler::tests::*, infra::jwt_sql_tables};
+
+    #[tokio::test]
+    async fn test_create_password_reset_token() {
+        let fixture = TestFixture::new().await;
+        jwt_sql_tables::init_table(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .create_password_reset_token(&UserId::new("unknown"), chrono::Duration::days(1))
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        let bob = UserId::new("bob");
+        let first_token = fixture
+            .handler
+            .create_password_reset_token(&bob, chrono::Duration::days(1))
+            .await
+            .unwrap();
+        let second_token = fixture
+            .handler
+            .create_password_reset_token(&bob, chrono::Duration::days(1))
+            .await
+            .unwrap();
+        assert_ne!(first_token, second_token);
+        let tokens = model::PasswordResetTokens::find()
+            .filter(PasswordResetTokensColumn::UserId.eq(&bob))
+            .all(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, second_token);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, CreatePendingUserRequest, CreateUserRequest, PendingUserBackendHandler},
+    model::{self, PendingUsersColumn, UserColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{PendingUser, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+use tracing::{debug, instrument};
+
+impl SqlBackendHandler {
+    async fn check_user_id_and_email_are_free<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        email: &str,
+    ) -> Result<()> {
+        if model::User::find_by_id(user_id.clone())
+            .one(conn)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "User '{}' already exists",
+                user_id
+            )));
+        }
+        if model::User::find()
+            .filter(UserColumn::Email.eq(email))
+            .one(conn)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "A user already has the email '{}'",
+                email
+            )));
+        }
+        Ok(())
+    }
+
+    async fn take_pending_user<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+    ) -> Result<PendingUser> {
+        let pending_user = model::PendingUsers::find_by_id(user_id.clone())
+            .one(conn)
+            .await?
+            .ok_or_else(|| {
+                DomainError::EntityNotFound(format!("No pending request for '{}'", user_id))
+            })?;
+        model::PendingUsers::delete_by_id(user_id.clone())
+            .exec(conn)
+            .await?;
+        Ok(pending_user.into())
+    }
+}
+
+#[async_trait]
+impl PendingUserBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_pending_user(&self, request: CreatePendingUserRequest) -> Result<()> {
+        debug!(user_id = ?request.user_id, email = ?request.email);
+        let transaction = self.sql_pool.begin().await?;
+        self.check_user_id_and_email_are_free(&transaction, &request.user_id, &request.email)
+            .await?;
+        if model::PendingUsers::find()
+            .filter(
+                PendingUsersColumn::UserId
+                    .eq(request.user_id.clone())
+                    .or(PendingUsersColumn::Email.eq(request.email.as_str())),
+            )
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(
+                "A request with the same user ID or email is already pending".to_owned(),
+            ));
+        }
+        let target = format!("pending_user:{}", request.user_id);
+        model::pending_users::ActiveModel {
+            user_id: ActiveValue::Set(request.user_id),
+            email: ActiveValue::Set(request.email),
+            display_name: ActiveValue::Set(request.display_name),
+            creation_date: ActiveValue::Set(chrono::Utc::now()),
+        }
+        .insert(&transaction)
+        .await?;
+        transaction.commit().await?;
+        self.audit("create_pending_user", target, None).await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_pending_users(&self) -> Result<Vec<PendingUser>> {
+        Ok(model::PendingUsers::find()
+            .order_by_asc(PendingUsersColumn::CreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn approve_pending_user(&self, user_id: &UserId) -> Result<PendingUser> {
+        debug!(?user_id);
+        let transaction = self.sql_pool.begin().await?;
+        let pending_user = self.take_pending_user(&transaction, user_id).await?;
+        // The ID or the email could have been taken since the request.
+        self.check_user_id_and_email_are_free(&transaction, user_id, &pending_user.email)
+            .await?;
+        self.insert_new_user(
+            &transaction,
+            CreateUserRequest {
+                user_id: pending_user.user_id.clone(),
+                email: pending_user.email.clone(),
+                display_name: pending_user.display_name.clone(),
+                ..Default::default()
+            },
+            Vec::new(),
+        )
+        .await?;
+        transaction.commit().await?;
+        self.audit(
+            "approve_pending_user",
+            AuditEvent::user_target(&pending_user.user_id),
+            None,
+        )
+        .await;
+        Ok(pending_user)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn reject_pending_user(&self, user_id: &UserId) -> Result<PendingUser> {
+        debug!(?user_id);
+        let pending_user = self.take_pending_user(&self.sql_pool, user_id).await?;
+        self.audit(
+            "reject_pending_user",
+            format!("pending_user:{}", pending_user.user_id),
+            None,
+        )
+        .await;
+        Ok(pending_user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::UserBackendHandler, sql_backend_handler::tests::*};
+
+    fn make_request(user_id: &str, email: &str) -> CreatePendingUserRequest {
+        CreatePendingUserRequest {
+            user_id: UserId::new(user_id),
+            email: email.to_owned(),
+            display_name: Some("Display".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_users() {
+        let fixture = TestFixture::new().await;
+        // Taken by an existing user.
+        assert!(fixture
+            .handler
+            .create_pending_user(make_request("bob", "new@example.com"))
+            .await
+            .is_err());
+        fixture
+            .handler
+            .create_pending_user(make_request("alice", "alice@example.com"))
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .create_pending_user(make_request("eve", "eve@example.com"))
+            .await
+            .unwrap();
+        // Already pending.
+        assert!(fixture
+            .handler
+            .create_pending_user(make_request("alice2", "alice@example.com"))
+            .await
+            .is_err());
+        assert_eq!(
+            fixture
+                .handler
+                .list_pending_users()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|p| p.user_id)
+                .collect::<Vec<_>>(),
+            vec![UserId::new("alice"), UserId::new("eve")]
+        );
+
+        let alice = UserId::new("alice");
+        let pending_user = fixture.handler.approve_pending_user(&alice).await.unwrap();
+        assert_eq!(pending_user.email, "alice@example.com");
+        let user = fixture.handler.get_user_details(&alice).await.unwrap();
+        assert_eq!(user.email, "alice@example.com");
+        assert_eq!(user.display_name.as_deref(), Some("Display"));
+
+        let eve = UserId::new("eve");
+        fixture.handler.reject_pending_user(&eve).await.unwrap();
+        assert!(fixture.handler.get_user_details(&eve).await.is_err());
+        assert!(fixture.handler.approve_pending_user(&eve).await.is_err());
+        assert_eq!(fixture.handler.list_pending_users().await.unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,476 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, RecycleBinBackendHandler},
+    model::{
+        self, DeletedGroupsColumn, DeletedUsersColumn, GroupAttributesColumn, GroupColumn,
+        GroupMembershipColumn, MembershipColumn, UserAttributesColumn, UserEmailsColumn,
+    },
+    sql_backend_handler::SqlBackendHandler,
+    sql_group_backend_handler::get_reachable_groups,
+    types::{ChangeType, DeletedGroup, DeletedUser, GroupId, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{debug, instrument};
+
+/// What is kept of a deleted user to restore it. The sessions, tokens and security keys are not.
+#[derive(Serialize, Deserialize)]
+struct DeletedUserData {
+    user: model::users::Model,
+    memberships: Vec<model::memberships::Model>,
+    attributes: Vec<model::user_attributes::Model>,
+    /// Missing from the users deleted by the older versions.
+    #[serde(default)]
+    emails: Vec<model::user_emails::Model>,
+}
+
+/// What is kept of a deleted group to restore it.
+#[derive(Serialize, Deserialize)]
+struct DeletedGroupData {
+    group: model::groups::Model,
+    memberships: Vec<model::memberships::Model>,
+    /// Both the groups nested in this one and the groups it is nested in.
+    group_memberships: Vec<model::group_memberships::Model>,
+    attributes: Vec<model::group_attributes::Model>,
+}
+
+fn to_json<T: Serialize>(data: &T) -> Result<String> {
+    serde_json::to_string(data).map_err(|e| {
+        DomainError::InternalError(format!("Could not serialize the deleted entry: {}", e))
+    })
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> Result<T> {
+    serde_json::from_str(data).map_err(|e| {
+        DomainError::InternalError(format!("Could not deserialize the deleted entry: {}", e))
+    })
+}
+
+impl SqlBackendHandler {
+    /// Copies the user to the recycle bin, replacing an earlier deletion of the same user ID. The
+    /// caller then deletes the user.
+    pub(crate) async fn copy_user_to_recycle_bin<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+    ) -> Result<()> {
+        let user = model::User::find_by_id(user_id.clone())
+            .one(conn)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("No such user: '{}'", user_id)))?;
+        let memberships = model::Membership::find()
+            .filter(MembershipColumn::UserId.eq(user_id.clone()))
+            .all(conn)
+            .await?;
+        let attributes = model::UserAttributes::find()
+            .filter(UserAttributesColumn::UserId.eq(user_id.clone()))
+            .all(conn)
+            .await?;
+        let emails = model::UserEmails::find()
+            .filter(UserEmailsColumn::UserId.eq(user_id.clone()))
+            .all(conn)
+            .await?;
+        model::DeletedUsers::delete_by_id(user_id.clone())
+            .exec(conn)
+            .await?;
+        model::deleted_users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            email: ActiveValue::Set(user.email.clone()),
+            display_name: ActiveValue::Set(user.display_name.clone()),
+            deletion_date: ActiveValue::Set(chrono::Utc::now()),
+            data: ActiveValue::Set(to_json(&DeletedUserData {
+                user,
+                memberships,
+                attributes,
+                emails,
+            })?),
+        }
+        .insert(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Same as `copy_user_to_recycle_bin`, for groups.
+    pub(crate) async fn copy_group_to_recycle_bin<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        group_id: GroupId,
+    ) -> Result<()> {
+        let group = model::Group::find_by_id(group_id)
+            .one(conn)
+            .await?
+            .ok_or_else(|| {
+                DomainError::EntityNotFound(format!("No such group: '{:?}'", group_id))
+            })?;
+        let memberships = model::Membership::find()
+            .filter(MembershipColumn::GroupId.eq(group_id))
+            .all(conn)
+            .await?;
+        let group_memberships = model::GroupMembership::find()
+            .filter(
+                GroupMembershipColumn::ParentGroupId
+                    .eq(group_id)
+                    .or(GroupMembershipColumn::ChildGroupId.eq(group_id)),
+            )
+            .all(conn)
+            .await?;
+        let attributes = model::GroupAttributes::find()
+            .filter(GroupAttributesColumn::GroupId.eq(group_id))
+            .all(conn)
+            .await?;
+        model::DeletedGroups::delete_by_id(group_id)
+            .exec(conn)
+            .await?;
+        model::deleted_groups::ActiveModel {
+            group_id: ActiveValue::Set(group_id),
+            display_name: ActiveValue::Set(group.display_name.clone()),
+            deletion_date: ActiveValue::Set(chrono::Utc::now()),
+            data: ActiveValue::Set(to_json(&DeletedGroupData {
+                group,
+                memberships,
+                group_memberships,
+                attributes,
+            })?),
+        }
+        .insert(conn)
+        .await?;
+        Ok(())
+    }
+
+    // The attributes whose schema was deleted since can't be restored.
+    async fn get_user_attribute_names<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+    ) -> Result<HashSet<String>> {
+        Ok(model::UserAttributeSchema::find()
+            .all(conn)
+            .await?
+            .into_iter()
+            .map(|a| a.attribute_name)
+            .collect())
+    }
+
+    async fn get_group_attribute_names<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+    ) -> Result<HashSet<String>> {
+        Ok(model::GroupAttributeSchema::find()
+            .all(conn)
+            .await?
+            .into_iter()
+            .map(|a| a.attribute_name)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl RecycleBinBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_deleted_users(&self) -> Result<Vec<DeletedUser>> {
+        Ok(model::DeletedUsers::find()
+            .order_by_desc(DeletedUsersColumn::DeletionDate)
+            .all(self.read_pool())
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn restore_user(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        let transaction = self.sql_pool.begin().await?;
+        let deleted_user = model::DeletedUsers::find_by_id(user_id.clone())
+            .one(&transaction)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("No deleted user '{}'", user_id)))?;
+        let mut data: DeletedUserData = from_json(&deleted_user.data)?;
+        if model::User::find_by_id(user_id.clone())
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "User '{}' already exists",
+                user_id
+            )));
+        }
+        data.user.modified_date = chrono::Utc::now();
+        // Otherwise, the stale accounts job would delete it again.
+        data.user.stale_disabled_date = None;
+        model::User::insert(data.user.into_active_model())
+            .exec(&transaction)
+            .await?;
+        let attribute_names = self.get_user_attribute_names(&transaction).await?;
+        let attributes: Vec<_> = data
+            .attributes
+            .into_iter()
+            .filter(|a| attribute_names.contains(&a.attribute_name))
+            .map(IntoActiveModel::into_active_model)
+            .collect();
+        if !attributes.is_empty() {
+            model::UserAttributes::insert_many(attributes)
+                .exec(&transaction)
+                .await?;
+        }
+        // The addresses taken by another user since the deletion are not restored.
+        for email in data.emails {
+            if model::UserEmails::find_by_id(email.email.clone())
+                .one(&transaction)
+                .await?
+                .is_none()
+            {
+                email.into_active_model().insert(&transaction).await?;
+            }
+        }
+        self.log_user_change(&transaction, user_id, ChangeType::Add)
+            .await?;
+        for membership in data.memberships {
+            if model::Group::find_by_id(membership.group_id)
+                .one(&transaction)
+                .await?
+                .is_some()
+            {
+                self.insert_membership(
+                    &transaction,
+                    user_id,
+                    membership.group_id,
+                    membership.expiry_date,
+                )
+                .await?;
+            }
+        }
+        model::DeletedUsers::delete_by_id(user_id.clone())
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.audit("restore_user", AuditEvent::user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn purge_user(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        let res = model::DeletedUsers::delete_by_id(user_id.clone())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No deleted user '{}'",
+                user_id
+            )));
+        }
+        self.audit("purge_user", AuditEvent::user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_deleted_groups(&self) -> Result<Vec<DeletedGroup>> {
+        Ok(model::DeletedGroups::find()
+            .order_by_desc(DeletedGroupsColumn::DeletionDate)
+            .all(self.read_pool())
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn restore_group(&self, group_id: GroupId) -> Result<()> {
+        debug!(?group_id);
+        let transaction = self.sql_pool.begin().await?;
+        let deleted_group = model::DeletedGroups::find_by_id(group_id)
+            .one(&transaction)
+            .await?
+            .ok_or_else(|| {
+                DomainError::EntityNotFound(format!("No deleted group '{:?}'", group_id))
+            })?;
+        let mut data: DeletedGroupData = from_json(&deleted_group.data)?;
+        if model::Group::find()
+            .filter(
+                GroupColumn::GroupId
+                    .eq(group_id)
+                    .or(GroupColumn::DisplayName.eq(data.group.display_name.as_str())),
+            )
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "Group '{}' already exists",
+                data.group.display_name
+            )));
+        }
+        data.group.modified_date = chrono::Utc::now();
+        model::Group::insert(data.group.into_active_model())
+            .exec(&transaction)
+            .await?;
+        let attribute_names = self.get_group_attribute_names(&transaction).await?;
+        let attributes: Vec<_> = data
+            .attributes
+            .into_iter()
+            .filter(|a| attribute_names.contains(&a.attribute_name))
+            .map(IntoActiveModel::into_active_model)
+            .collect();
+        if !attributes.is_empty() {
+            model::GroupAttributes::insert_many(attributes)
+                .exec(&transaction)
+                .await?;
+        }
+        self.log_group_change(&transaction, group_id, ChangeType::Add)
+            .await?;
+        for membership in data.memberships {
+            if model::User::find_by_id(membership.user_id.clone())
+                .one(&transaction)
+                .await?
+                .is_some()
+            {
+                self.insert_membership(
+                    &transaction,
+                    &membership.user_id,
+                    group_id,
+                    membership.expiry_date,
+                )
+                .await?;
+            }
+        }
+        for membership in data.group_memberships {
+            let other_group = if membership.parent_group_id == group_id {
+                membership.child_group_id
+            } else {
+                membership.parent_group_id
+            };
+            if model::Group::find_by_id(other_group)
+                .one(&transaction)
+                .await?
+                .is_none()
+            {
+                continue;
+            }
+            // The groups could have been nested the other way around since the deletion.
+            let nesting = self.get_group_nesting(&transaction).await?;
+            if get_reachable_groups(&nesting, [membership.child_group_id])
+                .contains(&membership.parent_group_id)
+            {
+                continue;
+            }
+            self.log_group_change(&transaction, membership.parent_group_id, ChangeType::Modify)
+                .await?;
+            model::GroupMembership::insert(membership.into_active_model())
+                .exec(&transaction)
+                .await?;
+        }
+        model::DeletedGroups::delete_by_id(group_id)
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.audit("restore_group", AuditEvent::group_target(group_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn purge_group(&self, group_id: GroupId) -> Result<()> {
+        debug!(?group_id);
+        let res = model::DeletedGroups::delete_by_id(group_id)
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No deleted group '{:?}'",
+                group_id
+            )));
+        }
+        self.audit("purge_group", AuditEvent::group_target(group_id), None)
+            .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UserBackendHandler, UserRequestFilter},
+        sql_backend_handler::tests::*,
+    };
+
+    #[tokio::test]
+    async fn test_restore_user() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        fixture.handler.delete_user(&bob).await.unwrap();
+        assert!(fixture.handler.get_user_details(&bob).await.is_err());
+        let deleted_users = fixture.handler.list_deleted_users().await.unwrap();
+        assert_eq!(deleted_users.len(), 1);
+        assert_eq!(deleted_users[0].user_id, bob);
+
+        fixture.handler.restore_user(&bob).await.unwrap();
+        fixture.handler.get_user_details(&bob).await.unwrap();
+        assert_eq!(
+            fixture.handler.get_user_groups(&bob).await.unwrap().len(),
+            1
+        );
+        assert!(fixture
+            .handler
+            .list_deleted_users()
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(fixture.handler.restore_user(&bob).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_restore_user_id_taken() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        fixture.handler.delete_user(&bob).await.unwrap();
+        insert_user(&fixture.handler, "bob", "pass").await;
+        assert!(fixture.handler.restore_user(&bob).await.is_err());
+        fixture.handler.purge_user(&bob).await.unwrap();
+        assert!(fixture
+            .handler
+            .list_deleted_users()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_group() {
+        let fixture = TestFixture::new().await;
+        let group_id = fixture.groups[0];
+        let parent_id = insert_group(&fixture.handler, "Parent Group").await;
+        fixture
+            .handler
+            .add_group_to_group(parent_id, group_id)
+            .await
+            .unwrap();
+        fixture.handler.delete_group(group_id).await.unwrap();
+        assert!(fixture.handler.get_group_details(group_id).await.is_err());
+        assert_eq!(
+            fixture.handler.list_deleted_groups().await.unwrap()[0].group_id,
+            group_id
+        );
+
+        fixture.handler.restore_group(group_id).await.unwrap();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(parent_id))
+            )
+            .await,
+            vec!["bob", "patrick"]
+        );
+        assert!(fixture.handler.purge_group(group_id).await.is_err());
+    }
+}
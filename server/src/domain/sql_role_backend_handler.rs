@@ -0,0 +1,283 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, CreateRoleRequest, RoleBackendHandler, UserBackendHandler},
+    model::{self, GroupManagersColumn, RoleGroupsColumn, RolesColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{Capabilities, GroupId, Role, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use tracing::{debug, instrument};
+
+#[async_trait]
+impl RoleBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_roles(&self) -> Result<Vec<Role>> {
+        Ok(model::Roles::find()
+            .order_by_asc(RolesColumn::RoleId)
+            .find_with_related(model::RoleGroups)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|(role, groups)| Role {
+                role_id: role.role_id,
+                display_name: role.display_name,
+                read_all: role.read_all,
+                reset_passwords: role.reset_passwords,
+                managed_group_id: role.managed_group_id,
+                group_ids: groups.into_iter().map(|g| g.group_id).collect(),
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_role(&self, request: CreateRoleRequest) -> Result<i32> {
+        debug!(?request);
+        if request.display_name.is_empty() {
+            return Err(DomainError::InvalidRequest(
+                "The role name cannot be empty".to_owned(),
+            ));
+        }
+        let role = model::roles::ActiveModel {
+            display_name: ActiveValue::Set(request.display_name),
+            read_all: ActiveValue::Set(request.read_all),
+            reset_passwords: ActiveValue::Set(request.reset_passwords),
+            managed_group_id: ActiveValue::Set(request.managed_group_id),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit(
+            "create_role",
+            format!("role:{}", role.role_id),
+            Some(role.display_name),
+        )
+        .await;
+        Ok(role.role_id)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_role(&self, role_id: i32) -> Result<()> {
+        debug!(?role_id);
+        let res = model::Roles::delete_by_id(role_id)
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such role: '{}'",
+                role_id
+            )));
+        }
+        self.audit("delete_role", format!("role:{}", role_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_role_to_group(&self, role_id: i32, group_id: GroupId) -> Result<()> {
+        debug!(?role_id, ?group_id);
+        model::role_groups::ActiveModel {
+            role_id: ActiveValue::Set(role_id),
+            group_id: ActiveValue::Set(group_id),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit(
+            "add_role_to_group",
+            format!("role:{}", role_id),
+            Some(AuditEvent::group_target(group_id)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_role_from_group(&self, role_id: i32, group_id: GroupId) -> Result<()> {
+        debug!(?role_id, ?group_id);
+        let res = model::RoleGroups::delete_by_id((role_id, group_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "The role '{}' is not granted to the group '{}'",
+                role_id, group_id.0
+            )));
+        }
+        self.audit(
+            "remove_role_from_group",
+            format!("role:{}", role_id),
+            Some(AuditEvent::group_target(group_id)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_user_capabilities(&self, user_id: &UserId) -> Result<Capabilities> {
+        debug!(?user_id);
+        let group_ids: Vec<GroupId> = self
+            .get_user_groups_from_primary(user_id)
+            .await?
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect();
+        let mut capabilities = Capabilities {
+            managed_memberships: model::GroupManagers::find()
+                .filter(GroupManagersColumn::UserId.eq(user_id.clone()))
+                .order_by_asc(GroupManagersColumn::GroupId)
+                .all(&self.sql_pool)
+                .await?
+                .into_iter()
+                .map(|m| m.group_id)
+                .collect(),
+            ..Default::default()
+        };
+        if group_ids.is_empty() {
+            return Ok(capabilities);
+        }
+        for role in model::Roles::find()
+            .inner_join(model::RoleGroups)
+            .filter(RoleGroupsColumn::GroupId.is_in(group_ids))
+            .distinct()
+            .all(&self.sql_pool)
+            .await?
+        {
+            capabilities.merge(Capabilities {
+                read_all: role.read_all,
+                reset_passwords: role.reset_passwords,
+                manage_users: false,
+                managed_groups: role.managed_group_id.into_iter().collect(),
+                managed_memberships: Vec::new(),
+            });
+        }
+        Ok(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::GroupBackendHandler, sql_backend_handler::tests::*};
+
+    #[tokio::test]
+    async fn test_roles() {
+        let fixture = TestFixture::new().await;
+        let helpdesk = fixture
+            .handler
+            .create_role(CreateRoleRequest {
+                display_name: "helpdesk".to_owned(),
+                reset_passwords: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let manager = fixture
+            .handler
+            .create_role(CreateRoleRequest {
+                display_name: "manager".to_owned(),
+                read_all: true,
+                managed_group_id: Some(fixture.groups[2]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // The names are unique.
+        assert!(fixture
+            .handler
+            .create_role(CreateRoleRequest {
+                display_name: "manager".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .is_err());
+
+        // bob is in groups[0], patrick in groups[0] and groups[1].
+        fixture
+            .handler
+            .add_role_to_group(helpdesk, fixture.groups[0])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_role_to_group(manager, fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_capabilities(&UserId::new("bob"))
+                .await
+                .unwrap(),
+            Capabilities {
+                reset_passwords: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_capabilities(&UserId::new("patrick"))
+                .await
+                .unwrap(),
+            Capabilities {
+                read_all: true,
+                reset_passwords: true,
+                manage_users: false,
+                managed_groups: vec![fixture.groups[2]],
+                managed_memberships: vec![],
+            }
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_capabilities(&UserId::new("NoGroup"))
+                .await
+                .unwrap(),
+            Capabilities::default()
+        );
+
+        let roles = fixture.handler.list_roles().await.unwrap();
+        assert_eq!(roles.len(), 2);
+        assert_eq!(roles[1].display_name, "manager");
+        assert_eq!(roles[1].group_ids, vec![fixture.groups[1]]);
+
+        fixture
+            .handler
+            .remove_role_from_group(manager, fixture.groups[1])
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .remove_role_from_group(manager, fixture.groups[1])
+            .await
+            .is_err());
+        // Deleting the managed group keeps the role, without the group.
+        fixture
+            .handler
+            .delete_group(fixture.groups[2])
+            .await
+            .unwrap();
+        fixture.handler.delete_role(helpdesk).await.unwrap();
+        assert_eq!(
+            fixture.handler.list_roles().await.unwrap(),
+            vec![Role {
+                role_id: manager,
+                display_name: "manager".to_owned(),
+                read_all: true,
+                reset_passwords: false,
+                managed_group_id: None,
+                group_ids: vec![],
+            }]
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_capabilities(&UserId::new("bob"))
+                .await
+                .unwrap(),
+            Capabilities::default()
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::SchemaBackendHandler,
+    ldap::utils::{map_group_field, map_user_field},
+    model::{self, GroupAttributeSchemaColumn, UserAttributeSchemaColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{AttributeSchema, AttributeType, AttributeValue, JpegPhoto},
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, QueryOrder};
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+// Attributes that are handled specially and cannot be redefined.
+const RESERVED_USER_ATTRIBUTE_NAMES: &[&str] = &[
+    "objectclass",
+    "dn",
+    "distinguishedname",
+    "memberof",
+    "jpegphoto",
+    "sshpublickey",
+    "othermailbox",
+    "mailalias",
+];
+
+const RESERVED_GROUP_ATTRIBUTE_NAMES: &[&str] = &[
+    "objectclass",
+    "dn",
+    "distinguishedname",
+    "member",
+    "uniquemember",
+    "memberuid",
+];
+
+fn validate_attribute_name(name: &str, is_reserved: impl Fn(&str) -> bool) -> Result<()> {
+    if name.is_empty()
+        || !name.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(DomainError::InvalidRequest(format!(
+            "Invalid attribute name: '{}'",
+            name
+        )));
+    }
+    if is_reserved(name) {
+        return Err(DomainError::InvalidRequest(format!(
+            "Attribute name is reserved: '{}'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn validate_attribute_value(schema: &AttributeSchema, value: &[String]) -> Result<()> {
+    let invalid = |reason: String| {
+        Err(DomainError::InvalidRequest(format!(
+            "Invalid value for attribute '{}': {}",
+            schema.name, reason
+        )))
+    };
+    if !schema.is_list && value.len() != 1 {
+        return invalid("expected a single value".to_owned());
+    }
+    for v in value {
+        match schema.attribute_type {
+            AttributeType::String => {}
+            AttributeType::Integer => {
+                if let Err(e) = v.parse::<i64>() {
+                    return invalid(e.to_string());
+                }
+            }
+            AttributeType::DateTime => {
+                if let Err(e) = chrono::DateTime::parse_from_rfc3339(v) {
+                    return invalid(e.to_string());
+                }
+            }
+            AttributeType::JpegPhoto => {
+                if let Err(e) = JpegPhoto::try_from(v.clone()) {
+                    return invalid(e.to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn normalize_attribute_names(attributes: Vec<AttributeValue>) -> Vec<AttributeValue> {
+    attributes
+        .into_iter()
+        .map(|a| AttributeValue {
+            name: a.name.to_ascii_lowercase(),
+            value: a.value,
+        })
+        .collect()
+}
+
+/// Attribute values are stored as a JSON list of strings.
+pub(crate) fn serialize_attribute_value(value: &[String]) -> String {
+    serde_json::to_string(value).expect("A list of strings is always serializable")
+}
+
+pub(crate) fn deserialize_attribute_value(name: &str, value: &str) -> Result<Vec<String>> {
+    serde_json::from_str(value).map_err(|e| {
+        DomainError::InternalError(format!(
+            "Invalid stored value for attribute '{}': {}",
+            name, e
+        ))
+    })
+}
+
+// Checks that the attributes are defined in the schema, and that their values match the
+// attribute definitions.
+fn validate_attributes(schema: Vec<AttributeSchema>, attributes: &[AttributeValue]) -> Result<()> {
+    let schema: HashMap<String, AttributeSchema> =
+        schema.into_iter().map(|s| (s.name.clone(), s)).collect();
+    for attribute in attributes {
+        let attribute_schema = schema.get(&attribute.name).ok_or_else(|| {
+            DomainError::InvalidRequest(format!("Unknown attribute: '{}'", attribute.name))
+        })?;
+        validate_attribute_value(attribute_schema, &attribute.value)?;
+    }
+    Ok(())
+}
+
+impl SqlBackendHandler {
+    pub(crate) async fn validate_user_attributes(
+        &self,
+        attributes: &[AttributeValue],
+    ) -> Result<()> {
+        if attributes.is_empty() {
+            return Ok(());
+        }
+        validate_attributes(self.get_user_attributes_schema().await?, attributes)
+    }
+
+    pub(crate) async fn validate_group_attributes(
+        &self,
+        attributes: &[AttributeValue],
+    ) -> Result<()> {
+        if attributes.is_empty() {
+            return Ok(());
+        }
+        validate_attributes(self.get_group_attributes_schema().await?, attributes)
+    }
+}
+
+#[async_trait]
+impl SchemaBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_user_attributes_schema(&self) -> Result<Vec<AttributeSchema>> {
+        Ok(model::UserAttributeSchema::find()
+            .order_by_asc(UserAttributeSchemaColumn::AttributeName)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(AttributeSchema::from)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()> {
+        debug!(?schema);
+        let name = schema.name.to_ascii_lowercase();
+        validate_attribute_name(&name, |name| {
+            RESERVED_USER_ATTRIBUTE_NAMES.contains(&name) || map_user_field(name).is_some()
+        })?;
+        let new_attribute = model::user_attribute_schema::ActiveModel {
+            attribute_name: ActiveValue::Set(name.clone()),
+            attribute_type: ActiveValue::Set(schema.attribute_type),
+            is_list: ActiveValue::Set(schema.is_list),
+            is_visible: ActiveValue::Set(schema.is_visible),
+        };
+        new_attribute.insert(&self.sql_pool).await?;
+        self.audit(
+            "add_user_attribute",
+            format!("user_attribute:{}", name),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_user_attribute(&self, name: &str) -> Result<()> {
+        debug!(?name);
+        let res = model::UserAttributeSchema::delete_by_id(name.to_ascii_lowercase())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such attribute: '{}'",
+                name
+            )));
+        }
+        // The values of the attribute are deleted along with it.
+        self.invalidate_cache();
+        self.audit(
+            "delete_user_attribute",
+            format!("user_attribute:{}", name.to_ascii_lowercase()),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_group_attributes_schema(&self) -> Result<Vec<AttributeSchema>> {
+        Ok(model::GroupAttributeSchema::find()
+            .order_by_asc(GroupAttributeSchemaColumn::AttributeName)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(AttributeSchema::from)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()> {
+        debug!(?schema);
+        let name = schema.name.to_ascii_lowercase();
+        validate_attribute_name(&name, |name| {
+            RESERVED_GROUP_ATTRIBUTE_NAMES.contains(&name) || map_group_field(name).is_some()
+        })?;
+        let new_attribute = model::group_attribute_schema::ActiveModel {
+            attribute_name: ActiveValue::Set(name.clone()),
+            attribute_type: ActiveValue::Set(schema.attribute_type),
+            is_list: ActiveValue::Set(schema.is_list),
+            is_visible: ActiveValue::Set(schema.is_visible),
+        };
+        new_attribute.insert(&self.sql_pool).await?;
+        self.audit(
+            "add_group_attribute",
+            format!("group_attribute:{}", name),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_group_attribute(&self, name: &str) -> Result<()> {
+        debug!(?name);
+        let res = model::GroupAttributeSchema::delete_by_id(name.to_ascii_lowercase())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such attribute: '{}'",
+                name
+            )));
+        }
+        // The values of the attribute are deleted along with it.
+        self.invalidate_cache();
+        self.audit(
+            "delete_group_attribute",
+            format!("group_attribute:{}", name.to_ascii_lowercase()),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{
+            GroupBackendHandler, GroupRequestFilter, UpdateGroupRequest, UpdateUserRequest,
+            UserBackendHandler,
+        },
+        sql_backend_handler::tests::*,
+        types::UserId,
+    };
+
+    fn department_attribute() -> AttributeSchema {
+        AttributeSchema {
+            name: "department".to_owned(),
+            attribute_type: AttributeType::String,
+            is_list: false,
+            is_visible: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_delete_attribute() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .add_user_attribute(AttributeSchema {
+                name: "Department".to_owned(),
+                ..department_attribute()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture.handler.get_user_attributes_schema().await.unwrap(),
+            vec![department_attribute()]
+        );
+        fixture
+            .handler
+            .delete_user_attribute("department")
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .get_user_attributes_schema()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reserved_attribute_name() {
+        let fixture = TestFixture::new().await;
+        for name in ["mail", "memberOf", "uid number"] {
+            assert!(matches!(
+                fixture
+                    .handler
+                    .add_user_attribute(AttributeSchema {
+                        name: name.to_owned(),
+                        ..department_attribute()
+                    })
+                    .await,
+                Err(DomainError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_attribute_values() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .add_user_attribute(department_attribute())
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_user_attribute(AttributeSchema {
+                name: "employeenumber".to_owned(),
+                attribute_type: AttributeType::Integer,
+                is_list: false,
+                is_visible: false,
+            })
+            .await
+            .unwrap();
+        let bob = UserId::new("bob");
+        assert!(matches!(
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: bob.clone(),
+                    insert_attributes: vec![AttributeValue {
+                        name: "employeenumber".to_owned(),
+                        value: vec!["abc".to_owned()],
+                    }],
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                insert_attributes: vec![
+                    AttributeValue {
+                        name: "department".to_owned(),
+                        value: vec!["R&D".to_owned()],
+                    },
+                    AttributeValue {
+                        name: "employeenumber".to_owned(),
+                        value: vec!["42".to_owned()],
+                    },
+                ],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                delete_attributes: vec!["employeenumber".to_owned()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_details(&bob)
+                .await
+                .unwrap()
+                .attributes,
+            vec![AttributeValue {
+                name: "department".to_owned(),
+                value: vec!["R&D".to_owned()],
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_attribute_values() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .add_group_attribute(AttributeSchema {
+                name: "mailAlias".to_owned(),
+                attribute_type: AttributeType::String,
+                is_list: true,
+                is_visible: true,
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .add_group_attribute(AttributeSchema {
+                    name: "gidNumber".to_owned(),
+                    ..department_attribute()
+                })
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        let mail_aliases = AttributeValue {
+            name: "mailalias".to_owned(),
+            value: vec!["best@example.com".to_owned(), "top@example.com".to_owned()],
+        };
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                insert_attributes: vec![mail_aliases.clone()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let groups = fixture
+            .handler
+            .list_groups(Some(GroupRequestFilter::GroupId(fixture.groups[0])))
+            .await
+            .unwrap();
+        assert_eq!(groups[0].attributes, vec![mail_aliases]);
+        assert_eq!(groups[0].display_name, "Best Group");
+    }
+}
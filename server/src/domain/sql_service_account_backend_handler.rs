@@ -0,0 +1,280 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{
+        CreateServiceAccountRequest, ServiceAccountBackendHandler, UpdateServiceAccountRequest,
+    },
+    model::{self, ServiceAccountsColumn},
+    sql_api_token_backend_handler::hash_token,
+    sql_backend_handler::SqlBackendHandler,
+    types::ServiceAccount,
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, QueryOrder};
+use tracing::{debug, instrument};
+
+// Generated, so that it is as strong as an API token and can be hashed the same way.
+fn generate_password() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+// The ID ends up in the DN of the account, which is compared in lowercase.
+fn normalize_service_id(service_id: &str) -> Result<String> {
+    let service_id = service_id.to_lowercase();
+    if service_id.is_empty()
+        || !service_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        return Err(DomainError::InvalidRequest(format!(
+            "Invalid service account ID '{}': only letters, digits, '-', '_' and '.' are allowed",
+            service_id
+        )));
+    }
+    Ok(service_id)
+}
+
+fn not_found(service_id: &str) -> DomainError {
+    DomainError::EntityNotFound(format!("No such service account: '{}'", service_id))
+}
+
+impl SqlBackendHandler {
+    async fn get_service_account_model(
+        &self,
+        service_id: &str,
+    ) -> Result<model::service_accounts::Model> {
+        model::ServiceAccounts::find_by_id(service_id.to_lowercase())
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| not_found(service_id))
+    }
+}
+
+#[async_trait]
+impl ServiceAccountBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_service_account(
+        &self,
+        request: CreateServiceAccountRequest,
+    ) -> Result<(ServiceAccount, String)> {
+        debug!(service_id = ?request.service_id, read_all = ?request.read_all);
+        let service_id = normalize_service_id(&request.service_id)?;
+        if model::ServiceAccounts::find_by_id(service_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "The service account '{}' already exists",
+                service_id
+            )));
+        }
+        let password = generate_password();
+        let account = model::service_accounts::ActiveModel {
+            service_id: ActiveValue::Set(service_id),
+            description: ActiveValue::Set(request.description.filter(|d| !d.is_empty())),
+            read_all: ActiveValue::Set(request.read_all),
+            password_hash: ActiveValue::Set(hash_token(&password)),
+            creation_date: ActiveValue::Set(chrono::Utc::now()),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        self.audit(
+            "create_service_account",
+            format!("service_account:{}", account.service_id),
+            account.read_all.then(|| "read_all".to_owned()),
+        )
+        .await;
+        Ok((account.into(), password))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_service_accounts(&self) -> Result<Vec<ServiceAccount>> {
+        Ok(model::ServiceAccounts::find()
+            .order_by_asc(ServiceAccountsColumn::ServiceId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn update_service_account(&self, request: UpdateServiceAccountRequest) -> Result<()> {
+        debug!(?request);
+        let mut account: model::service_accounts::ActiveModel = self
+            .get_service_account_model(&request.service_id)
+            .await?
+            .into();
+        if let Some(description) = request.description {
+            account.description = ActiveValue::Set(Some(description).filter(|d| !d.is_empty()));
+        }
+        if let Some(read_all) = request.read_all {
+            account.read_all = ActiveValue::Set(read_all);
+        }
+        let account = account.update(&self.sql_pool).await?;
+        self.audit(
+            "update_service_account",
+            format!("service_account:{}", account.service_id),
+            request
+                .read_all
+                .map(|read_all| format!("read_all: {}", read_all)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn reset_service_account_password(&self, service_id: &str) -> Result<String> {
+        debug!(?service_id);
+        let mut account: model::service_accounts::ActiveModel =
+            self.get_service_account_model(service_id).await?.into();
+        let password = generate_password();
+        account.password_hash = ActiveValue::Set(hash_token(&password));
+        let account = account.update(&self.sql_pool).await?;
+        self.audit(
+            "reset_service_account_password",
+            format!("service_account:{}", account.service_id),
+            None,
+        )
+        .await;
+        Ok(password)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_service_account(&self, service_id: &str) -> Result<()> {
+        debug!(?service_id);
+        let res = model::ServiceAccounts::delete_by_id(service_id.to_lowercase())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(not_found(service_id));
+        }
+        self.audit(
+            "delete_service_account",
+            format!("service_account:{}", service_id.to_lowercase()),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn bind_service_account(
+        &self,
+        service_id: &str,
+        password: &str,
+    ) -> Result<ServiceAccount> {
+        debug!(?service_id);
+        let invalid = || DomainError::AuthenticationError("Invalid service account".to_owned());
+        let account = self
+            .get_service_account_model(service_id)
+            .await
+            .map_err(|_| invalid())?;
+        if account.password_hash != hash_token(password) {
+            return Err(invalid());
+        }
+        Ok(account.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::UserBackendHandler, sql_backend_handler::tests::*};
+
+    #[tokio::test]
+    async fn test_service_accounts() {
+        let fixture = TestFixture::new().await;
+        let (account, password) = fixture
+            .handler
+            .create_service_account(CreateServiceAccountRequest {
+                service_id: "Nextcloud".to_owned(),
+                description: Some("Nextcloud LDAP backend".to_owned()),
+                read_all: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(account.service_id, "nextcloud");
+        assert!(account.read_all);
+        assert_eq!(
+            fixture
+                .handler
+                .bind_service_account("nextcloud", &password)
+                .await
+                .unwrap(),
+            account
+        );
+        assert!(fixture
+            .handler
+            .bind_service_account("nextcloud", "wrong")
+            .await
+            .is_err());
+        // The service accounts are not users.
+        assert!(fixture
+            .handler
+            .list_users(None, false)
+            .await
+            .unwrap()
+            .iter()
+            .all(|u| u.user.user_id.as_str() != "nextcloud"));
+        for service_id in ["nextcloud", "", "next cloud"] {
+            assert!(fixture
+                .handler
+                .create_service_account(CreateServiceAccountRequest {
+                    service_id: service_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .is_err());
+        }
+
+        fixture
+            .handler
+            .update_service_account(UpdateServiceAccountRequest {
+                service_id: "nextcloud".to_owned(),
+                description: Some(String::new()),
+                read_all: Some(false),
+            })
+            .await
+            .unwrap();
+        let new_password = fixture
+            .handler
+            .reset_service_account_password("nextcloud")
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .bind_service_account("nextcloud", &password)
+            .await
+            .is_err());
+        let account = fixture
+            .handler
+            .bind_service_account("nextcloud", &new_password)
+            .await
+            .unwrap();
+        assert_eq!(account.description, None);
+        assert!(!account.read_all);
+
+        fixture
+            .handler
+            .delete_service_account("nextcloud")
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .list_service_accounts()
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(fixture
+            .handler
+            .delete_service_account("nextcloud")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, SessionBackendHandler},
+    model::{self, JwtRefreshStorageColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{Session, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use std::cmp::Reverse;
+use tracing::{debug, instrument};
+
+#[async_trait]
+impl SessionBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>> {
+        debug!(?user_id);
+        let mut sessions: Vec<Session> = model::JwtRefreshStorage::find()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .filter(JwtRefreshStorageColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        // Sorted here rather than in SQL: the databases don't agree on where the NULLs go.
+        sessions.sort_by_key(|s| Reverse(s.last_used.or(s.creation_date)));
+        Ok(sessions)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_user_session(&self, user_id: &UserId, session_id: i64) -> Result<()> {
+        debug!(?user_id, ?session_id);
+        let res = model::JwtRefreshStorage::delete_many()
+            .filter(JwtRefreshStorageColumn::RefreshTokenHash.eq(session_id))
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such session for user '{}'",
+                user_id
+            )));
+        }
+        self.audit("revoke_session", AuditEvent::user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_all_user_sessions(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        model::JwtRefreshStorage::delete_many()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        self.audit(
+            "revoke_all_sessions",
+            AuditEvent::user_target(user_id),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::sql_backend_handler::tests::*, infra::jwt_sql_tables};
+    use sea_orm::{ActiveModelTrait, IntoActiveModel};
+
+    async fn insert_session(
+        handler: &SqlBackendHandler,
+        session_id: i64,
+        user: &str,
+        last_used_days_ago: Option<i64>,
+        expires_in_days: i64,
+    ) {
+        let now = chrono::Utc::now();
+        model::jwt_refresh_storage::Model {
+            refresh_token_hash: session_id,
+            user_id: UserId::new(user),
+            expiry_date: now + chrono::Duration::days(expires_in_days),
+            creation_date: Some(now - chrono::Duration::days(10)),
+            last_used: last_used_days_ago.map(|days| now - chrono::Duration::days(days)),
+            user_agent: Some("Firefox".to_owned()),
+            ip_address: None,
+        }
+        .into_active_model()
+        .insert(&handler.sql_pool)
+        .await
+        .unwrap();
+    }
+
+    fn session_ids(sessions: Vec<Session>) -> Vec<i64> {
+        sessions.into_iter().map(|s| s.session_id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_user_sessions() {
+        let fixture = TestFixture::new().await;
+        jwt_sql_tables::init_table(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        insert_session(&fixture.handler, 1, "bob", None, 30).await;
+        insert_session(&fixture.handler, 2, "bob", Some(1), 30).await;
+        // Expired.
+        insert_session(&fixture.handler, 3, "bob", Some(2), -1).await;
+        insert_session(&fixture.handler, 4, "patrick", None, 30).await;
+        let bob = UserId::new("bob");
+
+        assert_eq!(
+            session_ids(fixture.handler.list_user_sessions(&bob).await.unwrap()),
+            vec![2, 1]
+        );
+
+        // Patrick's session cannot be deleted as Bob.
+        assert!(fixture.handler.delete_user_session(&bob, 4).await.is_err());
+        fixture.handler.delete_user_session(&bob, 2).await.unwrap();
+        assert_eq!(
+            session_ids(fixture.handler.list_user_sessions(&bob).await.unwrap()),
+            vec![1]
+        );
+
+        fixture
+            .handler
+            .delete_all_user_sessions(&bob)
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .list_user_sessions(&bob)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            session_ids(
+                fixture
+                    .handler
+                    .list_user_sessions(&UserId::new("patrick"))
+                    .await
+                    .unwrap()
+            ),
+            vec![4]
+        );
+    }
+}
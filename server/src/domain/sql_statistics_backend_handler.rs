@@ -0,0 +1,198 @@
+use crate::domain::{
+    error::Result,
+    handler::StatisticsBackendHandler,
+    model::{self, GroupColumn, MembershipColumn, UserColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{DateTime, DirectoryStatistics, GroupId, GroupMemberCount, UserId, UserLastLogin},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::{Cond, Expr, Query},
+    ColumnTrait, EntityTrait, FromQueryResult, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use std::collections::HashMap;
+use tracing::instrument;
+
+#[derive(FromQueryResult)]
+struct GroupIdAndName {
+    group_id: GroupId,
+    display_name: String,
+}
+
+#[derive(FromQueryResult)]
+struct MemberCount {
+    group_id: GroupId,
+    member_count: i64,
+}
+
+#[derive(FromQueryResult)]
+struct LoginDates {
+    user_id: UserId,
+    last_ldap_login: Option<DateTime>,
+    last_web_login: Option<DateTime>,
+}
+
+impl SqlBackendHandler {
+    async fn get_members_per_group(&self) -> Result<Vec<GroupMemberCount>> {
+        let counts = model::Membership::find()
+            .select_only()
+            .column(MembershipColumn::GroupId)
+            .column_as(Expr::col(MembershipColumn::UserId).count(), "member_count")
+            .group_by(MembershipColumn::GroupId)
+            .into_model::<MemberCount>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|c| (c.group_id, c.member_count))
+            .collect::<HashMap<_, _>>();
+        Ok(model::Group::find()
+            .select_only()
+            .column(GroupColumn::GroupId)
+            .column(GroupColumn::DisplayName)
+            .order_by_asc(GroupColumn::GroupId)
+            .into_model::<GroupIdAndName>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|g| GroupMemberCount {
+                member_count: counts.get(&g.group_id).copied().unwrap_or(0) as u64,
+                group_id: g.group_id,
+                display_name: g.display_name,
+            })
+            .collect())
+    }
+
+    async fn get_last_logins(&self) -> Result<Vec<UserLastLogin>> {
+        let mut last_logins = model::User::find()
+            .select_only()
+            .column(UserColumn::UserId)
+            .column(UserColumn::LastLdapLogin)
+            .column(UserColumn::LastWebLogin)
+            .filter(
+                Cond::any()
+                    .add(UserColumn::LastLdapLogin.is_not_null())
+                    .add(UserColumn::LastWebLogin.is_not_null()),
+            )
+            .into_model::<LoginDates>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .filter_map(|u| {
+                Some(UserLastLogin {
+                    last_login: u.last_ldap_login.max(u.last_web_login)?,
+                    user_id: u.user_id,
+                })
+            })
+            .collect::<Vec<_>>();
+        last_logins.sort_by(|a, b| {
+            b.last_login
+                .cmp(&a.last_login)
+                .then_with(|| a.user_id.as_str().cmp(b.user_id.as_str()))
+        });
+        Ok(last_logins)
+    }
+}
+
+#[async_trait]
+impl StatisticsBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_statistics(&self) -> Result<DirectoryStatistics> {
+        let user_count = model::User::find().count(&self.sql_pool).await? as u64;
+        let group_count = model::Group::find().count(&self.sql_pool).await? as u64;
+        let users_without_password = model::User::find()
+            .filter(UserColumn::PasswordHash.is_null())
+            .filter(
+                UserColumn::UserId.not_in_subquery(
+                    Query::select()
+                        .column(model::LegacyPasswordHashesColumn::UserId)
+                        .from(model::LegacyPasswordHashes)
+                        .to_owned(),
+                ),
+            )
+            .count(&self.sql_pool)
+            .await? as u64;
+        let disabled_users = model::User::find()
+            .filter(UserColumn::Enabled.eq(false))
+            .count(&self.sql_pool)
+            .await? as u64;
+        Ok(DirectoryStatistics {
+            user_count,
+            group_count,
+            members_per_group: self.get_members_per_group().await?,
+            users_without_password,
+            disabled_users,
+            last_logins: self.get_last_logins().await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{CreateUserRequest, LoginKind, UpdateUserRequest, UserBackendHandler},
+        sql_backend_handler::tests::*,
+    };
+
+    #[tokio::test]
+    async fn test_get_statistics() {
+        let fixture = TestFixture::new().await;
+        insert_user(&fixture.handler, "alice", "password").await;
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("carol"),
+                email: "carol@example.com".to_owned(),
+                legacy_password_hash: Some("{SSHA}abcdef".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("john"),
+                enabled: Some(false),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let before_login = chrono::Utc::now();
+        for (user, kind) in [
+            ("patrick", LoginKind::Web),
+            ("bob", LoginKind::Ldap),
+            ("bob", LoginKind::Web),
+        ] {
+            fixture
+                .handler
+                .record_login(&UserId::new(user), kind)
+                .await
+                .unwrap();
+        }
+
+        let stats = fixture.handler.get_statistics().await.unwrap();
+        assert_eq!(stats.user_count, 6);
+        assert_eq!(stats.group_count, 3);
+        assert_eq!(
+            stats
+                .members_per_group
+                .iter()
+                .map(|g| (g.display_name.as_str(), g.member_count))
+                .collect::<Vec<_>>(),
+            vec![("Best Group", 2), ("Worst Group", 2), ("Empty Group", 0)]
+        );
+        // Alice has a password, Carol an imported hash.
+        assert_eq!(stats.users_without_password, 4);
+        assert_eq!(stats.disabled_users, 1);
+        assert_eq!(
+            stats
+                .last_logins
+                .iter()
+                .map(|l| l.user_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bob", "patrick"]
+        );
+        assert!(stats.last_logins[1].last_login >= before_login);
+    }
+}
@@ -3,7 +3,7 @@ use sea_orm::Value;
 
 pub type DbConnection = sea_orm::DatabaseConnection;
 
-#[derive(Copy, PartialEq, Eq, Debug, Clone)]
+#[derive(Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub struct SchemaVersion(pub u8);
 
 impl sea_orm::TryGetable for SchemaVersion {
@@ -180,11 +180,164 @@ mod tests {
             .unwrap()
             .unwrap(),
             sql_migrations::JustSchemaVersion {
-                version: SchemaVersion(1)
+                version: sql_migrations::LAST_SCHEMA_VERSION
             }
         );
     }
 
+    #[tokio::test]
+    async fn test_migrate_mixed_case_user_ids() {
+        let sql_pool = get_in_memory_db().await;
+        sql_pool
+            .execute(raw_statement(
+                r#"CREATE TABLE users ( user_id TEXT , creation_date TEXT);"#,
+            ))
+            .await
+            .unwrap();
+        sql_pool
+            .execute(raw_statement(
+                r#"INSERT INTO users (user_id, creation_date)
+                       VALUES ("Bob", "1970-01-01 00:00:00"), ("bob", "1971-01-01 00:00:00"),
+                              ("BOB", "1972-01-01 00:00:00"), ("Alice", "1970-01-01 00:00:00")"#,
+            ))
+            .await
+            .unwrap();
+        init_table(&sql_pool).await.unwrap();
+        #[derive(FromQueryResult, PartialEq, Eq, Debug)]
+        struct JustUserId {
+            user_id: String,
+        }
+        assert_eq!(
+            JustUserId::find_by_statement(raw_statement(
+                r#"SELECT user_id FROM users ORDER BY user_id"#
+            ))
+            .all(&sql_pool)
+            .await
+            .unwrap(),
+            ["alice", "bob", "bob-2", "bob-3"]
+                .map(|user_id| JustUserId {
+                    user_id: user_id.to_owned()
+                })
+                .to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_mixed_case_user_id_references() {
+        let sql_pool = get_in_memory_db().await;
+        init_table(&sql_pool).await.unwrap();
+        crate::infra::jwt_sql_tables::init_table(&sql_pool)
+            .await
+            .unwrap();
+        // "Bob" clashes with the older "bob", and has a row in every table referencing users.
+        for statement in [
+            r#"INSERT INTO users (user_id, email, display_name, creation_date, uuid)
+                   VALUES ("bob", "bob@example.com", "", "1970-01-01 00:00:00", "1"),
+                          ("Bob", "bob2@example.com", "", "1971-01-01 00:00:00", "2")"#,
+            r#"INSERT INTO groups (group_id, display_name, creation_date, uuid)
+                   VALUES (100, "Best Group", "1970-01-01 00:00:00", "3")"#,
+            r#"INSERT INTO memberships (user_id, group_id) VALUES ("bob", 100), ("Bob", 100)"#,
+            r#"INSERT INTO group_managers (group_id, user_id) VALUES (100, "Bob")"#,
+            r#"INSERT INTO user_attribute_schema (attribute_name, attribute_type, is_list, is_visible)
+                   VALUES ("nickname", "String", 0, 1)"#,
+            r#"INSERT INTO user_attributes (user_id, attribute_name, value)
+                   VALUES ("Bob", "nickname", "Bobby")"#,
+            r#"INSERT INTO password_history (user_id, password_hash, creation_date)
+                   VALUES ("Bob", "hash", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO failed_logins (user_id, failed_attempts, last_failure)
+                   VALUES ("Bob", 1, "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO webauthn_credentials (credential_id, user_id, display_name, passkey, creation_date)
+                   VALUES ("credential", "Bob", "Key", "{}", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO oidc_clients (client_id, client_secret, display_name, redirect_uris, creation_date)
+                   VALUES ("client", "secret", "Client", "[]", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO oidc_authorization_codes (code, client_id, user_id, redirect_uri, scope, expiry_date)
+                   VALUES ("code", "client", "Bob", "https://example.com", "openid", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO legacy_password_hashes (user_id, password_hash) VALUES ("Bob", "hash")"#,
+            r#"INSERT INTO remote_avatars (user_id, email_hash, fetch_date)
+                   VALUES ("Bob", "hash", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO user_emails (email, user_id) VALUES ("bobby@example.com", "Bob")"#,
+            r#"INSERT INTO jwt_refresh_storage (refresh_token_hash, user_id, expiry_date)
+                   VALUES (1, "Bob", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO jwt_storage (jwt_hash, user_id, expiry_date)
+                   VALUES (1, "Bob", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO password_reset_tokens (token, user_id, expiry_date)
+                   VALUES ("token", "Bob", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO api_tokens (token_id, name, scope, token_hash, created_by, creation_date)
+                   VALUES (1, "Token", "read", "hash", "Bob", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO invitations (token, email, created_by, creation_date, expiry_date)
+                   VALUES ("token", "alice@example.com", "Bob", "1971-01-01 00:00:00", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO audit_log (audit_id, actor, source, action, target, date)
+                   VALUES (1, "Bob", "ldap", "password_change", "user:Bob", "1971-01-01 00:00:00")"#,
+            r#"INSERT INTO change_log (entry_type, entry_id, entry_uuid, change_type, change_date)
+                   VALUES ("User", "Bob", "2", "Modify", "1971-01-01 00:00:00")"#,
+        ] {
+            sql_pool.execute(raw_statement(statement)).await.unwrap();
+        }
+        sql_migrations::apply_migration_step(
+            &sql_pool,
+            sql_migrations::MigrationStep::Upgrade(SchemaVersion(29)),
+        )
+        .await
+        .unwrap();
+        #[derive(FromQueryResult, PartialEq, Eq, Debug)]
+        struct JustUserId {
+            user_id: String,
+        }
+        let pool = &sql_pool;
+        let get_user_ids = move |table: &str, column: &str| {
+            JustUserId::find_by_statement(raw_statement(&format!(
+                "SELECT {} AS user_id FROM {} ORDER BY user_id",
+                column, table
+            )))
+            .all(pool)
+        };
+        let user_ids = |ids: &[&str]| {
+            ids.iter()
+                .map(|user_id| JustUserId {
+                    user_id: (*user_id).to_owned(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            get_user_ids("users", "user_id").await.unwrap(),
+            user_ids(&["bob", "bob-2"])
+        );
+        assert_eq!(
+            get_user_ids("memberships", "user_id").await.unwrap(),
+            user_ids(&["bob", "bob-2"])
+        );
+        for (table, column) in [
+            ("group_managers", "user_id"),
+            ("user_attributes", "user_id"),
+            ("password_history", "user_id"),
+            ("failed_logins", "user_id"),
+            ("webauthn_credentials", "user_id"),
+            ("oidc_authorization_codes", "user_id"),
+            ("legacy_password_hashes", "user_id"),
+            ("remote_avatars", "user_id"),
+            ("user_emails", "user_id"),
+            ("jwt_refresh_storage", "user_id"),
+            ("jwt_storage", "user_id"),
+            ("password_reset_tokens", "user_id"),
+            ("api_tokens", "created_by"),
+            ("invitations", "created_by"),
+            ("audit_log", "actor"),
+            ("change_log", "entry_id"),
+        ] {
+            assert_eq!(
+                get_user_ids(table, column).await.unwrap(),
+                user_ids(&["bob-2"]),
+                "{}.{}",
+                table,
+                column
+            );
+        }
+        assert_eq!(
+            get_user_ids("audit_log", "target").await.unwrap(),
+            user_ids(&["user:bob-2"])
+        );
+    }
+
     #[tokio::test]
     async fn test_too_high_version() {
         let sql_pool = get_in_memory_db().await;
@@ -203,4 +356,62 @@ mod tests {
             .unwrap();
         assert!(init_table(&sql_pool).await.is_err());
     }
+
+    #[test]
+    fn test_migration_plan() {
+        use sql_migrations::{migration_plan, MigrationStep};
+        assert_eq!(
+            migration_plan(SchemaVersion(20), SchemaVersion(22)).unwrap(),
+            vec![
+                MigrationStep::Upgrade(SchemaVersion(21)),
+                MigrationStep::Upgrade(SchemaVersion(22))
+            ]
+        );
+        assert_eq!(
+            migration_plan(SchemaVersion(22), SchemaVersion(20)).unwrap(),
+            vec![
+                MigrationStep::Downgrade(SchemaVersion(22)),
+                MigrationStep::Downgrade(SchemaVersion(21))
+            ]
+        );
+        assert!(migration_plan(SchemaVersion(22), SchemaVersion(22))
+            .unwrap()
+            .is_empty());
+        // The older migrations can't be undone.
+        assert!(migration_plan(SchemaVersion(22), SchemaVersion(10)).is_err());
+        assert!(migration_plan(SchemaVersion(127), SchemaVersion(22)).is_err());
+        assert!(migration_plan(SchemaVersion(22), SchemaVersion(127)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_and_upgrade() {
+        let sql_pool = get_in_memory_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let target = sql_migrations::OLDEST_REVERSIBLE_SCHEMA_VERSION;
+        for step in
+            sql_migrations::migration_plan(sql_migrations::LAST_SCHEMA_VERSION, target).unwrap()
+        {
+            sql_migrations::apply_migration_step(&sql_pool, step)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            sql_migrations::get_schema_version(&sql_pool).await,
+            Some(target)
+        );
+        assert!(sql_pool
+            .execute(raw_statement("SELECT * FROM oidc_clients"))
+            .await
+            .is_err());
+        // The tables are created again on startup.
+        init_table(&sql_pool).await.unwrap();
+        assert_eq!(
+            sql_migrations::get_schema_version(&sql_pool).await,
+            Some(sql_migrations::LAST_SCHEMA_VERSION)
+        );
+        sql_pool
+            .execute(raw_statement("SELECT * FROM oidc_clients"))
+            .await
+            .unwrap();
+    }
 }
@@ -1,21 +1,55 @@
 use super::{
     error::{DomainError, Result},
-    handler::{CreateUserRequest, UpdateUserRequest, UserBackendHandler, UserRequestFilter},
-    model::{self, GroupColumn, UserColumn},
-    sql_backend_handler::SqlBackendHandler,
-    types::{GroupDetails, GroupId, User, UserAndGroups, UserId, Uuid},
+    handler::{
+        AuditEvent, CreateUserRequest, LoginKind, UpdateUserRequest, UserBackendHandler, UserOrder,
+        UserPage, UserRequestFilter,
+    },
+    model::{
+        self, GroupColumn, JwtRefreshStorageColumn, MembershipColumn, UserAttributesColumn,
+        UserColumn, UserEmailsColumn,
+    },
+    security_events::SecurityEvent,
+    sql_backend_handler::{
+        case_ignore_eq, case_ignore_like, normalize_case_ignore, to_sql_order, SqlBackendHandler,
+    },
+    sql_group_backend_handler::{get_reachable_groups, invert_nesting, GroupNesting},
+    sql_schema_backend_handler::{
+        deserialize_attribute_value, normalize_attribute_names, serialize_attribute_value,
+    },
+    sql_tables::DbConnection,
+    types::{
+        AttributeValue, ChangeDiff, ChangeEntryType, ChangeType, DateTime, GroupDetails, GroupId,
+        SshPublicKeys, User, UserAndGroups, UserId, Uuid,
+    },
 };
+use crate::infra::configuration::UsernamePolicyOptions;
 use async_trait::async_trait;
+use futures_util::{
+    stream::{self, LocalBoxStream},
+    StreamExt, TryStreamExt,
+};
 use sea_orm::{
     entity::IntoActiveValue,
     sea_query::{Cond, Expr, IntoCondition, SimpleExpr},
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, ModelTrait, QueryFilter, QueryOrder,
-    QuerySelect, QueryTrait, Set,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, FromQueryResult,
+    IdenStatic, Iterable, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+    QueryTrait, Select, Set, TransactionTrait,
 };
-use sea_query::{Alias, IntoColumnRef};
-use std::collections::HashSet;
+use sea_query::{Alias, Func, IntoColumnRef, Query, SelectStatement};
+use std::collections::{HashMap, HashSet};
 use tracing::{debug, instrument};
 
+/// The users created before the date, that haven't logged in since.
+pub(crate) fn inactive_since(date: DateTime) -> Cond {
+    [UserColumn::LastLdapLogin, UserColumn::LastWebLogin]
+        .into_iter()
+        .map(|column| Cond::any().add(column.is_null()).add(column.lt(date)))
+        .fold(
+            Cond::all().add(UserColumn::CreationDate.lt(date)),
+            Cond::add,
+        )
+}
+
 fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
     use UserRequestFilter::*;
     let group_table = Alias::new("r1");
@@ -37,191 +71,458 @@ fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
         Or(fs) => get_repeated_filter(fs, Cond::any(), false),
         Not(f) => get_user_filter_expr(*f).not(),
         UserId(user_id) => ColumnTrait::eq(&UserColumn::UserId, user_id).into_condition(),
-        Equality(s1, s2) => {
-            if s1 == UserColumn::UserId {
-                panic!("User id should be wrapped")
-            } else {
-                ColumnTrait::eq(&s1, s2).into_condition()
-            }
+        Equality(s1, s2) => match s1 {
+            UserColumn::UserId => panic!("User id should be wrapped"),
+            UserColumn::UidNumber | UserColumn::GidNumber => match s2.parse::<i32>() {
+                Ok(number) => ColumnTrait::eq(&s1, number).into_condition(),
+                Err(_) => SimpleExpr::Value(false.into()).into_condition(),
+            },
+            UserColumn::Email
+            | UserColumn::DisplayName
+            | UserColumn::FirstName
+            | UserColumn::LastName => case_ignore_eq(s1, &s2).into_condition(),
+            _ => ColumnTrait::eq(&s1, s2).into_condition(),
+        },
+        SubString(column, filter) => case_ignore_like(column, &filter).into_condition(),
+        Present(column) => column.is_not_null().into_condition(),
+        ModifiedSince(date) => UserColumn::ModifiedDate.gte(date).into_condition(),
+        InactiveSince(date) => inactive_since(date),
+        MemberOf(group) => {
+            case_ignore_eq((group_table, GroupColumn::DisplayName), &group).into_condition()
         }
-        MemberOf(group) => Expr::col((group_table, GroupColumn::DisplayName))
-            .eq(group)
-            .into_condition(),
         MemberOfId(group_id) => Expr::col((group_table, GroupColumn::GroupId))
             .eq(group_id)
             .into_condition(),
+        MemberOfUuid(uuid) => Expr::col((group_table, GroupColumn::Uuid))
+            .eq(uuid)
+            .into_condition(),
+        SecondaryEmail(email) => UserColumn::UserId
+            .in_subquery(
+                Query::select()
+                    .column(UserEmailsColumn::UserId)
+                    .from(model::UserEmails)
+                    .and_where(Expr::col(UserEmailsColumn::Email).eq(normalize_email(&email)))
+                    .to_owned(),
+            )
+            .into_condition(),
     }
 }
-fn to_value(opt_name: &Option<String>) -> ActiveValue<Option<String>> {
-    match opt_name {
-        None => ActiveValue::NotSet,
-        Some(name) => ActiveValue::Set(if name.is_empty() {
-            None
-        } else {
-            Some(name.to_owned())
-        }),
+
+/// The email addresses are compared in lower case, and the secondary ones stored that way.
+pub(crate) fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Checks a user ID, as given by the client, against the policy.
+pub fn check_user_id(policy: &UsernamePolicyOptions, user_id: &str) -> Result<()> {
+    let invalid = |reason: String| {
+        Err(DomainError::InvalidRequest(format!(
+            "Invalid user ID '{}': {}",
+            user_id, reason
+        )))
+    };
+    let length = user_id.chars().count();
+    if length < policy.min_length {
+        return invalid(format!(
+            "it must be at least {} characters long",
+            policy.min_length
+        ));
+    }
+    if length > policy.max_length {
+        return invalid(format!(
+            "it must be at most {} characters long",
+            policy.max_length
+        ));
+    }
+    if !policy.lowercase && user_id.chars().any(char::is_uppercase) {
+        return invalid("it must be in lowercase".to_owned());
+    }
+    if let Some(allowed) = &policy.allowed_characters {
+        if let Some(c) = user_id
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !allowed.contains(*c))
+        {
+            return invalid(format!("the character '{}' is not allowed", c));
+        }
     }
+    Ok(())
 }
 
-#[async_trait]
-impl UserBackendHandler for SqlBackendHandler {
-    #[instrument(skip_all, level = "debug", ret, err)]
-    async fn list_users(
-        &self,
-        filters: Option<UserRequestFilter>,
-        get_groups: bool,
-    ) -> Result<Vec<UserAndGroups>> {
-        debug!(?filters);
-        let query = model::User::find()
-            .filter(
-                filters
-                    .map(|f| {
-                        UserColumn::UserId
-                            .in_subquery(
-                                model::User::find()
-                                    .find_also_linked(model::memberships::UserToGroup)
-                                    .select_only()
-                                    .column(UserColumn::UserId)
-                                    .filter(get_user_filter_expr(f))
-                                    .into_query(),
-                            )
-                            .into_condition()
-                    })
-                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
-            )
-            .order_by_asc(UserColumn::UserId);
-        if !get_groups {
-            Ok(query
-                .into_model::<User>()
-                .all(&self.sql_pool)
-                .await?
-                .into_iter()
-                .map(|u| UserAndGroups {
-                    user: u,
-                    groups: None,
-                })
-                .collect())
-        } else {
-            let results = query
-                //find_with_linked?
-                .find_also_linked(model::memberships::UserToGroup)
-                .order_by_asc(SimpleExpr::Column(
-                    (Alias::new("r1"), GroupColumn::GroupId).into_column_ref(),
-                ))
-                .all(&self.sql_pool)
-                .await?;
-            use itertools::Itertools;
-            Ok(results
-                .iter()
-                .group_by(|(u, _)| u)
+// Normalizes the secondary addresses, without duplicates, and rejects the invalid ones.
+fn normalize_secondary_emails(emails: Vec<String>, primary: &str) -> Result<Vec<String>> {
+    let primary = normalize_email(primary);
+    let mut emails: Vec<String> = emails
+        .iter()
+        .map(String::as_str)
+        .map(normalize_email)
+        .collect();
+    emails.sort();
+    emails.dedup();
+    for email in &emails {
+        if email.len() > 255 || email.contains(char::is_whitespace) || !email.contains('@') {
+            return Err(DomainError::InvalidRequest(format!(
+                "Invalid email address: '{}'",
+                email
+            )));
+        }
+        if *email == primary {
+            return Err(DomainError::InvalidRequest(format!(
+                "'{}' is already the primary email address",
+                email
+            )));
+        }
+    }
+    Ok(emails)
+}
+
+// Normalizes a language tag such as "fr_ca" to "fr-CA", and rejects the invalid ones. An empty
+// tag removes the locale.
+fn normalize_locale(locale: Option<String>) -> Result<Option<String>> {
+    let locale = match locale {
+        Some(locale) if !locale.is_empty() => locale,
+        _ => return Ok(locale),
+    };
+    let invalid = || DomainError::InvalidRequest(format!("Invalid locale: '{}'", locale));
+    let mut subtags = locale.split(['-', '_']);
+    let language = subtags.next().unwrap_or_default();
+    if locale.len() > 35
+        || !(2..=3).contains(&language.len())
+        || !language.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return Err(invalid());
+    }
+    let mut normalized = language.to_ascii_lowercase();
+    for subtag in subtags {
+        if subtag.is_empty()
+            || subtag.len() > 8
+            || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(invalid());
+        }
+        normalized.push('-');
+        match subtag.len() {
+            // Region, e.g. "CA".
+            2 => normalized.push_str(&subtag.to_ascii_uppercase()),
+            // Script, e.g. "Hant".
+            4 => {
+                normalized.push_str(&subtag[..1].to_ascii_uppercase());
+                normalized.push_str(&subtag[1..].to_ascii_lowercase());
+            }
+            _ => normalized.push_str(&subtag.to_ascii_lowercase()),
+        }
+    }
+    Ok(Some(normalized))
+}
+
+// Rejects the time zones that cannot be IANA names, such as "Europe/Paris".
+fn validate_timezone(timezone: &Option<String>) -> Result<()> {
+    match timezone {
+        Some(timezone)
+            if timezone.len() > 64
+                || timezone.starts_with('/')
+                || !timezone
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/_-+".contains(c)) =>
+        {
+            Err(DomainError::InvalidRequest(format!(
+                "Invalid time zone: '{}'",
+                timezone
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The last login dates are only written when older than this, so that the clients binding
+/// repeatedly don't cause a write each time.
+const LOGIN_RECORD_INTERVAL_MINUTES: i64 = 5;
+
+// Number of users fetched at once when streaming them.
+#[cfg(not(test))]
+const USER_PAGE_SIZE: u64 = 500;
+#[cfg(test)]
+const USER_PAGE_SIZE: u64 = 2;
+
+#[derive(FromQueryResult)]
+struct UserIdResult {
+    user_id: UserId,
+}
+
+// The names of the fields changed by the request, for the audit log. Enabling and disabling the
+// account are told apart.
+pub(crate) fn updated_user_fields(request: &UpdateUserRequest) -> Option<String> {
+    let fields = [
+        ("email", request.email.is_some()),
+        ("display_name", request.display_name.is_some()),
+        ("first_name", request.first_name.is_some()),
+        ("last_name", request.last_name.is_some()),
+        ("avatar", request.avatar.is_some()),
+        ("uid_number", request.uid_number.is_some()),
+        ("gid_number", request.gid_number.is_some()),
+        ("home_directory", request.home_directory.is_some()),
+        ("login_shell", request.login_shell.is_some()),
+        ("telephone_number", request.telephone_number.is_some()),
+        ("mobile", request.mobile.is_some()),
+        ("title", request.title.is_some()),
+        ("department", request.department.is_some()),
+        ("locality", request.locality.is_some()),
+        ("postal_address", request.postal_address.is_some()),
+        ("locale", request.locale.is_some()),
+        ("timezone", request.timezone.is_some()),
+        ("ssh_public_keys", request.ssh_public_keys.is_some()),
+        ("secondary_emails", request.secondary_emails.is_some()),
+        ("enabled", request.enabled == Some(true)),
+        ("disabled", request.enabled == Some(false)),
+        ("valid_from", request.valid_from.is_some()),
+        ("valid_until", request.valid_until.is_some()),
+    ];
+    let fields: Vec<&str> = fields
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| *name)
+        .chain(request.insert_attributes.iter().map(|a| a.name.as_str()))
+        .chain(request.delete_attributes.iter().map(String::as_str))
+        .collect();
+    (!fields.is_empty()).then(|| fields.join(", "))
+}
+
+// Selects the IDs of the users matching the filter.
+fn get_user_id_subquery(filter: UserRequestFilter) -> SelectStatement {
+    model::User::find()
+        .find_also_linked(model::memberships::UserToGroup)
+        .select_only()
+        .column(UserColumn::UserId)
+        .filter(get_user_filter_expr(filter))
+        .into_query()
+}
+
+fn to_attribute_model(
+    user_id: &UserId,
+    attribute: AttributeValue,
+) -> model::user_attributes::ActiveModel {
+    model::user_attributes::ActiveModel {
+        user_id: Set(user_id.clone()),
+        attribute_name: Set(attribute.name),
+        value: Set(serialize_attribute_value(&attribute.value)),
+    }
+}
+
+// Replaces each group membership check with a check on the group or any of its subgroups,
+// transitively.
+fn expand_member_of_filter(
+    filter: UserRequestFilter,
+    nesting: &GroupNesting,
+    group_ids: &HashMap<String, GroupId>,
+    group_uuids: &HashMap<Uuid, GroupId>,
+) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let rec = |f| expand_member_of_filter(f, nesting, group_ids, group_uuids);
+    let expand = |fs: Vec<UserRequestFilter>| fs.into_iter().map(rec).collect();
+    match filter {
+        And(fs) => And(expand(fs)),
+        Or(fs) => Or(expand(fs)),
+        Not(f) => Not(Box::new(rec(*f))),
+        MemberOf(group) => match group_ids.get(&normalize_case_ignore(&group)) {
+            Some(id) if nesting.contains_key(id) => rec(MemberOfId(*id)),
+            _ => MemberOf(group),
+        },
+        MemberOfUuid(uuid) => match group_uuids.get(&uuid) {
+            Some(id) if nesting.contains_key(id) => rec(MemberOfId(*id)),
+            _ => MemberOfUuid(uuid),
+        },
+        MemberOfId(group_id) if nesting.contains_key(&group_id) => {
+            let mut groups: Vec<_> = get_reachable_groups(nesting, [group_id])
                 .into_iter()
-                .map(|(user, groups)| {
-                    let groups: Vec<_> = groups
-                        .into_iter()
-                        .flat_map(|(_, g)| g)
-                        .map(|g| GroupDetails::from(g.clone()))
-                        .collect();
-                    UserAndGroups {
-                        user: user.clone().into(),
-                        groups: Some(groups),
-                    }
-                })
-                .collect())
+                .collect();
+            groups.sort();
+            Or(groups.into_iter().map(MemberOfId).collect())
         }
+        f => f,
     }
+}
 
-    #[instrument(skip_all, level = "debug", ret)]
-    async fn get_user_details(&self, user_id: &UserId) -> Result<User> {
-        debug!(?user_id);
-        model::User::find_by_id(user_id.to_owned())
-            .into_model::<User>()
-            .one(&self.sql_pool)
+impl SqlBackendHandler {
+    // Fetches the custom attributes of the users matching the condition, sorted by name.
+    async fn get_user_attributes(
+        &self,
+        pool: &DbConnection,
+        condition: Cond,
+    ) -> Result<HashMap<UserId, Vec<AttributeValue>>> {
+        let mut attributes: HashMap<UserId, Vec<AttributeValue>> = HashMap::new();
+        for attribute in model::UserAttributes::find()
+            .filter(condition)
+            .order_by_asc(UserAttributesColumn::AttributeName)
+            .all(pool)
             .await?
-            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))
+        {
+            let value = deserialize_attribute_value(&attribute.attribute_name, &attribute.value)?;
+            attributes
+                .entry(attribute.user_id)
+                .or_default()
+                .push(AttributeValue {
+                    name: attribute.attribute_name,
+                    value,
+                });
+        }
+        Ok(attributes)
     }
 
-    #[instrument(skip_all, level = "debug", ret, err)]
-    async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>> {
-        debug!(?user_id);
-        let user = model::User::find_by_id(user_id.to_owned())
-            .one(&self.sql_pool)
+    // Fetches the secondary email addresses of the users matching the condition, sorted.
+    async fn get_user_emails(
+        &self,
+        pool: &DbConnection,
+        condition: Cond,
+    ) -> Result<HashMap<UserId, Vec<String>>> {
+        let mut emails: HashMap<UserId, Vec<String>> = HashMap::new();
+        for email in model::UserEmails::find()
+            .filter(condition)
+            .order_by_asc(UserEmailsColumn::Email)
+            .all(pool)
             .await?
-            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
-        Ok(HashSet::from_iter(
-            user.find_linked(model::memberships::UserToGroup)
-                .into_model::<GroupDetails>()
-                .all(&self.sql_pool)
-                .await?,
-        ))
+        {
+            emails.entry(email.user_id).or_default().push(email.email);
+        }
+        Ok(emails)
     }
 
-    #[instrument(skip_all, level = "debug", err)]
-    async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
-        debug!(user_id = ?request.user_id);
-        let now = chrono::Utc::now();
-        let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
-        let new_user = model::users::ActiveModel {
-            user_id: Set(request.user_id),
-            email: Set(request.email),
-            display_name: to_value(&request.display_name),
-            first_name: to_value(&request.first_name),
-            last_name: to_value(&request.last_name),
-            avatar: request.avatar.into_active_value(),
-            creation_date: ActiveValue::Set(now),
-            uuid: ActiveValue::Set(uuid),
-            ..Default::default()
+    // Fails if one of the addresses, normalized, is already used by another user: as a secondary
+    // address, or as a primary one if `check_primary`. Only the secondary addresses are unique in
+    // the database, the older directories can have several users with the same primary address.
+    async fn check_emails_available<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        emails: &[String],
+        check_primary: bool,
+    ) -> Result<()> {
+        if emails.is_empty() {
+            return Ok(());
+        }
+        let taken = model::UserEmails::find()
+            .filter(UserEmailsColumn::Email.is_in(emails.iter().cloned()))
+            .filter(UserEmailsColumn::UserId.ne(user_id.clone()))
+            .one(conn)
+            .await?
+            .map(|e| e.email);
+        let taken = match taken {
+            None if check_primary => model::User::find()
+                .filter(
+                    Expr::expr(Func::lower(Expr::col(UserColumn::Email)))
+                        .is_in(emails.iter().cloned()),
+                )
+                .filter(UserColumn::UserId.ne(user_id.clone()))
+                .one(conn)
+                .await?
+                .map(|u| normalize_email(&u.email)),
+            taken => taken,
         };
-        new_user.insert(&self.sql_pool).await?;
-        Ok(())
+        match taken {
+            Some(email) => Err(DomainError::InvalidRequest(format!(
+                "The email address '{}' is already used by another user",
+                email
+            ))),
+            None => Ok(()),
+        }
     }
 
-    #[instrument(skip_all, level = "debug", err)]
-    async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
-        debug!(user_id = ?request.user_id);
-        let update_user = model::users::ActiveModel {
-            user_id: ActiveValue::Set(request.user_id),
-            email: request.email.map(ActiveValue::Set).unwrap_or_default(),
-            display_name: to_value(&request.display_name),
-            first_name: to_value(&request.first_name),
-            last_name: to_value(&request.last_name),
-            avatar: request.avatar.into_active_value(),
-            ..Default::default()
-        };
-        update_user.update(&self.sql_pool).await?;
+    async fn replace_user_emails<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        emails: Vec<String>,
+    ) -> Result<()> {
+        model::UserEmails::delete_many()
+            .filter(UserEmailsColumn::UserId.eq(user_id.clone()))
+            .exec(conn)
+            .await?;
+        if emails.is_empty() {
+            return Ok(());
+        }
+        model::UserEmails::insert_many(emails.into_iter().map(|email| {
+            model::user_emails::ActiveModel {
+                email: Set(email),
+                user_id: Set(user_id.clone()),
+            }
+        }))
+        .exec(conn)
+        .await?;
         Ok(())
     }
 
-    #[instrument(skip_all, level = "debug", err)]
-    async fn delete_user(&self, user_id: &UserId) -> Result<()> {
-        debug!(?user_id);
-        let res = model::User::delete_by_id(user_id.clone())
-            .exec(&self.sql_pool)
+    // Bumps the modification date of the user, e.g. when its group memberships change.
+    async fn touch_user<C: ConnectionTrait>(&self, conn: &C, user_id: &UserId) -> Result<()> {
+        model::User::update_many()
+            .col_expr(UserColumn::ModifiedDate, Expr::value(chrono::Utc::now()))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(conn)
             .await?;
-        if res.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such user: '{}'",
-                user_id
-            )));
-        }
         Ok(())
     }
 
-    #[instrument(skip_all, level = "debug", err)]
-    async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
-        debug!(?user_id, ?group_id);
+    pub(crate) async fn insert_membership<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<DateTime>,
+    ) -> Result<()> {
         let new_membership = model::memberships::ActiveModel {
             user_id: ActiveValue::Set(user_id.clone()),
             group_id: ActiveValue::Set(group_id),
+            expiry_date: ActiveValue::Set(expiry_date),
+        };
+        new_membership.insert(conn).await?;
+        self.record_membership_change(conn, user_id, group_id, true)
+            .await
+    }
+
+    /// Fails with `LastAdmin` if removing the users from `lldap_admin` would leave it without
+    /// members, since nobody could administer the directory anymore. With `from_group`, only the
+    /// removal from that group is checked; otherwise the users leave all their groups.
+    pub(crate) async fn check_admins_remain<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        from_group: Option<GroupId>,
+        removed_users: &[UserId],
+    ) -> Result<()> {
+        let admin_group = match model::Group::find()
+            .filter(case_ignore_eq(GroupColumn::DisplayName, "lldap_admin"))
+            .one(conn)
+            .await?
+        {
+            Some(group) => group.group_id,
+            None => return Ok(()),
         };
-        new_membership.insert(&self.sql_pool).await?;
+        if from_group.map_or(false, |group_id| group_id != admin_group) {
+            return Ok(());
+        }
+        let admins: Vec<UserId> = model::Membership::find()
+            .filter(MembershipColumn::GroupId.eq(admin_group))
+            .all(conn)
+            .await?
+            .into_iter()
+            .map(|m| m.user_id)
+            .collect();
+        if !admins.is_empty() && admins.iter().all(|admin| removed_users.contains(admin)) {
+            return Err(DomainError::LastAdmin(
+                admins
+                    .iter()
+                    .map(UserId::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ));
+        }
         Ok(())
     }
 
-    #[instrument(skip_all, level = "debug", err)]
-    async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
-        debug!(?user_id, ?group_id);
+    pub(crate) async fn delete_membership<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        group_id: GroupId,
+    ) -> Result<()> {
         let res = model::Membership::delete_by_id((user_id.clone(), group_id))
-            .exec(&self.sql_pool)
+            .exec(conn)
             .await?;
         if res.rows_affected == 0 {
             return Err(DomainError::EntityNotFound(format!(
@@ -229,407 +530,2562 @@ impl UserBackendHandler for SqlBackendHandler {
                 user_id, group_id
             )));
         }
-        Ok(())
+        self.record_membership_change(conn, user_id, group_id, false)
+            .await
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::domain::{
-        sql_backend_handler::tests::*,
-        types::{JpegPhoto, UserColumn},
-    };
 
-    #[tokio::test]
-    async fn test_list_users_no_filter() {
-        let fixture = TestFixture::new().await;
-        let users = get_user_names(&fixture.handler, None).await;
-        assert_eq!(users, vec!["bob", "john", "nogroup", "patrick"]);
+    // Updates the modification date of both sides of a membership, and logs the change.
+    async fn record_membership_change<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        group_id: GroupId,
+        added: bool,
+    ) -> Result<()> {
+        self.touch_user(conn, user_id).await?;
+        self.touch_group(conn, group_id).await?;
+        let group_name = model::Group::find_by_id(group_id)
+            .one(conn)
+            .await?
+            .map(|group| group.display_name)
+            .unwrap_or_default();
+        let diff = |name: String| {
+            if added {
+                ChangeDiff {
+                    added_memberships: vec![name],
+                    ..Default::default()
+                }
+            } else {
+                ChangeDiff {
+                    removed_memberships: vec![name],
+                    ..Default::default()
+                }
+            }
+        };
+        self.log_user_modification(conn, user_id, diff(group_name))
+            .await?;
+        self.log_group_modification(conn, group_id, diff(user_id.to_string()))
+            .await
     }
 
-    #[tokio::test]
-    async fn test_list_users_user_id_filter() {
-        let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::UserId(UserId::new("bob"))),
-        )
-        .await;
-        assert_eq!(users, vec!["bob"]);
+    pub(crate) fn publish_membership_changes(
+        &self,
+        user_ids: &[UserId],
+        group_id: GroupId,
+        added: bool,
+    ) {
+        for user_id in user_ids {
+            self.publish_security_event(SecurityEvent::GroupMembershipChanged {
+                user_id: user_id.clone(),
+                group_id,
+                added,
+            });
+        }
     }
 
-    #[tokio::test]
-    async fn test_list_users_display_name_filter() {
-        let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Equality(
-                UserColumn::DisplayName,
-                "display bob".to_string(),
-            )),
-        )
-        .await;
-        assert_eq!(users, vec!["bob"]);
+    // One audit log entry per member, to find them by user.
+    pub(crate) async fn audit_memberships(
+        &self,
+        action: &str,
+        user_ids: &[UserId],
+        group_id: GroupId,
+    ) {
+        for user_id in user_ids {
+            self.audit(
+                action,
+                AuditEvent::user_target(user_id),
+                Some(AuditEvent::group_target(group_id)),
+            )
+            .await;
+        }
     }
 
-    #[tokio::test]
-    async fn test_list_users_other_filter() {
-        let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Equality(
-                UserColumn::FirstName,
-                "first bob".to_string(),
-            )),
-        )
-        .await;
-        assert_eq!(users, vec!["bob"]);
+    // Lists the IDs of the first `USER_PAGE_SIZE` users matching the filter, sorted, that come
+    // after the given ID.
+    async fn list_user_ids_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        after: Option<UserId>,
+    ) -> Result<Vec<UserId>> {
+        let mut query = self.select_user_ids(filters).await?;
+        if let Some(after) = after {
+            query = query.filter(UserColumn::UserId.gt(after));
+        }
+        Ok(query
+            .order_by_asc(UserColumn::UserId)
+            .limit(USER_PAGE_SIZE)
+            .into_model::<UserIdResult>()
+            .all(self.read_pool())
+            .await?
+            .into_iter()
+            .map(|u| u.user_id)
+            .collect())
+    }
+
+    // Returns the IDs of the users in the given window, along with the total number of users
+    // matching the filter.
+    async fn list_user_ids_window(
+        &self,
+        filters: Option<UserRequestFilter>,
+        order: UserOrder,
+        offset: u64,
+        limit: Option<u64>,
+    ) -> Result<(Vec<UserId>, u64)> {
+        let mut query = self.select_user_ids(filters).await?;
+        let total_count = query.clone().count(self.read_pool()).await? as u64;
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        let user_ids = query
+            .order_by(order.column, to_sql_order(order.direction))
+            .order_by_asc(UserColumn::UserId)
+            .offset(offset)
+            .into_model::<UserIdResult>()
+            .all(self.read_pool())
+            .await?
+            .into_iter()
+            .map(|u| u.user_id)
+            .collect();
+        Ok((user_ids, total_count))
+    }
+
+    async fn select_user_ids(
+        &self,
+        filters: Option<UserRequestFilter>,
+    ) -> Result<Select<model::User>> {
+        let mut query = model::User::find().select_only().column(UserColumn::UserId);
+        if let Some(filter) = filters {
+            let nesting = self.get_group_nesting(self.read_pool()).await?;
+            let filter = self.expand_nested_groups_filter(filter, &nesting).await?;
+            query = query.filter(UserColumn::UserId.in_subquery(get_user_id_subquery(filter)));
+        }
+        Ok(query)
+    }
+
+    // Updates a user whose new attributes were already validated.
+    pub(crate) async fn apply_user_update<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        request: UpdateUserRequest,
+        insert_attributes: Vec<AttributeValue>,
+    ) -> Result<()> {
+        let user_id = request.user_id;
+        let attributes_changed =
+            !insert_attributes.is_empty() || !request.delete_attributes.is_empty();
+        let locale = normalize_locale(request.locale)?;
+        validate_timezone(&request.timezone)?;
+        if let Some(email) = &request.email {
+            self.check_emails_available(
+                conn,
+                &user_id,
+                &[normalize_email(email)],
+                self.config.unique_emails,
+            )
+            .await?;
+        }
+        let secondary_emails = match request.secondary_emails {
+            None => None,
+            Some(emails) => {
+                let primary = match &request.email {
+                    Some(email) => email.clone(),
+                    None => {
+                        model::User::find_by_id(user_id.clone())
+                            .one(conn)
+                            .await?
+                            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?
+                            .email
+                    }
+                };
+                let emails = normalize_secondary_emails(emails, &primary)?;
+                self.check_emails_available(conn, &user_id, &emails, true)
+                    .await?;
+                Some(emails)
+            }
+        };
+        if request.avatar.is_some() {
+            // The avatar set by the user is never replaced by the remote one.
+            model::RemoteAvatars::delete_by_id(user_id.clone())
+                .exec(conn)
+                .await?;
+        }
+        let mut update_user = model::users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            email: request.email.map(ActiveValue::Set).unwrap_or_default(),
+            display_name: to_value(&request.display_name),
+            first_name: to_value(&request.first_name),
+            last_name: to_value(&request.last_name),
+            avatar: request.avatar.into_active_value(),
+            uid_number: request
+                .uid_number
+                .map(|n| ActiveValue::Set(Some(n)))
+                .unwrap_or_default(),
+            gid_number: request
+                .gid_number
+                .map(|n| ActiveValue::Set(Some(n)))
+                .unwrap_or_default(),
+            home_directory: to_value(&request.home_directory),
+            login_shell: to_value(&request.login_shell),
+            telephone_number: to_value(&request.telephone_number),
+            mobile: to_value(&request.mobile),
+            title: to_value(&request.title),
+            department: to_value(&request.department),
+            locality: to_value(&request.locality),
+            postal_address: to_value(&request.postal_address),
+            locale: to_value(&locale),
+            timezone: to_value(&request.timezone),
+            ssh_public_keys: request
+                .ssh_public_keys
+                .map(ActiveValue::Set)
+                .unwrap_or_default(),
+            enabled: request.enabled.map(ActiveValue::Set).unwrap_or_default(),
+            valid_from: request.valid_from.map(ActiveValue::Set).unwrap_or_default(),
+            valid_until: request
+                .valid_until
+                .map(ActiveValue::Set)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let mut changed_fields: Vec<String> = UserColumn::iter()
+            .filter(|c| !matches!(c, UserColumn::UserId))
+            .filter(|c| update_user.get(*c).is_set())
+            .map(|c| c.as_str().to_owned())
+            .collect();
+        // Re-enabled by an admin: it's no longer deleted by the stale accounts job.
+        if request.enabled == Some(true) {
+            update_user.stale_disabled_date = ActiveValue::Set(None);
+        }
+        // Disabled: the sessions are revoked, so that the JWTs cannot be refreshed anymore.
+        if request.enabled == Some(false) {
+            model::JwtRefreshStorage::delete_many()
+                .filter(JwtRefreshStorageColumn::UserId.eq(user_id.clone()))
+                .exec(conn)
+                .await?;
+        }
+        let changed =
+            attributes_changed || secondary_emails.is_some() || !changed_fields.is_empty();
+        if changed {
+            update_user.modified_date = ActiveValue::Set(chrono::Utc::now());
+            update_user.update(conn).await?;
+        }
+        let replaced_attributes: Vec<_> = insert_attributes
+            .iter()
+            .map(|a| a.name.clone())
+            .chain(
+                request
+                    .delete_attributes
+                    .iter()
+                    .map(|name| name.to_ascii_lowercase()),
+            )
+            .collect();
+        if !replaced_attributes.is_empty() {
+            model::UserAttributes::delete_many()
+                .filter(UserAttributesColumn::UserId.eq(user_id.clone()))
+                .filter(UserAttributesColumn::AttributeName.is_in(replaced_attributes.clone()))
+                .exec(conn)
+                .await?;
+        }
+        self.insert_user_attributes(conn, &user_id, insert_attributes)
+            .await?;
+        if let Some(emails) = secondary_emails {
+            self.replace_user_emails(conn, &user_id, emails).await?;
+            changed_fields.push("secondary_emails".to_owned());
+        }
+        if changed {
+            changed_fields.extend(replaced_attributes);
+            self.log_user_modification(
+                conn,
+                &user_id,
+                ChangeDiff {
+                    fields: changed_fields,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Changes the SSH public keys of the user in a transaction, so that concurrent changes are not
+    // lost.
+    async fn update_ssh_public_keys(
+        &self,
+        user_id: &UserId,
+        change: impl FnOnce(&mut Vec<String>) -> Result<()>,
+    ) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
+        let user = model::User::find_by_id(user_id.clone())
+            .one(&transaction)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
+        let mut keys = user.ssh_public_keys.into_vec();
+        change(&mut keys)?;
+        let request = UpdateUserRequest {
+            user_id: user_id.clone(),
+            ssh_public_keys: Some(SshPublicKeys::new(keys)),
+            ..Default::default()
+        };
+        self.apply_user_update(&transaction, request, Vec::new())
+            .await?;
+        transaction.commit().await?;
+        self.invalidate_cache();
+        Ok(())
+    }
+
+    async fn insert_user_attributes<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        user_id: &UserId,
+        attributes: Vec<AttributeValue>,
+    ) -> Result<()> {
+        if attributes.is_empty() {
+            return Ok(());
+        }
+        model::UserAttributes::insert_many(
+            attributes
+                .into_iter()
+                .map(|a| to_attribute_model(user_id, a)),
+        )
+        .exec(conn)
+        .await?;
+        Ok(())
+    }
+
+    // Fills the fields of the request left empty from its user template, and returns the groups
+    // of the template.
+    async fn apply_user_template<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        request: &mut CreateUserRequest,
+    ) -> Result<Vec<GroupId>> {
+        let name = match &request.template {
+            None => return Ok(Vec::new()),
+            Some(name) => name,
+        };
+        let template = self
+            .config
+            .user_templates
+            .iter()
+            .find(|t| &t.name == name)
+            .ok_or_else(|| {
+                DomainError::InvalidRequest(format!("No user template named '{}'", name))
+            })?;
+        if request.gid_number.is_none() {
+            request.gid_number = template.gid_number;
+        }
+        if request.home_directory.is_none() {
+            request.home_directory = template
+                .home_directory
+                .as_ref()
+                .map(|pattern| pattern.replace("{user_id}", request.user_id.as_str()));
+        }
+        if request.locale.is_none() {
+            request.locale = template.locale.clone();
+        }
+        let mut group_ids = Vec::with_capacity(template.groups.len());
+        for group in &template.groups {
+            let group_id = model::Group::find()
+                .filter(case_ignore_eq(GroupColumn::DisplayName, group))
+                .one(conn)
+                .await?
+                .ok_or_else(|| {
+                    DomainError::InvalidRequest(format!(
+                        "The group '{}' of the user template '{}' doesn't exist",
+                        group, template.name
+                    ))
+                })?
+                .group_id;
+            group_ids.push(group_id);
+        }
+        Ok(group_ids)
+    }
+
+    // Inserts a user whose attributes were already validated, and returns the groups it was added
+    // to by its template.
+    pub(crate) async fn insert_new_user<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        mut request: CreateUserRequest,
+        attributes: Vec<AttributeValue>,
+    ) -> Result<Vec<GroupId>> {
+        check_user_id(&self.config.username_policy, request.user_id.as_str())?;
+        let group_ids = self.apply_user_template(conn, &mut request).await?;
+        self.check_emails_available(
+            conn,
+            &request.user_id,
+            &[normalize_email(&request.email)],
+            self.config.unique_emails,
+        )
+        .await?;
+        let locale = normalize_locale(request.locale)?;
+        validate_timezone(&request.timezone)?;
+        let now = chrono::Utc::now();
+        let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
+        let new_user = model::users::ActiveModel {
+            user_id: Set(request.user_id),
+            email: Set(request.email),
+            display_name: to_value(&request.display_name),
+            first_name: to_value(&request.first_name),
+            last_name: to_value(&request.last_name),
+            avatar: request.avatar.into_active_value(),
+            creation_date: ActiveValue::Set(now),
+            modified_date: ActiveValue::Set(now),
+            uuid: ActiveValue::Set(uuid.clone()),
+            uid_number: ActiveValue::Set(request.uid_number),
+            gid_number: ActiveValue::Set(request.gid_number),
+            home_directory: to_value(&request.home_directory),
+            login_shell: to_value(&request.login_shell),
+            telephone_number: to_value(&request.telephone_number),
+            mobile: to_value(&request.mobile),
+            title: to_value(&request.title),
+            department: to_value(&request.department),
+            locality: to_value(&request.locality),
+            postal_address: to_value(&request.postal_address),
+            locale: to_value(&locale),
+            timezone: to_value(&request.timezone),
+            enabled: ActiveValue::Set(true),
+            valid_from: ActiveValue::Set(request.valid_from),
+            valid_until: ActiveValue::Set(request.valid_until),
+            ..Default::default()
+        };
+        let user_id = new_user.insert(conn).await?.user_id;
+        if let Some(password_hash) = request.legacy_password_hash {
+            model::legacy_password_hashes::ActiveModel {
+                user_id: Set(user_id.clone()),
+                password_hash: Set(password_hash),
+            }
+            .insert(conn)
+            .await?;
+        }
+        self.insert_user_attributes(conn, &user_id, attributes)
+            .await?;
+        self.log_change(
+            conn,
+            ChangeEntryType::User,
+            user_id.clone().into_string(),
+            uuid,
+            ChangeType::Add,
+            None,
+        )
+        .await?;
+        for group_id in &group_ids {
+            self.insert_membership(conn, &user_id, *group_id, None)
+                .await?;
+        }
+        Ok(group_ids)
+    }
+
+    async fn expand_nested_groups_filter(
+        &self,
+        filter: UserRequestFilter,
+        nesting: &GroupNesting,
+    ) -> Result<UserRequestFilter> {
+        if nesting.is_empty() {
+            return Ok(filter);
+        }
+        let groups = model::Group::find().all(self.read_pool()).await?;
+        let group_ids = groups
+            .iter()
+            .map(|g| (normalize_case_ignore(&g.display_name), g.group_id))
+            .collect();
+        let group_uuids = groups.into_iter().map(|g| (g.uuid, g.group_id)).collect();
+        Ok(expand_member_of_filter(
+            filter,
+            nesting,
+            &group_ids,
+            &group_uuids,
+        ))
+    }
+
+    // Adds to each user the groups that contain the user's groups, transitively.
+    async fn add_parent_groups(
+        &self,
+        users: Vec<UserAndGroups>,
+        nesting: &GroupNesting,
+    ) -> Result<Vec<UserAndGroups>> {
+        if nesting.is_empty() {
+            return Ok(users);
+        }
+        let parents = invert_nesting(nesting);
+        let all_groups: HashMap<GroupId, GroupDetails> = model::Group::find()
+            .into_model::<GroupDetails>()
+            .all(self.read_pool())
+            .await?
+            .into_iter()
+            .map(|g| (g.group_id, g))
+            .collect();
+        Ok(users
+            .into_iter()
+            .map(|UserAndGroups { user, groups }| {
+                let groups = groups.map(|groups| {
+                    let mut group_ids: Vec<_> =
+                        get_reachable_groups(&parents, groups.into_iter().map(|g| g.group_id))
+                            .into_iter()
+                            .collect();
+                    group_ids.sort();
+                    group_ids
+                        .into_iter()
+                        .filter_map(|id| all_groups.get(&id).cloned())
+                        .collect()
+                });
+                UserAndGroups { user, groups }
+            })
+            .collect())
+    }
+
+    async fn fetch_user_details(&self, pool: &DbConnection, user_id: &UserId) -> Result<User> {
+        let mut user = model::User::find_by_id(user_id.to_owned())
+            .one(pool)
+            .await?
+            .map(User::from)
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
+        user.attributes = self
+            .get_user_attributes(
+                pool,
+                UserAttributesColumn::UserId
+                    .eq(user_id.clone())
+                    .into_condition(),
+            )
+            .await?
+            .remove(user_id)
+            .unwrap_or_default();
+        user.secondary_emails = self
+            .get_user_emails(
+                pool,
+                UserEmailsColumn::UserId
+                    .eq(user_id.clone())
+                    .into_condition(),
+            )
+            .await?
+            .remove(user_id)
+            .unwrap_or_default();
+        Ok(user)
+    }
+
+    async fn fetch_user_groups(
+        &self,
+        pool: &DbConnection,
+        user_id: &UserId,
+    ) -> Result<HashSet<GroupDetails>> {
+        let user = model::User::find_by_id(user_id.to_owned())
+            .one(pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
+        Ok(HashSet::from_iter(
+            user.find_linked(model::memberships::UserToGroup)
+                .into_model::<GroupDetails>()
+                .all(pool)
+                .await?,
+        ))
+    }
+
+    // The cache is also read by the login and permission checks, so it is only filled from the
+    // primary: the replica only serves the details that are not cached.
+    async fn cached_user_details(&self, pool: &DbConnection, user_id: &UserId) -> Result<User> {
+        match &self.cache {
+            Some(cache) => {
+                cache
+                    .user_details(user_id, self.fetch_user_details(&self.sql_pool, user_id))
+                    .await
+            }
+            None => self.fetch_user_details(pool, user_id).await,
+        }
+    }
+
+    async fn cached_user_groups(
+        &self,
+        pool: &DbConnection,
+        user_id: &UserId,
+    ) -> Result<HashSet<GroupDetails>> {
+        match &self.cache {
+            Some(cache) => {
+                cache
+                    .user_groups(user_id, self.fetch_user_groups(&self.sql_pool, user_id))
+                    .await
+            }
+            None => self.fetch_user_groups(pool, user_id).await,
+        }
+    }
+}
+
+pub(crate) fn to_value(opt_name: &Option<String>) -> ActiveValue<Option<String>> {
+    match opt_name {
+        None => ActiveValue::NotSet,
+        Some(name) => ActiveValue::Set(if name.is_empty() {
+            None
+        } else {
+            Some(name.to_owned())
+        }),
+    }
+}
+
+#[async_trait]
+impl UserBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+    ) -> Result<Vec<UserAndGroups>> {
+        debug!(?filters);
+        let nesting = self.get_group_nesting(self.read_pool()).await?;
+        let filters = match filters {
+            Some(f) => Some(self.expand_nested_groups_filter(f, &nesting).await?),
+            None => None,
+        };
+        let user_ids = filters.map(get_user_id_subquery);
+        let mut attributes = self
+            .get_user_attributes(
+                self.read_pool(),
+                user_ids
+                    .clone()
+                    .map(|q| UserAttributesColumn::UserId.in_subquery(q).into_condition())
+                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
+            )
+            .await?;
+        let mut emails = self
+            .get_user_emails(
+                self.read_pool(),
+                user_ids
+                    .clone()
+                    .map(|q| UserEmailsColumn::UserId.in_subquery(q).into_condition())
+                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
+            )
+            .await?;
+        let mut to_user = |model: model::users::Model| {
+            let mut user = User::from(model);
+            user.attributes = attributes.remove(&user.user_id).unwrap_or_default();
+            user.secondary_emails = emails.remove(&user.user_id).unwrap_or_default();
+            user
+        };
+        let query = model::User::find()
+            .filter(
+                user_ids
+                    .map(|q| UserColumn::UserId.in_subquery(q).into_condition())
+                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
+            )
+            .order_by_asc(UserColumn::UserId);
+        if !get_groups {
+            Ok(query
+                .all(self.read_pool())
+                .await?
+                .into_iter()
+                .map(|u| UserAndGroups {
+                    user: to_user(u),
+                    groups: None,
+                })
+                .collect())
+        } else {
+            let results = query
+                //find_with_linked?
+                .find_also_linked(model::memberships::UserToGroup)
+                .order_by_asc(SimpleExpr::Column(
+                    (Alias::new("r1"), GroupColumn::GroupId).into_column_ref(),
+                ))
+                .all(self.read_pool())
+                .await?;
+            use itertools::Itertools;
+            let users = results
+                .iter()
+                .group_by(|(u, _)| u)
+                .into_iter()
+                .map(|(user, groups)| {
+                    let groups: Vec<_> = groups
+                        .into_iter()
+                        .flat_map(|(_, g)| g)
+                        .map(|g| GroupDetails::from(g.clone()))
+                        .collect();
+                    UserAndGroups {
+                        user: to_user(user.clone()),
+                        groups: Some(groups),
+                    }
+                })
+                .collect();
+            self.add_parent_groups(users, &nesting).await
+        }
+    }
+
+    fn stream_users<'a>(
+        &'a self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+    ) -> LocalBoxStream<'a, Result<UserAndGroups>> {
+        // The users are fetched by pages, keyed on the last user ID of the previous page.
+        stream::try_unfold(Some(None), move |after: Option<Option<UserId>>| {
+            let filters = filters.clone();
+            async move {
+                let after = match after {
+                    Some(after) => after,
+                    None => return Ok::<_, DomainError>(None),
+                };
+                let user_ids = self.list_user_ids_page(filters, after).await?;
+                let next = if user_ids.len() < USER_PAGE_SIZE as usize {
+                    None
+                } else {
+                    Some(user_ids.last().cloned())
+                };
+                let users = if user_ids.is_empty() {
+                    Vec::new()
+                } else {
+                    self.list_users(
+                        Some(UserRequestFilter::Or(
+                            user_ids
+                                .into_iter()
+                                .map(UserRequestFilter::UserId)
+                                .collect(),
+                        )),
+                        get_groups,
+                    )
+                    .await?
+                };
+                Ok(Some((
+                    stream::iter(users.into_iter().map(Ok::<_, DomainError>)),
+                    next,
+                )))
+            }
+        })
+        .try_flatten()
+        .boxed_local()
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        order: UserOrder,
+        offset: u64,
+        limit: Option<u64>,
+    ) -> Result<UserPage> {
+        debug!(?filters, ?order, offset, ?limit);
+        let (user_ids, total_count) = self
+            .list_user_ids_window(filters, order, offset, limit)
+            .await?;
+        let mut users = if user_ids.is_empty() {
+            Vec::new()
+        } else {
+            self.list_users(
+                Some(UserRequestFilter::Or(
+                    user_ids
+                        .iter()
+                        .cloned()
+                        .map(UserRequestFilter::UserId)
+                        .collect(),
+                )),
+                get_groups,
+            )
+            .await?
+        };
+        // `list_users` returns the users sorted by ID, restore the requested order.
+        let positions: HashMap<_, _> = user_ids
+            .into_iter()
+            .enumerate()
+            .map(|(position, user_id)| (user_id, position))
+            .collect();
+        users.sort_by_key(|u| positions.get(&u.user.user_id).copied());
+        Ok(UserPage { users, total_count })
+    }
+
+    #[instrument(skip_all, level = "debug", ret)]
+    async fn get_user_details(&self, user_id: &UserId) -> Result<User> {
+        debug!(?user_id);
+        self.cached_user_details(self.read_pool(), user_id).await
+    }
+
+    #[instrument(skip_all, level = "debug", ret)]
+    async fn get_user_details_from_primary(&self, user_id: &UserId) -> Result<User> {
+        debug!(?user_id);
+        self.cached_user_details(&self.sql_pool, user_id).await
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>> {
+        debug!(?user_id);
+        self.cached_user_groups(self.read_pool(), user_id).await
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_user_groups_from_primary(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashSet<GroupDetails>> {
+        debug!(?user_id);
+        self.cached_user_groups(&self.sql_pool, user_id).await
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_users_groups(
+        &self,
+        user_ids: &[UserId],
+    ) -> Result<HashMap<UserId, HashSet<GroupDetails>>> {
+        debug!(count = user_ids.len());
+        let mut users_groups: HashMap<UserId, HashSet<GroupDetails>> = HashMap::new();
+        for (membership, group) in model::Membership::find()
+            .filter(MembershipColumn::UserId.is_in(user_ids.iter().cloned()))
+            .find_also_related(model::Group)
+            .all(self.read_pool())
+            .await?
+        {
+            if let Some(group) = group {
+                users_groups
+                    .entry(membership.user_id)
+                    .or_default()
+                    .insert(GroupDetails::from(group));
+            }
+        }
+        Ok(users_groups)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_user(&self, mut request: CreateUserRequest) -> Result<()> {
+        debug!(user_id = ?request.user_id);
+        let attributes = normalize_attribute_names(std::mem::take(&mut request.attributes));
+        self.validate_user_attributes(&attributes).await?;
+        // The memberships of the template are inserted along with the user.
+        let transaction = self.sql_pool.begin().await?;
+        let user_id = request.user_id.clone();
+        self.insert_new_user(&transaction, request, attributes)
+            .await?;
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.audit("create_user", AuditEvent::user_target(&user_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_users(&self, requests: Vec<CreateUserRequest>) -> Result<Vec<Result<()>>> {
+        debug!(count = requests.len());
+        // The attributes are validated before starting the transaction, which holds a connection.
+        let mut users = Vec::with_capacity(requests.len());
+        for mut request in requests {
+            let attributes = normalize_attribute_names(std::mem::take(&mut request.attributes));
+            users.push(
+                self.validate_user_attributes(&attributes)
+                    .await
+                    .map(|()| (request, attributes)),
+            );
+        }
+        let transaction = self.sql_pool.begin().await?;
+        let mut results = Vec::with_capacity(users.len());
+        let mut created = Vec::new();
+        for user in users {
+            results.push(match user {
+                Err(e) => Err(e),
+                Ok((request, attributes)) => {
+                    // Each user is created in a savepoint, so that a failure only rolls back
+                    // that user.
+                    let savepoint = transaction.begin().await?;
+                    let user_id = request.user_id.clone();
+                    match self.insert_new_user(&savepoint, request, attributes).await {
+                        Ok(_) => {
+                            savepoint.commit().await?;
+                            created.push(user_id);
+                            Ok(())
+                        }
+                        Err(e) => {
+                            savepoint.rollback().await?;
+                            Err(e)
+                        }
+                    }
+                }
+            });
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        for user_id in created {
+            self.audit("create_user", AuditEvent::user_target(&user_id), None)
+                .await;
+        }
+        Ok(results)
+    }
+
+    fn check_user_id(&self, user_id: &str) -> Result<()> {
+        check_user_id(&self.config.username_policy, user_id)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn update_user(&self, mut request: UpdateUserRequest) -> Result<()> {
+        debug!(user_id = ?request.user_id);
+        let target = AuditEvent::user_target(&request.user_id);
+        let details = updated_user_fields(&request);
+        let insert_attributes =
+            normalize_attribute_names(std::mem::take(&mut request.insert_attributes));
+        self.validate_user_attributes(&insert_attributes).await?;
+        self.apply_user_update(&self.sql_pool, request, insert_attributes)
+            .await?;
+        self.invalidate_cache();
+        self.audit("update_user", target, details).await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()> {
+        debug!(?user_id);
+        self.update_ssh_public_keys(user_id, |keys| {
+            if keys.iter().any(|k| k == key) {
+                return Err(DomainError::InvalidRequest(
+                    "SSH key already present".to_owned(),
+                ));
+            }
+            keys.push(key.to_owned());
+            Ok(())
+        })
+        .await?;
+        self.audit("add_ssh_public_key", AuditEvent::user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()> {
+        debug!(?user_id);
+        self.update_ssh_public_keys(user_id, |keys| {
+            let num_keys = keys.len();
+            keys.retain(|k| k != key);
+            if keys.len() == num_keys {
+                return Err(DomainError::EntityNotFound("No such SSH key".to_owned()));
+            }
+            Ok(())
+        })
+        .await?;
+        self.audit(
+            "remove_ssh_public_key",
+            AuditEvent::user_target(user_id),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<()> {
+        debug!(?user_id, ?new_user_id);
+        check_user_id(&self.config.username_policy, new_user_id.as_str())?;
+        let transaction = self.sql_pool.begin().await?;
+        if model::User::find_by_id(new_user_id.clone())
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::InvalidRequest(format!(
+                "User '{}' already exists",
+                new_user_id
+            )));
+        }
+        // The password hash is stored with the user, and the memberships, attributes and tokens
+        // follow the new ID through `ON UPDATE CASCADE`.
+        let res = model::User::update_many()
+            .col_expr(UserColumn::UserId, Expr::value(new_user_id.clone()))
+            .col_expr(UserColumn::ModifiedDate, Expr::value(chrono::Utc::now()))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&transaction)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        self.log_user_modification(
+            &transaction,
+            new_user_id,
+            ChangeDiff {
+                renamed_from: Some(user_id.to_string()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        // The groups' member list changed.
+        let group_ids: Vec<GroupId> = model::Membership::find()
+            .filter(MembershipColumn::UserId.eq(new_user_id.clone()))
+            .all(&transaction)
+            .await?
+            .into_iter()
+            .map(|m| m.group_id)
+            .collect();
+        for group_id in group_ids {
+            self.log_group_modification(
+                &transaction,
+                group_id,
+                ChangeDiff {
+                    added_memberships: vec![new_user_id.to_string()],
+                    removed_memberships: vec![user_id.to_string()],
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.audit(
+            "rename_user",
+            AuditEvent::user_target(user_id),
+            Some(AuditEvent::user_target(new_user_id)),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_user(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        let transaction = self.sql_pool.begin().await?;
+        let group_ids: Vec<GroupId> = model::Membership::find()
+            .filter(MembershipColumn::UserId.eq(user_id.clone()))
+            .all(&transaction)
+            .await?
+            .into_iter()
+            .map(|m| m.group_id)
+            .collect();
+        self.check_admins_remain(&transaction, None, std::slice::from_ref(user_id))
+            .await?;
+        // Fails if the user doesn't exist.
+        self.copy_user_to_recycle_bin(&transaction, user_id).await?;
+        self.log_user_change(&transaction, user_id, ChangeType::Delete)
+            .await?;
+        model::User::delete_by_id(user_id.clone())
+            .exec(&transaction)
+            .await?;
+        // The groups lost a member.
+        for group_id in group_ids {
+            self.log_group_change(&transaction, group_id, ChangeType::Modify)
+                .await?;
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.audit("delete_user", AuditEvent::user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
+        debug!(?user_id, ?group_id);
+        self.insert_membership(&self.sql_pool, user_id, group_id, None)
+            .await?;
+        self.invalidate_cache();
+        self.publish_membership_changes(std::slice::from_ref(user_id), group_id, true);
+        self.audit_memberships("add_user_to_group", std::slice::from_ref(user_id), group_id)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_user_to_group_until(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: DateTime,
+    ) -> Result<()> {
+        debug!(?user_id, ?group_id, ?expiry_date);
+        self.insert_membership(&self.sql_pool, user_id, group_id, Some(expiry_date))
+            .await?;
+        self.invalidate_cache();
+        self.publish_membership_changes(std::slice::from_ref(user_id), group_id, true);
+        self.audit_memberships("add_user_to_group", std::slice::from_ref(user_id), group_id)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()> {
+        debug!(?user_ids, ?group_id);
+        let transaction = self.sql_pool.begin().await?;
+        for user_id in user_ids {
+            self.insert_membership(&transaction, user_id, group_id, None)
+                .await?;
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.publish_membership_changes(user_ids, group_id, true);
+        self.audit_memberships("add_user_to_group", user_ids, group_id)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
+        debug!(?user_id, ?group_id);
+        let transaction = self.sql_pool.begin().await?;
+        self.check_admins_remain(&transaction, Some(group_id), std::slice::from_ref(user_id))
+            .await?;
+        self.delete_membership(&transaction, user_id, group_id)
+            .await?;
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.publish_membership_changes(std::slice::from_ref(user_id), group_id, false);
+        self.audit_memberships(
+            "remove_user_from_group",
+            std::slice::from_ref(user_id),
+            group_id,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_users_from_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()> {
+        debug!(?user_ids, ?group_id);
+        let transaction = self.sql_pool.begin().await?;
+        self.check_admins_remain(&transaction, Some(group_id), user_ids)
+            .await?;
+        for user_id in user_ids {
+            self.delete_membership(&transaction, user_id, group_id)
+                .await?;
+        }
+        transaction.commit().await?;
+        self.invalidate_cache();
+        self.publish_membership_changes(user_ids, group_id, false);
+        self.audit_memberships("remove_user_from_group", user_ids, group_id)
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn record_login(&self, user_id: &UserId, kind: LoginKind) -> Result<()> {
+        debug!(?user_id, ?kind);
+        let column = match kind {
+            LoginKind::Ldap => UserColumn::LastLdapLogin,
+            LoginKind::Web => UserColumn::LastWebLogin,
+        };
+        let now = chrono::Utc::now();
+        // Neither the modification date nor the cache are updated: it's not a change to the user.
+        // Logging in cancels the warnings about the inactive accounts.
+        model::User::update_many()
+            .col_expr(column, Expr::value(now))
+            .col_expr(UserColumn::StaleWarningDate, Expr::value(None::<DateTime>))
+            .filter(UserColumn::UserId.eq(user_id))
+            .filter(
+                Cond::any()
+                    .add(column.is_null())
+                    .add(column.lt(now - chrono::Duration::minutes(LOGIN_RECORD_INTERVAL_MINUTES))),
+            )
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, SortDirection, SubStringFilter},
+        sql_backend_handler::tests::*,
+        types::{JpegPhoto, SshPublicKeys, UserColumn},
+    };
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_list_users_no_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(&fixture.handler, None).await;
+        assert_eq!(users, vec!["bob", "john", "nogroup", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_user_id_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::UserId(UserId::new("bob"))),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_display_name_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Equality(
+                UserColumn::DisplayName,
+                "display bob".to_string(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_case_ignore_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Or(vec![
+                UserRequestFilter::Equality(
+                    UserColumn::DisplayName,
+                    "  Display   BOB ".to_string(),
+                ),
+                UserRequestFilter::MemberOf("empty GROUP".to_string()),
+            ])),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOf("best group".to_string())),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_substring_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::SubString(
+                UserColumn::DisplayName,
+                SubStringFilter {
+                    initial: Some("DISPLAY".to_owned()),
+                    any: vec![],
+                    final_: Some("ick".to_owned()),
+                },
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+        // LIKE wildcards in the filter are matched literally.
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::SubString(
+                UserColumn::UserId,
+                SubStringFilter {
+                    initial: None,
+                    any: vec!["_".to_owned()],
+                    final_: None,
+                },
+            )),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn test_list_users_present_filter() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("john"),
+                login_shell: Some("/bin/sh".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Present(UserColumn::LoginShell)),
+        )
+        .await;
+        assert_eq!(users, vec!["john"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_users() {
+        let fixture = TestFixture::new().await;
+        for (filters, get_groups) in [
+            (None, false),
+            (None, true),
+            (Some(UserRequestFilter::MemberOfId(fixture.groups[0])), true),
+            (
+                Some(UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(
+                    UserId::new("bob"),
+                )))),
+                false,
+            ),
+        ] {
+            let streamed: Vec<_> = fixture
+                .handler
+                .stream_users(filters.clone(), get_groups)
+                .try_collect()
+                .await
+                .unwrap();
+            let listed = fixture
+                .handler
+                .list_users(filters, get_groups)
+                .await
+                .unwrap();
+            assert_eq!(streamed, listed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_users_page() {
+        let fixture = TestFixture::new().await;
+        let get_page = |filters: Option<UserRequestFilter>, offset: u64, limit: Option<u64>| {
+            let handler = &fixture.handler;
+            async move {
+                let page = handler
+                    .list_users_page(filters, false, UserOrder::default(), offset, limit)
+                    .await
+                    .unwrap();
+                (
+                    page.users
+                        .into_iter()
+                        .map(|u| u.user.user_id.to_string())
+                        .collect::<Vec<_>>(),
+                    page.total_count,
+                )
+            }
+        };
+        assert_eq!(
+            get_page(None, 0, Some(2)).await,
+            (vec!["bob".to_owned(), "john".to_owned()], 4)
+        );
+        assert_eq!(
+            get_page(None, 2, Some(3)).await,
+            (vec!["nogroup".to_owned(), "patrick".to_owned()], 4)
+        );
+        assert_eq!(get_page(None, 4, None).await, (vec![], 4));
+        assert_eq!(
+            get_page(
+                Some(UserRequestFilter::MemberOfId(fixture.groups[1])),
+                1,
+                None
+            )
+            .await,
+            (vec!["patrick".to_owned()], 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_page_sorted() {
+        let fixture = TestFixture::new().await;
+        let page = fixture
+            .handler
+            .list_users_page(
+                None,
+                true,
+                UserOrder {
+                    column: UserColumn::DisplayName,
+                    direction: SortDirection::Descending,
+                },
+                1,
+                Some(2),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            page.users
+                .iter()
+                .map(|u| u.user.user_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bob", "nogroup"]
+        );
+        assert_eq!(page.total_count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_other_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Equality(
+                UserColumn::FirstName,
+                "first bob".to_string(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_false_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
+                vec![],
+            )))),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn test_list_users_member_of() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOf("Best Group".to_string())),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_member_of_and_uuid() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Or(vec![
+                UserRequestFilter::MemberOf("Best Group".to_string()),
+                UserRequestFilter::Equality(UserColumn::Uuid, "abc".to_string()),
+            ])),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_member_of_id() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_member_of_uuid() {
+        let fixture = TestFixture::new().await;
+        let uuid = fixture
+            .handler
+            .get_group_details(fixture.groups[0])
+            .await
+            .unwrap()
+            .uuid;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOfUuid(uuid)),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_nested_groups() {
+        let fixture = TestFixture::new().await;
+        // "Empty Group" contains "Worst Group".
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[2], fixture.groups[1])
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOf("Empty Group".to_string())),
+        )
+        .await;
+        assert_eq!(users, vec!["john", "patrick"]);
+        let users = fixture
+            .handler
+            .list_users(Some(UserRequestFilter::UserId(UserId::new("john"))), true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| {
+                u.groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|g| g.group_id)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(users, vec![vec![fixture.groups[1], fixture.groups[2]]]);
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn test_list_users_invalid_userid_filter() {
+        let fixture = TestFixture::new().await;
+        get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Equality(
+                UserColumn::UserId,
+                "first bob".to_string(),
+            )),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_list_users_filter_or() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Or(vec![
+                UserRequestFilter::UserId(UserId::new("bob")),
+                UserRequestFilter::UserId(UserId::new("John")),
+            ])),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "john"]);
     }
 
     #[tokio::test]
-    async fn test_list_users_false_filter() {
+    async fn test_list_users_filter_many_or() {
         let fixture = TestFixture::new().await;
         let users = get_user_names(
             &fixture.handler,
-            Some(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
-                vec![],
-            )))),
+            Some(UserRequestFilter::Or(vec![
+                UserRequestFilter::Or(vec![]),
+                UserRequestFilter::Or(vec![
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::UserId(UserId::new("John")),
+                    UserRequestFilter::UserId(UserId::new("random")),
+                ]),
+            ])),
         )
         .await;
-        assert_eq!(users, Vec::<String>::new());
+        assert_eq!(users, vec!["bob", "john"]);
     }
 
     #[tokio::test]
-    async fn test_list_users_member_of() {
+    async fn test_list_users_filter_not() {
         let fixture = TestFixture::new().await;
         let users = get_user_names(
             &fixture.handler,
-            Some(UserRequestFilter::MemberOf("Best Group".to_string())),
+            Some(UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(
+                UserId::new("bob"),
+            )))),
         )
         .await;
-        assert_eq!(users, vec!["bob", "patrick"]);
+        assert_eq!(users, vec!["john", "nogroup", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_with_groups() {
+        let fixture = TestFixture::new().await;
+        let users = fixture
+            .handler
+            .list_users(None, true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| {
+                (
+                    u.user.user_id.to_string(),
+                    u.user
+                        .display_name
+                        .as_deref()
+                        .unwrap_or("<unknown>")
+                        .to_owned(),
+                    u.groups
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|g| g.group_id)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            users,
+            vec![
+                (
+                    "bob".to_string(),
+                    "display bob".to_string(),
+                    vec![fixture.groups[0]]
+                ),
+                (
+                    "john".to_string(),
+                    "display John".to_string(),
+                    vec![fixture.groups[1]]
+                ),
+                ("nogroup".to_string(), "display NoGroup".to_string(), vec![]),
+                (
+                    "patrick".to_string(),
+                    "display patrick".to_string(),
+                    vec![fixture.groups[0], fixture.groups[1]]
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_groups_have_different_creation_date_than_users() {
+        let fixture = TestFixture::new().await;
+        let users = fixture
+            .handler
+            .list_users(None, true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| {
+                (
+                    u.user.creation_date,
+                    u.groups
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|g| g.creation_date)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (user_date, groups) in users {
+            for group_date in groups {
+                assert_ne!(user_date, group_date);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_user_details() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        {
+            let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+            assert_eq!(user.user_id.as_str(), "bob");
+            assert!(user.enabled);
+        }
+        {
+            handler
+                .get_user_details(&UserId::new("John"))
+                .await
+                .unwrap_err();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_lowercase() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user_no_password(&handler, "Bob").await;
+        {
+            let user = handler.get_user_details(&UserId::new("bOb")).await.unwrap();
+            assert_eq!(user.user_id.as_str(), "bob");
+        }
+        {
+            handler
+                .get_user_details(&UserId::new("John"))
+                .await
+                .unwrap_err();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .delete_user(&UserId::new("bob"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["john", "nogroup", "patrick"]
+        );
+
+        // Insert new user and remove two
+        insert_user_no_password(&fixture.handler, "NewBoi").await;
+        fixture
+            .handler
+            .delete_user(&UserId::new("nogroup"))
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .delete_user(&UserId::new("NewBoi"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["john", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user() {
+        let fixture = TestFixture::new().await;
+        insert_user(&fixture.handler, "alice", "password").await;
+        fixture
+            .handler
+            .add_user_to_group(&UserId::new("alice"), fixture.groups[1])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .rename_user(&UserId::new("alice"), &UserId::new("Alicia"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["alicia", "bob", "john", "nogroup", "patrick"]
+        );
+        let user = model::User::find_by_id(UserId::new("alicia"))
+            .one(&fixture.handler.sql_pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.password_hash.is_some());
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_groups(&UserId::new("alicia"))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![fixture.groups[1]]
+        );
+
+        // The new ID is taken.
+        fixture
+            .handler
+            .rename_user(&UserId::new("alicia"), &UserId::new("bob"))
+            .await
+            .unwrap_err();
+        // The user doesn't exist.
+        fixture
+            .handler
+            .rename_user(&UserId::new("alice"), &UserId::new("carol"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["alicia", "bob", "john", "nogroup", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_user_groups() {
+        let fixture = TestFixture::new().await;
+        let get_group_ids = |user: &'static str| async {
+            let mut groups = fixture
+                .handler
+                .get_user_groups(&UserId::new(user))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>();
+            groups.sort_by(|g1, g2| g1.0.cmp(&g2.0));
+            groups
+        };
+        assert_eq!(get_group_ids("bob").await, vec![fixture.groups[0]]);
+        assert_eq!(
+            get_group_ids("patrick").await,
+            vec![fixture.groups[0], fixture.groups[1]]
+        );
+        assert_eq!(get_group_ids("nogroup").await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_get_users_groups() {
+        let fixture = TestFixture::new().await;
+        let users_groups = fixture
+            .handler
+            .get_users_groups(&[
+                UserId::new("bob"),
+                UserId::new("patrick"),
+                UserId::new("nogroup"),
+            ])
+            .await
+            .unwrap();
+        let get_group_ids = |user: &str| {
+            let mut groups = users_groups[&UserId::new(user)]
+                .iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>();
+            groups.sort();
+            groups
+        };
+        assert_eq!(get_group_ids("bob"), vec![fixture.groups[0]]);
+        assert_eq!(
+            get_group_ids("patrick"),
+            vec![fixture.groups[0], fixture.groups[1]]
+        );
+        assert!(!users_groups.contains_key(&UserId::new("nogroup")));
+        assert!(!users_groups.contains_key(&UserId::new("John")));
+    }
+
+    #[tokio::test]
+    async fn test_create_users() {
+        let fixture = TestFixture::new().await;
+        let results = fixture
+            .handler
+            .create_users(
+                ["alice", "bob", "carol"]
+                    .iter()
+                    .map(|user_id| CreateUserRequest {
+                        user_id: UserId::new(user_id),
+                        email: format!("{}@example.com", user_id),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            results.iter().map(Result::is_ok).collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["alice", "bob", "carol", "john", "nogroup", "patrick"]
+        );
     }
 
     #[tokio::test]
-    async fn test_list_users_member_of_and_uuid() {
+    async fn test_update_user_all_values() {
         let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Or(vec![
-                UserRequestFilter::MemberOf("Best Group".to_string()),
-                UserRequestFilter::Equality(UserColumn::Uuid, "abc".to_string()),
-            ])),
-        )
-        .await;
-        assert_eq!(users, vec!["bob", "patrick"]);
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("email".to_string()),
+                display_name: Some("display_name".to_string()),
+                first_name: Some("first_name".to_string()),
+                last_name: Some("last_name".to_string()),
+                avatar: Some(JpegPhoto::for_tests()),
+                uid_number: Some(1000),
+                gid_number: Some(1000),
+                home_directory: Some("/home/bob".to_string()),
+                login_shell: Some("/bin/bash".to_string()),
+                telephone_number: Some("+1 555 0100".to_string()),
+                mobile: Some("+1 555 0101".to_string()),
+                title: Some("Engineer".to_string()),
+                department: Some("R&D".to_string()),
+                locality: Some("Springfield".to_string()),
+                postal_address: Some("742 Evergreen Terrace".to_string()),
+                locale: Some("fr_ca".to_string()),
+                timezone: Some("America/Montreal".to_string()),
+                ssh_public_keys: Some(SshPublicKeys::new(vec![
+                    ED25519_PUBLIC_KEY.to_string(),
+                    RSA_PUBLIC_KEY.to_string(),
+                ])),
+                secondary_emails: None,
+                enabled: Some(false),
+                valid_from: Some(None),
+                valid_until: Some(Some(chrono::Utc.timestamp_opt(42, 0).unwrap())),
+                insert_attributes: Vec::new(),
+                delete_attributes: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(user.email, "email");
+        assert_eq!(user.display_name.unwrap(), "display_name");
+        assert_eq!(user.first_name.unwrap(), "first_name");
+        assert_eq!(user.last_name.unwrap(), "last_name");
+        assert_eq!(user.avatar, Some(JpegPhoto::for_tests()));
+        assert_eq!(user.uid_number, Some(1000));
+        assert_eq!(user.gid_number, Some(1000));
+        assert_eq!(user.home_directory.unwrap(), "/home/bob");
+        assert_eq!(user.login_shell.unwrap(), "/bin/bash");
+        assert_eq!(user.telephone_number.unwrap(), "+1 555 0100");
+        assert_eq!(user.mobile.unwrap(), "+1 555 0101");
+        assert_eq!(user.title.unwrap(), "Engineer");
+        assert_eq!(user.department.unwrap(), "R&D");
+        assert_eq!(user.locality.unwrap(), "Springfield");
+        assert_eq!(user.postal_address.unwrap(), "742 Evergreen Terrace");
+        assert_eq!(user.locale.unwrap(), "fr-CA");
+        assert_eq!(user.timezone.unwrap(), "America/Montreal");
+        assert_eq!(
+            user.ssh_public_keys.into_vec(),
+            vec![ED25519_PUBLIC_KEY.to_string(), RSA_PUBLIC_KEY.to_string()]
+        );
+        assert!(!user.enabled);
+        assert_eq!(user.valid_from, None);
+        assert_eq!(
+            user.valid_until,
+            Some(chrono::Utc.timestamp_opt(42, 0).unwrap())
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::Equality(
+                    UserColumn::UidNumber,
+                    "1000".to_string()
+                ))
+            )
+            .await,
+            vec!["bob"]
+        );
     }
 
     #[tokio::test]
-    async fn test_list_users_member_of_id() {
+    async fn test_update_user_some_values() {
         let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
-        )
-        .await;
-        assert_eq!(users, vec!["bob", "patrick"]);
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                first_name: Some("first_name".to_string()),
+                last_name: Some(String::new()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(user.display_name.unwrap(), "display bob");
+        assert_eq!(user.first_name.unwrap(), "first_name");
+        assert_eq!(user.last_name, None);
+        assert_eq!(user.avatar, None);
     }
 
     #[tokio::test]
-    #[should_panic]
-    async fn test_list_users_invalid_userid_filter() {
+    async fn test_add_and_remove_ssh_public_keys() {
         let fixture = TestFixture::new().await;
-        get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Equality(
-                UserColumn::UserId,
-                "first bob".to_string(),
-            )),
-        )
-        .await;
+        let bob = UserId::new("bob");
+        let get_keys = || async {
+            fixture
+                .handler
+                .get_user_details(&bob)
+                .await
+                .unwrap()
+                .ssh_public_keys
+                .into_vec()
+        };
+        for key in [ED25519_PUBLIC_KEY, ECDSA_PUBLIC_KEY, RSA_PUBLIC_KEY] {
+            fixture.handler.add_ssh_public_key(&bob, key).await.unwrap();
+        }
+        fixture
+            .handler
+            .add_ssh_public_key(&bob, ECDSA_PUBLIC_KEY)
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .remove_ssh_public_key(&bob, ECDSA_PUBLIC_KEY)
+            .await
+            .unwrap();
+        assert_eq!(get_keys().await, vec![ED25519_PUBLIC_KEY, RSA_PUBLIC_KEY]);
+        fixture
+            .handler
+            .remove_ssh_public_key(&bob, ECDSA_PUBLIC_KEY)
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .add_ssh_public_key(&UserId::new("nobody"), ED25519_PUBLIC_KEY)
+            .await
+            .unwrap_err();
+        assert_eq!(get_keys().await, vec![ED25519_PUBLIC_KEY, RSA_PUBLIC_KEY]);
     }
 
     #[tokio::test]
-    async fn test_list_users_filter_or() {
+    async fn test_disable_user_revokes_sessions() {
+        use crate::domain::handler::SessionBackendHandler;
+        use sea_orm::{ActiveModelTrait, IntoActiveModel};
         let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Or(vec![
-                UserRequestFilter::UserId(UserId::new("bob")),
-                UserRequestFilter::UserId(UserId::new("John")),
-            ])),
-        )
-        .await;
-        assert_eq!(users, vec!["bob", "john"]);
+        for (session_id, user) in [(1, "bob"), (2, "bob"), (3, "patrick")] {
+            model::jwt_refresh_storage::Model {
+                refresh_token_hash: session_id,
+                user_id: UserId::new(user),
+                expiry_date: chrono::Utc::now() + chrono::Duration::days(30),
+                creation_date: None,
+                last_used: None,
+                user_agent: None,
+                ip_address: None,
+            }
+            .into_active_model()
+            .insert(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        }
+        let bob = UserId::new("bob");
+        let patrick = UserId::new("patrick");
+        // Other changes keep the sessions.
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                display_name: Some("Bob".to_owned()),
+                enabled: Some(true),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_user_sessions(&bob)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                enabled: Some(false),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .list_user_sessions(&bob)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            fixture
+                .handler
+                .list_user_sessions(&patrick)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
     }
 
     #[tokio::test]
-    async fn test_list_users_filter_many_or() {
+    async fn test_add_user_to_group_until() {
         let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Or(vec![
-                UserRequestFilter::Or(vec![]),
-                UserRequestFilter::Or(vec![
-                    UserRequestFilter::UserId(UserId::new("bob")),
-                    UserRequestFilter::UserId(UserId::new("John")),
-                    UserRequestFilter::UserId(UserId::new("random")),
-                ]),
-            ])),
-        )
-        .await;
-        assert_eq!(users, vec!["bob", "john"]);
+        let expiry_date = chrono::Utc.timestamp_opt(1_000_000_000, 0).unwrap();
+        fixture
+            .handler
+            .add_user_to_group_until(&UserId::new("nogroup"), fixture.groups[2], expiry_date)
+            .await
+            .unwrap();
+        let membership = model::Membership::find_by_id((UserId::new("nogroup"), fixture.groups[2]))
+            .one(&fixture.handler.sql_pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(membership.expiry_date, Some(expiry_date));
+        let membership = model::Membership::find_by_id((UserId::new("bob"), fixture.groups[0]))
+            .one(&fixture.handler.sql_pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(membership.expiry_date, None);
     }
 
     #[tokio::test]
-    async fn test_list_users_filter_not() {
+    async fn test_remove_last_admin() {
         let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(
-                UserId::new("bob"),
-            )))),
-        )
-        .await;
-        assert_eq!(users, vec!["john", "nogroup", "patrick"]);
+        let admin_group = insert_group(&fixture.handler, "lldap_admin").await;
+        insert_membership(&fixture.handler, admin_group, "bob").await;
+        insert_membership(&fixture.handler, admin_group, "patrick").await;
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("bob"), admin_group)
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .remove_user_from_group(&UserId::new("patrick"), admin_group)
+                .await,
+            Err(DomainError::LastAdmin(_))
+        ));
+        assert!(matches!(
+            fixture
+                .handler
+                .remove_users_from_group(&[UserId::new("patrick")], admin_group)
+                .await,
+            Err(DomainError::LastAdmin(_))
+        ));
+        assert!(matches!(
+            fixture.handler.delete_user(&UserId::new("patrick")).await,
+            Err(DomainError::LastAdmin(_))
+        ));
+        // The other groups of the last admin can lose them.
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(admin_group)),
+            )
+            .await,
+            vec!["patrick"]
+        );
     }
 
     #[tokio::test]
-    async fn test_list_users_with_groups() {
+    async fn test_remove_user_from_group() {
         let fixture = TestFixture::new().await;
-        let users = fixture
+
+        fixture
             .handler
-            .list_users(None, true)
+            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
             .await
-            .unwrap()
-            .into_iter()
-            .map(|u| {
-                (
-                    u.user.user_id.to_string(),
-                    u.user
-                        .display_name
-                        .as_deref()
-                        .unwrap_or("<unknown>")
-                        .to_owned(),
-                    u.groups
-                        .unwrap_or_default()
-                        .into_iter()
-                        .map(|g| g.group_id)
-                        .collect::<Vec<_>>(),
-                )
-            })
-            .collect::<Vec<_>>();
+            .unwrap();
+
         assert_eq!(
-            users,
-            vec![
-                (
-                    "bob".to_string(),
-                    "display bob".to_string(),
-                    vec![fixture.groups[0]]
-                ),
-                (
-                    "john".to_string(),
-                    "display John".to_string(),
-                    vec![fixture.groups[1]]
-                ),
-                ("nogroup".to_string(), "display NoGroup".to_string(), vec![]),
-                (
-                    "patrick".to_string(),
-                    "display patrick".to_string(),
-                    vec![fixture.groups[0], fixture.groups[1]]
-                ),
-            ]
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+            .await,
+            vec!["patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_user_groups_invalidated() {
+        let fixture = TestFixture::new().await;
+        let handler = fixture.handler.clone().with_cache(
+            &crate::infra::configuration::CacheOptionsBuilder::default()
+                .enabled(true)
+                .build()
+                .unwrap(),
         );
+        let bob = UserId::new("bob");
+        assert_eq!(handler.get_user_groups(&bob).await.unwrap().len(), 1);
+        handler
+            .remove_user_from_group(&bob, fixture.groups[0])
+            .await
+            .unwrap();
+        assert!(handler.get_user_groups(&bob).await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_list_users_groups_have_different_creation_date_than_users() {
+    async fn test_membership_security_events() {
         let fixture = TestFixture::new().await;
-        let users = fixture
-            .handler
-            .list_users(None, true)
+        let bus = crate::domain::security_events::new_bus();
+        let mut events = bus.subscribe();
+        let handler = fixture.handler.clone().with_security_events(bus);
+        let bob = UserId::new("bob");
+        handler
+            .remove_user_from_group(&bob, fixture.groups[0])
             .await
-            .unwrap()
-            .into_iter()
-            .map(|u| {
-                (
-                    u.user.creation_date,
-                    u.groups
-                        .unwrap_or_default()
-                        .into_iter()
-                        .map(|g| g.creation_date)
-                        .collect::<Vec<_>>(),
-                )
-            })
-            .collect::<Vec<_>>();
-        for (user_date, groups) in users {
-            for group_date in groups {
-                assert_ne!(user_date, group_date);
+            .unwrap();
+        handler
+            .add_user_to_group(&bob, fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SecurityEvent::GroupMembershipChanged {
+                user_id: bob.clone(),
+                group_id: fixture.groups[0],
+                added: false,
             }
-        }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SecurityEvent::GroupMembershipChanged {
+                user_id: bob,
+                group_id: fixture.groups[1],
+                added: true,
+            }
+        );
+        assert!(events.try_recv().is_err());
     }
 
     #[tokio::test]
-    async fn test_get_user_details() {
-        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
-        insert_user_no_password(&handler, "bob").await;
-        {
-            let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
-            assert_eq!(user.user_id.as_str(), "bob");
-        }
-        {
-            handler
-                .get_user_details(&UserId::new("John"))
-                .await
-                .unwrap_err();
-        }
-    }
+    async fn test_add_and_remove_users_to_group() {
+        let fixture = TestFixture::new().await;
+        let get_members = || {
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[2])),
+            )
+        };
 
-    #[tokio::test]
-    async fn test_user_lowercase() {
-        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
-        insert_user_no_password(&handler, "Bob").await;
-        {
-            let user = handler.get_user_details(&UserId::new("bOb")).await.unwrap();
-            assert_eq!(user.user_id.as_str(), "bob");
-        }
-        {
-            handler
-                .get_user_details(&UserId::new("John"))
-                .await
-                .unwrap_err();
-        }
-    }
+        fixture
+            .handler
+            .add_users_to_group(
+                &[UserId::new("john"), UserId::new("nogroup")],
+                fixture.groups[2],
+            )
+            .await
+            .unwrap();
+        assert_eq!(get_members().await, vec!["john", "nogroup"]);
 
-    #[tokio::test]
-    async fn test_delete_user() {
-        let fixture = TestFixture::new().await;
+        // John is already a member: nothing is added.
         fixture
             .handler
-            .delete_user(&UserId::new("bob"))
+            .add_users_to_group(
+                &[UserId::new("bob"), UserId::new("john")],
+                fixture.groups[2],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(get_members().await, vec!["john", "nogroup"]);
+
+        // Bob is not a member: nothing is removed.
+        fixture
+            .handler
+            .remove_users_from_group(
+                &[UserId::new("john"), UserId::new("bob")],
+                fixture.groups[2],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(get_members().await, vec!["john", "nogroup"]);
+
+        fixture
+            .handler
+            .remove_users_from_group(
+                &[UserId::new("john"), UserId::new("nogroup")],
+                fixture.groups[2],
+            )
             .await
             .unwrap();
+        assert!(get_members().await.is_empty());
+    }
 
+    #[tokio::test]
+    async fn test_list_users_modified_since() {
+        let fixture = TestFixture::new().await;
+        let before_update = chrono::Utc::now();
         assert_eq!(
-            get_user_names(&fixture.handler, None).await,
-            vec!["john", "nogroup", "patrick"]
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::ModifiedSince(before_update)),
+            )
+            .await,
+            Vec::<String>::new()
         );
 
-        // Insert new user and remove two
-        insert_user_no_password(&fixture.handler, "NewBoi").await;
         fixture
             .handler
-            .delete_user(&UserId::new("nogroup"))
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                first_name: Some("first_name".to_string()),
+                ..Default::default()
+            })
             .await
             .unwrap();
         fixture
             .handler
-            .delete_user(&UserId::new("NewBoi"))
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[0])
             .await
             .unwrap();
 
         assert_eq!(
-            get_user_names(&fixture.handler, None).await,
-            vec!["john", "patrick"]
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::ModifiedSince(before_update)),
+            )
+            .await,
+            vec!["bob", "patrick"]
         );
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert!(user.modified_date >= before_update);
+        assert!(user.creation_date < before_update);
     }
 
     #[tokio::test]
-    async fn test_get_user_groups() {
+    async fn test_record_login() {
         let fixture = TestFixture::new().await;
-        let get_group_ids = |user: &'static str| async {
-            let mut groups = fixture
+        let bob = UserId::new("bob");
+        let before_login = chrono::Utc::now();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::InactiveSince(before_login)),
+            )
+            .await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+
+        fixture
+            .handler
+            .record_login(&bob, LoginKind::Ldap)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .record_login(&UserId::new("patrick"), LoginKind::Web)
+            .await
+            .unwrap();
+        let user = fixture.handler.get_user_details(&bob).await.unwrap();
+        let last_ldap_login = user.last_ldap_login.unwrap();
+        assert!(last_ldap_login >= before_login);
+        assert_eq!(user.last_web_login, None);
+        assert!(user.modified_date < before_login);
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::InactiveSince(before_login)),
+            )
+            .await,
+            vec!["john", "nogroup"]
+        );
+
+        // Too soon to be written again.
+        fixture
+            .handler
+            .record_login(&bob, LoginKind::Ldap)
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
                 .handler
-                .get_user_groups(&UserId::new(user))
+                .get_user_details(&bob)
                 .await
                 .unwrap()
-                .into_iter()
-                .map(|g| g.group_id)
-                .collect::<Vec<_>>();
-            groups.sort_by(|g1, g2| g1.0.cmp(&g2.0));
-            groups
-        };
-        assert_eq!(get_group_ids("bob").await, vec![fixture.groups[0]]);
-        assert_eq!(
-            get_group_ids("patrick").await,
-            vec![fixture.groups[0], fixture.groups[1]]
+                .last_ldap_login,
+            Some(last_ldap_login)
         );
-        assert_eq!(get_group_ids("nogroup").await, vec![]);
     }
 
     #[tokio::test]
-    async fn test_update_user_all_values() {
+    async fn test_update_avatar_removes_remote_avatar() {
         let fixture = TestFixture::new().await;
-
+        model::remote_avatars::ActiveModel {
+            user_id: Set(UserId::new("bob")),
+            email_hash: Set("hash".to_owned()),
+            fetch_date: Set(chrono::Utc::now()),
+        }
+        .insert(&fixture.handler.sql_pool)
+        .await
+        .unwrap();
         fixture
             .handler
             .update_user(UpdateUserRequest {
                 user_id: UserId::new("bob"),
-                email: Some("email".to_string()),
-                display_name: Some("display_name".to_string()),
-                first_name: Some("first_name".to_string()),
-                last_name: Some("last_name".to_string()),
                 avatar: Some(JpegPhoto::for_tests()),
+                ..Default::default()
             })
             .await
             .unwrap();
+        assert!(model::RemoteAvatars::find_by_id(UserId::new("bob"))
+            .one(&fixture.handler.sql_pool)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_user_invalid_locale_and_timezone() {
+        let fixture = TestFixture::new().await;
+        for (locale, timezone) in [
+            (Some("french"), None),
+            (Some("fr--CA"), None),
+            (None, Some("Europe/Paris; DROP")),
+        ] {
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new("bob"),
+                    locale: locale.map(str::to_owned),
+                    timezone: timezone.map(str::to_owned),
+                    ..Default::default()
+                })
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(
+            normalize_locale(Some("zh_hant_tw".to_owned())).unwrap(),
+            Some("zh-Hant-TW".to_owned())
+        );
+    }
 
+    fn set_secondary_emails(user_id: &str, emails: &[&str]) -> UpdateUserRequest {
+        UpdateUserRequest {
+            user_id: UserId::new(user_id),
+            secondary_emails: Some(emails.iter().map(|e| e.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_secondary_emails() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .update_user(set_secondary_emails(
+                "bob",
+                &[
+                    "Bob.Alias@example.com ",
+                    "bob.alias@example.com",
+                    "b@example.com",
+                ],
+            ))
+            .await
+            .unwrap();
         let user = fixture
             .handler
             .get_user_details(&UserId::new("bob"))
             .await
             .unwrap();
-        assert_eq!(user.email, "email");
-        assert_eq!(user.display_name.unwrap(), "display_name");
-        assert_eq!(user.first_name.unwrap(), "first_name");
-        assert_eq!(user.last_name.unwrap(), "last_name");
-        assert_eq!(user.avatar, Some(JpegPhoto::for_tests()));
-    }
-
-    #[tokio::test]
-    async fn test_update_user_some_values() {
-        let fixture = TestFixture::new().await;
+        assert_eq!(
+            user.secondary_emails,
+            vec!["b@example.com", "bob.alias@example.com"]
+        );
+        let users = fixture.handler.list_users(None, false).await.unwrap();
+        assert_eq!(
+            users
+                .iter()
+                .find(|u| u.user.user_id.as_str() == "bob")
+                .unwrap()
+                .user
+                .secondary_emails,
+            user.secondary_emails
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::SecondaryEmail(
+                    "BOB.alias@example.com".to_owned()
+                )),
+            )
+            .await,
+            vec!["bob"]
+        );
 
+        // Already a secondary address of bob.
+        assert!(matches!(
+            fixture
+                .handler
+                .update_user(set_secondary_emails("patrick", &["b@example.com"]))
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new("patrick"),
+                    email: Some("B@example.com".to_owned()),
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        // The primary address of another user.
         fixture
             .handler
             .update_user(UpdateUserRequest {
-                user_id: UserId::new("bob"),
-                first_name: Some("first_name".to_string()),
-                last_name: Some(String::new()),
+                user_id: UserId::new("John"),
+                email: Some("John@example.com".to_owned()),
                 ..Default::default()
             })
             .await
             .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .update_user(set_secondary_emails("patrick", &["john@example.com"]))
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        // Not an address, or the primary one.
+        for email in ["not an address", "bob@bob.bob"] {
+            assert!(matches!(
+                fixture
+                    .handler
+                    .update_user(set_secondary_emails("patrick", &[email]))
+                    .await,
+                Err(DomainError::InvalidRequest(_))
+            ));
+        }
 
-        let user = fixture
+        // The removed addresses can be used by another user.
+        fixture
             .handler
-            .get_user_details(&UserId::new("bob"))
+            .update_user(set_secondary_emails("bob", &[]))
             .await
             .unwrap();
-        assert_eq!(user.display_name.unwrap(), "display bob");
-        assert_eq!(user.first_name.unwrap(), "first_name");
-        assert_eq!(user.last_name, None);
-        assert_eq!(user.avatar, None);
-    }
-
-    #[tokio::test]
-    async fn test_remove_user_from_group() {
-        let fixture = TestFixture::new().await;
-
         fixture
             .handler
-            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
+            .update_user(set_secondary_emails("patrick", &["b@example.com"]))
             .await
             .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unique_emails() {
+        let mut config = get_default_config();
+        config.unique_emails = true;
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        for user_id in ["patrick", "john"] {
+            assert!(matches!(
+                handler
+                    .create_user(CreateUserRequest {
+                        user_id: UserId::new(user_id),
+                        email: " Bob@Bob.bob".to_owned(),
+                        ..Default::default()
+                    })
+                    .await,
+                Err(DomainError::InvalidRequest(_))
+            ));
+        }
+    }
 
+    #[tokio::test]
+    async fn test_create_user_with_template() {
+        let mut config = get_default_config();
+        config.user_templates = vec![crate::infra::configuration::UserTemplateOptions {
+            name: "employee".to_owned(),
+            groups: vec!["staff".to_owned()],
+            gid_number: Some(1000),
+            home_directory: Some("/home/{user_id}".to_owned()),
+            locale: Some("fr".to_owned()),
+        }];
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        let create_alice = || CreateUserRequest {
+            user_id: UserId::new("alice"),
+            email: "alice@example.com".to_owned(),
+            gid_number: Some(2000),
+            template: Some("employee".to_owned()),
+            ..Default::default()
+        };
+        // The groups of the template must exist.
+        assert!(matches!(
+            handler.create_user(create_alice()).await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+        let staff = insert_group(&handler, "Staff").await;
+        handler.create_user(create_alice()).await.unwrap();
+        let alice = UserId::new("alice");
+        let user = handler.get_user_details(&alice).await.unwrap();
+        assert_eq!(user.gid_number, Some(2000));
+        assert_eq!(user.home_directory.as_deref(), Some("/home/alice"));
+        assert_eq!(user.locale.as_deref(), Some("fr"));
         assert_eq!(
-            get_user_names(
-                &fixture.handler,
-                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
-            )
-            .await,
-            vec!["patrick"]
+            handler
+                .get_user_groups(&alice)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![staff]
         );
+        assert!(matches!(
+            handler
+                .create_user(CreateUserRequest {
+                    user_id: UserId::new("bob"),
+                    email: "bob@example.com".to_owned(),
+                    template: Some("contractor".to_owned()),
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_user_id() {
+        let policy = UsernamePolicyOptions {
+            min_length: 3,
+            max_length: 8,
+            allowed_characters: Some("._-".to_owned()),
+            lowercase: false,
+        };
+        check_user_id(&policy, "bob.smith").unwrap_err();
+        check_user_id(&policy, "bo").unwrap_err();
+        check_user_id(&policy, "Bob").unwrap_err();
+        check_user_id(&policy, "bob@home").unwrap_err();
+        check_user_id(&policy, "bob_s-1").unwrap();
+        check_user_id(&UsernamePolicyOptions::default(), "Bob@home").unwrap();
     }
 }
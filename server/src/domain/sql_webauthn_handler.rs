@@ -0,0 +1,404 @@
+use super::{
+    error::{DomainError, Result},
+    handler::{AuditEvent, UserBackendHandler, WebauthnCredentialBackendHandler},
+    model::{self, WebauthnCredentialsColumn},
+    security_events::SecurityEvent,
+    sql_backend_handler::SqlBackendHandler,
+    types::{UserId, WebauthnCredential},
+    webauthn_handler::{login, registration, WebauthnHandler},
+};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, instrument};
+use webauthn_rs::prelude::{
+    CredentialID, Passkey, Url, Uuid, Webauthn, WebauthnBuilder, WebauthnError,
+};
+
+/// How long the clients have to complete a registration or a login.
+const CHALLENGE_VALIDITY_MINUTES: i64 = 5;
+
+fn webauthn_error(error: WebauthnError) -> DomainError {
+    DomainError::AuthenticationError(format!("WebAuthn: {}", error))
+}
+
+fn encode_credential_id(credential_id: &CredentialID) -> String {
+    base64::encode_config(&credential_id.0, base64::URL_SAFE_NO_PAD)
+}
+
+fn check_challenge_not_expired(expiry: DateTime<Utc>) -> Result<()> {
+    if expiry < Utc::now() {
+        return Err(DomainError::AuthenticationError(
+            "The WebAuthn challenge expired".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+impl SqlBackendHandler {
+    fn get_webauthn(&self) -> Result<Webauthn> {
+        let origin = Url::parse(&self.config.http_url).map_err(|e| {
+            DomainError::InternalError(format!("Invalid http_url for WebAuthn: {}", e))
+        })?;
+        let rp_id = origin
+            .host_str()
+            .ok_or_else(|| DomainError::InternalError("The http_url has no host".to_owned()))?
+            .to_owned();
+        WebauthnBuilder::new(&rp_id, &origin)
+            .and_then(|builder| builder.rp_name("LLDAP").build())
+            .map_err(|e| {
+                DomainError::InternalError(format!("Invalid WebAuthn configuration: {}", e))
+            })
+    }
+
+    // The ceremony state is kept by the client, like for OPAQUE.
+    fn seal_server_data<T: Serialize>(&self, server_data: &T) -> Result<String> {
+        let serialized = serde_json::to_vec(server_data)
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        Ok(base64::encode(orion::aead::seal(
+            &self.get_orion_secret_key()?,
+            &serialized,
+        )?))
+    }
+
+    fn open_server_data<T: DeserializeOwned>(&self, server_data: &str) -> Result<T> {
+        let serialized =
+            orion::aead::open(&self.get_orion_secret_key()?, &base64::decode(server_data)?)?;
+        serde_json::from_slice(&serialized).map_err(|e| DomainError::InternalError(e.to_string()))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_user_passkeys(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<(model::webauthn_credentials::Model, Passkey)>> {
+        model::WebauthnCredentials::find()
+            .filter(WebauthnCredentialsColumn::UserId.eq(user_id))
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|credential| {
+                let passkey = serde_json::from_str(&credential.passkey).map_err(|e| {
+                    DomainError::InternalError(format!(
+                        "Corrupted WebAuthn credential {}: {}",
+                        credential.credential_id, e
+                    ))
+                })?;
+                Ok((credential, passkey))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WebauthnHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn webauthn_registration_start(
+        &self,
+        user_id: &UserId,
+    ) -> Result<registration::ServerRegistrationStartResponse> {
+        debug!(?user_id);
+        let user = self.get_user_details(user_id).await?;
+        let user_uuid = Uuid::parse_str(user.uuid.as_str())
+            .map_err(|e| DomainError::InternalError(format!("Invalid user UUID: {}", e)))?;
+        // The authenticators refuse to register the same credential twice.
+        let existing_credentials = self
+            .get_user_passkeys(user_id)
+            .await?
+            .into_iter()
+            .map(|(_, passkey)| passkey.cred_id().clone())
+            .collect();
+        let (creation_options, state) = self
+            .get_webauthn()?
+            .start_passkey_registration(
+                user_uuid,
+                user_id.as_str(),
+                user.display_name
+                    .as_deref()
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| user_id.as_str()),
+                Some(existing_credentials),
+            )
+            .map_err(webauthn_error)?;
+        let server_data = registration::ServerData {
+            username: user_id.as_str().to_owned(),
+            expiry: Utc::now() + chrono::Duration::minutes(CHALLENGE_VALIDITY_MINUTES),
+            state,
+        };
+        Ok(registration::ServerRegistrationStartResponse {
+            server_data: self.seal_server_data(&server_data)?,
+            creation_options,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn webauthn_registration_finish(
+        &self,
+        user_id: &UserId,
+        request: registration::ClientRegistrationFinishRequest,
+    ) -> Result<()> {
+        debug!(?user_id);
+        let registration::ServerData {
+            username,
+            expiry,
+            state,
+        } = self.open_server_data(&request.server_data)?;
+        if UserId::new(&username) != *user_id {
+            return Err(DomainError::AuthenticationError(format!(
+                "The WebAuthn registration was started for another user than '{}'",
+                user_id
+            )));
+        }
+        check_challenge_not_expired(expiry)?;
+        let passkey = self
+            .get_webauthn()?
+            .finish_passkey_registration(&request.credential, &state)
+            .map_err(webauthn_error)?;
+        model::webauthn_credentials::ActiveModel {
+            credential_id: ActiveValue::Set(encode_credential_id(passkey.cred_id())),
+            user_id: ActiveValue::Set(user_id.clone()),
+            display_name: ActiveValue::Set(request.display_name),
+            passkey: ActiveValue::Set(
+                serde_json::to_string(&passkey)
+                    .map_err(|e| DomainError::InternalError(e.to_string()))?,
+            ),
+            creation_date: ActiveValue::Set(Utc::now()),
+            last_used: ActiveValue::Set(None),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn webauthn_login_start(
+        &self,
+        request: login::ClientLoginStartRequest,
+    ) -> Result<login::ServerLoginStartResponse> {
+        let user_id = UserId::new(&request.username);
+        debug!(?user_id);
+        self.check_account_not_locked(&user_id).await?;
+        let passkeys: Vec<Passkey> = self
+            .get_user_passkeys(&user_id)
+            .await?
+            .into_iter()
+            .map(|(_, passkey)| passkey)
+            .collect();
+        if passkeys.is_empty() {
+            return Err(DomainError::AuthenticationError(format!(
+                "No WebAuthn credential for user '{}'",
+                user_id
+            )));
+        }
+        let (request_options, state) = self
+            .get_webauthn()?
+            .start_passkey_authentication(&passkeys)
+            .map_err(webauthn_error)?;
+        let server_data = login::ServerData {
+            username: request.username,
+            expiry: Utc::now() + chrono::Duration::minutes(CHALLENGE_VALIDITY_MINUTES),
+            state,
+        };
+        Ok(login::ServerLoginStartResponse {
+            server_data: self.seal_server_data(&server_data)?,
+            request_options,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn webauthn_login_finish(
+        &self,
+        request: login::ClientLoginFinishRequest,
+    ) -> Result<UserId> {
+        let login::ServerData {
+            username,
+            expiry,
+            state,
+        } = self.open_server_data(&request.server_data)?;
+        let user_id = UserId::new(&username);
+        debug!(?user_id);
+        check_challenge_not_expired(expiry)?;
+        self.check_account_not_locked(&user_id).await?;
+        let result = match self
+            .get_webauthn()?
+            .finish_passkey_authentication(&request.credential, &state)
+        {
+            Ok(result) => result,
+            Err(e) => {
+                self.record_login_failure(&user_id).await?;
+                return Err(webauthn_error(e));
+            }
+        };
+        // The credential could have been deleted since the start of the login.
+        let credential_id = encode_credential_id(result.cred_id());
+        let (credential, mut passkey) = self
+            .get_user_passkeys(&user_id)
+            .await?
+            .into_iter()
+            .find(|(credential, _)| credential.credential_id == credential_id)
+            .ok_or_else(|| {
+                DomainError::AuthenticationError(format!(
+                    "Unknown WebAuthn credential for user '{}'",
+                    user_id
+                ))
+            })?;
+        self.record_login_success(&user_id).await?;
+        // Keeps the signature counter up to date, to detect cloned authenticators.
+        passkey.update_credential(&result);
+        model::webauthn_credentials::ActiveModel {
+            credential_id: ActiveValue::Set(credential.credential_id),
+            passkey: ActiveValue::Set(
+                serde_json::to_string(&passkey)
+                    .map_err(|e| DomainError::InternalError(e.to_string()))?,
+            ),
+            last_used: ActiveValue::Set(Some(Utc::now())),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(user_id)
+    }
+}
+
+#[async_trait]
+impl WebauthnCredentialBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>> {
+        debug!(?user_id);
+        Ok(model::WebauthnCredentials::find()
+            .filter(WebauthnCredentialsColumn::UserId.eq(user_id))
+            .order_by_asc(WebauthnCredentialsColumn::CreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_webauthn_credential(
+        &self,
+        user_id: &UserId,
+        credential_id: &str,
+    ) -> Result<()> {
+        debug!(?user_id, ?credential_id);
+        let res = model::WebauthnCredentials::delete_many()
+            .filter(WebauthnCredentialsColumn::CredentialId.eq(credential_id))
+            .filter(WebauthnCredentialsColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such WebAuthn credential for user '{}'",
+                user_id
+            )));
+        }
+        self.publish_security_event(SecurityEvent::WebauthnCredentialRemoved(user_id.clone()));
+        self.audit(
+            "delete_webauthn_credential",
+            AuditEvent::user_target(user_id),
+            Some(credential_id.to_owned()),
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::*;
+    use sea_orm::IntoActiveModel;
+
+    async fn insert_credential(
+        handler: &SqlBackendHandler,
+        credential_id: &str,
+        user: &str,
+        created_days_ago: i64,
+    ) {
+        model::webauthn_credentials::Model {
+            credential_id: credential_id.to_owned(),
+            user_id: UserId::new(user),
+            display_name: format!("Key {}", credential_id),
+            passkey: "{}".to_owned(),
+            creation_date: Utc::now() - chrono::Duration::days(created_days_ago),
+            last_used: None,
+        }
+        .into_active_model()
+        .insert(&handler.sql_pool)
+        .await
+        .unwrap();
+    }
+
+    fn credential_ids(credentials: Vec<WebauthnCredential>) -> Vec<String> {
+        credentials.into_iter().map(|c| c.credential_id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_webauthn_credentials() {
+        let fixture = TestFixture::new().await;
+        insert_credential(&fixture.handler, "new", "bob", 1).await;
+        insert_credential(&fixture.handler, "old", "bob", 2).await;
+        insert_credential(&fixture.handler, "patrick_key", "patrick", 1).await;
+        let bob = UserId::new("bob");
+
+        assert_eq!(
+            credential_ids(
+                fixture
+                    .handler
+                    .list_webauthn_credentials(&bob)
+                    .await
+                    .unwrap()
+            ),
+            vec!["old", "new"]
+        );
+        // Patrick's credential cannot be deleted as Bob.
+        assert!(fixture
+            .handler
+            .delete_webauthn_credential(&bob, "patrick_key")
+            .await
+            .is_err());
+        fixture
+            .handler
+            .delete_webauthn_credential(&bob, "old")
+            .await
+            .unwrap();
+        assert_eq!(
+            credential_ids(
+                fixture
+                    .handler
+                    .list_webauthn_credentials(&bob)
+                    .await
+                    .unwrap()
+            ),
+            vec!["new"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webauthn_ceremony_start() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        let response = fixture
+            .handler
+            .webauthn_registration_start(&bob)
+            .await
+            .unwrap();
+        let server_data: registration::ServerData = fixture
+            .handler
+            .open_server_data(&response.server_data)
+            .unwrap();
+        assert_eq!(server_data.username, "bob");
+        // No credential to log in with yet.
+        assert!(matches!(
+            fixture
+                .handler
+                .webauthn_login_start(login::ClientLoginStartRequest {
+                    username: "bob".to_owned(),
+                })
+                .await,
+            Err(DomainError::AuthenticationError(_))
+        ));
+    }
+}
@@ -1,7 +1,8 @@
 use sea_orm::{
     entity::IntoActiveValue,
     sea_query::{value::ValueType, ArrayType, ColumnType, Nullable, ValueTypeErr},
-    DbErr, FromQueryResult, QueryResult, TryFromU64, TryGetError, TryGetable, Value,
+    DbErr, DeriveActiveEnum, EnumIter, FromQueryResult, QueryResult, TryFromU64, TryGetError,
+    TryGetable, Value,
 };
 use serde::{Deserialize, Serialize};
 
@@ -93,7 +94,7 @@ macro_rules! uuid {
     };
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(from = "String")]
 pub struct UserId(String);
 
@@ -300,16 +301,182 @@ impl IntoActiveValue<JpegPhoto> for JpegPhoto {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+/// A list of SSH public keys, stored in a single column with one key per line.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SshPublicKeys(Vec<String>);
+
+impl SshPublicKeys {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self(keys)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.0.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<String> {
+        self.0
+    }
+
+    fn from_column(column: Option<String>) -> Self {
+        Self(
+            column
+                .unwrap_or_default()
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    fn to_column(&self) -> Option<String> {
+        if self.0.is_empty() {
+            None
+        } else {
+            Some(self.0.join("\n"))
+        }
+    }
+}
+
+impl From<SshPublicKeys> for Value {
+    fn from(keys: SshPublicKeys) -> Self {
+        keys.to_column().into()
+    }
+}
+
+impl From<&SshPublicKeys> for Value {
+    fn from(keys: &SshPublicKeys) -> Self {
+        keys.to_column().into()
+    }
+}
+
+impl TryGetable for SshPublicKeys {
+    fn try_get(res: &QueryResult, pre: &str, col: &str) -> Result<Self, TryGetError> {
+        Ok(SshPublicKeys::from_column(Option::<String>::try_get(
+            res, pre, col,
+        )?))
+    }
+}
+
+impl ValueType for SshPublicKeys {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        Ok(SshPublicKeys::from_column(
+            <Option<String> as ValueType>::try_from(v)?,
+        ))
+    }
+
+    fn type_name() -> String {
+        "SshPublicKeys".to_owned()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Text
+    }
+}
+
+impl Nullable for SshPublicKeys {
+    fn null() -> Value {
+        SshPublicKeys::default().into()
+    }
+}
+
+impl IntoActiveValue<SshPublicKeys> for SshPublicKeys {
+    fn into_active_value(self) -> sea_orm::ActiveValue<SshPublicKeys> {
+        sea_orm::ActiveValue::Set(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(64))")]
+pub enum AttributeType {
+    #[sea_orm(string_value = "String")]
+    String,
+    #[sea_orm(string_value = "Integer")]
+    Integer,
+    #[sea_orm(string_value = "JpegPhoto")]
+    JpegPhoto,
+    #[sea_orm(string_value = "DateTime")]
+    DateTime,
+}
+
+/// Definition of an admin-defined attribute.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeSchema {
+    pub name: String,
+    pub attribute_type: AttributeType,
+    pub is_list: bool,
+    /// Whether the attribute is visible to the user it belongs to, rather than only to admins.
+    pub is_visible: bool,
+}
+
+/// Values of an admin-defined attribute. Values are stored in their string form: JPEG photos are
+/// base64 encoded, and dates are in RFC 3339 format.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeValue {
+    pub name: String,
+    pub value: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub user_id: UserId,
     pub email: String,
+    /// The other addresses of the user, e.g. the aliases of their mailbox, in lower case. They
+    /// are unique across the users.
+    pub secondary_emails: Vec<String>,
     pub display_name: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
     pub creation_date: DateTime,
+    pub modified_date: DateTime,
     pub uuid: Uuid,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub ssh_public_keys: SshPublicKeys,
+    /// Disabled users cannot log in or bind, but are kept with their details and memberships.
+    pub enabled: bool,
+    /// The account can only be used after this date, if set.
+    pub valid_from: Option<DateTime>,
+    /// The account can only be used before this date, if set.
+    pub valid_until: Option<DateTime>,
+    pub telephone_number: Option<String>,
+    pub mobile: Option<String>,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub locality: Option<String>,
+    pub postal_address: Option<String>,
+    /// The preferred language, as a BCP 47 tag such as "fr-CA", used for the emails.
+    pub locale: Option<String>,
+    /// The IANA time zone, such as "Europe/Paris".
+    pub timezone: Option<String>,
+    /// When the password was last set, if the user has one.
+    pub password_modified_date: Option<DateTime>,
+    /// The last successful LDAP bind, updated at most every few minutes.
+    pub last_ldap_login: Option<DateTime>,
+    /// The last login to the web UI, updated at most every few minutes.
+    pub last_web_login: Option<DateTime>,
+    pub attributes: Vec<AttributeValue>,
+}
+
+impl User {
+    /// Whether the user can log in or bind at the given time: the account must be enabled, and
+    /// within its validity period.
+    pub fn can_log_in_at(&self, now: DateTime) -> bool {
+        self.enabled
+            && self.valid_from.map_or(true, |from| from <= now)
+            && self.valid_until.map_or(true, |until| now < until)
+    }
 }
 
 #[cfg(test)]
@@ -320,17 +487,41 @@ impl Default for User {
         User {
             user_id: UserId::default(),
             email: String::new(),
+            secondary_emails: Vec::new(),
             display_name: None,
             first_name: None,
             last_name: None,
             avatar: None,
             creation_date: epoch,
+            modified_date: epoch,
             uuid: Uuid::from_name_and_date("", &epoch),
+            uid_number: None,
+            gid_number: None,
+            home_directory: None,
+            login_shell: None,
+            ssh_public_keys: SshPublicKeys::default(),
+            enabled: true,
+            valid_from: None,
+            valid_until: None,
+            telephone_number: None,
+            mobile: None,
+            title: None,
+            department: None,
+            locality: None,
+            postal_address: None,
+            locale: None,
+            timezone: None,
+            password_modified_date: None,
+            last_ldap_login: None,
+            last_web_login: None,
+            attributes: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
 pub struct GroupId(pub i32);
 
 impl From<GroupId> for Value {
@@ -363,19 +554,31 @@ impl ValueType for GroupId {
     }
 }
 
+impl Nullable for GroupId {
+    fn null() -> Value {
+        Value::Int(None)
+    }
+}
+
 impl TryFromU64 for GroupId {
     fn try_from_u64(n: u64) -> Result<Self, DbErr> {
         Ok(GroupId(i32::try_from_u64(n)?))
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct Group {
     pub id: GroupId,
     pub display_name: String,
     pub creation_date: DateTime,
+    pub modified_date: DateTime,
     pub uuid: Uuid,
+    pub gid_number: Option<i32>,
+    pub description: Option<String>,
+    /// The address of the group, e.g. of its mailing list.
+    pub mail: Option<String>,
     pub users: Vec<UserId>,
+    pub attributes: Vec<AttributeValue>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromQueryResult)]
@@ -384,6 +587,325 @@ pub struct GroupDetails {
     pub display_name: String,
     pub creation_date: DateTime,
     pub uuid: Uuid,
+    pub gid_number: Option<i32>,
+}
+
+/// The kind of entry affected by a change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum ChangeEntryType {
+    #[sea_orm(string_value = "User")]
+    User,
+    #[sea_orm(string_value = "Group")]
+    Group,
+}
+
+/// What happened to the entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum ChangeType {
+    #[sea_orm(string_value = "Add")]
+    Add,
+    #[sea_orm(string_value = "Modify")]
+    Modify,
+    #[sea_orm(string_value = "Delete")]
+    Delete,
+}
+
+/// An entry of the change log, recording that a user or group was created, modified or deleted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    /// Increasing identifier of the change.
+    pub change_id: i32,
+    pub entry_type: ChangeEntryType,
+    /// The user ID or group name, as of the change.
+    pub entry_id: String,
+    pub entry_uuid: Uuid,
+    pub change_type: ChangeType,
+    pub change_date: DateTime,
+    /// What the modification changed, when known.
+    pub diff: Option<ChangeDiff>,
+}
+
+/// What a modification of a user or group changed, for the incremental synchronization. Only
+/// the names of the modified fields are recorded, not their values.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeDiff {
+    /// The modified fields and custom attributes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// The groups joined by the user, or the users added to the group.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_memberships: Vec<String>,
+    /// The groups left by the user, or the users removed from the group.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_memberships: Vec<String>,
+    /// The former ID of a renamed user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+}
+
+/// Where a mutating operation came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum AuditSource {
+    #[sea_orm(string_value = "Ldap")]
+    Ldap,
+    #[sea_orm(string_value = "Graphql")]
+    Graphql,
+    #[sea_orm(string_value = "Scim")]
+    Scim,
+    #[sea_orm(string_value = "Rest")]
+    Rest,
+    /// The server itself, e.g. the scheduled jobs and the bootstrap.
+    #[sea_orm(string_value = "Server")]
+    Server,
+    /// The maintenance commands of the command line, e.g. `import`.
+    #[sea_orm(string_value = "Cli")]
+    Cli,
+}
+
+/// An entry of the audit log, recording who modified the directory, and how.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub audit_id: i32,
+    /// The authenticated user that performed the operation.
+    pub actor: UserId,
+    pub source: AuditSource,
+    /// The name of the operation, e.g. "create_user".
+    pub action: String,
+    /// The entry affected by the operation, e.g. "user:bob" or "group:3".
+    pub target: String,
+    /// Additional information, such as the other side of a membership.
+    pub details: Option<String>,
+    pub date: DateTime,
+}
+
+/// What an API token is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum ApiTokenScope {
+    /// Read access to all the users and groups.
+    #[sea_orm(string_value = "ReadOnly")]
+    ReadOnly,
+    /// Read access, and management of the non-admin users and their memberships.
+    #[sea_orm(string_value = "UserManagement")]
+    UserManagement,
+    /// Same as an admin user.
+    #[sea_orm(string_value = "Admin")]
+    Admin,
+}
+
+/// A long-lived token to access the API without logging in. Only a hash of the token is stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub token_id: i32,
+    pub name: String,
+    pub scope: ApiTokenScope,
+    /// The user that created the token, acting as the user when the token is used.
+    pub created_by: UserId,
+    pub creation_date: DateTime,
+}
+
+/// An account for an application to bind to the LDAP server, e.g. "nextcloud", under
+/// `ou=services`. It is kept apart from the users: it doesn't appear in the user listings, and
+/// cannot log in to the web UI. Only a hash of its generated password is stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub service_id: String,
+    pub description: Option<String>,
+    /// Whether it can read the whole directory. Otherwise, it only sees what the regular users
+    /// can see of the directory.
+    pub read_all: bool,
+    pub creation_date: DateTime,
+}
+
+/// A login session of a user, backed by a refresh token. The client details are only known for
+/// the sessions opened since they are recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Hash of the refresh token.
+    pub session_id: i64,
+    pub user_id: UserId,
+    pub creation_date: Option<DateTime>,
+    /// When the session was last used to get a new JWT.
+    pub last_used: Option<DateTime>,
+    pub expiry_date: DateTime,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// A WebAuthn credential (hardware key or passkey) registered by a user to log in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebauthnCredential {
+    /// Base64url-encoded ID of the credential.
+    pub credential_id: String,
+    pub user_id: UserId,
+    /// Name given by the user, to recognize the credential.
+    pub display_name: String,
+    pub creation_date: DateTime,
+    pub last_used: Option<DateTime>,
+}
+
+/// The number of direct members of a group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupMemberCount {
+    pub group_id: GroupId,
+    pub display_name: String,
+    pub member_count: u64,
+}
+
+/// The most recent login of a user, through LDAP or the web UI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserLastLogin {
+    pub user_id: UserId,
+    pub last_login: DateTime,
+}
+
+/// Aggregate figures about the directory, for the dashboards.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryStatistics {
+    pub user_count: u64,
+    pub group_count: u64,
+    /// By group ID, including the empty groups.
+    pub members_per_group: Vec<GroupMemberCount>,
+    /// The users with neither a password nor an imported password hash, who can only log in after
+    /// a reset.
+    pub users_without_password: u64,
+    pub disabled_users: u64,
+    /// Most recent first, only for the users who logged in since their login dates are recorded.
+    pub last_logins: Vec<UserLastLogin>,
+}
+
+/// A pending invitation to create an account. The invitee chooses their user ID when accepting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invitation {
+    pub email: String,
+    pub group_ids: Vec<GroupId>,
+    pub created_by: UserId,
+    pub creation_date: DateTime,
+    pub expiry_date: DateTime,
+    pub template: Option<String>,
+}
+
+/// A request to create an account through the public sign-up, waiting for an administrator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUser {
+    pub user_id: UserId,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub creation_date: DateTime,
+}
+
+/// A user in the recycle bin, until it is restored or purged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletedUser {
+    pub user_id: UserId,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub deletion_date: DateTime,
+}
+
+/// A group in the recycle bin, until it is restored or purged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletedGroup {
+    pub group_id: GroupId,
+    pub display_name: String,
+    pub deletion_date: DateTime,
+}
+
+/// What a user is allowed to do beyond their own account, granted by their roles.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Read access to all the users and groups.
+    pub read_all: bool,
+    /// Can change the password of the non-admin users.
+    pub reset_passwords: bool,
+    /// Can create, modify and delete all the non-admin users, and manage their memberships.
+    pub manage_users: bool,
+    /// Can modify and delete the non-admin users that are only in these groups, add them to these
+    /// groups and remove any non-admin user from them.
+    pub managed_groups: Vec<GroupId>,
+    /// Can only add or remove non-admin users from these groups, as their manager.
+    pub managed_memberships: Vec<GroupId>,
+}
+
+impl Capabilities {
+    pub fn merge(&mut self, other: Capabilities) {
+        self.read_all |= other.read_all;
+        self.reset_passwords |= other.reset_passwords;
+        self.manage_users |= other.manage_users;
+        for group_id in other.managed_groups {
+            if !self.managed_groups.contains(&group_id) {
+                self.managed_groups.push(group_id);
+            }
+        }
+        for group_id in other.managed_memberships {
+            if !self.managed_memberships.contains(&group_id) {
+                self.managed_memberships.push(group_id);
+            }
+        }
+    }
+}
+
+/// A role defined by the admins, held by the members of some groups.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Role {
+    pub role_id: i32,
+    pub display_name: String,
+    pub read_all: bool,
+    pub reset_passwords: bool,
+    /// The holders can manage the members of this group.
+    pub managed_group_id: Option<GroupId>,
+    /// The groups whose members hold the role.
+    pub group_ids: Vec<GroupId>,
+}
+
+impl Role {
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            read_all: self.read_all,
+            reset_passwords: self.reset_passwords,
+            manage_users: false,
+            managed_groups: self.managed_group_id.into_iter().collect(),
+            managed_memberships: Vec::new(),
+        }
+    }
+}
+
+/// An application authenticating its users through the OpenID Connect provider.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OidcClient {
+    pub client_id: String,
+    /// Also the key of the ID tokens signatures (HS256), so it is kept in clear.
+    pub client_secret: String,
+    pub display_name: String,
+    /// The only URIs the authorization codes are sent to.
+    pub redirect_uris: Vec<String>,
+    pub creation_date: DateTime,
+}
+
+/// A claim added to the tokens of the members of the group, e.g. `role: "Admin"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OidcGroupClaim {
+    pub client_id: String,
+    pub group_id: GroupId,
+    pub claim: String,
+    pub value: String,
+}
+
+/// What the user authorized the client to get, until the code is exchanged for the tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OidcAuthorizationCode {
+    pub client_id: String,
+    pub user_id: UserId,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub nonce: Option<String>,
+    /// For PKCE, with its method ("S256" or "plain").
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub expiry_date: DateTime,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
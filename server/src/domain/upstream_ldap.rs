@@ -0,0 +1,129 @@
+use crate::{
+    domain::{
+        error::{DomainError, Result},
+        types::UserId,
+    },
+    infra::configuration::UpstreamLdapOptions,
+};
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::time::Duration;
+use tracing::{debug, instrument, warn};
+
+/// LDAP result code for a bind with the wrong password.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// The attributes of a user authenticated upstream, to provision their local account.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpstreamUser {
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+fn user_bind_dn(options: &UpstreamLdapOptions, user_id: &UserId) -> String {
+    options
+        .bind_dn_format
+        .replace("{user_id}", &dn_escape(user_id.as_str()))
+}
+
+fn user_search_filter(options: &UpstreamLdapOptions, user_id: &UserId) -> String {
+    options
+        .user_filter
+        .replace("{user_id}", &ldap_escape(user_id.as_str()))
+}
+
+fn to_domain_error(e: ldap3::LdapError) -> DomainError {
+    DomainError::InternalError(format!("Upstream LDAP error: {}", e))
+}
+
+/// Checks the password of the user with a bind to the upstream server. Returns `None` if the
+/// password is wrong, and the attributes of the user otherwise.
+#[instrument(skip_all, level = "debug", err)]
+pub async fn authenticate(
+    options: &UpstreamLdapOptions,
+    user_id: &UserId,
+    password: &str,
+) -> Result<Option<UpstreamUser>> {
+    debug!(?user_id);
+    // An empty password would be an unauthenticated bind, which succeeds.
+    if password.is_empty() {
+        return Ok(None);
+    }
+    let settings =
+        LdapConnSettings::new().set_conn_timeout(Duration::from_secs(options.timeout_seconds));
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &options.url)
+        .await
+        .map_err(to_domain_error)?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.drive().await {
+            warn!("Upstream LDAP connection error: {}", e);
+        }
+    });
+    let bind_result = ldap
+        .simple_bind(&user_bind_dn(options, user_id), password)
+        .await
+        .map_err(to_domain_error)?;
+    if bind_result.rc == INVALID_CREDENTIALS {
+        debug!("Invalid upstream password");
+        return Ok(None);
+    }
+    bind_result.success().map_err(to_domain_error)?;
+    let (entries, _) = ldap
+        .search(
+            &options.base_dn,
+            Scope::Subtree,
+            &user_search_filter(options, user_id),
+            vec!["mail", "displayName", "cn", "givenName", "sn"],
+        )
+        .await
+        .and_then(|r| r.success())
+        .map_err(to_domain_error)?;
+    let _ = ldap.unbind().await;
+    let entry = match entries.into_iter().next() {
+        Some(entry) => SearchEntry::construct(entry),
+        None => {
+            debug!("No upstream entry matching the user filter");
+            return Ok(Some(UpstreamUser::default()));
+        }
+    };
+    let get_attribute = |name: &str| {
+        entry
+            .attrs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, values)| values.first().cloned())
+    };
+    Ok(Some(UpstreamUser {
+        email: get_attribute("mail"),
+        display_name: get_attribute("displayName").or_else(|| get_attribute("cn")),
+        first_name: get_attribute("givenName"),
+        last_name: get_attribute("sn"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_bind_dn_and_filter() {
+        let options = UpstreamLdapOptions {
+            bind_dn_format: "{user_id}@example.com".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            user_bind_dn(&options, &UserId::new("bob")),
+            "bob@example.com"
+        );
+        assert_eq!(
+            user_search_filter(&UpstreamLdapOptions::default(), &UserId::new("bob")),
+            "(uid=bob)"
+        );
+        // No filter injection.
+        assert_eq!(
+            user_search_filter(&UpstreamLdapOptions::default(), &UserId::new("*")),
+            r"(uid=\2a)"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::domain::{error::Result, types::UserId};
+use async_trait::async_trait;
+
+/// The messages for the 2-step WebAuthn registration of a new credential by a logged-in user.
+pub mod registration {
+    use chrono::prelude::*;
+    use serde::{Deserialize, Serialize};
+    use webauthn_rs::prelude::{
+        CreationChallengeResponse, PasskeyRegistration, RegisterPublicKeyCredential,
+    };
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerData {
+        pub username: String,
+        pub expiry: DateTime<Utc>,
+        pub state: PasskeyRegistration,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerRegistrationStartResponse {
+        /// Base64, encrypted ServerData to be passed back to the server.
+        pub server_data: String,
+        /// To pass to `navigator.credentials.create()`.
+        pub creation_options: CreationChallengeResponse,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientRegistrationFinishRequest {
+        /// Encrypted ServerData from the previous step.
+        pub server_data: String,
+        /// Name of the credential, to recognize it later.
+        pub display_name: String,
+        pub credential: RegisterPublicKeyCredential,
+    }
+}
+
+/// The messages for the 2-step WebAuthn login.
+pub mod login {
+    use chrono::prelude::*;
+    use serde::{Deserialize, Serialize};
+    use webauthn_rs::prelude::{
+        PasskeyAuthentication, PublicKeyCredential, RequestChallengeResponse,
+    };
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerData {
+        pub username: String,
+        pub expiry: DateTime<Utc>,
+        pub state: PasskeyAuthentication,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientLoginStartRequest {
+        pub username: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerLoginStartResponse {
+        /// Base64, encrypted ServerData to be passed back to the server.
+        pub server_data: String,
+        /// To pass to `navigator.credentials.get()`.
+        pub request_options: RequestChallengeResponse,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientLoginFinishRequest {
+        /// Encrypted ServerData from the previous step.
+        pub server_data: String,
+        pub credential: PublicKeyCredential,
+    }
+}
+
+/// Login with hardware keys or platform passkeys, as an alternative to the password. The relying
+/// party is derived from `http_url`, which must be the URL the users see in their browser.
+#[async_trait]
+pub trait WebauthnHandler: Clone + Send {
+    async fn webauthn_registration_start(
+        &self,
+        user_id: &UserId,
+    ) -> Result<registration::ServerRegistrationStartResponse>;
+    async fn webauthn_registration_finish(
+        &self,
+        user_id: &UserId,
+        request: registration::ClientRegistrationFinishRequest,
+    ) -> Result<()>;
+    async fn webauthn_login_start(
+        &self,
+        request: login::ClientLoginStartRequest,
+    ) -> Result<login::ServerLoginStartResponse>;
+    async fn webauthn_login_finish(
+        &self,
+        request: login::ClientLoginFinishRequest,
+    ) -> Result<UserId>;
+}
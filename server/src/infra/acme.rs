@@ -0,0 +1,371 @@
+use crate::infra::{
+    configuration::{AcmeChallengeType, AcmeOptions, LdapsOptions},
+    tcp_server::AppState,
+    tls_certificate::ReloadableCertificate,
+};
+use actix::prelude::{Actor, AsyncContext, Context};
+use actix_web::{web, HttpResponse};
+use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
+use async_trait::async_trait;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus,
+};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::{error, info, instrument};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 3600);
+const RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+const MAX_ORDER_POLLS: u32 = 10;
+
+/// The key authorizations of the pending HTTP-01 challenges, by token.
+pub type AcmeChallenges = Arc<RwLock<HashMap<String, String>>>;
+
+/// Publishes the TXT records of the DNS-01 challenges.
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+    async fn set_txt_record(&self, name: &str, value: &str) -> Result<()>;
+    async fn clear_txt_record(&self, name: &str, value: &str) -> Result<()>;
+}
+
+/// Delegates the DNS updates to an external command, called with "set" or "clear", the name and
+/// the value of the record. It should only return once the record is visible.
+pub struct CommandDnsProvider {
+    command: String,
+}
+
+impl CommandDnsProvider {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    async fn run(&self, action: &str, name: &str, value: &str) -> Result<()> {
+        let status = tokio::process::Command::new(&self.command)
+            .args([action, name, value])
+            .status()
+            .await
+            .with_context(|| format!("while running {}", self.command))?;
+        if !status.success() {
+            bail!(
+                "{} {} {} failed with {}",
+                self.command,
+                action,
+                name,
+                status
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DnsProvider for CommandDnsProvider {
+    async fn set_txt_record(&self, name: &str, value: &str) -> Result<()> {
+        self.run("set", name, value).await
+    }
+
+    async fn clear_txt_record(&self, name: &str, value: &str) -> Result<()> {
+        self.run("clear", name, value).await
+    }
+}
+
+enum ChallengeSolver {
+    Http(AcmeChallenges),
+    Dns(Box<dyn DnsProvider>),
+}
+
+impl ChallengeSolver {
+    fn challenge_type(&self) -> ChallengeType {
+        match self {
+            Self::Http(_) => ChallengeType::Http01,
+            Self::Dns(_) => ChallengeType::Dns01,
+        }
+    }
+}
+
+// A challenge to clean up once the order is done: the HTTP-01 token, or the DNS-01 record value.
+enum PendingChallenge {
+    Http(String),
+    Dns(String),
+}
+
+/// Returns whether the certificate is missing or expires in less than `renew_before_days`.
+fn needs_renewal(cert_file: &str, renew_before_days: u32) -> Result<bool> {
+    let content = match std::fs::read(cert_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e).with_context(|| format!("while reading {}", cert_file)),
+    };
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&content)
+        .map_err(|e| anyhow!("Invalid certificate in {}: {}", cert_file, e))?;
+    let certificate = pem
+        .parse_x509()
+        .map_err(|e| anyhow!("Invalid certificate in {}: {}", cert_file, e))?;
+    let remaining_seconds =
+        certificate.validity().not_after.timestamp() - chrono::Utc::now().timestamp();
+    Ok(remaining_seconds < i64::from(renew_before_days) * 24 * 3600)
+}
+
+/// Keeps the certificate of the LDAPS server valid: it is obtained from the ACME server when it is
+/// missing, then renewed before it expires.
+pub struct AcmeManager {
+    state: Option<AcmeState>,
+}
+
+struct AcmeState {
+    options: AcmeOptions,
+    cert_file: String,
+    key_file: String,
+    solver: ChallengeSolver,
+    certificates: Vec<Arc<ReloadableCertificate>>,
+}
+
+impl Actor for AcmeManager {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("ACME certificate manager started");
+        let state = self
+            .state
+            .take()
+            .expect("The ACME manager can only be started once");
+        context.spawn(actix::fut::wrap_future::<_, Self>(state.run()));
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        info!("ACME certificate manager stopped");
+    }
+}
+
+impl AcmeManager {
+    /// `challenges` are served by the HTTP server, for the HTTP-01 challenges. `certificates` are
+    /// reloaded after each renewal.
+    pub fn new(
+        options: AcmeOptions,
+        ldaps_options: &LdapsOptions,
+        challenges: AcmeChallenges,
+        certificates: Vec<Arc<ReloadableCertificate>>,
+    ) -> Self {
+        let solver = match options.challenge {
+            AcmeChallengeType::Http01 => ChallengeSolver::Http(challenges),
+            AcmeChallengeType::Dns01 => ChallengeSolver::Dns(Box::new(CommandDnsProvider::new(
+                options.dns_hook_command.clone().unwrap_or_default(),
+            ))),
+        };
+        Self {
+            state: Some(AcmeState {
+                options,
+                cert_file: ldaps_options.cert_file.clone(),
+                key_file: ldaps_options.key_file.clone(),
+                solver,
+                certificates,
+            }),
+        }
+    }
+}
+
+impl AcmeState {
+    async fn run(self) {
+        loop {
+            let delay = match needs_renewal(&self.cert_file, self.options.renew_before_days) {
+                Ok(false) => CHECK_INTERVAL,
+                renewal => {
+                    if let Err(e) = renewal {
+                        error!("{:#}, requesting a new one", e);
+                    }
+                    match self.renew().await {
+                        Ok(()) => CHECK_INTERVAL,
+                        Err(e) => {
+                            error!("Could not get a certificate from the ACME server: {:#}", e);
+                            RETRY_INTERVAL
+                        }
+                    }
+                }
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn load_account(&self) -> Result<Account> {
+        let account_file = &self.options.account_file;
+        match std::fs::read_to_string(account_file) {
+            Ok(content) => {
+                let credentials: AccountCredentials = serde_json::from_str(&content)
+                    .with_context(|| format!("while reading {}", account_file))?;
+                Ok(Account::from_credentials(credentials)?)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                info!("Creating an ACME account");
+                let contact = format!("mailto:{}", self.options.email);
+                let account = Account::create(
+                    &NewAccount {
+                        contact: &[contact.as_str()],
+                        terms_of_service_agreed: true,
+                        only_return_existing: false,
+                    },
+                    &self.options.directory_url,
+                )
+                .await
+                .context("while creating the ACME account")?;
+                std::fs::write(account_file, serde_json::to_string(&account.credentials())?)
+                    .with_context(|| format!("while writing {}", account_file))?;
+                Ok(account)
+            }
+            Err(e) => Err(e).with_context(|| format!("while reading {}", account_file)),
+        }
+    }
+
+    #[instrument(skip_all, level = "info", fields(domain = %self.options.domain))]
+    async fn renew(&self) -> Result<()> {
+        let account = self.load_account().await?;
+        let (mut order, state) = account
+            .new_order(&NewOrder {
+                identifiers: &[Identifier::Dns(self.options.domain.clone())],
+            })
+            .await?;
+        let mut pending_challenges = Vec::new();
+        let result = self
+            .complete_order(&mut order, &state.authorizations, &mut pending_challenges)
+            .await;
+        for pending_challenge in pending_challenges {
+            self.clean_up(pending_challenge).await;
+        }
+        let (private_key, certificate_chain) = result?;
+        std::fs::write(&self.key_file, private_key)
+            .with_context(|| format!("while writing {}", self.key_file))?;
+        std::fs::write(&self.cert_file, certificate_chain)
+            .with_context(|| format!("while writing {}", self.cert_file))?;
+        info!("Obtained a new certificate for {}", self.options.domain);
+        for certificate in &self.certificates {
+            certificate.reload()?;
+        }
+        Ok(())
+    }
+
+    // Solves the challenges and returns the private key and the certificate chain, in PEM.
+    async fn complete_order(
+        &self,
+        order: &mut Order,
+        authorization_urls: &[String],
+        pending_challenges: &mut Vec<PendingChallenge>,
+    ) -> Result<(String, String)> {
+        for authorization in order.authorizations(authorization_urls).await? {
+            if matches!(authorization.status, AuthorizationStatus::Valid) {
+                continue;
+            }
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|c| c.r#type == self.solver.challenge_type())
+                .ok_or_else(|| anyhow!("The ACME server doesn't offer the configured challenge"))?;
+            let key_authorization = order.key_authorization(challenge);
+            match &self.solver {
+                ChallengeSolver::Http(challenges) => {
+                    challenges.write().unwrap().insert(
+                        challenge.token.clone(),
+                        key_authorization.as_str().to_owned(),
+                    );
+                    pending_challenges.push(PendingChallenge::Http(challenge.token.clone()));
+                }
+                ChallengeSolver::Dns(provider) => {
+                    let value = key_authorization.dns_value();
+                    provider
+                        .set_txt_record(&self.dns_record_name(), &value)
+                        .await?;
+                    pending_challenges.push(PendingChallenge::Dns(value));
+                }
+            }
+            order.set_challenge_ready(&challenge.url).await?;
+        }
+        let mut delay = Duration::from_secs(1);
+        let mut polls = 0;
+        let state = loop {
+            tokio::time::sleep(delay).await;
+            let state = order.state().await?;
+            match state.status {
+                OrderStatus::Ready => break state,
+                OrderStatus::Invalid => bail!("The ACME server rejected the challenges"),
+                _ if polls >= MAX_ORDER_POLLS => bail!("The ACME order is still not ready"),
+                _ => {
+                    polls += 1;
+                    delay *= 2;
+                }
+            }
+        };
+        let certificate =
+            rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![self
+                .options
+                .domain
+                .clone()]))?;
+        let certificate_chain = order
+            .finalize(&certificate.serialize_request_der()?, &state.finalize)
+            .await?;
+        Ok((certificate.serialize_private_key_pem(), certificate_chain))
+    }
+
+    fn dns_record_name(&self) -> String {
+        format!("_acme-challenge.{}", self.options.domain)
+    }
+
+    async fn clean_up(&self, pending_challenge: PendingChallenge) {
+        match (&self.solver, pending_challenge) {
+            (ChallengeSolver::Http(challenges), PendingChallenge::Http(token)) => {
+                challenges.write().unwrap().remove(&token);
+            }
+            (ChallengeSolver::Dns(provider), PendingChallenge::Dns(value)) => {
+                if let Err(e) = provider
+                    .clear_txt_record(&self.dns_record_name(), &value)
+                    .await
+                {
+                    error!("Could not remove the DNS challenge: {:#}", e);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+async fn get_challenge<Backend: 'static>(
+    data: web::Data<AppState<Backend>>,
+    token: web::Path<String>,
+) -> HttpResponse {
+    match data.acme_challenges.read().unwrap().get(token.as_str()) {
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization.clone()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+pub fn configure_endpoint<Backend: 'static>(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/.well-known/acme-challenge/{token}",
+        web::get().to(get_challenge::<Backend>),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_renewal() {
+        let dir = std::env::temp_dir().join(format!("lldap_acme_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_file = dir.join("cert.pem");
+        let cert_file = cert_file.to_str().unwrap();
+        assert!(needs_renewal(cert_file, 30).unwrap());
+        // rcgen certificates are valid until 4096.
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        std::fs::write(cert_file, certificate.serialize_pem().unwrap()).unwrap();
+        assert!(!needs_renewal(cert_file, 30).unwrap());
+        std::fs::write(cert_file, "garbage").unwrap();
+        assert!(needs_renewal(cert_file, 30).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+//! Client of the GraphQL API of a running server, behind the `user` and `group` commands.
+
+use crate::infra::cli::{ApiClientOpts, GroupCommand, UserCommand};
+use anyhow::{anyhow, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+struct GraphQLError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
+
+impl<T> GraphQLResponse<T> {
+    fn into_result(self) -> Result<T> {
+        if !self.errors.is_empty() {
+            return Err(anyhow!(
+                "{}",
+                self.errors
+                    .into_iter()
+                    .map(|e| e.message)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ));
+        }
+        self.data.context("Missing data in the response")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserSummary {
+    id: String,
+    email: String,
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupSummary {
+    id: i32,
+    display_name: String,
+}
+
+pub struct ApiClient {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl ApiClient {
+    pub fn new(opts: &ApiClientOpts) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: opts.url.trim_end_matches('/').to_owned(),
+            token: opts.token.clone(),
+        }
+    }
+
+    async fn query<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T> {
+        self.client
+            .post(format!("{}/api/graphql", self.url))
+            .bearer_auth(&self.token)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await
+            .with_context(|| format!("while connecting to {}", self.url))?
+            .error_for_status()?
+            .json::<GraphQLResponse<T>>()
+            .await
+            .context("while reading the response")?
+            .into_result()
+    }
+
+    async fn find_group_id(&self, name: &str) -> Result<i32> {
+        #[derive(Deserialize)]
+        struct Groups {
+            groups: Vec<GroupSummary>,
+        }
+        self.query::<Groups>("query { groups { id displayName } }", json!({}))
+            .await?
+            .groups
+            .into_iter()
+            .find(|g| g.display_name == name)
+            .map(|g| g.id)
+            .with_context(|| format!("No group named \"{}\"", name))
+    }
+
+    async fn set_password(&self, user_id: &str, password: &str) -> Result<()> {
+        self.query::<serde_json::Value>(
+            "mutation($userId: String!, $password: String!) {
+               resetUserPassword(userId: $userId, newPassword: $password) { ok }
+             }",
+            json!({ "userId": user_id, "password": password }),
+        )
+        .await
+        .context("while setting the password")?;
+        Ok(())
+    }
+}
+
+pub async fn run_user_command(command: &UserCommand) -> Result<()> {
+    match command {
+        UserCommand::Add(opts) => {
+            let client = ApiClient::new(&opts.api);
+            client
+                .query::<serde_json::Value>(
+                    "mutation($user: CreateUserInput!) { createUser(user: $user) { id } }",
+                    json!({ "user": {
+                        "id": opts.user_id,
+                        "email": opts.email,
+                        "displayName": opts.display_name,
+                        "firstName": opts.first_name,
+                        "lastName": opts.last_name,
+                    }}),
+                )
+                .await
+                .context("while creating the user")?;
+            if let Some(password) = &opts.password {
+                client.set_password(&opts.user_id, password).await?;
+            }
+            println!("Created the user {}", opts.user_id);
+        }
+        UserCommand::Delete(opts) => {
+            ApiClient::new(&opts.api)
+                .query::<serde_json::Value>(
+                    "mutation($userId: String!) { deleteUser(userId: $userId) { ok } }",
+                    json!({ "userId": opts.user_id }),
+                )
+                .await
+                .context("while deleting the user")?;
+            println!("Deleted the user {}", opts.user_id);
+        }
+        UserCommand::List(opts) => {
+            #[derive(Deserialize)]
+            struct Users {
+                users: Vec<UserSummary>,
+            }
+            let users = ApiClient::new(&opts.api)
+                .query::<Users>("query { users { id email displayName } }", json!({}))
+                .await
+                .context("while listing the users")?
+                .users;
+            for user in users {
+                println!("{}\t{}\t{}", user.id, user.email, user.display_name);
+            }
+        }
+        UserCommand::SetPassword(opts) => {
+            ApiClient::new(&opts.api)
+                .set_password(&opts.user_id, &opts.password)
+                .await?;
+            println!("Set the password of {}", opts.user_id);
+        }
+        UserCommand::SendPasswordReset(opts) => {
+            ApiClient::new(&opts.api)
+                .query::<serde_json::Value>(
+                    "mutation($userId: String!) { sendPasswordResetEmail(userId: $userId) { ok } }",
+                    json!({ "userId": opts.user_id }),
+                )
+                .await
+                .context("while sending the password reset email")?;
+            println!("Sent a password reset email to {}", opts.user_id);
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_group_command(command: &GroupCommand) -> Result<()> {
+    match command {
+        GroupCommand::Add(opts) => {
+            ApiClient::new(&opts.api)
+                .query::<serde_json::Value>(
+                    "mutation($name: String!) { createGroup(name: $name) { id } }",
+                    json!({ "name": opts.group }),
+                )
+                .await
+                .context("while creating the group")?;
+            println!("Created the group {}", opts.group);
+        }
+        GroupCommand::Delete(opts) => {
+            let client = ApiClient::new(&opts.api);
+            let group_id = client.find_group_id(&opts.group).await?;
+            client
+                .query::<serde_json::Value>(
+                    "mutation($groupId: Int!) { deleteGroup(groupId: $groupId) { ok } }",
+                    json!({ "groupId": group_id }),
+                )
+                .await
+                .context("while deleting the group")?;
+            println!("Deleted the group {}", opts.group);
+        }
+        GroupCommand::AddMember(opts) => {
+            let client = ApiClient::new(&opts.api);
+            let group_id = client.find_group_id(&opts.group).await?;
+            client
+                .query::<serde_json::Value>(
+                    "mutation($userId: String!, $groupId: Int!) {
+                       addUserToGroup(userId: $userId, groupId: $groupId) { ok }
+                     }",
+                    json!({ "userId": opts.user_id, "groupId": group_id }),
+                )
+                .await
+                .context("while adding the user to the group")?;
+            println!("Added {} to the group {}", opts.user_id, opts.group);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_errors() {
+        let response: GraphQLResponse<serde_json::Value> = serde_json::from_str(
+            r#"{"data": null, "errors": [{"message": "Unauthorized", "locations": []}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response.into_result().unwrap_err().to_string(),
+            "Unauthorized"
+        );
+        let response: GraphQLResponse<serde_json::Value> =
+            serde_json::from_str(r#"{"data": {"deleteUser": {"ok": true}}}"#).unwrap();
+        assert_eq!(
+            response.into_result().unwrap(),
+            json!({"deleteUser": {"ok": true}})
+        );
+    }
+}
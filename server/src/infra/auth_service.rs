@@ -14,39 +14,122 @@ use anyhow::Result;
 use chrono::prelude::*;
 use futures::future::{ok, Ready};
 use futures_util::FutureExt;
-use hmac::Hmac;
+use hmac::{Hmac, NewMac};
 use jwt::{SignWithKey, VerifyWithKey};
+use secstr::SecUtf8;
+use serde::{de::DeserializeOwned, Serialize};
 use sha2::Sha512;
 use time::ext::NumericalDuration;
 use tracing::{debug, instrument, warn};
 
-use lldap_auth::{login, password_reset, registration, JWTClaims};
+use lldap_auth::{invitation, login, password_reset, registration, sign_up, JWTClaims};
 
 use crate::{
     domain::{
         error::DomainError,
-        handler::{BackendHandler, BindRequest, LoginHandler, UserRequestFilter},
+        handler::{
+            BackendHandler, BindRequest, CreatePendingUserRequest, LoginHandler, LoginKind,
+            UserRequestFilter, API_TOKEN_PREFIX,
+        },
         opaque_handler::OpaqueHandler,
-        types::{GroupDetails, UserColumn, UserId},
+        types::{
+            ApiTokenScope, AuditSource, Capabilities, GroupDetails, GroupId, UserColumn, UserId,
+        },
+        webauthn_handler::{self, WebauthnHandler},
     },
     infra::{
+        configuration::{JwtSecretOptions, MailOptions, RegularUserVisibility},
+        read_only::{ReadOnlyGuardFactory, ReadOnlyStrictGuardFactory},
         tcp_backend_handler::*,
         tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+        trusted_proxies::client_ip,
     },
 };
 
 type Token<S> = jwt::Token<jwt::Header, JWTClaims, S>;
 type SignedToken = Token<jwt::token::Signed>;
 
-fn create_jwt(key: &Hmac<Sha512>, user: String, groups: HashSet<GroupDetails>) -> SignedToken {
+/// The keys used to sign and verify the JWTs. The JWT secret can be rotated without logging
+/// everyone out: the new tokens are signed with the current key, but the previous keys are still
+/// accepted.
+#[derive(Clone)]
+pub struct JwtKeys {
+    // The current key first.
+    keys: Vec<(String, Hmac<Sha512>)>,
+}
+
+impl JwtKeys {
+    pub fn new(key_id: &str, secret: &SecUtf8, previous_secrets: &[JwtSecretOptions]) -> Self {
+        let make_key = |secret: &SecUtf8| Hmac::new_varkey(secret.unsecure().as_bytes()).unwrap();
+        Self {
+            keys: std::iter::once((key_id.to_owned(), make_key(secret)))
+                .chain(
+                    previous_secrets
+                        .iter()
+                        .map(|key| (key.key_id.clone(), make_key(&key.secret))),
+                )
+                .collect(),
+        }
+    }
+
+    fn signing_key(&self) -> &(String, Hmac<Sha512>) {
+        &self.keys[0]
+    }
+
+    fn verify(&self, token_str: &str) -> Option<Token<jwt::token::Verified>> {
+        self.verify_claims(token_str)
+    }
+
+    /// Signs other kinds of claims than the login ones, e.g. the OpenID Connect access tokens.
+    pub(crate) fn sign_claims<C: Serialize>(&self, claims: C) -> String {
+        let (key_id, key) = self.signing_key();
+        let header = jwt::Header {
+            algorithm: jwt::AlgorithmType::Hs512,
+            key_id: Some(key_id.clone()),
+            ..Default::default()
+        };
+        jwt::Token::new(header, claims)
+            .sign_with_key(key)
+            .unwrap()
+            .as_str()
+            .to_owned()
+    }
+
+    pub(crate) fn verify_claims<C: DeserializeOwned>(
+        &self,
+        token_str: &str,
+    ) -> Option<jwt::Token<jwt::Header, C, jwt::token::Verified>> {
+        let key_id =
+            jwt::Token::<jwt::Header, C, jwt::token::Unverified>::parse_unverified(token_str)
+                .ok()?
+                .header()
+                .key_id
+                .clone();
+        match key_id {
+            Some(key_id) => {
+                let (_, key) = self.keys.iter().find(|(id, _)| id == &key_id)?;
+                VerifyWithKey::verify_with_key(token_str, key).ok()
+            }
+            // Tokens signed before the key IDs were introduced.
+            None => self
+                .keys
+                .iter()
+                .find_map(|(_, key)| VerifyWithKey::verify_with_key(token_str, key).ok()),
+        }
+    }
+}
+
+fn create_jwt(keys: &JwtKeys, user: String, groups: HashSet<GroupDetails>) -> SignedToken {
     let claims = JWTClaims {
         exp: Utc::now() + chrono::Duration::days(1),
         iat: Utc::now(),
         user,
         groups: groups.into_iter().map(|g| g.display_name).collect(),
     };
+    let (key_id, key) = keys.signing_key();
     let header = jwt::Header {
         algorithm: jwt::AlgorithmType::Hs512,
+        key_id: Some(key_id.clone()),
         ..Default::default()
     };
     jwt::Token::new(header, claims).sign_with_key(key).unwrap()
@@ -79,6 +162,95 @@ fn get_refresh_token(request: HttpRequest) -> TcpResult<(u64, UserId)> {
     }
 }
 
+// Disabled or expired accounts keep their password, but cannot log in or refresh their token.
+pub(crate) async fn check_user_can_log_in<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: &UserId,
+) -> TcpResult<()> {
+    if !backend_handler
+        .get_user_details_from_primary(user)
+        .await?
+        .can_log_in_at(chrono::Utc::now())
+    {
+        return Err(TcpError::DomainError(DomainError::AuthenticationError(
+            format!("Account '{}' is disabled or expired", user),
+        )));
+    }
+    Ok(())
+}
+
+// The JWTs are not stored, so they cannot be revoked: the ones of the accounts that cannot log in
+// anymore are rejected instead, until they expire.
+pub(crate) async fn check_jwt_user_can_log_in<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: &UserId,
+) -> Result<(), actix_web::Error> {
+    check_user_can_log_in(backend_handler, user)
+        .await
+        .map_err(jwt_user_error_to_actix_error)
+}
+
+// The JWT of a login with an expired password is only good to choose a new password: the other
+// API requests are rejected until then.
+pub(crate) async fn check_jwt_user_can_use_api<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: &UserId,
+) -> Result<(), actix_web::Error> {
+    check_jwt_user_can_log_in(backend_handler, user).await?;
+    check_password_not_expired(backend_handler, user)
+        .await
+        .map_err(jwt_user_error_to_actix_error)
+}
+
+fn jwt_user_error_to_actix_error(error: TcpError) -> actix_web::Error {
+    match error {
+        TcpError::DomainError(DomainError::AuthenticationError(message)) => {
+            ErrorUnauthorized(message)
+        }
+        TcpError::DomainError(DomainError::EntityNotFound(_)) => {
+            ErrorUnauthorized("The user doesn't exist anymore")
+        }
+        e => actix_web::error::ErrorInternalServerError(e.to_string()),
+    }
+}
+
+// Checked before handing out a session, whatever the authentication method.
+async fn check_user_can_open_session<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: &UserId,
+) -> TcpResult<()> {
+    check_user_can_log_in(backend_handler, user).await?;
+    check_password_not_expired(backend_handler, user).await
+}
+
+async fn check_password_not_expired<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: &UserId,
+) -> TcpResult<()> {
+    if has_password_expired(backend_handler, user).await? {
+        return Err(TcpError::DomainError(DomainError::AuthenticationError(
+            format!("The password of '{}' has expired", user),
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) async fn has_password_expired<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: &UserId,
+) -> TcpResult<bool> {
+    let policy = backend_handler.get_password_policy();
+    if policy.max_age_days == 0 {
+        return Ok(false);
+    }
+    Ok(backend_handler
+        .get_user_details_from_primary(user)
+        .await?
+        .password_modified_date
+        .and_then(|date| policy.password_expiry_date(date))
+        .map_or(false, |expiry| expiry <= chrono::Utc::now()))
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn get_refresh<Backend>(
     data: web::Data<AppState<Backend>>,
@@ -88,7 +260,7 @@ where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
     let backend_handler = &data.backend_handler;
-    let jwt_key = &data.jwt_key;
+    let jwt_keys = &data.jwt_keys;
     let (refresh_token_hash, user) = get_refresh_token(request)?;
     let found = data
         .backend_handler
@@ -99,10 +271,11 @@ where
             "Invalid refresh token".to_string(),
         )));
     }
+    check_user_can_open_session(backend_handler, &user).await?;
     Ok(backend_handler
-        .get_user_groups(&user)
+        .get_user_groups_from_primary(&user)
         .await
-        .map(|groups| create_jwt(jwt_key, user.to_string(), groups))
+        .map(|groups| create_jwt(jwt_keys, user.to_string(), groups))
         .map(|token| {
             HttpResponse::Ok()
                 .cookie(
@@ -116,6 +289,7 @@ where
                 .json(&login::ServerLoginResponse {
                     token: token.as_str().to_owned(),
                     refresh_token: None,
+                    password_expired: false,
                 })
         })?)
 }
@@ -132,6 +306,15 @@ where
         .unwrap_or_else(error_to_http_response)
 }
 
+fn check_password_reset_enabled(mail_options: &MailOptions) -> TcpResult<()> {
+    if !mail_options.enable_password_reset {
+        return Err(TcpError::BadRequest(
+            "The password reset is not enabled".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn get_password_reset_step1<Backend>(
     data: web::Data<AppState<Backend>>,
@@ -140,6 +323,7 @@ async fn get_password_reset_step1<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
+    check_password_reset_enabled(&data.mail_options())?;
     let user_string = request
         .match_info()
         .get("user_id")
@@ -175,9 +359,10 @@ where
             .as_deref()
             .unwrap_or_else(|| user.user_id.as_str()),
         &user.email,
+        user.locale.as_deref(),
         &token,
         &data.server_url,
-        &data.mail_options,
+        &data.mail_options(),
     )
     .await
     {
@@ -211,6 +396,7 @@ async fn get_password_reset_step2<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
+    check_password_reset_enabled(&data.mail_options())?;
     let token = request
         .match_info()
         .get("token")
@@ -223,9 +409,14 @@ where
         .backend_handler
         .delete_password_reset_token(token)
         .await;
+    Ok(get_password_setting_response(&data.jwt_keys, &user_id))
+}
+
+/// Returns a token to set the password of the user, through an OPAQUE registration.
+fn get_password_setting_response(jwt_keys: &JwtKeys, user_id: &UserId) -> HttpResponse {
     let groups = HashSet::new();
-    let token = create_jwt(&data.jwt_key, user_id.to_string(), groups);
-    Ok(HttpResponse::Ok()
+    let token = create_jwt(jwt_keys, user_id.to_string(), groups);
+    HttpResponse::Ok()
         .cookie(
             Cookie::build("token", token.as_str())
                 .max_age(5.minutes())
@@ -238,7 +429,27 @@ where
         .json(&password_reset::ServerPasswordResetResponse {
             user_id: user_id.to_string(),
             token: token.as_str().to_owned(),
-        }))
+        })
+}
+
+/// Like [`get_password_setting_response`], for a login with an expired password: the web UI asks
+/// for a new password before logging in.
+fn get_expired_password_response(jwt_keys: &JwtKeys, user_id: &UserId) -> HttpResponse {
+    let token = create_jwt(jwt_keys, user_id.to_string(), HashSet::new());
+    HttpResponse::Ok()
+        .cookie(
+            Cookie::build("token", token.as_str())
+                .max_age(5.minutes())
+                .path("/auth")
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
+        .json(&login::ServerLoginResponse {
+            token: token.as_str().to_owned(),
+            refresh_token: None,
+            password_expired: true,
+        })
 }
 
 async fn get_password_reset_step2_handler<Backend>(
@@ -253,6 +464,144 @@ where
         .unwrap_or_else(error_to_http_response)
 }
 
+#[instrument(skip_all, level = "debug")]
+async fn get_invitation<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<invitation::ServerInvitationResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let token = request
+        .match_info()
+        .get("token")
+        .ok_or_else(|| TcpError::BadRequest("Missing invitation token".to_string()))?;
+    let invitation = data.backend_handler.get_invitation(token).await?;
+    Ok(invitation::ServerInvitationResponse {
+        email: invitation.email,
+    })
+}
+
+async fn get_invitation_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> ApiResult<invitation::ServerInvitationResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    get_invitation(data, request)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_accept_invitation<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Json<invitation::ClientAcceptInvitationRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let token = request
+        .match_info()
+        .get("token")
+        .ok_or_else(|| TcpError::BadRequest("Missing invitation token".to_string()))?;
+    if body.user_id.is_empty() {
+        return Err(TcpError::BadRequest(
+            "The user ID cannot be empty".to_string(),
+        ));
+    }
+    let user_id = UserId::new(&body.user_id);
+    debug!(?user_id);
+    data.backend_handler
+        .accept_invitation(token, &user_id)
+        .await?;
+    Ok(get_password_setting_response(&data.jwt_keys, &user_id))
+}
+
+async fn post_accept_invitation_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Json<invitation::ClientAcceptInvitationRequest>,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    post_accept_invitation(data, request, body)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Records a request for an account, and notifies the administrators. Once approved, the user sets
+/// their password through the password reset, so both need to be enabled.
+#[instrument(skip_all, level = "debug")]
+async fn post_sign_up<Backend>(
+    data: web::Data<AppState<Backend>>,
+    body: web::Json<sign_up::ClientSignUpRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    if !data.enable_self_registration {
+        return Err(TcpError::BadRequest(
+            "The self-registration is not enabled".to_string(),
+        ));
+    }
+    check_password_reset_enabled(&data.mail_options())?;
+    let body = body.into_inner();
+    if body.user_id.is_empty() || body.email.is_empty() {
+        return Err(TcpError::BadRequest(
+            "The user ID and the email cannot be empty".to_string(),
+        ));
+    }
+    data.backend_handler.check_user_id(&body.user_id)?;
+    let user_id = UserId::new(&body.user_id);
+    debug!(?user_id, email = ?body.email);
+    data.backend_handler
+        .create_pending_user(CreatePendingUserRequest {
+            user_id: user_id.clone(),
+            email: body.email.clone(),
+            display_name: body.display_name.filter(|name| !name.is_empty()),
+        })
+        .await?;
+    let admins = data
+        .backend_handler
+        .list_users(
+            Some(UserRequestFilter::MemberOf("lldap_admin".to_owned())),
+            false,
+        )
+        .await?;
+    for admin in admins.iter().filter(|admin| !admin.user.email.is_empty()) {
+        if let Err(e) = super::mail::send_sign_up_notification_email(
+            &admin.user.email,
+            user_id.as_str(),
+            &body.email,
+            &data.server_url,
+            &data.mail_options(),
+        )
+        .await
+        {
+            // The request is recorded, the admins will see it anyway.
+            warn!("Error sending email: {:#?}", e);
+        }
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn post_sign_up_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    body: web::Json<sign_up::ClientSignUpRequest>,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    post_sign_up(data, body)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn get_logout<Backend>(
     data: web::Data<AppState<Backend>>,
@@ -327,15 +676,37 @@ where
 async fn get_login_successful_response<Backend>(
     data: &web::Data<AppState<Backend>>,
     name: &UserId,
+    http_request: &HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler,
 {
+    check_user_can_open_session(&data.backend_handler, name).await?;
     // The authentication was successful, we need to fetch the groups to create the JWT
     // token.
-    let groups = data.backend_handler.get_user_groups(name).await?;
-    let (refresh_token, max_age) = data.backend_handler.create_refresh_token(name).await?;
-    let token = create_jwt(&data.jwt_key, name.to_string(), groups);
+    let groups = data
+        .backend_handler
+        .get_user_groups_from_primary(name)
+        .await?;
+    // Recorded to help the users recognize their sessions.
+    let user_agent = http_request
+        .headers()
+        .get(actix_http::header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+        .map(str::to_owned);
+    let ip_address = client_ip(http_request).map(|ip| ip.to_string());
+    let (refresh_token, max_age) = data
+        .backend_handler
+        .create_refresh_token(name, user_agent, ip_address)
+        .await?;
+    if let Err(e) = data
+        .backend_handler
+        .record_login(name, LoginKind::Web)
+        .await
+    {
+        warn!("Could not record the login of {}: {}", name, e);
+    }
+    let token = create_jwt(&data.jwt_keys, name.to_string(), groups);
     let refresh_token_plus_name = refresh_token + "+" + name.as_str();
 
     Ok(HttpResponse::Ok()
@@ -358,6 +729,7 @@ where
         .json(&login::ServerLoginResponse {
             token: token.as_str().to_owned(),
             refresh_token: Some(refresh_token_plus_name),
+            password_expired: false,
         }))
 }
 
@@ -365,6 +737,7 @@ where
 async fn opaque_login_finish<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
@@ -373,17 +746,22 @@ where
         .backend_handler
         .login_finish(request.into_inner())
         .await?;
-    get_login_successful_response(&data, &name).await
+    if has_password_expired(&data.backend_handler, &name).await? {
+        check_user_can_log_in(&data.backend_handler, &name).await?;
+        return Ok(get_expired_password_response(&data.jwt_keys, &name));
+    }
+    get_login_successful_response(&data, &name, &http_request).await
 }
 
 async fn opaque_login_finish_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    opaque_login_finish(data, request)
+    opaque_login_finish(data, request, http_request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -392,6 +770,7 @@ where
 async fn simple_login<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientSimpleLoginRequest>,
+    http_request: HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
@@ -402,17 +781,18 @@ where
         password: request.password.clone(),
     };
     data.backend_handler.bind(bind_request).await?;
-    get_login_successful_response(&data, &user_id).await
+    get_login_successful_response(&data, &user_id, &http_request).await
 }
 
 async fn simple_login_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientSimpleLoginRequest>,
+    http_request: HttpRequest,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
-    simple_login(data, request)
+    simple_login(data, request, http_request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -421,6 +801,7 @@ where
 async fn post_authorize<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<BindRequest>,
+    http_request: HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
@@ -428,17 +809,18 @@ where
     let name = request.name.clone();
     debug!(%name);
     data.backend_handler.bind(request.into_inner()).await?;
-    get_login_successful_response(&data, &name).await
+    get_login_successful_response(&data, &name, &http_request).await
 }
 
 async fn post_authorize_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<BindRequest>,
+    http_request: HttpRequest,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
 {
-    post_authorize(data, request)
+    post_authorize(data, request, http_request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -453,13 +835,19 @@ where
     Backend: BackendHandler + OpaqueHandler + 'static,
 {
     use actix_web::FromRequest;
-    let validation_result = BearerAuth::from_request(&request, &mut payload.0)
+    let mut validation_result = BearerAuth::from_request(&request, &mut payload.0)
         .await
         .ok()
         .and_then(|bearer| check_if_token_is_valid(&data, bearer.token()).ok())
         .ok_or_else(|| {
             TcpError::UnauthorizedError("Not authorized to change the user's password".to_string())
         })?;
+    check_jwt_user_can_log_in(&data.backend_handler, &validation_result.user)
+        .await
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?;
+    add_role_capabilities(&data.backend_handler, &mut validation_result)
+        .await
+        .map_err(|e| TcpError::InternalServerError(e.to_string()))?;
     let registration_start_request =
         web::Json::<registration::ClientRegistrationStartRequest>::from_request(
             &request,
@@ -469,13 +857,15 @@ where
         .map_err(|e| TcpError::BadRequest(format!("{:#?}", e)))?
         .into_inner();
     let user_id = UserId::new(&registration_start_request.username);
-    let user_is_admin = data
+    let user_groups = data
         .backend_handler
-        .get_user_groups(&user_id)
-        .await?
-        .iter()
-        .any(|g| g.display_name == "lldap_admin");
-    if !validation_result.can_change_password(&user_id, user_is_admin) {
+        .get_user_groups_from_primary(&user_id)
+        .await?;
+    // With an expired password, the users can only choose their new one.
+    if !validation_result.can_change_password(&user_id, &user_groups)
+        || (user_id != validation_result.user
+            && has_password_expired(&data.backend_handler, &validation_result.user).await?)
+    {
         return Err(TcpError::UnauthorizedError(
             "Not authorized to change the user's password".to_string(),
         ));
@@ -503,12 +893,21 @@ where
 #[instrument(skip_all, level = "debug")]
 async fn opaque_register_finish<Backend>(
     data: web::Data<AppState<Backend>>,
+    credentials: Option<BearerAuth>,
     request: web::Json<registration::ClientRegistrationFinishRequest>,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    data.backend_handler
+    // The password is changed on behalf of the logged in user, if any: the registration can also
+    // follow a password reset link.
+    let backend_handler = match credentials
+        .and_then(|bearer| check_if_token_is_valid(&data, bearer.token()).ok())
+    {
+        Some(validation_result) => data.backend_handler_for(&validation_result, AuditSource::Rest),
+        None => data.backend_handler.clone(),
+    };
+    backend_handler
         .registration_finish(request.into_inner())
         .await?;
     Ok(HttpResponse::Ok().finish())
@@ -516,12 +915,141 @@ where
 
 async fn opaque_register_finish_handler<Backend>(
     data: web::Data<AppState<Backend>>,
+    credentials: Option<BearerAuth>,
     request: web::Json<registration::ClientRegistrationFinishRequest>,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    opaque_register_finish(data, request)
+    opaque_register_finish(data, credentials, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+async fn get_webauthn_registration_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    credentials: &BearerAuth,
+) -> TcpResult<UserId> {
+    // Only the users themselves can register their authenticators.
+    let user = check_if_token_is_valid(data, credentials.token())
+        .map_err(|_| unauthorized_webauthn_registration())?
+        .user;
+    check_webauthn_registration_user(&data.backend_handler, user).await
+}
+
+// The token of a disabled or deleted account stays valid until it expires, but cannot be used to
+// add a credential to the account, nor can the token of a login with an expired password.
+async fn check_webauthn_registration_user<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user: UserId,
+) -> TcpResult<UserId> {
+    check_jwt_user_can_use_api(backend_handler, &user)
+        .await
+        .map_err(|_| unauthorized_webauthn_registration())?;
+    Ok(user)
+}
+
+fn unauthorized_webauthn_registration() -> TcpError {
+    TcpError::UnauthorizedError("Not authorized to register a WebAuthn credential".to_string())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_register_start<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> TcpResult<webauthn_handler::registration::ServerRegistrationStartResponse>
+where
+    Backend: WebauthnHandler + BackendHandler + 'static,
+{
+    let user_id = get_webauthn_registration_user(&data, &credentials).await?;
+    Ok(data
+        .backend_handler
+        .webauthn_registration_start(&user_id)
+        .await?)
+}
+
+async fn webauthn_register_start_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> ApiResult<webauthn_handler::registration::ServerRegistrationStartResponse>
+where
+    Backend: WebauthnHandler + BackendHandler + 'static,
+{
+    webauthn_register_start(data, credentials)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_register_finish<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    request: web::Json<webauthn_handler::registration::ClientRegistrationFinishRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: WebauthnHandler + BackendHandler + 'static,
+{
+    let user_id = get_webauthn_registration_user(&data, &credentials).await?;
+    data.backend_handler
+        .webauthn_registration_finish(&user_id, request.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn webauthn_register_finish_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    request: web::Json<webauthn_handler::registration::ClientRegistrationFinishRequest>,
+) -> HttpResponse
+where
+    Backend: WebauthnHandler + BackendHandler + 'static,
+{
+    webauthn_register_finish(data, credentials, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_login_start<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn_handler::login::ClientLoginStartRequest>,
+) -> ApiResult<webauthn_handler::login::ServerLoginStartResponse>
+where
+    Backend: WebauthnHandler + 'static,
+{
+    data.backend_handler
+        .webauthn_login_start(request.into_inner())
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_login_finish<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn_handler::login::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + WebauthnHandler + 'static,
+{
+    let name = data
+        .backend_handler
+        .webauthn_login_finish(request.into_inner())
+        .await?;
+    get_login_successful_response(&data, &name, &http_request).await
+}
+
+async fn webauthn_login_finish_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn_handler::login::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + WebauthnHandler + 'static,
+{
+    webauthn_login_finish(data, request, http_request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -582,29 +1110,102 @@ where
     }
 }
 
+/// The built-in permission levels, granted by the `lldap_*` groups or the API token scopes. The
+/// admin-defined roles add capabilities on top of them.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Permission {
     Admin,
     PasswordManager,
+    /// Can read everything, and manage the non-admin users. Only granted to API tokens.
+    UserManager,
     Readonly,
     Regular,
 }
 
+impl Permission {
+    pub fn from_groups(is_in_group: impl Fn(&str) -> bool) -> Self {
+        if is_in_group("lldap_admin") {
+            Permission::Admin
+        } else if is_in_group("lldap_password_manager") {
+            Permission::PasswordManager
+        } else if is_in_group("lldap_strict_readonly") {
+            Permission::Readonly
+        } else {
+            Permission::Regular
+        }
+    }
+
+    fn capabilities(self) -> Capabilities {
+        match self {
+            Permission::Admin => Capabilities {
+                read_all: true,
+                reset_passwords: true,
+                manage_users: true,
+                ..Default::default()
+            },
+            Permission::PasswordManager => Capabilities {
+                read_all: true,
+                reset_passwords: true,
+                ..Default::default()
+            },
+            Permission::UserManager => Capabilities {
+                read_all: true,
+                manage_users: true,
+                ..Default::default()
+            },
+            Permission::Readonly => Capabilities {
+                read_all: true,
+                ..Default::default()
+            },
+            Permission::Regular => Capabilities::default(),
+        }
+    }
+}
+
+impl From<ApiTokenScope> for Permission {
+    fn from(scope: ApiTokenScope) -> Self {
+        match scope {
+            ApiTokenScope::ReadOnly => Permission::Readonly,
+            ApiTokenScope::UserManagement => Permission::UserManager,
+            ApiTokenScope::Admin => Permission::Admin,
+        }
+    }
+}
+
+fn is_admin_user(user_groups: &HashSet<GroupDetails>) -> bool {
+    user_groups.iter().any(|g| g.display_name == "lldap_admin")
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidationResults {
     pub user: UserId,
     pub permission: Permission,
+    /// Those of the permission, along with the ones of the roles of the user.
+    pub capabilities: Capabilities,
+    /// What the user can see if they cannot read everything.
+    pub regular_user_visibility: RegularUserVisibility,
 }
 
 impl ValidationResults {
-    #[cfg(test)]
-    pub fn admin() -> Self {
+    pub fn new(user: UserId, permission: Permission) -> Self {
         Self {
-            user: UserId::new("admin"),
-            permission: Permission::Admin,
+            user,
+            permission,
+            capabilities: permission.capabilities(),
+            regular_user_visibility: RegularUserVisibility::default(),
         }
     }
 
+    #[cfg(test)]
+    pub fn admin() -> Self {
+        Self::new(UserId::new("admin"), Permission::Admin)
+    }
+
+    /// Adds the capabilities granted by the roles of the user.
+    pub fn add_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities.merge(capabilities);
+    }
+
     #[must_use]
     pub fn is_admin(&self) -> bool {
         self.permission == Permission::Admin
@@ -612,30 +1213,118 @@ impl ValidationResults {
 
     #[must_use]
     pub fn is_admin_or_readonly(&self) -> bool {
-        self.permission == Permission::Admin
-            || self.permission == Permission::Readonly
-            || self.permission == Permission::PasswordManager
+        self.is_admin() || self.capabilities.read_all
+    }
+
+    /// Whether the user can see their own entry and the groups they belong to in the directory.
+    #[must_use]
+    pub fn can_read_own_entries(&self) -> bool {
+        self.is_admin_or_readonly()
+            || self.regular_user_visibility == RegularUserVisibility::OwnEntries
     }
 
     #[must_use]
     pub fn can_read(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin
-            || self.permission == Permission::PasswordManager
-            || self.permission == Permission::Readonly
-            || &self.user == user
+        self.is_admin_or_readonly() || &self.user == user
     }
 
+    /// Whether the user can create users, and modify and delete any non-admin user. Only admins
+    /// can manage other admins.
     #[must_use]
-    pub fn can_change_password(&self, user: &UserId, user_is_admin: bool) -> bool {
-        self.permission == Permission::Admin
-            || (self.permission == Permission::PasswordManager && !user_is_admin)
-            || &self.user == user
+    pub fn can_manage_users(&self) -> bool {
+        self.is_admin() || self.capabilities.manage_users
+    }
+
+    /// Whether the user can modify or delete the user with the given groups. Through a role, only
+    /// the users that are exclusively in the managed groups can be managed.
+    #[must_use]
+    pub fn can_manage_user(&self, user_groups: &HashSet<GroupDetails>) -> bool {
+        if self.is_admin() {
+            return true;
+        }
+        !is_admin_user(user_groups)
+            && (self.capabilities.manage_users
+                || (!user_groups.is_empty()
+                    && user_groups
+                        .iter()
+                        .all(|g| self.capabilities.managed_groups.contains(&g.group_id))))
+    }
+
+    /// Whether the user can add or remove the user with the given groups from the group. The
+    /// admin group is only managed by the admins.
+    #[must_use]
+    pub fn can_manage_membership(
+        &self,
+        group_id: GroupId,
+        user_groups: &HashSet<GroupDetails>,
+    ) -> bool {
+        if self.is_admin() {
+            return true;
+        }
+        if is_admin_user(user_groups) {
+            return false;
+        }
+        if self.capabilities.manage_users {
+            return true;
+        }
+        if self.capabilities.managed_groups.contains(&group_id)
+            && !user_groups.iter().any(|g| g.group_id == group_id)
+        {
+            // Joining a managed group makes the user manageable: only the users that already are
+            // can be added, so that a role cannot be used to take over any user.
+            return self.can_manage_user(user_groups);
+        }
+        self.capabilities.managed_groups.contains(&group_id)
+            || self.capabilities.managed_memberships.contains(&group_id)
+    }
+
+    /// Whether the user has any capability to manage other users, to skip the lookups otherwise.
+    #[must_use]
+    pub fn can_manage_any_user(&self) -> bool {
+        self.can_manage_users()
+            || !self.capabilities.managed_groups.is_empty()
+            || !self.capabilities.managed_memberships.is_empty()
+    }
+
+    #[must_use]
+    pub fn can_change_password(&self, user: &UserId, user_groups: &HashSet<GroupDetails>) -> bool {
+        &self.user == user || self.can_reset_password(user, user_groups)
+    }
+
+    /// Whether the user can set the password of the user with the given groups without knowing
+    /// the current one. Except for the admins, the users need their current password to change
+    /// their own.
+    #[must_use]
+    pub fn can_reset_password(&self, user: &UserId, user_groups: &HashSet<GroupDetails>) -> bool {
+        self.is_admin()
+            || (&self.user != user
+                && ((self.capabilities.reset_passwords && !is_admin_user(user_groups))
+                    || self.can_manage_user(user_groups)))
     }
 
     #[must_use]
     pub fn can_write(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin || &self.user == user
+        self.is_admin() || &self.user == user
+    }
+}
+
+/// Adds the capabilities of the roles of the user. Admins already have all of them.
+pub(crate) async fn add_role_capabilities<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    validation_result: &mut ValidationResults,
+) -> Result<(), actix_web::Error> {
+    if validation_result.is_admin() {
+        return Ok(());
     }
+    let capabilities = backend_handler
+        .get_user_capabilities(&validation_result.user)
+        .await
+        .map_err(|e| match e {
+            DomainError::EntityNotFound(_) => ErrorUnauthorized("The user doesn't exist anymore"),
+            e => actix_web::error::ErrorInternalServerError(e.to_string()),
+        })?;
+    validation_result.add_capabilities(capabilities);
+    Ok(())
 }
 
 #[instrument(skip_all, level = "debug", err, ret)]
@@ -643,8 +1332,10 @@ pub(crate) fn check_if_token_is_valid<Backend>(
     state: &AppState<Backend>,
     token_str: &str,
 ) -> Result<ValidationResults, actix_web::Error> {
-    let token: Token<_> = VerifyWithKey::verify_with_key(token_str, &state.jwt_key)
-        .map_err(|_| ErrorUnauthorized("Invalid JWT"))?;
+    let token = state
+        .jwt_keys
+        .verify(token_str)
+        .ok_or_else(|| ErrorUnauthorized("Invalid JWT"))?;
     if token.claims().exp.lt(&Utc::now()) {
         return Err(ErrorUnauthorized("Expired JWT"));
     }
@@ -662,24 +1353,47 @@ pub(crate) fn check_if_token_is_valid<Backend>(
     if state.jwt_blacklist.read().unwrap().contains(&jwt_hash) {
         return Err(ErrorUnauthorized("JWT was logged out"));
     }
-    let is_in_group = |name| token.claims().groups.contains(name);
-    Ok(ValidationResults {
-        user: UserId::new(&token.claims().user),
-        permission: if is_in_group("lldap_admin") {
-            Permission::Admin
-        } else if is_in_group("lldap_password_manager") {
-            Permission::PasswordManager
-        } else if is_in_group("lldap_strict_readonly") {
-            Permission::Readonly
-        } else {
-            Permission::Regular
-        },
-    })
+    Ok(ValidationResults::new(
+        UserId::new(&token.claims().user),
+        Permission::from_groups(|name| token.claims().groups.contains(name)),
+    ))
+}
+
+/// Validates the bearer token of an API request, either a JWT or an API token. API tokens act as
+/// a pseudo-user named after the token, e.g. "api_token:3", with the permission of their scope.
+pub(crate) async fn check_if_bearer_is_valid<Backend: BackendHandler>(
+    state: &AppState<Backend>,
+    token_str: &str,
+) -> Result<ValidationResults, actix_web::Error> {
+    if !token_str.starts_with(API_TOKEN_PREFIX) {
+        let mut validation_result = check_if_token_is_valid(state, token_str)?;
+        check_jwt_user_can_use_api(&state.backend_handler, &validation_result.user).await?;
+        validation_result.regular_user_visibility = state.regular_user_visibility;
+        add_role_capabilities(&state.backend_handler, &mut validation_result).await?;
+        return Ok(validation_result);
+    }
+    let token = state
+        .backend_handler
+        .validate_api_token(token_str)
+        .await
+        .map_err(|e| match e {
+            DomainError::AuthenticationError(_) => ErrorUnauthorized("Invalid API token"),
+            e => actix_web::error::ErrorInternalServerError(e.to_string()),
+        })?;
+    Ok(ValidationResults::new(
+        UserId::new(&format!("api_token:{}", token.token_id)),
+        token.scope.into(),
+    ))
 }
 
 pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
 where
-    Backend: TcpBackendHandler + LoginHandler + OpaqueHandler + BackendHandler + 'static,
+    Backend: TcpBackendHandler
+        + LoginHandler
+        + OpaqueHandler
+        + WebauthnHandler
+        + BackendHandler
+        + 'static,
 {
     cfg.service(web::resource("").route(web::post().to(post_authorize_handler::<Backend>)))
         .service(
@@ -694,18 +1408,33 @@ where
             web::resource("/simple/login").route(web::post().to(simple_login_handler::<Backend>)),
         )
         .service(web::resource("/refresh").route(web::get().to(get_refresh_handler::<Backend>)))
+        // The reset steps store and consume the reset tokens.
         .service(
             web::resource("/reset/step1/{user_id}")
+                .wrap(ReadOnlyStrictGuardFactory)
                 .route(web::get().to(get_password_reset_step1_handler::<Backend>)),
         )
         .service(
             web::resource("/reset/step2/{token}")
+                .wrap(ReadOnlyStrictGuardFactory)
                 .route(web::get().to(get_password_reset_step2_handler::<Backend>)),
         )
+        .service(
+            web::resource("/invitation/{token}")
+                .wrap(ReadOnlyGuardFactory)
+                .route(web::get().to(get_invitation_handler::<Backend>))
+                .route(web::post().to(post_accept_invitation_handler::<Backend>)),
+        )
+        .service(
+            web::resource("/sign-up")
+                .wrap(ReadOnlyGuardFactory)
+                .route(web::post().to(post_sign_up_handler::<Backend>)),
+        )
         .service(web::resource("/logout").route(web::get().to(get_logout_handler::<Backend>)))
         .service(
             web::scope("/opaque/register")
                 .wrap(CookieToHeaderTranslatorFactory)
+                .wrap(ReadOnlyGuardFactory)
                 .service(
                     web::resource("/start")
                         .route(web::post().to(opaque_register_start_handler::<Backend>)),
@@ -714,5 +1443,341 @@ where
                     web::resource("/finish")
                         .route(web::post().to(opaque_register_finish_handler::<Backend>)),
                 ),
+        )
+        .service(
+            web::resource("/webauthn/login/start")
+                .route(web::post().to(webauthn_login_start::<Backend>)),
+        )
+        .service(
+            web::resource("/webauthn/login/finish")
+                .route(web::post().to(webauthn_login_finish_handler::<Backend>)),
+        )
+        .service(
+            web::scope("/webauthn/register")
+                .wrap(CookieToHeaderTranslatorFactory)
+                .wrap(ReadOnlyGuardFactory)
+                .service(
+                    web::resource("/start")
+                        .route(web::post().to(webauthn_register_start_handler::<Backend>)),
+                )
+                .service(
+                    web::resource("/finish")
+                        .route(web::post().to(webauthn_register_finish_handler::<Backend>)),
+                ),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_token(keys: &JwtKeys) -> String {
+        create_jwt(keys, "bob".to_owned(), HashSet::new())
+            .as_str()
+            .to_owned()
+    }
+
+    #[test]
+    fn test_jwt_key_rotation() {
+        let old_keys = JwtKeys::new("1", &SecUtf8::from("old_secret"), &[]);
+        let new_keys = JwtKeys::new(
+            "2",
+            &SecUtf8::from("new_secret"),
+            &[JwtSecretOptions {
+                key_id: "1".to_owned(),
+                secret: SecUtf8::from("old_secret"),
+            }],
+        );
+        let old_token = make_token(&old_keys);
+        let new_token = make_token(&new_keys);
+        assert!(new_keys.verify(&old_token).is_some());
+        assert_eq!(
+            new_keys
+                .verify(&new_token)
+                .unwrap()
+                .header()
+                .key_id
+                .as_deref(),
+            Some("2")
+        );
+        assert!(old_keys.verify(&new_token).is_none());
+        // Tokens signed before the key IDs were introduced.
+        let legacy_token = jwt::Token::new(
+            jwt::Header {
+                algorithm: jwt::AlgorithmType::Hs512,
+                ..Default::default()
+            },
+            JWTClaims {
+                exp: Utc::now() + chrono::Duration::days(1),
+                iat: Utc::now(),
+                user: "bob".to_owned(),
+                groups: HashSet::new(),
+            },
+        )
+        .sign_with_key(&new_keys.keys[1].1)
+        .unwrap();
+        assert!(new_keys.verify(legacy_token.as_str()).is_some());
+        assert!(JwtKeys::new("3", &SecUtf8::from("other_secret"), &[])
+            .verify(legacy_token.as_str())
+            .is_none());
+    }
+
+    fn make_groups(names: &[(i32, &str)]) -> HashSet<GroupDetails> {
+        names
+            .iter()
+            .map(|(id, name)| GroupDetails {
+                group_id: GroupId(*id),
+                display_name: name.to_string(),
+                creation_date: Utc.timestamp_opt(42, 42).unwrap(),
+                uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                gid_number: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_role_capabilities() {
+        let admin_groups = make_groups(&[(1, "lldap_admin"), (3, "team")]);
+        let team_groups = make_groups(&[(3, "team")]);
+        let other_groups = make_groups(&[(4, "other")]);
+        let mut manager = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        assert!(!manager.can_manage_any_user());
+        manager.add_capabilities(Capabilities {
+            managed_groups: vec![GroupId(3)],
+            ..Default::default()
+        });
+        assert!(manager.can_manage_any_user());
+        assert!(!manager.can_manage_users());
+        assert!(!manager.is_admin_or_readonly());
+        assert!(manager.can_manage_user(&team_groups));
+        assert!(!manager.can_manage_user(&other_groups));
+        // Admins can only be managed by admins.
+        assert!(!manager.can_manage_user(&admin_groups));
+        assert!(manager.can_change_password(&UserId::new("john"), &team_groups));
+        assert!(!manager.can_change_password(&UserId::new("john"), &other_groups));
+        assert!(!manager.can_manage_membership(GroupId(4), &other_groups));
+        assert!(!manager.can_manage_membership(GroupId(3), &admin_groups));
+        assert!(manager.can_manage_membership(GroupId(3), &team_groups));
+
+        let mut helpdesk = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        helpdesk.add_capabilities(Capabilities {
+            read_all: true,
+            reset_passwords: true,
+            ..Default::default()
+        });
+        assert!(helpdesk.can_read(&UserId::new("john")));
+        assert!(helpdesk.can_change_password(&UserId::new("john"), &other_groups));
+        assert!(!helpdesk.can_change_password(&UserId::new("john"), &admin_groups));
+        assert!(!helpdesk.can_manage_user(&other_groups));
+        // Without knowing it, they can only set the password of the others.
+        assert!(helpdesk.can_change_password(&UserId::new("bob"), &other_groups));
+        assert!(!helpdesk.can_reset_password(&UserId::new("bob"), &other_groups));
+        assert!(helpdesk.can_reset_password(&UserId::new("john"), &other_groups));
+
+        let mut group_manager = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        group_manager.add_capabilities(Capabilities {
+            managed_memberships: vec![GroupId(3)],
+            ..Default::default()
+        });
+        assert!(group_manager.can_manage_any_user());
+        assert!(group_manager.can_manage_membership(GroupId(3), &other_groups));
+        assert!(!group_manager.can_manage_membership(GroupId(4), &team_groups));
+        assert!(!group_manager.can_manage_membership(GroupId(3), &admin_groups));
+        // Only the memberships.
+        assert!(!group_manager.can_manage_user(&team_groups));
+        assert!(!group_manager.can_change_password(&UserId::new("john"), &team_groups));
+    }
+
+    #[test]
+    fn test_role_cannot_take_over_outsiders() {
+        let mut manager = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        manager.add_capabilities(Capabilities {
+            managed_groups: vec![GroupId(3)],
+            managed_memberships: vec![GroupId(3)],
+            ..Default::default()
+        });
+        let john = UserId::new("john");
+        // The manager cannot bring an outsider into the managed group...
+        let outsider_groups = make_groups(&[(4, "other")]);
+        assert!(!manager.can_manage_membership(GroupId(3), &outsider_groups));
+        assert!(!manager.can_manage_membership(GroupId(3), &HashSet::new()));
+        // ...and once added by someone else, they still cannot delete or reset them.
+        let added_groups = make_groups(&[(3, "team"), (4, "other")]);
+        assert!(!manager.can_manage_user(&added_groups));
+        assert!(!manager.can_change_password(&john, &added_groups));
+        assert!(!manager.can_manage_user(&HashSet::new()));
+        // But they can still take them out of the group.
+        assert!(manager.can_manage_membership(GroupId(3), &added_groups));
+
+        // The members of a group with a manager only are not affected.
+        let mut group_manager = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        group_manager.add_capabilities(Capabilities {
+            managed_memberships: vec![GroupId(3)],
+            ..Default::default()
+        });
+        assert!(group_manager.can_manage_membership(GroupId(3), &outsider_groups));
+        assert!(!group_manager.can_manage_user(&added_groups));
+    }
+
+    #[test]
+    fn test_regular_user_visibility() {
+        let mut regular = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        assert!(regular.can_read_own_entries());
+        regular.regular_user_visibility = RegularUserVisibility::Nothing;
+        assert!(!regular.can_read_own_entries());
+        // The restriction doesn't apply to the users that can read everything.
+        let mut readonly = ValidationResults::new(UserId::new("bob"), Permission::Readonly);
+        readonly.regular_user_visibility = RegularUserVisibility::Nothing;
+        assert!(readonly.can_read_own_entries());
+        regular.add_capabilities(Capabilities {
+            read_all: true,
+            ..Default::default()
+        });
+        assert!(regular.can_read_own_entries());
+    }
+
+    #[tokio::test]
+    async fn test_jwt_of_disabled_user() {
+        use crate::domain::{handler::UpdateUserRequest, sql_backend_handler::tests::*};
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        check_jwt_user_can_log_in(&fixture.handler, &bob)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                enabled: Some(false),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            check_jwt_user_can_log_in(&fixture.handler, &bob)
+                .await
+                .unwrap_err()
+                .error_response()
+                .status(),
+            actix_web::http::StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            check_jwt_user_can_log_in(&fixture.handler, &UserId::new("nobody"))
+                .await
+                .unwrap_err()
+                .error_response()
+                .status(),
+            actix_web::http::StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webauthn_registration_of_disabled_user() {
+        use crate::domain::{handler::UpdateUserRequest, sql_backend_handler::tests::*};
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        assert_eq!(
+            check_webauthn_registration_user(&fixture.handler, bob.clone())
+                .await
+                .unwrap(),
+            bob
         );
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                enabled: Some(false),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        for user in [bob, UserId::new("nobody")] {
+            assert!(matches!(
+                check_webauthn_registration_user(&fixture.handler, user).await,
+                Err(TcpError::UnauthorizedError(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_password() {
+        use crate::{
+            domain::{handler::MockTestBackendHandler, types::User},
+            infra::configuration::PasswordPolicyOptions,
+        };
+        let bob = UserId::new("bob");
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_password_policy()
+            .returning(|| PasswordPolicyOptions {
+                max_age_days: 30,
+                ..Default::default()
+            });
+        mock.expect_get_user_details().returning(|user_id| {
+            Ok(User {
+                user_id: user_id.clone(),
+                password_modified_date: Some(Utc::now() - chrono::Duration::days(31)),
+                ..Default::default()
+            })
+        });
+        // The user can still choose a new password.
+        check_jwt_user_can_log_in(&mock, &bob).await.unwrap();
+        // But cannot log in with the password, a WebAuthn credential or through the auth
+        // endpoint, nor use the rest of the API.
+        assert!(matches!(
+            check_user_can_open_session(&mock, &bob).await,
+            Err(TcpError::DomainError(DomainError::AuthenticationError(_)))
+        ));
+        assert_eq!(
+            check_jwt_user_can_use_api(&mock, &bob)
+                .await
+                .unwrap_err()
+                .error_response()
+                .status(),
+            actix_web::http::StatusCode::UNAUTHORIZED
+        );
+        assert!(matches!(
+            check_webauthn_registration_user(&mock, bob).await,
+            Err(TcpError::UnauthorizedError(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_read_only_auth_routes() {
+        use crate::{
+            domain::sql_backend_handler::SqlBackendHandler, infra::read_only::ReadOnlyMode,
+        };
+        use actix_web::{http::StatusCode, test, App};
+        // The requests are rejected before reaching the handlers, which need the application state.
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ReadOnlyMode::new(true)))
+                .service(web::scope("/auth").configure(configure_server::<SqlBackendHandler>)),
+        )
+        .await;
+        for uri in [
+            "/auth/opaque/register/start",
+            "/auth/opaque/register/finish",
+            "/auth/webauthn/register/start",
+            "/auth/webauthn/register/finish",
+            "/auth/invitation/token",
+            "/auth/sign-up",
+        ] {
+            let request = test::TestRequest::post().uri(uri).to_request();
+            assert_eq!(
+                test::call_service(&app, request).await.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{}",
+                uri
+            );
+        }
+        for uri in ["/auth/reset/step1/bob", "/auth/reset/step2/token"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(
+                test::call_service(&app, request).await.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{}",
+                uri
+            );
+        }
+    }
 }
@@ -0,0 +1,187 @@
+//! Conversion of the uploaded avatars to the JPEG photos stored for the users, and fetching of
+//! the remote avatars from Gravatar or a similar service.
+
+use crate::{domain::types::JpegPhoto, infra::configuration::AvatarOptions};
+use anyhow::{bail, Context, Result};
+use image::{
+    imageops::FilterType, io::Limits, DynamicImage, ImageFormat, ImageOutputFormat, Rgb, RgbImage,
+};
+
+const JPEG_QUALITY: u8 = 85;
+const REMOTE_AVATAR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// Guards against the small files that decode to huge images.
+const MAX_SOURCE_DIMENSION: u32 = 10000;
+
+/// Replaces the transparent parts of the image with white, since JPEG has no transparency.
+fn flatten(image: DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as u32;
+        Rgb([0, 1, 2].map(|i| ((pixel[i] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8))
+    })
+}
+
+/// Converts a JPEG, PNG, WebP or GIF image to a JPEG photo that fits in the configured
+/// dimensions. The JPEG images that already fit are kept as they are. An empty upload removes the
+/// avatar.
+pub fn process_avatar(bytes: Vec<u8>, options: &AvatarOptions) -> Result<JpegPhoto> {
+    if bytes.is_empty() {
+        return Ok(JpegPhoto::null());
+    }
+    if bytes.len() > options.max_upload_bytes {
+        bail!(
+            "The image is too large: {} bytes, the limit is {}",
+            bytes.len(),
+            options.max_upload_bytes
+        );
+    }
+    let format = image::guess_format(&bytes).context("Unknown image format")?;
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif
+    ) {
+        bail!(
+            "Unsupported image format {:?}, expected JPEG, PNG, WebP or GIF",
+            format
+        );
+    }
+    let mut reader = image::io::Reader::with_format(std::io::Cursor::new(bytes.as_slice()), format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    reader.limits(limits);
+    let image = reader.decode().context("Invalid image")?;
+    let max_dimension = options.max_dimension;
+    let fits = image.width() <= max_dimension && image.height() <= max_dimension;
+    if format == ImageFormat::Jpeg && fits {
+        return JpegPhoto::try_from(bytes);
+    }
+    let image = if fits {
+        image
+    } else {
+        // Keeps the aspect ratio.
+        image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    };
+    let mut jpeg = Vec::new();
+    DynamicImage::ImageRgb8(flatten(image))
+        .write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            ImageOutputFormat::Jpeg(JPEG_QUALITY),
+        )
+        .context("while converting the image to JPEG")?;
+    JpegPhoto::try_from(jpeg)
+}
+
+/// The hex SHA-256 of the trimmed, lowercase email, as expected by Gravatar.
+pub fn email_hash(email: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!(
+        "{:x}",
+        Sha256::digest(email.trim().to_lowercase().as_bytes())
+    )
+}
+
+pub fn remote_avatar_url(template: &str, email_hash: &str) -> String {
+    template.replace("{email_sha256}", email_hash)
+}
+
+pub fn remote_avatar_client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(REMOTE_AVATAR_TIMEOUT)
+        .build()?)
+}
+
+/// Fetches and converts the remote avatar, if the service has one for the email.
+pub async fn fetch_remote_avatar(
+    client: &reqwest::Client,
+    url: &str,
+    options: &AvatarOptions,
+) -> Result<Option<JpegPhoto>> {
+    let response = client.get(url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let bytes = response.error_for_status()?.bytes().await?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    process_avatar(bytes.to_vec(), options).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    fn encode(image: DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    fn decode(photo: JpegPhoto) -> DynamicImage {
+        image::load_from_memory_with_format(&photo.into_bytes(), ImageFormat::Jpeg).unwrap()
+    }
+
+    #[test]
+    fn test_small_jpeg_is_kept() {
+        let photo = JpegPhoto::for_tests();
+        let bytes = photo.clone().into_bytes();
+        assert_eq!(
+            process_avatar(bytes, &AvatarOptions::default()).unwrap(),
+            photo
+        );
+    }
+
+    #[test]
+    fn test_png_is_converted_and_resized() {
+        let png = encode(
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(200, 100, Rgba([255, 0, 0, 0]))),
+            ImageOutputFormat::Png,
+        );
+        let options = AvatarOptions {
+            max_dimension: 50,
+            ..Default::default()
+        };
+        let image = decode(process_avatar(png, &options).unwrap());
+        assert_eq!(image.dimensions(), (50, 25));
+        // The transparent pixels are white.
+        assert!(image.to_rgb8().get_pixel(10, 10).0.iter().all(|c| *c > 240));
+    }
+
+    #[test]
+    fn test_invalid_avatars() {
+        let options = AvatarOptions {
+            max_upload_bytes: 10,
+            ..Default::default()
+        };
+        assert!(process_avatar(JpegPhoto::for_tests().into_bytes(), &options).is_err());
+        assert!(process_avatar(b"not an image".to_vec(), &AvatarOptions::default()).is_err());
+        assert_eq!(
+            process_avatar(Vec::new(), &options).unwrap(),
+            JpegPhoto::null()
+        );
+    }
+
+    #[test]
+    fn test_remote_avatar_url() {
+        let hash = email_hash(" MyEmailAddress@example.com ");
+        assert_eq!(
+            hash,
+            "84059b07d4be67b806386c0aad8070a23f18836bbaae342275dc0a83414c32ee"
+        );
+        assert_eq!(
+            remote_avatar_url(
+                "https://www.gravatar.com/avatar/{email_sha256}?d=404",
+                &hash
+            ),
+            format!("https://www.gravatar.com/avatar/{}?d=404", hash)
+        );
+    }
+}
@@ -0,0 +1,332 @@
+//! Declarative management of the directory: the users, groups and memberships listed in the
+//! bootstrap file are reconciled with the database on every start.
+
+use crate::domain::{
+    handler::{BackendHandler, CreateUserRequest},
+    types::UserId,
+};
+use anyhow::{Context, Result};
+use figment::{
+    providers::{Format, Toml, Yaml},
+    Figment,
+};
+use serde::Deserialize;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// Prefix of the groups created by the server, which are never pruned.
+const BUILT_IN_GROUP_PREFIX: &str = "lldap_";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BootstrapUser {
+    pub id: String,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// A hash exported from another directory (bcrypt, argon2, SSHA...), checked on the first
+    /// bind. Without it, the user has to reset their password.
+    pub password_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BootstrapGroup {
+    pub name: String,
+    /// The user IDs of the members.
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BootstrapFile {
+    #[serde(default)]
+    pub users: Vec<BootstrapUser>,
+    #[serde(default)]
+    pub groups: Vec<BootstrapGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BootstrapReport {
+    pub created_users: Vec<UserId>,
+    pub created_groups: Vec<String>,
+    pub added_memberships: usize,
+    pub removed_memberships: usize,
+    pub deleted_users: Vec<UserId>,
+    pub deleted_groups: Vec<String>,
+    /// The entries that could not be reconciled, which don't stop the others.
+    pub errors: Vec<String>,
+}
+
+fn is_yaml(file_name: &str) -> bool {
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"))
+        .unwrap_or(false)
+}
+
+/// Parses the content of the bootstrap file, in YAML if its name ends with ".yaml" or ".yml",
+/// otherwise in TOML.
+pub fn parse(file_name: &str, content: &str) -> Result<BootstrapFile> {
+    let figment = if is_yaml(file_name) {
+        Figment::from(Yaml::string(content))
+    } else {
+        Figment::from(Toml::string(content))
+    };
+    Ok(figment.extract()?)
+}
+
+pub fn read(file_name: &str) -> Result<BootstrapFile> {
+    let content = std::fs::read_to_string(file_name)
+        .with_context(|| format!("while reading {}", file_name))?;
+    parse(file_name, &content).with_context(|| format!("while parsing {}", file_name))
+}
+
+/// Creates the users, groups and memberships of the file that don't exist, leaving the existing
+/// ones as they are. With `prune`, also deletes the users and groups missing from the file and
+/// removes the members of the listed groups missing from their `members`, except for the admin
+/// user and the built-in groups. A failure only skips the corresponding entry, and is reported.
+pub async fn reconcile<Handler: BackendHandler>(
+    handler: &Handler,
+    file: BootstrapFile,
+    prune: bool,
+    admin: &UserId,
+) -> Result<BootstrapReport> {
+    let mut report = BootstrapReport::default();
+
+    let existing_users = handler
+        .list_users(None, false)
+        .await?
+        .into_iter()
+        .map(|u| u.user.user_id)
+        .collect::<Vec<_>>();
+    let mut declared_users = HashSet::new();
+    for user in file.users {
+        let user_id = UserId::new(&user.id);
+        declared_users.insert(user_id.clone());
+        if existing_users.contains(&user_id) {
+            continue;
+        }
+        let request = CreateUserRequest {
+            user_id: user_id.clone(),
+            email: user.email,
+            display_name: user.display_name,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            legacy_password_hash: user.password_hash,
+            ..Default::default()
+        };
+        let result = match handler.check_user_id(&user.id) {
+            Ok(()) => handler.create_user(request).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => report.created_users.push(user_id),
+            Err(e) => report.errors.push(format!("user {}: {}", user.id, e)),
+        }
+    }
+
+    let existing_groups = handler.list_groups(None).await?;
+    let mut declared_groups = HashSet::new();
+    for group in file.groups {
+        declared_groups.insert(group.name.clone());
+        let (group_id, members) = match existing_groups
+            .iter()
+            .find(|g| g.display_name == group.name)
+        {
+            Some(g) => (g.id, g.users.iter().cloned().collect::<HashSet<_>>()),
+            None => match handler.create_group(&group.name).await {
+                Ok(group_id) => {
+                    report.created_groups.push(group.name.clone());
+                    (group_id, HashSet::new())
+                }
+                Err(e) => {
+                    report.errors.push(format!("group {}: {}", group.name, e));
+                    continue;
+                }
+            },
+        };
+        let declared_members = group
+            .members
+            .iter()
+            .map(|m| UserId::new(m))
+            .collect::<HashSet<_>>();
+        for member in group.members.iter().map(|m| UserId::new(m)) {
+            if members.contains(&member) {
+                continue;
+            }
+            match handler.add_user_to_group(&member, group_id).await {
+                Ok(()) => report.added_memberships += 1,
+                Err(e) => report.errors.push(format!(
+                    "group {}: could not add {}: {}",
+                    group.name, member, e
+                )),
+            }
+        }
+        if !prune {
+            continue;
+        }
+        for member in members
+            .iter()
+            .filter(|m| !declared_members.contains(m) && *m != admin)
+        {
+            match handler.remove_user_from_group(member, group_id).await {
+                Ok(()) => report.removed_memberships += 1,
+                Err(e) => report.errors.push(format!(
+                    "group {}: could not remove {}: {}",
+                    group.name, member, e
+                )),
+            }
+        }
+    }
+
+    if prune {
+        for user_id in existing_users
+            .into_iter()
+            .filter(|u| !declared_users.contains(u) && u != admin)
+        {
+            match handler.delete_user(&user_id).await {
+                Ok(()) => report.deleted_users.push(user_id),
+                Err(e) => report
+                    .errors
+                    .push(format!("user {}: could not delete: {}", user_id, e)),
+            }
+        }
+        for group in existing_groups.into_iter().filter(|g| {
+            !declared_groups.contains(&g.display_name)
+                && !g.display_name.starts_with(BUILT_IN_GROUP_PREFIX)
+        }) {
+            match handler.delete_group(group.id).await {
+                Ok(()) => report.deleted_groups.push(group.display_name),
+                Err(e) => report.errors.push(format!(
+                    "group {}: could not delete: {}",
+                    group.display_name, e
+                )),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Reads the bootstrap file and reconciles the directory with it, logging the changes and the
+/// errors. Only the file itself failing to be read stops the start.
+pub async fn run<Handler: BackendHandler>(
+    handler: &Handler,
+    file_name: &str,
+    prune: bool,
+    admin: &UserId,
+) -> Result<()> {
+    let file = read(file_name)?;
+    let report = reconcile(handler, file, prune, admin).await?;
+    for error in &report.errors {
+        warn!("{}: {}", file_name, error);
+    }
+    info!(
+        "Bootstrap from {}: {} users and {} groups created, {} memberships added, {} removed, \
+         {} users and {} groups deleted, {} errors",
+        file_name,
+        report.created_users.len(),
+        report.created_groups.len(),
+        report.added_memberships,
+        report.removed_memberships,
+        report.deleted_users.len(),
+        report.deleted_groups.len(),
+        report.errors.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::GroupBackendHandler, sql_backend_handler::tests::*};
+
+    const TOML: &str = r#"
+        [[users]]
+        id = "Alice"
+        email = "alice@example.com"
+        display_name = "Alice"
+
+        [[users]]
+        id = "bob"
+        email = "bob@bob.bob"
+
+        [[groups]]
+        name = "Best Group"
+        members = ["alice", "bob"]
+
+        [[groups]]
+        name = "staff"
+        members = ["alice"]
+    "#;
+
+    #[test]
+    fn test_parse() {
+        let file = parse("bootstrap.toml", TOML).unwrap();
+        let yaml = "users:\n\
+                    - id: Alice\n  \
+                      email: alice@example.com\n  \
+                      display_name: Alice\n\
+                    - id: bob\n  \
+                      email: bob@bob.bob\n\
+                    groups:\n\
+                    - name: Best Group\n  \
+                      members: [alice, bob]\n\
+                    - name: staff\n  \
+                      members: [alice]\n";
+        assert_eq!(parse("bootstrap.yml", yaml).unwrap(), file);
+        assert_eq!(file.users[0].id, "Alice");
+        assert_eq!(file.users[0].password_hash, None);
+        assert_eq!(file.groups[1].members, vec!["alice"]);
+        parse("bootstrap.toml", "[[users]]\nid = \"bob\"\n").unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_reconcile() {
+        let fixture = TestFixture::new().await;
+        let admin = UserId::new("patrick");
+        let file = parse("bootstrap.toml", TOML).unwrap();
+
+        let report = reconcile(&fixture.handler, file.clone(), false, &admin)
+            .await
+            .unwrap();
+        assert_eq!(report.created_users, vec![UserId::new("alice")]);
+        assert_eq!(report.created_groups, vec!["staff"]);
+        // Bob is already in the best group.
+        assert_eq!(report.added_memberships, 2);
+        assert!(report.deleted_users.is_empty());
+        assert!(report.errors.is_empty());
+
+        // Running it again changes nothing.
+        let report = reconcile(&fixture.handler, file.clone(), false, &admin)
+            .await
+            .unwrap();
+        assert_eq!(report, BootstrapReport::default());
+
+        let report = reconcile(&fixture.handler, file, true, &admin)
+            .await
+            .unwrap();
+        assert_eq!(report.removed_memberships, 0);
+        assert_eq!(
+            report.deleted_users,
+            vec![UserId::new("john"), UserId::new("nogroup")]
+        );
+        assert_eq!(report.deleted_groups, vec!["Empty Group", "Worst Group"]);
+        let groups = fixture.handler.list_groups(None).await.unwrap();
+        let best_group = groups
+            .iter()
+            .find(|g| g.display_name == "Best Group")
+            .unwrap();
+        assert_eq!(
+            best_group.users.iter().cloned().collect::<HashSet<_>>(),
+            HashSet::from([
+                UserId::new("alice"),
+                UserId::new("bob"),
+                UserId::new("patrick")
+            ])
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use crate::domain::{
+    handler::{ChangeLogBackendHandler, GroupBackendHandler, GroupRequestFilter},
+    types::{Change, ChangeEntryType, ChangeType, DateTime, UserId, Uuid},
+};
+use actix::prelude::{Actor, AsyncContext, Context};
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, info, instrument};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// Number of events kept for the slowest consumer before it starts missing some.
+const BUS_CAPACITY: usize = 1024;
+
+/// A change to a user or a group, as broadcast to the webhooks and the GraphQL subscriptions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ChangeEvent {
+    /// Increasing identifier of the event, the same as the underlying change.
+    pub id: i32,
+    /// One of "user.created", "user.updated", "user.deleted", "group.created", "group.updated"
+    /// or "group.deleted". Membership changes are reported as updates of both the user and the
+    /// group.
+    pub event: String,
+    /// The user ID or group name.
+    pub entry_id: String,
+    pub entry_uuid: Uuid,
+    pub date: DateTime,
+    /// The current members of the group, for the group creations and updates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<String>>,
+}
+
+/// The internal event bus: each subscriber receives all the events published after it subscribed.
+pub type ChangeEventBus = broadcast::Sender<ChangeEvent>;
+
+pub fn new_bus() -> ChangeEventBus {
+    broadcast::channel(BUS_CAPACITY).0
+}
+
+pub(crate) fn event_name(entry_type: ChangeEntryType, change_type: ChangeType) -> String {
+    let entry = match entry_type {
+        ChangeEntryType::User => "user",
+        ChangeEntryType::Group => "group",
+    };
+    let action = match change_type {
+        ChangeType::Add => "created",
+        ChangeType::Modify => "updated",
+        ChangeType::Delete => "deleted",
+    };
+    format!("{}.{}", entry, action)
+}
+
+async fn make_event<Backend: GroupBackendHandler>(
+    backend_handler: &Backend,
+    change: Change,
+) -> Result<ChangeEvent> {
+    let members = if change.entry_type == ChangeEntryType::Group
+        && change.change_type != ChangeType::Delete
+    {
+        // The group might have been deleted since, in which case there are no members to report.
+        backend_handler
+            .list_groups(Some(GroupRequestFilter::Uuid(change.entry_uuid.clone())))
+            .await?
+            .into_iter()
+            .next()
+            .map(|group| group.users.into_iter().map(UserId::into_string).collect())
+    } else {
+        None
+    };
+    Ok(ChangeEvent {
+        id: change.change_id,
+        event: event_name(change.entry_type, change.change_type),
+        entry_id: change.entry_id,
+        entry_uuid: change.entry_uuid,
+        date: change.change_date,
+        members,
+    })
+}
+
+/// Polls the change log and publishes each new change on the event bus, in order.
+pub struct ChangeNotifier<Backend> {
+    backend_handler: Backend,
+    bus: ChangeEventBus,
+}
+
+impl<Backend> Actor for ChangeNotifier<Backend>
+where
+    Backend: ChangeLogBackendHandler + GroupBackendHandler + Clone + Unpin + 'static,
+{
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Change notifier started");
+        let future = actix::fut::wrap_future::<_, Self>(Self::run(
+            self.backend_handler.clone(),
+            self.bus.clone(),
+        ));
+        context.spawn(future);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        info!("Change notifier stopped");
+    }
+}
+
+impl<Backend> ChangeNotifier<Backend>
+where
+    Backend: ChangeLogBackendHandler + GroupBackendHandler + Clone + Unpin + 'static,
+{
+    pub fn new(backend_handler: Backend, bus: ChangeEventBus) -> Self {
+        Self {
+            backend_handler,
+            bus,
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn run(backend_handler: Backend, bus: ChangeEventBus) {
+        // Only the changes from now on are published.
+        let mut last_change_id = loop {
+            match backend_handler.get_last_change_id().await {
+                Ok(id) => break id,
+                Err(e) => error!("Could not get the last change: {:#}", e),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let changes = match backend_handler.list_changes_since(last_change_id).await {
+                Ok(changes) => changes,
+                Err(e) => {
+                    error!("Could not list the changes: {:#}", e);
+                    continue;
+                }
+            };
+            for change in changes {
+                let change_id = change.change_id;
+                match make_event(&backend_handler, change).await {
+                    // Fails only when nobody is listening, which is fine.
+                    Ok(event) => drop(bus.send(event)),
+                    Err(e) => {
+                        // Retried at the next poll.
+                        error!(
+                            "Could not build the event for change {}: {:#}",
+                            change_id, e
+                        );
+                        break;
+                    }
+                };
+                last_change_id = change_id;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::MockTestBackendHandler,
+        types::{Group, GroupId},
+    };
+    use chrono::TimeZone;
+    use mockall::predicate::eq;
+
+    fn make_change(entry_type: ChangeEntryType, change_type: ChangeType) -> Change {
+        Change {
+            change_id: 3,
+            entry_type,
+            entry_id: "entry".to_owned(),
+            entry_uuid: Uuid::from_name_and_date(
+                "entry",
+                &chrono::Utc.timestamp_opt(0, 0).unwrap(),
+            ),
+            change_type,
+            change_date: chrono::Utc.timestamp_opt(1, 0).unwrap(),
+            diff: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_make_user_event() {
+        let mock = MockTestBackendHandler::new();
+        let event = make_event(&mock, make_change(ChangeEntryType::User, ChangeType::Add))
+            .await
+            .unwrap();
+        assert_eq!(event.event, "user.created");
+        assert_eq!(event.members, None);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "id": 3,
+                "event": "user.created",
+                "entry_id": "entry",
+                "entry_uuid": event.entry_uuid.as_str(),
+                "date": "1970-01-01T00:00:01Z",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_make_group_event() {
+        let mut mock = MockTestBackendHandler::new();
+        let change = make_change(ChangeEntryType::Group, ChangeType::Modify);
+        let uuid = change.entry_uuid.clone();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Uuid(uuid.clone()))))
+            .times(1)
+            .return_once(move |_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "entry".to_owned(),
+                    creation_date: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(1, 0).unwrap(),
+                    uuid,
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                    users: vec![UserId::new("bob"), UserId::new("john")],
+                    attributes: Vec::new(),
+                }])
+            });
+        let event = make_event(&mock, change).await.unwrap();
+        assert_eq!(event.event, "group.updated");
+        assert_eq!(
+            event.members,
+            Some(vec!["bob".to_owned(), "john".to_owned()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_events() {
+        use crate::domain::{
+            handler::{
+                CreateUserRequest, DirectoryChangesBackendHandler, DirectoryOperation,
+                UpdateGroupRequest, UpdateUserRequest,
+            },
+            sql_backend_handler::tests::TestFixture,
+        };
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let alice = UserId::new("alice");
+        let last_change_id = handler.get_last_change_id().await.unwrap();
+        handler
+            .apply_changes(vec![
+                DirectoryOperation::CreateUser(CreateUserRequest {
+                    user_id: alice.clone(),
+                    email: "alice@example.com".to_owned(),
+                    ..Default::default()
+                }),
+                DirectoryOperation::CreateGroup("Interns".to_owned()),
+                DirectoryOperation::UpdateGroup(UpdateGroupRequest {
+                    group_id: fixture.groups[2],
+                    display_name: Some("Full Group".to_owned()),
+                    ..Default::default()
+                }),
+                DirectoryOperation::UpdateUser(UpdateUserRequest {
+                    user_id: alice.clone(),
+                    display_name: Some("Alice".to_owned()),
+                    ..Default::default()
+                }),
+                DirectoryOperation::AddUserToGroup {
+                    user_id: alice,
+                    group_id: fixture.groups[2],
+                },
+            ])
+            .await
+            .unwrap();
+        // Like the single operations, every operation of the batch is published.
+        let mut events = Vec::new();
+        for change in handler.list_changes_since(last_change_id).await.unwrap() {
+            let event = make_event(handler, change).await.unwrap();
+            events.push((event.event, event.entry_id, event.members));
+        }
+        let full_group_members = Some(vec!["alice".to_owned()]);
+        assert_eq!(
+            events,
+            vec![
+                ("user.created".to_owned(), "alice".to_owned(), None),
+                (
+                    "group.created".to_owned(),
+                    "Interns".to_owned(),
+                    Some(vec![])
+                ),
+                (
+                    "group.updated".to_owned(),
+                    "Full Group".to_owned(),
+                    full_group_members.clone()
+                ),
+                ("user.updated".to_owned(), "alice".to_owned(), None),
+                ("user.updated".to_owned(), "alice".to_owned(), None),
+                (
+                    "group.updated".to_owned(),
+                    "Full Group".to_owned(),
+                    full_group_members
+                ),
+            ]
+        );
+    }
+}
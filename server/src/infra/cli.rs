@@ -22,10 +22,45 @@ pub enum Command {
     Run(RunOpts),
     /// Test whether the LDAP and GraphQL server are responsive.
     #[clap(name = "healthcheck")]
-    HealthCheck(RunOpts),
+    HealthCheck(HealthCheckOpts),
     /// Send a test email.
     #[clap(name = "send_test_email")]
     SendTestEmail(TestEmailOpts),
+    /// Export the users, groups, memberships and custom attributes to a file.
+    #[clap(name = "backup")]
+    Backup(BackupOpts),
+    /// Import a backup into an empty database, possibly with another database backend.
+    #[clap(name = "restore")]
+    Restore(RestoreOpts),
+    /// Export the users and groups in the LDIF format, as they are served over LDAP.
+    #[clap(name = "export_ldif")]
+    ExportLdif(ExportLdifOpts),
+    /// Create users and groups from an LDIF or CSV file.
+    #[clap(name = "import")]
+    Import(ImportOpts),
+    /// Copy the users, groups and memberships of another LDAP directory (OpenLDAP, Active
+    /// Directory...). The passwords cannot be copied.
+    #[clap(name = "migrate_from_ldap")]
+    MigrateFromLdap(MigrateFromLdapOpts),
+    /// Set the password of the admin user directly in the database, without a running server.
+    #[clap(name = "reset_admin_password")]
+    ResetAdminPassword(ResetAdminPasswordOpts),
+    /// Manage the users of a running server, through its GraphQL API.
+    #[clap(subcommand, name = "user")]
+    User(UserCommand),
+    /// Manage the groups of a running server, through its GraphQL API.
+    #[clap(subcommand, name = "group")]
+    Group(GroupCommand),
+    /// Load and validate the configuration: secrets, TLS files, database and, with `--online`,
+    /// the SMTP server. Exits with an error if any check fails.
+    #[clap(name = "check_config", alias = "check-config")]
+    CheckConfig(CheckConfigOpts),
+    /// Copy the SQLite database to a new file, while the server is running.
+    #[clap(name = "backup_sqlite")]
+    BackupSqlite(BackupSqliteOpts),
+    /// Upgrade or roll back the database schema.
+    #[clap(name = "migrate")]
+    Migrate(MigrateOpts),
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -54,7 +89,7 @@ pub struct RunOpts {
     #[clap(long, env = "LLDAP_SERVER_KEY_FILE")]
     pub server_key_file: Option<String>,
 
-    /// Change ldap host. Default: "0.0.0.0"
+    /// Change ldap host, or several hosts separated by commas. Default: "0.0.0.0"
     #[clap(long, env = "LLDAP_LDAP_HOST")]
     pub ldap_host: Option<String>,
 
@@ -62,7 +97,7 @@ pub struct RunOpts {
     #[clap(long, env = "LLDAP_LDAP_PORT")]
     pub ldap_port: Option<u16>,
 
-    /// Change HTTP API host. Default: "0.0.0.0"
+    /// Change HTTP API host, or several hosts separated by commas. Default: "0.0.0.0"
     #[clap(long, env = "LLDAP_HTTP_HOST")]
     pub http_host: Option<String>,
 
@@ -74,6 +109,16 @@ pub struct RunOpts {
     #[clap(long, env = "LLDAP_HTTP_URL")]
     pub http_url: Option<String>,
 
+    /// Keep the users and groups in memory instead of the database, for demos and tests: they are
+    /// lost when the server stops.
+    #[clap(long, env = "LLDAP_EPHEMERAL")]
+    pub ephemeral: bool,
+
+    /// Run as a read-only replica: serve the LDAP searches from the replicated database, and
+    /// forward the binds and the writes to the primary (see `replica_options`).
+    #[clap(long, env = "LLDAP_REPLICA")]
+    pub replica: bool,
+
     #[clap(flatten)]
     pub smtp_opts: SmtpOpts,
 
@@ -94,6 +139,330 @@ pub struct TestEmailOpts {
     pub smtp_opts: SmtpOpts,
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct BackupOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// File to write the backup to. It must not exist.
+    #[clap(short, long)]
+    pub output_file: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct RestoreOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Backup file to import.
+    #[clap(short, long)]
+    pub input_file: String,
+
+    /// Replace the users and groups already in the database.
+    #[clap(long)]
+    pub replace: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ExportLdifOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// File to write the LDIF to.
+    #[clap(short, long)]
+    pub output_file: String,
+
+    /// Include the attributes maintained by the server (createTimestamp, entryUUID...), which
+    /// can't be imported with ldapadd.
+    #[clap(long)]
+    pub operational_attributes: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// LDIF or CSV file to import.
+    #[clap(short, long)]
+    pub input_file: String,
+
+    /// Format of the file, "ldif" or "csv". Guessed from the extension by default.
+    #[clap(long)]
+    pub format: Option<String>,
+
+    /// Only report what would be created, and the errors.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Number of users created per transaction.
+    #[clap(long, default_value = "100")]
+    pub batch_size: usize,
+
+    /// CSV column holding the user IDs.
+    #[clap(long, default_value = "id")]
+    pub csv_user_id_column: String,
+
+    /// CSV column holding the emails.
+    #[clap(long, default_value = "email")]
+    pub csv_email_column: String,
+
+    /// CSV column holding the display names.
+    #[clap(long, default_value = "display_name")]
+    pub csv_display_name_column: String,
+
+    /// CSV column holding the first names.
+    #[clap(long, default_value = "first_name")]
+    pub csv_first_name_column: String,
+
+    /// CSV column holding the last names.
+    #[clap(long, default_value = "last_name")]
+    pub csv_last_name_column: String,
+
+    /// CSV column listing the groups of the users, separated by ';'.
+    #[clap(long, default_value = "groups")]
+    pub csv_groups_column: String,
+
+    /// CSV column holding password hashes exported from another directory (bcrypt, argon2,
+    /// SSHA...), checked on the first LDAP bind.
+    #[clap(long, default_value = "password_hash")]
+    pub csv_password_hash_column: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct MigrateFromLdapOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// URL of the directory to migrate from, "ldap://" or "ldaps://".
+    #[clap(long)]
+    pub url: String,
+
+    /// DN to bind with, able to read all the users and groups.
+    #[clap(long)]
+    pub bind_dn: String,
+
+    #[clap(long, env = "LLDAP_MIGRATION_BIND_PASSWORD", hide_env_values = true)]
+    pub bind_password: String,
+
+    /// Where to search for the users and groups.
+    #[clap(long)]
+    pub base_dn: String,
+
+    #[clap(
+        long,
+        default_value = "(|(objectClass=person)(objectClass=posixAccount))"
+    )]
+    pub user_filter: String,
+
+    #[clap(
+        long,
+        default_value = "(|(objectClass=groupOfNames)(objectClass=groupOfUniqueNames)(objectClass=posixGroup)(objectClass=group))"
+    )]
+    pub group_filter: String,
+
+    /// Only report what would be created, and the errors.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Email the created users a link to choose their password.
+    #[clap(long)]
+    pub send_password_emails: bool,
+
+    /// How long the emailed links are valid.
+    #[clap(long, default_value = "7")]
+    pub password_link_validity_days: i64,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ResetAdminPasswordOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// The admin user. Default: ldap_user_dn from the configuration.
+    #[clap(long)]
+    pub user: Option<String>,
+
+    /// The new password. Default: ldap_user_pass from the configuration.
+    #[clap(long, env = "LLDAP_NEW_ADMIN_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+}
+
+clap::arg_enum! {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthCheckOutput {
+    Text,
+    Json,
+}
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct HealthCheckOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// "text" logs the failures and exits with 1 if the server is down, 0 otherwise. "json"
+    /// prints the report of the checks, with their latency, and exits with 0 if the server is
+    /// healthy, 1 if it is down and 2 if it is degraded (warnings only, e.g. a certificate about
+    /// to expire).
+    #[clap(long, default_value = "text", possible_values = HealthCheckOutput::variants(), case_insensitive = true)]
+    pub output: HealthCheckOutput,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CheckConfigOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Also connect to the SMTP server.
+    #[clap(long)]
+    pub online: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ApiClientOpts {
+    /// URL of the HTTP API of the server.
+    #[clap(long, env = "LLDAP_API_URL", default_value = "http://localhost:17170")]
+    pub url: String,
+
+    /// JWT of an admin user, as returned by "/auth/simple/login".
+    #[clap(long, env = "LLDAP_TOKEN", hide_env_values = true)]
+    pub token: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub enum UserCommand {
+    /// Create a user.
+    #[clap(name = "add")]
+    Add(UserAddOpts),
+    /// Delete a user.
+    #[clap(name = "delete")]
+    Delete(UserDeleteOpts),
+    /// List the users.
+    #[clap(name = "list")]
+    List(UserListOpts),
+    /// Set the password of a user.
+    #[clap(name = "set-password")]
+    SetPassword(UserSetPasswordOpts),
+    /// Email a user a link to choose a new password.
+    #[clap(name = "send-password-reset")]
+    SendPasswordReset(UserSendPasswordResetOpts),
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct UserAddOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+
+    pub user_id: String,
+
+    #[clap(long)]
+    pub email: String,
+
+    #[clap(long)]
+    pub display_name: Option<String>,
+
+    #[clap(long)]
+    pub first_name: Option<String>,
+
+    #[clap(long)]
+    pub last_name: Option<String>,
+
+    /// Initial password. Without it, the user can only log in after a password reset.
+    #[clap(long, env = "LLDAP_USER_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct UserDeleteOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+
+    pub user_id: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct UserListOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct UserSetPasswordOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+
+    pub user_id: String,
+
+    #[clap(long, env = "LLDAP_USER_PASSWORD", hide_env_values = true)]
+    pub password: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct UserSendPasswordResetOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+
+    pub user_id: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub enum GroupCommand {
+    /// Create a group.
+    #[clap(name = "add")]
+    Add(GroupNameOpts),
+    /// Delete a group.
+    #[clap(name = "delete")]
+    Delete(GroupNameOpts),
+    /// Add a user to a group.
+    #[clap(name = "add-member")]
+    AddMember(GroupAddMemberOpts),
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct GroupNameOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+
+    /// Display name of the group.
+    pub group: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct GroupAddMemberOpts {
+    #[clap(flatten)]
+    pub api: ApiClientOpts,
+
+    /// Display name of the group.
+    pub group: String,
+
+    pub user_id: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct BackupSqliteOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// File to write the copy of the database to. It must not exist.
+    #[clap(short, long)]
+    pub output_file: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct MigrateOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Schema version to migrate to. Default: the latest one.
+    #[clap(long)]
+    pub to: Option<u8>,
+
+    /// Only print the migrations, without applying them.
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
 #[derive(Debug, Parser, Clone)]
 #[clap(next_help_heading = Some("LDAPS"), setting = clap::AppSettings::DeriveDisplayOrder)]
 pub struct LdapsOpts {
@@ -101,6 +470,10 @@ pub struct LdapsOpts {
     #[clap(long, env = "LLDAP_LDAPS_OPTIONS__ENABLED")]
     pub ldaps_enabled: Option<bool>,
 
+    /// Change ldap ssl host, or several hosts separated by commas. Default: same as the ldap host
+    #[clap(long, env = "LLDAP_LDAPS_OPTIONS__HOST")]
+    pub ldaps_host: Option<String>,
+
     /// Change ldap ssl port. Default: 6360
     #[clap(long, env = "LLDAP_LDAPS_OPTIONS__PORT")]
     pub ldaps_port: Option<u16>,
@@ -157,11 +530,30 @@ pub struct SmtpOpts {
     pub smtp_encryption: Option<SmtpEncryption>,
 }
 
+clap::arg_enum! {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaFormat {
+    Sdl,
+    Json,
+}
+}
+
 #[derive(Debug, Parser, Clone)]
 pub struct ExportGraphQLSchemaOpts {
     /// Output to a file. If not specified, the config is printed to the standard output.
     #[clap(short, long)]
     pub output_file: Option<String>,
+
+    /// Format of the schema: the schema language, or the JSON result of the introspection query
+    /// used by the client code generators.
+    #[clap(long, default_value = "sdl", possible_values = SchemaFormat::variants(), case_insensitive = true)]
+    pub format: SchemaFormat,
+
+    /// Annotate the fields of the queries, mutations and subscriptions with the permissions
+    /// required to use them: as comments in the schema language, and as a "requiredPermissions"
+    /// list in the JSON.
+    #[clap(long)]
+    pub with_permissions: bool,
 }
 
 pub fn init() -> CLIOpts {
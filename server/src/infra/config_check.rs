@@ -0,0 +1,248 @@
+//! Validation of the configuration before deploying it, behind the `check_config` command.
+
+use crate::infra::{
+    bootstrap,
+    configuration::{Configuration, ExternalAuthType, SecretsProviderType},
+    database,
+    graphql::persisted_queries::PersistedQueries,
+    ldap_client_profiles::ClientProfiles,
+    mail, secrets_provider, tls_certificate,
+};
+use anyhow::{Context, Result};
+use secstr::SecUtf8;
+
+// Below this length, the JWT secret can be brute-forced offline from a single token.
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+const MIN_ADMIN_PASSWORD_LENGTH: usize = 8;
+
+fn check_secrets(config: &Configuration) -> Result<()> {
+    if config.jwt_secret == SecUtf8::from("secretjwtsecret") {
+        anyhow::bail!("jwt_secret is the default one, set it to a long random string");
+    }
+    if config.jwt_secret.unsecure().len() < MIN_JWT_SECRET_LENGTH {
+        anyhow::bail!(
+            "jwt_secret is too short, use at least {} characters",
+            MIN_JWT_SECRET_LENGTH
+        );
+    }
+    if let Some(key) = config
+        .previous_jwt_secrets
+        .iter()
+        .find(|key| key.secret.unsecure().len() < MIN_JWT_SECRET_LENGTH)
+    {
+        anyhow::bail!(
+            "The previous JWT secret '{}' is too short, use at least {} characters",
+            key.key_id,
+            MIN_JWT_SECRET_LENGTH
+        );
+    }
+    if config.ldap_user_pass == SecUtf8::from("password") {
+        anyhow::bail!("ldap_user_pass is the default one, choose another admin password");
+    }
+    if config.ldap_user_pass.unsecure().len() < MIN_ADMIN_PASSWORD_LENGTH {
+        anyhow::bail!(
+            "ldap_user_pass is too short, use at least {} characters",
+            MIN_ADMIN_PASSWORD_LENGTH
+        );
+    }
+    Ok(())
+}
+
+fn check_certificate(cert_file: &str, key_file: &str) -> Result<()> {
+    tls_certificate::ReloadableCertificate::new(cert_file, key_file)
+        .map(|_| ())
+        .with_context(|| {
+            format!(
+                "Check that {} and {} exist, are readable and in the PEM format",
+                cert_file, key_file
+            )
+        })
+}
+
+fn check_tls_files(config: &Configuration) -> Result<()> {
+    // With ACME, the certificate is provisioned by the server itself.
+    if config.acme_options.enabled {
+        return Ok(());
+    }
+    if config.ldaps_options.enabled {
+        check_certificate(
+            &config.ldaps_options.cert_file,
+            &config.ldaps_options.key_file,
+        )
+        .context("ldaps_options")?;
+    }
+    if config.https_options.enabled {
+        check_certificate(
+            &config.https_options.cert_file,
+            &config.https_options.key_file,
+        )
+        .context("https_options")?;
+    }
+    Ok(())
+}
+
+async fn check_database(config: &Configuration) -> Result<()> {
+    for database_url in
+        std::iter::once(&config.database_url).chain(&config.database_read_replica_url)
+    {
+        database::connect(&config.database_options, database_url)
+            .await
+            .context("Check the URL, the credentials and that the database server is reachable")?;
+    }
+    Ok(())
+}
+
+fn check_external_auth(config: &Configuration) -> Result<()> {
+    let options = &config.external_auth;
+    match options.hook {
+        ExternalAuthType::Exec if options.command.is_none() => {
+            anyhow::bail!("external_auth.command is not set")
+        }
+        ExternalAuthType::Http if options.url.is_none() => {
+            anyhow::bail!("external_auth.url is not set")
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_dn_structure(config: &Configuration) -> Result<()> {
+    let options = &config.membership_options;
+    if !["uid", "cn"].contains(&options.user_dn_attribute.to_ascii_lowercase().as_str()) {
+        anyhow::bail!(
+            "membership_options.user_dn_attribute is \"{}\", expected \"uid\" or \"cn\"",
+            options.user_dn_attribute
+        );
+    }
+    let ous = [&options.user_ou, &options.group_ou];
+    if let Some(ou) = ous
+        .iter()
+        .find(|ou| ou.is_empty() || ou.contains(|c| c == ',' || c == '='))
+    {
+        anyhow::bail!("Invalid organizational unit \"{}\"", ou);
+    }
+    let lowercase = ous.map(|ou| ou.to_ascii_lowercase());
+    if lowercase[0] == lowercase[1] || lowercase.contains(&"services".to_owned()) {
+        anyhow::bail!(
+            "membership_options.user_ou and group_ou must be different, and not \"services\""
+        );
+    }
+    Ok(())
+}
+
+async fn check_smtp(config: &Configuration) -> Result<()> {
+    mail::check_smtp_connection(&config.smtp_options)
+        .await
+        .context("Check smtp_options: the server, port, encryption and credentials")
+}
+
+/// Runs all the checks, printing the result of each of them. Only connects to the SMTP server
+/// when `online` is set. Returns the number of failed checks.
+pub async fn check_config(config: &Configuration, online: bool) -> usize {
+    let mut config = config.clone();
+    let mut results = Vec::new();
+    if config.secrets_provider.provider != SecretsProviderType::None {
+        results.push((
+            "Secrets provider",
+            secrets_provider::apply_secrets(&mut config).await,
+        ));
+    }
+    results.push(("Secrets", check_secrets(&config)));
+    results.push(("TLS certificates", check_tls_files(&config)));
+    results.push(("Database", check_database(&config).await));
+    results.push(("LDAP DN structure", check_dn_structure(&config)));
+    if config.external_auth.hook != ExternalAuthType::None {
+        results.push(("External authentication hook", check_external_auth(&config)));
+    }
+    if !config.client_profiles.is_empty() {
+        results.push((
+            "LDAP client profiles",
+            ClientProfiles::new(&config.client_profiles).map(|_| ()),
+        ));
+    }
+    if config.graphql_options.persisted_queries_file.is_some()
+        || config.graphql_options.only_persisted_queries
+    {
+        results.push((
+            "Persisted GraphQL queries",
+            PersistedQueries::new(&config.graphql_options).map(|_| ()),
+        ));
+    }
+    if let Some(file) = &config.bootstrap_options.file {
+        results.push((
+            "Bootstrap file",
+            bootstrap::read(file)
+                .map(|_| ())
+                .context("Check bootstrap_options.file"),
+        ));
+    }
+    if config.smtp_options.dkim.enabled {
+        results.push((
+            "DKIM key",
+            mail::check_dkim_key(&config.smtp_options)
+                .context("Check smtp_options.dkim.private_key and algorithm"),
+        ));
+    }
+    if config.smtp_options.templates_dir.is_some() {
+        results.push((
+            "Email templates",
+            mail::check_templates(&config.smtp_options)
+                .context("Check the files of smtp_options.templates_dir"),
+        ));
+    }
+    if online {
+        results.push(("SMTP server", check_smtp(&config).await));
+    }
+    let mut failures = 0;
+    for (name, result) in results {
+        match result {
+            Ok(()) => println!("[OK] {}", name),
+            Err(e) => {
+                println!("[ERROR] {}: {:#}", name, e);
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::ConfigurationBuilder;
+
+    #[test]
+    fn test_check_secrets() {
+        let mut config = ConfigurationBuilder::for_tests();
+        assert!(check_secrets(&config).is_err());
+        config.jwt_secret = SecUtf8::from("a".repeat(MIN_JWT_SECRET_LENGTH));
+        assert!(check_secrets(&config).is_err());
+        config.ldap_user_pass = SecUtf8::from("short");
+        assert!(check_secrets(&config).is_err());
+        config.ldap_user_pass = SecUtf8::from("a long password");
+        assert!(check_secrets(&config).is_ok());
+    }
+
+    #[test]
+    fn test_check_tls_files() {
+        let mut config = ConfigurationBuilder::for_tests();
+        assert!(check_tls_files(&config).is_ok());
+        config.ldaps_options.enabled = true;
+        config.ldaps_options.cert_file = "/nonexistent/cert.pem".to_owned();
+        assert!(check_tls_files(&config).is_err());
+    }
+
+    #[test]
+    fn test_check_dn_structure() {
+        let mut config = ConfigurationBuilder::for_tests();
+        assert!(check_dn_structure(&config).is_ok());
+        config.membership_options.user_dn_attribute = "sAMAccountName".to_owned();
+        assert!(check_dn_structure(&config).is_err());
+        config.membership_options.user_dn_attribute = "CN".to_owned();
+        config.membership_options.user_ou = "Groups".to_owned();
+        assert!(check_dn_structure(&config).is_err());
+        config.membership_options.user_ou = "users,ou=corp".to_owned();
+        assert!(check_dn_structure(&config).is_err());
+        config.membership_options.user_ou = "users".to_owned();
+        assert!(check_dn_structure(&config).is_ok());
+    }
+}
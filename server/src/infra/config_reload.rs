@@ -0,0 +1,136 @@
+use crate::{
+    domain::handler::BackendHandler,
+    infra::{
+        auth_service::check_if_bearer_is_valid,
+        cli::RunOpts,
+        configuration::{self, MailOptions},
+        http_rate_limiter::HttpRateLimiter,
+        ldap_rate_limiter::LdapRateLimiter,
+        logging::LogLevelHandle,
+        secrets_provider,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+        tls_certificate::ReloadableCertificate,
+    },
+};
+use actix_web::{web, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use anyhow::{Context, Result};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, instrument};
+
+/// The SMTP options, replaced when the configuration is reloaded.
+pub type SharedMailOptions = Arc<RwLock<MailOptions>>;
+
+/// Applies the parts of the configuration that can change without a restart: the log level, the
+/// SMTP options, the rate limits and the content of the LDAPS and HTTPS certificate files. The other options
+/// are only read at startup.
+#[derive(Clone)]
+pub struct ConfigReloader {
+    opts: RunOpts,
+    log_level_handle: LogLevelHandle,
+    mail_options: SharedMailOptions,
+    ldap_rate_limiter: LdapRateLimiter,
+    http_rate_limiter: HttpRateLimiter,
+    certificates: Vec<Arc<ReloadableCertificate>>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        opts: RunOpts,
+        log_level_handle: LogLevelHandle,
+        mail_options: SharedMailOptions,
+        ldap_rate_limiter: LdapRateLimiter,
+        http_rate_limiter: HttpRateLimiter,
+        certificates: Vec<Arc<ReloadableCertificate>>,
+    ) -> Self {
+        Self {
+            opts,
+            log_level_handle,
+            mail_options,
+            ldap_rate_limiter,
+            http_rate_limiter,
+            certificates,
+        }
+    }
+
+    /// Reads the configuration file, the environment and the secrets provider again. Nothing
+    /// changes if the new configuration is invalid.
+    #[instrument(skip_all, level = "info", err)]
+    pub async fn reload(&self) -> Result<()> {
+        let mut config = configuration::init(self.opts.clone())
+            .context("while reading the new configuration")?;
+        secrets_provider::apply_secrets(&mut config).await?;
+        for certificate in &self.certificates {
+            certificate.reload()?;
+        }
+        self.log_level_handle.set_verbose(config.verbose)?;
+        *self.mail_options.write().unwrap() = config.smtp_options;
+        self.ldap_rate_limiter
+            .set_options(config.ldap_rate_limit_options);
+        self.http_rate_limiter
+            .set_options(config.http_rate_limit_options);
+        info!("Configuration reloaded");
+        Ok(())
+    }
+}
+
+/// Reloads the configuration every time the process receives a SIGHUP.
+pub async fn reload_on_sighup(reloader: ConfigReloader) {
+    use actix_rt::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Could not listen to SIGHUP: {:#}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        // The error is already logged.
+        let _ = reloader.reload().await;
+    }
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_reload<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> TcpResult<()>
+where
+    Backend: BackendHandler + 'static,
+{
+    let validation_result = check_if_bearer_is_valid(&data, credentials.token())
+        .await
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?;
+    if !validation_result.is_admin() {
+        return Err(TcpError::UnauthorizedError(
+            "Only the admins can reload the configuration".to_owned(),
+        ));
+    }
+    data.config_reloader
+        .reload()
+        .await
+        .map_err(|e| TcpError::BadRequest(format!("{:#}", e)))
+}
+
+async fn post_reload_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    post_reload(data, credentials)
+        .await
+        .map(|()| HttpResponse::NoContent().finish())
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + 'static,
+{
+    cfg.service(
+        web::resource("/reload_configuration")
+            .route(web::post().to(post_reload_handler::<Backend>)),
+    );
+}
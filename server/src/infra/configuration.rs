@@ -1,6 +1,13 @@
 use crate::{
     domain::types::UserId,
-    infra::cli::{GeneralConfigOpts, LdapsOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts},
+    infra::{
+        cli::{
+            BackupOpts, BackupSqliteOpts, CheckConfigOpts, ExportLdifOpts, GeneralConfigOpts,
+            HealthCheckOpts, ImportOpts, LdapsOpts, MigrateFromLdapOpts, MigrateOpts,
+            ResetAdminPasswordOpts, RestoreOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts,
+        },
+        database,
+    },
 };
 use anyhow::{Context, Result};
 use figment::{
@@ -11,6 +18,7 @@ use lettre::message::Mailbox;
 use lldap_auth::opaque::{server::ServerSetup, KeyPair};
 use secstr::SecUtf8;
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
 #[builder(pattern = "owned")]
@@ -34,48 +42,1235 @@ pub struct MailOptions {
     /// Deprecated.
     #[builder(default = "None")]
     pub tls_required: Option<bool>,
+    /// Directory of the email templates replacing the built-in ones, see `infra::mail`.
+    #[builder(default = "None")]
+    pub templates_dir: Option<String>,
+    #[builder(default)]
+    pub oauth2: SmtpOAuth2Options,
+    /// Domain of the Message-ID header, the domain of the sender by default.
+    #[builder(default = "None")]
+    pub message_id_domain: Option<String>,
+    #[builder(default)]
+    pub dkim: DkimOptions,
+}
+
+impl std::default::Default for MailOptions {
+    fn default() -> Self {
+        MailOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Authentication to the SMTP server with an OAuth2 access token (XOAUTH2) instead of the
+/// password, see `infra::smtp_oauth2`.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct SmtpOAuth2Options {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The token endpoint of the provider.
+    #[builder(default = "String::new()")]
+    pub token_url: String,
+    #[builder(default = "String::new()")]
+    pub client_id: String,
+    #[builder(default = r#"SecUtf8::from("")"#)]
+    pub client_secret: SecUtf8,
+    /// Uses the refresh token grant when set, the client credentials grant otherwise.
+    #[builder(default = r#"SecUtf8::from("")"#)]
+    pub refresh_token: SecUtf8,
+    #[builder(default = "String::new()")]
+    pub scope: String,
+}
+
+impl std::default::Default for SmtpOAuth2Options {
+    fn default() -> Self {
+        SmtpOAuth2OptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DkimAlgorithm {
+    #[default]
+    #[serde(rename = "rsa")]
+    Rsa,
+    #[serde(rename = "ed25519")]
+    Ed25519,
+}
+
+/// DKIM signature of the emails, for the receiving servers to check that they come from the
+/// domain of the sender.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct DkimOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    #[builder(default = r#"String::from("lldap")"#)]
+    pub selector: String,
+    /// The signing domain, the domain of the sender by default.
+    #[builder(default = "None")]
+    pub domain: Option<String>,
+    /// The private key, in PEM format.
+    #[builder(default = r#"SecUtf8::from("")"#)]
+    pub private_key: SecUtf8,
+    #[builder(default)]
+    pub algorithm: DkimAlgorithm,
+}
+
+impl std::default::Default for DkimOptions {
+    fn default() -> Self {
+        DkimOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The addresses a server listens on, e.g. `["0.0.0.0", "::"]`. A single string is accepted as
+/// well, with the addresses separated by commas, e.g. in the environment variables.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct ListenAddresses(Vec<String>);
+
+impl<'de> Deserialize<'de> for ListenAddresses {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Addresses {
+            One(String),
+            Many(Vec<String>),
+        }
+        Ok(match Addresses::deserialize(deserializer)? {
+            Addresses::One(addresses) => ListenAddresses::from(addresses.as_str()),
+            Addresses::Many(addresses) => ListenAddresses(addresses),
+        })
+    }
+}
+
+impl From<&str> for ListenAddresses {
+    fn from(addresses: &str) -> Self {
+        ListenAddresses(
+            addresses
+                .split(',')
+                // Also accepts the TOML syntax of a list, and the IPv6 addresses in brackets.
+                .map(|address| {
+                    address.trim_matches(|c: char| c.is_whitespace() || "[]\"'".contains(c))
+                })
+                .filter(|address| !address.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+}
+
+impl ListenAddresses {
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// The address used to reach the server from the same machine.
+    pub fn first(&self) -> &str {
+        self.0.first().map(String::as_str).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapsOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Addresses to listen on, `ldap_host` by default.
+    #[builder(default)]
+    pub host: Option<ListenAddresses>,
+    #[builder(default = "6360")]
+    pub port: u16,
+    #[builder(default = r#"String::from("cert.pem")"#)]
+    pub cert_file: String,
+    #[builder(default = r#"String::from("key.pem")"#)]
+    pub key_file: String,
+}
+
+impl std::default::Default for LdapsOptions {
+    fn default() -> Self {
+        LdapsOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Lowest TLS version accepted by a server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Serves the web UI and the API over HTTPS as well, on a separate port.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct HttpsOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Addresses to listen on, `http_host` by default.
+    #[builder(default)]
+    pub host: Option<ListenAddresses>,
+    #[builder(default = "17171")]
+    pub port: u16,
+    #[builder(default = r#"String::from("cert.pem")"#)]
+    pub cert_file: String,
+    #[builder(default = r#"String::from("key.pem")"#)]
+    pub key_file: String,
+    #[builder(default)]
+    pub min_tls_version: TlsVersion,
+    /// Names of the allowed cipher suites, e.g. "TLS13_AES_256_GCM_SHA384". All the secure ones
+    /// are allowed when empty.
+    #[builder(default)]
+    pub cipher_suites: Vec<String>,
+}
+
+impl std::default::Default for HttpsOptions {
+    fn default() -> Self {
+        HttpsOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Pass-through authentication to an upstream LDAP server (e.g. Active Directory), to migrate the
+/// users progressively: when their local password doesn't match, the bind is checked upstream,
+/// and on success the password is saved locally and the missing accounts are created.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct UpstreamLdapOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// URL of the upstream server, "ldap://" or "ldaps://".
+    #[builder(default = r#"String::from("ldap://localhost:389")"#)]
+    pub url: String,
+    /// DN the users bind with upstream, where "{user_id}" is replaced by the user ID.
+    #[builder(default = r#"String::from("uid={user_id},ou=people,dc=example,dc=com")"#)]
+    pub bind_dn_format: String,
+    /// Where to look for the user entry after the bind, to provision the local account.
+    #[builder(default = r#"String::from("dc=example,dc=com")"#)]
+    pub base_dn: String,
+    /// Filter matching the user entry, where "{user_id}" is replaced by the user ID.
+    #[builder(default = r#"String::from("(uid={user_id})")"#)]
+    pub user_filter: String,
+    /// The members of this group always authenticate upstream, and their password is not saved
+    /// locally.
+    #[builder(default = r#"String::from("lldap_upstream_auth")"#)]
+    pub group: String,
+    /// Creates the local account of the users that only exist upstream.
+    #[builder(default = "true")]
+    pub create_users: bool,
+    #[builder(default = "5")]
+    pub timeout_seconds: u64,
+}
+
+impl std::default::Default for UpstreamLdapOptions {
+    fn default() -> Self {
+        UpstreamLdapOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// How the external authentication hook is called.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExternalAuthType {
+    #[default]
+    None,
+    /// A command reading the credentials on its standard input, exiting with 0 to allow the bind.
+    Exec,
+    /// An HTTP endpoint receiving the credentials in a POST request, answering with a 2xx status
+    /// to allow the bind.
+    Http,
+}
+
+/// External authentication hook, to check the passwords with a backend that lldap doesn't
+/// support, e.g. RADIUS or an HR system. The members of `group` authenticate through the hook
+/// instead of their local password, which they don't need to have.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ExternalAuthOptions {
+    #[builder(default)]
+    pub hook: ExternalAuthType,
+    /// Executable run without arguments for each bind.
+    #[builder(default)]
+    pub command: Option<String>,
+    /// URL of the endpoint, which should be "https://" unless it is local.
+    #[builder(default)]
+    pub url: Option<String>,
+    #[builder(default = r#"String::from("lldap_external_auth")"#)]
+    pub group: String,
+    #[builder(default = "5")]
+    pub timeout_seconds: u64,
+}
+
+impl std::default::Default for ExternalAuthOptions {
+    fn default() -> Self {
+        ExternalAuthOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Standby mode: the instance serves the LDAP searches from a copy of the database kept up to
+/// date by replication (e.g. a PostgreSQL hot standby), and forwards the binds and the writes to
+/// the LDAP server of the primary instance. The database is never written to, and the HTTP server
+/// is not started.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ReplicaOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// URL of the LDAP server of the primary, "ldap://" or "ldaps://".
+    #[builder(default = r#"String::from("ldap://localhost:3890")"#)]
+    pub primary_ldap_url: String,
+    #[builder(default = "5")]
+    pub timeout_seconds: u64,
+}
+
+impl std::default::Default for ReplicaOptions {
+    fn default() -> Self {
+        ReplicaOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Declarative management of the directory: the users, groups and memberships listed in `file`
+/// (TOML, or YAML with a ".yaml" or ".yml" extension) are created on every start.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct BootstrapOptions {
+    #[builder(default)]
+    pub file: Option<String>,
+    /// Also delete the users and groups missing from the file, and the memberships of the listed
+    /// groups missing from it. The admin user and the built-in groups are kept.
+    #[builder(default = "false")]
+    pub prune: bool,
+}
+
+impl std::default::Default for BootstrapOptions {
+    fn default() -> Self {
+        BootstrapOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Options of the OpenID Connect provider. The issuer is `http_url`, and the clients are
+/// registered by the admins through the API.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct OidcOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Lifetime of the ID and access tokens.
+    #[builder(default = "3600")]
+    pub token_lifetime_seconds: u64,
+}
+
+impl std::default::Default for OidcOptions {
+    fn default() -> Self {
+        OidcOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The pool of connections to the database.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct DatabaseOptions {
+    #[builder(default = "5")]
+    pub max_connections: u32,
+    /// Connections kept open even when idle.
+    #[builder(default = "0")]
+    pub min_connections: u32,
+    /// How long a query waits for a free connection before failing.
+    #[builder(default = "30")]
+    pub acquire_timeout_seconds: u64,
+    /// Idle connections above `min_connections` are closed after this delay.
+    #[builder(default = "600")]
+    pub idle_timeout_seconds: u64,
+    /// Level at which the SQL statements are logged: "off", "error", "warn", "info", "debug" or
+    /// "trace".
+    #[builder(default = r#"String::from("debug")"#)]
+    pub statement_log_level: String,
+    /// With SQLite, whether to use the write-ahead log, which lets the reads proceed during a
+    /// write.
+    #[builder(default = "true")]
+    pub sqlite_wal: bool,
+    /// With SQLite, how long a query waits for the database to be unlocked by another connection
+    /// before failing with "database is locked".
+    #[builder(default = "5")]
+    pub sqlite_busy_timeout_seconds: u64,
+    /// The statements taking longer than this many milliseconds are logged as warnings, with
+    /// their SQL and duration. 0 disables these logs.
+    #[builder(default = "1000")]
+    pub slow_statement_threshold_ms: u64,
+}
+
+impl std::default::Default for DatabaseOptions {
+    fn default() -> Self {
+        DatabaseOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// In-memory cache of the user details, user groups and group lists, for the clients repeating
+/// the same lookups. The changes made through LLDAP clear it, the ones made directly in the
+/// database are only visible once the entries expire.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct CacheOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// How long an entry is served from the cache.
+    #[builder(default = "10")]
+    pub ttl_seconds: u64,
+    /// Maximum number of entries of each kind.
+    #[builder(default = "10000")]
+    pub max_entries: usize,
+}
+
+impl std::default::Default for CacheOptions {
+    fn default() -> Self {
+        CacheOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Tuning of the LDAP and HTTP servers.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct RuntimeOptions {
+    /// Number of threads handling the connections, or 0 for one per CPU core.
+    #[builder(default = "1")]
+    pub workers: usize,
+    /// Maximum number of connections waiting to be accepted, per listening socket.
+    #[builder(default = "2048")]
+    pub backlog: u32,
+    /// Maximum number of connections handled at the same time by each worker. The next ones wait
+    /// in the backlog.
+    #[builder(default = "25000")]
+    pub max_connections_per_worker: usize,
+}
+
+impl std::default::Default for RuntimeOptions {
+    fn default() -> Self {
+        RuntimeOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Limits on the duration of the requests, so that the ones with pathological filters don't tie
+/// up the server. 0 disables a limit.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct TimeoutOptions {
+    /// Time spent producing the results of an LDAP search. The persistent searches of the content
+    /// synchronization are not limited.
+    #[builder(default = "30")]
+    pub ldap_search_seconds: u64,
+    #[builder(default = "10")]
+    pub ldap_bind_seconds: u64,
+    /// Time to execute a GraphQL query or mutation.
+    #[builder(default = "30")]
+    pub graphql_seconds: u64,
+}
+
+impl std::default::Default for TimeoutOptions {
+    fn default() -> Self {
+        TimeoutOptionsBuilder::default().build().unwrap()
+    }
+}
+
+fn to_timeout(seconds: u64) -> Option<Duration> {
+    match seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    }
+}
+
+impl TimeoutOptions {
+    pub fn ldap_search_timeout(&self) -> Option<Duration> {
+        to_timeout(self.ldap_search_seconds)
+    }
+
+    pub fn ldap_bind_timeout(&self) -> Option<Duration> {
+        to_timeout(self.ldap_bind_seconds)
+    }
+
+    pub fn graphql_timeout(&self) -> Option<Duration> {
+        to_timeout(self.graphql_seconds)
+    }
+}
+
+/// Limits on the shape of the GraphQL queries, checked before executing them. The nested lists,
+/// e.g. the users of the groups of the users, can each turn into one SQL query per item. 0
+/// disables a limit.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct GraphqlOptions {
+    /// Maximum nesting of the fields. The introspection query of the GraphQL clients is 13
+    /// levels deep.
+    #[builder(default = "15")]
+    pub max_depth: usize,
+    /// Maximum number of fields selected by a query, counting each fragment every time it is
+    /// used.
+    #[builder(default = "1000")]
+    pub max_complexity: usize,
+    /// Answers the introspection queries (`__schema` and `__type`) of the GraphQL clients and of
+    /// the playground.
+    #[builder(default = "true")]
+    pub introspection: bool,
+    /// A JSON object mapping the hex SHA-256 of the persisted queries to their documents. The
+    /// clients can send the hash alone, in `extensions.persistedQuery.sha256Hash`.
+    #[builder(default)]
+    pub persisted_queries_file: Option<String>,
+    /// Rejects the queries that are not in `persisted_queries_file`.
+    #[builder(default = "false")]
+    pub only_persisted_queries: bool,
+}
+
+impl std::default::Default for GraphqlOptions {
+    fn default() -> Self {
+        GraphqlOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Serves the web UI and the API on a Unix socket as well, for the reverse proxies running on the
+/// same machine.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct UnixSocketOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    #[builder(default = r#"String::from("lldap.sock")"#)]
+    pub path: String,
+    /// Permissions of the socket file, in octal.
+    #[builder(default = r#"String::from("660")"#)]
+    pub permissions: String,
+}
+
+impl std::default::Default for UnixSocketOptions {
+    fn default() -> Self {
+        UnixSocketOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl UnixSocketOptions {
+    pub fn permissions_mode(&self) -> Result<u32> {
+        u32::from_str_radix(&self.permissions, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o777)
+            .ok_or_else(|| anyhow::anyhow!("Invalid socket permissions: '{}'", self.permissions))
+    }
+}
+
+/// Cross-origin requests to the API, for the web applications hosted on other domains. The origin
+/// of `http_url` is always allowed.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct CorsOptions {
+    /// Origins allowed to call the API, e.g. "https://app.example.com", or "*" for all of them.
+    /// CORS is disabled when empty.
+    #[builder(default)]
+    pub allowed_origins: Vec<String>,
+    /// Request headers the applications can set.
+    #[builder(default = r#"vec!["authorization".to_owned(), "content-type".to_owned()]"#)]
+    pub allowed_headers: Vec<String>,
+    /// Whether the browsers send the cookies along with the cross-origin requests.
+    #[builder(default = "false")]
+    pub allow_credentials: bool,
+    /// How long the browsers can cache the preflight responses.
+    #[builder(default = "3600")]
+    pub max_age_seconds: usize,
+}
+
+impl std::default::Default for CorsOptions {
+    fn default() -> Self {
+        CorsOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// How the ACME server checks that we control the domain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AcmeChallengeType {
+    /// A file served by the HTTP server, which has to be reachable on port 80 of the domain.
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    /// A TXT record, set through `dns_hook_command`.
+    #[serde(rename = "dns-01")]
+    Dns01,
+}
+
+/// Automatic provisioning of the LDAPS certificate from an ACME server, e.g. Let's Encrypt. The
+/// certificate and key are written to the files of the `ldaps_options`, and the HTTPS certificate
+/// is reloaded with them.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AcmeOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Domain name of the certificate.
+    #[builder(default)]
+    pub domain: String,
+    /// Contact of the account, for the expiration notices.
+    #[builder(default)]
+    pub email: String,
+    #[builder(default = r#"String::from("https://acme-v02.api.letsencrypt.org/directory")"#)]
+    pub directory_url: String,
+    #[builder(default)]
+    pub challenge: AcmeChallengeType,
+    /// Command setting up the DNS-01 challenges, called with "set" or "clear", the name and the
+    /// value of the TXT record.
+    #[builder(default)]
+    pub dns_hook_command: Option<String>,
+    /// Where the credentials of the ACME account are kept.
+    #[builder(default = r#"String::from("acme_account.json")"#)]
+    pub account_file: String,
+    /// The certificate is renewed when it expires in less than this many days.
+    #[builder(default = "30")]
+    pub renew_before_days: u32,
+}
+
+impl std::default::Default for AcmeOptions {
+    fn default() -> Self {
+        AcmeOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// How the log lines are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable, with the nested spans as a tree.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the event and of its spans.
+    Json,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LoggingOptions {
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+/// Export of the tracing spans to an OpenTelemetry collector, over OTLP/HTTP.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct OpenTelemetryOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// URL of the OTLP/HTTP traces endpoint of the collector.
+    #[builder(default = r#"String::from("http://localhost:4318/v1/traces")"#)]
+    pub endpoint: String,
+    /// Extra headers sent with the exports, typically for authentication.
+    #[builder(default)]
+    pub headers: HashMap<String, String>,
+    /// Fraction of the traces to export, between 0 and 1.
+    #[builder(default = "1.0")]
+    pub sampling_ratio: f64,
+}
+
+impl std::default::Default for OpenTelemetryOptions {
+    fn default() -> Self {
+        OpenTelemetryOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Debugging of the LDAP clients: the decoded operations are logged, without the passwords.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapWireLogOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Also write the operations to this file, in the pcap format.
+    #[builder(default)]
+    pub capture_file: Option<String>,
+}
+
+impl std::default::Default for LdapWireLogOptions {
+    fn default() -> Self {
+        LdapWireLogOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Limits on the LDAP clients. A value of 0 means no limit.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapRateLimitOptions {
+    /// Maximum number of concurrent connections to the LDAP and LDAPS servers.
+    #[builder(default = "0")]
+    pub max_connections: usize,
+    /// Maximum number of concurrent connections from the same IP.
+    #[builder(default = "0")]
+    pub max_connections_per_ip: usize,
+    /// Maximum number of bind attempts from the same IP in any 60 seconds.
+    #[builder(default = "0")]
+    pub max_bind_attempts_per_minute: usize,
+}
+
+impl std::default::Default for LdapRateLimitOptions {
+    fn default() -> Self {
+        LdapRateLimitOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Limits on the requests to the HTTP authentication endpoints (`/auth/*`). A value of 0 means no
+/// limit.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct HttpRateLimitOptions {
+    /// Maximum number of requests from the same IP in any 60 seconds.
+    #[builder(default = "0")]
+    pub max_requests_per_ip_per_minute: usize,
+    /// Maximum number of requests for the same username in any 60 seconds.
+    #[builder(default = "0")]
+    pub max_requests_per_user_per_minute: usize,
+    /// Delay imposed on the IP and the username after a failed login, doubled after each
+    /// consecutive failure and reset by a successful login.
+    #[builder(default = "0")]
+    pub failure_backoff_seconds: u64,
+    /// Upper bound of the delay after failed logins.
+    #[builder(default = "300")]
+    pub max_failure_backoff_seconds: u64,
+    /// Use the X-Forwarded-For/Forwarded headers to find the client IP, whoever sends them. Only
+    /// enable this behind a reverse proxy that sets them, otherwise the clients can pick their own
+    /// IP. Prefer `trusted_proxies`, which applies to all of the HTTP server.
+    #[builder(default = "false")]
+    pub trust_forwarded_headers: bool,
+}
+
+impl std::default::Default for HttpRateLimitOptions {
+    fn default() -> Self {
+        HttpRateLimitOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// How the values of the membership attributes are formatted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MembershipValueFormat {
+    /// Full DN of the member/group, e.g. "uid=bob,ou=people,dc=example,dc=com".
+    #[default]
+    Dn,
+    /// Bare ID of the member/group, e.g. "bob".
+    Id,
+}
+
+/// What the users that cannot read the whole directory (i.e. neither admins, read-only users nor
+/// members of a role granting it) can see, through LDAP and GraphQL.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegularUserVisibility {
+    /// Their own entry, and the groups they belong to.
+    #[default]
+    OwnEntries,
+    /// Nothing: their LDAP searches return no entries, and their groups are hidden. They can still
+    /// bind to check their password, and see their own profile in the web UI.
+    Nothing,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct MembershipOptions {
+    /// Attribute of the user entries listing the groups the user belongs to.
+    #[builder(default = r#"String::from("memberOf")"#)]
+    pub member_of_attribute: String,
+    /// Attributes of the group entries listing the members of the group.
+    #[builder(default = r#"vec![String::from("member"), String::from("uniqueMember")]"#)]
+    pub member_attributes: Vec<String>,
+    /// Naming attribute of the group DNs, used both for the group entries and for the values of
+    /// `member_of_attribute`. With "entryUUID", the groups are named by their UUID instead of their
+    /// name, so that renaming a group doesn't change its DN.
+    #[builder(default = r#"String::from("cn")"#)]
+    pub group_dn_attribute: String,
+    #[builder(default)]
+    pub value_format: MembershipValueFormat,
+    /// Naming attribute of the user DNs, "uid" or "cn", e.g. "cn=bob,ou=people,dc=example,dc=com"
+    /// for the clients that expect Active Directory DNs. Its value is the user ID either way, and
+    /// the binds accept both.
+    #[builder(default = r#"String::from("uid")"#)]
+    pub user_dn_attribute: String,
+    /// Organizational unit of the user DNs, e.g. "users" for "uid=bob,ou=users,dc=example,dc=com".
+    #[builder(default = r#"String::from("people")"#)]
+    pub user_ou: String,
+    /// Organizational unit of the group DNs.
+    #[builder(default = r#"String::from("groups")"#)]
+    pub group_ou: String,
+}
+
+impl std::default::Default for MembershipOptions {
+    fn default() -> Self {
+        MembershipOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Active Directory compatibility, for the software that only supports an "Active Directory" LDAP
+/// mode: the users and groups get the `sAMAccountName`, `userPrincipalName` and `objectCategory`
+/// attributes and the "user" and "group" object classes, `memberOf` lists the group DNs, and the
+/// binds accept "DOMAIN\user" and "user@domain" besides the DNs.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ActiveDirectoryOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Domain of the `userPrincipalName`, e.g. "example.com" for "bob@example.com". Defaults to the
+    /// "dc" components of the base DN.
+    #[builder(default)]
+    pub domain: Option<String>,
+    /// NetBIOS name of the domain accepted in the "DOMAIN\user" binds. Any domain is accepted when
+    /// not set.
+    #[builder(default)]
+    pub netbios_domain: Option<String>,
+}
+
+impl std::default::Default for ActiveDirectoryOptions {
+    fn default() -> Self {
+        ActiveDirectoryOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Processing of the avatars uploaded through GraphQL, and fetching of the remote avatars, see
+/// `infra::avatar`.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AvatarOptions {
+    /// The larger avatars are downscaled to fit in a square of this size, in pixels.
+    #[builder(default = "512")]
+    pub max_dimension: u32,
+    /// Size limit of the uploaded image, before any processing, in bytes.
+    #[builder(default = "5 * 1024 * 1024")]
+    pub max_upload_bytes: usize,
+    /// If set, the users without an avatar get the one at this URL, where `{email_sha256}` is
+    /// replaced by the hex SHA-256 of their lowercase email, e.g.
+    /// "https://www.gravatar.com/avatar/{email_sha256}?s=512&d=404". Fetched by the
+    /// "remote_avatars" maintenance job.
+    #[builder(default)]
+    pub remote_url_template: Option<String>,
+    /// The remote avatars are fetched again after this many days.
+    #[builder(default = "7")]
+    pub remote_refresh_days: u64,
+}
+
+impl std::default::Default for AvatarOptions {
+    fn default() -> Self {
+        AvatarOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl MembershipOptions {
+    pub fn is_member_of_attribute(&self, attribute: &str) -> bool {
+        self.member_of_attribute.eq_ignore_ascii_case(attribute)
+    }
+
+    pub fn is_member_attribute(&self, attribute: &str) -> bool {
+        self.member_attributes
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(attribute))
+    }
+
+    pub fn uses_group_uuids(&self) -> bool {
+        self.group_dn_attribute.eq_ignore_ascii_case("entryuuid")
+    }
+}
+
+/// Rules for the IDs of the new users, applied to all the ways of creating or renaming a user.
+/// The existing users are not affected.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct UsernamePolicyOptions {
+    /// Minimum number of characters.
+    #[builder(default = "1")]
+    pub min_length: usize,
+    /// Maximum number of characters.
+    #[builder(default = "255")]
+    pub max_length: usize,
+    /// The characters allowed besides the ASCII letters and digits, e.g. "._-". Any character is
+    /// allowed when not set.
+    #[builder(default)]
+    pub allowed_characters: Option<String>,
+    /// Converts the user IDs to lowercase. When disabled, the user IDs with uppercase letters are
+    /// refused instead: the user IDs are always stored in lowercase. Either way, the binds and
+    /// searches match the user IDs regardless of their case.
+    #[builder(default = "true")]
+    pub lowercase: bool,
+}
+
+impl std::default::Default for UsernamePolicyOptions {
+    fn default() -> Self {
+        UsernamePolicyOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Requirements for the new passwords. They can only be enforced when the server sees the password
+/// in clear, i.e. when it is set by an admin through GraphQL or through the LDAP password modify
+/// operation: with OPAQUE, the web UI only sends a blinded version of it.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct PasswordPolicyOptions {
+    /// Minimum number of characters.
+    #[builder(default = "8")]
+    pub min_length: usize,
+    #[builder(default)]
+    pub require_lowercase: bool,
+    #[builder(default)]
+    pub require_uppercase: bool,
+    #[builder(default)]
+    pub require_digit: bool,
+    /// Requires a character that is neither a letter nor a digit.
+    #[builder(default)]
+    pub require_special: bool,
+    /// Passwords that are refused, ignoring case.
+    #[builder(default)]
+    pub banned_passwords: Vec<String>,
+    /// Number of previous passwords of the user that cannot be reused, or 0 to allow any.
+    #[builder(default)]
+    pub history_depth: usize,
+    /// Number of days after which a password expires and must be changed at the next login to the
+    /// web UI, or 0 for the passwords to never expire.
+    #[builder(default)]
+    pub max_age_days: u64,
+    /// The users are warned by email this many days before their password expires, by the
+    /// "password_expiry_warnings" maintenance job. 0 disables the warnings.
+    #[builder(default = "7")]
+    pub expiry_warning_days: u64,
+    /// Minimum strength of the passwords, as estimated by zxcvbn from 0 (too guessable) to 4 (very
+    /// unguessable). The user ID, email and names of the user are taken into account.
+    #[builder(default)]
+    pub min_strength_score: u8,
+    /// Refuses the passwords found in the HaveIBeenPwned corpus of breached passwords. Only the
+    /// first 5 characters of the SHA-1 of the password are sent to `pwned_passwords_url`.
+    #[builder(default)]
+    pub check_pwned_passwords: bool,
+    /// The range API endpoint, to which the hash prefix is appended. It can point to a local
+    /// mirror for the deployments without internet access.
+    #[builder(default = r#""https://api.pwnedpasswords.com/range/".to_string()"#)]
+    pub pwned_passwords_url: String,
+}
+
+impl std::default::Default for PasswordPolicyOptions {
+    fn default() -> Self {
+        PasswordPolicyOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl PasswordPolicyOptions {
+    /// When a password set at the given date expires, if the passwords have a maximum age.
+    pub fn password_expiry_date(
+        &self,
+        password_modified_date: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        (self.max_age_days > 0)
+            .then(|| password_modified_date + chrono::Duration::days(self.max_age_days as i64))
+    }
+}
+
+/// Temporary lockout of the accounts after repeated failed logins, over LDAP or the web UI.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AccountLockoutOptions {
+    /// Number of consecutive failed logins after which the account is locked, or 0 to never lock
+    /// the accounts.
+    #[builder(default = "0")]
+    pub max_failed_attempts: u32,
+    /// How long the account stays locked, in seconds. The failed logins older than that are
+    /// forgotten.
+    #[builder(default = "300")]
+    pub lockout_duration_seconds: u64,
+}
+
+impl std::default::Default for AccountLockoutOptions {
+    fn default() -> Self {
+        AccountLockoutOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Emails to the users about the security-relevant changes to their account, sent with the
+/// `smtp_options`.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct SecurityNotificationOptions {
+    /// When their password is changed, except the first one.
+    #[builder(default = "false")]
+    pub password_changed: bool,
+    /// When one of their WebAuthn credentials is removed.
+    #[builder(default = "false")]
+    pub webauthn_credential_removed: bool,
+    /// When they are added to or removed from one of the `admin_groups`.
+    #[builder(default = "false")]
+    pub admin_group_changed: bool,
+    #[builder(default = r#"vec!["lldap_admin".to_owned(), "lldap_password_manager".to_owned()]"#)]
+    pub admin_groups: Vec<String>,
 }
 
-impl std::default::Default for MailOptions {
+impl std::default::Default for SecurityNotificationOptions {
     fn default() -> Self {
-        MailOptionsBuilder::default().build().unwrap()
+        SecurityNotificationOptionsBuilder::default()
+            .build()
+            .unwrap()
+    }
+}
+
+impl SecurityNotificationOptions {
+    pub fn any_enabled(&self) -> bool {
+        self.password_changed || self.webauthn_credential_removed || self.admin_group_changed
     }
 }
 
+/// Lifecycle of the accounts nobody logged in to for a while, through LDAP or the web UI, applied
+/// by the "stale_accounts" maintenance job. The accounts never used count from their creation.
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
 #[builder(pattern = "owned")]
-pub struct LdapsOptions {
-    #[builder(default = "false")]
-    pub enabled: bool,
-    #[builder(default = "6360")]
-    pub port: u16,
-    #[builder(default = r#"String::from("cert.pem")"#)]
-    pub cert_file: String,
-    #[builder(default = r#"String::from("key.pem")"#)]
-    pub key_file: String,
+pub struct StaleAccountOptions {
+    /// Number of days of inactivity after which the accounts are disabled, or 0 to leave them
+    /// enabled.
+    #[builder(default = "0")]
+    pub disable_after_days: u64,
+    /// The users are warned by email this many days before their account is disabled, and the
+    /// accounts are only disabled that long after the warning. 0 disables the warnings.
+    #[builder(default = "7")]
+    pub warning_days: u64,
+    /// Number of days of inactivity after which the accounts disabled by this policy are deleted,
+    /// or 0 to keep them. They go to the recycle bin like the other deleted users.
+    #[builder(default = "0")]
+    pub delete_after_days: u64,
+    /// The members of these groups, directly or through a subgroup, are left alone.
+    #[builder(default = r#"vec!["lldap_admin".to_owned()]"#)]
+    pub exempt_groups: Vec<String>,
 }
 
-impl std::default::Default for LdapsOptions {
+impl std::default::Default for StaleAccountOptions {
     fn default() -> Self {
-        LdapsOptionsBuilder::default().build().unwrap()
+        StaleAccountOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// An HTTP endpoint notified of the changes to the users and groups.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookOptions {
+    pub url: String,
+    /// Key used to sign the payloads with HMAC-SHA256, so the receiver can authenticate them.
+    pub secret: SecUtf8,
+    /// Number of retries of a failed delivery before giving up on the event.
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_webhook_max_retries() -> u32 {
+    5
+}
+
+/// Restricts what an application reads through LDAP. It applies to the connections bound as one
+/// of `bind_dns`, or otherwise coming from one of `client_ips`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClientProfileOptions {
+    pub name: String,
+    #[serde(default)]
+    pub bind_dns: Vec<String>,
+    /// IPs or networks, e.g. "10.0.0.0/8".
+    #[serde(default)]
+    pub client_ips: Vec<String>,
+    /// The attributes returned by the searches, all of them if empty.
+    #[serde(default)]
+    pub attributes: Vec<String>,
+    /// The DNs of the subtrees whose entries are returned, e.g. "ou=people,dc=example,dc=com".
+    /// The whole tree if empty.
+    #[serde(default)]
+    pub subtrees: Vec<String>,
+}
+
+/// Defaults for the new users, e.g. the standard employees, chosen by name when creating or
+/// inviting a user. The values given explicitly for the user take precedence.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UserTemplateOptions {
+    pub name: String,
+    /// Display names of the groups the users join.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub gid_number: Option<i32>,
+    /// "{user_id}" is replaced by the ID of the user, e.g. "/home/{user_id}".
+    #[serde(default)]
+    pub home_directory: Option<String>,
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// A former JWT secret, still accepted to verify the tokens signed with it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JwtSecretOptions {
+    pub key_id: String,
+    pub secret: SecUtf8,
+}
+
+/// A task of the periodic database cleanup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceJob {
+    /// Deletes the expired JWTs and refresh tokens.
+    ExpiredTokens,
+    ExpiredPasswordResetTokens,
+    ExpiredInvitations,
+    /// Disables the accounts past their `valid_until` date.
+    ExpiredAccounts,
+    /// Removes the temporary group memberships past their expiry date.
+    ExpiredMemberships,
+    /// Purges the users and groups deleted longer than the retention period ago.
+    DeletedEntries,
+    /// Fetches the remote avatars of the users without one, if a URL template is configured.
+    RemoteAvatars,
+    /// Emails the users whose password expires soon, if the passwords have a maximum age.
+    PasswordExpiryWarnings,
+    /// Warns, disables then deletes the inactive accounts, if configured in `stale_accounts`.
+    StaleAccounts,
+}
+
+impl MaintenanceJob {
+    pub const ALL: [MaintenanceJob; 9] = [
+        MaintenanceJob::ExpiredTokens,
+        MaintenanceJob::ExpiredPasswordResetTokens,
+        MaintenanceJob::ExpiredInvitations,
+        MaintenanceJob::ExpiredAccounts,
+        MaintenanceJob::ExpiredMemberships,
+        MaintenanceJob::DeletedEntries,
+        MaintenanceJob::RemoteAvatars,
+        MaintenanceJob::PasswordExpiryWarnings,
+        MaintenanceJob::StaleAccounts,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceJob::ExpiredTokens => "expired_tokens",
+            MaintenanceJob::ExpiredPasswordResetTokens => "expired_password_reset_tokens",
+            MaintenanceJob::ExpiredInvitations => "expired_invitations",
+            MaintenanceJob::ExpiredAccounts => "expired_accounts",
+            MaintenanceJob::ExpiredMemberships => "expired_memberships",
+            MaintenanceJob::DeletedEntries => "deleted_entries",
+            MaintenanceJob::RemoteAvatars => "remote_avatars",
+            MaintenanceJob::PasswordExpiryWarnings => "password_expiry_warnings",
+            MaintenanceJob::StaleAccounts => "stale_accounts",
+        }
+    }
+}
+
+/// The periodic cleanup of the database.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct MaintenanceOptions {
+    /// Cron expression, with the seconds. Every hour by default.
+    #[builder(default = r#"String::from("0 0 * * * * *")"#)]
+    pub schedule: String,
+    /// Each run is delayed by a random duration of up to this many seconds, so that the instances
+    /// sharing a database don't all clean it up at the same time.
+    #[builder(default = "0")]
+    pub jitter_seconds: u64,
+    #[builder(default = "MaintenanceJob::ALL.to_vec()")]
+    pub jobs: Vec<MaintenanceJob>,
+    /// How long the deleted users and groups stay in the recycle bin, where they can be restored.
+    #[builder(default = "30")]
+    pub deleted_entries_retention_days: u64,
+}
+
+impl std::default::Default for MaintenanceOptions {
+    fn default() -> Self {
+        MaintenanceOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Where the secrets are fetched from, when they are not in the configuration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretsProviderType {
+    #[default]
+    None,
+    /// A KV secret of HashiCorp Vault.
+    Vault,
+    /// A command printing the secrets as a JSON object.
+    Exec,
+}
+
+/// An external store of the secrets: `jwt_secret`, `ldap_user_pass`, `smtp_user` and
+/// `smtp_password`. The ones it returns override the configuration.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct SecretsProviderOptions {
+    #[builder(default)]
+    pub provider: SecretsProviderType,
+    #[builder(default = r#"String::from("http://localhost:8200")"#)]
+    pub vault_url: String,
+    #[builder(default = r#"SecUtf8::from("")"#)]
+    pub vault_token: SecUtf8,
+    /// Path of the secret, including the "data" segment of the KV version 2 engine.
+    #[builder(default = r#"String::from("secret/data/lldap")"#)]
+    pub vault_path: String,
+    /// Executable printing the secrets, run without arguments.
+    #[builder(default)]
+    pub command: Option<String>,
+    /// How often the secrets are fetched again, or 0 to only fetch them at startup. Only the SMTP
+    /// credentials are updated without a restart.
+    #[builder(default = "0")]
+    pub refresh_interval_seconds: u64,
+}
+
+impl std::default::Default for SecretsProviderOptions {
+    fn default() -> Self {
+        SecretsProviderOptionsBuilder::default().build().unwrap()
     }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
 #[builder(pattern = "owned", build_fn(name = "private_build"))]
 pub struct Configuration {
-    #[builder(default = r#"String::from("0.0.0.0")"#)]
-    pub ldap_host: String,
+    #[builder(default = r#"ListenAddresses::from("0.0.0.0")"#)]
+    pub ldap_host: ListenAddresses,
     #[builder(default = "3890")]
     pub ldap_port: u16,
-    #[builder(default = r#"String::from("0.0.0.0")"#)]
-    pub http_host: String,
+    #[builder(default)]
+    pub ldap_proxy_protocol: bool,
+    #[builder(default)]
+    pub ldap_rate_limit_options: LdapRateLimitOptions,
+    /// Number of seconds without any request after which an LDAP connection is closed, or 0 to
+    /// keep connections open indefinitely.
+    #[builder(default = "0")]
+    pub ldap_idle_timeout_seconds: u64,
+    #[builder(default)]
+    pub ldap_wire_log: LdapWireLogOptions,
+    /// On SIGTERM, how long the requests in progress have to complete before the server stops.
+    #[builder(default = "30")]
+    pub shutdown_grace_period_seconds: u64,
+    #[builder(default)]
+    pub runtime_options: RuntimeOptions,
+    #[builder(default)]
+    pub timeout_options: TimeoutOptions,
+    #[builder(default)]
+    pub graphql_options: GraphqlOptions,
+    #[builder(default = r#"ListenAddresses::from("0.0.0.0")"#)]
+    pub http_host: ListenAddresses,
     #[builder(default = "17170")]
     pub http_port: u16,
+    #[builder(default)]
+    pub http_rate_limit_options: HttpRateLimitOptions,
+    #[builder(default)]
+    pub https_options: HttpsOptions,
+    /// The health checks warn when the LDAPS or HTTPS certificate expires in less than this many
+    /// days.
+    #[builder(default = "14")]
+    pub certificate_expiry_warning_days: u32,
+    /// IPs or networks (e.g. "10.0.0.0/8") of the reverse proxies in front of the HTTP server,
+    /// trusted to report the client IP and protocol in the forwarding headers.
+    #[builder(default)]
+    pub trusted_proxies: Vec<String>,
+    #[builder(default)]
+    pub unix_socket_options: UnixSocketOptions,
     #[builder(default = r#"SecUtf8::from("secretjwtsecret")"#)]
     pub jwt_secret: SecUtf8,
+    /// Identifier of `jwt_secret`, set in the header of the tokens it signs.
+    #[builder(default = r#"String::from("default")"#)]
+    pub jwt_key_id: String,
+    /// Secrets that were rotated out, still accepted to verify the tokens until they expire.
+    #[builder(default)]
+    pub previous_jwt_secrets: Vec<JwtSecretOptions>,
     #[builder(default = r#"String::from("dc=example,dc=com")"#)]
     pub ldap_base_dn: String,
+    #[builder(default)]
+    pub ldap_base_dn_aliases: Vec<String>,
     #[builder(default = r#"UserId::new("admin")"#)]
     pub ldap_user_dn: UserId,
     #[builder(default = r#"String::default()"#)]
@@ -84,20 +1279,88 @@ pub struct Configuration {
     pub ldap_user_pass: SecUtf8,
     #[builder(default = r#"String::from("sqlite://users.db?mode=rwc")"#)]
     pub database_url: String,
+    /// A read-only replica of the database, for the listings and searches. It uses the same pool
+    /// options as the primary database.
+    #[builder(default)]
+    pub database_read_replica_url: Option<String>,
+    #[builder(default)]
+    pub database_options: DatabaseOptions,
+    #[builder(default)]
+    pub cache_options: CacheOptions,
     #[builder(default)]
     pub ignored_user_attributes: Vec<String>,
     #[builder(default)]
     pub ignored_group_attributes: Vec<String>,
     #[builder(default = "false")]
     pub verbose: bool,
+    /// Start in read-only mode, rejecting the changes until an admin turns it off.
+    #[builder(default = "false")]
+    pub read_only: bool,
+    #[builder(default)]
+    pub logging: LoggingOptions,
+    #[builder(default)]
+    pub opentelemetry_options: OpenTelemetryOptions,
     #[builder(default = r#"String::from("server_key")"#)]
     pub key_file: String,
     #[builder(default)]
     pub smtp_options: MailOptions,
     #[builder(default)]
     pub ldaps_options: LdapsOptions,
+    #[builder(default)]
+    pub acme_options: AcmeOptions,
+    #[builder(default)]
+    pub upstream_ldap_options: UpstreamLdapOptions,
+    #[builder(default)]
+    pub replica_options: ReplicaOptions,
+    #[builder(default)]
+    pub external_auth: ExternalAuthOptions,
+    #[builder(default)]
+    pub bootstrap_options: BootstrapOptions,
+    #[builder(default)]
+    pub oidc_options: OidcOptions,
+    #[builder(default)]
+    pub cors_options: CorsOptions,
+    #[builder(default)]
+    pub membership_options: MembershipOptions,
+    #[builder(default)]
+    pub active_directory_options: ActiveDirectoryOptions,
+    #[builder(default)]
+    pub client_profiles: Vec<ClientProfileOptions>,
+    #[builder(default)]
+    pub user_templates: Vec<UserTemplateOptions>,
+    #[builder(default)]
+    pub avatar_options: AvatarOptions,
+    #[builder(default)]
+    pub password_policy: PasswordPolicyOptions,
+    #[builder(default)]
+    pub username_policy: UsernamePolicyOptions,
+    /// Refuses to give a user the email address of another user, ignoring case. The secondary
+    /// addresses are always unique.
+    #[builder(default = "false")]
+    pub unique_emails: bool,
+    #[builder(default)]
+    pub account_lockout: AccountLockoutOptions,
+    #[builder(default)]
+    pub security_notifications: SecurityNotificationOptions,
+    #[builder(default)]
+    pub webhooks: Vec<WebhookOptions>,
+    #[builder(default)]
+    pub secrets_provider: SecretsProviderOptions,
+    #[builder(default)]
+    pub maintenance_options: MaintenanceOptions,
+    #[builder(default)]
+    pub stale_accounts: StaleAccountOptions,
     #[builder(default = r#"String::from("http://localhost")"#)]
     pub http_url: String,
+    /// Allows anyone to request an account through `/auth/sign-up`. The requests wait for an
+    /// administrator to approve them.
+    #[builder(default = "false")]
+    pub enable_self_registration: bool,
+    /// Serves the Prometheus metrics on `/metrics`, without authentication.
+    #[builder(default = "false")]
+    pub enable_metrics: bool,
+    #[builder(default)]
+    pub regular_user_visibility: RegularUserVisibility,
     #[serde(skip)]
     #[builder(field(private), default = "None")]
     server_setup: Option<ServerSetup>,
@@ -115,6 +1378,12 @@ impl ConfigurationBuilder {
         Ok(self.server_setup(Some(server_setup)).private_build()?)
     }
 
+    /// The default configuration, without reading or generating the server key, for the commands
+    /// that don't authenticate anyone like the export of the GraphQL schema.
+    pub fn without_server_setup() -> Configuration {
+        ConfigurationBuilder::default().private_build().unwrap()
+    }
+
     #[cfg(test)]
     pub fn for_tests() -> Configuration {
         ConfigurationBuilder::default()
@@ -184,12 +1453,72 @@ impl TopLevelCommandOpts for RunOpts {
     }
 }
 
+impl TopLevelCommandOpts for HealthCheckOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
 impl TopLevelCommandOpts for TestEmailOpts {
     fn general_config(&self) -> &GeneralConfigOpts {
         &self.general_config
     }
 }
 
+impl TopLevelCommandOpts for BackupOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for RestoreOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for ExportLdifOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for ImportOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for MigrateFromLdapOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for ResetAdminPasswordOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for CheckConfigOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for BackupSqliteOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for MigrateOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
 impl ConfigOverrider for RunOpts {
     fn override_config(&self, config: &mut Configuration) {
         self.general_config.override_config(config);
@@ -198,10 +1527,18 @@ impl ConfigOverrider for RunOpts {
             config.key_file = path.to_string();
         }
 
+        if let Some(host) = self.ldap_host.as_deref() {
+            config.ldap_host = ListenAddresses::from(host);
+        }
+
         if let Some(port) = self.ldap_port {
             config.ldap_port = port;
         }
 
+        if let Some(host) = self.http_host.as_deref() {
+            config.http_host = ListenAddresses::from(host);
+        }
+
         if let Some(port) = self.http_port {
             config.http_port = port;
         }
@@ -209,11 +1546,26 @@ impl ConfigOverrider for RunOpts {
         if let Some(url) = self.http_url.as_ref() {
             config.http_url = url.to_string();
         }
+
+        if self.ephemeral {
+            config.database_url = database::IN_MEMORY_DATABASE_URL.to_owned();
+            config.database_read_replica_url = None;
+        }
+
+        if self.replica {
+            config.replica_options.enabled = true;
+        }
         self.smtp_opts.override_config(config);
         self.ldaps_opts.override_config(config);
     }
 }
 
+impl ConfigOverrider for HealthCheckOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
 impl ConfigOverrider for TestEmailOpts {
     fn override_config(&self, config: &mut Configuration) {
         self.general_config.override_config(config);
@@ -221,11 +1573,68 @@ impl ConfigOverrider for TestEmailOpts {
     }
 }
 
+impl ConfigOverrider for BackupOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for RestoreOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for ExportLdifOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for ImportOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for MigrateFromLdapOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for ResetAdminPasswordOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for CheckConfigOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for BackupSqliteOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl ConfigOverrider for MigrateOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
 impl ConfigOverrider for LdapsOpts {
     fn override_config(&self, config: &mut Configuration) {
         if let Some(enabled) = self.ldaps_enabled {
             config.ldaps_options.enabled = enabled;
         }
+        if let Some(host) = self.ldaps_host.as_ref() {
+            config.ldaps_options.host = Some(ListenAddresses::from(host.as_str()));
+        }
         if let Some(port) = self.ldaps_port {
             config.ldaps_options.port = port;
         }
@@ -272,6 +1681,44 @@ impl ConfigOverrider for SmtpOpts {
     }
 }
 
+// Secret files (e.g. Docker or Kubernetes secrets) usually end with a newline, which is not part of
+// the secret.
+fn trim_line_ending(secret: &mut SecUtf8) {
+    let trimmed = secret.unsecure().trim_end_matches(&['\r', '\n'][..]);
+    if trimmed.len() != secret.unsecure().len() {
+        *secret = SecUtf8::from(trimmed);
+    }
+}
+
+/// Reads the configuration file and the `LLDAP_` environment variables. Any value can be read from
+/// a file instead, by adding `_file` to its name: `jwt_secret_file = "/run/secrets/jwt_secret"`
+/// or `LLDAP_SMTP_OPTIONS__PASSWORD_FILE=/run/secrets/smtp_password`.
+fn read_config(config_file: &str) -> Result<Configuration> {
+    use figment_file_provider_adapter::FileAdapter;
+    // These are paths, not values to read from a file.
+    let ignore_keys = ["key_file", "cert_file", "account_file"];
+    let mut config: Configuration = Figment::from(Serialized::defaults(
+        ConfigurationBuilder::default().private_build().unwrap(),
+    ))
+    .merge(FileAdapter::wrap(Toml::file(config_file)).ignore(&ignore_keys))
+    .merge(FileAdapter::wrap(Env::prefixed("LLDAP_").split("__")).ignore(&ignore_keys))
+    .extract()?;
+    trim_line_ending(&mut config.jwt_secret);
+    trim_line_ending(&mut config.ldap_user_pass);
+    trim_line_ending(&mut config.smtp_options.password);
+    trim_line_ending(&mut config.smtp_options.oauth2.client_secret);
+    trim_line_ending(&mut config.smtp_options.oauth2.refresh_token);
+    for key in &mut config.previous_jwt_secrets {
+        trim_line_ending(&mut key.secret);
+    }
+    for webhook in &mut config.webhooks {
+        trim_line_ending(&mut webhook.secret);
+    }
+    trim_line_ending(&mut config.secrets_provider.vault_token);
+    config.database_url = config.database_url.trim_end().to_owned();
+    Ok(config)
+}
+
 pub fn init<C>(overrides: C) -> Result<Configuration>
 where
     C: TopLevelCommandOpts + ConfigOverrider,
@@ -283,24 +1730,99 @@ where
         overrides.general_config().config_file
     );
 
-    use figment_file_provider_adapter::FileAdapter;
-    let ignore_keys = ["key_file", "cert_file"];
-    let mut config: Configuration = Figment::from(Serialized::defaults(
-        ConfigurationBuilder::default().private_build().unwrap(),
-    ))
-    .merge(FileAdapter::wrap(Toml::file(config_file)).ignore(&ignore_keys))
-    .merge(FileAdapter::wrap(Env::prefixed("LLDAP_").split("__")).ignore(&ignore_keys))
-    .extract()?;
-
+    let mut config = read_config(&config_file)?;
     overrides.override_config(&mut config);
     if config.verbose {
         println!("Configuration: {:#?}", &config);
     }
     config.server_setup = Some(get_server_setup(&config.key_file)?);
-    if config.jwt_secret == SecUtf8::from("secretjwtsecret") {
+    let mut jwt_key_ids = std::collections::HashSet::from([config.jwt_key_id.as_str()]);
+    for key in &config.previous_jwt_secrets {
+        if !jwt_key_ids.insert(key.key_id.as_str()) {
+            anyhow::bail!("Duplicate JWT key ID: '{}'", key.key_id);
+        }
+    }
+    for (name, addresses) in [
+        ("ldap_host", Some(&config.ldap_host)),
+        ("http_host", Some(&config.http_host)),
+        ("ldaps_options.host", config.ldaps_options.host.as_ref()),
+        ("https_options.host", config.https_options.host.as_ref()),
+    ] {
+        if addresses.map_or(false, ListenAddresses::is_empty) {
+            anyhow::bail!("{} must contain at least one address", name);
+        }
+    }
+    if config.database_options.min_connections > config.database_options.max_connections {
+        anyhow::bail!("database_options.min_connections is above max_connections");
+    }
+    if config.unix_socket_options.enabled {
+        config.unix_socket_options.permissions_mode()?;
+    }
+    if let Err(e) = cron::Schedule::from_str(&config.maintenance_options.schedule) {
+        anyhow::bail!("Invalid maintenance_options.schedule: {}", e);
+    }
+    if config.stale_accounts.delete_after_days > 0
+        && config.stale_accounts.delete_after_days <= config.stale_accounts.disable_after_days
+    {
+        anyhow::bail!("stale_accounts.delete_after_days must be above disable_after_days");
+    }
+    if config.stale_accounts.delete_after_days > 0 && config.stale_accounts.disable_after_days == 0
+    {
+        anyhow::bail!("stale_accounts.delete_after_days requires disable_after_days");
+    }
+    let mut user_template_names = std::collections::HashSet::new();
+    for template in &config.user_templates {
+        if !user_template_names.insert(template.name.as_str()) {
+            anyhow::bail!("Duplicate user template: '{}'", template.name);
+        }
+        // The user managers can pick any template, they must not grant more than they have.
+        if template
+            .groups
+            .iter()
+            .any(|g| g.eq_ignore_ascii_case("lldap_admin"))
+        {
+            anyhow::bail!(
+                "The user template '{}' cannot add the users to lldap_admin",
+                template.name
+            );
+        }
+    }
+    if config.username_policy.min_length == 0
+        || config.username_policy.min_length > config.username_policy.max_length
+    {
+        anyhow::bail!("username_policy.min_length must be between 1 and max_length");
+    }
+    if let Some(template) = &config.avatar_options.remote_url_template {
+        if !template.contains("{email_sha256}") {
+            anyhow::bail!("avatar_options.remote_url_template must contain {{email_sha256}}");
+        }
+    }
+    if config.secrets_provider.provider == SecretsProviderType::Exec
+        && config.secrets_provider.command.is_none()
+    {
+        anyhow::bail!("secrets_provider.command is required for the exec provider");
+    }
+    if config.acme_options.enabled {
+        if config.acme_options.domain.is_empty() {
+            anyhow::bail!("acme_options.domain is required to request a certificate");
+        }
+        if config.acme_options.challenge == AcmeChallengeType::Dns01
+            && config.acme_options.dns_hook_command.is_none()
+        {
+            anyhow::bail!("acme_options.dns_hook_command is required for the DNS-01 challenge");
+        }
+    }
+    if database::is_in_memory(&config.database_url) {
+        println!(
+            "WARNING: The database is in memory, all the data will be lost when the server stops."
+        );
+    }
+    // Otherwise, the secrets are checked once fetched.
+    let has_secrets_provider = config.secrets_provider.provider != SecretsProviderType::None;
+    if !has_secrets_provider && config.jwt_secret == SecUtf8::from("secretjwtsecret") {
         println!("WARNING: Default JWT secret used! This is highly unsafe and can allow attackers to log in as admin.");
     }
-    if config.ldap_user_pass == SecUtf8::from("password") {
+    if !has_secrets_provider && config.ldap_user_pass == SecUtf8::from("password") {
         println!("WARNING: Unsecure default admin password is used.");
     }
     if config.smtp_options.tls_required.is_some() {
@@ -308,3 +1830,37 @@ where
     }
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_from_files() {
+        let dir = std::env::temp_dir().join(format!("lldap_config_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret_file = dir.join("jwt_secret");
+        std::fs::write(&secret_file, "secret from a file\n").unwrap();
+        std::fs::write(dir.join("smtp_password"), "smtp password\r\n").unwrap();
+        let config_file = dir.join("lldap_config.toml");
+        std::fs::write(
+            &config_file,
+            format!(
+                "jwt_secret_file = \"{}\"\n\
+                 [smtp_options]\n\
+                 password_file = \"{}\"\n\
+                 [acme_options]\n\
+                 account_file = \"/data/acme_account.json\"\n",
+                secret_file.display(),
+                dir.join("smtp_password").display()
+            ),
+        )
+        .unwrap();
+        let config = read_config(config_file.to_str().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.jwt_secret.unsecure(), "secret from a file");
+        assert_eq!(config.smtp_options.password.unsecure(), "smtp password");
+        assert_eq!(config.acme_options.account_file, "/data/acme_account.json");
+    }
+}
@@ -0,0 +1,78 @@
+//! The correlation IDs of the HTTP requests and of the LDAP operations. They are recorded in the
+//! root span of each request, so that all its logs down to the SQL statements carry them, and
+//! returned with the GraphQL errors to find these logs.
+
+use rand::Rng;
+
+/// The header with the correlation ID chosen by the client or a reverse proxy, if any.
+pub const CORRELATION_ID_HEADER: &str = "X-Correlation-ID";
+
+const MAX_CLIENT_ID_LENGTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    pub fn new() -> Self {
+        Self(format!("{:016x}", rand::thread_rng().gen::<u64>()))
+    }
+
+    /// The ID sent by the client, if it is safe to log: short, with only letters, digits and
+    /// dashes, dots or underscores.
+    pub fn from_client(id: &str) -> Option<Self> {
+        (!id.is_empty()
+            && id.len() <= MAX_CLIENT_ID_LENGTH
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_')))
+        .then(|| Self(id.to_owned()))
+    }
+
+    /// The ID of an operation of an LDAP connection, from its message ID.
+    pub fn for_operation(&self, message_id: i32) -> Self {
+        Self(format!("{}-{}", self.0, message_id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for CorrelationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_client() {
+        assert_eq!(
+            CorrelationId::from_client("req-42_a.b").map(|id| id.to_string()),
+            Some("req-42_a.b".to_owned())
+        );
+        assert_eq!(CorrelationId::from_client(""), None);
+        assert_eq!(CorrelationId::from_client("a b"), None);
+        assert_eq!(CorrelationId::from_client("a\nINFO forged"), None);
+        assert_eq!(CorrelationId::from_client(&"a".repeat(65)), None);
+    }
+
+    #[test]
+    fn test_for_operation() {
+        let id = CorrelationId::new();
+        assert_eq!(id.as_str().len(), 16);
+        assert_eq!(
+            id.for_operation(3).to_string(),
+            format!("{}-3", id.as_str())
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    domain::{
+        sql_backend_handler::SqlBackendHandler,
+        sql_tables::{init_table, DbConnection},
+    },
+    infra::{
+        configuration::{Configuration, DatabaseOptions},
+        jwt_sql_tables,
+    },
+};
+use anyhow::{Context, Result};
+use sea_orm::{
+    ConnectionTrait, DbBackend, SqlxMySqlConnector, SqlxPostgresConnector, SqlxSqliteConnector,
+    Statement,
+};
+use sqlx::{
+    mysql::MySqlConnectOptions,
+    pool::PoolOptions,
+    postgres::PgConnectOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    ConnectOptions,
+};
+use std::{path::Path, str::FromStr, time::Duration};
+
+fn statement_log_level(options: &DatabaseOptions) -> Result<log::LevelFilter> {
+    options
+        .statement_log_level
+        .parse::<log::LevelFilter>()
+        .with_context(|| {
+            format!(
+                "Invalid database statement log level: '{}'",
+                options.statement_log_level
+            )
+        })
+}
+
+// The statements are logged at `statement_log_level`, and the slow ones as warnings along with
+// their duration.
+fn configure_statement_logs(
+    connect_options: &mut impl ConnectOptions,
+    options: &DatabaseOptions,
+) -> Result<()> {
+    connect_options.log_statements(statement_log_level(options)?);
+    connect_options.log_slow_statements(
+        match options.slow_statement_threshold_ms {
+            0 => log::LevelFilter::Off,
+            _ => log::LevelFilter::Warn,
+        },
+        Duration::from_millis(options.slow_statement_threshold_ms),
+    );
+    Ok(())
+}
+
+fn pool_options<DB: sqlx::Database>(options: &DatabaseOptions) -> PoolOptions<DB> {
+    PoolOptions::new()
+        .max_connections(options.max_connections)
+        .min_connections(options.min_connections)
+        .acquire_timeout(Duration::from_secs(options.acquire_timeout_seconds))
+        .idle_timeout(Duration::from_secs(options.idle_timeout_seconds))
+}
+
+/// The URL of an ephemeral database, kept in memory and lost when the server stops.
+pub const IN_MEMORY_DATABASE_URL: &str = "sqlite::memory:";
+
+pub fn is_in_memory(database_url: &str) -> bool {
+    database_url.starts_with("sqlite:")
+        && (database_url.contains(":memory:") || database_url.contains("mode=memory"))
+}
+
+/// The backend of the ephemeral instances and of the tests: the SQL backend, over an in-memory
+/// SQLite database. A separate implementation of the backend traits would have to follow every
+/// feature of the SQL one, for no measurable gain in the speed of the tests.
+pub type MemoryBackendHandler = SqlBackendHandler;
+
+/// A backend with an empty in-memory database, lost when the last clone of the handler is dropped.
+pub async fn memory_backend_handler(config: Configuration) -> Result<MemoryBackendHandler> {
+    let pool = connect(&config.database_options, IN_MEMORY_DATABASE_URL).await?;
+    init_table(&pool)
+        .await
+        .context("while creating the tables")?;
+    jwt_sql_tables::init_table(&pool)
+        .await
+        .context("while creating the token tables")?;
+    Ok(SqlBackendHandler::new(config, pool))
+}
+
+// The SQLite pool is built directly with sqlx, since sea-orm doesn't expose the journal mode and
+// the busy timeout.
+async fn connect_to_sqlite(options: &DatabaseOptions, database_url: &str) -> Result<DbConnection> {
+    let mut connect_options = SqliteConnectOptions::from_str(database_url)
+        .context("while parsing the SQLite database URL")?
+        .journal_mode(if options.sqlite_wal {
+            SqliteJournalMode::Wal
+        } else {
+            SqliteJournalMode::Delete
+        })
+        .busy_timeout(Duration::from_secs(options.sqlite_busy_timeout_seconds));
+    configure_statement_logs(&mut connect_options, options)?;
+    let pool_options = pool_options(options);
+    let pool_options = if is_in_memory(database_url) {
+        // Each connection has its own in-memory database, dropped with it: a single one is kept
+        // open for the lifetime of the server.
+        pool_options
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        pool_options
+    };
+    let pool = pool_options.connect_with(connect_options).await?;
+    Ok(SqlxSqliteConnector::from_sqlx_sqlite_pool(pool))
+}
+
+/// Opens a pool of connections to the database, with the pool options of the configuration.
+/// The pools are built with sqlx, since sea-orm doesn't expose the logging of the slow
+/// statements.
+pub async fn connect(options: &DatabaseOptions, database_url: &str) -> Result<DbConnection> {
+    if database_url.starts_with("sqlite:") {
+        return connect_to_sqlite(options, database_url).await;
+    }
+    if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+        let mut connect_options = PgConnectOptions::from_str(database_url)
+            .context("while parsing the PostgreSQL database URL")?;
+        configure_statement_logs(&mut connect_options, options)?;
+        let pool = pool_options(options).connect_with(connect_options).await?;
+        return Ok(SqlxPostgresConnector::from_sqlx_postgres_pool(pool));
+    }
+    if database_url.starts_with("mysql:") {
+        let mut connect_options = MySqlConnectOptions::from_str(database_url)
+            .context("while parsing the MySQL database URL")?;
+        configure_statement_logs(&mut connect_options, options)?;
+        let pool = pool_options(options).connect_with(connect_options).await?;
+        return Ok(SqlxMySqlConnector::from_sqlx_mysql_pool(pool));
+    }
+    anyhow::bail!("Unsupported database URL, it should start with sqlite:, postgres: or mysql:")
+}
+
+/// Copies the SQLite database to a new file, without stopping the server: `VACUUM INTO` reads
+/// the database in a single transaction, so the copy is consistent even with concurrent writes,
+/// and it includes the content of the WAL.
+pub async fn backup_sqlite(pool: &DbConnection, output_file: &str) -> Result<()> {
+    if pool.get_database_backend() != DbBackend::Sqlite {
+        anyhow::bail!("Online backups are only supported for SQLite databases");
+    }
+    if Path::new(output_file).exists() {
+        anyhow::bail!("{} already exists", output_file);
+    }
+    pool.execute(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        "VACUUM INTO ?",
+        [output_file.into()],
+    ))
+    .await
+    .with_context(|| format!("while backing up the database to {}", output_file))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            handler::{CreateUserRequest, UserBackendHandler},
+            model,
+            types::UserId,
+        },
+        infra::configuration::ConfigurationBuilder,
+    };
+    use sea_orm::{EntityTrait, PaginatorTrait};
+
+    #[tokio::test]
+    async fn test_backup_sqlite() {
+        let options = DatabaseOptions::default();
+        let pool = connect(&options, IN_MEMORY_DATABASE_URL).await.unwrap();
+        init_table(&pool).await.unwrap();
+        let output_file =
+            std::env::temp_dir().join(format!("lldap_backup_test_{}.db", std::process::id()));
+        let output_file = output_file.to_str().unwrap();
+        backup_sqlite(&pool, output_file).await.unwrap();
+        // The existing files are not overwritten.
+        assert!(backup_sqlite(&pool, output_file).await.is_err());
+        let backup = connect(&options, &format!("sqlite://{}", output_file))
+            .await
+            .unwrap();
+        assert_eq!(model::User::find().count(&backup).await.unwrap(), 0);
+        std::fs::remove_file(output_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_database_is_shared() {
+        let pool = connect(&DatabaseOptions::default(), IN_MEMORY_DATABASE_URL)
+            .await
+            .unwrap();
+        init_table(&pool).await.unwrap();
+        // All the queries see the same database, even concurrent ones.
+        let (first, second) = tokio::join!(
+            model::User::find().count(&pool),
+            model::Group::find().count(&pool)
+        );
+        assert_eq!(first.unwrap(), 0);
+        assert_eq!(second.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_handler() {
+        let handler = memory_backend_handler(ConfigurationBuilder::for_tests())
+            .await
+            .unwrap();
+        handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // The clones of the handler share the database.
+        assert_eq!(
+            handler
+                .clone()
+                .get_user_details(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .email,
+            "bob@example.com"
+        );
+        // Each handler has its own database.
+        assert!(memory_backend_handler(ConfigurationBuilder::for_tests())
+            .await
+            .unwrap()
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_is_in_memory() {
+        assert!(is_in_memory(IN_MEMORY_DATABASE_URL));
+        assert!(is_in_memory("sqlite:file:lldap?mode=memory&cache=shared"));
+        assert!(!is_in_memory("sqlite:///data/users.db?mode=rwc"));
+        assert!(!is_in_memory("postgres://lldap@db/memory"));
+    }
+}
@@ -1,19 +1,112 @@
-use crate::domain::{
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
-    sql_tables::DbConnection,
+use crate::{
+    domain::{
+        handler::{
+            AuditActor, AuditEvent, UpdateUserRequest, UserBackendHandler, UserRequestFilter,
+        },
+        lookup_cache::LookupCache,
+        model::{
+            self, DeletedGroupsColumn, DeletedUsersColumn, GroupColumn, InvitationsColumn,
+            JwtRefreshStorageColumn, JwtStorageColumn, MembershipColumn, PasswordResetTokensColumn,
+            UserColumn,
+        },
+        sql_audit_log_backend_handler::audit,
+        sql_backend_handler::SqlBackendHandler,
+        sql_tables::DbConnection,
+        sql_user_backend_handler::inactive_since,
+        types::{AuditSource, ChangeEntryType, ChangeType, GroupId, JpegPhoto, UserId, Uuid},
+    },
+    infra::{
+        avatar,
+        config_reload::SharedMailOptions,
+        configuration::{
+            AvatarOptions, MaintenanceJob, MaintenanceOptions, PasswordPolicyOptions,
+            StaleAccountOptions,
+        },
+        mail,
+    },
 };
-use actix::prelude::{Actor, AsyncContext, Context};
+use actix::prelude::{Actor, AsyncContext, Context, Handler, Message, ResponseFuture};
 use cron::Schedule;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
-use std::{str::FromStr, time::Duration};
-use tracing::{error, info, instrument};
+use futures_util::{stream, StreamExt};
+use rand::Rng;
+use sea_orm::{
+    sea_query::Cond, ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use tracing::{error, info, instrument, warn};
+
+/// Number of remote avatars fetched at the same time.
+const REMOTE_AVATAR_CONCURRENCY: usize = 8;
+
+/// What the DB cleanup runs, when it last ran and when it runs next.
+#[derive(Clone, Debug, Default)]
+pub struct SchedulerRuns {
+    pub schedule: String,
+    pub jobs: Vec<MaintenanceJob>,
+    pub last_run: Option<chrono::DateTime<chrono::Utc>>,
+    /// The jobs that failed during the last run.
+    pub failed_jobs: Vec<MaintenanceJob>,
+    pub next_run: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Updated by the scheduler, read by the health checks and the GraphQL API.
+pub type SchedulerStatus = Arc<std::sync::RwLock<SchedulerRuns>>;
+
+/// What the password expiry warnings need, besides the database.
+#[derive(Clone)]
+pub struct PasswordExpiryWarnings {
+    pub policy: PasswordPolicyOptions,
+    pub mail_options: SharedMailOptions,
+    pub server_url: String,
+}
+
+/// What the stale accounts lifecycle needs, besides the database. The accounts are disabled and
+/// deleted through the backend handler, like by the admins: the changes are recorded and published,
+/// and the deleted accounts go to the recycle bin.
+#[derive(Clone)]
+pub struct StaleAccountLifecycle {
+    pub options: StaleAccountOptions,
+    pub mail_options: SharedMailOptions,
+    pub server_url: String,
+    pub backend_handler: SqlBackendHandler,
+}
+
+#[derive(Default)]
+struct StaleAccountCounts {
+    warned: usize,
+    disabled: usize,
+    deleted: usize,
+}
 
 // Define actor
 pub struct Scheduler {
     schedule: Schedule,
+    jitter_seconds: u64,
+    jobs: Vec<MaintenanceJob>,
+    deleted_entries_retention: chrono::Duration,
+    avatar_options: AvatarOptions,
+    password_expiry_warnings: PasswordExpiryWarnings,
+    stale_accounts: StaleAccountLifecycle,
     sql_pool: DbConnection,
+    // Cleared after the cleanup, which disables users and removes memberships.
+    lookup_cache: Option<LookupCache>,
+    // Held while cleaning the DB.
+    running: Arc<Mutex<()>>,
+    status: SchedulerStatus,
 }
 
+/// Waits for the cleanup in progress, if any, to complete. Sent before shutting down, so that it
+/// is not interrupted.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Flush;
+
 // Provide Actor implementation for our actor
 impl Actor for Scheduler {
     type Context = Context<Self>;
@@ -31,14 +124,79 @@ impl Actor for Scheduler {
     }
 }
 
+impl Handler<Flush> for Scheduler {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _: Flush, _ctx: &mut Context<Self>) -> Self::Result {
+        let running = self.running.clone();
+        Box::pin(async move {
+            let _running = running.lock().await;
+        })
+    }
+}
+
 impl Scheduler {
-    pub fn new(cron_expression: &str, sql_pool: DbConnection) -> Self {
-        let schedule = Schedule::from_str(cron_expression).unwrap();
-        Self { schedule, sql_pool }
+    pub fn new(
+        options: &MaintenanceOptions,
+        avatar_options: AvatarOptions,
+        password_expiry_warnings: PasswordExpiryWarnings,
+        stale_accounts: StaleAccountLifecycle,
+        sql_pool: DbConnection,
+        lookup_cache: Option<LookupCache>,
+        status: SchedulerStatus,
+    ) -> Self {
+        // Validated with the rest of the configuration.
+        let schedule = Schedule::from_str(&options.schedule).unwrap();
+        {
+            let mut status = status.write().unwrap();
+            status.schedule = options.schedule.clone();
+            status.jobs = options.jobs.clone();
+        }
+        Self {
+            schedule,
+            jitter_seconds: options.jitter_seconds,
+            jobs: options.jobs.clone(),
+            deleted_entries_retention: chrono::Duration::days(
+                options.deleted_entries_retention_days as i64,
+            ),
+            avatar_options,
+            password_expiry_warnings,
+            stale_accounts,
+            sql_pool,
+            lookup_cache,
+            running: Arc::default(),
+            status,
+        }
     }
 
     fn schedule_task(&self, ctx: &mut Context<Self>) {
-        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(self.sql_pool.clone()));
+        let sql_pool = self.sql_pool.clone();
+        let jobs = self.jobs.clone();
+        let deleted_entries_retention = self.deleted_entries_retention;
+        let avatar_options = self.avatar_options.clone();
+        let password_expiry_warnings = self.password_expiry_warnings.clone();
+        let stale_accounts = self.stale_accounts.clone();
+        let lookup_cache = self.lookup_cache.clone();
+        let running = self.running.clone();
+        let status = self.status.clone();
+        let future = actix::fut::wrap_future::<_, Self>(async move {
+            let _running = running.lock().await;
+            let failed_jobs = Self::cleanup_db(
+                sql_pool,
+                jobs,
+                deleted_entries_retention,
+                &avatar_options,
+                &password_expiry_warnings,
+                &stale_accounts,
+            )
+            .await;
+            if let Some(cache) = lookup_cache {
+                cache.invalidate();
+            }
+            let mut status = status.write().unwrap();
+            status.last_run = Some(chrono::Utc::now());
+            status.failed_jobs = failed_jobs;
+        });
         ctx.spawn(future);
 
         ctx.run_later(self.duration_until_next(), move |this, ctx| {
@@ -46,37 +204,795 @@ impl Scheduler {
         });
     }
 
+    async fn run_job(
+        sql_pool: &DbConnection,
+        job: MaintenanceJob,
+        deleted_entries_retention: chrono::Duration,
+        avatar_options: &AvatarOptions,
+        password_expiry_warnings: &PasswordExpiryWarnings,
+        stale_accounts: &StaleAccountLifecycle,
+    ) -> Result<(), sea_orm::DbErr> {
+        let now = chrono::Utc::now().naive_utc();
+        match job {
+            MaintenanceJob::ExpiredTokens => {
+                model::JwtRefreshStorage::delete_many()
+                    .filter(JwtRefreshStorageColumn::ExpiryDate.lt(now))
+                    .exec(sql_pool)
+                    .await?;
+                model::JwtStorage::delete_many()
+                    .filter(JwtStorageColumn::ExpiryDate.lt(now))
+                    .exec(sql_pool)
+                    .await?;
+            }
+            MaintenanceJob::ExpiredPasswordResetTokens => {
+                model::PasswordResetTokens::delete_many()
+                    .filter(PasswordResetTokensColumn::ExpiryDate.lt(now))
+                    .exec(sql_pool)
+                    .await?;
+            }
+            MaintenanceJob::ExpiredInvitations => {
+                model::Invitations::delete_many()
+                    .filter(InvitationsColumn::ExpiryDate.lt(now))
+                    .exec(sql_pool)
+                    .await?;
+            }
+            MaintenanceJob::ExpiredAccounts => match Self::disable_expired_users(sql_pool).await? {
+                0 => {}
+                count => info!("Disabled {} expired accounts", count),
+            },
+            MaintenanceJob::ExpiredMemberships => {
+                match Self::remove_expired_memberships(sql_pool).await? {
+                    0 => {}
+                    count => info!("Removed {} expired group memberships", count),
+                }
+            }
+            MaintenanceJob::DeletedEntries => {
+                Self::purge_deleted_entries(sql_pool, now - deleted_entries_retention).await?
+            }
+            MaintenanceJob::RemoteAvatars => {
+                match Self::refresh_remote_avatars(sql_pool, avatar_options).await? {
+                    0 => {}
+                    count => info!("Updated {} remote avatars", count),
+                }
+            }
+            MaintenanceJob::PasswordExpiryWarnings => {
+                match Self::send_password_expiry_warnings(sql_pool, password_expiry_warnings)
+                    .await?
+                {
+                    0 => {}
+                    count => info!("Warned {} users of their password expiry", count),
+                }
+            }
+            MaintenanceJob::StaleAccounts => {
+                let counts = Self::apply_stale_account_policy(sql_pool, stale_accounts).await?;
+                if counts.warned > 0 {
+                    info!("Warned {} inactive users", counts.warned);
+                }
+                if counts.disabled > 0 {
+                    info!("Disabled {} inactive accounts", counts.disabled);
+                }
+                if counts.deleted > 0 {
+                    info!("Deleted {} inactive accounts", counts.deleted);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the jobs that failed.
     #[instrument(skip_all)]
-    async fn cleanup_db(sql_pool: DbConnection) {
+    async fn cleanup_db(
+        sql_pool: DbConnection,
+        jobs: Vec<MaintenanceJob>,
+        deleted_entries_retention: chrono::Duration,
+        avatar_options: &AvatarOptions,
+        password_expiry_warnings: &PasswordExpiryWarnings,
+        stale_accounts: &StaleAccountLifecycle,
+    ) -> Vec<MaintenanceJob> {
         info!("Cleaning DB");
-        if let Err(e) = model::JwtRefreshStorage::delete_many()
-            .filter(JwtRefreshStorageColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
-            .exec(&sql_pool)
+        let mut failed_jobs = Vec::new();
+        for job in jobs {
+            if let Err(e) = Self::run_job(
+                &sql_pool,
+                job,
+                deleted_entries_retention,
+                avatar_options,
+                password_expiry_warnings,
+                stale_accounts,
+            )
             .await
+            {
+                error!("DB error while running the {} job: {}", job.name(), e);
+                failed_jobs.push(job);
+            }
+        }
+        info!("DB cleaned!");
+        failed_jobs
+    }
+
+    // Empties the recycle bin of the entries deleted before the date.
+    async fn purge_deleted_entries(
+        sql_pool: &DbConnection,
+        deleted_before: chrono::NaiveDateTime,
+    ) -> Result<(), sea_orm::DbErr> {
+        let server = AuditActor::system(AuditSource::Server);
+        for user in model::DeletedUsers::find()
+            .filter(DeletedUsersColumn::DeletionDate.lt(deleted_before))
+            .all(sql_pool)
+            .await?
         {
-            error!("DB error while cleaning up JWT refresh tokens: {}", e);
+            model::DeletedUsers::delete_by_id(user.user_id.clone())
+                .exec(sql_pool)
+                .await?;
+            audit(
+                sql_pool,
+                &server,
+                "purge_user",
+                AuditEvent::user_target(&user.user_id),
+                None,
+            )
+            .await;
         }
-        if let Err(e) = model::JwtStorage::delete_many()
-            .filter(JwtStorageColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
-            .exec(&sql_pool)
-            .await
+        for group in model::DeletedGroups::find()
+            .filter(DeletedGroupsColumn::DeletionDate.lt(deleted_before))
+            .all(sql_pool)
+            .await?
         {
-            error!("DB error while cleaning up JWT storage: {}", e);
-        };
-        if let Err(e) = model::PasswordResetTokens::delete_many()
-            .filter(PasswordResetTokensColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
-            .exec(&sql_pool)
+            model::DeletedGroups::delete_by_id(group.group_id)
+                .exec(sql_pool)
+                .await?;
+            audit(
+                sql_pool,
+                &server,
+                "purge_group",
+                AuditEvent::group_target(group.group_id),
+                None,
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    // Disables the accounts whose validity period is over, and records the change so that LDAP
+    // sync clients pick it up.
+    async fn disable_expired_users(sql_pool: &DbConnection) -> Result<usize, sea_orm::DbErr> {
+        let now = chrono::Utc::now();
+        let expired_users = model::User::find()
+            .filter(UserColumn::Enabled.eq(true))
+            .filter(UserColumn::ValidUntil.lt(now.naive_utc()))
+            .all(sql_pool)
+            .await?;
+        let server = AuditActor::system(AuditSource::Server);
+        for user in &expired_users {
+            model::users::ActiveModel {
+                user_id: ActiveValue::Set(user.user_id.clone()),
+                enabled: ActiveValue::Set(false),
+                modified_date: ActiveValue::Set(now),
+                ..Default::default()
+            }
+            .update(sql_pool)
+            .await?;
+            Self::log_modification(
+                sql_pool,
+                ChangeEntryType::User,
+                user.user_id.to_string(),
+                user.uuid.clone(),
+            )
+            .await?;
+            audit(
+                sql_pool,
+                &server,
+                "update_user",
+                AuditEvent::user_target(&user.user_id),
+                Some("disabled".to_owned()),
+            )
+            .await;
+        }
+        Ok(expired_users.len())
+    }
+
+    // Emails the users whose password expires within the warning period, once per password. The
+    // failed emails are retried at the next run.
+    async fn send_password_expiry_warnings(
+        sql_pool: &DbConnection,
+        options: &PasswordExpiryWarnings,
+    ) -> Result<usize, sea_orm::DbErr> {
+        let policy = &options.policy;
+        if policy.max_age_days == 0 || policy.expiry_warning_days == 0 {
+            return Ok(0);
+        }
+        let now = chrono::Utc::now();
+        let warned_before_expiry =
+            chrono::Duration::days(policy.expiry_warning_days.min(policy.max_age_days) as i64);
+        let set_before =
+            now - chrono::Duration::days(policy.max_age_days as i64) + warned_before_expiry;
+        let users = model::User::find()
+            .filter(UserColumn::Enabled.eq(true))
+            .filter(UserColumn::PasswordExpiryWarned.eq(false))
+            .filter(UserColumn::PasswordModifiedDate.lt(set_before.naive_utc()))
+            .all(sql_pool)
+            .await?;
+        let mail_options = options.mail_options.read().unwrap().clone();
+        let mut warned = 0;
+        for user in users {
+            let expiry_date = match user
+                .password_modified_date
+                .and_then(|date| policy.password_expiry_date(date))
+            {
+                Some(date) => date,
+                None => continue,
+            };
+            // The expired passwords are changed at the next login instead.
+            if now < expiry_date {
+                let username = user
+                    .display_name
+                    .as_deref()
+                    .unwrap_or_else(|| user.user_id.as_str());
+                if let Err(e) = mail::send_password_expiry_warning_email(
+                    username,
+                    &user.email,
+                    user.locale.as_deref(),
+                    &expiry_date,
+                    (expiry_date - now).num_days(),
+                    &options.server_url,
+                    &mail_options,
+                )
+                .await
+                {
+                    warn!(
+                        "Could not warn {} of their password expiry: {:#}",
+                        &user.user_id, e
+                    );
+                    continue;
+                }
+                warned += 1;
+            }
+            model::users::ActiveModel {
+                user_id: ActiveValue::Set(user.user_id),
+                password_expiry_warned: ActiveValue::Set(true),
+                ..Default::default()
+            }
+            .update(sql_pool)
+            .await?;
+        }
+        Ok(warned)
+    }
+
+    // The members of the exempt groups, directly or through a subgroup.
+    async fn stale_account_exemptions(
+        lifecycle: &StaleAccountLifecycle,
+    ) -> Result<HashSet<UserId>, sea_orm::DbErr> {
+        if lifecycle.options.exempt_groups.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let filter = UserRequestFilter::Or(
+            lifecycle
+                .options
+                .exempt_groups
+                .iter()
+                .cloned()
+                .map(UserRequestFilter::MemberOf)
+                .collect(),
+        );
+        Ok(lifecycle
+            .backend_handler
+            .list_users(Some(filter), false)
             .await
+            .map_err(|e| sea_orm::DbErr::Custom(e.to_string()))?
+            .into_iter()
+            .map(|user| user.user.user_id)
+            .collect())
+    }
+
+    // Warns the users inactive for almost the configured period, disables their account once the
+    // period is over and the warning period passed, then deletes the accounts it disabled that
+    // stay inactive. The accounts are only disabled after a warning, which is recorded even if the
+    // email fails: an invalid address would otherwise keep the account enabled forever.
+    async fn apply_stale_account_policy(
+        sql_pool: &DbConnection,
+        lifecycle: &StaleAccountLifecycle,
+    ) -> Result<StaleAccountCounts, sea_orm::DbErr> {
+        let options = &lifecycle.options;
+        let mut counts = StaleAccountCounts::default();
+        if options.disable_after_days == 0 {
+            return Ok(counts);
+        }
+        let exempt_users = Self::stale_account_exemptions(lifecycle).await?;
+        let now = chrono::Utc::now();
+        let disable_after = chrono::Duration::days(options.disable_after_days as i64);
+        let warning_period =
+            chrono::Duration::days(options.warning_days.min(options.disable_after_days) as i64);
+        if options.warning_days > 0 {
+            let users = model::User::find()
+                .filter(UserColumn::Enabled.eq(true))
+                .filter(UserColumn::StaleWarningDate.is_null())
+                .filter(inactive_since(now - disable_after + warning_period))
+                .all(sql_pool)
+                .await?;
+            let mail_options = lifecycle.mail_options.read().unwrap().clone();
+            for user in users
+                .into_iter()
+                .filter(|user| !exempt_users.contains(&user.user_id))
+            {
+                let last_activity = [user.last_ldap_login, user.last_web_login]
+                    .into_iter()
+                    .flatten()
+                    .fold(user.creation_date, std::cmp::max);
+                let disable_date = (last_activity + disable_after).max(now + warning_period);
+                let username = user
+                    .display_name
+                    .as_deref()
+                    .unwrap_or_else(|| user.user_id.as_str());
+                if let Err(e) = mail::send_stale_account_warning_email(
+                    username,
+                    &user.email,
+                    user.locale.as_deref(),
+                    &disable_date,
+                    (disable_date - now).num_days(),
+                    &lifecycle.server_url,
+                    &mail_options,
+                )
+                .await
+                {
+                    warn!(
+                        "Could not warn {} that their account will be disabled: {:#}",
+                        &user.user_id, e
+                    );
+                } else {
+                    counts.warned += 1;
+                }
+                model::users::ActiveModel {
+                    user_id: ActiveValue::Set(user.user_id),
+                    stale_warning_date: ActiveValue::Set(Some(now)),
+                    ..Default::default()
+                }
+                .update(sql_pool)
+                .await?;
+            }
+        }
+        let mut to_disable = model::User::find()
+            .filter(UserColumn::Enabled.eq(true))
+            .filter(inactive_since(now - disable_after));
+        if options.warning_days > 0 {
+            to_disable = to_disable.filter(UserColumn::StaleWarningDate.lte(now - warning_period));
+        }
+        for user in to_disable
+            .all(sql_pool)
+            .await?
+            .into_iter()
+            .filter(|user| !exempt_users.contains(&user.user_id))
+        {
+            // Like the other changes to the users, to record and publish the change.
+            if let Err(e) = lifecycle
+                .backend_handler
+                .update_user(UpdateUserRequest {
+                    user_id: user.user_id.clone(),
+                    enabled: Some(false),
+                    ..Default::default()
+                })
+                .await
+            {
+                warn!(
+                    "Could not disable the inactive account {}: {:#}",
+                    &user.user_id, e
+                );
+                continue;
+            }
+            // Re-enabled by an admin, the account gets a new warning.
+            model::users::ActiveModel {
+                user_id: ActiveValue::Set(user.user_id),
+                stale_warning_date: ActiveValue::Set(None),
+                stale_disabled_date: ActiveValue::Set(Some(now)),
+                ..Default::default()
+            }
+            .update(sql_pool)
+            .await?;
+            counts.disabled += 1;
+        }
+        if options.delete_after_days > 0 {
+            let delete_after = chrono::Duration::days(options.delete_after_days as i64);
+            let users = model::User::find()
+                .filter(UserColumn::Enabled.eq(false))
+                .filter(UserColumn::StaleDisabledDate.is_not_null())
+                .filter(inactive_since(now - delete_after))
+                .all(sql_pool)
+                .await?;
+            for user in users
+                .into_iter()
+                .filter(|user| !exempt_users.contains(&user.user_id))
+            {
+                if let Err(e) = lifecycle.backend_handler.delete_user(&user.user_id).await {
+                    warn!(
+                        "Could not delete the inactive account {}: {:#}",
+                        &user.user_id, e
+                    );
+                    continue;
+                }
+                counts.deleted += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    // Removes the temporary group memberships past their expiry date, and records the changes of
+    // both the users and the groups.
+    async fn remove_expired_memberships(sql_pool: &DbConnection) -> Result<usize, sea_orm::DbErr> {
+        let now = chrono::Utc::now();
+        let expired_memberships = model::Membership::find()
+            .filter(MembershipColumn::ExpiryDate.lt(now.naive_utc()))
+            .all(sql_pool)
+            .await?;
+        let server = AuditActor::system(AuditSource::Server);
+        let mut users = HashSet::<UserId>::new();
+        let mut groups = HashSet::<GroupId>::new();
+        for membership in &expired_memberships {
+            model::Membership::delete_by_id((membership.user_id.clone(), membership.group_id))
+                .exec(sql_pool)
+                .await?;
+            audit(
+                sql_pool,
+                &server,
+                "remove_user_from_group",
+                AuditEvent::user_target(&membership.user_id),
+                Some(AuditEvent::group_target(membership.group_id)),
+            )
+            .await;
+            users.insert(membership.user_id.clone());
+            groups.insert(membership.group_id);
+        }
+        for user in model::User::find()
+            .filter(UserColumn::UserId.is_in(users))
+            .all(sql_pool)
+            .await?
+        {
+            model::users::ActiveModel {
+                user_id: ActiveValue::Set(user.user_id.clone()),
+                modified_date: ActiveValue::Set(now),
+                ..Default::default()
+            }
+            .update(sql_pool)
+            .await?;
+            Self::log_modification(
+                sql_pool,
+                ChangeEntryType::User,
+                user.user_id.into_string(),
+                user.uuid,
+            )
+            .await?;
+        }
+        for group in model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(groups))
+            .all(sql_pool)
+            .await?
         {
-            error!("DB error while cleaning up password reset tokens: {}", e);
+            model::groups::ActiveModel {
+                group_id: ActiveValue::Set(group.group_id),
+                modified_date: ActiveValue::Set(now),
+                ..Default::default()
+            }
+            .update(sql_pool)
+            .await?;
+            Self::log_modification(
+                sql_pool,
+                ChangeEntryType::Group,
+                group.display_name,
+                group.uuid,
+            )
+            .await?;
+        }
+        Ok(expired_memberships.len())
+    }
+
+    // Fetches the remote avatars of the users without an avatar, and refreshes the ones fetched
+    // before if they are stale or the email changed. The avatars set by the users are left alone.
+    // The fetch errors are only logged, and retried on the next run.
+    async fn refresh_remote_avatars(
+        sql_pool: &DbConnection,
+        options: &AvatarOptions,
+    ) -> Result<usize, sea_orm::DbErr> {
+        let template = match &options.remote_url_template {
+            Some(template) => template,
+            None => return Ok(0),
         };
-        info!("DB cleaned!");
+        let client = match avatar::remote_avatar_client() {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Could not create the HTTP client for the avatars: {:#}", e);
+                return Ok(0);
+            }
+        };
+        let now = chrono::Utc::now();
+        let stale_before = now - chrono::Duration::days(options.remote_refresh_days as i64);
+        let remote_avatars = model::RemoteAvatars::find()
+            .all(sql_pool)
+            .await?
+            .into_iter()
+            .map(|remote| (remote.user_id.clone(), remote))
+            .collect::<HashMap<_, _>>();
+        let users = model::User::find()
+            .filter(
+                Cond::any()
+                    .add(UserColumn::Avatar.is_null())
+                    .add(UserColumn::Avatar.eq(JpegPhoto::null()))
+                    .add(UserColumn::UserId.is_in(remote_avatars.keys().cloned())),
+            )
+            .all(sql_pool)
+            .await?;
+        let to_fetch = users.into_iter().filter_map(|user| {
+            let email_hash = avatar::email_hash(&user.email);
+            let fetched_before = remote_avatars.get(&user.user_id);
+            match fetched_before {
+                Some(remote)
+                    if remote.fetch_date >= stale_before && remote.email_hash == email_hash =>
+                {
+                    None
+                }
+                _ => Some((user, email_hash, fetched_before.is_some())),
+            }
+        });
+        let client = &client;
+        let mut fetched = stream::iter(to_fetch)
+            .map(|(user, email_hash, fetched_before)| async move {
+                let url = avatar::remote_avatar_url(template, &email_hash);
+                let result = avatar::fetch_remote_avatar(client, &url, options).await;
+                (user, email_hash, fetched_before, result)
+            })
+            .buffer_unordered(REMOTE_AVATAR_CONCURRENCY);
+        let mut updated = 0;
+        while let Some((user, email_hash, fetched_before, result)) = fetched.next().await {
+            let photo = match result {
+                Ok(photo) => photo,
+                Err(e) => {
+                    warn!(
+                        "Could not fetch the remote avatar of {}: {:#}",
+                        user.user_id, e
+                    );
+                    continue;
+                }
+            };
+            let remote = model::remote_avatars::ActiveModel {
+                user_id: ActiveValue::Set(user.user_id.clone()),
+                email_hash: ActiveValue::Set(email_hash),
+                fetch_date: ActiveValue::Set(now),
+            };
+            if fetched_before {
+                remote.update(sql_pool).await?;
+            } else {
+                remote.insert(sql_pool).await?;
+            }
+            // A 404 keeps the user without an avatar, until the next refresh.
+            let current = user.avatar.filter(|avatar| avatar != &JpegPhoto::null());
+            if photo == current {
+                continue;
+            }
+            model::users::ActiveModel {
+                user_id: ActiveValue::Set(user.user_id.clone()),
+                avatar: ActiveValue::Set(photo),
+                modified_date: ActiveValue::Set(now),
+                ..Default::default()
+            }
+            .update(sql_pool)
+            .await?;
+            Self::log_modification(
+                sql_pool,
+                ChangeEntryType::User,
+                user.user_id.into_string(),
+                user.uuid,
+            )
+            .await?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    async fn log_modification(
+        sql_pool: &DbConnection,
+        entry_type: ChangeEntryType,
+        entry_id: String,
+        entry_uuid: Uuid,
+    ) -> Result<(), sea_orm::DbErr> {
+        model::change_log::ActiveModel {
+            entry_type: ActiveValue::Set(entry_type),
+            entry_id: ActiveValue::Set(entry_id),
+            entry_uuid: ActiveValue::Set(entry_uuid),
+            change_type: ActiveValue::Set(ChangeType::Modify),
+            change_date: ActiveValue::Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+        .insert(sql_pool)
+        .await?;
+        Ok(())
     }
 
     fn duration_until_next(&self) -> Duration {
         let now = chrono::Utc::now();
-        let next = self.schedule.upcoming(chrono::Utc).next().unwrap();
+        let jitter = match self.jitter_seconds {
+            0 => 0,
+            max => rand::thread_rng().gen_range(0..=max),
+        };
+        let next = self.schedule.upcoming(chrono::Utc).next().unwrap()
+            + chrono::Duration::seconds(jitter as i64);
+        self.status.write().unwrap().next_run = Some(next);
         let duration_until = next.signed_duration_since(now);
         duration_until.to_std().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{
+            AuditLogBackendHandler, AuditLogFilter, ChangeLogBackendHandler,
+            RecycleBinBackendHandler,
+        },
+        sql_backend_handler::tests::*,
+    };
+    use crate::infra::configuration::MailOptions;
+    use std::sync::RwLock;
+
+    fn lifecycle(handler: &SqlBackendHandler, delete_after_days: u64) -> StaleAccountLifecycle {
+        StaleAccountLifecycle {
+            options: StaleAccountOptions {
+                disable_after_days: 30,
+                warning_days: 7,
+                delete_after_days,
+                exempt_groups: vec!["Best Group".to_owned()],
+            },
+            // Without an SMTP server, the warnings fail but are still recorded.
+            mail_options: Arc::new(RwLock::new(MailOptions::default())),
+            server_url: "http://localhost".to_owned(),
+            backend_handler: handler.clone(),
+        }
+    }
+
+    async fn set_user_dates(
+        handler: &SqlBackendHandler,
+        user_id: &str,
+        creation_date: chrono::DateTime<chrono::Utc>,
+        stale_warning_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) {
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(UserId::new(user_id)),
+            creation_date: ActiveValue::Set(creation_date),
+            stale_warning_date: ActiveValue::Set(stale_warning_date),
+            ..Default::default()
+        }
+        .update(&handler.sql_pool)
+        .await
+        .unwrap();
+    }
+
+    async fn get_stale_warning_date(
+        handler: &SqlBackendHandler,
+        user_id: &str,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        model::User::find_by_id(UserId::new(user_id))
+            .one(&handler.sql_pool)
+            .await
+            .unwrap()
+            .unwrap()
+            .stale_warning_date
+    }
+
+    #[tokio::test]
+    async fn test_stale_account_policy() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let long_ago = chrono::Utc::now() - chrono::Duration::days(70);
+        // Bob is exempt through the "Best Group", patrick is still active.
+        for user_id in ["bob", "john", "nogroup"] {
+            set_user_dates(handler, user_id, long_ago, None).await;
+        }
+
+        // The inactive users are warned first.
+        let counts =
+            Scheduler::apply_stale_account_policy(&handler.sql_pool, &lifecycle(handler, 60))
+                .await
+                .unwrap();
+        assert_eq!((counts.disabled, counts.deleted), (0, 0));
+        assert!(get_stale_warning_date(handler, "john").await.is_some());
+        assert!(get_stale_warning_date(handler, "nogroup").await.is_some());
+        assert!(get_stale_warning_date(handler, "bob").await.is_none());
+        assert!(get_stale_warning_date(handler, "patrick").await.is_none());
+
+        // Once the warning period is over, they are disabled and the change is recorded.
+        let warned = chrono::Utc::now() - chrono::Duration::days(8);
+        for user_id in ["john", "nogroup"] {
+            set_user_dates(handler, user_id, long_ago, Some(warned)).await;
+        }
+        let last_change_id = handler.get_last_change_id().await.unwrap();
+        let counts =
+            Scheduler::apply_stale_account_policy(&handler.sql_pool, &lifecycle(handler, 0))
+                .await
+                .unwrap();
+        assert_eq!((counts.disabled, counts.deleted), (2, 0));
+        for user_id in ["john", "nogroup"] {
+            let user = handler
+                .get_user_details(&UserId::new(user_id))
+                .await
+                .unwrap();
+            assert!(!user.enabled);
+            assert!(get_stale_warning_date(handler, user_id).await.is_none());
+        }
+        assert!(
+            handler
+                .get_user_details(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .enabled
+        );
+        let mut changed = handler
+            .list_changes_since(last_change_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|change| change.entry_id)
+            .collect::<Vec<_>>();
+        changed.sort();
+        assert_eq!(changed, vec!["john", "nogroup"]);
+
+        // Then deleted, to the recycle bin.
+        let counts =
+            Scheduler::apply_stale_account_policy(&handler.sql_pool, &lifecycle(handler, 60))
+                .await
+                .unwrap();
+        assert_eq!((counts.disabled, counts.deleted), (0, 2));
+        assert_eq!(get_user_names(handler, None).await, vec!["bob", "patrick"]);
+        let mut deleted = handler
+            .list_deleted_users()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|user| user.user_id.into_string())
+            .collect::<Vec<_>>();
+        deleted.sort();
+        assert_eq!(deleted, vec!["john", "nogroup"]);
+    }
+
+    #[tokio::test]
+    async fn test_disable_expired_users() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(UserId::new("bob")),
+            valid_until: ActiveValue::Set(Some(chrono::Utc::now() - chrono::Duration::days(1))),
+            ..Default::default()
+        }
+        .update(&handler.sql_pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            Scheduler::disable_expired_users(&handler.sql_pool)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(
+            !handler
+                .get_user_details(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .enabled
+        );
+        // The scheduled job is recorded in the audit log as the server.
+        let entries = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    action: Some("update_user".to_owned()),
+                    target: Some("user:bob".to_owned()),
+                    ..Default::default()
+                },
+                0,
+                None,
+            )
+            .await
+            .unwrap()
+            .entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor, UserId::new("system"));
+        assert_eq!(entries[0].source, AuditSource::Server);
+        assert_eq!(entries[0].details.as_deref(), Some("disabled"));
+    }
+}
@@ -1,40 +1,181 @@
 use crate::{
-    domain::handler::BackendHandler,
+    domain::{handler::BackendHandler, opaque_handler::OpaqueHandler, types::AuditSource},
     infra::{
-        auth_service::{check_if_token_is_valid, ValidationResults},
-        cli::ExportGraphQLSchemaOpts,
+        auth_service::{check_if_bearer_is_valid, ValidationResults},
+        change_events::{self, ChangeEventBus},
+        cli::{ExportGraphQLSchemaOpts, SchemaFormat},
+        configuration::{AvatarOptions, GraphqlOptions, MailOptions},
+        correlation_id::CorrelationId,
+        db_cleaner::SchedulerStatus,
+        read_only::{ReadOnlyMode, ALLOWED_MUTATIONS, READ_ONLY_ERROR},
         tcp_server::AppState,
     },
 };
-use actix_web::{web, Error, HttpResponse};
+use actix_web::{web, Error, HttpMessage, HttpResponse, HttpResponseBuilder};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use juniper::{EmptySubscription, RootNode};
-use juniper_actix::{graphiql_handler, graphql_handler, playground_handler};
+use juniper::{
+    http::{GraphQLBatchRequest, GraphQLBatchResponse, GraphQLRequest},
+    InputValue, RootNode,
+};
+use juniper_actix::{graphiql_handler, playground_handler};
+use juniper_graphql_ws::ConnectionConfig;
+use serde::Deserialize;
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::warn;
 
-use super::{mutation::Mutation, query::Query};
+use super::{
+    limits::{check_query_limits, mutation_fields, uses_introspection},
+    loader::UserGroupsLoader,
+    mutation::Mutation,
+    permissions::{self, FieldPermissions},
+    persisted_queries::PersistedQueries,
+    query::Query,
+    subscription::Subscription,
+    websocket::{subscriptions_handler, OperationCheck},
+};
 
 pub struct Context<Handler: BackendHandler> {
     pub handler: Box<Handler>,
     pub validation_result: ValidationResults,
+    /// To send the invitation emails.
+    pub mail_options: MailOptions,
+    pub server_url: String,
+    /// To report the runs of the DB cleanup.
+    pub scheduler_status: SchedulerStatus,
+    /// Batches the lookups of the users' groups within the request.
+    pub user_groups_loader: UserGroupsLoader,
+    pub avatar_options: AvatarOptions,
+    pub read_only: ReadOnlyMode,
 }
 
 impl<Handler: BackendHandler> juniper::Context for Context<Handler> {}
 
-type Schema<Handler> =
-    RootNode<'static, Query<Handler>, Mutation<Handler>, EmptySubscription<Context<Handler>>>;
+type Schema<Handler> = RootNode<'static, Query<Handler>, Mutation<Handler>, Subscription<Handler>>;
 
-fn schema<Handler: BackendHandler + Sync>() -> Schema<Handler> {
+fn schema<Handler: BackendHandler + OpaqueHandler + Sync>(
+    change_events: ChangeEventBus,
+) -> Schema<Handler> {
     Schema::new(
         Query::<Handler>::new(),
         Mutation::<Handler>::new(),
-        EmptySubscription::<Context<Handler>>::new(),
+        Subscription::<Handler>::new(change_events),
     )
 }
 
-pub fn export_schema(opts: ExportGraphQLSchemaOpts) -> anyhow::Result<()> {
+/// The schema language, with the permissions required by the fields of the root types as
+/// trailing comments.
+fn annotate_schema_language(
+    schema: &str,
+    permissions: &BTreeMap<&str, FieldPermissions>,
+) -> String {
+    let mut fields = None;
+    let mut in_description = false;
+    let mut output = String::with_capacity(schema.len());
+    for line in schema.lines() {
+        output.push_str(line);
+        if let Some(type_name) = line
+            .strip_prefix("type ")
+            .and_then(|rest| rest.strip_suffix(" {"))
+        {
+            fields = permissions.get(type_name);
+        } else if line == "}" {
+            fields = None;
+        } else if line.trim() == "\"\"\"" {
+            in_description = !in_description;
+        } else if let (Some(fields), false) = (fields, in_description) {
+            let field = line
+                .trim_start()
+                .split(|c: char| c == '(' || c == ':')
+                .next()
+                .unwrap_or_default();
+            if let Some(required) = fields.get(field) {
+                output.push_str(&format!(" # Requires: {}", permissions::describe(required)));
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Adds the permissions required by the fields of the root types to the result of the
+/// introspection query, as a `requiredPermissions` list next to their description.
+fn annotate_introspection(
+    introspection: &mut serde_json::Value,
+    permissions: &BTreeMap<&str, FieldPermissions>,
+) {
+    let types = introspection["__schema"]["types"].as_array_mut();
+    for graphql_type in types.into_iter().flatten() {
+        let fields = match graphql_type["name"]
+            .as_str()
+            .and_then(|name| permissions.get(name))
+        {
+            Some(fields) => fields,
+            None => continue,
+        };
+        for field in graphql_type["fields"].as_array_mut().into_iter().flatten() {
+            let required = field["name"].as_str().and_then(|name| fields.get(name));
+            if let Some(required) = required {
+                field["requiredPermissions"] =
+                    serde_json::json!(required.iter().map(|p| p.name()).collect::<Vec<_>>());
+            }
+        }
+    }
+}
+
+/// The result of the introspection query. The context is not used by the introspection, it
+/// doesn't connect to the database.
+fn introspection_json() -> anyhow::Result<serde_json::Value> {
+    use crate::{
+        domain::sql_backend_handler::SqlBackendHandler, infra::configuration::ConfigurationBuilder,
+    };
+    let context = Context::<SqlBackendHandler> {
+        handler: Box::new(SqlBackendHandler::new(
+            ConfigurationBuilder::without_server_setup(),
+            sea_orm::DatabaseConnection::Disconnected,
+        )),
+        validation_result: ValidationResults::admin(),
+        mail_options: MailOptions::default(),
+        server_url: String::new(),
+        scheduler_status: SchedulerStatus::default(),
+        user_groups_loader: UserGroupsLoader::default(),
+        avatar_options: AvatarOptions::default(),
+        read_only: ReadOnlyMode::default(),
+    };
+    let schema = schema::<SqlBackendHandler>(change_events::new_bus());
+    let (introspection, errors) =
+        juniper::introspect(&schema, &context, juniper::IntrospectionFormat::default())
+            .map_err(|e| anyhow::anyhow!("Introspection failed: {}", e))?;
+    if !errors.is_empty() {
+        anyhow::bail!("Introspection failed: {:?}", errors);
+    }
+    Ok(serde_json::to_value(&introspection)?)
+}
+
+fn schema_output(opts: &ExportGraphQLSchemaOpts) -> anyhow::Result<String> {
     use crate::domain::sql_backend_handler::SqlBackendHandler;
+    let permissions = permissions::root_field_permissions();
+    Ok(match opts.format {
+        SchemaFormat::Sdl => {
+            let output = schema::<SqlBackendHandler>(change_events::new_bus()).as_schema_language();
+            if opts.with_permissions {
+                annotate_schema_language(&output, &permissions)
+            } else {
+                output
+            }
+        }
+        SchemaFormat::Json => {
+            let mut introspection = introspection_json()?;
+            if opts.with_permissions {
+                annotate_introspection(&mut introspection, &permissions);
+            }
+            serde_json::to_string_pretty(&introspection)?
+        }
+    })
+}
+
+pub fn export_schema(opts: ExportGraphQLSchemaOpts) -> anyhow::Result<()> {
     use anyhow::Context;
-    let output = schema::<SqlBackendHandler>().as_schema_language();
+    let output = schema_output(&opts)?;
     match opts.output_file {
         None => println!("{}", output),
         Some(path) => {
@@ -58,24 +199,339 @@ async fn playground_route() -> Result<HttpResponse, Error> {
     playground_handler("/api/graphql", None).await
 }
 
-async fn graphql_route<Handler: BackendHandler + Sync>(
+#[derive(Deserialize)]
+struct QueryDocument {
+    query: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QueryDocuments {
+    Single(QueryDocument),
+    Batch(Vec<QueryDocument>),
+}
+
+/// The GraphQL documents of the request, from the query string of a GET or the body of a POST.
+fn get_query_documents(
+    req: &actix_web::HttpRequest,
+    body: &[u8],
+) -> std::result::Result<Vec<String>, String> {
+    if req.method() == actix_web::http::Method::GET {
+        return serde_urlencoded::from_str::<QueryDocument>(req.query_string())
+            .map(|document| vec![document.query])
+            .map_err(|e| format!("Invalid query string: {}", e));
+    }
+    if req.content_type() == "application/graphql" {
+        return String::from_utf8(body.to_vec())
+            .map(|document| vec![document])
+            .map_err(|_| "The query is not valid UTF-8".to_owned());
+    }
+    match serde_json::from_slice(body).map_err(|e| format!("Invalid request: {}", e))? {
+        QueryDocuments::Single(document) => Ok(vec![document.query]),
+        QueryDocuments::Batch(documents) => Ok(documents.into_iter().map(|d| d.query).collect()),
+    }
+}
+
+fn check_request_limits(
+    req: &actix_web::HttpRequest,
+    body: &[u8],
+    options: &GraphqlOptions,
+) -> std::result::Result<(), String> {
+    if options.max_depth == 0 && options.max_complexity == 0 {
+        return Ok(());
+    }
+    get_query_documents(req, body)?
+        .iter()
+        .try_for_each(|document| check_query_limits(document, options))
+}
+
+fn check_no_introspection(document: &str) -> std::result::Result<(), String> {
+    if uses_introspection(document)? {
+        return Err("The introspection is disabled".to_owned());
+    }
+    Ok(())
+}
+
+fn check_introspection(
+    req: &actix_web::HttpRequest,
+    body: &[u8],
+) -> std::result::Result<(), String> {
+    get_query_documents(req, body)?
+        .iter()
+        .try_for_each(|document| check_no_introspection(document))
+}
+
+/// Resolves the persisted queries, then checks the documents against the limits. Returns the body
+/// to execute.
+fn check_request(
+    req: &actix_web::HttpRequest,
+    body: web::Bytes,
+    options: &GraphqlOptions,
+    persisted_queries: &PersistedQueries,
+) -> std::result::Result<web::Bytes, String> {
+    let body = persisted_queries.resolve(req, body)?;
+    check_request_limits(req, &body, options)?;
+    if !options.introspection {
+        check_introspection(req, &body)?;
+    }
+    Ok(body)
+}
+
+fn check_no_mutations(document: &str) -> std::result::Result<(), String> {
+    if mutation_fields(document)?
+        .iter()
+        .any(|field| !ALLOWED_MUTATIONS.contains(&field.as_str()))
+    {
+        return Err(READ_ONLY_ERROR.to_owned());
+    }
+    Ok(())
+}
+
+/// In read-only mode, rejects the documents with mutations other than the allowed ones.
+fn check_read_only(req: &actix_web::HttpRequest, body: &[u8]) -> std::result::Result<(), String> {
+    get_query_documents(req, body)?
+        .iter()
+        .try_for_each(|document| check_no_mutations(document))
+}
+
+/// Checks the operations started on the WebSocket like the documents of the HTTP requests. The
+/// read-only mode is checked for each operation, since it can change during the connection.
+fn websocket_operation_check(
+    options: GraphqlOptions,
+    persisted_queries: PersistedQueries,
+    read_only: ReadOnlyMode,
+) -> OperationCheck {
+    Box::new(move |document| {
+        persisted_queries.check_allowed(document)?;
+        check_query_limits(document, &options)?;
+        if !options.introspection {
+            check_no_introspection(document)?;
+        }
+        if read_only.is_enabled() {
+            check_no_mutations(document)?;
+        }
+        Ok(())
+    })
+}
+
+#[derive(Deserialize)]
+struct GetGraphQLRequest {
+    query: String,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    /// As JSON.
+    variables: Option<String>,
+}
+
+/// The request to execute, from the query string of a GET or the body of a POST.
+fn parse_request(
+    req: &actix_web::HttpRequest,
+    body: &[u8],
+) -> std::result::Result<GraphQLBatchRequest, String> {
+    if req.method() == actix_web::http::Method::GET {
+        let request = serde_urlencoded::from_str::<GetGraphQLRequest>(req.query_string())
+            .map_err(|e| format!("Invalid query string: {}", e))?;
+        let variables = request
+            .variables
+            .map(|variables| serde_json::from_str::<InputValue>(&variables))
+            .transpose()
+            .map_err(|e| format!("Invalid variables: {}", e))?;
+        return Ok(GraphQLBatchRequest::Single(GraphQLRequest::new(
+            request.query,
+            request.operation_name,
+            variables,
+        )));
+    }
+    if req.content_type() == "application/graphql" {
+        return String::from_utf8(body.to_vec())
+            .map(|query| GraphQLBatchRequest::Single(GraphQLRequest::new(query, None, None)))
+            .map_err(|_| "The query is not valid UTF-8".to_owned());
+    }
+    serde_json::from_slice(body).map_err(|e| format!("Invalid request: {}", e))
+}
+
+/// Adds the correlation ID of the request to the extensions of the errors of the (possibly batch)
+/// response, to find the logs of the request.
+fn add_correlation_id(response: &mut serde_json::Value, correlation_id: &CorrelationId) {
+    let responses = match response {
+        serde_json::Value::Array(responses) => responses.iter_mut().collect(),
+        response => vec![response],
+    };
+    for error in responses
+        .into_iter()
+        .filter_map(|response| response.get_mut("errors"))
+        .filter_map(serde_json::Value::as_array_mut)
+        .flatten()
+        .filter_map(serde_json::Value::as_object_mut)
+    {
+        if let Some(extensions) = error
+            .entry("extensions")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+        {
+            extensions.insert(
+                "correlationId".to_owned(),
+                serde_json::Value::String(correlation_id.to_string()),
+            );
+        }
+    }
+}
+
+fn json_response(
+    mut response: HttpResponseBuilder,
+    mut body: serde_json::Value,
+    correlation_id: Option<&CorrelationId>,
+) -> HttpResponse {
+    if let Some(correlation_id) = correlation_id {
+        add_correlation_id(&mut body, correlation_id);
+    }
+    response.json(body)
+}
+
+fn error_response(
+    response: HttpResponseBuilder,
+    message: &str,
+    correlation_id: Option<&CorrelationId>,
+) -> HttpResponse {
+    json_response(
+        response,
+        serde_json::json!({ "errors": [{ "message": message }] }),
+        correlation_id,
+    )
+}
+
+fn graphql_response(
+    response: &GraphQLBatchResponse,
+    correlation_id: Option<&CorrelationId>,
+) -> Result<HttpResponse, Error> {
+    let status = if response.is_ok() {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::BadRequest()
+    };
+    let body =
+        serde_json::to_value(response).map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(json_response(status, body, correlation_id))
+}
+
+async fn graphql_route<Handler: BackendHandler + OpaqueHandler + Sync>(
+    req: actix_web::HttpRequest,
+    mut payload: actix_web::web::Payload,
+    data: web::Data<AppState<Handler>>,
+    read_only: web::Data<ReadOnlyMode>,
+) -> Result<HttpResponse, Error> {
+    use actix_web::FromRequest;
+    let timer = data.metrics.graphql_duration().start_timer();
+    // Set with the root span of the request.
+    let correlation_id = req.extensions().get::<CorrelationId>().cloned();
+    let correlation_id = correlation_id.as_ref();
+    let bearer = BearerAuth::from_request(&req, &mut payload.0).await?;
+    let validation_result = check_if_bearer_is_valid(&data, bearer.token()).await?;
+    // The body is read to check the queries before they are executed, then replayed.
+    let body = web::Bytes::from_request(&req, &mut payload.0).await?;
+    let body = match check_request(&req, body, &data.graphql_options, &data.persisted_queries) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Rejected a GraphQL request: {}", e);
+            timer.observe_duration();
+            return Ok(error_response(
+                HttpResponse::BadRequest(),
+                &e,
+                correlation_id,
+            ));
+        }
+    };
+    if read_only.is_enabled() {
+        if let Err(e) = check_read_only(&req, &body) {
+            timer.observe_duration();
+            return Ok(error_response(
+                HttpResponse::ServiceUnavailable(),
+                &e,
+                correlation_id,
+            ));
+        }
+    }
+    let request = match parse_request(&req, &body) {
+        Ok(request) => request,
+        Err(e) => {
+            timer.observe_duration();
+            return Ok(error_response(
+                HttpResponse::BadRequest(),
+                &e,
+                correlation_id,
+            ));
+        }
+    };
+    let context = Context::<Handler> {
+        handler: Box::new(data.backend_handler_for(&validation_result, AuditSource::Graphql)),
+        validation_result,
+        mail_options: data.mail_options(),
+        server_url: data.server_url.clone(),
+        scheduler_status: data.scheduler_status.clone(),
+        user_groups_loader: UserGroupsLoader::default(),
+        avatar_options: data.avatar_options.clone(),
+        read_only: read_only.get_ref().clone(),
+    };
+    let schema = schema(data.change_events.clone());
+    let execution = request.execute(&schema, &context);
+    let response = match data.graphql_timeout {
+        None => graphql_response(&execution.await, correlation_id),
+        Some(timeout) => match tokio::time::timeout(timeout, execution).await {
+            Ok(response) => graphql_response(&response, correlation_id),
+            Err(_) => {
+                warn!(
+                    "The GraphQL request took longer than {:?}, interrupting it",
+                    timeout
+                );
+                Ok(error_response(
+                    HttpResponse::ServiceUnavailable(),
+                    "The request timed out",
+                    correlation_id,
+                ))
+            }
+        },
+    };
+    timer.observe_duration();
+    response
+}
+
+async fn subscriptions_route<Handler: BackendHandler + OpaqueHandler + Sync + 'static>(
     req: actix_web::HttpRequest,
     mut payload: actix_web::web::Payload,
     data: web::Data<AppState<Handler>>,
+    read_only: web::Data<ReadOnlyMode>,
 ) -> Result<HttpResponse, Error> {
     use actix_web::FromRequest;
+    // Browsers can't set headers on WebSockets, but the token cookie is translated to a header.
     let bearer = BearerAuth::from_request(&req, &mut payload.0).await?;
-    let validation_result = check_if_token_is_valid(&data, bearer.token())?;
+    let validation_result = check_if_bearer_is_valid(&data, bearer.token()).await?;
     let context = Context::<Handler> {
         handler: Box::new(data.backend_handler.clone()),
         validation_result,
+        mail_options: data.mail_options(),
+        server_url: data.server_url.clone(),
+        scheduler_status: data.scheduler_status.clone(),
+        user_groups_loader: UserGroupsLoader::default(),
+        avatar_options: data.avatar_options.clone(),
+        read_only: read_only.get_ref().clone(),
     };
-    graphql_handler(&schema(), &context, req, payload).await
+    subscriptions_handler(
+        req,
+        payload,
+        Arc::new(schema(data.change_events.clone())),
+        ConnectionConfig::new(context),
+        websocket_operation_check(
+            data.graphql_options.clone(),
+            data.persisted_queries.clone(),
+            read_only.get_ref().clone(),
+        ),
+    )
+    .await
 }
 
 pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
 where
-    Backend: BackendHandler + Sync + 'static,
+    Backend: BackendHandler + OpaqueHandler + Sync + 'static,
 {
     let json_config = web::JsonConfig::default()
         .limit(4096)
@@ -96,6 +552,65 @@ where
             .route(web::post().to(graphql_route::<Backend>))
             .route(web::get().to(graphql_route::<Backend>)),
     );
+    cfg.service(
+        web::resource("/graphql/subscriptions")
+            .route(web::get().to(subscriptions_route::<Backend>)),
+    );
     cfg.service(web::resource("/graphql/playground").route(web::get().to(playground_route)));
     cfg.service(web::resource("/graphql/graphiql").route(web::get().to(graphiql_route)));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::graphql::persisted_queries::query_hash;
+
+    const CHANGES: &str = "subscription { changes { id event } }";
+
+    #[test]
+    fn test_websocket_operation_check() {
+        let options = GraphqlOptions {
+            max_depth: 2,
+            introspection: false,
+            ..Default::default()
+        };
+        let read_only = ReadOnlyMode::new(false);
+        let check = websocket_operation_check(
+            options.clone(),
+            PersistedQueries::default(),
+            read_only.clone(),
+        );
+        assert_eq!(check(CHANGES), Ok(()));
+        assert!(check("{ users { groups { id } } }").is_err());
+        assert!(check("{ __type(name: \"User\") { name } }").is_err());
+        let delete = "mutation { deleteUser(userId: \"bob\") { ok } }";
+        assert_eq!(check(delete), Ok(()));
+        // The mode is checked for each operation.
+        read_only.set(true);
+        assert_eq!(check(delete), Err(READ_ONLY_ERROR.to_owned()));
+        assert_eq!(
+            check("mutation { setReadOnlyMode(enabled: false) { ok } }"),
+            Ok(())
+        );
+
+        let file_name = std::env::temp_dir().join(format!(
+            "lldap_websocket_persisted_queries_test_{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &file_name,
+            serde_json::json!({ query_hash(CHANGES): CHANGES }).to_string(),
+        )
+        .unwrap();
+        let persisted_queries = PersistedQueries::new(&GraphqlOptions {
+            persisted_queries_file: Some(file_name.to_str().unwrap().to_owned()),
+            only_persisted_queries: true,
+            ..options.clone()
+        })
+        .unwrap();
+        std::fs::remove_file(&file_name).unwrap();
+        let check = websocket_operation_check(options, persisted_queries, read_only);
+        assert_eq!(check(CHANGES), Ok(()));
+        assert!(check("subscription { changes { id } }").is_err());
+    }
+}
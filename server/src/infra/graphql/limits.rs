@@ -0,0 +1,511 @@
+//! Static analysis of the GraphQL documents, to reject the deeply nested or very large queries
+//! before they are executed, the mutations in read-only mode and the introspection when it is
+//! disabled.
+
+use crate::infra::configuration::GraphqlOptions;
+use std::collections::HashMap;
+
+// Bounds the recursion of the analysis, whatever the configured limits.
+const MAX_NESTING: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Punctuator(char),
+    Spread,
+    Name(String),
+    // A string or a number, only found in the arguments and the default values.
+    Value,
+}
+
+fn tokenize(document: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = document.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            // Commas are insignificant, like white space.
+            ',' | '\u{feff}' => i += 1,
+            c if c.is_whitespace() => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' && chars[i] != '\r' {
+                    i += 1;
+                }
+            }
+            '.' => {
+                if chars.get(i..i + 3) != Some(&['.', '.', '.'][..]) {
+                    return Err("Unexpected '.'".to_owned());
+                }
+                tokens.push(Token::Spread);
+                i += 3;
+            }
+            '"' if chars.get(i..i + 3) == Some(&['"', '"', '"'][..]) => {
+                i += 3;
+                loop {
+                    match chars.get(i..i + 3) {
+                        None => return Err("Unterminated block string".to_owned()),
+                        Some(['"', '"', '"']) => break,
+                        Some(['\\', '"', '"']) => i += 4,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 3;
+                tokens.push(Token::Value);
+            }
+            '"' => {
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') | Some('\r') => {
+                            return Err("Unterminated string".to_owned())
+                        }
+                        Some('"') => break,
+                        Some('\\') => i += 2,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 1;
+                tokens.push(Token::Value);
+            }
+            '-' | '0'..='9' => {
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '.' | '+' | '-'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Value);
+            }
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let start = i;
+                while i < chars.len() && (chars[i] == '_' || chars[i].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+            }
+            '!' | '$' | '&' | '(' | ')' | ':' | '=' | '@' | '[' | ']' | '{' | '|' | '}' => {
+                tokens.push(Token::Punctuator(c));
+                i += 1;
+            }
+            c => return Err(format!("Unexpected character '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+enum Selection {
+    /// The name of the field, not its alias, and its selections.
+    Field(String, Vec<Selection>),
+    FragmentSpread(String),
+    InlineFragment(Vec<Selection>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    nesting: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "Unexpected end of the document".to_owned())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_is(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punctuator(c))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punctuator(p) if p == c => Ok(()),
+            token => Err(format!("Expected '{}', got {:?}", c, token)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            token => Err(format!("Expected a name, got {:?}", token)),
+        }
+    }
+
+    /// Skips the arguments or the variable definitions, if any.
+    fn skip_parentheses(&mut self) -> Result<(), String> {
+        if !self.next_is('(') {
+            return Ok(());
+        }
+        let mut depth = 0;
+        loop {
+            match self.next()? {
+                Token::Punctuator('(') => depth += 1,
+                Token::Punctuator(')') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn skip_directives(&mut self) -> Result<(), String> {
+        while self.next_is('@') {
+            self.next()?;
+            self.name()?;
+            self.skip_parentheses()?;
+        }
+        Ok(())
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Selection>, String> {
+        self.expect('{')?;
+        self.nesting += 1;
+        if self.nesting > MAX_NESTING {
+            return Err("The selections are nested too deeply".to_owned());
+        }
+        let mut selections = Vec::new();
+        while !self.next_is('}') {
+            selections.push(match self.next()? {
+                Token::Spread => match self.peek() {
+                    Some(Token::Name(name)) if name != "on" => {
+                        let name = self.name()?;
+                        self.skip_directives()?;
+                        Selection::FragmentSpread(name)
+                    }
+                    _ => {
+                        if self.peek() == Some(&Token::Name("on".to_owned())) {
+                            self.next()?;
+                            self.name()?;
+                        }
+                        self.skip_directives()?;
+                        Selection::InlineFragment(self.selection_set()?)
+                    }
+                },
+                Token::Name(mut name) => {
+                    if self.next_is(':') {
+                        self.next()?;
+                        name = self.name()?;
+                    }
+                    self.skip_parentheses()?;
+                    self.skip_directives()?;
+                    let selections = if self.next_is('{') {
+                        self.selection_set()?
+                    } else {
+                        Vec::new()
+                    };
+                    Selection::Field(name, selections)
+                }
+                token => return Err(format!("Expected a selection, got {:?}", token)),
+            });
+        }
+        self.expect('}')?;
+        self.nesting -= 1;
+        Ok(selections)
+    }
+}
+
+struct Operation {
+    is_mutation: bool,
+    selections: Vec<Selection>,
+}
+
+struct Document {
+    operations: Vec<Operation>,
+    fragments: HashMap<String, Vec<Selection>>,
+}
+
+fn parse(document: &str) -> Result<Document, String> {
+    let mut parser = Parser {
+        tokens: tokenize(document)?,
+        position: 0,
+        nesting: 0,
+    };
+    let mut operations = Vec::new();
+    let mut fragments = HashMap::new();
+    while let Some(token) = parser.peek() {
+        match token {
+            Token::Punctuator('{') => operations.push(Operation {
+                is_mutation: false,
+                selections: parser.selection_set()?,
+            }),
+            Token::Name(keyword) if keyword == "fragment" => {
+                parser.next()?;
+                let name = parser.name()?;
+                parser.name()?; // "on"
+                parser.name()?;
+                parser.skip_directives()?;
+                fragments.insert(name, parser.selection_set()?);
+            }
+            Token::Name(keyword) if ["query", "mutation", "subscription"].contains(&&**keyword) => {
+                let is_mutation = keyword == "mutation";
+                parser.next()?;
+                if let Some(Token::Name(_)) = parser.peek() {
+                    parser.next()?;
+                }
+                parser.skip_parentheses()?;
+                parser.skip_directives()?;
+                operations.push(Operation {
+                    is_mutation,
+                    selections: parser.selection_set()?,
+                });
+            }
+            token => return Err(format!("Expected a definition, got {:?}", token)),
+        }
+    }
+    Ok(Document {
+        operations,
+        fragments,
+    })
+}
+
+/// The depth and the number of fields of a selection set.
+#[derive(Clone, Copy, Default)]
+struct Cost {
+    depth: usize,
+    complexity: usize,
+}
+
+struct Analyzer<'a> {
+    fragments: &'a HashMap<String, Vec<Selection>>,
+    // The fragments are analyzed once, to avoid an exponential blow-up when they are nested.
+    fragment_costs: HashMap<&'a str, Cost>,
+    visiting: Vec<&'a str>,
+    // The current recursion depth, through the fields and the fragments.
+    nesting: usize,
+}
+
+impl<'a> Analyzer<'a> {
+    fn cost(&mut self, selections: &'a [Selection]) -> Result<Cost, String> {
+        self.nesting += 1;
+        if self.nesting > MAX_NESTING {
+            return Err("The fragments are nested too deeply".to_owned());
+        }
+        let mut total = Cost::default();
+        for selection in selections {
+            let cost = match selection {
+                Selection::Field(_, children) => {
+                    let children = self.cost(children)?;
+                    Cost {
+                        depth: children.depth + 1,
+                        complexity: children.complexity.saturating_add(1),
+                    }
+                }
+                Selection::InlineFragment(children) => self.cost(children)?,
+                Selection::FragmentSpread(name) => self.fragment_cost(name)?,
+            };
+            total.depth = total.depth.max(cost.depth);
+            total.complexity = total.complexity.saturating_add(cost.complexity);
+        }
+        self.nesting -= 1;
+        Ok(total)
+    }
+
+    fn fragment_cost(&mut self, name: &'a str) -> Result<Cost, String> {
+        if let Some(cost) = self.fragment_costs.get(name) {
+            return Ok(*cost);
+        }
+        if self.visiting.contains(&name) {
+            return Err(format!("The fragment '{}' spreads itself", name));
+        }
+        let fragments = self.fragments;
+        let selections = fragments
+            .get(name)
+            .ok_or_else(|| format!("Unknown fragment '{}'", name))?;
+        self.visiting.push(name);
+        let cost = self.cost(selections)?;
+        self.visiting.pop();
+        self.fragment_costs.insert(name, cost);
+        Ok(cost)
+    }
+}
+
+/// Checks the depth and the number of fields of each operation of the document against the
+/// limits, a limit of 0 meaning no limit. Documents that cannot be parsed are rejected.
+pub fn check_query_limits(document: &str, options: &GraphqlOptions) -> Result<(), String> {
+    if options.max_depth == 0 && options.max_complexity == 0 {
+        return Ok(());
+    }
+    let document = parse(document).map_err(|e| format!("Invalid query: {}", e))?;
+    let mut analyzer = Analyzer {
+        fragments: &document.fragments,
+        fragment_costs: HashMap::new(),
+        visiting: Vec::new(),
+        nesting: 0,
+    };
+    for operation in &document.operations {
+        let cost = analyzer.cost(&operation.selections)?;
+        if options.max_depth != 0 && cost.depth > options.max_depth {
+            return Err(format!(
+                "The query is nested {} levels deep, the limit is {}",
+                cost.depth, options.max_depth
+            ));
+        }
+        if options.max_complexity != 0 && cost.complexity > options.max_complexity {
+            return Err(format!(
+                "The query selects {} fields, the limit is {}",
+                cost.complexity, options.max_complexity
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn collect_root_fields(
+    selections: &[Selection],
+    fragments: &HashMap<String, Vec<Selection>>,
+    nesting: usize,
+    fields: &mut Vec<String>,
+) -> Result<(), String> {
+    if nesting > MAX_NESTING {
+        return Err("The fragments are nested too deeply".to_owned());
+    }
+    for selection in selections {
+        match selection {
+            Selection::Field(name, _) => fields.push(name.clone()),
+            Selection::InlineFragment(children) => {
+                collect_root_fields(children, fragments, nesting + 1, fields)?
+            }
+            Selection::FragmentSpread(name) => collect_root_fields(
+                fragments
+                    .get(name)
+                    .ok_or_else(|| format!("Unknown fragment '{}'", name))?,
+                fragments,
+                nesting + 1,
+                fields,
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// The fields selected at the root of the mutations of the document, through the fragments too.
+/// Documents that cannot be parsed are rejected.
+pub fn mutation_fields(document: &str) -> Result<Vec<String>, String> {
+    let document = parse(document).map_err(|e| format!("Invalid query: {}", e))?;
+    let mut fields = Vec::new();
+    for operation in document.operations.iter().filter(|o| o.is_mutation) {
+        collect_root_fields(&operation.selections, &document.fragments, 0, &mut fields)?;
+    }
+    Ok(fields)
+}
+
+fn has_introspection_field(selections: &[Selection]) -> bool {
+    selections.iter().any(|selection| match selection {
+        Selection::Field(name, children) => {
+            name == "__schema" || name == "__type" || has_introspection_field(children)
+        }
+        Selection::InlineFragment(children) => has_introspection_field(children),
+        Selection::FragmentSpread(_) => false,
+    })
+}
+
+/// Whether the document queries the schema, through `__schema` or `__type`. `__typename` is not
+/// introspection. Documents that cannot be parsed are rejected.
+pub fn uses_introspection(document: &str) -> Result<bool, String> {
+    let document = parse(document).map_err(|e| format!("Invalid query: {}", e))?;
+    Ok(document
+        .operations
+        .iter()
+        .map(|operation| &operation.selections)
+        .chain(document.fragments.values())
+        .any(|selections| has_introspection_field(selections)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::GraphqlOptionsBuilder;
+
+    fn options(max_depth: usize, max_complexity: usize) -> GraphqlOptions {
+        GraphqlOptionsBuilder::default()
+            .max_depth(max_depth)
+            .max_complexity(max_complexity)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_uses_introspection() {
+        assert!(uses_introspection("{ __schema { types { name } } }").unwrap());
+        assert!(uses_introspection(
+            "query { users { ...F } } fragment F on User { __type(name: \"User\") { name } }"
+        )
+        .unwrap());
+        assert!(!uses_introspection("{ users { __typename id } }").unwrap());
+        assert!(uses_introspection("{ users").is_err());
+    }
+
+    #[test]
+    fn test_check_query_limits_depth() {
+        let query = r#"
+            query GetUserDetails($id: String!) {
+              user(userId: $id) {
+                id # comment with { braces
+                displayName
+                groups { id displayName(arg: "string with }") }
+              }
+            }"#;
+        assert!(check_query_limits(query, &options(3, 0)).is_ok());
+        assert!(check_query_limits(query, &options(2, 0)).is_err());
+    }
+
+    #[test]
+    fn test_check_query_limits_complexity() {
+        let query = "{ users(filters: {eq: {field: \"id\", value: \"bob\"}}) { id email } }";
+        assert!(check_query_limits(query, &options(0, 3)).is_ok());
+        assert!(check_query_limits(query, &options(0, 2)).is_err());
+    }
+
+    #[test]
+    fn test_check_query_limits_fragments() {
+        let query = r#"
+            query { users { ...UserFields ... on User { id } } }
+            fragment UserFields on User { groups { users { ...Ids } } }
+            fragment Ids on User @include(if: true) { id }
+        "#;
+        assert!(check_query_limits(query, &options(4, 0)).is_ok());
+        assert!(check_query_limits(query, &options(3, 0)).is_err());
+        assert!(check_query_limits(query, &options(0, 5)).is_ok());
+        assert!(check_query_limits(query, &options(0, 4)).is_err());
+    }
+
+    #[test]
+    fn test_check_query_limits_invalid() {
+        assert!(check_query_limits("{ users { id }", &options(10, 0)).is_err());
+        assert!(
+            check_query_limits("{ ...A } fragment A on Query { ...A }", &options(10, 0)).is_err()
+        );
+        assert!(check_query_limits("{ users { id }", &options(0, 0)).is_ok());
+    }
+
+    #[test]
+    fn test_mutation_fields() {
+        assert_eq!(
+            mutation_fields("{ users { id } } query Q { groups { id } }").unwrap(),
+            Vec::<String>::new()
+        );
+        let document = r#"
+            mutation Update($id: String!) {
+              renamed: deleteUser(userId: $id) { ok }
+              ...Mutations
+            }
+            fragment Mutations on Mutation { ... on Mutation { setReadOnlyMode(enabled: false) { ok } } }
+        "#;
+        assert_eq!(
+            mutation_fields(document).unwrap(),
+            vec!["deleteUser".to_owned(), "setReadOnlyMode".to_owned()]
+        );
+        assert!(mutation_fields("mutation { deleteUser(userId: \"bob\") { ok }").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::domain::{
+    error::Result,
+    handler::BackendHandler,
+    types::{GroupDetails, UserId},
+};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+#[derive(Default)]
+struct State {
+    /// The users whose groups were requested, but not loaded yet.
+    pending: HashSet<UserId>,
+    /// The groups loaded for the pending users, removed once returned.
+    loaded: HashMap<UserId, HashSet<GroupDetails>>,
+}
+
+/// Loads the groups of the users of a list in a single query, instead of one query per user.
+///
+/// The fields of the users of a list are resolved concurrently: each resolver registers its user
+/// and yields, so that the first one to resume loads the groups of all the registered users.
+#[derive(Default)]
+pub struct UserGroupsLoader {
+    state: Mutex<State>,
+}
+
+impl UserGroupsLoader {
+    pub async fn load<Handler: BackendHandler>(
+        &self,
+        handler: &Handler,
+        user_id: &UserId,
+    ) -> Result<HashSet<GroupDetails>> {
+        self.state.lock().await.pending.insert(user_id.clone());
+        tokio::task::yield_now().await;
+        // Held during the query, so that the other resolvers wait for its result.
+        let mut state = self.state.lock().await;
+        if let Some(groups) = state.loaded.remove(user_id) {
+            return Ok(groups);
+        }
+        let mut user_ids: Vec<_> = state.pending.drain().collect();
+        if !user_ids.contains(user_id) {
+            user_ids.push(user_id.clone());
+        }
+        if let [single_user] = user_ids.as_slice() {
+            return handler.get_user_groups(single_user).await;
+        }
+        let mut users_groups = handler.get_users_groups(&user_ids).await?;
+        let groups = users_groups.remove(user_id).unwrap_or_default();
+        for other_user in user_ids.into_iter().filter(|u| u != user_id) {
+            let other_groups = users_groups.remove(&other_user).unwrap_or_default();
+            state.loaded.insert(other_user, other_groups);
+        }
+        Ok(groups)
+    }
+}
@@ -1,3 +1,9 @@
 pub mod api;
+pub mod limits;
+pub mod loader;
 pub mod mutation;
+pub mod permissions;
+pub mod persisted_queries;
 pub mod query;
+pub mod subscription;
+pub mod websocket;
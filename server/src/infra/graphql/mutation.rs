@@ -1,12 +1,34 @@
 use crate::domain::{
-    handler::{BackendHandler, CreateUserRequest, UpdateGroupRequest, UpdateUserRequest},
-    types::{GroupId, JpegPhoto, UserId},
+    error::DomainError,
+    handler::{
+        AuditEvent, BackendHandler, CreateApiTokenRequest, CreateInvitationRequest,
+        CreateOidcClientRequest, CreateRoleRequest, CreateServiceAccountRequest, CreateUserRequest,
+        DirectoryOperation, UpdateGroupRequest, UpdateServiceAccountRequest, UpdateUserRequest,
+        UserRequestFilter,
+    },
+    opaque_handler::OpaqueHandler,
+    sql_opaque_handler::{register_password, verify_password},
+    types::{
+        AttributeSchema, AttributeValue, AuditSource, GroupDetails, GroupId, JpegPhoto,
+        OidcGroupClaim, User, UserId,
+    },
 };
+use crate::infra::{avatar, configuration::AvatarOptions, import};
 use anyhow::Context as AnyhowContext;
-use juniper::{graphql_object, FieldResult, GraphQLInputObject, GraphQLObject};
-use tracing::{debug, debug_span, Instrument};
+use juniper::{
+    graphql_object, graphql_value, FieldError, FieldResult, GraphQLEnum, GraphQLInputObject,
+    GraphQLObject,
+};
+use secstr::SecUtf8;
+use tracing::{debug, debug_span, error, Instrument};
+
+use super::{
+    api::Context,
+    query::{ApiToken, ApiTokenScope, AttributeType, OidcClient, Role, ServiceAccount},
+};
 
-use super::api::Context;
+/// How long the links of `sendPasswordResetEmail` are valid, for the users to onboard.
+const PASSWORD_RESET_EMAIL_VALIDITY_DAYS: i64 = 7;
 
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL mutation type.
@@ -32,6 +54,28 @@ pub struct CreateUserInput {
     last_name: Option<String>,
     // Base64 encoded JpegPhoto.
     avatar: Option<String>,
+    uid_number: Option<i32>,
+    gid_number: Option<i32>,
+    home_directory: Option<String>,
+    login_shell: Option<String>,
+    telephone_number: Option<String>,
+    mobile: Option<String>,
+    title: Option<String>,
+    department: Option<String>,
+    locality: Option<String>,
+    postal_address: Option<String>,
+    /// The preferred language, as a BCP 47 tag such as "fr-CA", used for the emails.
+    locale: Option<String>,
+    /// The IANA time zone, such as "Europe/Paris".
+    timezone: Option<String>,
+    /// The account can only be used after this date, if set.
+    valid_from: Option<chrono::DateTime<chrono::Utc>>,
+    /// The account can only be used before this date, if set.
+    valid_until: Option<chrono::DateTime<chrono::Utc>>,
+    attributes: Option<Vec<AttributeValueInput>>,
+    /// A user template of the configuration: the user joins its groups, and gets its gidNumber,
+    /// home directory and locale unless given.
+    template: Option<String>,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -44,6 +88,40 @@ pub struct UpdateUserInput {
     last_name: Option<String>,
     // Base64 encoded JpegPhoto.
     avatar: Option<String>,
+    uid_number: Option<i32>,
+    gid_number: Option<i32>,
+    home_directory: Option<String>,
+    login_shell: Option<String>,
+    telephone_number: Option<String>,
+    mobile: Option<String>,
+    title: Option<String>,
+    department: Option<String>,
+    locality: Option<String>,
+    postal_address: Option<String>,
+    /// The preferred language, as a BCP 47 tag such as "fr-CA", used for the emails.
+    locale: Option<String>,
+    /// The IANA time zone, such as "Europe/Paris".
+    timezone: Option<String>,
+    /// Custom attributes to set, replacing their previous value.
+    insert_attributes: Option<Vec<AttributeValueInput>>,
+    /// Names of the custom attributes to remove.
+    remove_attributes: Option<Vec<String>>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The value(s) of a custom user attribute.
+pub struct AttributeValueInput {
+    name: String,
+    value: Vec<String>,
+}
+
+impl From<AttributeValueInput> for AttributeValue {
+    fn from(attribute: AttributeValueInput) -> Self {
+        Self {
+            name: attribute.name,
+            value: attribute.value,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -51,21 +129,593 @@ pub struct UpdateUserInput {
 pub struct UpdateGroupInput {
     id: i32,
     display_name: Option<String>,
+    gid_number: Option<i32>,
+    /// An empty value removes the description.
+    description: Option<String>,
+    /// The address of the group, e.g. of its mailing list. An empty value removes it.
+    mail: Option<String>,
+    /// Custom attributes to set, replacing their previous value.
+    insert_attributes: Option<Vec<AttributeValueInput>>,
+    /// Names of the custom attributes to remove.
+    remove_attributes: Option<Vec<String>>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+pub struct MembershipInput {
+    user_id: String,
+    group_id: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+#[graphql(name = "DirectoryOperation")]
+/// A change of `applyChanges`. Exactly one of the fields must be set.
+pub struct DirectoryOperationInput {
+    create_user: Option<CreateUserInput>,
+    update_user: Option<UpdateUserInput>,
+    /// The name of the group to create.
+    create_group: Option<String>,
+    update_group: Option<UpdateGroupInput>,
+    add_user_to_group: Option<MembershipInput>,
+    remove_user_from_group: Option<MembershipInput>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+pub struct ApplyChangesResult {
+    /// The IDs of the groups created by the operations, in order.
+    created_group_ids: Vec<i32>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The capabilities granted by a new role.
+pub struct CreateRoleInput {
+    display_name: String,
+    /// Read access to all the users and groups.
+    read_all: Option<bool>,
+    /// Can change the password of the non-admin users.
+    reset_passwords: Option<bool>,
+    /// Can modify the non-admin users that are only in this group, and remove non-admin users from
+    /// it.
+    managed_group_id: Option<i32>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The details of a new client application of the OpenID Connect provider.
+pub struct CreateOidcClientInput {
+    /// The `client_id` configured in the application.
+    client_id: String,
+    display_name: String,
+    /// The only URIs the users can be sent back to after logging in.
+    redirect_uris: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The outcome of the creation of one user, in a bulk creation.
+pub struct UserCreationResult {
+    id: String,
+    ok: bool,
+    /// Why the user could not be created.
+    error: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+pub enum ImportFormat {
+    Ldif,
+    Csv,
+}
+
+impl From<ImportFormat> for import::ImportFormat {
+    fn from(format: ImportFormat) -> Self {
+        match format {
+            ImportFormat::Ldif => Self::Ldif,
+            ImportFormat::Csv => Self::Csv,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The names of the CSV columns holding the fields of the users, when they differ from the
+/// defaults ("id", "email", "display_name", "first_name", "last_name", "groups" and
+/// "password_hash").
+pub struct CsvColumnsInput {
+    user_id: Option<String>,
+    email: Option<String>,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    /// The names of the groups of the user, separated by ';'.
+    groups: Option<String>,
+    /// A password hash exported from another directory (bcrypt, argon2, SSHA...), checked on
+    /// the first LDAP bind.
+    password_hash: Option<String>,
+}
+
+impl From<CsvColumnsInput> for import::CsvColumns {
+    fn from(columns: CsvColumnsInput) -> Self {
+        let default = Self::default();
+        Self {
+            user_id: columns.user_id.unwrap_or(default.user_id),
+            email: columns.email.unwrap_or(default.email),
+            display_name: columns.display_name.unwrap_or(default.display_name),
+            first_name: columns.first_name.unwrap_or(default.first_name),
+            last_name: columns.last_name.unwrap_or(default.last_name),
+            groups: columns.groups.unwrap_or(default.groups),
+            password_hash: columns.password_hash.unwrap_or(default.password_hash),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// An entry of an imported file that could not be imported.
+pub struct ImportRowError {
+    line: i32,
+    /// The user ID, group name or DN of the entry.
+    id: String,
+    error: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The outcome of an import. In a dry run, what would have been created.
+pub struct ImportResult {
+    created_users: Vec<String>,
+    created_groups: Vec<String>,
+    added_memberships: i32,
+    errors: Vec<ImportRowError>,
+}
+
+impl From<import::ImportReport> for ImportResult {
+    fn from(report: import::ImportReport) -> Self {
+        Self {
+            created_users: report
+                .created_users
+                .into_iter()
+                .map(UserId::into_string)
+                .collect(),
+            created_groups: report.created_groups,
+            added_memberships: report.added_memberships as i32,
+            errors: report
+                .errors
+                .into_iter()
+                .map(|e| ImportRowError {
+                    line: e.line as i32,
+                    id: e.id,
+                    error: e.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A newly created API token.
+pub struct ApiTokenCreationResult {
+    token: ApiToken,
+    /// The value to use as a bearer token. It is not stored, and cannot be retrieved later.
+    secret: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A newly created service account.
+pub struct ServiceAccountCreationResult {
+    account: ServiceAccount,
+    /// The password to bind with. It is not stored, and cannot be retrieved later.
+    password: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A newly registered OpenID Connect client.
+pub struct OidcClientCreationResult {
+    client: OidcClient,
+    /// The `client_secret` to configure in the application. It cannot be retrieved later.
+    secret: String,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLObject)]
 pub struct Success {
+    /// For a dry run, whether the mutation would succeed.
     ok: bool,
+    /// For a dry run, the reasons the mutation would fail, then what it would do. Empty
+    /// otherwise.
+    report: Vec<String>,
 }
 
 impl Success {
     fn new() -> Self {
-        Self { ok: true }
+        Self {
+            ok: true,
+            report: Vec::new(),
+        }
+    }
+}
+
+/// What a mutation run with `dryRun` would do, and why it would fail. The checks that the
+/// mutation makes before writing anything, like the permissions, still return an error.
+#[derive(Default)]
+struct DryRunReport {
+    effects: Vec<String>,
+    failures: Vec<String>,
+}
+
+impl From<DryRunReport> for Success {
+    fn from(report: DryRunReport) -> Self {
+        Self {
+            ok: report.failures.is_empty(),
+            report: report.failures.into_iter().chain(report.effects).collect(),
+        }
+    }
+}
+
+/// Exposes the estimated strength of a refused weak password in the error extensions
+/// ("passwordScore" and "minPasswordScore"), for the frontend to show meaningful feedback.
+fn password_policy_error(error: DomainError) -> FieldError {
+    let extensions = match &error {
+        DomainError::WeakPassword {
+            score, min_score, ..
+        } => {
+            let (score, min_score) = (*score as i32, *min_score as i32);
+            graphql_value!({ "passwordScore": score, "minPasswordScore": min_score })
+        }
+        _ => return error.into(),
+    };
+    FieldError::new(error, extensions)
+}
+
+/// Checks that the key looks like an OpenSSH public key ("<type> <base64 key> [comment]"), and
+/// returns it trimmed.
+fn validate_ssh_public_key(key: &str) -> anyhow::Result<String> {
+    let key = key.trim();
+    let mut parts = key.split_whitespace();
+    let key_type = parts.next().unwrap_or_default();
+    let key_data = parts.next().unwrap_or_default();
+    // The key data starts with the key type, prefixed by its length.
+    let data_key_type = base64::decode(key_data).ok().and_then(|data| {
+        let length = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        data.get(4..length.checked_add(4)?).map(<[u8]>::to_vec)
+    });
+    if key.contains('\n')
+        || !(key_type.starts_with("ssh-")
+            || key_type.starts_with("ecdsa-")
+            || key_type.starts_with("sk-"))
+        || data_key_type.as_deref() != Some(key_type.as_bytes())
+    {
+        anyhow::bail!("Invalid SSH public key");
+    }
+    Ok(key.to_owned())
+}
+
+/// Decodes the base64 avatar, and converts it to a JPEG photo.
+fn decode_avatar(
+    avatar: Option<String>,
+    options: &AvatarOptions,
+) -> anyhow::Result<Option<JpegPhoto>> {
+    avatar
+        .map(base64::decode)
+        .transpose()
+        .context("Invalid base64 image")?
+        .map(|bytes| avatar::process_avatar(bytes, options))
+        .transpose()
+        .context("Provided image is not a valid JPEG, PNG, WebP or GIF image")
+}
+
+fn to_create_user_request(
+    user: CreateUserInput,
+    avatar_options: &AvatarOptions,
+) -> anyhow::Result<CreateUserRequest> {
+    let avatar = decode_avatar(user.avatar, avatar_options)?;
+    Ok(CreateUserRequest {
+        user_id: UserId::new(&user.id),
+        email: user.email,
+        display_name: user.display_name,
+        first_name: user.first_name,
+        last_name: user.last_name,
+        avatar,
+        uid_number: user.uid_number,
+        gid_number: user.gid_number,
+        home_directory: user.home_directory,
+        login_shell: user.login_shell,
+        telephone_number: user.telephone_number,
+        mobile: user.mobile,
+        title: user.title,
+        department: user.department,
+        locality: user.locality,
+        postal_address: user.postal_address,
+        locale: user.locale,
+        timezone: user.timezone,
+        valid_from: user.valid_from,
+        valid_until: user.valid_until,
+        attributes: user
+            .attributes
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        legacy_password_hash: None,
+        template: user.template,
+    })
+}
+
+fn to_update_user_request(
+    user: UpdateUserInput,
+    avatar_options: &AvatarOptions,
+) -> anyhow::Result<UpdateUserRequest> {
+    let avatar = decode_avatar(user.avatar, avatar_options)?;
+    Ok(UpdateUserRequest {
+        user_id: UserId::new(&user.id),
+        email: user.email,
+        display_name: user.display_name,
+        first_name: user.first_name,
+        last_name: user.last_name,
+        avatar,
+        uid_number: user.uid_number,
+        gid_number: user.gid_number,
+        home_directory: user.home_directory,
+        login_shell: user.login_shell,
+        telephone_number: user.telephone_number,
+        mobile: user.mobile,
+        title: user.title,
+        department: user.department,
+        locality: user.locality,
+        postal_address: user.postal_address,
+        locale: user.locale,
+        timezone: user.timezone,
+        ssh_public_keys: None,
+        secondary_emails: None,
+        enabled: None,
+        valid_from: None,
+        valid_until: None,
+        insert_attributes: user
+            .insert_attributes
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        delete_attributes: user.remove_attributes.unwrap_or_default(),
+    })
+}
+
+fn to_update_group_request(group: UpdateGroupInput) -> UpdateGroupRequest {
+    UpdateGroupRequest {
+        group_id: GroupId(group.id),
+        display_name: group.display_name,
+        gid_number: group.gid_number,
+        description: group.description,
+        mail: group.mail,
+        insert_attributes: group
+            .insert_attributes
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        delete_attributes: group.remove_attributes.unwrap_or_default(),
+    }
+}
+
+fn to_directory_operation<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    operation: DirectoryOperationInput,
+) -> anyhow::Result<DirectoryOperation> {
+    let DirectoryOperationInput {
+        create_user,
+        update_user,
+        create_group,
+        update_group,
+        add_user_to_group,
+        remove_user_from_group,
+    } = operation;
+    let set_fields = [
+        create_user.is_some(),
+        update_user.is_some(),
+        create_group.is_some(),
+        update_group.is_some(),
+        add_user_to_group.is_some(),
+        remove_user_from_group.is_some(),
+    ]
+    .iter()
+    .filter(|set| **set)
+    .count();
+    if set_fields > 1 {
+        anyhow::bail!("Only one field of the operation can be set");
+    }
+    if let Some(user) = create_user {
+        context.handler.check_user_id(&user.id)?;
+        return Ok(DirectoryOperation::CreateUser(to_create_user_request(
+            user,
+            &context.avatar_options,
+        )?));
+    }
+    if let Some(user) = update_user {
+        return Ok(DirectoryOperation::UpdateUser(to_update_user_request(
+            user,
+            &context.avatar_options,
+        )?));
+    }
+    if let Some(name) = create_group {
+        return Ok(DirectoryOperation::CreateGroup(name));
+    }
+    if let Some(group) = update_group {
+        if group.id == 1 {
+            anyhow::bail!("Cannot change admin group details");
+        }
+        return Ok(DirectoryOperation::UpdateGroup(to_update_group_request(
+            group,
+        )));
+    }
+    let (membership, added) = match (add_user_to_group, remove_user_from_group) {
+        (Some(membership), _) => (membership, true),
+        (None, Some(membership)) => (membership, false),
+        (None, None) => anyhow::bail!("One field of the operation must be set"),
+    };
+    let user_id = UserId::new(&membership.user_id);
+    let group_id = GroupId(membership.group_id);
+    if !added && context.validation_result.user == user_id && group_id == GroupId(1) {
+        anyhow::bail!("Cannot remove admin rights for current user");
+    }
+    Ok(if added {
+        DirectoryOperation::AddUserToGroup { user_id, group_id }
+    } else {
+        DirectoryOperation::RemoveUserFromGroup { user_id, group_id }
+    })
+}
+
+// Why only the admins can make the update, if it changes their fields.
+fn admin_only_user_update(user: &UpdateUserInput) -> Option<&'static str> {
+    let changes_posix_attributes = user.uid_number.is_some()
+        || user.gid_number.is_some()
+        || user.home_directory.is_some()
+        || user.login_shell.is_some();
+    if changes_posix_attributes {
+        return Some("Only admins can update POSIX attributes");
+    }
+    if user.insert_attributes.is_some() || user.remove_attributes.is_some() {
+        return Some("Only admins can update custom attributes");
+    }
+    None
+}
+
+// Admins can manage all the users, user managers and the roles managing a group only the ones
+// that are not admins.
+async fn can_manage_user<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_id: &UserId,
+) -> FieldResult<bool> {
+    if context.validation_result.is_admin() {
+        return Ok(true);
+    }
+    if !context.validation_result.can_manage_any_user() {
+        return Ok(false);
+    }
+    let user_groups = context
+        .handler
+        .get_user_groups_from_primary(user_id)
+        .await?;
+    Ok(context.validation_result.can_manage_user(&user_groups))
+}
+
+/// The user, or `None` after reporting that it doesn't exist.
+async fn get_reported_user<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_id: &UserId,
+    report: &mut DryRunReport,
+) -> FieldResult<Option<User>> {
+    match context.handler.get_user_details(user_id).await {
+        Ok(user) => Ok(Some(user)),
+        Err(DomainError::EntityNotFound(_)) => {
+            report
+                .failures
+                .push(format!("User '{}' does not exist", user_id));
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The group, or `None` after reporting that it doesn't exist.
+async fn get_reported_group<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group_id: GroupId,
+    report: &mut DryRunReport,
+) -> FieldResult<Option<GroupDetails>> {
+    match context.handler.get_group_details(group_id).await {
+        Ok(group) => Ok(Some(group)),
+        Err(DomainError::EntityNotFound(_)) => {
+            report
+                .failures
+                .push(format!("Group {} does not exist", group_id.0));
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn get_group_members<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group_id: GroupId,
+) -> FieldResult<Vec<UserId>> {
+    Ok(context
+        .handler
+        .list_users(Some(UserRequestFilter::MemberOfId(group_id)), false)
+        .await?
+        .into_iter()
+        .map(|u| u.user.user_id)
+        .collect())
+}
+
+/// Reports the groups that would be left without members once the users are removed from them.
+async fn report_emptied_groups<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    groups: &[GroupDetails],
+    removed_users: &[UserId],
+    report: &mut DryRunReport,
+) -> FieldResult<()> {
+    for group in groups {
+        let members = get_group_members(context, group.group_id).await?;
+        if !members.iter().all(|m| removed_users.contains(m)) {
+            continue;
+        }
+        let message = format!(
+            "Group '{}' would be left without members",
+            group.display_name
+        );
+        // The backend refuses to remove the last admin.
+        if group.group_id == GroupId(1) && !members.is_empty() {
+            report.failures.push(message);
+        } else {
+            report.effects.push(message);
+        }
     }
+    Ok(())
+}
+
+async fn report_users_deletion<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_ids: &[UserId],
+) -> FieldResult<DryRunReport> {
+    let mut report = DryRunReport::default();
+    let mut groups = Vec::<GroupDetails>::new();
+    for user_id in user_ids {
+        if get_reported_user(context, user_id, &mut report)
+            .await?
+            .is_none()
+        {
+            continue;
+        }
+        report.effects.push(format!(
+            "User '{}' would be moved to the recycle bin",
+            user_id
+        ));
+        for group in context.handler.get_user_groups(user_id).await? {
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+    }
+    groups.sort_by_key(|g| g.group_id);
+    report_emptied_groups(context, &groups, user_ids, &mut report).await?;
+    Ok(report)
+}
+
+async fn can_manage_membership<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_id: &UserId,
+    group_id: GroupId,
+) -> FieldResult<bool> {
+    if context.validation_result.is_admin() {
+        return Ok(true);
+    }
+    // Only admins can grant or revoke admin rights.
+    if group_id == GroupId(1) || !context.validation_result.can_manage_any_user() {
+        return Ok(false);
+    }
+    let user_groups = context
+        .handler
+        .get_user_groups_from_primary(user_id)
+        .await?;
+    Ok(context
+        .validation_result
+        .can_manage_membership(group_id, &user_groups))
 }
 
 #[graphql_object(context = Context<Handler>)]
-impl<Handler: BackendHandler + Sync> Mutation<Handler> {
+impl<Handler: BackendHandler + OpaqueHandler + Sync> Mutation<Handler> {
     async fn create_user(
         context: &Context<Handler>,
         user: CreateUserInput,
@@ -74,29 +724,16 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         span.in_scope(|| {
             debug!(?user.id);
         });
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_users() {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized user creation".into());
         }
-        let user_id = UserId::new(&user.id);
-        let avatar = user
-            .avatar
-            .map(base64::decode)
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
-        context
-            .handler
-            .create_user(CreateUserRequest {
-                user_id: user_id.clone(),
-                email: user.email,
-                display_name: user.display_name,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                avatar,
-            })
+        context.handler.check_user_id(&user.id)?;
+        let request = to_create_user_request(user, &context.avatar_options)?;
+        let user_id = request.user_id.clone();
+        context
+            .handler
+            .create_user(request)
             .instrument(span.clone())
             .await?;
         Ok(context
@@ -107,6 +744,156 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             .map(Into::into)?)
     }
 
+    /// Creates the users in a single transaction. A failure only prevents the creation of the
+    /// corresponding user, and is reported in its result.
+    async fn create_users(
+        context: &Context<Handler>,
+        users: Vec<CreateUserInput>,
+    ) -> FieldResult<Vec<UserCreationResult>> {
+        let span = debug_span!("[GraphQL mutation] create_users");
+        span.in_scope(|| {
+            debug!(count = users.len());
+        });
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user creation".into());
+        }
+        let mut results: Vec<_> = users
+            .into_iter()
+            .map(|user| {
+                let id = user.id.clone();
+                let request = context
+                    .handler
+                    .check_user_id(&id)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|()| to_create_user_request(user, &context.avatar_options));
+                (id, request)
+            })
+            .collect();
+        let requests: Vec<_> = results
+            .iter_mut()
+            .filter_map(|(_, request)| match request {
+                Ok(request) => Some(std::mem::take(request)),
+                Err(_) => None,
+            })
+            .collect();
+        let mut creations = context
+            .handler
+            .create_users(requests)
+            .instrument(span)
+            .await?
+            .into_iter();
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(id, request)| {
+                let error = match request {
+                    Err(e) => Some(format!("{:#}", e)),
+                    Ok(_) => match creations.next() {
+                        Some(Ok(())) => None,
+                        Some(Err(e)) => Some(e.to_string()),
+                        None => Some("Missing creation result".to_string()),
+                    },
+                };
+                UserCreationResult {
+                    id,
+                    ok: error.is_none(),
+                    error,
+                }
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// Creates the users, groups and memberships of an LDIF or CSV file, skipping the existing
+    /// ones. The entries that can't be imported are reported without stopping the import. With
+    /// `dryRun`, nothing is written.
+    async fn import_directory(
+        context: &Context<Handler>,
+        content: String,
+        format: ImportFormat,
+        dry_run: Option<bool>,
+        csv_columns: Option<CsvColumnsInput>,
+    ) -> FieldResult<ImportResult> {
+        let span = debug_span!("[GraphQL mutation] import_directory");
+        span.in_scope(|| {
+            debug!(?format, ?dry_run);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized import".into());
+        }
+        let dry_run = dry_run.unwrap_or(false);
+        let data = import::read(
+            &content,
+            format.into(),
+            &csv_columns.map(Into::into).unwrap_or_default(),
+        )?;
+        let report = import::import(
+            context.handler.as_ref(),
+            data,
+            import::DEFAULT_BATCH_SIZE,
+            dry_run,
+        )
+        .instrument(span)
+        .await?;
+        Ok(report.into())
+    }
+
+    /// Applies the operations in order, in a single transaction: if one of them fails, none of
+    /// them is applied, and the error gives the index of the failing one. Each operation requires
+    /// the same permissions as the corresponding mutation.
+    async fn apply_changes(
+        context: &Context<Handler>,
+        operations: Vec<DirectoryOperationInput>,
+    ) -> FieldResult<ApplyChangesResult> {
+        let span = debug_span!("[GraphQL mutation] apply_changes");
+        span.in_scope(|| {
+            debug!(count = operations.len());
+        });
+        let mut requests = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.into_iter().enumerate() {
+            // Each operation requires the same permissions as the corresponding mutation.
+            let denial = if let Some(user) = &operation.update_user {
+                let user_id = UserId::new(&user.id);
+                if !context.validation_result.can_write(&user_id)
+                    && !can_manage_user(context, &user_id).await?
+                {
+                    Some("Unauthorized user update")
+                } else {
+                    admin_only_user_update(user).filter(|_| !context.validation_result.is_admin())
+                }
+            } else if let Some(membership) = operation
+                .add_user_to_group
+                .as_ref()
+                .or(operation.remove_user_from_group.as_ref())
+            {
+                let user_id = UserId::new(&membership.user_id);
+                (!can_manage_membership(context, &user_id, GroupId(membership.group_id)).await?)
+                    .then_some("Unauthorized group membership modification")
+            } else if operation.create_user.is_some() {
+                (!context.validation_result.can_manage_users())
+                    .then_some("Unauthorized user creation")
+            } else {
+                (!context.validation_result.is_admin()).then_some("Unauthorized group modification")
+            };
+            if let Some(error) = denial {
+                span.in_scope(|| debug!(index, error, "Unauthorized"));
+                return Err(format!("Operation {}: {}", index, error).into());
+            }
+            let request = to_directory_operation(context, operation)
+                .map_err(|e| format!("Operation {}: {:#}", index, e))?;
+            requests.push(request);
+        }
+        let group_ids = context
+            .handler
+            .apply_changes(requests)
+            .instrument(span)
+            .await?;
+        Ok(ApplyChangesResult {
+            created_group_ids: group_ids.into_iter().map(|g| g.0).collect(),
+        })
+    }
+
     async fn create_group(
         context: &Context<Handler>,
         name: String,
@@ -137,28 +924,55 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             debug!(?user.id);
         });
         let user_id = UserId::new(&user.id);
-        if !context.validation_result.can_write(&user_id) {
+        if !context.validation_result.can_write(&user_id)
+            && !can_manage_user(context, &user_id).await?
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized user update".into());
         }
-        let avatar = user
-            .avatar
-            .map(base64::decode)
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
+        if let Some(error) =
+            admin_only_user_update(&user).filter(|_| !context.validation_result.is_admin())
+        {
+            span.in_scope(|| debug!(error, "Unauthorized"));
+            return Err(error.into());
+        }
+        let request = to_update_user_request(user, &context.avatar_options)?;
         context
             .handler
-            .update_user(UpdateUserRequest {
-                user_id,
-                email: user.email,
-                display_name: user.display_name,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                avatar,
-            })
+            .update_user(request)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Renames the user, keeping their password, attributes, memberships and sessions.
+    async fn update_user_id(
+        context: &Context<Handler>,
+        old_id: String,
+        new_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] update_user_id");
+        span.in_scope(|| {
+            debug!(?old_id, ?new_id);
+        });
+        let old_id = UserId::new(&old_id);
+        let new_id_input = new_id;
+        let new_id = UserId::new(&new_id_input);
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user rename".into());
+        }
+        if context.validation_result.user == old_id {
+            span.in_scope(|| debug!("Cannot rename current user"));
+            return Err("Cannot rename current user".into());
+        }
+        if new_id.as_str().is_empty() {
+            return Err("The new user ID cannot be empty".into());
+        }
+        context.handler.check_user_id(&new_id_input)?;
+        context
+            .handler
+            .rename_user(&old_id, &new_id)
             .instrument(span)
             .await?;
         Ok(Success::new())
@@ -180,35 +994,51 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             span.in_scope(|| debug!("Cannot change admin group details"));
             return Err("Cannot change admin group details".into());
         }
+        let group_id = GroupId(group.id);
         context
             .handler
-            .update_group(UpdateGroupRequest {
-                group_id: GroupId(group.id),
-                display_name: group.display_name,
-            })
+            .update_group(to_update_group_request(group))
             .instrument(span)
             .await?;
         Ok(Success::new())
     }
 
+    /// Adds the user to the group. If `expiresAt` is set, the membership is temporary and gets
+    /// removed automatically after that date.
     async fn add_user_to_group(
         context: &Context<Handler>,
         user_id: String,
         group_id: i32,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_user_to_group");
         span.in_scope(|| {
-            debug!(?user_id, ?group_id);
+            debug!(?user_id, ?group_id, ?expires_at);
         });
-        if !context.validation_result.is_admin() {
+        let user_id = UserId::new(&user_id);
+        if !can_manage_membership(context, &user_id, GroupId(group_id)).await? {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized group membership modification".into());
         }
-        context
-            .handler
-            .add_user_to_group(&UserId::new(&user_id), GroupId(group_id))
-            .instrument(span)
-            .await?;
+        match expires_at {
+            None => {
+                context
+                    .handler
+                    .add_user_to_group(&user_id, GroupId(group_id))
+                    .instrument(span)
+                    .await?
+            }
+            Some(expiry_date) => {
+                if expiry_date <= chrono::Utc::now() {
+                    return Err("The expiry date must be in the future".into());
+                }
+                context
+                    .handler
+                    .add_user_to_group_until(&user_id, GroupId(group_id), expiry_date)
+                    .instrument(span)
+                    .await?
+            }
+        }
         Ok(Success::new())
     }
 
@@ -221,11 +1051,11 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         span.in_scope(|| {
             debug!(?user_id, ?group_id);
         });
-        if !context.validation_result.is_admin() {
+        let user_id = UserId::new(&user_id);
+        if !can_manage_membership(context, &user_id, GroupId(group_id)).await? {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized group membership modification".into());
         }
-        let user_id = UserId::new(&user_id);
         if context.validation_result.user == user_id && group_id == 1 {
             span.in_scope(|| debug!("Cannot remove admin rights for current user"));
             return Err("Cannot remove admin rights for current user".into());
@@ -238,46 +1068,1896 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         Ok(Success::new())
     }
 
-    async fn delete_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
-        let span = debug_span!("[GraphQL mutation] delete_user");
+    /// Adds all the users to the group, or none of them if one of the memberships cannot be added.
+    async fn add_users_to_group(
+        context: &Context<Handler>,
+        user_ids: Vec<String>,
+        group_id: i32,
+        dry_run: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_users_to_group");
         span.in_scope(|| {
-            debug!(?user_id);
+            debug!(?user_ids, ?group_id, ?dry_run);
         });
-        let user_id = UserId::new(&user_id);
-        if !context.validation_result.is_admin() {
-            span.in_scope(|| debug!("Unauthorized"));
-            return Err("Unauthorized user deletion".into());
+        let user_ids: Vec<_> = user_ids.iter().map(|id| UserId::new(id)).collect();
+        for user_id in &user_ids {
+            if !can_manage_membership(context, user_id, GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+            {
+                span.in_scope(|| debug!(?user_id, "Unauthorized"));
+                return Err("Unauthorized group membership modification".into());
+            }
         }
-        if context.validation_result.user == user_id {
-            span.in_scope(|| debug!("Cannot delete current user"));
-            return Err("Cannot delete current user".into());
+        if dry_run.unwrap_or(false) {
+            let mut report = DryRunReport::default();
+            let group = match get_reported_group(context, GroupId(group_id), &mut report)
+                .instrument(span.clone())
+                .await?
+            {
+                Some(group) => group,
+                None => return Ok(report.into()),
+            };
+            let members = get_group_members(context, group.group_id)
+                .instrument(span.clone())
+                .await?;
+            for user_id in &user_ids {
+                if get_reported_user(context, user_id, &mut report)
+                    .instrument(span.clone())
+                    .await?
+                    .is_none()
+                {
+                    continue;
+                }
+                if members.contains(user_id) {
+                    report.failures.push(format!(
+                        "User '{}' is already a member of group '{}'",
+                        user_id, group.display_name
+                    ));
+                } else {
+                    report.effects.push(format!(
+                        "User '{}' would be added to group '{}'",
+                        user_id, group.display_name
+                    ));
+                }
+            }
+            return Ok(report.into());
         }
         context
             .handler
-            .delete_user(&user_id)
+            .add_users_to_group(&user_ids, GroupId(group_id))
             .instrument(span)
             .await?;
         Ok(Success::new())
     }
 
-    async fn delete_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
-        let span = debug_span!("[GraphQL mutation] delete_group");
+    /// Removes all the users from the group, or none of them if one of them is not a member.
+    async fn remove_users_from_group(
+        context: &Context<Handler>,
+        user_ids: Vec<String>,
+        group_id: i32,
+        dry_run: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_users_from_group");
         span.in_scope(|| {
-            debug!(?group_id);
+            debug!(?user_ids, ?group_id, ?dry_run);
         });
-        if !context.validation_result.is_admin() {
-            span.in_scope(|| debug!("Unauthorized"));
-            return Err("Unauthorized group deletion".into());
+        let user_ids: Vec<_> = user_ids.iter().map(|id| UserId::new(id)).collect();
+        for user_id in &user_ids {
+            if !can_manage_membership(context, user_id, GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+            {
+                span.in_scope(|| debug!(?user_id, "Unauthorized"));
+                return Err("Unauthorized group membership modification".into());
+            }
         }
-        if group_id == 1 {
-            span.in_scope(|| debug!("Cannot delete admin group"));
-            return Err("Cannot delete admin group".into());
+        if group_id == 1 && user_ids.contains(&context.validation_result.user) {
+            span.in_scope(|| debug!("Cannot remove admin rights for current user"));
+            return Err("Cannot remove admin rights for current user".into());
+        }
+        if dry_run.unwrap_or(false) {
+            let mut report = DryRunReport::default();
+            let group = match get_reported_group(context, GroupId(group_id), &mut report)
+                .instrument(span.clone())
+                .await?
+            {
+                Some(group) => group,
+                None => return Ok(report.into()),
+            };
+            let members = get_group_members(context, group.group_id)
+                .instrument(span.clone())
+                .await?;
+            for user_id in &user_ids {
+                if members.contains(user_id) {
+                    report.effects.push(format!(
+                        "User '{}' would be removed from group '{}'",
+                        user_id, group.display_name
+                    ));
+                } else {
+                    report.failures.push(format!(
+                        "User '{}' is not a member of group '{}'",
+                        user_id, group.display_name
+                    ));
+                }
+            }
+            report_emptied_groups(context, &[group], &user_ids, &mut report)
+                .instrument(span)
+                .await?;
+            return Ok(report.into());
         }
         context
             .handler
-            .delete_group(GroupId(group_id))
+            .remove_users_from_group(&user_ids, GroupId(group_id))
             .instrument(span)
             .await?;
         Ok(Success::new())
     }
+
+    /// Sets the password of the user, running the OPAQUE registration on the server. Only
+    /// available to admins, and for the other non-admin users to the password managers and the
+    /// users managing them: the users change their own password with `changePassword`.
+    async fn reset_user_password(
+        context: &Context<Handler>,
+        user_id: String,
+        new_password: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] reset_user_password");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let user_groups = context
+            .handler
+            .get_user_groups_from_primary(&user_id)
+            .instrument(span.clone())
+            .await?;
+        if !context
+            .validation_result
+            .can_reset_password(&user_id, &user_groups)
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized password reset".into());
+        }
+        // Fail early if the user doesn't exist.
+        context
+            .handler
+            .get_user_details(&user_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .check_password_policy(&user_id, &new_password)
+            .instrument(span.clone())
+            .await
+            .map_err(password_policy_error)?;
+        register_password(
+            context.handler.as_ref(),
+            &user_id,
+            &SecUtf8::from(new_password.clone()),
+        )
+        .instrument(span.clone())
+        .await?;
+        context
+            .handler
+            .add_password_to_history(&user_id, &new_password)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Emails the user a link to choose a new password, valid for a week, so that nobody needs to
+    /// know a temporary password. Same permissions as `resetUserPassword`.
+    async fn send_password_reset_email(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] send_password_reset_email");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let user_groups = context
+            .handler
+            .get_user_groups_from_primary(&user_id)
+            .instrument(span.clone())
+            .await?;
+        if !context
+            .validation_result
+            .can_reset_password(&user_id, &user_groups)
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized password reset".into());
+        }
+        let user = context
+            .handler
+            .get_user_details(&user_id)
+            .instrument(span.clone())
+            .await?;
+        let token = context
+            .handler
+            .create_password_reset_token(
+                &user_id,
+                chrono::Duration::days(PASSWORD_RESET_EMAIL_VALIDITY_DAYS),
+            )
+            .instrument(span.clone())
+            .await?;
+        if let Err(e) = crate::infra::mail::send_admin_password_reset_email(
+            user.display_name
+                .as_deref()
+                .unwrap_or_else(|| user.user_id.as_str()),
+            &user.email,
+            user.locale.as_deref(),
+            &token,
+            PASSWORD_RESET_EMAIL_VALIDITY_DAYS,
+            &context.server_url,
+            &context.mail_options,
+        )
+        .instrument(span.clone())
+        .await
+        {
+            span.in_scope(|| error!("Error sending the password reset email: {:#}", e));
+            return Err(format!("Could not send the password reset email: {}", e).into());
+        }
+        Ok(Success::new())
+    }
+
+    /// Changes the password of the current user, after checking their current password. Unlike
+    /// the OPAQUE registration, this doesn't rely only on the token of the user.
+    async fn change_password(
+        context: &Context<Handler>,
+        old_password: String,
+        new_password: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] change_password");
+        let user_id = context.validation_result.user.clone();
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        verify_password(
+            context.handler.as_ref(),
+            &user_id,
+            &SecUtf8::from(old_password),
+        )
+        .instrument(span.clone())
+        .await
+        .map_err(|e| -> FieldError {
+            match e {
+                DomainError::AuthenticationError(_) => "The current password is incorrect".into(),
+                e => e.into(),
+            }
+        })?;
+        context
+            .handler
+            .check_password_policy(&user_id, &new_password)
+            .instrument(span.clone())
+            .await
+            .map_err(password_policy_error)?;
+        register_password(
+            context.handler.as_ref(),
+            &user_id,
+            &SecUtf8::from(new_password.clone()),
+        )
+        .instrument(span.clone())
+        .await?;
+        context
+            .handler
+            .add_password_to_history(&user_id, &new_password)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn add_group_to_group(
+        context: &Context<Handler>,
+        parent_group_id: i32,
+        child_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_to_group");
+        span.in_scope(|| {
+            debug!(?parent_group_id, ?child_group_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group membership modification".into());
+        }
+        context
+            .handler
+            .add_group_to_group(GroupId(parent_group_id), GroupId(child_group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_group_from_group(
+        context: &Context<Handler>,
+        parent_group_id: i32,
+        child_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_from_group");
+        span.in_scope(|| {
+            debug!(?parent_group_id, ?child_group_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group membership modification".into());
+        }
+        context
+            .handler
+            .remove_group_from_group(GroupId(parent_group_id), GroupId(child_group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn add_ssh_public_key(
+        context: &Context<Handler>,
+        user_id: String,
+        key: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_ssh_public_key");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized SSH key modification".into());
+        }
+        let key = validate_ssh_public_key(&key)?;
+        context
+            .handler
+            .add_ssh_public_key(&user_id, &key)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_ssh_public_key(
+        context: &Context<Handler>,
+        user_id: String,
+        key: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_ssh_public_key");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized SSH key modification".into());
+        }
+        context
+            .handler
+            .remove_ssh_public_key(&user_id, key.trim())
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Adds a secondary email address to the user, e.g. an alias of their mailbox. The addresses
+    /// are unique across the users. Only available to admins and the users managing the user, since
+    /// mail servers can deliver the mail sent to the alias to the user.
+    async fn add_user_email(
+        context: &Context<Handler>,
+        user_id: String,
+        email: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_user_email");
+        span.in_scope(|| {
+            debug!(?user_id, ?email);
+        });
+        let user_id = UserId::new(&user_id);
+        if !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized email address modification".into());
+        }
+        let user = context
+            .handler
+            .get_user_details(&user_id)
+            .instrument(span.clone())
+            .await?;
+        let mut emails = user.secondary_emails;
+        let email = email.trim().to_lowercase();
+        if emails.contains(&email) {
+            return Err("Email address already present".into());
+        }
+        emails.push(email);
+        context
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id,
+                secondary_emails: Some(emails),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_user_email(
+        context: &Context<Handler>,
+        user_id: String,
+        email: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_user_email");
+        span.in_scope(|| {
+            debug!(?user_id, ?email);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id)
+            && !can_manage_user(context, &user_id).await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized email address modification".into());
+        }
+        let user = context
+            .handler
+            .get_user_details(&user_id)
+            .instrument(span.clone())
+            .await?;
+        let mut emails = user.secondary_emails;
+        let email = email.trim().to_lowercase();
+        let num_emails = emails.len();
+        emails.retain(|e| *e != email);
+        if emails.len() == num_emails {
+            return Err("No such email address".into());
+        }
+        context
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id,
+                secondary_emails: Some(emails),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Makes one of the secondary email addresses of the user the primary one. The former primary
+    /// address becomes a secondary one.
+    async fn set_primary_email(
+        context: &Context<Handler>,
+        user_id: String,
+        email: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_primary_email");
+        span.in_scope(|| {
+            debug!(?user_id, ?email);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id)
+            && !can_manage_user(context, &user_id).await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized email address modification".into());
+        }
+        let user = context
+            .handler
+            .get_user_details(&user_id)
+            .instrument(span.clone())
+            .await?;
+        let mut emails = user.secondary_emails;
+        let email = email.trim().to_lowercase();
+        let num_emails = emails.len();
+        emails.retain(|e| *e != email);
+        if emails.len() == num_emails {
+            return Err("Not a secondary email address of the user".into());
+        }
+        if !user.email.is_empty() {
+            emails.push(user.email);
+        }
+        context
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id,
+                email: Some(email),
+                secondary_emails: Some(emails),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn add_user_attribute(
+        context: &Context<Handler>,
+        name: String,
+        attribute_type: AttributeType,
+        is_list: bool,
+        is_visible: bool,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_user_attribute");
+        span.in_scope(|| {
+            debug!(?name, ?attribute_type, is_list, is_visible);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized attribute creation".into());
+        }
+        context
+            .handler
+            .add_user_attribute(AttributeSchema {
+                name,
+                attribute_type: attribute_type.into(),
+                is_list,
+                is_visible,
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn delete_user_attribute(
+        context: &Context<Handler>,
+        name: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_user_attribute");
+        span.in_scope(|| {
+            debug!(?name);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized attribute deletion".into());
+        }
+        context
+            .handler
+            .delete_user_attribute(&name)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn add_group_attribute(
+        context: &Context<Handler>,
+        name: String,
+        attribute_type: AttributeType,
+        is_list: bool,
+        is_visible: bool,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_attribute");
+        span.in_scope(|| {
+            debug!(?name, ?attribute_type, is_list, is_visible);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized attribute creation".into());
+        }
+        context
+            .handler
+            .add_group_attribute(AttributeSchema {
+                name,
+                attribute_type: attribute_type.into(),
+                is_list,
+                is_visible,
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn delete_group_attribute(
+        context: &Context<Handler>,
+        name: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_group_attribute");
+        span.in_scope(|| {
+            debug!(?name);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized attribute deletion".into());
+        }
+        context
+            .handler
+            .delete_group_attribute(&name)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Moves the user to the recycle bin. With `dryRun`, nothing is written, and the result
+    /// reports what would be done, e.g. the groups left without members.
+    async fn delete_user(
+        context: &Context<Handler>,
+        user_id: String,
+        dry_run: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_user");
+        span.in_scope(|| {
+            debug!(?user_id, ?dry_run);
+        });
+        let user_id = UserId::new(&user_id);
+        if !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user deletion".into());
+        }
+        if context.validation_result.user == user_id {
+            span.in_scope(|| debug!("Cannot delete current user"));
+            return Err("Cannot delete current user".into());
+        }
+        if dry_run.unwrap_or(false) {
+            return Ok(
+                report_users_deletion(context, std::slice::from_ref(&user_id))
+                    .instrument(span)
+                    .await?
+                    .into(),
+            );
+        }
+        context
+            .handler
+            .delete_user(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Deletes all the users, after checking that each of them can be deleted. If the deletion of
+    /// one of them fails, the users before it stay deleted.
+    async fn delete_users(
+        context: &Context<Handler>,
+        user_ids: Vec<String>,
+        dry_run: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_users");
+        span.in_scope(|| {
+            debug!(?user_ids, ?dry_run);
+        });
+        let user_ids: Vec<_> = user_ids.iter().map(|id| UserId::new(id)).collect();
+        for user_id in &user_ids {
+            if !can_manage_user(context, user_id).await? {
+                span.in_scope(|| debug!("Unauthorized"));
+                return Err("Unauthorized user deletion".into());
+            }
+        }
+        if user_ids.contains(&context.validation_result.user) {
+            span.in_scope(|| debug!("Cannot delete current user"));
+            return Err("Cannot delete current user".into());
+        }
+        if dry_run.unwrap_or(false) {
+            return Ok(report_users_deletion(context, &user_ids)
+                .instrument(span)
+                .await?
+                .into());
+        }
+        for user_id in &user_ids {
+            context
+                .handler
+                .delete_user(user_id)
+                .instrument(span.clone())
+                .await?;
+        }
+        Ok(Success::new())
+    }
+
+    /// Recreates a deleted user from the recycle bin, with its attributes and group memberships.
+    async fn restore_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] restore_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user restoration".into());
+        }
+        let user_id = UserId::new(&user_id);
+        context
+            .handler
+            .restore_user(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Removes a deleted user from the recycle bin, for good.
+    async fn purge_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] purge_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user purge".into());
+        }
+        let user_id = UserId::new(&user_id);
+        context
+            .handler
+            .purge_user(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Suspends the account: the user cannot log in or bind anymore and their sessions are revoked,
+    /// but the account is not deleted.
+    async fn disable_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] disable_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user suspension".into());
+        }
+        if context.validation_result.user == user_id {
+            span.in_scope(|| debug!("Cannot disable current user"));
+            return Err("Cannot disable current user".into());
+        }
+        context
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id,
+                enabled: Some(false),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Suspends all the accounts, after checking that each of them can be suspended.
+    async fn disable_users(
+        context: &Context<Handler>,
+        user_ids: Vec<String>,
+        dry_run: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] disable_users");
+        span.in_scope(|| {
+            debug!(?user_ids, ?dry_run);
+        });
+        let user_ids: Vec<_> = user_ids.iter().map(|id| UserId::new(id)).collect();
+        for user_id in &user_ids {
+            if !can_manage_user(context, user_id).await? {
+                span.in_scope(|| debug!("Unauthorized"));
+                return Err("Unauthorized user suspension".into());
+            }
+        }
+        if user_ids.contains(&context.validation_result.user) {
+            span.in_scope(|| debug!("Cannot disable current user"));
+            return Err("Cannot disable current user".into());
+        }
+        if dry_run.unwrap_or(false) {
+            let mut report = DryRunReport::default();
+            for user_id in &user_ids {
+                match get_reported_user(context, user_id, &mut report)
+                    .instrument(span.clone())
+                    .await?
+                {
+                    None => {}
+                    Some(user) if !user.enabled => report
+                        .effects
+                        .push(format!("User '{}' is already suspended", user_id)),
+                    Some(_) => report
+                        .effects
+                        .push(format!("User '{}' would be suspended", user_id)),
+                }
+            }
+            return Ok(report.into());
+        }
+        for user_id in &user_ids {
+            context
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: user_id.clone(),
+                    enabled: Some(false),
+                    ..Default::default()
+                })
+                .instrument(span.clone())
+                .await?;
+        }
+        Ok(Success::new())
+    }
+
+    async fn enable_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] enable_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user activation".into());
+        }
+        context
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id,
+                enabled: Some(true),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Lifts the lockout of an account locked after too many failed logins.
+    async fn unlock_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] unlock_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized account unlock".into());
+        }
+        let user_id = UserId::new(&user_id);
+        context
+            .handler
+            .unlock_account(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Sets the validity period of the account, outside of which the user cannot log in or
+    /// bind. A missing bound leaves that side of the period open.
+    async fn set_user_validity(
+        context: &Context<Handler>,
+        user_id: String,
+        valid_from: Option<chrono::DateTime<chrono::Utc>>,
+        valid_until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_user_validity");
+        span.in_scope(|| {
+            debug!(?user_id, ?valid_from, ?valid_until);
+        });
+        let user_id = UserId::new(&user_id);
+        if !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized validity update".into());
+        }
+        if let (Some(from), Some(until)) = (valid_from, valid_until) {
+            if until <= from {
+                return Err("The end of the validity period must be after its start".into());
+            }
+        }
+        context
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id,
+                valid_from: Some(valid_from),
+                valid_until: Some(valid_until),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Moves the group to the recycle bin. With `dryRun`, nothing is written, and the result
+    /// reports what would be done, e.g. the members losing the group.
+    async fn delete_group(
+        context: &Context<Handler>,
+        group_id: i32,
+        dry_run: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_group");
+        span.in_scope(|| {
+            debug!(?group_id, ?dry_run);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group deletion".into());
+        }
+        if group_id == 1 {
+            span.in_scope(|| debug!("Cannot delete admin group"));
+            return Err("Cannot delete admin group".into());
+        }
+        if dry_run.unwrap_or(false) {
+            let mut report = DryRunReport::default();
+            if let Some(group) = get_reported_group(context, GroupId(group_id), &mut report)
+                .instrument(span.clone())
+                .await?
+            {
+                report.effects.push(format!(
+                    "Group '{}' would be moved to the recycle bin",
+                    group.display_name
+                ));
+                for user_id in get_group_members(context, group.group_id)
+                    .instrument(span)
+                    .await?
+                {
+                    report.effects.push(format!(
+                        "User '{}' would be removed from group '{}'",
+                        user_id, group.display_name
+                    ));
+                }
+            }
+            return Ok(report.into());
+        }
+        context
+            .handler
+            .delete_group(GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Recreates a deleted group from the recycle bin, with its attributes and members.
+    async fn restore_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] restore_group");
+        span.in_scope(|| {
+            debug!(?group_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group restoration".into());
+        }
+        context
+            .handler
+            .restore_group(GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Removes a deleted group from the recycle bin, for good.
+    async fn purge_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] purge_group");
+        span.in_scope(|| {
+            debug!(?group_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group purge".into());
+        }
+        context
+            .handler
+            .purge_group(GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Creates a long-lived API token, to use as a bearer token instead of logging in.
+    async fn create_api_token(
+        context: &Context<Handler>,
+        name: String,
+        scope: ApiTokenScope,
+    ) -> FieldResult<ApiTokenCreationResult> {
+        let span = debug_span!("[GraphQL mutation] create_api_token");
+        span.in_scope(|| {
+            debug!(?name, ?scope);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized API token creation".into());
+        }
+        if name.is_empty() {
+            return Err("The token name cannot be empty".into());
+        }
+        let (token, secret) = context
+            .handler
+            .create_api_token(CreateApiTokenRequest {
+                name,
+                scope: scope.into(),
+                created_by: context.validation_result.user.clone(),
+            })
+            .instrument(span)
+            .await?;
+        Ok(ApiTokenCreationResult {
+            token: token.into(),
+            secret,
+        })
+    }
+
+    /// Emails an invitation to create an account, in the given groups and with the given user
+    /// template. The invitee chooses their user ID and password; the invitation expires after a
+    /// week.
+    async fn invite_user(
+        context: &Context<Handler>,
+        email: String,
+        groups: Option<Vec<i32>>,
+        template: Option<String>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] invite_user");
+        span.in_scope(|| {
+            debug!(?email, ?groups, ?template);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user invitation".into());
+        }
+        let token = context
+            .handler
+            .create_invitation(CreateInvitationRequest {
+                email: email.clone(),
+                group_ids: groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(GroupId)
+                    .collect(),
+                created_by: context.validation_result.user.clone(),
+                template,
+            })
+            .instrument(span.clone())
+            .await?;
+        if let Err(e) = crate::infra::mail::send_invitation_email(
+            &email,
+            &token,
+            &context.server_url,
+            &context.mail_options,
+        )
+        .instrument(span.clone())
+        .await
+        {
+            span.in_scope(|| error!("Error sending the invitation email: {:#}", e));
+            // The invitee couldn't use it anyway.
+            let _ = context
+                .handler
+                .delete_invitation(&token)
+                .instrument(span)
+                .await;
+            return Err(format!("Could not send the invitation email: {}", e).into());
+        }
+        Ok(Success::new())
+    }
+
+    /// Creates the account of a sign-up request. The user is notified by email, and chooses
+    /// their password through the password reset.
+    async fn approve_pending_user(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] approve_pending_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized approval of a pending user".into());
+        }
+        let pending_user = context
+            .handler
+            .approve_pending_user(&UserId::new(&user_id))
+            .instrument(span.clone())
+            .await?;
+        if let Err(e) = crate::infra::mail::send_sign_up_approved_email(
+            pending_user
+                .display_name
+                .as_deref()
+                .unwrap_or_else(|| pending_user.user_id.as_str()),
+            &pending_user.email,
+            &context.server_url,
+            &context.mail_options,
+        )
+        .instrument(span.clone())
+        .await
+        {
+            // The account exists: the user can still go through the password reset.
+            span.in_scope(|| error!("Error sending the approval email: {:#}", e));
+        }
+        Ok(Success::new())
+    }
+
+    /// Deletes a sign-up request, and notifies the user by email.
+    async fn reject_pending_user(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] reject_pending_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized rejection of a pending user".into());
+        }
+        let pending_user = context
+            .handler
+            .reject_pending_user(&UserId::new(&user_id))
+            .instrument(span.clone())
+            .await?;
+        if let Err(e) = crate::infra::mail::send_sign_up_rejected_email(
+            pending_user
+                .display_name
+                .as_deref()
+                .unwrap_or_else(|| pending_user.user_id.as_str()),
+            &pending_user.email,
+            &context.mail_options,
+        )
+        .instrument(span.clone())
+        .await
+        {
+            span.in_scope(|| error!("Error sending the rejection email: {:#}", e));
+        }
+        Ok(Success::new())
+    }
+
+    /// Defines a new role, to grant to groups.
+    async fn create_role(context: &Context<Handler>, role: CreateRoleInput) -> FieldResult<Role> {
+        let span = debug_span!("[GraphQL mutation] create_role");
+        span.in_scope(|| {
+            debug!(?role);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized role creation".into());
+        }
+        let role_id = context
+            .handler
+            .create_role(CreateRoleRequest {
+                display_name: role.display_name,
+                read_all: role.read_all.unwrap_or(false),
+                reset_passwords: role.reset_passwords.unwrap_or(false),
+                managed_group_id: role.managed_group_id.map(GroupId),
+            })
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .list_roles()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .find(|r| r.role_id == role_id)
+            .map(Into::into)
+            .ok_or_else(|| "Could not find the new role".into())
+    }
+
+    async fn delete_role(context: &Context<Handler>, role_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_role");
+        span.in_scope(|| {
+            debug!(?role_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized role deletion".into());
+        }
+        context
+            .handler
+            .delete_role(role_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Grants the role to the members of the group.
+    async fn add_role_to_group(
+        context: &Context<Handler>,
+        role_id: i32,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_role_to_group");
+        span.in_scope(|| {
+            debug!(?role_id, ?group_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized role modification".into());
+        }
+        context
+            .handler
+            .add_role_to_group(role_id, GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_role_from_group(
+        context: &Context<Handler>,
+        role_id: i32,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_role_from_group");
+        span.in_scope(|| {
+            debug!(?role_id, ?group_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized role modification".into());
+        }
+        context
+            .handler
+            .remove_role_from_group(role_id, GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Lets the user add and remove the members of the group, without being an admin.
+    async fn add_group_manager(
+        context: &Context<Handler>,
+        group_id: i32,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_manager");
+        span.in_scope(|| {
+            debug!(?group_id, ?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group manager modification".into());
+        }
+        if group_id == 1 {
+            span.in_scope(|| debug!("Cannot delegate the admin group"));
+            return Err("The admin group cannot have managers".into());
+        }
+        let user_id = UserId::new(&user_id);
+        context
+            .handler
+            .add_group_manager(GroupId(group_id), &user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_group_manager(
+        context: &Context<Handler>,
+        group_id: i32,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_manager");
+        span.in_scope(|| {
+            debug!(?group_id, ?user_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group manager modification".into());
+        }
+        let user_id = UserId::new(&user_id);
+        context
+            .handler
+            .remove_group_manager(GroupId(group_id), &user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Registers a client application of the OpenID Connect provider.
+    async fn create_oidc_client(
+        context: &Context<Handler>,
+        client: CreateOidcClientInput,
+    ) -> FieldResult<OidcClientCreationResult> {
+        let span = debug_span!("[GraphQL mutation] create_oidc_client");
+        span.in_scope(|| {
+            debug!(?client);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized OpenID Connect client creation".into());
+        }
+        let secret = context
+            .handler
+            .create_oidc_client(CreateOidcClientRequest {
+                client_id: client.client_id.clone(),
+                display_name: client.display_name,
+                redirect_uris: client.redirect_uris,
+            })
+            .instrument(span.clone())
+            .await?;
+        let client = context
+            .handler
+            .get_oidc_client(&client.client_id)
+            .instrument(span)
+            .await?;
+        Ok(OidcClientCreationResult {
+            client: OidcClient::new(client, Vec::new()),
+            secret,
+        })
+    }
+
+    /// Deletes the client along with its group claims. The tokens it already got stay valid until
+    /// they expire.
+    async fn delete_oidc_client(
+        context: &Context<Handler>,
+        client_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_oidc_client");
+        span.in_scope(|| {
+            debug!(?client_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized OpenID Connect client deletion".into());
+        }
+        context
+            .handler
+            .delete_oidc_client(&client_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Adds the claim to the tokens the client gets for the members of the group.
+    async fn add_oidc_group_claim(
+        context: &Context<Handler>,
+        client_id: String,
+        group_id: i32,
+        claim: String,
+        value: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_oidc_group_claim");
+        span.in_scope(|| {
+            debug!(?client_id, ?group_id, ?claim, ?value);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized OpenID Connect client modification".into());
+        }
+        context
+            .handler
+            .add_oidc_group_claim(OidcGroupClaim {
+                client_id,
+                group_id: GroupId(group_id),
+                claim,
+                value,
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_oidc_group_claim(
+        context: &Context<Handler>,
+        client_id: String,
+        group_id: i32,
+        claim: String,
+        value: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_oidc_group_claim");
+        span.in_scope(|| {
+            debug!(?client_id, ?group_id, ?claim, ?value);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized OpenID Connect client modification".into());
+        }
+        context
+            .handler
+            .remove_oidc_group_claim(OidcGroupClaim {
+                client_id,
+                group_id: GroupId(group_id),
+                claim,
+                value,
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Creates an account for an application to bind to the LDAP server, with a generated
+    /// password.
+    async fn create_service_account(
+        context: &Context<Handler>,
+        id: String,
+        description: Option<String>,
+        read_all: bool,
+    ) -> FieldResult<ServiceAccountCreationResult> {
+        let span = debug_span!("[GraphQL mutation] create_service_account");
+        span.in_scope(|| {
+            debug!(?id, ?read_all);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized service account creation".into());
+        }
+        let (account, password) = context
+            .handler
+            .create_service_account(CreateServiceAccountRequest {
+                service_id: id,
+                description,
+                read_all,
+            })
+            .instrument(span)
+            .await?;
+        Ok(ServiceAccountCreationResult {
+            account: account.into(),
+            password,
+        })
+    }
+
+    /// Updates the service account. The unset fields are left unchanged, and an empty description
+    /// removes it.
+    async fn update_service_account(
+        context: &Context<Handler>,
+        id: String,
+        description: Option<String>,
+        read_all: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] update_service_account");
+        span.in_scope(|| {
+            debug!(?id, ?read_all);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized service account modification".into());
+        }
+        context
+            .handler
+            .update_service_account(UpdateServiceAccountRequest {
+                service_id: id,
+                description,
+                read_all,
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Replaces the password of the service account with a newly generated one, and returns it.
+    /// The old password stops working immediately.
+    async fn reset_service_account_password(
+        context: &Context<Handler>,
+        id: String,
+    ) -> FieldResult<String> {
+        let span = debug_span!("[GraphQL mutation] reset_service_account_password");
+        span.in_scope(|| {
+            debug!(?id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized service account modification".into());
+        }
+        let password = context
+            .handler
+            .reset_service_account_password(&id)
+            .instrument(span)
+            .await?;
+        Ok(password)
+    }
+
+    async fn delete_service_account(
+        context: &Context<Handler>,
+        id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_service_account");
+        span.in_scope(|| {
+            debug!(?id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized service account deletion".into());
+        }
+        context
+            .handler
+            .delete_service_account(&id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Revokes the API token: it cannot be used anymore.
+    async fn revoke_api_token(context: &Context<Handler>, token_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_api_token");
+        span.in_scope(|| {
+            debug!(?token_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized API token revocation".into());
+        }
+        context
+            .handler
+            .delete_api_token(token_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Revokes one of the sessions of the current user.
+    async fn revoke_session(
+        context: &Context<Handler>,
+        session_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_session");
+        span.in_scope(|| {
+            debug!(?session_id);
+        });
+        let user_id = context.validation_result.user.clone();
+        let session_id = session_id
+            .parse::<i64>()
+            .map_err(|_| "Invalid session ID")?;
+        context
+            .handler
+            .delete_user_session(&user_id, session_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Revokes all the sessions of a user, e.g. after a device was compromised. The tokens already
+    /// issued stay valid until they expire, up to a day.
+    async fn revoke_all_sessions(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_all_sessions");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if context.validation_result.user != user_id && !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized session revocation".into());
+        }
+        context
+            .handler
+            .delete_all_user_sessions(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Deletes a WebAuthn credential of a user, e.g. a lost hardware key.
+    async fn delete_webauthn_credential(
+        context: &Context<Handler>,
+        user_id: String,
+        credential_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_webauthn_credential");
+        span.in_scope(|| {
+            debug!(?user_id, ?credential_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if context.validation_result.user != user_id && !can_manage_user(context, &user_id).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized WebAuthn credential deletion".into());
+        }
+        context
+            .handler
+            .delete_webauthn_credential(&user_id, &credential_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Puts the server in read-only mode, e.g. during a migration or a backup, or back to normal.
+    /// In read-only mode, the searches and the logins still work, but the changes are rejected.
+    async fn set_read_only_mode(context: &Context<Handler>, enabled: bool) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_read_only_mode");
+        span.in_scope(|| {
+            debug!(?enabled);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized read-only mode change".into());
+        }
+        context.read_only.set(enabled);
+        // The mode is not stored by a backend handler, so it is recorded here.
+        if let Err(e) = context
+            .handler
+            .record_audit_event(AuditEvent {
+                actor: context.validation_result.user.clone(),
+                source: AuditSource::Graphql,
+                action: "set_read_only_mode".to_owned(),
+                target: "server".to_owned(),
+                details: Some(enabled.to_string()),
+            })
+            .instrument(span)
+            .await
+        {
+            error!(
+                "Could not record set_read_only_mode in the audit log: {}",
+                e
+            );
+        }
+        Ok(Success::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            handler::UserBackendHandler,
+            sql_backend_handler::{tests::*, SqlBackendHandler},
+            types::Capabilities,
+        },
+        infra::{
+            auth_service::{Permission, ValidationResults},
+            configuration::MailOptions,
+            db_cleaner::SchedulerStatus,
+            graphql::{loader::UserGroupsLoader, query::Query},
+            read_only::ReadOnlyMode,
+        },
+    };
+    use juniper::{execute, EmptySubscription, RootNode, Variables};
+
+    async fn execute_mutation(
+        handler: &SqlBackendHandler,
+        validation_result: ValidationResults,
+        query: &str,
+    ) -> Result<serde_json::Value, String> {
+        let context = Context {
+            handler: Box::new(handler.clone()),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
+            validation_result,
+        };
+        let schema = RootNode::new(
+            Query::<SqlBackendHandler>::new(),
+            Mutation::<SqlBackendHandler>::new(),
+            EmptySubscription::<Context<SqlBackendHandler>>::new(),
+        );
+        let (result, errors) = execute(query, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        match errors.first() {
+            None => Ok(serde_json::to_value(result).unwrap()),
+            Some(e) => Err(e.error().message().to_owned()),
+        }
+    }
+
+    async fn apply_changes(
+        handler: &SqlBackendHandler,
+        validation_result: ValidationResults,
+        operations: &str,
+    ) -> Result<(), String> {
+        let query = format!(
+            "mutation {{ applyChanges(operations: {}) {{ createdGroupIds }} }}",
+            operations
+        );
+        execute_mutation(handler, validation_result, &query)
+            .await
+            .map(|_| ())
+    }
+
+    #[test]
+    fn test_validate_ssh_public_key() {
+        for key in [ED25519_PUBLIC_KEY, ECDSA_PUBLIC_KEY, RSA_PUBLIC_KEY] {
+            assert_eq!(
+                validate_ssh_public_key(&format!("  {}\n", key)).unwrap(),
+                key
+            );
+        }
+        // The comment is optional.
+        let (ed25519_type, ed25519_data) = {
+            let mut parts = ED25519_PUBLIC_KEY.split(' ');
+            (parts.next().unwrap(), parts.next().unwrap())
+        };
+        let uncommented = format!("{} {}", ed25519_type, ed25519_data);
+        assert_eq!(validate_ssh_public_key(&uncommented).unwrap(), uncommented);
+
+        for invalid in [
+            String::new(),
+            "ssh-ed25519 AAAA bob@laptop".to_owned(),
+            "ssh-ed25519 not_base64 bob@laptop".to_owned(),
+            // The type doesn't match the key.
+            format!("ssh-rsa {} bob@laptop", ed25519_data),
+            format!("pgp-ed25519 {} bob@laptop", ed25519_data),
+            ed25519_data.to_owned(),
+            format!("{}\n{}", ED25519_PUBLIC_KEY, RSA_PUBLIC_KEY),
+        ] {
+            validate_ssh_public_key(&invalid).unwrap_err();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_permissions() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let manager = || ValidationResults::new(UserId::new("manager"), Permission::UserManager);
+        // Like `createUser` and `addUserToGroup`, the user managers can make these changes.
+        let user_changes = format!(
+            r#"[{{ createUser: {{ id: "alice", email: "alice@example.com" }} }},
+                {{ addUserToGroup: {{ userId: "nogroup", groupId: {} }} }}]"#,
+            fixture.groups[1].0
+        );
+        assert_eq!(
+            apply_changes(handler, manager(), &user_changes).await,
+            Ok(())
+        );
+        assert_eq!(
+            get_user_names(handler, None).await,
+            vec!["alice", "bob", "john", "nogroup", "patrick"]
+        );
+        // Only the admins can create groups: nothing is applied.
+        assert_eq!(
+            apply_changes(
+                handler,
+                manager(),
+                r#"[{ createUser: { id: "carol", email: "carol@example.com" } },
+                    { createGroup: "Interns" }]"#,
+            )
+            .await,
+            Err("Operation 1: Unauthorized group modification".to_owned())
+        );
+        assert!(handler
+            .get_user_details(&UserId::new("carol"))
+            .await
+            .is_err());
+        assert_eq!(
+            apply_changes(
+                handler,
+                manager(),
+                r#"[{ updateUser: { id: "bob", uidNumber: 1000 } }]"#,
+            )
+            .await,
+            Err("Operation 0: Only admins can update POSIX attributes".to_owned())
+        );
+
+        // The regular users can only update themselves.
+        let bob = || ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        assert_eq!(
+            apply_changes(
+                handler,
+                bob(),
+                r#"[{ updateUser: { id: "bob", displayName: "Bobby" } }]"#,
+            )
+            .await,
+            Ok(())
+        );
+        assert_eq!(
+            apply_changes(
+                handler,
+                bob(),
+                r#"[{ updateUser: { id: "bob", displayName: "Bob" } },
+                    { createUser: { id: "carol", email: "carol@example.com" } }]"#,
+            )
+            .await,
+            Err("Operation 1: Unauthorized user creation".to_owned())
+        );
+        assert_eq!(
+            handler
+                .get_user_details(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .display_name
+                .as_deref(),
+            Some("Bobby")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bulk_membership_permissions() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let managed_group = fixture.groups[2].0;
+        let manager = || {
+            let mut manager = ValidationResults::new(UserId::new("bob"), Permission::Regular);
+            manager.add_capabilities(Capabilities {
+                managed_memberships: vec![GroupId(managed_group)],
+                ..Default::default()
+            });
+            manager
+        };
+        let members = || {
+            get_user_names(
+                handler,
+                Some(UserRequestFilter::MemberOfId(GroupId(managed_group))),
+            )
+        };
+        // Like `addUserToGroup` and `removeUserFromGroup`, one user at a time.
+        assert!(execute_mutation(
+            handler,
+            manager(),
+            &format!(
+                r#"mutation {{ addUsersToGroup(userIds: ["john", "nogroup"], groupId: {}) {{ ok }} }}"#,
+                managed_group
+            ),
+        )
+        .await
+        .is_ok());
+        assert_eq!(members().await, vec!["john", "nogroup"]);
+        assert!(execute_mutation(
+            handler,
+            manager(),
+            &format!(
+                r#"mutation {{ removeUsersFromGroup(userIds: ["john"], groupId: {}) {{ ok }} }}"#,
+                managed_group
+            ),
+        )
+        .await
+        .is_ok());
+        assert_eq!(members().await, vec!["nogroup"]);
+
+        // Not for the groups they don't manage, nor for the users without a role.
+        let regular = || ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        for (validation_result, group_id) in
+            [(manager(), fixture.groups[1].0), (regular(), managed_group)]
+        {
+            for mutation in ["addUsersToGroup", "removeUsersFromGroup"] {
+                assert_eq!(
+                    execute_mutation(
+                        handler,
+                        validation_result.clone(),
+                        &format!(
+                            r#"mutation {{ {}(userIds: ["nogroup"], groupId: {}) {{ ok }} }}"#,
+                            mutation, group_id
+                        ),
+                    )
+                    .await,
+                    Err("Unauthorized group membership modification".to_owned())
+                );
+            }
+        }
+        assert_eq!(members().await, vec!["nogroup"]);
+    }
+
+    #[tokio::test]
+    async fn test_reset_own_password() {
+        let fixture = TestFixture::new().await;
+        let bob = || ValidationResults::new(UserId::new("bob"), Permission::Regular);
+        // The users need their current password, with `changePassword`.
+        for mutation in [
+            r#"mutation { resetUserPassword(userId: "bob", newPassword: "new password") { ok } }"#,
+            r#"mutation { sendPasswordResetEmail(userId: "bob") { ok } }"#,
+        ] {
+            assert_eq!(
+                execute_mutation(&fixture.handler, bob(), mutation).await,
+                Err("Unauthorized password reset".to_owned())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let admin = || ValidationResults::new(UserId::new("admin"), Permission::Admin);
+        assert_eq!(
+            execute_mutation(
+                handler,
+                admin(),
+                r#"mutation { deleteUsers(userIds: ["bob", "patrick"], dryRun: true) { ok report } }"#,
+            )
+            .await
+            .unwrap(),
+            serde_json::json!({
+                "deleteUsers": {
+                    "ok": true,
+                    "report": [
+                        "User 'bob' would be moved to the recycle bin",
+                        "User 'patrick' would be moved to the recycle bin",
+                        "Group 'Best Group' would be left without members",
+                    ],
+                }
+            })
+        );
+        // The failures come first.
+        assert_eq!(
+            execute_mutation(
+                handler,
+                admin(),
+                &format!(
+                    r#"mutation {{ addUsersToGroup(userIds: ["nogroup", "bob"], groupId: {}, dryRun: true) {{ ok report }} }}"#,
+                    fixture.groups[0].0
+                ),
+            )
+            .await
+            .unwrap(),
+            serde_json::json!({
+                "addUsersToGroup": {
+                    "ok": false,
+                    "report": [
+                        "User 'bob' is already a member of group 'Best Group'",
+                        "User 'nogroup' would be added to group 'Best Group'",
+                    ],
+                }
+            })
+        );
+        // Nothing was written.
+        assert_eq!(
+            get_user_names(handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+        assert!(handler
+            .get_user_groups(&UserId::new("nogroup"))
+            .await
+            .unwrap()
+            .is_empty());
+        // Without `dryRun`, the report is empty.
+        assert_eq!(
+            execute_mutation(
+                handler,
+                admin(),
+                r#"mutation { deleteUser(userId: "bob") { ok report } }"#,
+            )
+            .await
+            .unwrap(),
+            serde_json::json!({ "deleteUser": { "ok": true, "report": [] } })
+        );
+        assert_eq!(
+            get_user_names(handler, None).await,
+            vec!["john", "nogroup", "patrick"]
+        );
+    }
 }
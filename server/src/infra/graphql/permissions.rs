@@ -0,0 +1,249 @@
+//! The permissions required by the fields of the root GraphQL types, read from the authorization
+//! checks of their resolvers, so that the exported schema stays in sync with the code.
+
+use std::collections::BTreeMap;
+
+/// Who can use a field. The admins can use all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequiredPermission {
+    Admin,
+    Readonly,
+    UserManager,
+    PasswordManager,
+    /// The user targeted by the field, e.g. to change their own email.
+    OwnUser,
+    /// Any authenticated user.
+    Authenticated,
+}
+
+impl RequiredPermission {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequiredPermission::Admin => "admin",
+            RequiredPermission::Readonly => "readonly",
+            RequiredPermission::UserManager => "user_manager",
+            RequiredPermission::PasswordManager => "password_manager",
+            RequiredPermission::OwnUser => "self",
+            RequiredPermission::Authenticated => "authenticated",
+        }
+    }
+}
+
+use RequiredPermission::*;
+
+/// The checks of the `ValidationResults` and of the helpers of the mutations, with who passes
+/// them. They are matched against the resolvers without their white space.
+const CHECKS: &[(&str, &[RequiredPermission])] = &[
+    ("is_admin()", &[Admin]),
+    ("is_admin_or_readonly()", &[Admin, Readonly]),
+    ("can_read_own_entries()", &[Admin, Readonly, OwnUser]),
+    ("can_read(", &[Admin, Readonly, OwnUser]),
+    ("can_write(", &[Admin, OwnUser]),
+    ("can_manage_users()", &[Admin, UserManager]),
+    ("can_manage_any_user()", &[Admin, UserManager]),
+    ("can_manage_user(", &[Admin, UserManager]),
+    ("can_manage_membership(", &[Admin, UserManager]),
+    (
+        "can_change_password(",
+        &[Admin, UserManager, PasswordManager, OwnUser],
+    ),
+    (
+        "can_reset_password(",
+        &[Admin, UserManager, PasswordManager],
+    ),
+    // Lets the users act on themselves, before checking the permissions over the others.
+    ("validation_result.user!=", &[OwnUser]),
+];
+
+/// The sources of the resolvers of the root types.
+const ROOT_TYPES: [(&str, &str); 3] = [
+    ("Query", include_str!("query.rs")),
+    ("Mutation", include_str!("mutation.rs")),
+    ("Subscription", include_str!("subscription.rs")),
+];
+
+pub type FieldPermissions = BTreeMap<String, Vec<RequiredPermission>>;
+
+fn to_camel_case(name: &str) -> String {
+    let mut camel_case = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            camel_case.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel_case.push(c);
+        }
+    }
+    camel_case
+}
+
+fn resolver_name(line: &str) -> Option<&str> {
+    let line = line.strip_prefix("    ")?;
+    let line = line.strip_prefix("pub ").unwrap_or(line);
+    let signature = line
+        .strip_prefix("async fn ")
+        .or_else(|| line.strip_prefix("fn "))?;
+    signature
+        .split(|c: char| c == '(' || c == '<')
+        .next()
+        .map(str::trim)
+}
+
+fn permissions_of(body: &str) -> Vec<RequiredPermission> {
+    let body: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut permissions: Vec<_> = CHECKS
+        .iter()
+        .filter(|(check, _)| body.contains(check))
+        .flat_map(|(_, permissions)| permissions.iter().copied())
+        .collect();
+    if permissions.is_empty() {
+        permissions.push(Authenticated);
+    }
+    permissions.sort();
+    permissions.dedup();
+    permissions
+}
+
+/// The permissions required by the fields of the GraphQL object `type_name`, found in the
+/// `#[graphql_object]` (or `#[graphql_subscription]`) impl block of `source`.
+pub fn field_permissions(source: &str, type_name: &str) -> FieldPermissions {
+    let impl_suffix = format!(" {}<Handler> {{", type_name);
+    let mut lines = source.lines();
+    let mut previous = "";
+    for line in lines.by_ref() {
+        if line.starts_with("impl")
+            && line.ends_with(&impl_suffix)
+            && previous.starts_with("#[graphql_")
+        {
+            break;
+        }
+        previous = line;
+    }
+    let mut fields = FieldPermissions::new();
+    let mut current: Option<(String, String)> = None;
+    for line in lines.take_while(|line| *line != "}") {
+        if let Some(name) = resolver_name(line) {
+            if let Some((field, body)) = current.take() {
+                fields.insert(field, permissions_of(&body));
+            }
+            current = Some((to_camel_case(name), String::new()));
+        }
+        if let Some((_, body)) = current.as_mut() {
+            body.push_str(line);
+        }
+    }
+    if let Some((field, body)) = current {
+        fields.insert(field, permissions_of(&body));
+    }
+    fields
+}
+
+/// The permissions required by the fields of `Query`, `Mutation` and `Subscription`.
+pub fn root_field_permissions() -> BTreeMap<&'static str, FieldPermissions> {
+    ROOT_TYPES
+        .iter()
+        .map(|(type_name, source)| (*type_name, field_permissions(source, type_name)))
+        .collect()
+}
+
+/// The permissions as listed in the exported schema, e.g. "admin, self".
+pub fn describe(permissions: &[RequiredPermission]) -> String {
+    permissions
+        .iter()
+        .map(RequiredPermission::name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_permissions() {
+        const SOURCE: &str = r#"
+impl<Handler: BackendHandler> Query<Handler> {
+    fn not_a_field() {}
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> Query<Handler> {
+    fn api_version() -> &'static str {
+        "1.0"
+    }
+
+    async fn user_emails(context: &Context<Handler>, user_id: String) -> FieldResult<Vec<String>> {
+        if !context.validation_result.can_read(&user_id) {
+            return Err("Unauthorized".into());
+        }
+        Ok(Vec::new())
+    }
+
+    async fn reset_password(context: &Context<Handler>, user_id: String) -> FieldResult<bool> {
+        if !context
+            .validation_result
+            .can_change_password(&user_id, &groups)
+        {
+            return Err("Unauthorized".into());
+        }
+        Ok(true)
+    }
+
+    pub async fn audit_logs(context: &Context<Handler>) -> FieldResult<Vec<String>> {
+        if !context.validation_result.is_admin() {
+            return Err("Unauthorized".into());
+        }
+        Ok(Vec::new())
+    }
+}
+
+impl<Handler: BackendHandler> Other<Handler> {
+    fn ignored() {}
+}
+"#;
+        let fields = field_permissions(SOURCE, "Query");
+        assert_eq!(
+            fields.keys().collect::<Vec<_>>(),
+            vec!["apiVersion", "auditLogs", "resetPassword", "userEmails"]
+        );
+        assert_eq!(fields["apiVersion"], vec![Authenticated]);
+        assert_eq!(fields["userEmails"], vec![Admin, Readonly, OwnUser]);
+        assert_eq!(
+            fields["resetPassword"],
+            vec![Admin, UserManager, PasswordManager, OwnUser]
+        );
+        assert_eq!(describe(&fields["auditLogs"]), "admin");
+    }
+
+    #[test]
+    fn test_root_field_permissions() {
+        let permissions = root_field_permissions();
+        assert_eq!(permissions["Query"]["apiVersion"], vec![Authenticated]);
+        assert_eq!(permissions["Query"]["user"], vec![Admin, Readonly, OwnUser]);
+        assert_eq!(permissions["Query"]["auditLogs"], vec![Admin]);
+        assert_eq!(permissions["Query"]["users"], vec![Admin, Readonly]);
+        assert_eq!(
+            permissions["Mutation"]["changePassword"],
+            vec![Authenticated]
+        );
+        assert_eq!(
+            permissions["Mutation"]["resetUserPassword"],
+            vec![Admin, UserManager, PasswordManager]
+        );
+        assert_eq!(
+            permissions["Mutation"]["revokeAllSessions"],
+            vec![Admin, UserManager, OwnUser]
+        );
+        assert_eq!(
+            permissions["Mutation"]["applyChanges"],
+            vec![Admin, UserManager, OwnUser]
+        );
+        assert_eq!(
+            permissions["Subscription"]["changes"],
+            vec![Admin, Readonly]
+        );
+    }
+}
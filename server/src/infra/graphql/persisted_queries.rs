@@ -0,0 +1,213 @@
+//! The persisted queries: known GraphQL documents, identified by their SHA-256, that the clients
+//! can send by hash instead of in full. They can also be the only accepted documents.
+
+use crate::infra::configuration::GraphqlOptions;
+use actix_web::{http::Method, web, HttpMessage, HttpRequest};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+
+/// The error of the unknown hashes, as expected by the Apollo clients.
+const NOT_FOUND_ERROR: &str = "PersistedQueryNotFound";
+
+/// The hex SHA-256 of a document.
+pub fn query_hash(document: &str) -> String {
+    format!("{:x}", Sha256::digest(document.as_bytes()))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PersistedQueries {
+    /// By hash, in lowercase.
+    documents: Arc<HashMap<String, String>>,
+    only_persisted: bool,
+}
+
+impl PersistedQueries {
+    pub fn new(options: &GraphqlOptions) -> Result<Self> {
+        let file_name = match &options.persisted_queries_file {
+            None if options.only_persisted_queries => {
+                bail!("graphql_options.only_persisted_queries requires persisted_queries_file")
+            }
+            None => return Ok(Self::default()),
+            Some(file_name) => file_name,
+        };
+        let content = std::fs::read_to_string(file_name)
+            .with_context(|| format!("while reading {}", file_name))?;
+        let documents = serde_json::from_str::<HashMap<String, String>>(&content)
+            .with_context(|| format!("while parsing {}", file_name))?
+            .into_iter()
+            .map(|(hash, document)| {
+                let hash = hash.to_ascii_lowercase();
+                if hash != query_hash(&document) {
+                    return Err(anyhow!(
+                        "The hash {} of {} doesn't match its document",
+                        hash,
+                        file_name
+                    ));
+                }
+                Ok((hash, document))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            documents: Arc::new(documents),
+            only_persisted: options.only_persisted_queries,
+        })
+    }
+
+    /// Rejects the documents that are not persisted, if only the persisted queries are allowed.
+    pub fn check_allowed(&self, document: &str) -> std::result::Result<(), String> {
+        if self.only_persisted && !self.documents.contains_key(&query_hash(document)) {
+            return Err("Only the persisted queries are allowed".to_owned());
+        }
+        Ok(())
+    }
+
+    fn resolve_request(&self, request: &mut Value) -> std::result::Result<(), String> {
+        let hash = request
+            .pointer("/extensions/persistedQuery/sha256Hash")
+            .and_then(Value::as_str)
+            .map(str::to_ascii_lowercase);
+        let document = request
+            .get("query")
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+        match (document, hash) {
+            (Some(document), _) => self.check_allowed(&document),
+            (None, Some(hash)) => {
+                let document = self
+                    .documents
+                    .get(&hash)
+                    .ok_or_else(|| NOT_FOUND_ERROR.to_owned())?;
+                request
+                    .as_object_mut()
+                    .ok_or_else(|| "Invalid request".to_owned())?
+                    .insert("query".to_owned(), Value::String(document.clone()));
+                Ok(())
+            }
+            (None, None) => Err("The request has no query".to_owned()),
+        }
+    }
+
+    /// Replaces the hashes of the request by their documents, and checks that only the persisted
+    /// queries are used if required. Returns the body to execute.
+    pub fn resolve(
+        &self,
+        req: &HttpRequest,
+        body: web::Bytes,
+    ) -> std::result::Result<web::Bytes, String> {
+        if self.documents.is_empty() && !self.only_persisted {
+            return Ok(body);
+        }
+        if req.method() == Method::GET {
+            let parameters =
+                serde_urlencoded::from_str::<HashMap<String, String>>(req.query_string())
+                    .map_err(|e| format!("Invalid query string: {}", e))?;
+            return match parameters.get("query") {
+                Some(document) => self.check_allowed(document).map(|()| body),
+                None => Err("The persisted queries must be sent with POST".to_owned()),
+            };
+        }
+        if req.content_type() == "application/graphql" {
+            let document = std::str::from_utf8(&body)
+                .map_err(|_| "The query is not valid UTF-8".to_owned())?;
+            return self.check_allowed(document).map(|()| body);
+        }
+        let mut request = serde_json::from_slice::<Value>(&body)
+            .map_err(|e| format!("Invalid request: {}", e))?;
+        match &mut request {
+            Value::Array(requests) => requests
+                .iter_mut()
+                .try_for_each(|request| self.resolve_request(request))?,
+            request => self.resolve_request(request)?,
+        }
+        serde_json::to_vec(&request)
+            .map(web::Bytes::from)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const USERS: &str = "query { users { id } }";
+
+    fn persisted_queries(only_persisted: bool) -> PersistedQueries {
+        PersistedQueries {
+            documents: Arc::new(HashMap::from([(query_hash(USERS), USERS.to_owned())])),
+            only_persisted,
+        }
+    }
+
+    fn post(body: Value) -> (HttpRequest, web::Bytes) {
+        (
+            TestRequest::post()
+                .uri("/api/graphql")
+                .insert_header(("content-type", "application/json"))
+                .to_http_request(),
+            web::Bytes::from(body.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_resolve_hash() {
+        let (req, body) = post(serde_json::json!({
+            "variables": {},
+            "extensions": {"persistedQuery": {"version": 1, "sha256Hash": query_hash(USERS)}},
+        }));
+        let body = persisted_queries(false).resolve(&req, body).unwrap();
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(request["query"], USERS);
+
+        let (req, body) = post(serde_json::json!([{
+            "extensions": {"persistedQuery": {"version": 1, "sha256Hash": query_hash("{ me }")}},
+        }]));
+        assert_eq!(
+            persisted_queries(false).resolve(&req, body),
+            Err(NOT_FOUND_ERROR.to_owned())
+        );
+    }
+
+    #[test]
+    fn test_only_persisted_queries() {
+        let (req, body) = post(serde_json::json!({"query": USERS}));
+        assert!(persisted_queries(true).resolve(&req, body).is_ok());
+        let (req, body) = post(serde_json::json!({"query": "{ groups { id } }"}));
+        assert!(persisted_queries(false).resolve(&req, body.clone()).is_ok());
+        assert!(persisted_queries(true).resolve(&req, body).is_err());
+        let req = TestRequest::get()
+            .uri("/api/graphql?query=%7B%20groups%20%7B%20id%20%7D%20%7D")
+            .to_http_request();
+        assert!(persisted_queries(true)
+            .resolve(&req, web::Bytes::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let file_name = std::env::temp_dir().join(format!(
+            "lldap_persisted_queries_test_{}.json",
+            std::process::id()
+        ));
+        let options = GraphqlOptions {
+            persisted_queries_file: Some(file_name.to_str().unwrap().to_owned()),
+            ..Default::default()
+        };
+        std::fs::write(&file_name, serde_json::json!({ "1234": USERS }).to_string()).unwrap();
+        assert!(PersistedQueries::new(&options).is_err());
+        std::fs::write(
+            &file_name,
+            serde_json::json!({ query_hash(USERS).to_uppercase(): USERS }).to_string(),
+        )
+        .unwrap();
+        assert_eq!(PersistedQueries::new(&options).unwrap().documents.len(), 1);
+        std::fs::remove_file(&file_name).unwrap();
+        assert!(PersistedQueries::new(&GraphqlOptions {
+            only_persisted_queries: true,
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
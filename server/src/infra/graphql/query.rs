@@ -1,9 +1,17 @@
 use crate::domain::{
-    handler::BackendHandler,
+    handler::{
+        BackendHandler, GroupOrder, GroupRequestFilter, SortDirection, SubStringFilter, UserOrder,
+    },
     ldap::utils::map_user_field,
-    types::{GroupDetails, GroupId, UserColumn, UserId},
+    types::{GroupColumn, GroupDetails, GroupId, UserColumn, UserId},
 };
-use juniper::{graphql_object, FieldResult, GraphQLInputObject};
+use crate::infra::{
+    auth_service::Permission,
+    change_events::event_name,
+    configuration::{MaintenanceJob, PasswordPolicyOptions},
+    db_cleaner::SchedulerRuns,
+};
+use juniper::{graphql_object, FieldResult, GraphQLEnum, GraphQLInputObject, GraphQLObject};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, debug_span, Instrument};
 
@@ -11,6 +19,27 @@ type DomainRequestFilter = crate::domain::handler::UserRequestFilter;
 type DomainUser = crate::domain::types::User;
 type DomainGroup = crate::domain::types::Group;
 type DomainUserAndGroups = crate::domain::types::UserAndGroups;
+type DomainAttributeType = crate::domain::types::AttributeType;
+type DomainAttributeSchema = crate::domain::types::AttributeSchema;
+type DomainAttributeValue = crate::domain::types::AttributeValue;
+type DomainAuditLogFilter = crate::domain::handler::AuditLogFilter;
+type DomainAuditLogEntry = crate::domain::types::AuditLogEntry;
+type DomainAuditSource = crate::domain::types::AuditSource;
+type DomainChange = crate::domain::types::Change;
+type DomainApiToken = crate::domain::types::ApiToken;
+type DomainApiTokenScope = crate::domain::types::ApiTokenScope;
+type DomainServiceAccount = crate::domain::types::ServiceAccount;
+type DomainSession = crate::domain::types::Session;
+type DomainWebauthnCredential = crate::domain::types::WebauthnCredential;
+type DomainPendingUser = crate::domain::types::PendingUser;
+type DomainDeletedUser = crate::domain::types::DeletedUser;
+type DomainDeletedGroup = crate::domain::types::DeletedGroup;
+type DomainRole = crate::domain::types::Role;
+type DomainOidcClient = crate::domain::types::OidcClient;
+type DomainOidcGroupClaim = crate::domain::types::OidcGroupClaim;
+type DomainDirectoryStatistics = crate::domain::types::DirectoryStatistics;
+type DomainGroupMemberCount = crate::domain::types::GroupMemberCount;
+type DomainUserLastLogin = crate::domain::types::UserLastLogin;
 use super::api::Context;
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -21,8 +50,15 @@ pub struct RequestFilter {
     all: Option<Vec<RequestFilter>>,
     not: Option<Box<RequestFilter>>,
     eq: Option<EqualityConstraint>,
+    not_equals: Option<EqualityConstraint>,
+    /// The field contains the value, ignoring case.
+    contains: Option<EqualityConstraint>,
+    /// The field starts with the value, ignoring case.
+    starts_with: Option<EqualityConstraint>,
     member_of: Option<String>,
     member_of_id: Option<i32>,
+    /// Only the users modified at or after this date.
+    modified_since: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl TryInto<DomainRequestFilter> for RequestFilter {
@@ -41,12 +77,24 @@ impl TryInto<DomainRequestFilter> for RequestFilter {
         if self.eq.is_some() {
             field_count += 1;
         }
+        if self.not_equals.is_some() {
+            field_count += 1;
+        }
+        if self.contains.is_some() {
+            field_count += 1;
+        }
+        if self.starts_with.is_some() {
+            field_count += 1;
+        }
         if self.member_of.is_some() {
             field_count += 1;
         }
         if self.member_of_id.is_some() {
             field_count += 1;
         }
+        if self.modified_since.is_some() {
+            field_count += 1;
+        }
         if field_count == 0 {
             return Err("No field specified in request filter".to_string());
         }
@@ -54,14 +102,24 @@ impl TryInto<DomainRequestFilter> for RequestFilter {
             return Err("Multiple fields specified in request filter".to_string());
         }
         if let Some(e) = self.eq {
-            if let Some(column) = map_user_field(&e.field) {
-                if column == UserColumn::UserId {
-                    return Ok(DomainRequestFilter::UserId(UserId::new(&e.value)));
-                }
-                return Ok(DomainRequestFilter::Equality(column, e.value));
-            } else {
-                return Err(format!("Unknown request filter: {}", &e.field));
-            }
+            return e.into_equality_filter();
+        }
+        if let Some(e) = self.not_equals {
+            return Ok(DomainRequestFilter::Not(Box::new(
+                e.into_equality_filter()?,
+            )));
+        }
+        if let Some(e) = self.contains {
+            return e.into_substring_filter(|value| SubStringFilter {
+                any: vec![value],
+                ..Default::default()
+            });
+        }
+        if let Some(e) = self.starts_with {
+            return e.into_substring_filter(|value| SubStringFilter {
+                initial: Some(value),
+                ..Default::default()
+            });
         }
         if let Some(c) = self.any {
             return Ok(DomainRequestFilter::Or(
@@ -86,16 +144,228 @@ impl TryInto<DomainRequestFilter> for RequestFilter {
         if let Some(group_id) = self.member_of_id {
             return Ok(DomainRequestFilter::MemberOfId(GroupId(group_id)));
         }
+        if let Some(date) = self.modified_since {
+            return Ok(DomainRequestFilter::ModifiedSince(date));
+        }
         unreachable!();
     }
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// A constraint on a field of the users, or on their group membership with the field `memberOf`.
 pub struct EqualityConstraint {
     field: String,
     value: String,
 }
 
+impl EqualityConstraint {
+    fn is_member_of(&self) -> bool {
+        matches!(
+            self.field.to_ascii_lowercase().as_str(),
+            "memberof" | "member_of"
+        )
+    }
+
+    fn is_secondary_email(&self) -> bool {
+        matches!(
+            self.field.to_ascii_lowercase().as_str(),
+            "secondaryemails" | "secondary_emails" | "othermailbox" | "mailalias"
+        )
+    }
+
+    fn into_equality_filter(self) -> Result<DomainRequestFilter, String> {
+        if self.is_member_of() {
+            return Ok(DomainRequestFilter::MemberOf(self.value));
+        }
+        if self.is_secondary_email() {
+            return Ok(DomainRequestFilter::SecondaryEmail(self.value));
+        }
+        match map_user_field(&self.field.to_ascii_lowercase()) {
+            Some(UserColumn::UserId) => Ok(DomainRequestFilter::UserId(UserId::new(&self.value))),
+            Some(column) => Ok(DomainRequestFilter::Equality(column, self.value)),
+            None => Err(format!("Unknown request filter: {}", &self.field)),
+        }
+    }
+
+    fn into_substring_filter(
+        self,
+        make_filter: impl FnOnce(String) -> SubStringFilter,
+    ) -> Result<DomainRequestFilter, String> {
+        if self.is_member_of() {
+            return Err("Substring matching is not supported on group membership".to_string());
+        }
+        match map_user_field(&self.field.to_ascii_lowercase()) {
+            Some(
+                column @ (UserColumn::UserId
+                | UserColumn::Email
+                | UserColumn::DisplayName
+                | UserColumn::FirstName
+                | UserColumn::LastName
+                | UserColumn::HomeDirectory
+                | UserColumn::LoginShell),
+            ) => Ok(DomainRequestFilter::SubString(
+                column,
+                make_filter(self.value),
+            )),
+            Some(_) => Err(format!(
+                "Unsupported field for substring matching: {}",
+                &self.field
+            )),
+            None => Err(format!("Unknown request filter: {}", &self.field)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+pub enum OrderDirection {
+    Asc,
+    Desc,
+}
+
+impl From<OrderDirection> for SortDirection {
+    fn from(direction: OrderDirection) -> Self {
+        match direction {
+            OrderDirection::Asc => Self::Ascending,
+            OrderDirection::Desc => Self::Descending,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+/// The fields by which users can be sorted.
+pub enum UserOrderField {
+    Id,
+    Email,
+    DisplayName,
+    FirstName,
+    LastName,
+    CreationDate,
+    ModifiedDate,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The order of a list of users. Users with the same value are sorted by ID.
+pub struct UserOrderBy {
+    field: UserOrderField,
+    /// Ascending by default.
+    direction: Option<OrderDirection>,
+}
+
+impl From<UserOrderBy> for UserOrder {
+    fn from(order: UserOrderBy) -> Self {
+        Self {
+            column: match order.field {
+                UserOrderField::Id => UserColumn::UserId,
+                UserOrderField::Email => UserColumn::Email,
+                UserOrderField::DisplayName => UserColumn::DisplayName,
+                UserOrderField::FirstName => UserColumn::FirstName,
+                UserOrderField::LastName => UserColumn::LastName,
+                UserOrderField::CreationDate => UserColumn::CreationDate,
+                UserOrderField::ModifiedDate => UserColumn::ModifiedDate,
+            },
+            direction: order.direction.unwrap_or(OrderDirection::Asc).into(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+/// The fields by which groups can be sorted.
+pub enum GroupOrderField {
+    Id,
+    DisplayName,
+    CreationDate,
+    ModifiedDate,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The order of a list of groups. Groups with the same value are sorted by ID.
+pub struct GroupOrderBy {
+    field: GroupOrderField,
+    /// Ascending by default.
+    direction: Option<OrderDirection>,
+}
+
+impl From<GroupOrderBy> for GroupOrder {
+    fn from(order: GroupOrderBy) -> Self {
+        Self {
+            column: match order.field {
+                GroupOrderField::Id => GroupColumn::GroupId,
+                GroupOrderField::DisplayName => GroupColumn::DisplayName,
+                GroupOrderField::CreationDate => GroupColumn::CreationDate,
+                GroupOrderField::ModifiedDate => GroupColumn::ModifiedDate,
+            },
+            direction: order.direction.unwrap_or(OrderDirection::Asc).into(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+/// The type of the values of a custom attribute.
+pub enum AttributeType {
+    String,
+    Integer,
+    JpegPhoto,
+    DateTime,
+}
+
+impl From<DomainAttributeType> for AttributeType {
+    fn from(attribute_type: DomainAttributeType) -> Self {
+        match attribute_type {
+            DomainAttributeType::String => Self::String,
+            DomainAttributeType::Integer => Self::Integer,
+            DomainAttributeType::JpegPhoto => Self::JpegPhoto,
+            DomainAttributeType::DateTime => Self::DateTime,
+        }
+    }
+}
+
+impl From<AttributeType> for DomainAttributeType {
+    fn from(attribute_type: AttributeType) -> Self {
+        match attribute_type {
+            AttributeType::String => Self::String,
+            AttributeType::Integer => Self::Integer,
+            AttributeType::JpegPhoto => Self::JpegPhoto,
+            AttributeType::DateTime => Self::DateTime,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The definition of a custom user attribute.
+pub struct AttributeSchema {
+    name: String,
+    attribute_type: AttributeType,
+    is_list: bool,
+    is_visible: bool,
+}
+
+impl From<DomainAttributeSchema> for AttributeSchema {
+    fn from(schema: DomainAttributeSchema) -> Self {
+        Self {
+            name: schema.name,
+            attribute_type: schema.attribute_type.into(),
+            is_list: schema.is_list,
+            is_visible: schema.is_visible,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The value(s) of a custom user attribute.
+pub struct AttributeValue {
+    name: String,
+    value: Vec<String>,
+}
+
+impl From<DomainAttributeValue> for AttributeValue {
+    fn from(attribute: DomainAttributeValue) -> Self {
+        Self {
+            name: attribute.name,
+            value: attribute.value,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL query type.
 pub struct Query<Handler: BackendHandler> {
@@ -134,13 +404,67 @@ impl<Handler: BackendHandler + Sync> Query<Handler> {
             .map(Into::into)?)
     }
 
+    /// The authenticated user, along with their permission level.
+    async fn me(context: &Context<Handler>) -> FieldResult<CurrentUser<Handler>> {
+        let span = debug_span!("[GraphQL query] me");
+        let user_id = &context.validation_result.user;
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        Ok(CurrentUser {
+            user: context
+                .handler
+                .get_user_details(user_id)
+                .instrument(span)
+                .await?
+                .into(),
+            permission: context.validation_result.permission.into(),
+        })
+    }
+
     async fn users(
         context: &Context<Handler>,
         #[graphql(name = "where")] filters: Option<RequestFilter>,
+        order_by: Option<UserOrderBy>,
     ) -> FieldResult<Vec<User<Handler>>> {
         let span = debug_span!("[GraphQL query] users");
         span.in_scope(|| {
-            debug!(?filters);
+            debug!(?filters, ?order_by);
+        });
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to user list".into());
+        }
+        let filters = filters.map(TryInto::try_into).transpose()?;
+        let users = match order_by {
+            None => {
+                context
+                    .handler
+                    .list_users(filters, false)
+                    .instrument(span)
+                    .await?
+            }
+            Some(order) => {
+                context
+                    .handler
+                    .list_users_page(filters, false, order.into(), 0, None)
+                    .instrument(span)
+                    .await?
+                    .users
+            }
+        };
+        Ok(users.into_iter().map(Into::into).collect())
+    }
+
+    /// The users that existed before the date and haven't logged in since, neither through LDAP
+    /// nor the web UI, to find the stale accounts.
+    async fn users_inactive_since(
+        context: &Context<Handler>,
+        date: chrono::DateTime<chrono::Utc>,
+    ) -> FieldResult<Vec<User<Handler>>> {
+        let span = debug_span!("[GraphQL query] users_inactive_since");
+        span.in_scope(|| {
+            debug!(?date);
         });
         if !context.validation_result.is_admin_or_readonly() {
             span.in_scope(|| debug!("Unauthorized"));
@@ -148,24 +472,80 @@ impl<Handler: BackendHandler + Sync> Query<Handler> {
         }
         Ok(context
             .handler
-            .list_users(filters.map(TryInto::try_into).transpose()?, false)
+            .list_users(Some(DomainRequestFilter::InactiveSince(date)), false)
             .instrument(span)
             .await
-            .map(|v| v.into_iter().map(Into::into).collect())?)
+            .map(|users| users.into_iter().map(Into::into).collect())?)
     }
 
-    async fn groups(context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
-        let span = debug_span!("[GraphQL query] groups");
+    /// A page of the users, with at most `first` users after the given cursor.
+    async fn users_connection(
+        context: &Context<Handler>,
+        filters: Option<RequestFilter>,
+        order_by: Option<UserOrderBy>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<UserConnection<Handler>> {
+        let span = debug_span!("[GraphQL query] users_connection");
+        span.in_scope(|| {
+            debug!(?filters, ?order_by, ?first, ?after);
+        });
         if !context.validation_result.is_admin_or_readonly() {
             span.in_scope(|| debug!("Unauthorized"));
-            return Err("Unauthorized access to group list".into());
+            return Err("Unauthorized access to user list".into());
         }
-        Ok(context
+        let limit = first
+            .map(|first| u64::try_from(first).map_err(|_| "Invalid negative page size"))
+            .transpose()?;
+        let offset = match after {
+            Some(cursor) => decode_cursor("user", &cursor)? + 1,
+            None => 0,
+        };
+        let page = context
             .handler
-            .list_groups(None)
+            .list_users_page(
+                filters.map(TryInto::try_into).transpose()?,
+                false,
+                order_by.map(Into::into).unwrap_or_default(),
+                offset,
+                limit,
+            )
             .instrument(span)
-            .await
-            .map(|v| v.into_iter().map(Into::into).collect())?)
+            .await?;
+        let count = page.users.len() as u64;
+        Ok(UserConnection {
+            nodes: page.users.into_iter().map(Into::into).collect(),
+            total_count: page.total_count.try_into()?,
+            page_info: PageInfo {
+                end_cursor: (count > 0).then(|| encode_cursor("user", offset + count - 1)),
+                has_next_page: offset + count < page.total_count,
+            },
+        })
+    }
+
+    async fn groups(
+        context: &Context<Handler>,
+        order_by: Option<GroupOrderBy>,
+    ) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] groups");
+        span.in_scope(|| {
+            debug!(?order_by);
+        });
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group list".into());
+        }
+        let groups = match order_by {
+            None => context.handler.list_groups(None).instrument(span).await,
+            Some(order) => {
+                context
+                    .handler
+                    .list_groups_sorted(None, order.into())
+                    .instrument(span)
+                    .await
+            }
+        };
+        Ok(groups.map(|v| v.into_iter().map(Into::into).collect())?)
     }
 
     async fn group(context: &Context<Handler>, group_id: i32) -> FieldResult<Group<Handler>> {
@@ -184,57 +564,1114 @@ impl<Handler: BackendHandler + Sync> Query<Handler> {
             .await
             .map(Into::into)?)
     }
-}
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
-/// Represents a single user.
-pub struct User<Handler: BackendHandler> {
-    user: DomainUser,
-    _phantom: std::marker::PhantomData<Box<Handler>>,
-}
+    /// The definitions of the custom group attributes.
+    async fn group_attributes_schema(
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<AttributeSchema>> {
+        let span = debug_span!("[GraphQL query] group_attributes_schema");
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the attributes schema".into());
+        }
+        Ok(context
+            .handler
+            .get_group_attributes_schema()
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The definitions of the custom user attributes.
+    async fn user_attributes_schema(
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<AttributeSchema>> {
+        let span = debug_span!("[GraphQL query] user_attributes_schema");
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the attributes schema".into());
+        }
+        Ok(context
+            .handler
+            .get_user_attributes_schema()
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The operations that modified the directory, newest first. Only available to admins.
+    async fn audit_logs(
+        context: &Context<Handler>,
+        filter: Option<AuditLogFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<AuditLogConnection> {
+        let span = debug_span!("[GraphQL query] audit_logs");
+        span.in_scope(|| {
+            debug!(?filter, ?first, ?after);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the audit log".into());
+        }
+        let limit = first
+            .map(|first| u64::try_from(first).map_err(|_| "Invalid negative page size"))
+            .transpose()?;
+        let offset = match after {
+            Some(cursor) => decode_cursor("audit", &cursor)? + 1,
+            None => 0,
+        };
+        let page = context
+            .handler
+            .list_audit_logs(filter.map(Into::into).unwrap_or_default(), offset, limit)
+            .instrument(span)
+            .await?;
+        let count = page.entries.len() as u64;
+        Ok(AuditLogConnection {
+            nodes: page.entries.into_iter().map(Into::into).collect(),
+            total_count: page.total_count.try_into()?,
+            page_info: PageInfo {
+                end_cursor: (count > 0).then(|| encode_cursor("audit", offset + count - 1)),
+                has_next_page: offset + count < page.total_count,
+            },
+        })
+    }
+
+    /// The changes to the users and groups recorded after the cursor, oldest first, at most
+    /// `first` of them (100 by default). Without a cursor, the change log is read from its start.
+    /// Only available to admins and read-only users.
+    async fn changes_since(
+        context: &Context<Handler>,
+        cursor: Option<String>,
+        first: Option<i32>,
+    ) -> FieldResult<ChangeLogPage> {
+        let span = debug_span!("[GraphQL query] changes_since");
+        span.in_scope(|| {
+            debug!(?cursor, ?first);
+        });
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the change log".into());
+        }
+        let limit = match first {
+            None => DEFAULT_CHANGE_PAGE_SIZE,
+            Some(first) => u64::try_from(first).map_err(|_| "Invalid negative page size")?,
+        };
+        let since = match &cursor {
+            Some(cursor) => i32::try_from(decode_cursor("change", cursor)?)?,
+            None => 0,
+        };
+        // The extra change tells whether there are more after the page.
+        let mut changes = context
+            .handler
+            .list_changes_page(since, limit + 1)
+            .instrument(span)
+            .await?;
+        let has_more = changes.len() as u64 > limit;
+        changes.truncate(limit as usize);
+        let last_change_id = changes.last().map_or(since, |change| change.change_id);
+        Ok(ChangeLogPage {
+            changes: changes.into_iter().map(Into::into).collect(),
+            cursor: encode_cursor("change", last_change_id as u64),
+            has_more,
+        })
+    }
+
+    /// The API tokens, without their secret value. Only available to admins.
+    async fn api_tokens(context: &Context<Handler>) -> FieldResult<Vec<ApiToken>> {
+        let span = debug_span!("[GraphQL query] api_tokens");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the API tokens".into());
+        }
+        Ok(context
+            .handler
+            .list_api_tokens()
+            .instrument(span)
+            .await
+            .map(|tokens| tokens.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The accounts of the applications binding to the LDAP server, without their password.
+    /// Only available to admins.
+    async fn service_accounts(context: &Context<Handler>) -> FieldResult<Vec<ServiceAccount>> {
+        let span = debug_span!("[GraphQL query] service_accounts");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the service accounts".into());
+        }
+        Ok(context
+            .handler
+            .list_service_accounts()
+            .instrument(span)
+            .await
+            .map(|accounts| accounts.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The requirements for the new passwords, available to any logged-in user.
+    fn password_policy(context: &Context<Handler>) -> PasswordPolicy {
+        context.handler.get_password_policy().into()
+    }
+
+    /// The active login sessions of a user, most recently used first. Defaults to the current
+    /// user.
+    async fn sessions(
+        context: &Context<Handler>,
+        user_id: Option<String>,
+    ) -> FieldResult<Vec<Session>> {
+        let span = debug_span!("[GraphQL query] sessions");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = user_id
+            .map(|id| UserId::new(&id))
+            .unwrap_or_else(|| context.validation_result.user.clone());
+        if !context.validation_result.can_read(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the sessions".into());
+        }
+        Ok(context
+            .handler
+            .list_user_sessions(&user_id)
+            .instrument(span)
+            .await
+            .map(|sessions| sessions.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The WebAuthn credentials (hardware keys, passkeys) registered by a user to log in, oldest
+    /// first. Defaults to the current user.
+    async fn webauthn_credentials(
+        context: &Context<Handler>,
+        user_id: Option<String>,
+    ) -> FieldResult<Vec<WebauthnCredential>> {
+        let span = debug_span!("[GraphQL query] webauthn_credentials");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = user_id
+            .map(|id| UserId::new(&id))
+            .unwrap_or_else(|| context.validation_result.user.clone());
+        if !context.validation_result.can_read(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the WebAuthn credentials".into());
+        }
+        Ok(context
+            .handler
+            .list_webauthn_credentials(&user_id)
+            .instrument(span)
+            .await
+            .map(|credentials| credentials.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The sign-up requests waiting for an administrator, oldest first.
+    async fn pending_users(context: &Context<Handler>) -> FieldResult<Vec<PendingUser>> {
+        let span = debug_span!("[GraphQL query] pending_users");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the pending users".into());
+        }
+        Ok(context
+            .handler
+            .list_pending_users()
+            .instrument(span)
+            .await
+            .map(|pending_users| pending_users.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The users in the recycle bin, most recently deleted first.
+    async fn deleted_users(context: &Context<Handler>) -> FieldResult<Vec<DeletedUser>> {
+        let span = debug_span!("[GraphQL query] deleted_users");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the deleted users".into());
+        }
+        Ok(context
+            .handler
+            .list_deleted_users()
+            .instrument(span)
+            .await
+            .map(|deleted_users| deleted_users.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The groups in the recycle bin, most recently deleted first.
+    async fn deleted_groups(context: &Context<Handler>) -> FieldResult<Vec<DeletedGroup>> {
+        let span = debug_span!("[GraphQL query] deleted_groups");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the deleted groups".into());
+        }
+        Ok(context
+            .handler
+            .list_deleted_groups()
+            .instrument(span)
+            .await
+            .map(|deleted_groups| deleted_groups.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The roles defined by the admins, and the groups they are granted to.
+    async fn roles(context: &Context<Handler>) -> FieldResult<Vec<Role>> {
+        let span = debug_span!("[GraphQL query] roles");
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the roles".into());
+        }
+        Ok(context
+            .handler
+            .list_roles()
+            .instrument(span)
+            .await
+            .map(|roles| roles.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The client applications of the OpenID Connect provider.
+    async fn oidc_clients(context: &Context<Handler>) -> FieldResult<Vec<OidcClient>> {
+        let span = debug_span!("[GraphQL query] oidc_clients");
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the OpenID Connect clients".into());
+        }
+        let clients = context
+            .handler
+            .list_oidc_clients()
+            .instrument(span.clone())
+            .await?;
+        let mut result = Vec::with_capacity(clients.len());
+        for client in clients {
+            let group_claims = context
+                .handler
+                .list_oidc_group_claims(&client.client_id)
+                .instrument(span.clone())
+                .await?;
+            result.push(OidcClient::new(client, group_claims));
+        }
+        Ok(result)
+    }
+
+    /// The runs of the periodic database cleanup. Only available to admins.
+    fn maintenance_status(context: &Context<Handler>) -> FieldResult<MaintenanceStatus> {
+        let span = debug_span!("[GraphQL query] maintenance_status");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the maintenance status".into());
+        }
+        Ok((&*context.scheduler_status.read().unwrap()).into())
+    }
+
+    /// Counts of the users and groups, for the dashboards. Only available to admins.
+    async fn stats(context: &Context<Handler>) -> FieldResult<Statistics> {
+        let span = debug_span!("[GraphQL query] stats");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the statistics".into());
+        }
+        Ok(context
+            .handler
+            .get_statistics()
+            .instrument(span)
+            .await
+            .map(Into::into)?)
+    }
+
+    /// Whether the server is in read-only mode, rejecting the changes.
+    fn read_only_mode(context: &Context<Handler>) -> bool {
+        context.read_only.is_enabled()
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// Restricts the audit log entries to return: all the set fields must match.
+pub struct AuditLogFilter {
+    actor: Option<String>,
+    /// The name of the operation, e.g. "create_user".
+    action: Option<String>,
+    /// The affected entry, e.g. "user:bob" or "group:3".
+    target: Option<String>,
+    /// Only the entries recorded at or after this date.
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only the entries recorded before this date.
+    until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<AuditLogFilter> for DomainAuditLogFilter {
+    fn from(filter: AuditLogFilter) -> Self {
+        Self {
+            actor: filter.actor.as_deref().map(UserId::new),
+            action: filter.action,
+            target: filter.target,
+            since: filter.since,
+            until: filter.until,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+/// Where an operation came from.
+pub enum AuditSource {
+    Ldap,
+    Graphql,
+    Scim,
+    Rest,
+    Server,
+    Cli,
+}
+
+impl From<DomainAuditSource> for AuditSource {
+    fn from(source: DomainAuditSource) -> Self {
+        match source {
+            DomainAuditSource::Ldap => Self::Ldap,
+            DomainAuditSource::Graphql => Self::Graphql,
+            DomainAuditSource::Scim => Self::Scim,
+            DomainAuditSource::Rest => Self::Rest,
+            DomainAuditSource::Server => Self::Server,
+            DomainAuditSource::Cli => Self::Cli,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// An operation that modified the directory.
+pub struct AuditLogEntry {
+    id: i32,
+    /// The user that performed the operation.
+    actor: String,
+    source: AuditSource,
+    /// The name of the operation, e.g. "create_user".
+    action: String,
+    /// The affected entry, e.g. "user:bob" or "group:3".
+    target: String,
+    /// Additional information, such as the other side of a membership.
+    details: Option<String>,
+    date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainAuditLogEntry> for AuditLogEntry {
+    fn from(entry: DomainAuditLogEntry) -> Self {
+        Self {
+            id: entry.audit_id,
+            actor: entry.actor.into_string(),
+            source: entry.source.into(),
+            action: entry.action,
+            target: entry.target,
+            details: entry.details,
+            date: entry.date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A page of the audit log.
+pub struct AuditLogConnection {
+    nodes: Vec<AuditLogEntry>,
+    /// The number of entries matching the filter, in all the pages.
+    total_count: i32,
+    page_info: PageInfo,
+}
+
+const DEFAULT_CHANGE_PAGE_SIZE: u64 = 100;
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A change to a user or a group, as recorded in the change log.
+pub struct ChangeLogEntry {
+    /// Increasing identifier of the change, the same as the one of the change events.
+    id: i32,
+    /// One of "user.created", "user.updated", "user.deleted", "group.created", "group.updated"
+    /// or "group.deleted".
+    event: String,
+    /// The user ID or group name, as of the change.
+    entry_id: String,
+    entry_uuid: String,
+    date: chrono::DateTime<chrono::Utc>,
+    /// The modified fields and custom attributes. Their values are not recorded, and must be read
+    /// from the directory.
+    changed_fields: Vec<String>,
+    /// The groups joined by the user, or the users added to the group.
+    added_memberships: Vec<String>,
+    /// The groups left by the user, or the users removed from the group.
+    removed_memberships: Vec<String>,
+    /// The former ID of a renamed user.
+    renamed_from: Option<String>,
+}
+
+impl From<DomainChange> for ChangeLogEntry {
+    fn from(change: DomainChange) -> Self {
+        let diff = change.diff.unwrap_or_default();
+        Self {
+            id: change.change_id,
+            event: event_name(change.entry_type, change.change_type),
+            entry_id: change.entry_id,
+            entry_uuid: change.entry_uuid.into_string(),
+            date: change.change_date,
+            changed_fields: diff.fields,
+            added_memberships: diff.added_memberships,
+            removed_memberships: diff.removed_memberships,
+            renamed_from: diff.renamed_from,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A page of the change log.
+pub struct ChangeLogPage {
+    changes: Vec<ChangeLogEntry>,
+    /// The cursor of the last change of the page, or the given one if there are no new changes.
+    /// It stays valid: pass it as `cursor` to get the next changes.
+    cursor: String,
+    /// Whether more changes were already recorded after the page.
+    has_more: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+/// What an API token is allowed to do.
+pub enum ApiTokenScope {
+    /// Read access to all the users and groups.
+    ReadOnly,
+    /// Read access, and management of the non-admin users and their memberships.
+    UserManagement,
+    /// Same as an admin user.
+    Admin,
+}
+
+impl From<DomainApiTokenScope> for ApiTokenScope {
+    fn from(scope: DomainApiTokenScope) -> Self {
+        match scope {
+            DomainApiTokenScope::ReadOnly => Self::ReadOnly,
+            DomainApiTokenScope::UserManagement => Self::UserManagement,
+            DomainApiTokenScope::Admin => Self::Admin,
+        }
+    }
+}
+
+impl From<ApiTokenScope> for DomainApiTokenScope {
+    fn from(scope: ApiTokenScope) -> Self {
+        match scope {
+            ApiTokenScope::ReadOnly => Self::ReadOnly,
+            ApiTokenScope::UserManagement => Self::UserManagement,
+            ApiTokenScope::Admin => Self::Admin,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A long-lived token to access the API without logging in, as a bearer token.
+pub struct ApiToken {
+    id: i32,
+    name: String,
+    scope: ApiTokenScope,
+    /// The user that created the token.
+    created_by: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainApiToken> for ApiToken {
+    fn from(token: DomainApiToken) -> Self {
+        Self {
+            id: token.token_id,
+            name: token.name,
+            scope: token.scope.into(),
+            created_by: token.created_by.into_string(),
+            creation_date: token.creation_date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// An account for an application to bind to the LDAP server, as
+/// "uid=<id>,ou=services,<base DN>". It doesn't appear among the users.
+pub struct ServiceAccount {
+    id: String,
+    description: Option<String>,
+    /// Whether it can read the whole directory, otherwise it sees what the regular users see.
+    read_all: bool,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainServiceAccount> for ServiceAccount {
+    fn from(account: DomainServiceAccount) -> Self {
+        Self {
+            id: account.service_id,
+            description: account.description,
+            read_all: account.read_all,
+            creation_date: account.creation_date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The requirements for the new passwords. The list of banned passwords is not exposed.
+pub struct PasswordPolicy {
+    min_length: i32,
+    require_lowercase: bool,
+    require_uppercase: bool,
+    require_digit: bool,
+    /// Requires a character that is neither a letter nor a digit.
+    require_special: bool,
+    /// Number of previous passwords of the user that cannot be reused.
+    history_depth: i32,
+    /// Number of days after which the passwords expire, or 0 if they never expire.
+    max_age_days: i32,
+}
+
+impl From<PasswordPolicyOptions> for PasswordPolicy {
+    fn from(policy: PasswordPolicyOptions) -> Self {
+        Self {
+            min_length: policy.min_length.try_into().unwrap_or(i32::MAX),
+            require_lowercase: policy.require_lowercase,
+            require_uppercase: policy.require_uppercase,
+            require_digit: policy.require_digit,
+            require_special: policy.require_special,
+            history_depth: policy.history_depth.try_into().unwrap_or(i32::MAX),
+            max_age_days: policy.max_age_days.try_into().unwrap_or(i32::MAX),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A login session, backed by a refresh token. The client details are unknown for the sessions
+/// opened before they were recorded.
+pub struct Session {
+    /// Opaque identifier of the session, to revoke it.
+    id: String,
+    creation_date: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the session was last used to get a new token.
+    last_used: Option<chrono::DateTime<chrono::Utc>>,
+    expiry_date: chrono::DateTime<chrono::Utc>,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+}
+
+impl From<DomainSession> for Session {
+    fn from(session: DomainSession) -> Self {
+        Self {
+            // GraphQL integers are only 32 bits.
+            id: session.session_id.to_string(),
+            creation_date: session.creation_date,
+            last_used: session.last_used,
+            expiry_date: session.expiry_date,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+        }
+    }
+}
+
+fn to_graphql_count(count: u64) -> i32 {
+    count.try_into().unwrap_or(i32::MAX)
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The number of direct members of a group.
+pub struct GroupMemberCount {
+    group_id: i32,
+    display_name: String,
+    member_count: i32,
+}
+
+impl From<DomainGroupMemberCount> for GroupMemberCount {
+    fn from(count: DomainGroupMemberCount) -> Self {
+        Self {
+            group_id: count.group_id.0,
+            display_name: count.display_name,
+            member_count: to_graphql_count(count.member_count),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The most recent login of a user, through LDAP or the web UI.
+pub struct UserLastLogin {
+    user_id: String,
+    last_login: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainUserLastLogin> for UserLastLogin {
+    fn from(last_login: DomainUserLastLogin) -> Self {
+        Self {
+            user_id: last_login.user_id.into_string(),
+            last_login: last_login.last_login,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// Aggregate figures about the directory.
+pub struct Statistics {
+    user_count: i32,
+    group_count: i32,
+    /// All the groups, by ID.
+    members_per_group: Vec<GroupMemberCount>,
+    /// The users who can't log in until their password is reset.
+    users_without_password: i32,
+    disabled_users: i32,
+    /// Most recent first, only for the users who logged in.
+    last_logins: Vec<UserLastLogin>,
+}
+
+impl From<DomainDirectoryStatistics> for Statistics {
+    fn from(stats: DomainDirectoryStatistics) -> Self {
+        Self {
+            user_count: to_graphql_count(stats.user_count),
+            group_count: to_graphql_count(stats.group_count),
+            members_per_group: stats
+                .members_per_group
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            users_without_password: to_graphql_count(stats.users_without_password),
+            disabled_users: to_graphql_count(stats.disabled_users),
+            last_logins: stats.last_logins.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A WebAuthn credential registered through the web UI, to log in without a password.
+pub struct WebauthnCredential {
+    /// Base64url-encoded ID of the credential, to delete it.
+    id: String,
+    /// Name given by the user when registering the credential.
+    display_name: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    last_used: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainWebauthnCredential> for WebauthnCredential {
+    fn from(credential: DomainWebauthnCredential) -> Self {
+        Self {
+            id: credential.credential_id,
+            display_name: credential.display_name,
+            creation_date: credential.creation_date,
+            last_used: credential.last_used,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A sign-up request, to approve or reject.
+pub struct PendingUser {
+    /// The requested user ID.
+    id: String,
+    email: String,
+    display_name: Option<String>,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A deleted user, that can be restored until it is purged.
+pub struct DeletedUser {
+    id: String,
+    email: String,
+    display_name: Option<String>,
+    deletion_date: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A deleted group, that can be restored until it is purged.
+pub struct DeletedGroup {
+    id: i32,
+    display_name: String,
+    deletion_date: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The periodic cleanup of the database.
+pub struct MaintenanceStatus {
+    /// Cron expression of the runs, with the seconds.
+    schedule: String,
+    /// The jobs of each run, e.g. expired_tokens.
+    jobs: Vec<String>,
+    last_run: Option<chrono::DateTime<chrono::Utc>>,
+    /// The jobs that failed during the last run.
+    failed_jobs: Vec<String>,
+    next_run: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<&SchedulerRuns> for MaintenanceStatus {
+    fn from(runs: &SchedulerRuns) -> Self {
+        let names =
+            |jobs: &[MaintenanceJob]| jobs.iter().map(|job| job.name().to_owned()).collect();
+        Self {
+            schedule: runs.schedule.clone(),
+            jobs: names(&runs.jobs),
+            last_run: runs.last_run,
+            failed_jobs: names(&runs.failed_jobs),
+            next_run: runs.next_run,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A role defined by the admins. The members of the groups it is granted to can, on top of their
+/// own permissions, read everything, reset the passwords of the non-admin users, or manage the
+/// non-admin members of a group.
+pub struct Role {
+    id: i32,
+    display_name: String,
+    read_all: bool,
+    reset_passwords: bool,
+    /// The holders can modify the non-admin users that are only in this group, and remove non-admin
+    /// users from it.
+    managed_group_id: Option<i32>,
+    /// The groups whose members hold the role.
+    group_ids: Vec<i32>,
+}
+
+impl From<DomainRole> for Role {
+    fn from(role: DomainRole) -> Self {
+        Self {
+            id: role.role_id,
+            display_name: role.display_name,
+            read_all: role.read_all,
+            reset_passwords: role.reset_passwords,
+            managed_group_id: role.managed_group_id.map(|g| g.0),
+            group_ids: role.group_ids.into_iter().map(|g| g.0).collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A client application of the OpenID Connect provider. Its secret is only returned when it is
+/// created.
+pub struct OidcClient {
+    client_id: String,
+    display_name: String,
+    /// The only URIs the users are sent back to after logging in.
+    redirect_uris: Vec<String>,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    /// The claims added to the tokens of the members of the groups.
+    group_claims: Vec<OidcGroupClaim>,
+}
+
+impl OidcClient {
+    pub fn new(client: DomainOidcClient, group_claims: Vec<DomainOidcGroupClaim>) -> Self {
+        Self {
+            client_id: client.client_id,
+            display_name: client.display_name,
+            redirect_uris: client.redirect_uris,
+            creation_date: client.creation_date,
+            group_claims: group_claims.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A claim added to the tokens of the members of the group, e.g. `role: "admin"`. If several
+/// groups of a user set the same claim, its value is the list of their values.
+pub struct OidcGroupClaim {
+    group_id: i32,
+    claim: String,
+    value: String,
+}
+
+impl From<DomainOidcGroupClaim> for OidcGroupClaim {
+    fn from(claim: DomainOidcGroupClaim) -> Self {
+        Self {
+            group_id: claim.group_id.0,
+            claim: claim.claim,
+            value: claim.value,
+        }
+    }
+}
+
+impl From<DomainPendingUser> for PendingUser {
+    fn from(pending_user: DomainPendingUser) -> Self {
+        Self {
+            id: pending_user.user_id.into_string(),
+            email: pending_user.email,
+            display_name: pending_user.display_name,
+            creation_date: pending_user.creation_date,
+        }
+    }
+}
+
+impl From<DomainDeletedUser> for DeletedUser {
+    fn from(deleted_user: DomainDeletedUser) -> Self {
+        Self {
+            id: deleted_user.user_id.into_string(),
+            email: deleted_user.email,
+            display_name: deleted_user.display_name,
+            deletion_date: deleted_user.deletion_date,
+        }
+    }
+}
+
+impl From<DomainDeletedGroup> for DeletedGroup {
+    fn from(deleted_group: DomainDeletedGroup) -> Self {
+        Self {
+            id: deleted_group.group_id.0,
+            display_name: deleted_group.display_name,
+            deletion_date: deleted_group.deletion_date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, GraphQLEnum)]
+/// What a user is allowed to do, based on their groups.
+pub enum PermissionLevel {
+    /// Member of `lldap_admin`: full access.
+    Admin,
+    /// Member of `lldap_password_manager`: can read everything, and change the password of
+    /// non-admin users.
+    PasswordManager,
+    /// API token with the user management scope: can read everything, and manage the non-admin
+    /// users.
+    UserManager,
+    /// Member of `lldap_strict_readonly`: can read everything.
+    Readonly,
+    /// Can only read and change their own details.
+    Regular,
+}
+
+impl From<Permission> for PermissionLevel {
+    fn from(permission: Permission) -> Self {
+        match permission {
+            Permission::Admin => Self::Admin,
+            Permission::PasswordManager => Self::PasswordManager,
+            Permission::UserManager => Self::UserManager,
+            Permission::Readonly => Self::Readonly,
+            Permission::Regular => Self::Regular,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+/// The authenticated user.
+pub struct CurrentUser<Handler: BackendHandler> {
+    user: User<Handler>,
+    permission: PermissionLevel,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> CurrentUser<Handler> {
+    fn user(&self) -> &User<Handler> {
+        &self.user
+    }
+    fn permission(&self) -> PermissionLevel {
+        self.permission
+    }
+}
+
+// The cursor of an element is its (opaque) index in the list of results, tagged with the kind of
+// list so that cursors cannot be mixed up.
+fn encode_cursor(kind: &str, index: u64) -> String {
+    base64::encode(format!("{}:{}", kind, index))
+}
+
+fn decode_cursor(kind: &str, cursor: &str) -> Result<u64, String> {
+    base64::decode(cursor)
+        .ok()
+        .and_then(|cursor| String::from_utf8(cursor).ok())
+        .and_then(|cursor| cursor.strip_prefix(kind)?.strip_prefix(':')?.parse().ok())
+        .ok_or_else(|| format!("Invalid cursor: {}", cursor))
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// Information about the current page of a paginated list.
+pub struct PageInfo {
+    /// The cursor of the last element of the page, to pass as `after` to get the next page.
+    end_cursor: Option<String>,
+    has_next_page: bool,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+/// A page of users.
+pub struct UserConnection<Handler: BackendHandler> {
+    nodes: Vec<User<Handler>>,
+    total_count: i32,
+    page_info: PageInfo,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> UserConnection<Handler> {
+    fn nodes(&self) -> &[User<Handler>] {
+        &self.nodes
+    }
+    /// The number of users matching the filter, in all the pages.
+    fn total_count(&self) -> i32 {
+        self.total_count
+    }
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+/// Represents a single user.
+pub struct User<Handler: BackendHandler> {
+    user: DomainUser,
+    _phantom: std::marker::PhantomData<Box<Handler>>,
+}
+
+#[cfg(test)]
+impl<Handler: BackendHandler> Default for User<Handler> {
+    fn default() -> Self {
+        Self {
+            user: DomainUser::default(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> User<Handler> {
+    fn id(&self) -> &str {
+        self.user.user_id.as_str()
+    }
+
+    fn email(&self) -> &str {
+        &self.user.email
+    }
+
+    /// The other email addresses of the user, e.g. the aliases of their mailbox.
+    fn secondary_emails(&self) -> Vec<String> {
+        self.user.secondary_emails.clone()
+    }
+
+    fn display_name(&self) -> &str {
+        self.user.display_name.as_deref().unwrap_or("")
+    }
+
+    fn first_name(&self) -> &str {
+        self.user.first_name.as_deref().unwrap_or("")
+    }
+
+    fn last_name(&self) -> &str {
+        self.user.last_name.as_deref().unwrap_or("")
+    }
+
+    fn avatar(&self) -> Option<String> {
+        self.user.avatar.as_ref().map(String::from)
+    }
+
+    fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
+        self.user.creation_date
+    }
+
+    fn modified_date(&self) -> chrono::DateTime<chrono::Utc> {
+        self.user.modified_date
+    }
+
+    fn uuid(&self) -> &str {
+        self.user.uuid.as_str()
+    }
+
+    fn uid_number(&self) -> Option<i32> {
+        self.user.uid_number
+    }
+
+    fn gid_number(&self) -> Option<i32> {
+        self.user.gid_number
+    }
+
+    fn home_directory(&self) -> Option<&str> {
+        self.user.home_directory.as_deref()
+    }
+
+    fn login_shell(&self) -> Option<&str> {
+        self.user.login_shell.as_deref()
+    }
+
+    fn telephone_number(&self) -> Option<&str> {
+        self.user.telephone_number.as_deref()
+    }
+
+    fn mobile(&self) -> Option<&str> {
+        self.user.mobile.as_deref()
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.user.title.as_deref()
+    }
+
+    fn department(&self) -> Option<&str> {
+        self.user.department.as_deref()
+    }
+
+    fn locality(&self) -> Option<&str> {
+        self.user.locality.as_deref()
+    }
+
+    fn postal_address(&self) -> Option<&str> {
+        self.user.postal_address.as_deref()
+    }
 
-#[cfg(test)]
-impl<Handler: BackendHandler> Default for User<Handler> {
-    fn default() -> Self {
-        Self {
-            user: DomainUser::default(),
-            _phantom: std::marker::PhantomData,
-        }
+    /// The preferred language, as a BCP 47 tag such as "fr-CA", used for the emails.
+    fn locale(&self) -> Option<&str> {
+        self.user.locale.as_deref()
     }
-}
 
-#[graphql_object(context = Context<Handler>)]
-impl<Handler: BackendHandler + Sync> User<Handler> {
-    fn id(&self) -> &str {
-        self.user.user_id.as_str()
+    /// The IANA time zone, such as "Europe/Paris".
+    fn timezone(&self) -> Option<&str> {
+        self.user.timezone.as_deref()
     }
 
-    fn email(&self) -> &str {
-        &self.user.email
+    fn ssh_public_keys(&self) -> Vec<String> {
+        self.user.ssh_public_keys.iter().cloned().collect()
     }
 
-    fn display_name(&self) -> &str {
-        self.user.display_name.as_deref().unwrap_or("")
+    /// Disabled users cannot log in or bind.
+    fn enabled(&self) -> bool {
+        self.user.enabled
     }
 
-    fn first_name(&self) -> &str {
-        self.user.first_name.as_deref().unwrap_or("")
+    /// The account can only be used after this date, if set.
+    fn valid_from(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.valid_from
     }
 
-    fn last_name(&self) -> &str {
-        self.user.last_name.as_deref().unwrap_or("")
+    /// The account can only be used before this date, if set.
+    fn valid_until(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.valid_until
     }
 
-    fn avatar(&self) -> Option<String> {
-        self.user.avatar.as_ref().map(String::from)
+    /// The last successful LDAP bind, to within a few minutes.
+    fn last_ldap_login(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.last_ldap_login
     }
 
-    fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
-        self.user.creation_date
+    /// The last login to the web UI, to within a few minutes.
+    fn last_web_login(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.last_web_login
     }
 
-    fn uuid(&self) -> &str {
-        self.user.uuid.as_str()
+    /// The custom attributes of the user. Attributes that are not visible are only returned to
+    /// admins.
+    async fn attributes(&self, context: &Context<Handler>) -> FieldResult<Vec<AttributeValue>> {
+        let span = debug_span!("[GraphQL query] user::attributes");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        let attributes = self.user.attributes.iter().cloned();
+        if context.validation_result.is_admin_or_readonly() {
+            return Ok(attributes.map(Into::into).collect());
+        }
+        let visible_attributes: Vec<_> = context
+            .handler
+            .get_user_attributes_schema()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .filter(|s| s.is_visible)
+            .map(|s| s.name)
+            .collect();
+        Ok(attributes
+            .filter(|a| visible_attributes.contains(&a.name))
+            .map(Into::into)
+            .collect())
+    }
+
+    /// When the account is locked after too many failed logins, the end of the lockout.
+    async fn locked_until(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<Option<chrono::DateTime<chrono::Utc>>> {
+        let span = debug_span!("[GraphQL query] user::locked_until");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        Ok(context
+            .handler
+            .get_account_lockout(&self.user.user_id)
+            .instrument(span)
+            .await?)
     }
 
     /// The groups to which this user belongs.
@@ -243,9 +1680,13 @@ impl<Handler: BackendHandler + Sync> User<Handler> {
         span.in_scope(|| {
             debug!(user_id = ?self.user.user_id);
         });
+        if !context.validation_result.can_read_own_entries() {
+            span.in_scope(|| debug!("Hidden groups"));
+            return Ok(Vec::new());
+        }
         Ok(context
-            .handler
-            .get_user_groups(&self.user.user_id)
+            .user_groups_loader
+            .load(context.handler.as_ref(), &self.user.user_id)
             .instrument(span)
             .await
             .map(|set| set.into_iter().map(Into::into).collect())?)
@@ -277,10 +1718,33 @@ pub struct Group<Handler: BackendHandler> {
     display_name: String,
     creation_date: chrono::DateTime<chrono::Utc>,
     uuid: String,
+    gid_number: Option<i32>,
+    // The fields below are only known when the group was fully loaded, and fetched otherwise.
+    modified_date: Option<chrono::DateTime<chrono::Utc>>,
+    description: Option<Option<String>>,
+    mail: Option<Option<String>>,
     members: Option<Vec<String>>,
+    attributes: Option<Vec<DomainAttributeValue>>,
     _phantom: std::marker::PhantomData<Box<Handler>>,
 }
 
+impl<Handler: BackendHandler> Group<Handler> {
+    async fn fetch_group(&self, context: &Context<Handler>) -> FieldResult<DomainGroup> {
+        let span = debug_span!("[GraphQL query] group::fetch_group");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        Ok(context
+            .handler
+            .list_groups(Some(GroupRequestFilter::GroupId(GroupId(self.group_id))))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or("Group not found")?)
+    }
+}
+
 #[graphql_object(context = Context<Handler>)]
 impl<Handler: BackendHandler + Sync> Group<Handler> {
     fn id(&self) -> i32 {
@@ -292,16 +1756,47 @@ impl<Handler: BackendHandler + Sync> Group<Handler> {
     fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
         self.creation_date
     }
+    async fn modified_date(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<chrono::DateTime<chrono::Utc>> {
+        if let Some(modified_date) = self.modified_date {
+            return Ok(modified_date);
+        }
+        Ok(self.fetch_group(context).await?.modified_date)
+    }
     fn uuid(&self) -> String {
         self.uuid.clone()
     }
+    fn gid_number(&self) -> Option<i32> {
+        self.gid_number
+    }
+    async fn description(&self, context: &Context<Handler>) -> FieldResult<Option<String>> {
+        if let Some(description) = &self.description {
+            return Ok(description.clone());
+        }
+        Ok(self.fetch_group(context).await?.description)
+    }
+    /// The address of the group, e.g. of its mailing list.
+    async fn mail(&self, context: &Context<Handler>) -> FieldResult<Option<String>> {
+        if let Some(mail) = &self.mail {
+            return Ok(mail.clone());
+        }
+        Ok(self.fetch_group(context).await?.mail)
+    }
     /// The groups to which this user belongs.
     async fn users(&self, context: &Context<Handler>) -> FieldResult<Vec<User<Handler>>> {
         let span = debug_span!("[GraphQL query] group::users");
         span.in_scope(|| {
             debug!(name = %self.display_name);
         });
-        if !context.validation_result.is_admin_or_readonly() {
+        if !context.validation_result.is_admin_or_readonly()
+            && !context
+                .validation_result
+                .capabilities
+                .managed_memberships
+                .contains(&GroupId(self.group_id))
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized access to group data".into());
         }
@@ -315,6 +1810,80 @@ impl<Handler: BackendHandler + Sync> Group<Handler> {
             .await
             .map(|v| v.into_iter().map(Into::into).collect())?)
     }
+    /// The IDs of the users allowed to add and remove the members of this group.
+    async fn managers(&self, context: &Context<Handler>) -> FieldResult<Vec<String>> {
+        let span = debug_span!("[GraphQL query] group::managers");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group data".into());
+        }
+        Ok(context
+            .handler
+            .list_group_managers(GroupId(self.group_id))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(|u| u.to_string())
+            .collect())
+    }
+    /// The custom attributes of the group. Attributes that are not visible are only returned to
+    /// admins.
+    async fn attributes(&self, context: &Context<Handler>) -> FieldResult<Vec<AttributeValue>> {
+        let span = debug_span!("[GraphQL query] group::attributes");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        let attributes = match &self.attributes {
+            Some(attributes) => attributes.clone(),
+            None => context
+                .handler
+                .list_groups(Some(GroupRequestFilter::GroupId(GroupId(self.group_id))))
+                .instrument(span.clone())
+                .await?
+                .into_iter()
+                .flat_map(|g| g.attributes)
+                .collect(),
+        };
+        if context.validation_result.is_admin_or_readonly() {
+            return Ok(attributes.into_iter().map(Into::into).collect());
+        }
+        let visible_attributes: Vec<_> = context
+            .handler
+            .get_group_attributes_schema()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .filter(|s| s.is_visible)
+            .map(|s| s.name)
+            .collect();
+        Ok(attributes
+            .into_iter()
+            .filter(|a| visible_attributes.contains(&a.name))
+            .map(Into::into)
+            .collect())
+    }
+    /// The groups directly nested in this group.
+    async fn subgroups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] group::subgroups");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group data".into());
+        }
+        Ok(context
+            .handler
+            .list_groups(Some(GroupRequestFilter::ParentGroup(GroupId(
+                self.group_id,
+            ))))
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
 }
 
 impl<Handler: BackendHandler> From<GroupDetails> for Group<Handler> {
@@ -324,7 +1893,12 @@ impl<Handler: BackendHandler> From<GroupDetails> for Group<Handler> {
             display_name: group_details.display_name,
             creation_date: group_details.creation_date,
             uuid: group_details.uuid.into_string(),
+            gid_number: group_details.gid_number,
+            modified_date: None,
+            description: None,
+            mail: None,
             members: None,
+            attributes: None,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -337,7 +1911,12 @@ impl<Handler: BackendHandler> From<DomainGroup> for Group<Handler> {
             display_name: group.display_name,
             creation_date: group.creation_date,
             uuid: group.uuid.into_string(),
+            gid_number: group.gid_number,
+            modified_date: Some(group.modified_date),
+            description: Some(group.description),
+            mail: Some(group.mail),
             members: Some(group.users.into_iter().map(UserId::into_string).collect()),
+            attributes: Some(group.attributes),
             _phantom: std::marker::PhantomData,
         }
     }
@@ -346,14 +1925,23 @@ impl<Handler: BackendHandler> From<DomainGroup> for Group<Handler> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{domain::handler::MockTestBackendHandler, infra::auth_service::ValidationResults};
+    use crate::{
+        domain::handler::{MockTestBackendHandler, UserPage},
+        infra::{
+            auth_service::ValidationResults,
+            configuration::{AvatarOptions, MailOptions},
+            db_cleaner::SchedulerStatus,
+            graphql::loader::UserGroupsLoader,
+            read_only::ReadOnlyMode,
+        },
+    };
     use chrono::TimeZone;
     use juniper::{
         execute, graphql_value, DefaultScalarValue, EmptyMutation, EmptySubscription, GraphQLType,
         RootNode, Variables,
     };
     use mockall::predicate::eq;
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
 
     fn schema<'q, C, Q>(query_root: Q) -> RootNode<'q, Q, EmptyMutation<C>, EmptySubscription<C>>
     where
@@ -400,6 +1988,7 @@ mod tests {
             display_name: "Bobbersons".to_string(),
             creation_date: chrono::Utc.timestamp_nanos(42),
             uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            gid_number: None,
         });
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
@@ -407,6 +1996,12 @@ mod tests {
 
         let context = Context::<MockTestBackendHandler> {
             handler: Box::new(mock),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
             validation_result: ValidationResults::admin(),
         };
 
@@ -433,6 +2028,207 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn get_me() {
+        const QUERY: &str = r#"{
+          me {
+            user {
+              id
+              groups {
+                displayName
+              }
+            }
+            permission
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(DomainUser {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        let mut groups = HashSet::new();
+        groups.insert(GroupDetails {
+            group_id: GroupId(3),
+            display_name: "lldap_strict_readonly".to_string(),
+            creation_date: chrono::Utc.timestamp_nanos(42),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            gid_number: None,
+        });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(groups));
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
+            validation_result: ValidationResults::new(UserId::new("bob"), Permission::Readonly),
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "me": {
+                        "user": {
+                            "id": "bob",
+                            "groups": [{"displayName": "lldap_strict_readonly"}]
+                        },
+                        "permission": "READONLY"
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn list_users_connection() {
+        const QUERY: &str = r#"{
+          usersConnection(orderBy: {field: EMAIL, direction: DESC}, first: 1, after: "dXNlcjow") {
+            nodes {
+              id
+            }
+            totalCount
+            pageInfo {
+              endCursor
+              hasNextPage
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users_page()
+            .with(
+                eq(None),
+                eq(false),
+                eq(UserOrder {
+                    column: UserColumn::Email,
+                    direction: SortDirection::Descending,
+                }),
+                eq(1),
+                eq(Some(1)),
+            )
+            .return_once(|_, _, _, _, _| {
+                Ok(UserPage {
+                    users: vec![DomainUserAndGroups {
+                        user: DomainUser {
+                            user_id: UserId::new("john"),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    }],
+                    total_count: 3,
+                })
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
+            validation_result: ValidationResults::admin(),
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "usersConnection": {
+                        "nodes": [{"id": "john"}],
+                        "totalCount": 3,
+                        "pageInfo": {
+                            "endCursor": "dXNlcjox",
+                            "hasNextPage": true
+                        }
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn list_users_rich_filter() {
+        const QUERY: &str = r#"{
+          users(filters: {
+            all: [
+              {contains: {field: "email", value: "bobbers"}},
+              {startsWith: {field: "displayName", value: "Bob"}},
+              {notEquals: {field: "memberOf", value: "admins"}}
+            ]}) {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(DomainRequestFilter::And(vec![
+                    DomainRequestFilter::SubString(
+                        UserColumn::Email,
+                        SubStringFilter {
+                            any: vec!["bobbers".to_string()],
+                            ..Default::default()
+                        },
+                    ),
+                    DomainRequestFilter::SubString(
+                        UserColumn::DisplayName,
+                        SubStringFilter {
+                            initial: Some("Bob".to_string()),
+                            ..Default::default()
+                        },
+                    ),
+                    DomainRequestFilter::Not(Box::new(DomainRequestFilter::MemberOf(
+                        "admins".to_string(),
+                    ))),
+                ]))),
+                eq(false),
+            )
+            .return_once(|_, _| {
+                Ok(vec![DomainUserAndGroups {
+                    user: DomainUser {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
+            validation_result: ValidationResults::admin(),
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((graphql_value!({"users": [{"id": "bob"}]}), vec![]))
+        );
+    }
+
     #[tokio::test]
     async fn list_users() {
         const QUERY: &str = r#"{
@@ -487,6 +2283,12 @@ mod tests {
 
         let context = Context::<MockTestBackendHandler> {
             handler: Box::new(mock),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
             validation_result: ValidationResults::admin(),
         };
 
@@ -511,4 +2313,79 @@ mod tests {
             ))
         );
     }
+
+    #[tokio::test]
+    async fn list_users_groups_batched() {
+        const QUERY: &str = r#"{
+          users {
+            id
+            groups {
+              displayName
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(None), eq(false))
+            .return_once(|_, _| {
+                Ok(["bob", "robert"]
+                    .into_iter()
+                    .map(|user_id| DomainUserAndGroups {
+                        user: DomainUser {
+                            user_id: UserId::new(user_id),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    })
+                    .collect())
+            });
+        mock.expect_get_users_groups()
+            .times(1)
+            .return_once(|user_ids| {
+                assert_eq!(user_ids.len(), 2);
+                Ok(HashMap::from([(
+                    UserId::new("bob"),
+                    HashSet::from([GroupDetails {
+                        group_id: GroupId(3),
+                        display_name: "Bobbersons".to_string(),
+                        creation_date: chrono::Utc.timestamp_nanos(42),
+                        uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        gid_number: None,
+                    }]),
+                )]))
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            mail_options: MailOptions::default(),
+            server_url: String::new(),
+            scheduler_status: SchedulerStatus::default(),
+            user_groups_loader: UserGroupsLoader::default(),
+            avatar_options: AvatarOptions::default(),
+            read_only: ReadOnlyMode::default(),
+            validation_result: ValidationResults::admin(),
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "users": [
+                        {
+                            "id": "bob",
+                            "groups": [{"displayName": "Bobbersons"}]
+                        },
+                        {
+                            "id": "robert",
+                            "groups": []
+                        },
+                    ]
+                }),
+                vec![]
+            ))
+        );
+    }
 }
@@ -0,0 +1,83 @@
+use crate::{
+    domain::handler::BackendHandler,
+    infra::{
+        change_events::{ChangeEvent as DomainChangeEvent, ChangeEventBus},
+        graphql::api::Context,
+    },
+};
+use futures::Stream;
+use juniper::{graphql_subscription, FieldResult, GraphQLObject};
+use std::pin::Pin;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, debug_span, warn};
+
+type ChangeEventStream = Pin<Box<dyn Stream<Item = FieldResult<ChangeEvent>> + Send>>;
+
+/// The top-level GraphQL subscription type.
+pub struct Subscription<Handler: BackendHandler> {
+    events: ChangeEventBus,
+    _phantom: std::marker::PhantomData<Box<Handler>>,
+}
+
+impl<Handler: BackendHandler> Subscription<Handler> {
+    pub fn new(events: ChangeEventBus) -> Self {
+        Self {
+            events,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+#[graphql_subscription(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> Subscription<Handler> {
+    /// The changes to the users and groups, as they happen.
+    async fn changes(&self, context: &Context<Handler>) -> FieldResult<ChangeEventStream> {
+        let span = debug_span!("[GraphQL subscription] changes");
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the change events".into());
+        }
+        let stream = futures::stream::unfold(self.events.subscribe(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((Ok(event.into()), events)),
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Subscriber too slow, {} change events were dropped", count)
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A change to a user or a group.
+pub struct ChangeEvent {
+    /// Increasing identifier of the event.
+    id: i32,
+    /// One of "user.created", "user.updated", "user.deleted", "group.created", "group.updated"
+    /// or "group.deleted". Membership changes are reported as updates of both the user and the
+    /// group.
+    event: String,
+    /// The user ID or group name.
+    entry_id: String,
+    entry_uuid: String,
+    date: chrono::DateTime<chrono::Utc>,
+    /// The current members of the group, for the group creations and updates.
+    members: Option<Vec<String>>,
+}
+
+impl From<DomainChangeEvent> for ChangeEvent {
+    fn from(event: DomainChangeEvent) -> Self {
+        Self {
+            id: event.id,
+            event: event.event,
+            entry_id: event.entry_id,
+            entry_uuid: event.entry_uuid.into_string(),
+            date: event.date,
+            members: event.members,
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! The GraphQL operations over WebSocket, with the graphql-ws protocol. Unlike the handler of
+//! `juniper_actix`, the operations are checked before they are started.
+
+use actix::{prelude::*, Actor, StreamHandler};
+use actix_web::{
+    http::header::{HeaderName, HeaderValue},
+    web, HttpRequest, HttpResponse,
+};
+use actix_web_actors::ws;
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use juniper::{DefaultScalarValue, GraphQLSubscriptionType, GraphQLTypeAsync, RootNode};
+use juniper_graphql_ws::{ArcSchema, ClientMessage, Connection, ConnectionConfig, ServerMessage};
+use std::{convert::TryFrom, sync::Arc};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Checks the document of an operation before it is started.
+pub type OperationCheck = Box<dyn Fn(&str) -> Result<(), String>>;
+
+type GraphqlConnection<Query, Mutation, Subscription, CtxT> = Connection<
+    ArcSchema<Query, Mutation, Subscription, CtxT, DefaultScalarValue>,
+    ConnectionConfig<CtxT>,
+>;
+
+/// Forwards the messages between the WebSocket and the graphql-ws connection.
+struct SubscriptionActor<Query, Mutation, Subscription, CtxT>
+where
+    Query: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Query::TypeInfo: Send + Sync,
+    Mutation: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Mutation::TypeInfo: Send + Sync,
+    Subscription: GraphQLSubscriptionType<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Subscription::TypeInfo: Send + Sync,
+    CtxT: Unpin + Send + Sync + 'static,
+{
+    graphql_tx:
+        Arc<Mutex<SplitSink<GraphqlConnection<Query, Mutation, Subscription, CtxT>, WsMessage>>>,
+    graphql_rx: Arc<Mutex<SplitStream<GraphqlConnection<Query, Mutation, Subscription, CtxT>>>>,
+    check_operation: OperationCheck,
+}
+
+/// Serves the graphql-ws protocol over a WebSocket connection, rejecting the operations that
+/// don't pass `check_operation`.
+pub async fn subscriptions_handler<Query, Mutation, Subscription, CtxT>(
+    req: HttpRequest,
+    stream: web::Payload,
+    root_node: Arc<RootNode<'static, Query, Mutation, Subscription>>,
+    config: ConnectionConfig<CtxT>,
+    check_operation: OperationCheck,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    Query: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Query::TypeInfo: Send + Sync,
+    Mutation: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Mutation::TypeInfo: Send + Sync,
+    Subscription: GraphQLSubscriptionType<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Subscription::TypeInfo: Send + Sync,
+    CtxT: Unpin + Send + Sync + 'static,
+{
+    let (graphql_tx, graphql_rx) =
+        Connection::new(ArcSchema(root_node), config).split::<WsMessage>();
+    let mut response = ws::start(
+        SubscriptionActor {
+            graphql_tx: Arc::new(Mutex::new(graphql_tx)),
+            graphql_rx: Arc::new(Mutex::new(graphql_rx)),
+            check_operation,
+        },
+        &req,
+        stream,
+    )?;
+    response.headers_mut().insert(
+        HeaderName::from_static("sec-websocket-protocol"),
+        HeaderValue::from_static("graphql-ws"),
+    );
+    Ok(response)
+}
+
+/// The error message to send back instead of starting the operation, if the message starts one
+/// that doesn't pass the check.
+fn check_start_message(text: &str, check_operation: &OperationCheck) -> Option<String> {
+    let message = serde_json::from_str::<serde_json::Value>(text).ok()?;
+    if message["type"] != "start" {
+        return None;
+    }
+    // Without a document, the operation is rejected by the connection.
+    let document = message["payload"]["query"].as_str()?;
+    let error = check_operation(document).err()?;
+    warn!("Rejected a GraphQL operation on the WebSocket: {}", error);
+    Some(
+        serde_json::json!({
+            "type": "error",
+            "id": message["id"],
+            "payload": [{ "message": error }],
+        })
+        .to_string(),
+    )
+}
+
+impl<Query, Mutation, Subscription, CtxT> StreamHandler<Result<ws::Message, ws::ProtocolError>>
+    for SubscriptionActor<Query, Mutation, Subscription, CtxT>
+where
+    Query: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Query::TypeInfo: Send + Sync,
+    Mutation: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Mutation::TypeInfo: Send + Sync,
+    Subscription: GraphQLSubscriptionType<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Subscription::TypeInfo: Send + Sync,
+    CtxT: Unpin + Send + Sync + 'static,
+{
+    fn handle(&mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let message = match message {
+            Ok(ws::Message::Ping(bytes)) => {
+                ctx.pong(&bytes);
+                return;
+            }
+            Ok(ws::Message::Text(text)) => {
+                if let Some(error) = check_start_message(&text, &self.check_operation) {
+                    ctx.text(error);
+                    return;
+                }
+                ws::Message::Text(text)
+            }
+            Ok(message) => message,
+            Err(e) => {
+                warn!("WebSocket protocol error: {}", e);
+                return;
+            }
+        };
+        let graphql_tx = self.graphql_tx.clone();
+        async move {
+            // The sink of the connection never fails.
+            let _ = graphql_tx.lock().await.send(WsMessage(message)).await;
+        }
+        .into_actor(self)
+        .wait(ctx);
+    }
+}
+
+impl<Query, Mutation, Subscription, CtxT> Actor
+    for SubscriptionActor<Query, Mutation, Subscription, CtxT>
+where
+    Query: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Query::TypeInfo: Send + Sync,
+    Mutation: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Mutation::TypeInfo: Send + Sync,
+    Subscription: GraphQLSubscriptionType<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Subscription::TypeInfo: Send + Sync,
+    CtxT: Unpin + Send + Sync + 'static,
+{
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let graphql_rx = self.graphql_rx.clone();
+        let address = ctx.address();
+        ctx.spawn(
+            async move {
+                let mut graphql_rx = graphql_rx.lock().await;
+                while let Some(message) = graphql_rx.next().await {
+                    // Sent back to the actor, to write it to the WebSocket.
+                    address.do_send(ServerMessageWrapper(message));
+                }
+            }
+            .into_actor(self),
+        );
+    }
+}
+
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct ServerMessageWrapper(ServerMessage<DefaultScalarValue>);
+
+impl<Query, Mutation, Subscription, CtxT> Handler<ServerMessageWrapper>
+    for SubscriptionActor<Query, Mutation, Subscription, CtxT>
+where
+    Query: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Query::TypeInfo: Send + Sync,
+    Mutation: GraphQLTypeAsync<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Mutation::TypeInfo: Send + Sync,
+    Subscription: GraphQLSubscriptionType<DefaultScalarValue, Context = CtxT> + Send + 'static,
+    Subscription::TypeInfo: Send + Sync,
+    CtxT: Unpin + Send + Sync + 'static,
+{
+    type Result = ();
+
+    fn handle(&mut self, message: ServerMessageWrapper, ctx: &mut Self::Context) {
+        match serde_json::to_string(&message.0) {
+            Ok(text) => ctx.text(text),
+            Err(e) => ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Error,
+                description: Some(format!("Error serializing the response: {}", e)),
+            })),
+        }
+    }
+}
+
+/// A message from the client, parsed by the connection.
+struct WsMessage(ws::Message);
+
+#[derive(thiserror::Error, Debug)]
+enum MessageError {
+    #[error("Invalid message: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Unexpected message from the client")]
+    Unexpected,
+}
+
+impl TryFrom<WsMessage> for ClientMessage<DefaultScalarValue> {
+    type Error = MessageError;
+
+    fn try_from(message: WsMessage) -> Result<Self, Self::Error> {
+        match message.0 {
+            ws::Message::Text(text) => Ok(serde_json::from_str(&text)?),
+            ws::Message::Close(_) => Ok(ClientMessage::ConnectionTerminate),
+            _ => Err(MessageError::Unexpected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reject_mutations() -> OperationCheck {
+        Box::new(|document: &str| {
+            if document.starts_with("mutation") {
+                Err("No mutations".to_owned())
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    #[test]
+    fn test_check_start_message() {
+        let check = reject_mutations();
+        let error = check_start_message(
+            r#"{"type": "start", "id": "1", "payload": {"query": "mutation { deleteUser(userId: \"bob\") { ok } }"}}"#,
+            &check,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&error).unwrap(),
+            serde_json::json!({
+                "type": "error",
+                "id": "1",
+                "payload": [{ "message": "No mutations" }],
+            })
+        );
+        assert_eq!(
+            check_start_message(
+                r#"{"type": "start", "id": "2", "payload": {"query": "subscription { changes { id } }"}}"#,
+                &check,
+            ),
+            None
+        );
+        // The other messages are left to the connection.
+        assert_eq!(
+            check_start_message(r#"{"type": "connection_init"}"#, &check),
+            None
+        );
+        assert_eq!(check_start_message("not JSON", &check), None);
+    }
+}
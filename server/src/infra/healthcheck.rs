@@ -1,5 +1,15 @@
-use crate::infra::configuration::LdapsOptions;
+use crate::{
+    domain::{sql_migrations::get_schema_version, sql_tables::DbConnection},
+    infra::{
+        configuration::{Configuration, LdapsOptions, ListenAddresses},
+        db_cleaner::SchedulerStatus,
+        tcp_server::AppState,
+        tls_certificate::{certificate_expiry_date, ReloadableCertificate},
+    },
+};
+use actix_web::{web, HttpResponse};
 use anyhow::{anyhow, bail, ensure, Context, Result};
+use chrono::{DateTime, Utc};
 use futures_util::SinkExt;
 use ldap3_proto::{
     proto::{
@@ -8,10 +18,17 @@ use ldap3_proto::{
     },
     LdapCodec,
 };
+use sea_orm::{ConnectionTrait, Statement};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::net::TcpStream;
 use tokio_rustls::TlsConnector as RustlsTlsConnector;
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{debug, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
 async fn check_ldap_endpoint<Stream>(stream: Stream) -> Result<()>
 where
@@ -69,8 +86,17 @@ where
 }
 
 #[instrument(skip_all, level = "info", err)]
-pub async fn check_ldap(port: u16) -> Result<()> {
-    check_ldap_endpoint(TcpStream::connect(format!("localhost:{}", port)).await?).await
+// The address to reach a server bound to `host` from the same machine.
+fn local_address(host: &str, port: u16) -> String {
+    match host {
+        "" | "0.0.0.0" | "::" => format!("localhost:{}", port),
+        host if host.contains(':') => format!("[{}]:{}", host, port),
+        host => format!("{}:{}", host, port),
+    }
+}
+
+pub async fn check_ldap(host: &str, port: u16) -> Result<()> {
+    check_ldap_endpoint(TcpStream::connect(local_address(host, port)).await?).await
 }
 
 fn get_root_certificates() -> rustls::RootCertStore {
@@ -96,29 +122,465 @@ fn get_tls_connector() -> Result<RustlsTlsConnector> {
     Ok(client_config.into())
 }
 
+/// Returns the expiry date of the certificate presented by the server.
 #[instrument(skip_all, level = "info", err)]
-pub async fn check_ldaps(ldaps_options: &LdapsOptions) -> Result<()> {
-    if !ldaps_options.enabled {
-        return Ok(());
-    };
+async fn check_ldaps(
+    ldaps_options: &LdapsOptions,
+    ldap_host: &str,
+) -> Result<Option<DateTime<Utc>>> {
     let tls_connector = get_tls_connector()?;
-    let url = format!("localhost:{}", ldaps_options.port);
-    check_ldap_endpoint(
-        tls_connector
-            .connect(
-                rustls::ServerName::try_from(url.as_str())?,
-                TcpStream::connect(&url).await?,
-            )
-            .await?,
-    )
-    .await
+    let url = local_address(
+        ldaps_options
+            .host
+            .as_ref()
+            .map_or(ldap_host, ListenAddresses::first),
+        ldaps_options.port,
+    );
+    let stream = tls_connector
+        .connect(
+            rustls::ServerName::try_from(url.as_str())?,
+            TcpStream::connect(&url).await?,
+        )
+        .await?;
+    let expiry_date = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(<[_]>::first)
+        .and_then(certificate_expiry_date);
+    check_ldap_endpoint(stream).await?;
+    Ok(expiry_date)
 }
 
+/// Returns the report of the checks run by the server, which is also sent when they fail.
 #[instrument(skip_all, level = "info", err)]
-pub async fn check_api(port: u16) -> Result<()> {
-    reqwest::get(format!("http://localhost:{}/health", port))
-        .await?
-        .error_for_status()?;
+async fn check_api(host: &str, port: u16) -> Result<HealthReport> {
+    let response = reqwest::get(format!("http://{}/health", local_address(host, port))).await?;
+    let status = response.status();
+    let report = response
+        .json::<HealthReport>()
+        .await
+        .with_context(|| format!("Invalid answer from the server ({})", status))?;
     info!("Success");
-    Ok(())
+    Ok(report)
+}
+
+// A DB cleanup this late means that the scheduler is stuck.
+const SCHEDULER_GRACE_PERIOD_SECONDS: i64 = 300;
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    /// Reported, but the server is still ready.
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckResult {
+    name: String,
+    status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// For the certificates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiry_days: Option<i64>,
+}
+
+impl CheckResult {
+    fn new(name: &str, status: HealthStatus, message: Option<String>) -> Self {
+        Self {
+            name: name.to_owned(),
+            status,
+            message,
+            expiry_days: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HealthReport {
+    status: HealthStatus,
+    /// Unknown if the database is unreachable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u8>,
+    checks: Vec<CheckResult>,
+}
+
+/// The checks behind `/health` and `/health/ready`: a database round-trip, the DB cleanup
+/// scheduler running on time, and the expiry of the TLS certificates.
+#[derive(Clone)]
+pub struct HealthChecker {
+    sql_pool: DbConnection,
+    scheduler_status: SchedulerStatus,
+    certificates: Vec<Arc<ReloadableCertificate>>,
+    certificate_expiry_warning_days: u32,
+}
+
+impl HealthChecker {
+    pub fn new(
+        sql_pool: DbConnection,
+        scheduler_status: SchedulerStatus,
+        certificates: Vec<Arc<ReloadableCertificate>>,
+        certificate_expiry_warning_days: u32,
+    ) -> Self {
+        Self {
+            sql_pool,
+            scheduler_status,
+            certificates,
+            certificate_expiry_warning_days,
+        }
+    }
+
+    async fn check_database(&self) -> CheckResult {
+        let query = self.sql_pool.execute(Statement::from_string(
+            self.sql_pool.get_database_backend(),
+            "SELECT 1".to_owned(),
+        ));
+        match tokio::time::timeout(DATABASE_TIMEOUT, query).await {
+            Ok(Ok(_)) => CheckResult::new("database", HealthStatus::Ok, None),
+            Ok(Err(e)) => CheckResult::new("database", HealthStatus::Error, Some(e.to_string())),
+            Err(_) => CheckResult::new(
+                "database",
+                HealthStatus::Error,
+                Some("The query timed out".to_owned()),
+            ),
+        }
+    }
+
+    fn check_scheduler(&self, now: DateTime<Utc>) -> CheckResult {
+        match self.scheduler_status.read().unwrap().next_run {
+            None => CheckResult::new(
+                "scheduler",
+                HealthStatus::Error,
+                Some("The DB cleanup scheduler is not running".to_owned()),
+            ),
+            Some(next_run)
+                if now.signed_duration_since(next_run).num_seconds()
+                    > SCHEDULER_GRACE_PERIOD_SECONDS =>
+            {
+                CheckResult::new(
+                    "scheduler",
+                    HealthStatus::Error,
+                    Some(format!(
+                        "The DB cleanup planned at {} did not run",
+                        next_run
+                    )),
+                )
+            }
+            Some(_) => CheckResult::new("scheduler", HealthStatus::Ok, None),
+        }
+    }
+
+    fn check_certificate(
+        &self,
+        certificate: &ReloadableCertificate,
+        now: DateTime<Utc>,
+    ) -> CheckResult {
+        let name = format!("certificate {}", certificate.cert_file());
+        let (status, message) = match certificate.expiry_date() {
+            // E.g. not provisioned by ACME yet.
+            None => (
+                HealthStatus::Warning,
+                Some("No certificate loaded".to_owned()),
+            ),
+            Some(expiry) if expiry < now => (
+                HealthStatus::Error,
+                Some(format!("The certificate expired on {}", expiry)),
+            ),
+            Some(expiry)
+                if expiry.signed_duration_since(now)
+                    < chrono::Duration::days(self.certificate_expiry_warning_days.into()) =>
+            {
+                (
+                    HealthStatus::Warning,
+                    Some(format!("The certificate expires on {}", expiry)),
+                )
+            }
+            Some(_) => (HealthStatus::Ok, None),
+        };
+        CheckResult {
+            expiry_days: certificate
+                .expiry_date()
+                .map(|expiry| expiry.signed_duration_since(now).num_days()),
+            ..CheckResult::new(&name, status, message)
+        }
+    }
+
+    async fn report(&self) -> HealthReport {
+        let now = Utc::now();
+        let database = self.check_database().await;
+        let schema_version = if database.status == HealthStatus::Ok {
+            get_schema_version(&self.sql_pool)
+                .await
+                .map(|version| version.0)
+        } else {
+            None
+        };
+        let mut checks = vec![database, self.check_scheduler(now)];
+        checks.extend(
+            self.certificates
+                .iter()
+                .map(|certificate| self.check_certificate(certificate, now)),
+        );
+        HealthReport {
+            status: checks
+                .iter()
+                .map(|check| check.status)
+                .max()
+                .unwrap_or(HealthStatus::Ok),
+            schema_version,
+            checks,
+        }
+    }
+}
+
+async fn ready_handler<Backend: 'static>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    let report = data.health_checker.report().await;
+    if report.status == HealthStatus::Error {
+        warn!("Health check failed: {:?}", report.checks);
+        HttpResponse::ServiceUnavailable().json(report)
+    } else {
+        HttpResponse::Ok().json(report)
+    }
+}
+
+/// `/health/live` only checks that the server answers, for the liveness probes; `/health` and
+/// `/health/ready` run all the checks, for the readiness probes.
+pub fn configure_endpoint<Backend: 'static>(cfg: &mut web::ServiceConfig) {
+    cfg.route("/health", web::get().to(ready_handler::<Backend>))
+        .route(
+            "/health/live",
+            web::get().to(|| HttpResponse::Ok().finish()),
+        )
+        .route("/health/ready", web::get().to(ready_handler::<Backend>));
+}
+
+/// How long each check of the `healthcheck` command can take.
+const ENDPOINT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A check of the `healthcheck` command, against an endpoint of the running server.
+#[derive(Debug, Serialize)]
+struct EndpointCheck {
+    name: &'static str,
+    status: HealthStatus,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate_expiry_days: Option<i64>,
+}
+
+impl EndpointCheck {
+    fn new<T>(name: &'static str, latency: Duration, result: &Result<T>) -> Self {
+        Self {
+            name,
+            status: match result {
+                Ok(_) => HealthStatus::Ok,
+                Err(_) => HealthStatus::Error,
+            },
+            latency_ms: latency.as_millis(),
+            message: result.as_ref().err().map(|e| format!("{:#}", e)),
+            certificate_expiry_days: None,
+        }
+    }
+}
+
+/// The report of the `healthcheck` command: "warning" means that the server is degraded,
+/// "error" that it is down.
+#[derive(Debug, Serialize)]
+pub struct EndpointReport {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u8>,
+    checks: Vec<EndpointCheck>,
+    /// The checks of the server itself, from `/health`.
+    server_checks: Vec<CheckResult>,
+}
+
+impl EndpointReport {
+    /// 0 if the server is healthy, 1 if it is down and 2 if it is degraded.
+    pub fn exit_code(&self) -> i32 {
+        match self.status {
+            HealthStatus::Ok => 0,
+            HealthStatus::Error => 1,
+            HealthStatus::Warning => 2,
+        }
+    }
+
+    pub fn log(&self) {
+        for check in &self.checks {
+            let message = check.message.as_deref().unwrap_or_default();
+            match check.status {
+                HealthStatus::Ok => (),
+                HealthStatus::Warning => warn!("{}: {}", check.name, message),
+                HealthStatus::Error => error!("{}: {}", check.name, message),
+            }
+        }
+    }
+}
+
+async fn timed<T>(check: impl Future<Output = Result<T>>) -> (Result<T>, Duration) {
+    let start = Instant::now();
+    let result = tokio::time::timeout(ENDPOINT_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| {
+            Err(anyhow!(
+                "No answer after {} seconds",
+                ENDPOINT_TIMEOUT.as_secs()
+            ))
+        });
+    (result, start.elapsed())
+}
+
+fn ldaps_check(
+    result: Result<Option<DateTime<Utc>>>,
+    latency: Duration,
+    warning_days: u32,
+    now: DateTime<Utc>,
+) -> EndpointCheck {
+    let mut check = EndpointCheck::new("ldaps", latency, &result);
+    if let Ok(Some(expiry)) = result {
+        let expiry_days = expiry.signed_duration_since(now).num_days();
+        check.certificate_expiry_days = Some(expiry_days);
+        if expiry_days < i64::from(warning_days) {
+            check.status = HealthStatus::Warning;
+            check.message = Some(format!("The certificate expires on {}", expiry));
+        }
+    }
+    check
+}
+
+/// Connects to the LDAP, LDAPS and HTTP endpoints of the server, at the same time.
+pub async fn check_endpoints(config: &Configuration) -> EndpointReport {
+    let ldap_host = config.ldap_host.first();
+    let ldaps = async {
+        if config.ldaps_options.enabled {
+            Some(timed(check_ldaps(&config.ldaps_options, ldap_host)).await)
+        } else {
+            None
+        }
+    };
+    let ((ldap, ldap_latency), ldaps, (api, api_latency)) = tokio::join!(
+        timed(check_ldap(ldap_host, config.ldap_port)),
+        ldaps,
+        timed(check_api(config.http_host.first(), config.http_port)),
+    );
+    let mut checks = vec![EndpointCheck::new("ldap", ldap_latency, &ldap)];
+    if let Some((ldaps, latency)) = ldaps {
+        checks.push(ldaps_check(
+            ldaps,
+            latency,
+            config.certificate_expiry_warning_days,
+            Utc::now(),
+        ));
+    }
+    let mut api_check = EndpointCheck::new("api", api_latency, &api);
+    let (schema_version, server_checks) = match api {
+        Ok(report) => {
+            api_check.status = report.status;
+            if report.status != HealthStatus::Ok {
+                api_check.message = Some(
+                    report
+                        .checks
+                        .iter()
+                        .filter(|check| check.status != HealthStatus::Ok)
+                        .map(|check| {
+                            format!(
+                                "{}: {}",
+                                check.name,
+                                check.message.as_deref().unwrap_or_default()
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                );
+            }
+            (report.schema_version, report.checks)
+        }
+        Err(_) => (None, Vec::new()),
+    };
+    checks.push(api_check);
+    EndpointReport {
+        status: checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(HealthStatus::Ok),
+        schema_version,
+        checks,
+        server_checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::get_in_memory_db;
+
+    #[tokio::test]
+    async fn test_health_report() {
+        let scheduler_status = SchedulerStatus::default();
+        let checker = HealthChecker::new(
+            get_in_memory_db().await,
+            scheduler_status.clone(),
+            Vec::new(),
+            14,
+        );
+        // The scheduler didn't start.
+        assert_eq!(checker.report().await.status, HealthStatus::Error);
+        scheduler_status.write().unwrap().next_run = Some(Utc::now() + chrono::Duration::hours(1));
+        let report = checker.report().await;
+        assert_eq!(report.status, HealthStatus::Ok);
+        assert_eq!(report.checks.len(), 2);
+        // The tables were not created.
+        assert_eq!(report.schema_version, None);
+        // The planned cleanup is long overdue.
+        scheduler_status.write().unwrap().next_run = Some(Utc::now() - chrono::Duration::hours(1));
+        assert_eq!(checker.report().await.status, HealthStatus::Error);
+    }
+
+    #[test]
+    fn test_missing_certificate_is_a_warning() {
+        let checker = HealthChecker {
+            sql_pool: sea_orm::DatabaseConnection::Disconnected,
+            scheduler_status: SchedulerStatus::default(),
+            certificates: Vec::new(),
+            certificate_expiry_warning_days: 14,
+        };
+        let certificate = ReloadableCertificate::new_pending("cert.pem", "key.pem");
+        let result = checker.check_certificate(&certificate, Utc::now());
+        assert_eq!(result.status, HealthStatus::Warning);
+        assert_eq!(result.name, "certificate cert.pem");
+    }
+
+    #[test]
+    fn test_ldaps_check() {
+        let now = Utc::now();
+        let latency = Duration::from_millis(12);
+        let check = ldaps_check(Ok(Some(now + chrono::Duration::days(60))), latency, 14, now);
+        assert_eq!(check.status, HealthStatus::Ok);
+        assert_eq!(check.latency_ms, 12);
+        assert_eq!(check.certificate_expiry_days, Some(60));
+        let check = ldaps_check(Ok(Some(now + chrono::Duration::days(3))), latency, 14, now);
+        assert_eq!(check.status, HealthStatus::Warning);
+        let check = ldaps_check(Err(anyhow!("Connection refused")), latency, 14, now);
+        assert_eq!(check.status, HealthStatus::Error);
+        assert_eq!(check.message.as_deref(), Some("Connection refused"));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let report = |status| EndpointReport {
+            status,
+            schema_version: Some(35),
+            checks: Vec::new(),
+            server_checks: Vec::new(),
+        };
+        assert_eq!(report(HealthStatus::Ok).exit_code(), 0);
+        assert_eq!(report(HealthStatus::Error).exit_code(), 1);
+        assert_eq!(report(HealthStatus::Warning).exit_code(), 2);
+    }
 }
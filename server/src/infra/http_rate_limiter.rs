@@ -0,0 +1,385 @@
+use crate::infra::{configuration::HttpRateLimitOptions, trusted_proxies::client_ip};
+use actix_http::{h1, header, StatusCode};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web, HttpResponse,
+};
+use futures::{
+    future::{ok, Ready},
+    StreamExt,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex, RwLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+const REQUESTS_WINDOW: Duration = Duration::from_secs(60);
+// The authentication requests are small, anything bigger is not worth buffering.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Ip(IpAddr),
+    User(String),
+}
+
+struct Backoff {
+    consecutive_failures: u32,
+    last_failure: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    requests: HashMap<Key, VecDeque<Instant>>,
+    backoffs: HashMap<Key, Backoff>,
+}
+
+/// Limits the rate of requests per client IP and per username, and makes the clients wait
+/// exponentially longer after each consecutive failed login. Shared between all the HTTP workers.
+#[derive(Clone)]
+pub struct HttpRateLimiter {
+    options: Arc<RwLock<HttpRateLimitOptions>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl HttpRateLimiter {
+    pub fn new(options: HttpRateLimitOptions) -> Self {
+        Self {
+            options: Arc::new(RwLock::new(options)),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    fn options(&self) -> HttpRateLimitOptions {
+        self.options.read().unwrap().clone()
+    }
+
+    /// Replaces the limits, keeping the recorded requests and failures.
+    pub fn set_options(&self, options: HttpRateLimitOptions) {
+        *self.options.write().unwrap() = options;
+    }
+
+    fn max_backoff(&self) -> Duration {
+        Duration::from_secs(self.options().max_failure_backoff_seconds)
+    }
+
+    fn backoff_delay(&self, consecutive_failures: u32) -> Duration {
+        let base = Duration::from_secs(self.options().failure_backoff_seconds);
+        let factor = 1u32
+            .checked_shl(consecutive_failures - 1)
+            .unwrap_or(u32::MAX);
+        std::cmp::min(base.saturating_mul(factor), self.max_backoff())
+    }
+
+    fn keys(ip: Option<IpAddr>, user: Option<&str>) -> Vec<Key> {
+        ip.map(Key::Ip)
+            .into_iter()
+            .chain(user.map(|u| Key::User(u.to_lowercase())))
+            .collect()
+    }
+
+    /// Records a request from the IP for the username, and returns how long to wait before
+    /// retrying if it is not allowed.
+    fn try_request_at(
+        &self,
+        ip: Option<IpAddr>,
+        user: Option<&str>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.retain(|_, requests| {
+            while matches!(requests.front(), Some(t) if now.duration_since(*t) >= REQUESTS_WINDOW) {
+                requests.pop_front();
+            }
+            !requests.is_empty()
+        });
+        let max_backoff = self.max_backoff();
+        state
+            .backoffs
+            .retain(|_, backoff| now.duration_since(backoff.last_failure) < max_backoff);
+        let keys = Self::keys(ip, user);
+        for key in &keys {
+            if let Some(backoff) = state.backoffs.get(key) {
+                let end = backoff.last_failure + self.backoff_delay(backoff.consecutive_failures);
+                if now < end {
+                    return Err(end - now);
+                }
+            }
+            let max_requests = match key {
+                Key::Ip(_) => self.options().max_requests_per_ip_per_minute,
+                Key::User(_) => self.options().max_requests_per_user_per_minute,
+            };
+            if max_requests == 0 {
+                continue;
+            }
+            if let Some(requests) = state.requests.get(key) {
+                if requests.len() >= max_requests {
+                    return Err(REQUESTS_WINDOW - now.duration_since(requests[0]));
+                }
+            }
+        }
+        for key in keys {
+            state.requests.entry(key).or_default().push_back(now);
+        }
+        Ok(())
+    }
+
+    /// Records the outcome of a login attempt: a failure delays the next attempts, a success resets
+    /// the delay.
+    fn record_login_at(&self, ip: Option<IpAddr>, user: Option<&str>, success: bool, now: Instant) {
+        if self.options().failure_backoff_seconds == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        for key in Self::keys(ip, user) {
+            if success {
+                state.backoffs.remove(&key);
+            } else {
+                let backoff = state.backoffs.entry(key).or_insert(Backoff {
+                    consecutive_failures: 0,
+                    last_failure: now,
+                });
+                backoff.consecutive_failures = backoff.consecutive_failures.saturating_add(1);
+                backoff.last_failure = now;
+            }
+        }
+    }
+}
+
+fn parse_ip(address: &str) -> Option<IpAddr> {
+    address
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| address.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
+
+fn username_from_json(body: &[u8]) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    // "username" for the OPAQUE and simple logins, "name" for the bind endpoint.
+    value
+        .get("username")
+        .or_else(|| value.get("name"))
+        .and_then(|name| name.as_str())
+        .map(str::to_owned)
+}
+
+// Finds the username targeted by the request, from the path, the refresh token or the body. The
+// body is buffered, and put back for the handler.
+async fn extract_username(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(user) = req.path().strip_prefix("/auth/reset/step1/") {
+        return Ok(Some(user.to_owned()));
+    }
+    let refresh_token = req
+        .cookie("refresh_token")
+        .map(|c| c.value().to_owned())
+        .or_else(|| {
+            req.headers()
+                .get("refresh-token")
+                .and_then(|t| t.to_str().ok())
+                .map(str::to_owned)
+        });
+    if let Some((_, user)) = refresh_token.as_deref().and_then(|t| t.split_once('+')) {
+        return Ok(Some(user.to_owned()));
+    }
+    if req.method() != actix_http::Method::POST {
+        return Ok(None);
+    }
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(actix_web::error::ErrorPayloadTooLarge(
+                "Authentication request too large",
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    let username = username_from_json(&body);
+    let (_, mut buffered_payload) = h1::Payload::create(true);
+    buffered_payload.unread_data(body);
+    req.set_payload(buffered_payload.into());
+    Ok(username)
+}
+
+/// Applies the rate limiter to the wrapped endpoints.
+pub struct HttpRateLimiterFactory {
+    rate_limiter: HttpRateLimiter,
+}
+
+impl HttpRateLimiterFactory {
+    pub fn new(rate_limiter: HttpRateLimiter) -> Self {
+        Self { rate_limiter }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for HttpRateLimiterFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = HttpRateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(HttpRateLimiterMiddleware {
+            service: Rc::new(service),
+            rate_limiter: self.rate_limiter.clone(),
+        })
+    }
+}
+
+pub struct HttpRateLimiterMiddleware<S> {
+    service: Rc<S>,
+    rate_limiter: HttpRateLimiter,
+}
+
+impl<S> Service<ServiceRequest> for HttpRateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn core::future::Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        Box::pin(async move {
+            let ip = if rate_limiter.options().trust_forwarded_headers {
+                req.connection_info()
+                    .realip_remote_addr()
+                    .and_then(parse_ip)
+            } else {
+                client_ip(req.request())
+            };
+            let user = extract_username(&mut req).await?;
+            if let Err(retry_after) =
+                rate_limiter.try_request_at(ip, user.as_deref(), Instant::now())
+            {
+                debug!(?ip, ?user, "Rate limited");
+                return Ok(req.into_response(
+                    HttpResponse::TooManyRequests()
+                        .insert_header((
+                            header::RETRY_AFTER,
+                            // Rounded up, to not retry too early.
+                            (retry_after.as_secs() + 1).to_string(),
+                        ))
+                        .body("Too many requests, try again later"),
+                ));
+            }
+            let response = service.call(req).await?;
+            match response.status() {
+                status if status.is_success() => {
+                    rate_limiter.record_login_at(ip, user.as_deref(), true, Instant::now())
+                }
+                StatusCode::UNAUTHORIZED => {
+                    warn!(?ip, ?user, "Failed authentication");
+                    rate_limiter.record_login_at(ip, user.as_deref(), false, Instant::now())
+                }
+                _ => {}
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_rate_limiter(
+        max_requests_per_ip_per_minute: usize,
+        max_requests_per_user_per_minute: usize,
+        failure_backoff_seconds: u64,
+    ) -> HttpRateLimiter {
+        HttpRateLimiter::new(HttpRateLimitOptions {
+            max_requests_per_ip_per_minute,
+            max_requests_per_user_per_minute,
+            failure_backoff_seconds,
+            max_failure_backoff_seconds: 60,
+            trust_forwarded_headers: false,
+        })
+    }
+
+    #[test]
+    fn test_requests_per_ip_and_user() {
+        let rate_limiter = make_rate_limiter(3, 2, 0);
+        let ip: Option<IpAddr> = Some("192.168.1.2".parse().unwrap());
+        let other_ip: Option<IpAddr> = Some("192.168.1.3".parse().unwrap());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert!(rate_limiter.try_request_at(ip, Some("bob"), at(0)).is_ok());
+        assert!(rate_limiter.try_request_at(ip, Some("Bob"), at(10)).is_ok());
+        // Bob's budget is spent, from any IP.
+        assert_eq!(
+            rate_limiter.try_request_at(other_ip, Some("bob"), at(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(rate_limiter
+            .try_request_at(ip, Some("john"), at(20))
+            .is_ok());
+        // The IP's budget is spent.
+        assert!(rate_limiter.try_request_at(ip, None, at(30)).is_err());
+        assert!(rate_limiter.try_request_at(other_ip, None, at(30)).is_ok());
+        assert!(rate_limiter.try_request_at(ip, Some("bob"), at(61)).is_ok());
+    }
+
+    #[test]
+    fn test_failure_backoff() {
+        let rate_limiter = make_rate_limiter(0, 0, 2);
+        let ip: Option<IpAddr> = Some("192.168.1.2".parse().unwrap());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        rate_limiter.record_login_at(ip, Some("bob"), false, at(0));
+        assert_eq!(
+            rate_limiter.try_request_at(None, Some("bob"), at(1)),
+            Err(Duration::from_secs(1))
+        );
+        assert!(rate_limiter.try_request_at(ip, Some("bob"), at(2)).is_ok());
+        rate_limiter.record_login_at(ip, Some("bob"), false, at(2));
+        // The delay doubles.
+        assert!(rate_limiter.try_request_at(ip, None, at(5)).is_err());
+        assert!(rate_limiter.try_request_at(ip, Some("bob"), at(6)).is_ok());
+        rate_limiter.record_login_at(ip, Some("bob"), true, at(6));
+        assert!(rate_limiter.try_request_at(ip, Some("bob"), at(6)).is_ok());
+    }
+
+    #[test]
+    fn test_backoff_delay_is_capped() {
+        let rate_limiter = make_rate_limiter(0, 0, 2);
+        assert_eq!(rate_limiter.backoff_delay(1), Duration::from_secs(2));
+        assert_eq!(rate_limiter.backoff_delay(3), Duration::from_secs(8));
+        assert_eq!(rate_limiter.backoff_delay(10), Duration::from_secs(60));
+        assert_eq!(rate_limiter.backoff_delay(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_username_from_json() {
+        assert_eq!(
+            username_from_json(br#"{"username": "bob", "password": "pass"}"#),
+            Some("bob".to_owned())
+        );
+        assert_eq!(
+            username_from_json(br#"{"name": "bob", "password": "pass"}"#),
+            Some("bob".to_owned())
+        );
+        assert_eq!(username_from_json(b"not json"), None);
+    }
+}
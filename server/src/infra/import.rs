@@ -0,0 +1,675 @@
+use crate::{
+    domain::{
+        handler::{BackendHandler, CreateUserRequest},
+        legacy_password,
+        types::{GroupId, UserId},
+    },
+    infra::ldif::{parse_ldif, LdifEntry},
+};
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+
+/// Default number of users created per transaction.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+// Separator of the groups in the CSV column listing the groups of a user.
+const CSV_GROUP_SEPARATOR: char = ';';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Ldif,
+    Csv,
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ldif" => Ok(Self::Ldif),
+            "csv" => Ok(Self::Csv),
+            _ => anyhow::bail!("Unknown import format '{}', expected 'ldif' or 'csv'", s),
+        }
+    }
+}
+
+impl ImportFormat {
+    /// Guesses the format from the extension of the file.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        std::path::Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.parse().ok())
+    }
+}
+
+/// The names of the CSV columns holding the fields of the users. Only the user ID and the email
+/// are required. The groups column lists the names of the groups of the user, separated by ';'.
+/// The password hash column holds hashes exported from another directory (bcrypt, argon2,
+/// SSHA...), checked on the first bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvColumns {
+    pub user_id: String,
+    pub email: String,
+    pub display_name: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub groups: String,
+    pub password_hash: String,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        Self {
+            user_id: "id".to_owned(),
+            email: "email".to_owned(),
+            display_name: "display_name".to_owned(),
+            first_name: "first_name".to_owned(),
+            last_name: "last_name".to_owned(),
+            groups: "groups".to_owned(),
+            password_hash: "password_hash".to_owned(),
+        }
+    }
+}
+
+/// An entry or row of the imported file that could not be imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    /// Line of the file.
+    pub line: usize,
+    /// The user ID, group name or DN of the entry.
+    pub id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedUser {
+    pub line: usize,
+    /// The user ID as written in the file, before its conversion to lowercase.
+    pub id: String,
+    pub request: CreateUserRequest,
+    /// Names of the groups the user should be added to.
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedGroup {
+    pub line: usize,
+    pub name: String,
+    pub members: Vec<UserId>,
+}
+
+/// The content of a file to import, with the entries that could not be read.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportData {
+    pub users: Vec<ImportedUser>,
+    pub groups: Vec<ImportedGroup>,
+    pub errors: Vec<ImportError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    pub created_users: Vec<UserId>,
+    pub created_groups: Vec<String>,
+    pub added_memberships: usize,
+    pub errors: Vec<ImportError>,
+}
+
+const USER_OBJECT_CLASSES: &[&str] = &[
+    "person",
+    "organizationalperson",
+    "inetorgperson",
+    "posixaccount",
+    "user",
+];
+const GROUP_OBJECT_CLASSES: &[&str] =
+    &["groupofnames", "groupofuniquenames", "posixgroup", "group"];
+
+fn has_object_class(entry: &LdifEntry, classes: &[&str]) -> bool {
+    entry.values("objectclass").any(|class| {
+        classes
+            .iter()
+            .any(|c| c.as_bytes().eq_ignore_ascii_case(class))
+    })
+}
+
+fn optional_number(entry: &LdifEntry, name: &str) -> Result<Option<i32>> {
+    entry
+        .first_value(name)
+        .map(|v| {
+            v.parse()
+                .with_context(|| format!("Invalid {}: '{}'", name, v))
+        })
+        .transpose()
+}
+
+fn ldif_user_id(entry: &LdifEntry) -> Option<&str> {
+    entry
+        .first_value("uid")
+        .or_else(|| entry.first_value("samaccountname"))
+}
+
+fn ldif_user(entry: &LdifEntry) -> Result<CreateUserRequest> {
+    let user_id = ldif_user_id(entry).context("Missing uid")?;
+    let email = entry.first_value("mail").context("Missing mail")?;
+    Ok(CreateUserRequest {
+        user_id: UserId::new(user_id),
+        email: email.to_owned(),
+        display_name: entry
+            .first_value("displayname")
+            .or_else(|| entry.first_value("cn"))
+            .map(str::to_owned),
+        first_name: entry.first_value("givenname").map(str::to_owned),
+        last_name: entry.first_value("sn").map(str::to_owned),
+        uid_number: optional_number(entry, "uidnumber")?,
+        gid_number: optional_number(entry, "gidnumber")?,
+        home_directory: entry.first_value("homedirectory").map(str::to_owned),
+        login_shell: entry.first_value("loginshell").map(str::to_owned),
+        telephone_number: entry.first_value("telephonenumber").map(str::to_owned),
+        mobile: entry.first_value("mobile").map(str::to_owned),
+        title: entry.first_value("title").map(str::to_owned),
+        department: entry.first_value("ou").map(str::to_owned),
+        locality: entry.first_value("l").map(str::to_owned),
+        postal_address: entry.first_value("postaladdress").map(str::to_owned),
+        locale: entry.first_value("preferredlanguage").map(str::to_owned),
+        // The clear text passwords and the unsupported hashes are ignored.
+        legacy_password_hash: entry
+            .first_value("userpassword")
+            .filter(|hash| legacy_password::is_supported(hash))
+            .map(str::to_owned),
+        ..Default::default()
+    })
+}
+
+// The user ID of a member DN: the user of the file with that DN, or else the value of a leading
+// "uid=" (for the users already in the directory).
+fn member_id(dn: &str, user_dns: &HashMap<String, UserId>) -> Option<UserId> {
+    let dn = dn.to_ascii_lowercase();
+    user_dns.get(&dn).cloned().or_else(|| {
+        dn.split(',')
+            .next()
+            .and_then(|rdn| rdn.strip_prefix("uid="))
+            .map(|uid| UserId::new(uid.trim()))
+    })
+}
+
+// The unknown members are returned separately, to be reported without failing the whole group.
+fn ldif_group(
+    entry: &LdifEntry,
+    user_dns: &HashMap<String, UserId>,
+) -> Result<(ImportedGroup, Vec<String>)> {
+    let name = entry.first_value("cn").context("Missing cn")?.to_owned();
+    let mut members = Vec::new();
+    let mut unknown_members = Vec::new();
+    for dn in entry.values("member").chain(entry.values("uniquemember")) {
+        let dn = String::from_utf8_lossy(dn);
+        match member_id(&dn, user_dns) {
+            Some(user_id) => members.push(user_id),
+            None => unknown_members.push(dn.into_owned()),
+        }
+    }
+    members.extend(
+        entry
+            .values("memberuid")
+            .map(|uid| UserId::new(&String::from_utf8_lossy(uid))),
+    );
+    Ok((
+        ImportedGroup {
+            line: entry.line,
+            name,
+            members,
+        },
+        unknown_members,
+    ))
+}
+
+/// Reads the users (person, inetOrgPerson, posixAccount...) and groups (groupOfNames,
+/// groupOfUniqueNames, posixGroup...) of an LDIF file. The other entries, like the organizational
+/// units, are ignored. The memberships are read from the groups.
+pub fn read_ldif(content: &str) -> Result<ImportData> {
+    Ok(read_entries(&parse_ldif(content)?))
+}
+
+/// Reads the users and groups of LDAP entries, see `read_ldif`. The computer accounts of Active
+/// Directory, which are also users, are ignored.
+pub fn read_entries(entries: &[LdifEntry]) -> ImportData {
+    let mut data = ImportData::default();
+    let mut user_dns = HashMap::new();
+    for entry in entries
+        .iter()
+        .filter(|e| has_object_class(e, USER_OBJECT_CLASSES))
+        .filter(|e| !has_object_class(e, &["computer"]))
+    {
+        match ldif_user(entry) {
+            Ok(request) => {
+                user_dns.insert(entry.dn.to_ascii_lowercase(), request.user_id.clone());
+                data.users.push(ImportedUser {
+                    line: entry.line,
+                    id: ldif_user_id(entry).unwrap_or_default().to_owned(),
+                    request,
+                    groups: Vec::new(),
+                });
+            }
+            Err(e) => data.errors.push(ImportError {
+                line: entry.line,
+                id: entry.dn.clone(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    for entry in entries
+        .iter()
+        .filter(|e| has_object_class(e, GROUP_OBJECT_CLASSES))
+    {
+        match ldif_group(entry, &user_dns) {
+            Ok((group, unknown_members)) => {
+                data.errors
+                    .extend(unknown_members.into_iter().map(|dn| ImportError {
+                        line: entry.line,
+                        id: group.name.clone(),
+                        message: format!("Unknown member {}", dn),
+                    }));
+                data.groups.push(group);
+            }
+            Err(e) => data.errors.push(ImportError {
+                line: entry.line,
+                id: entry.dn.clone(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    data
+}
+
+/// Reads the users of a CSV file with a header row, and the groups listed for them.
+pub fn read_csv(content: &str, columns: &CsvColumns) -> Result<ImportData> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader
+        .headers()
+        .context("while reading the CSV header")?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let user_id_column = column(&columns.user_id)
+        .with_context(|| format!("Missing the user ID column '{}'", columns.user_id))?;
+    let email_column = column(&columns.email)
+        .with_context(|| format!("Missing the email column '{}'", columns.email))?;
+    let display_name_column = column(&columns.display_name);
+    let first_name_column = column(&columns.first_name);
+    let last_name_column = column(&columns.last_name);
+    let groups_column = column(&columns.groups);
+    let password_hash_column = column(&columns.password_hash);
+    let mut data = ImportData::default();
+    for record in reader.records() {
+        let record = record.context("while reading the CSV file")?;
+        let line = record.position().map_or(0, |p| p.line() as usize);
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|c| record.get(c))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let (id, user_id) = match field(Some(user_id_column)) {
+            Some(user_id) => (user_id.to_owned(), UserId::new(user_id)),
+            None => {
+                data.errors.push(ImportError {
+                    line,
+                    id: String::new(),
+                    message: "Missing user ID".to_owned(),
+                });
+                continue;
+            }
+        };
+        let email = match field(Some(email_column)) {
+            Some(email) => email.to_owned(),
+            None => {
+                data.errors.push(ImportError {
+                    line,
+                    id: user_id.into_string(),
+                    message: "Missing email".to_owned(),
+                });
+                continue;
+            }
+        };
+        let legacy_password_hash = field(password_hash_column).map(str::to_owned);
+        if matches!(&legacy_password_hash, Some(hash) if !legacy_password::is_supported(hash)) {
+            data.errors.push(ImportError {
+                line,
+                id: user_id.into_string(),
+                message: "Unsupported password hash".to_owned(),
+            });
+            continue;
+        }
+        data.users.push(ImportedUser {
+            line,
+            id,
+            request: CreateUserRequest {
+                user_id,
+                email,
+                legacy_password_hash,
+                display_name: field(display_name_column).map(str::to_owned),
+                first_name: field(first_name_column).map(str::to_owned),
+                last_name: field(last_name_column).map(str::to_owned),
+                ..Default::default()
+            },
+            groups: field(groups_column)
+                .map(|groups| {
+                    groups
+                        .split(CSV_GROUP_SEPARATOR)
+                        .map(str::trim)
+                        .filter(|g| !g.is_empty())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        });
+    }
+    Ok(data)
+}
+
+pub fn read(content: &str, format: ImportFormat, columns: &CsvColumns) -> Result<ImportData> {
+    match format {
+        ImportFormat::Ldif => read_ldif(content),
+        ImportFormat::Csv => read_csv(content, columns),
+    }
+}
+
+/// Creates the users, groups and memberships read from the file, skipping the existing ones.
+/// The users are created in transactions of `batch_size` users. A failure only skips the
+/// corresponding entry, and is reported. With `dry_run`, nothing is written: only the conflicts
+/// with the existing users are detected.
+pub async fn import<Handler: BackendHandler>(
+    handler: &Handler,
+    data: ImportData,
+    batch_size: usize,
+    dry_run: bool,
+) -> Result<ImportReport> {
+    let mut report = ImportReport {
+        errors: data.errors,
+        ..Default::default()
+    };
+
+    // The groups, created first so that the memberships can be added along with the users.
+    let mut group_ids: HashMap<String, Option<GroupId>> = handler
+        .list_groups(None)
+        .await?
+        .into_iter()
+        .map(|g| (g.display_name, Some(g.id)))
+        .collect();
+    let group_names = data
+        .groups
+        .iter()
+        .map(|g| (g.line, g.name.clone()))
+        .chain(
+            data.users
+                .iter()
+                .flat_map(|u| u.groups.iter().map(move |g| (u.line, g.clone()))),
+        )
+        .collect::<Vec<_>>();
+    for (line, name) in group_names {
+        if group_ids.contains_key(&name) {
+            continue;
+        }
+        let group_id = if dry_run {
+            None
+        } else {
+            match handler.create_group(&name).await {
+                Ok(group_id) => Some(group_id),
+                Err(e) => {
+                    report.errors.push(ImportError {
+                        line,
+                        id: name,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            }
+        };
+        report.created_groups.push(name.clone());
+        group_ids.insert(name, group_id);
+    }
+
+    // The users, in batches.
+    let mut memberships = Vec::new();
+    let mut failed_users = HashSet::new();
+    let mut users = data.users.into_iter().peekable();
+    while users.peek().is_some() {
+        let mut batch = Vec::with_capacity(batch_size);
+        for user in users.by_ref() {
+            if let Err(e) = handler.check_user_id(&user.id) {
+                failed_users.insert(user.request.user_id.clone());
+                report.errors.push(ImportError {
+                    line: user.line,
+                    id: user.id,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+            match handler.get_user_details(&user.request.user_id).await {
+                // The memberships of the existing users are still imported.
+                Ok(_) => {
+                    memberships.extend(
+                        user.groups
+                            .into_iter()
+                            .map(|g| (user.line, user.request.user_id.clone(), g)),
+                    );
+                    report.errors.push(ImportError {
+                        line: user.line,
+                        id: user.request.user_id.into_string(),
+                        message: "The user already exists".to_owned(),
+                    });
+                }
+                Err(_) => batch.push(user),
+            }
+            if batch.len() == batch_size {
+                break;
+            }
+        }
+        let results = if dry_run {
+            batch.iter().map(|_| Ok(())).collect()
+        } else {
+            handler
+                .create_users(batch.iter().map(|u| u.request.clone()).collect())
+                .await?
+        };
+        for (user, result) in batch.into_iter().zip(results) {
+            let user_id = user.request.user_id;
+            match result {
+                Ok(()) => {
+                    memberships.extend(
+                        user.groups
+                            .into_iter()
+                            .map(|g| (user.line, user_id.clone(), g)),
+                    );
+                    report.created_users.push(user_id);
+                }
+                Err(e) => {
+                    report.errors.push(ImportError {
+                        line: user.line,
+                        id: user_id.to_string(),
+                        message: e.to_string(),
+                    });
+                    failed_users.insert(user_id);
+                }
+            }
+        }
+        info!(
+            "Imported {} users, {} errors",
+            report.created_users.len(),
+            report.errors.len()
+        );
+    }
+
+    // The memberships. The members of the imported groups can be existing users, whose groups
+    // are checked to skip the existing memberships.
+    memberships.extend(data.groups.into_iter().flat_map(|g| {
+        g.members
+            .into_iter()
+            .map(move |member| (g.line, member, g.name.clone()))
+    }));
+    let created_users = report.created_users.iter().collect::<HashSet<_>>();
+    let mut existing_memberships: HashMap<UserId, HashSet<GroupId>> = HashMap::new();
+    let mut added = HashSet::new();
+    for (line, user_id, group_name) in memberships {
+        // The user couldn't be created, the error was already reported.
+        if failed_users.contains(&user_id) {
+            continue;
+        }
+        let group_id = match group_ids.get(&group_name) {
+            Some(group_id) => *group_id,
+            // The group couldn't be created.
+            None => continue,
+        };
+        if !added.insert((user_id.clone(), group_name.clone())) {
+            continue;
+        }
+        if !created_users.contains(&user_id) {
+            if !existing_memberships.contains_key(&user_id) {
+                match handler.get_user_groups(&user_id).await {
+                    Ok(groups) => {
+                        existing_memberships.insert(
+                            user_id.clone(),
+                            groups.into_iter().map(|g| g.group_id).collect(),
+                        );
+                    }
+                    Err(_) => {
+                        report.errors.push(ImportError {
+                            line,
+                            id: group_name,
+                            message: format!("Unknown member {}", user_id),
+                        });
+                        continue;
+                    }
+                }
+            }
+            if matches!(group_id, Some(id) if existing_memberships[&user_id].contains(&id)) {
+                continue;
+            }
+        }
+        if let Some(group_id) = group_id.filter(|_| !dry_run) {
+            if let Err(e) = handler.add_user_to_group(&user_id, group_id).await {
+                report.errors.push(ImportError {
+                    line,
+                    id: group_name,
+                    message: format!("Could not add {}: {}", user_id, e),
+                });
+                continue;
+            }
+        }
+        report.added_memberships += 1;
+    }
+    report.errors.sort_by_key(|e| e.line);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UserBackendHandler},
+        sql_backend_handler::tests::*,
+    };
+
+    const LDIF: &str = "dn: ou=people,dc=example,dc=com\n\
+                        objectClass: organizationalUnit\n\
+                        \n\
+                        dn: uid=alice,ou=people,dc=example,dc=com\n\
+                        objectClass: inetOrgPerson\n\
+                        uid: alice\n\
+                        mail: alice@example.com\n\
+                        cn: Alice\n\
+                        \n\
+                        dn: uid=carol,ou=people,dc=example,dc=com\n\
+                        objectClass: inetOrgPerson\n\
+                        uid: carol\n\
+                        \n\
+                        dn: cn=imported,ou=groups,dc=example,dc=com\n\
+                        objectClass: groupOfUniqueNames\n\
+                        cn: imported\n\
+                        uniqueMember: uid=alice,ou=people,dc=example,dc=com\n\
+                        uniqueMember: uid=bob,ou=people,dc=other,dc=com\n\
+                        memberUid: patrick\n";
+
+    #[test]
+    fn test_read_ldif() {
+        let data = read_ldif(LDIF).unwrap();
+        assert_eq!(data.users.len(), 1);
+        assert_eq!(data.users[0].request.user_id, UserId::new("alice"));
+        assert_eq!(data.users[0].request.display_name.as_deref(), Some("Alice"));
+        assert_eq!(
+            data.errors,
+            vec![ImportError {
+                line: 10,
+                id: "uid=carol,ou=people,dc=example,dc=com".to_owned(),
+                message: "Missing mail".to_owned(),
+            }]
+        );
+        assert_eq!(data.groups.len(), 1);
+        assert_eq!(
+            data.groups[0].members,
+            vec![
+                UserId::new("alice"),
+                UserId::new("bob"),
+                UserId::new("patrick")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let columns = CsvColumns {
+            user_id: "login".to_owned(),
+            ..Default::default()
+        };
+        let csv = "login,email,first_name,groups\n\
+                   Alice,alice@example.com,Alice,staff; admins\n\
+                   carol,,Carol,\n";
+        let data = read_csv(csv, &columns).unwrap();
+        assert_eq!(data.users.len(), 1);
+        assert_eq!(data.users[0].line, 2);
+        assert_eq!(data.users[0].request.user_id, UserId::new("alice"));
+        assert_eq!(data.users[0].request.first_name.as_deref(), Some("Alice"));
+        assert_eq!(data.users[0].groups, vec!["staff", "admins"]);
+        assert_eq!(data.errors.len(), 1);
+        assert_eq!(data.errors[0].line, 3);
+        assert!(read_csv(csv, &CsvColumns::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let fixture = TestFixture::new().await;
+        let data = read_ldif(LDIF).unwrap();
+
+        let report = import(&fixture.handler, data.clone(), 1, true)
+            .await
+            .unwrap();
+        assert_eq!(report.created_users, vec![UserId::new("alice")]);
+        assert_eq!(report.created_groups, vec!["imported"]);
+        assert_eq!(report.added_memberships, 3);
+        assert!(fixture
+            .handler
+            .get_user_details(&UserId::new("alice"))
+            .await
+            .is_err());
+
+        let report = import(&fixture.handler, data.clone(), 1, false)
+            .await
+            .unwrap();
+        assert_eq!(report.created_users, vec![UserId::new("alice")]);
+        assert_eq!(report.added_memberships, 3);
+        assert_eq!(report.errors.len(), 1);
+        let groups = fixture.handler.list_groups(None).await.unwrap();
+        let imported = groups
+            .iter()
+            .find(|g| g.display_name == "imported")
+            .unwrap();
+        assert_eq!(imported.users.len(), 3);
+
+        // Importing again only reports the existing user.
+        let report = import(&fixture.handler, data, 1, false).await.unwrap();
+        assert!(report.created_users.is_empty());
+        assert!(report.created_groups.is_empty());
+        assert_eq!(report.added_memberships, 0);
+        assert_eq!(report.errors.len(), 2);
+    }
+}
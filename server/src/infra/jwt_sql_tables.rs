@@ -1,16 +1,10 @@
 use sea_orm::ConnectionTrait;
 use sea_query::{ColumnDef, ForeignKey, ForeignKeyAction, Iden, Table};
 
-pub use crate::domain::{sql_migrations::Users, sql_tables::DbConnection};
-
-/// Contains the refresh tokens for a given user.
-#[derive(Iden)]
-pub enum JwtRefreshStorage {
-    Table,
-    RefreshTokenHash,
-    UserId,
-    ExpiryDate,
-}
+pub use crate::domain::{
+    sql_migrations::{JwtRefreshStorage, Users},
+    sql_tables::DbConnection,
+};
 
 /// Contains the blacklisted JWT that haven't expired yet.
 #[derive(Iden)]
@@ -56,6 +50,10 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
                         .date_time()
                         .not_null(),
                 )
+                .col(ColumnDef::new(JwtRefreshStorage::CreationDate).date_time())
+                .col(ColumnDef::new(JwtRefreshStorage::LastUsed).date_time())
+                .col(ColumnDef::new(JwtRefreshStorage::UserAgent).string_len(255))
+                .col(ColumnDef::new(JwtRefreshStorage::IpAddress).string_len(64))
                 .foreign_key(
                     ForeignKey::create()
                         .name("JwtRefreshStorageUserForeignKey")
@@ -0,0 +1,235 @@
+//! Restrictions on what an application can read through LDAP, e.g. a printer only getting the
+//! names and emails of the users. The profile of a connection is chosen by the DN it is bound as,
+//! or otherwise by the IP of the client.
+
+use crate::{
+    domain::ldap::utils::{is_subtree, parse_distinguished_name},
+    infra::configuration::ClientProfileOptions,
+};
+use anyhow::{anyhow, Context, Result};
+use ipnet::IpNet;
+use ldap3_proto::proto::{LdapOp, LdapSearchResultEntry};
+use std::{collections::HashSet, net::IpAddr, sync::Arc};
+
+type Dn = Vec<(String, String)>;
+
+fn parse_dn(dn: &str) -> Result<Dn> {
+    parse_distinguished_name(&dn.to_ascii_lowercase()).map_err(|e| anyhow!("{}", e.message))
+}
+
+#[derive(Debug)]
+pub struct ClientProfile {
+    pub name: String,
+    bind_dns: Vec<Dn>,
+    networks: Vec<IpNet>,
+    /// Lowercase, empty for all the attributes.
+    attributes: HashSet<String>,
+    /// Empty for the whole tree.
+    subtrees: Vec<Dn>,
+}
+
+impl ClientProfile {
+    fn new(options: &ClientProfileOptions) -> Result<Self> {
+        Ok(Self {
+            name: options.name.clone(),
+            bind_dns: options
+                .bind_dns
+                .iter()
+                .map(|dn| {
+                    parse_dn(dn).with_context(|| {
+                        format!("Invalid bind DN in the profile {}: {}", options.name, dn)
+                    })
+                })
+                .collect::<Result<_>>()?,
+            networks: options
+                .client_ips
+                .iter()
+                .map(|network| {
+                    network
+                        .parse::<IpNet>()
+                        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+                        .map_err(|_| {
+                            anyhow!(
+                                "Invalid client IP in the profile {}: {}",
+                                options.name,
+                                network
+                            )
+                        })
+                })
+                .collect::<Result<_>>()?,
+            attributes: options
+                .attributes
+                .iter()
+                .map(|a| a.to_ascii_lowercase())
+                .collect(),
+            subtrees: options
+                .subtrees
+                .iter()
+                .map(|dn| {
+                    parse_dn(dn).with_context(|| {
+                        format!("Invalid subtree in the profile {}: {}", options.name, dn)
+                    })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn is_visible(&self, dn: &str) -> bool {
+        if self.subtrees.is_empty() {
+            return true;
+        }
+        match parse_dn(dn) {
+            Ok(dn) => self.subtrees.iter().any(|subtree| is_subtree(&dn, subtree)),
+            Err(_) => false,
+        }
+    }
+
+    /// Removes the attributes the client can't read from a search result, or the whole entry if
+    /// it is outside of the subtrees of the profile.
+    pub fn restrict(&self, op: LdapOp) -> Option<LdapOp> {
+        match op {
+            LdapOp::SearchResultEntry(entry) if !self.is_visible(&entry.dn) => None,
+            LdapOp::SearchResultEntry(LdapSearchResultEntry { dn, attributes })
+                if !self.attributes.is_empty() =>
+            {
+                Some(LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn,
+                    attributes: attributes
+                        .into_iter()
+                        .filter(|a| self.attributes.contains(&a.atype.to_ascii_lowercase()))
+                        .collect(),
+                }))
+            }
+            op => Some(op),
+        }
+    }
+}
+
+/// The configured profiles, shared by the LDAP sessions.
+#[derive(Clone, Debug, Default)]
+pub struct ClientProfiles(Arc<Vec<Arc<ClientProfile>>>);
+
+impl ClientProfiles {
+    pub fn new(options: &[ClientProfileOptions]) -> Result<Self> {
+        Ok(Self(Arc::new(
+            options
+                .iter()
+                .map(|options| ClientProfile::new(options).map(Arc::new))
+                .collect::<Result<_>>()?,
+        )))
+    }
+
+    /// The first profile listing the bind DN, or otherwise the first one listing the IP of the
+    /// client.
+    pub fn find(
+        &self,
+        bind_dn: Option<&str>,
+        client_ip: Option<IpAddr>,
+    ) -> Option<Arc<ClientProfile>> {
+        let bind_dn = bind_dn.and_then(|dn| parse_dn(dn).ok());
+        bind_dn
+            .and_then(|dn| self.0.iter().find(|p| p.bind_dns.contains(&dn)))
+            .or_else(|| {
+                let ip = client_ip?;
+                self.0
+                    .iter()
+                    .find(|p| p.networks.iter().any(|network| network.contains(&ip)))
+            })
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::LdapPartialAttribute;
+
+    fn profiles() -> ClientProfiles {
+        ClientProfiles::new(&[
+            ClientProfileOptions {
+                name: "printer".to_owned(),
+                bind_dns: vec!["uid=printer, ou=people, dc=example, dc=com".to_owned()],
+                client_ips: vec![],
+                attributes: vec!["cn".to_owned(), "mail".to_owned()],
+                subtrees: vec!["ou=people,dc=example,dc=com".to_owned()],
+            },
+            ClientProfileOptions {
+                name: "office".to_owned(),
+                bind_dns: vec![],
+                client_ips: vec!["10.0.0.0/8".to_owned(), "192.168.1.1".to_owned()],
+                attributes: vec![],
+                subtrees: vec![],
+            },
+        ])
+        .unwrap()
+    }
+
+    fn entry(dn: &str) -> LdapOp {
+        LdapOp::SearchResultEntry(LdapSearchResultEntry {
+            dn: dn.to_owned(),
+            attributes: vec![
+                LdapPartialAttribute {
+                    atype: "CN".to_owned(),
+                    vals: vec![b"Bob".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "telephoneNumber".to_owned(),
+                    vals: vec![b"0123".to_vec()],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn test_invalid_profile() {
+        let options = ClientProfileOptions {
+            name: "bad".to_owned(),
+            bind_dns: vec![],
+            client_ips: vec!["printer.example.com".to_owned()],
+            attributes: vec![],
+            subtrees: vec![],
+        };
+        ClientProfiles::new(&[options]).unwrap_err();
+    }
+
+    #[test]
+    fn test_find() {
+        let profiles = profiles();
+        let office_ip = "10.1.2.3".parse().ok();
+        let find = |dn, ip| profiles.find(dn, ip).map(|p| p.name.clone());
+        assert_eq!(
+            find(Some("UID=printer,ou=people,dc=example,dc=com"), office_ip),
+            Some("printer".to_owned())
+        );
+        assert_eq!(
+            find(Some("uid=bob,ou=people,dc=example,dc=com"), office_ip),
+            Some("office".to_owned())
+        );
+        assert_eq!(find(None, "192.168.1.2".parse().ok()), None);
+    }
+
+    #[test]
+    fn test_restrict() {
+        let profiles = profiles();
+        let printer = profiles
+            .find(Some("uid=printer,ou=people,dc=example,dc=com"), None)
+            .unwrap();
+        assert_eq!(
+            printer.restrict(entry("uid=bob,ou=people,dc=example,dc=com")),
+            Some(LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "CN".to_owned(),
+                    vals: vec![b"Bob".to_vec()],
+                }],
+            }))
+        );
+        assert_eq!(
+            printer.restrict(entry("cn=Best Group,ou=groups,dc=example,dc=com")),
+            None
+        );
+        let office = profiles.find(None, "10.1.2.3".parse().ok()).unwrap();
+        let bob = entry("uid=bob,ou=people,dc=example,dc=com");
+        assert_eq!(office.restrict(bob.clone()), Some(bob));
+    }
+}
@@ -1,27 +1,51 @@
 use crate::{
     domain::{
-        handler::{BackendHandler, BindRequest, CreateUserRequest, LoginHandler},
+        error::DomainError,
+        handler::{
+            AuditActor, BackendHandler, BindRequest, CreateUserRequest, GroupRequestFilter,
+            LoginHandler, LoginKind,
+        },
         ldap::{
             error::{LdapError, LdapResult},
             group::get_groups_list,
             user::get_user_list,
             utils::{
-                get_user_id_from_distinguished_name, is_subtree, parse_distinguished_name, LdapInfo,
+                get_service_account_id_from_distinguished_name, is_subtree,
+                parse_distinguished_name, LdapInfo,
             },
         },
+        legacy_password,
         opaque_handler::OpaqueHandler,
-        types::{JpegPhoto, UserId},
+        types::{AuditSource, Change, ChangeEntryType, ChangeType, GroupId, JpegPhoto, UserId},
+    },
+    infra::{
+        auth_service::{Permission, ValidationResults},
+        configuration::{
+            ActiveDirectoryOptions, MembershipOptions, RegularUserVisibility, ReplicaOptions,
+        },
+        ldap_client_profiles::{ClientProfile, ClientProfiles},
+        ldap_primary::PrimaryLdap,
+        read_only::{ReadOnlyMode, READ_ONLY_ERROR},
     },
-    infra::auth_service::{Permission, ValidationResults},
 };
 use anyhow::Result;
+use futures_util::{
+    future,
+    stream::{self, LocalBoxStream},
+    StreamExt, TryStreamExt,
+};
 use ldap3_proto::proto::{
-    LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapExtendedRequest,
-    LdapExtendedResponse, LdapFilter, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest,
+    LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapControl,
+    LdapExtendedRequest, LdapExtendedResponse, LdapFilter, LdapIntermediateResponse,
+    LdapModifyRequest, LdapModifyType, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest,
     LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry,
-    LdapSearchScope,
+    LdapSearchScope, SyncRequestMode, SyncStateValue,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::Arc,
 };
-use std::collections::HashMap;
 use tracing::{debug, instrument, warn};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -38,30 +62,28 @@ enum SearchScope {
     Invalid,
 }
 
-fn get_search_scope(base_dn: &[(String, String)], dn_parts: &[(String, String)]) -> SearchScope {
-    let base_dn_len = base_dn.len();
-    if !is_subtree(dn_parts, base_dn) {
+fn get_search_scope(ldap_info: &LdapInfo, dn_parts: &[(String, String)]) -> SearchScope {
+    let base_dn_len = ldap_info.base_dn.len();
+    let is_ou = |part: &(String, String), ou: &str| part.0 == "ou" && part.1 == ou;
+    let user_ou = &ldap_info.membership_options.user_ou;
+    let group_ou = &ldap_info.membership_options.group_ou;
+    if !is_subtree(dn_parts, &ldap_info.base_dn) {
         SearchScope::Invalid
     } else if dn_parts.len() == base_dn_len {
         SearchScope::Global
-    } else if dn_parts.len() == base_dn_len + 1
-        && dn_parts[0] == ("ou".to_string(), "people".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 1 && is_ou(&dn_parts[0], user_ou) {
         SearchScope::Users
-    } else if dn_parts.len() == base_dn_len + 1
-        && dn_parts[0] == ("ou".to_string(), "groups".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 1 && is_ou(&dn_parts[0], group_ou) {
         SearchScope::Groups
-    } else if dn_parts.len() == base_dn_len + 2
-        && dn_parts[1] == ("ou".to_string(), "people".to_string())
-    {
-        SearchScope::User(LdapFilter::Equality(
-            dn_parts[0].0.clone(),
-            dn_parts[0].1.clone(),
-        ))
-    } else if dn_parts.len() == base_dn_len + 2
-        && dn_parts[1] == ("ou".to_string(), "groups".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 2 && is_ou(&dn_parts[1], user_ou) {
+        // With "cn" as the naming attribute, its value in the DN is still the user ID.
+        let attribute = if dn_parts[0].0 == ldap_info.membership_options.user_dn_attribute {
+            "uid".to_owned()
+        } else {
+            dn_parts[0].0.clone()
+        };
+        SearchScope::User(LdapFilter::Equality(attribute, dn_parts[0].1.clone()))
+    } else if dn_parts.len() == base_dn_len + 2 && is_ou(&dn_parts[1], group_ou) {
         SearchScope::Group(LdapFilter::Equality(
             dn_parts[0].0.clone(),
             dn_parts[0].1.clone(),
@@ -71,6 +93,79 @@ fn get_search_scope(base_dn: &[(String, String)], dn_parts: &[(String, String)])
     }
 }
 
+fn scope_includes(scope: &SearchScope, entry_type: ChangeEntryType) -> bool {
+    matches!(
+        (scope, entry_type),
+        (SearchScope::Global, _)
+            | (
+                SearchScope::Users | SearchScope::User(_),
+                ChangeEntryType::User
+            )
+            | (
+                SearchScope::Groups | SearchScope::Group(_),
+                ChangeEntryType::Group
+            )
+    )
+}
+
+/// A response to a content synchronization search, along with its controls.
+type SyncResponse = (LdapOp, Vec<LdapControl>);
+
+/// How often the change log is checked for new changes while a synchronization search persists.
+const SYNC_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+// The sync cookie is the ID of the last change the client knows about.
+fn make_sync_cookie(change_id: i32) -> Vec<u8> {
+    change_id.to_string().into_bytes()
+}
+
+fn parse_sync_cookie(cookie: &[u8]) -> Option<i32> {
+    std::str::from_utf8(cookie).ok()?.parse().ok()
+}
+
+fn make_sync_state(
+    state: SyncStateValue,
+    entry_uuid: uuid::Uuid,
+    cookie: Option<i32>,
+) -> Vec<LdapControl> {
+    vec![LdapControl::SyncState {
+        state,
+        entry_uuid,
+        cookie: cookie.map(make_sync_cookie),
+    }]
+}
+
+fn make_change_log_error(e: DomainError) -> LdapError {
+    LdapError {
+        code: LdapResultCode::Other,
+        message: format!("Error while reading the change log: {:#}", e),
+    }
+}
+
+/// Keeps only the latest change of each entry, ordered by change, along with whether the entry
+/// was created by one of the changes.
+fn compact_changes(changes: Vec<Change>) -> Vec<(Change, bool)> {
+    let added: HashSet<_> = changes
+        .iter()
+        .filter(|c| c.change_type == ChangeType::Add)
+        .map(|c| c.entry_uuid.clone())
+        .collect();
+    let mut seen = HashSet::new();
+    let mut latest: Vec<_> = changes
+        .into_iter()
+        .rev()
+        .filter(|c| seen.insert(c.entry_uuid.clone()))
+        .collect();
+    latest.reverse();
+    latest
+        .into_iter()
+        .map(|c| {
+            let was_added = added.contains(&c.entry_uuid);
+            (c, was_added)
+        })
+        .collect()
+}
+
 fn make_search_success() -> LdapOp {
     make_search_error(LdapResultCode::Success, "".to_string())
 }
@@ -93,6 +188,15 @@ fn make_add_error(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
+fn make_modify_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::ModifyResponse(LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message,
+        referral: vec![],
+    })
+}
+
 fn make_extended_response(code: LdapResultCode, message: String) -> LdapOp {
     LdapOp::ExtendedResponse(LdapExtendedResponse {
         res: LdapResultOp {
@@ -135,7 +239,8 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
             },
             LdapPartialAttribute {
                 atype: "supportedControl".to_string(),
-                vals: vec![],
+                // Content synchronization (RFC 4533).
+                vals: vec![b"1.3.6.1.4.1.4203.1.9.1.1".to_vec()],
             },
             LdapPartialAttribute {
                 atype: "supportedFeatures".to_string(),
@@ -162,73 +267,216 @@ pub struct LdapHandler<Backend: BackendHandler + LoginHandler + OpaqueHandler> {
     user_info: Option<ValidationResults>,
     backend_handler: Backend,
     ldap_info: LdapInfo,
+    regular_user_visibility: RegularUserVisibility,
+    /// Set on a replica, to forward the binds and the writes.
+    primary: Option<PrimaryLdap>,
+    read_only: ReadOnlyMode,
+    client_profiles: ClientProfiles,
+    client_ip: Option<IpAddr>,
+    /// The restrictions on the search results of the session.
+    client_profile: Option<Arc<ClientProfile>>,
 }
 
 impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend> {
     pub fn new(
         backend_handler: Backend,
-        mut ldap_base_dn: String,
+        ldap_base_dn: String,
+        ldap_base_dn_aliases: Vec<String>,
         ignored_user_attributes: Vec<String>,
         ignored_group_attributes: Vec<String>,
+        membership_options: MembershipOptions,
+        regular_user_visibility: RegularUserVisibility,
     ) -> Self {
-        ldap_base_dn.make_ascii_lowercase();
         Self {
             user_info: None,
             backend_handler,
-            ldap_info: LdapInfo {
-                base_dn: parse_distinguished_name(&ldap_base_dn).unwrap_or_else(|_| {
-                    panic!(
-                        "Invalid value for ldap_base_dn in configuration: {}",
-                        ldap_base_dn
-                    )
-                }),
-                base_dn_str: ldap_base_dn,
+            ldap_info: LdapInfo::new(
+                ldap_base_dn,
+                &ldap_base_dn_aliases,
                 ignored_user_attributes,
                 ignored_group_attributes,
-            },
+                membership_options,
+            ),
+            regular_user_visibility,
+            primary: None,
+            read_only: ReadOnlyMode::default(),
+            client_profiles: ClientProfiles::default(),
+            client_ip: None,
+            client_profile: None,
+        }
+    }
+
+    pub fn with_active_directory(self, options: &ActiveDirectoryOptions) -> Self {
+        Self {
+            ldap_info: self.ldap_info.with_active_directory(options),
+            ..self
+        }
+    }
+
+    pub fn with_read_only_mode(self, read_only: ReadOnlyMode) -> Self {
+        Self { read_only, ..self }
+    }
+
+    pub fn with_client_profiles(self, client_profiles: ClientProfiles, client_ip: IpAddr) -> Self {
+        Self {
+            client_profile: client_profiles.find(None, Some(client_ip)),
+            client_profiles,
+            client_ip: Some(client_ip),
+            ..self
+        }
+    }
+
+    pub fn with_primary(self, options: ReplicaOptions) -> Self {
+        Self {
+            primary: Some(PrimaryLdap::new(options)),
+            ..self
         }
     }
 
-    #[instrument(skip_all, level = "debug")]
     pub async fn do_bind(&mut self, request: &LdapBindRequest) -> (LdapResultCode, String) {
+        let result = self.check_bind(request).await;
+        // The profile of the client can depend on the DN it is bound as.
+        let bind_dn =
+            (result.0 == LdapResultCode::Success).then(|| self.ldap_info.normalize_dn(&request.dn));
+        self.client_profile = self
+            .client_profiles
+            .find(bind_dn.as_deref(), self.client_ip);
+        result
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn check_bind(&mut self, request: &LdapBindRequest) -> (LdapResultCode, String) {
         debug!("DN: {}", &request.dn);
-        let user_id = match get_user_id_from_distinguished_name(
-            &request.dn.to_ascii_lowercase(),
+        let dn = self.ldap_info.normalize_dn(&request.dn);
+        let LdapBindCred::Simple(password) = &request.cred;
+        if let Ok(service_id) = get_service_account_id_from_distinguished_name(
+            &dn,
             &self.ldap_info.base_dn,
             &self.ldap_info.base_dn_str,
         ) {
+            let result = self.do_service_account_bind(&service_id, password).await;
+            if let Some(primary) = &mut self.primary {
+                primary.set_credentials(
+                    (result.0 == LdapResultCode::Success)
+                        .then(|| (request.dn.as_str(), password.as_str())),
+                );
+            }
+            return result;
+        }
+        let user_id = match self
+            .ldap_info
+            .parse_active_directory_name(request.dn.trim())
+            .map(Ok)
+            .unwrap_or_else(|| self.ldap_info.get_user_id_from_dn(&dn))
+        {
             Ok(s) => s,
             Err(e) => return (LdapResultCode::NamingViolation, e.to_string()),
         };
-        let LdapBindCred::Simple(password) = &request.cred;
-        match self
-            .backend_handler
-            .bind(BindRequest {
-                name: user_id.clone(),
-                password: password.clone(),
-            })
-            .await
-        {
+        // The primary records the failed attempts, the replica can't.
+        let bind_result = match &mut self.primary {
+            Some(primary) => primary.bind(&request.dn, password).await,
+            None => {
+                self.backend_handler
+                    .bind(BindRequest {
+                        name: user_id.clone(),
+                        password: password.clone(),
+                    })
+                    .await
+            }
+        };
+        match bind_result {
             Ok(()) => {
-                let user_groups = self.backend_handler.get_user_groups(&user_id).await;
-                let is_in_group = |name| {
+                match self
+                    .backend_handler
+                    .get_user_details_from_primary(&user_id)
+                    .await
+                {
+                    Ok(user) if !user.can_log_in_at(chrono::Utc::now()) => {
+                        debug!("Account is disabled or expired");
+                        return (
+                            LdapResultCode::UnwillingToPerform,
+                            "Account is disabled or expired".to_string(),
+                        );
+                    }
+                    Err(e) => return (LdapResultCode::OperationsError, e.to_string()),
+                    Ok(_) => {}
+                }
+                let user_groups = self
+                    .backend_handler
+                    .get_user_groups_from_primary(&user_id)
+                    .await;
+                let is_in_group = |name: &str| {
                     user_groups
                         .as_ref()
                         .map(|groups| groups.iter().any(|g| g.display_name == name))
                         .unwrap_or(false)
                 };
-                self.user_info = Some(ValidationResults {
-                    user: user_id,
-                    permission: if is_in_group("lldap_admin") {
-                        Permission::Admin
-                    } else if is_in_group("lldap_password_manager") {
-                        Permission::PasswordManager
-                    } else if is_in_group("lldap_strict_readonly") {
-                        Permission::Readonly
-                    } else {
-                        Permission::Regular
-                    },
-                });
+                let mut user_info =
+                    ValidationResults::new(user_id, Permission::from_groups(is_in_group));
+                user_info.regular_user_visibility = self.regular_user_visibility;
+                if !user_info.is_admin() {
+                    match self
+                        .backend_handler
+                        .get_user_capabilities(&user_info.user)
+                        .await
+                    {
+                        Ok(capabilities) => user_info.add_capabilities(capabilities),
+                        Err(e) => return (LdapResultCode::OperationsError, e.to_string()),
+                    }
+                }
+                // The database of a replica is only written by the synchronization.
+                if self.primary.is_none() {
+                    if let Err(e) = self
+                        .backend_handler
+                        .record_login(&user_info.user, LoginKind::Ldap)
+                        .await
+                    {
+                        warn!("Could not record the login of {}: {}", user_info.user, e);
+                    }
+                }
+                // The changes of the session are recorded in the audit log on behalf of the user.
+                self.backend_handler
+                    .set_audit_actor(AuditActor::new(user_info.user.clone(), AuditSource::Ldap));
+                self.user_info = Some(user_info);
+                debug!("Success!");
+                (LdapResultCode::Success, "".to_string())
+            }
+            // Same as 389-ds.
+            Err(DomainError::AccountLocked(_)) => (
+                LdapResultCode::ConstraintViolation,
+                "Exceed password retry limit. Please try later.".to_string(),
+            ),
+            Err(_) => (LdapResultCode::InvalidCredentials, "".to_string()),
+        }
+    }
+
+    // Like the API tokens, the service accounts act as a pseudo-user, e.g. "service:nextcloud",
+    // which has no entry nor groups in the directory.
+    async fn do_service_account_bind(
+        &mut self,
+        service_id: &str,
+        password: &str,
+    ) -> (LdapResultCode, String) {
+        match self
+            .backend_handler
+            .bind_service_account(service_id, password)
+            .await
+        {
+            Ok(account) => {
+                let permission = if account.read_all {
+                    Permission::Readonly
+                } else {
+                    Permission::Regular
+                };
+                let mut user_info = ValidationResults::new(
+                    UserId::new(&format!("service:{}", account.service_id)),
+                    permission,
+                );
+                user_info.regular_user_visibility = self.regular_user_visibility;
+                // The changes of the session are recorded in the audit log on behalf of the user.
+                self.backend_handler
+                    .set_audit_actor(AuditActor::new(user_info.user.clone(), AuditSource::Ldap));
+                self.user_info = Some(user_info);
                 debug!("Success!");
                 (LdapResultCode::Success, "".to_string())
             }
@@ -269,15 +517,14 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         })?;
         match (&request.user_identity, &request.new_password) {
             (Some(user), Some(password)) => {
-                match get_user_id_from_distinguished_name(
-                    user,
-                    &self.ldap_info.base_dn,
-                    &self.ldap_info.base_dn_str,
-                ) {
+                match self
+                    .ldap_info
+                    .get_user_id_from_dn(&self.ldap_info.normalize_dn(user))
+                {
                     Ok(uid) => {
-                        let user_is_admin = self
+                        let user_groups = self
                             .backend_handler
-                            .get_user_groups(&uid)
+                            .get_user_groups_from_primary(&uid)
                             .await
                             .map_err(|e| LdapError {
                                 code: LdapResultCode::OperationsError,
@@ -285,10 +532,8 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                                     "Internal error while requesting user's groups: {:#?}",
                                     e
                                 ),
-                            })?
-                            .iter()
-                            .any(|g| g.display_name == "lldap_admin");
-                        if !credentials.can_change_password(&uid, user_is_admin) {
+                            })?;
+                        if !credentials.can_change_password(&uid, &user_groups) {
                             Err(LdapError {
                                 code: LdapResultCode::InsufficentAccessRights,
                                 message: format!(
@@ -296,12 +541,28 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                                     &credentials.user, &uid
                                 ),
                             })
+                        } else if let Err(e) = self
+                            .backend_handler
+                            .check_password_policy(&uid, password)
+                            .await
+                        {
+                            Err(LdapError {
+                                code: LdapResultCode::ConstraintViolation,
+                                message: e.to_string(),
+                            })
                         } else if let Err(e) = self.change_password(&uid, password).await {
                             Err(LdapError {
                                 code: LdapResultCode::Other,
                                 message: format!("Error while changing the password: {:#?}", e),
                             })
                         } else {
+                            if let Err(e) = self
+                                .backend_handler
+                                .add_password_to_history(&uid, password)
+                                .await
+                            {
+                                warn!("Could not record the password in the history: {}", e);
+                            }
                             Ok(vec![make_extended_response(
                                 LdapResultCode::Success,
                                 "".to_string(),
@@ -334,18 +595,20 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         }
     }
 
-    pub async fn do_search_or_dse(
-        &mut self,
-        request: &LdapSearchRequest,
-    ) -> LdapResult<Vec<LdapOp>> {
+    /// Checks the permissions and starts the search, returning the matching entries as they are
+    /// produced.
+    fn search_or_dse_entries<'a>(
+        &'a self,
+        request: &'a LdapSearchRequest,
+    ) -> LdapResult<LocalBoxStream<'a, LdapResult<LdapOp>>> {
         if request.base.is_empty() && request.scope == LdapSearchScope::Base {
             if let LdapFilter::Present(attribute) = &request.filter {
                 if attribute.to_ascii_lowercase() == "objectclass" {
                     debug!("rootDSE request");
-                    return Ok(vec![
-                        root_dse_response(&self.ldap_info.base_dn_str),
-                        make_search_success(),
-                    ]);
+                    return Ok(stream::once(async move {
+                        Ok(root_dse_response(&self.ldap_info.base_dn_str))
+                    })
+                    .boxed_local());
                 }
             }
         }
@@ -353,79 +616,118 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             code: LdapResultCode::InsufficentAccessRights,
             message: "No user currently bound".to_string(),
         })?;
+        if !user_info.can_read_own_entries() {
+            debug!("Hidden entries");
+            return Ok(stream::empty().boxed_local());
+        }
         let user_filter = if user_info.is_admin_or_readonly() {
             None
         } else {
             Some(user_info.user.clone())
         };
-        self.do_search(request, user_filter).await
+        let entries = self.search_entries(request, user_filter)?;
+        Ok(match &self.client_profile {
+            None => entries,
+            Some(profile) => entries
+                .try_filter_map(move |op| future::ready(Ok(profile.restrict(op))))
+                .boxed_local(),
+        })
     }
 
-    #[instrument(skip_all, level = "debug")]
-    pub async fn do_search(
+    pub async fn do_search_or_dse(
         &mut self,
         request: &LdapSearchRequest,
-        user_filter: Option<UserId>,
     ) -> LdapResult<Vec<LdapOp>> {
-        let user_filter = user_filter.as_ref();
-        let dn_parts = parse_distinguished_name(&request.base.to_ascii_lowercase())?;
-        let scope = get_search_scope(&self.ldap_info.base_dn, &dn_parts);
-        debug!(?request.base, ?scope);
-        // Disambiguate the lifetimes.
-        fn cast<T, R, B: 'a, 'a>(x: T) -> T
-        where
-            T: Fn(&'a mut B, &'a LdapFilter) -> R + 'a,
-        {
-            x
+        let mut results: Vec<_> = self.search_or_dse_entries(request)?.try_collect().await?;
+        results.push(make_search_success());
+        Ok(results)
+    }
+
+    /// Same as `do_search_or_dse`, but the responses are produced one by one as the entries are
+    /// read from the backend, instead of collecting the whole result set in memory.
+    pub fn do_search_or_dse_streaming<'a>(
+        &'a self,
+        request: &'a LdapSearchRequest,
+    ) -> LocalBoxStream<'a, LdapOp> {
+        match self.search_or_dse_entries(request) {
+            Ok(entries) => entries
+                .chain(stream::once(async { Ok(make_search_success()) }))
+                // Stop at the first error, and report it instead of the success.
+                .scan(false, |failed, entry: LdapResult<LdapOp>| {
+                    future::ready(if *failed {
+                        None
+                    } else {
+                        Some(entry.unwrap_or_else(|e| {
+                            *failed = true;
+                            make_search_error(e.code, e.message)
+                        }))
+                    })
+                })
+                .boxed_local(),
+            Err(e) => {
+                stream::once(async move { make_search_error(e.code, e.message) }).boxed_local()
+            }
         }
+    }
 
-        let get_user_list = cast(|backend_handler: &mut Backend, filter: &LdapFilter| async {
+    fn search_entries<'a>(
+        &'a self,
+        request: &'a LdapSearchRequest,
+        user_filter: Option<UserId>,
+    ) -> LdapResult<LocalBoxStream<'a, LdapResult<LdapOp>>> {
+        let dn_parts = parse_distinguished_name(&self.ldap_info.normalize_dn(&request.base))?;
+        let scope = get_search_scope(&self.ldap_info, &dn_parts);
+        debug!(?request.base, ?scope);
+        let user_filter_for_groups = user_filter.clone();
+        let get_user_list = move |filter: LdapFilter| {
             get_user_list(
                 &self.ldap_info,
                 filter,
                 &request.attrs,
                 &request.base,
-                &user_filter,
-                backend_handler,
-            )
-            .await
-        });
-        let get_group_list = cast(|backend_handler: &mut Backend, filter: &LdapFilter| async {
-            get_groups_list(
-                &self.ldap_info,
-                filter,
-                &request.attrs,
-                &request.base,
-                &user_filter,
-                backend_handler,
+                user_filter.clone(),
+                &self.backend_handler,
             )
-            .await
-        });
-        let mut results: Vec<_> = match scope {
-            SearchScope::Global => {
-                let mut results = Vec::new();
-                results.extend(get_user_list(&mut self.backend_handler, &request.filter).await?);
-                results.extend(get_group_list(&mut self.backend_handler, &request.filter).await?);
-                results
-            }
-            SearchScope::Users => get_user_list(&mut self.backend_handler, &request.filter).await?,
-            SearchScope::Groups => {
-                get_group_list(&mut self.backend_handler, &request.filter).await?
-            }
+        };
+        let get_group_list = move |filter: LdapFilter| {
+            let user_filter = user_filter_for_groups.clone();
+            stream::once(async move {
+                get_groups_list(
+                    &self.ldap_info,
+                    &filter,
+                    &request.attrs,
+                    &request.base,
+                    &user_filter.as_ref(),
+                    &self.backend_handler,
+                )
+                .await
+            })
+            .map_ok(|groups| stream::iter(groups.into_iter().map(Ok::<_, LdapError>)))
+            .try_flatten()
+            .boxed_local()
+        };
+        Ok(match scope {
+            SearchScope::Global => get_user_list(request.filter.clone())
+                .chain(get_group_list(request.filter.clone()))
+                .boxed_local(),
+            SearchScope::Users => get_user_list(request.filter.clone()),
+            SearchScope::Groups => get_group_list(request.filter.clone()),
             SearchScope::User(filter) => {
-                let filter = LdapFilter::And(vec![request.filter.clone(), filter]);
-                get_user_list(&mut self.backend_handler, &filter).await?
+                get_user_list(LdapFilter::And(vec![request.filter.clone(), filter]))
             }
             SearchScope::Group(filter) => {
-                let filter = LdapFilter::And(vec![request.filter.clone(), filter]);
-                get_group_list(&mut self.backend_handler, &filter).await?
+                get_group_list(LdapFilter::And(vec![request.filter.clone(), filter]))
             }
             SearchScope::Unknown => {
                 warn!(
-                    r#"The requested search tree "{}" matches neither the user subtree "ou=people,{}" nor the group subtree "ou=groups,{}""#,
-                    &request.base, &self.ldap_info.base_dn_str, &self.ldap_info.base_dn_str
+                    r#"The requested search tree "{}" matches neither the user subtree "ou={},{}" nor the group subtree "ou={},{}""#,
+                    &request.base,
+                    &self.ldap_info.membership_options.user_ou,
+                    &self.ldap_info.base_dn_str,
+                    &self.ldap_info.membership_options.group_ou,
+                    &self.ldap_info.base_dn_str
                 );
-                Vec::new()
+                stream::empty().boxed_local()
             }
             SearchScope::Invalid => {
                 // Search path is not in our tree, just return an empty success.
@@ -433,13 +735,301 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                     "The specified search tree {:?} is not under the common subtree {:?}",
                     &dn_parts, &self.ldap_info.base_dn
                 );
-                Vec::new()
+                stream::empty().boxed_local()
             }
-        };
-        if results.is_empty() || matches!(results[results.len() - 1], LdapOp::SearchResultEntry(_))
-        {
-            results.push(make_search_success());
+        })
+    }
+
+    /// Handles a search with the content synchronization control (RFC 4533).
+    ///
+    /// Without a valid cookie, all the matching entries are returned. With the cookie of a
+    /// previous search, only the entries that changed since are returned, based on the change
+    /// log. In "refreshAndPersist" mode, the search then never completes: the change log is
+    /// polled and the new changes are sent as they are recorded.
+    pub fn do_sync_search<'a>(
+        &'a self,
+        request: &'a LdapSearchRequest,
+        mode: SyncRequestMode,
+        cookie: Option<Vec<u8>>,
+    ) -> LocalBoxStream<'a, SyncResponse> {
+        stream::once(async move {
+            let (responses, last_change_id, refresh_deletes) =
+                self.sync_refresh(request, cookie.as_deref()).await?;
+            let responses = stream::iter(responses);
+            let cookie = Some(make_sync_cookie(last_change_id));
+            Ok::<_, LdapError>(match mode {
+                SyncRequestMode::RefreshOnly => responses
+                    .chain(stream::once(future::ready((
+                        make_search_success(),
+                        vec![LdapControl::SyncDone {
+                            cookie,
+                            refresh_deletes,
+                        }],
+                    ))))
+                    .boxed_local(),
+                SyncRequestMode::RefreshAndPersist => {
+                    let refresh_done = if refresh_deletes {
+                        LdapIntermediateResponse::SyncInfoRefreshDelete { cookie, done: true }
+                    } else {
+                        LdapIntermediateResponse::SyncInfoRefreshPresent { cookie, done: true }
+                    };
+                    responses
+                        .chain(stream::once(future::ready((
+                            LdapOp::IntermediateResponse(refresh_done),
+                            vec![],
+                        ))))
+                        .chain(self.sync_persist(request, last_change_id))
+                        .boxed_local()
+                }
+            })
+        })
+        .flat_map(|responses| match responses {
+            Ok(responses) => responses,
+            Err(e) => stream::once(future::ready((
+                make_search_error(e.code, e.message),
+                vec![],
+            )))
+            .boxed_local(),
+        })
+        .filter_map(move |(op, controls)| {
+            future::ready(match &self.client_profile {
+                None => Some((op, controls)),
+                Some(profile) => profile.restrict(op).map(|op| (op, controls)),
+            })
+        })
+        .boxed_local()
+    }
+
+    /// Returns the responses of the refresh phase, the ID of the last change they include, and
+    /// whether the deleted entries were reported (rather than only the present ones).
+    async fn sync_refresh(
+        &self,
+        request: &LdapSearchRequest,
+        cookie: Option<&[u8]>,
+    ) -> LdapResult<(Vec<SyncResponse>, i32, bool)> {
+        let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: "No user currently bound".to_string(),
+        })?;
+        if !user_info.is_admin_or_readonly() {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Content synchronization is restricted to admins and read-only users"
+                    .to_string(),
+            });
         }
+        let last_change_id = self
+            .backend_handler
+            .get_last_change_id()
+            .await
+            .map_err(make_change_log_error)?;
+        match cookie.and_then(parse_sync_cookie) {
+            Some(since) if since <= last_change_id => Ok((
+                self.sync_changes(request, since, last_change_id, None)
+                    .await?,
+                last_change_id,
+                true,
+            )),
+            _ => Ok((
+                self.sync_search(request, None)
+                    .await?
+                    .into_iter()
+                    .map(|(entry, uuid)| {
+                        (
+                            LdapOp::SearchResultEntry(entry),
+                            make_sync_state(SyncStateValue::Add, uuid, None),
+                        )
+                    })
+                    .collect(),
+                last_change_id,
+                false,
+            )),
+        }
+    }
+
+    /// Polls the change log, and streams the changes as they are recorded.
+    fn sync_persist<'a>(
+        &'a self,
+        request: &'a LdapSearchRequest,
+        last_change_id: i32,
+    ) -> LocalBoxStream<'a, SyncResponse> {
+        stream::unfold(Some(last_change_id), move |since| async move {
+            let since = since?;
+            tokio::time::sleep(SYNC_POLL_INTERVAL).await;
+            let changes: LdapResult<(Vec<SyncResponse>, i32)> = async {
+                let until = self
+                    .backend_handler
+                    .get_last_change_id()
+                    .await
+                    .map_err(make_change_log_error)?;
+                if until == since {
+                    return Ok((vec![], until));
+                }
+                Ok((
+                    self.sync_changes(request, since, until, Some(until))
+                        .await?,
+                    until,
+                ))
+            }
+            .await;
+            Some(match changes {
+                Ok((responses, until)) => (stream::iter(responses).boxed_local(), Some(until)),
+                Err(e) => (
+                    stream::once(future::ready((
+                        make_search_error(e.code, e.message),
+                        vec![],
+                    )))
+                    .boxed_local(),
+                    None,
+                ),
+            })
+        })
+        .flatten()
+        .boxed_local()
+    }
+
+    /// Returns the entries that changed after `since`, up to `until`. The entries that were
+    /// deleted or no longer match the search are reported as deleted.
+    async fn sync_changes(
+        &self,
+        request: &LdapSearchRequest,
+        since: i32,
+        until: i32,
+        cookie: Option<i32>,
+    ) -> LdapResult<Vec<SyncResponse>> {
+        let changes: Vec<Change> = self
+            .backend_handler
+            .list_changes_since(since)
+            .await
+            .map_err(make_change_log_error)?
+            .into_iter()
+            .filter(|c| c.change_id <= until)
+            .collect();
+        let dn_parts = parse_distinguished_name(&self.ldap_info.normalize_dn(&request.base))?;
+        let scope = get_search_scope(&self.ldap_info, &dn_parts);
+        let changes: Vec<_> = compact_changes(changes)
+            .into_iter()
+            .filter(|(c, _)| scope_includes(&scope, c.entry_type))
+            .collect();
+        let updated_uuids: Vec<_> = changes
+            .iter()
+            .filter(|(c, _)| c.change_type != ChangeType::Delete)
+            .map(|(c, _)| c.entry_uuid.as_str())
+            .collect();
+        let mut entries: HashMap<uuid::Uuid, LdapSearchResultEntry> = if updated_uuids.is_empty() {
+            HashMap::new()
+        } else {
+            self.sync_search(request, Some(&updated_uuids))
+                .await?
+                .into_iter()
+                .map(|(entry, uuid)| (uuid, entry))
+                .collect()
+        };
+        Ok(changes
+            .into_iter()
+            .filter_map(|(change, added)| {
+                let uuid = uuid::Uuid::parse_str(change.entry_uuid.as_str()).ok()?;
+                Some(match entries.remove(&uuid) {
+                    Some(entry) => (
+                        LdapOp::SearchResultEntry(entry),
+                        make_sync_state(
+                            if added {
+                                SyncStateValue::Add
+                            } else {
+                                SyncStateValue::Modify
+                            },
+                            uuid,
+                            cookie,
+                        ),
+                    ),
+                    None => {
+                        let dn = match change.entry_type {
+                            ChangeEntryType::User => self.ldap_info.user_dn(&change.entry_id),
+                            ChangeEntryType::Group => self
+                                .ldap_info
+                                .group_dn(&change.entry_id, &change.entry_uuid),
+                        };
+                        (
+                            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                                dn,
+                                attributes: vec![],
+                            }),
+                            make_sync_state(SyncStateValue::Delete, uuid, cookie),
+                        )
+                    }
+                })
+            })
+            .collect())
+    }
+
+    /// Runs the search, optionally restricted to the given entry UUIDs, and returns the entries
+    /// along with their UUID, which the sync states refer to.
+    async fn sync_search(
+        &self,
+        request: &LdapSearchRequest,
+        uuids: Option<&[&str]>,
+    ) -> LdapResult<Vec<(LdapSearchResultEntry, uuid::Uuid)>> {
+        let keep_uuid = request.attrs.is_empty()
+            || request
+                .attrs
+                .iter()
+                .any(|a| a == "*" || a.eq_ignore_ascii_case("entryuuid"));
+        let mut attrs = if request.attrs.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            request.attrs.clone()
+        };
+        attrs.push("entryUUID".to_string());
+        let filter = match uuids {
+            None => request.filter.clone(),
+            Some(uuids) => LdapFilter::And(vec![
+                request.filter.clone(),
+                LdapFilter::Or(
+                    uuids
+                        .iter()
+                        .map(|uuid| LdapFilter::Equality("entryUUID".to_string(), uuid.to_string()))
+                        .collect(),
+                ),
+            ]),
+        };
+        let request = LdapSearchRequest {
+            filter,
+            attrs,
+            ..request.clone()
+        };
+        let entries: Vec<_> = self.search_entries(&request, None)?.try_collect().await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|op| match op {
+                LdapOp::SearchResultEntry(mut entry) => {
+                    let index = entry
+                        .attributes
+                        .iter()
+                        .position(|a| a.atype.eq_ignore_ascii_case("entryuuid"))?;
+                    let uuid = std::str::from_utf8(entry.attributes[index].vals.first()?)
+                        .ok()
+                        .and_then(|uuid| uuid::Uuid::parse_str(uuid).ok())?;
+                    if !keep_uuid {
+                        entry.attributes.remove(index);
+                    }
+                    Some((entry, uuid))
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    pub async fn do_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        user_filter: Option<UserId>,
+    ) -> LdapResult<Vec<LdapOp>> {
+        let mut results: Vec<_> = self
+            .search_entries(request, user_filter)?
+            .try_collect()
+            .await?;
+        results.push(make_search_success());
         Ok(results)
     }
 
@@ -447,7 +1037,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         if !self
             .user_info
             .as_ref()
-            .map(|u| u.is_admin())
+            .map(|u| u.can_manage_users())
             .unwrap_or(false)
         {
             return Err(LdapError {
@@ -455,11 +1045,22 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 message: "Unauthorized write".to_string(),
             });
         }
-        let user_id = get_user_id_from_distinguished_name(
-            &request.dn,
-            &self.ldap_info.base_dn,
-            &self.ldap_info.base_dn_str,
-        )?;
+        let user_id = self
+            .ldap_info
+            .get_user_id_from_dn(&self.ldap_info.normalize_dn(&request.dn))?;
+        // The DN is compared in lowercase, but the policy applies to the user ID as written.
+        let raw_user_id = request
+            .dn
+            .split(',')
+            .next()
+            .and_then(|rdn| rdn.split_once('='))
+            .map_or("", |(_, id)| id.trim());
+        self.backend_handler
+            .check_user_id(raw_user_id)
+            .map_err(|e| LdapError {
+                code: LdapResultCode::ConstraintViolation,
+                message: format!("{:#}", e),
+            })?;
         fn parse_attribute(mut attr: LdapPartialAttribute) -> LdapResult<(String, Vec<u8>)> {
             if attr.vals.len() > 1 {
                 Err(LdapError {
@@ -499,6 +1100,17 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 .map(Vec::as_slice)
                 .map(decode_attribute_value)
         };
+        let get_number_attribute = |name| -> LdapResult<Option<i32>> {
+            get_attribute(name)
+                .transpose()?
+                .map(|value| {
+                    value.parse::<i32>().map_err(|e| LdapError {
+                        code: LdapResultCode::ConstraintViolation,
+                        message: format!("Invalid number for attribute {}: {:#}", name, e),
+                    })
+                })
+                .transpose()
+        };
         self.backend_handler
             .create_user(CreateUserRequest {
                 user_id,
@@ -518,6 +1130,27 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                         code: LdapResultCode::ConstraintViolation,
                         message: format!("Invalid JPEG photo: {:#?}", e),
                     })?,
+                uid_number: get_number_attribute("uidnumber")?,
+                gid_number: get_number_attribute("gidnumber")?,
+                home_directory: get_attribute("homedirectory").transpose()?,
+                login_shell: get_attribute("loginshell").transpose()?,
+                telephone_number: get_attribute("telephonenumber").transpose()?,
+                mobile: get_attribute("mobile").transpose()?,
+                title: get_attribute("title").transpose()?,
+                department: get_attribute("ou").transpose()?,
+                locality: get_attribute("l").transpose()?,
+                postal_address: get_attribute("postaladdress").transpose()?,
+                locale: get_attribute("preferredlanguage").transpose()?,
+                timezone: None,
+                valid_from: None,
+                valid_until: None,
+                attributes: Vec::new(),
+                // A hashed userPassword, from the export of another directory, is checked on the
+                // first bind.
+                legacy_password_hash: get_attribute("userpassword")
+                    .transpose()?
+                    .filter(|hash| legacy_password::is_supported(hash)),
+                template: None,
             })
             .await
             .map_err(|e| LdapError {
@@ -527,35 +1160,178 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
     }
 
-    pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
-        Some(match ldap_op {
-            LdapOp::BindRequest(request) => {
-                let (code, message) = self.do_bind(&request).await;
-                vec![LdapOp::BindResponse(LdapBindResponse {
-                    res: LdapResultOp {
-                        code,
-                        matcheddn: "".to_string(),
-                        message,
-                        referral: vec![],
-                    },
-                    saslcreds: None,
-                })]
+    /// Adds or removes the members of a group. Admins can modify any group, the other users only
+    /// the groups they manage, and never the admin group.
+    async fn do_modify_request(&self, request: LdapModifyRequest) -> LdapResult<Vec<LdapOp>> {
+        let credentials = self.user_info.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: "No user currently bound".to_string(),
+        })?;
+        let group_filter = self.ldap_info.parse_group_dn(&request.dn)?;
+        let group_id = self
+            .backend_handler
+            .list_groups(Some(group_filter))
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while requesting the group: {:#?}", e),
+            })?
+            .into_iter()
+            .map(|g| g.id)
+            .next()
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::NoSuchObject,
+                message: format!("No such group: `{}`", request.dn),
+            })?;
+        for change in request.changes {
+            if !self
+                .ldap_info
+                .membership_options
+                .is_member_attribute(&change.modification.atype)
+            {
+                return Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported attribute modification: {}",
+                        change.modification.atype
+                    ),
+                });
             }
-            LdapOp::SearchRequest(request) => self
-                .do_search_or_dse(&request)
-                .await
-                .unwrap_or_else(|e: LdapError| vec![make_search_error(e.code, e.message)]),
-            LdapOp::UnbindRequest => {
-                self.user_info = None;
-                // No need to notify on unbind (per rfc4511)
-                return None;
+            let add = match change.operation {
+                LdapModifyType::Add => true,
+                LdapModifyType::Delete if !change.modification.vals.is_empty() => false,
+                _ => {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: "Members can only be added or removed one by one".to_string(),
+                    })
+                }
+            };
+            for value in &change.modification.vals {
+                let user_id =
+                    self.ldap_info
+                        .parse_member_value(std::str::from_utf8(value).map_err(|e| {
+                            LdapError {
+                                code: LdapResultCode::ConstraintViolation,
+                                message: format!("Member value is invalid UTF-8: {:#?}", e),
+                            }
+                        })?)?;
+                self.modify_membership(credentials, &user_id, group_id, add)
+                    .await?;
             }
-            LdapOp::ExtendedRequest(request) => self.do_extended_request(&request).await,
-            LdapOp::AddRequest(request) => self
-                .do_create_user(request)
-                .await
-                .unwrap_or_else(|e: LdapError| vec![make_add_error(e.code, e.message)]),
-            op => vec![make_extended_response(
+        }
+        Ok(vec![make_modify_response(
+            LdapResultCode::Success,
+            String::new(),
+        )])
+    }
+
+    async fn modify_membership(
+        &self,
+        credentials: &ValidationResults,
+        user_id: &UserId,
+        group_id: GroupId,
+        add: bool,
+    ) -> LdapResult<()> {
+        let user_groups = self
+            .backend_handler
+            .get_user_groups_from_primary(user_id)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while requesting user's groups: {:#?}", e),
+            })?;
+        if !credentials.is_admin()
+            && (group_id == GroupId(1)
+                || !credentials.can_manage_membership(group_id, &user_groups))
+        {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    r#"User `{}` cannot modify the members of the group"#,
+                    &credentials.user
+                ),
+            });
+        }
+        let result = if add {
+            self.backend_handler
+                .add_user_to_group(user_id, group_id)
+                .await
+        } else {
+            self.backend_handler
+                .remove_user_from_group(user_id, group_id)
+                .await
+        };
+        result.map_err(|e| LdapError {
+            code: match e {
+                DomainError::LastAdmin(_) => LdapResultCode::UnwillingToPerform,
+                _ => LdapResultCode::OperationsError,
+            },
+            message: format!("Could not modify the members of the group: {:#?}", e),
+        })?;
+        Ok(())
+    }
+
+    pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
+        if self.read_only.is_enabled() {
+            let code = LdapResultCode::UnwillingToPerform;
+            match &ldap_op {
+                LdapOp::AddRequest(_) => {
+                    return Some(vec![make_add_error(code, READ_ONLY_ERROR.to_owned())])
+                }
+                LdapOp::ModifyRequest(_) => {
+                    return Some(vec![make_modify_response(code, READ_ONLY_ERROR.to_owned())])
+                }
+                LdapOp::ExtendedRequest(_) => {
+                    return Some(vec![make_extended_response(
+                        code,
+                        READ_ONLY_ERROR.to_owned(),
+                    )])
+                }
+                _ => {}
+            }
+        }
+        Some(match ldap_op {
+            LdapOp::BindRequest(request) => {
+                let (code, message) = self.do_bind(&request).await;
+                vec![LdapOp::BindResponse(LdapBindResponse {
+                    res: LdapResultOp {
+                        code,
+                        matcheddn: "".to_string(),
+                        message,
+                        referral: vec![],
+                    },
+                    saslcreds: None,
+                })]
+            }
+            LdapOp::SearchRequest(request) => self
+                .do_search_or_dse(&request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_search_error(e.code, e.message)]),
+            LdapOp::UnbindRequest => {
+                self.user_info = None;
+                // No need to notify on unbind (per rfc4511)
+                return None;
+            }
+            LdapOp::ExtendedRequest(request) => match &self.primary {
+                Some(primary) => vec![LdapOp::ExtendedResponse(primary.extended(request).await)],
+                None => self.do_extended_request(&request).await,
+            },
+            LdapOp::AddRequest(request) => match &self.primary {
+                Some(primary) => vec![LdapOp::AddResponse(primary.add(request).await)],
+                None => self
+                    .do_create_user(request)
+                    .await
+                    .unwrap_or_else(|e: LdapError| vec![make_add_error(e.code, e.message)]),
+            },
+            LdapOp::ModifyRequest(request) => match &self.primary {
+                Some(primary) => vec![LdapOp::ModifyResponse(primary.modify(request).await)],
+                None => self
+                    .do_modify_request(request)
+                    .await
+                    .unwrap_or_else(|e: LdapError| vec![make_modify_response(e.code, e.message)]),
+            },
+            op => vec![make_extended_response(
                 LdapResultCode::UnwillingToPerform,
                 format!("Unsupported operation: {:#?}", op),
             )],
@@ -568,11 +1344,12 @@ mod tests {
     use super::*;
     use crate::{
         domain::{error::Result, handler::*, opaque_handler::*, types::*},
+        infra::configuration::{MembershipValueFormat, PasswordPolicyOptions},
         uuid,
     };
     use async_trait::async_trait;
     use chrono::TimeZone;
-    use ldap3_proto::proto::{LdapDerefAliases, LdapSearchScope};
+    use ldap3_proto::proto::{LdapDerefAliases, LdapModify, LdapSearchScope};
     use mockall::predicate::eq;
     use std::collections::HashSet;
     use tokio;
@@ -589,21 +1366,155 @@ mod tests {
         #[async_trait]
         impl GroupBackendHandler for TestBackendHandler {
             async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+            async fn list_groups_sorted(&self, filters: Option<GroupRequestFilter>, order: GroupOrder) -> Result<Vec<Group>>;
             async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
             async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
             async fn create_group(&self, group_name: &str) -> Result<GroupId>;
             async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+            async fn add_group_to_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
+            async fn remove_group_from_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
         }
         #[async_trait]
         impl UserBackendHandler for TestBackendHandler {
             async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+            async fn list_users_page(&self, filters: Option<UserRequestFilter>, get_groups: bool, order: UserOrder, offset: u64, limit: Option<u64>) -> Result<UserPage>;
             async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
             async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+            async fn create_users(&self, requests: Vec<CreateUserRequest>) -> Result<Vec<Result<()>>>;
+            fn check_user_id(&self, user_id: &str) -> Result<()>;
             async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+            async fn add_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+            async fn remove_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+            async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<()>;
             async fn delete_user(&self, user_id: &UserId) -> Result<()>;
             async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+            async fn get_users_groups(&self, user_ids: &[UserId]) -> Result<HashMap<UserId, HashSet<GroupDetails>>>;
             async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+            async fn add_user_to_group_until(&self, user_id: &UserId, group_id: GroupId, expiry_date: DateTime) -> Result<()>;
             async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+            async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+            async fn remove_users_from_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+            async fn record_login(&self, user_id: &UserId, kind: LoginKind) -> Result<()>;
+        }
+        #[async_trait]
+        impl SchemaBackendHandler for TestBackendHandler {
+            async fn get_user_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+            async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+            async fn delete_user_attribute(&self, name: &str) -> Result<()>;
+            async fn get_group_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+            async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+            async fn delete_group_attribute(&self, name: &str) -> Result<()>;
+        }
+        #[async_trait]
+        impl ChangeLogBackendHandler for TestBackendHandler {
+            async fn list_changes_since(&self, change_id: i32) -> Result<Vec<Change>>;
+            async fn list_changes_page(&self, change_id: i32, limit: u64) -> Result<Vec<Change>>;
+            async fn get_last_change_id(&self) -> Result<i32>;
+        }
+        #[async_trait]
+        impl AuditLogBackendHandler for TestBackendHandler {
+            async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+            async fn list_audit_logs(&self, filter: AuditLogFilter, offset: u64, limit: Option<u64>) -> Result<AuditLogPage>;
+        }
+        #[async_trait]
+        impl ApiTokenBackendHandler for TestBackendHandler {
+            async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+            async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+            async fn delete_api_token(&self, token_id: i32) -> Result<()>;
+            async fn validate_api_token(&self, token: &str) -> Result<ApiToken>;
+        }
+        #[async_trait]
+        impl ServiceAccountBackendHandler for TestBackendHandler {
+            async fn create_service_account(&self, request: CreateServiceAccountRequest) -> Result<(ServiceAccount, String)>;
+            async fn list_service_accounts(&self) -> Result<Vec<ServiceAccount>>;
+            async fn update_service_account(&self, request: UpdateServiceAccountRequest) -> Result<()>;
+            async fn reset_service_account_password(&self, service_id: &str) -> Result<String>;
+            async fn delete_service_account(&self, service_id: &str) -> Result<()>;
+            async fn bind_service_account(&self, service_id: &str, password: &str) -> Result<ServiceAccount>;
+        }
+        #[async_trait]
+        impl SessionBackendHandler for TestBackendHandler {
+            async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+            async fn delete_user_session(&self, user_id: &UserId, session_id: i64) -> Result<()>;
+            async fn delete_all_user_sessions(&self, user_id: &UserId) -> Result<()>;
+        }
+        #[async_trait]
+        impl PasswordPolicyBackendHandler for TestBackendHandler {
+            fn get_password_policy(&self) -> crate::infra::configuration::PasswordPolicyOptions;
+            async fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result<()>;
+            async fn add_password_to_history(&self, user_id: &UserId, password: &str) -> Result<()>;
+        }
+        #[async_trait]
+        impl AccountLockoutBackendHandler for TestBackendHandler {
+            async fn get_account_lockout(&self, user_id: &UserId) -> Result<Option<DateTime>>;
+            async fn unlock_account(&self, user_id: &UserId) -> Result<()>;
+        }
+        #[async_trait]
+        impl PasswordResetBackendHandler for TestBackendHandler {
+            async fn create_password_reset_token(&self, user_id: &UserId, validity: chrono::Duration) -> Result<String>;
+        }
+        #[async_trait]
+        impl WebauthnCredentialBackendHandler for TestBackendHandler {
+            async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+            async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str) -> Result<()>;
+        }
+        #[async_trait]
+        impl InvitationBackendHandler for TestBackendHandler {
+            async fn create_invitation(&self, request: CreateInvitationRequest) -> Result<String>;
+            async fn get_invitation(&self, token: &str) -> Result<Invitation>;
+            async fn accept_invitation(&self, token: &str, user_id: &UserId) -> Result<()>;
+            async fn delete_invitation(&self, token: &str) -> Result<()>;
+        }
+        #[async_trait]
+        impl PendingUserBackendHandler for TestBackendHandler {
+            async fn create_pending_user(&self, request: CreatePendingUserRequest) -> Result<()>;
+            async fn list_pending_users(&self) -> Result<Vec<PendingUser>>;
+            async fn approve_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+            async fn reject_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+        }
+        #[async_trait]
+        impl RecycleBinBackendHandler for TestBackendHandler {
+            async fn list_deleted_users(&self) -> Result<Vec<DeletedUser>>;
+            async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+            async fn purge_user(&self, user_id: &UserId) -> Result<()>;
+            async fn list_deleted_groups(&self) -> Result<Vec<DeletedGroup>>;
+            async fn restore_group(&self, group_id: GroupId) -> Result<()>;
+            async fn purge_group(&self, group_id: GroupId) -> Result<()>;
+        }
+        #[async_trait]
+        impl RoleBackendHandler for TestBackendHandler {
+            async fn list_roles(&self) -> Result<Vec<Role>>;
+            async fn create_role(&self, request: CreateRoleRequest) -> Result<i32>;
+            async fn delete_role(&self, role_id: i32) -> Result<()>;
+            async fn add_role_to_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+            async fn remove_role_from_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+            async fn get_user_capabilities(&self, user_id: &UserId) -> Result<Capabilities>;
+        }
+        #[async_trait]
+        impl GroupManagerBackendHandler for TestBackendHandler {
+            async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+            async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+            async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        }
+        #[async_trait]
+        impl OidcBackendHandler for TestBackendHandler {
+            async fn create_oidc_client(&self, request: CreateOidcClientRequest) -> Result<String>;
+            async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>>;
+            async fn get_oidc_client(&self, client_id: &str) -> Result<OidcClient>;
+            async fn delete_oidc_client(&self, client_id: &str) -> Result<()>;
+            async fn list_oidc_group_claims(&self, client_id: &str) -> Result<Vec<OidcGroupClaim>>;
+            async fn add_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+            async fn remove_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+            async fn create_oidc_authorization_code(&self, code: OidcAuthorizationCode) -> Result<String>;
+            async fn consume_oidc_authorization_code(&self, code: &str) -> Result<OidcAuthorizationCode>;
+        }
+        #[async_trait]
+        impl StatisticsBackendHandler for TestBackendHandler {
+            async fn get_statistics(&self) -> Result<DirectoryStatistics>;
+        }
+        #[async_trait]
+        impl DirectoryChangesBackendHandler for TestBackendHandler {
+            async fn apply_changes(&self, operations: Vec<DirectoryOperation>) -> Result<Vec<GroupId>>;
         }
         #[async_trait]
         impl BackendHandler for TestBackendHandler {}
@@ -650,8 +1561,16 @@ mod tests {
     }
 
     async fn setup_bound_handler_with_group(
+        mock: MockTestBackendHandler,
+        group: &str,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_visibility(mock, group, RegularUserVisibility::default()).await
+    }
+
+    async fn setup_bound_handler_with_visibility(
         mut mock: MockTestBackendHandler,
         group: &str,
+        regular_user_visibility: RegularUserVisibility,
     ) -> LdapHandler<MockTestBackendHandler> {
         mock.expect_bind()
             .with(eq(BindRequest {
@@ -659,6 +1578,15 @@ mod tests {
                 password: "pass".to_string(),
             }))
             .return_once(|_| Ok(()));
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("test"),
+                    ..Default::default()
+                })
+            });
         let group = group.to_string();
         mock.expect_get_user_groups()
             .with(eq(UserId::new("test")))
@@ -669,11 +1597,27 @@ mod tests {
                     display_name: group,
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    gid_number: None,
                 });
                 Ok(set)
             });
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=Example,dc=com".to_string(), vec![], vec![]);
+        mock.expect_get_user_capabilities()
+            .returning(|_| Ok(Capabilities::default()));
+        mock.expect_get_password_policy()
+            .returning(PasswordPolicyOptions::default);
+        mock.expect_get_user_attributes_schema()
+            .returning(|| Ok(vec![]));
+        mock.expect_get_group_attributes_schema()
+            .returning(|| Ok(vec![]));
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=Example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            regular_user_visibility,
+        );
         let request = LdapBindRequest {
             dn: "uid=test,ou=people,dc=example,dc=coM".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
@@ -704,51 +1648,26 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_bind() {
+    async fn test_bind_and_search_with_base_dn_alias() {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_bind()
-            .with(eq(crate::domain::handler::BindRequest {
+            .with(eq(BindRequest {
                 name: UserId::new("bob"),
                 password: "pass".to_string(),
             }))
             .times(1)
             .return_once(|_| Ok(()));
-        mock.expect_get_user_groups()
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details()
             .with(eq(UserId::new("bob")))
-            .return_once(|_| Ok(HashSet::new()));
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=eXample,dc=com".to_string(), vec![], vec![]);
-
-        let request = LdapOp::BindRequest(LdapBindRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        });
-        assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![LdapOp::BindResponse(LdapBindResponse {
-                res: LdapResultOp {
-                    code: LdapResultCode::Success,
-                    matcheddn: "".to_string(),
-                    message: "".to_string(),
-                    referral: vec![],
-                },
-                saslcreds: None,
-            })]),
-        );
-    }
-
-    #[tokio::test]
-    async fn test_admin_bind() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_bind()
-            .with(eq(crate::domain::handler::BindRequest {
-                name: UserId::new("test"),
-                password: "pass".to_string(),
-            }))
-            .times(1)
-            .return_once(|_| Ok(()));
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
         mock.expect_get_user_groups()
-            .with(eq(UserId::new("test")))
+            .with(eq(UserId::new("bob")))
             .return_once(|_| {
                 let mut set = HashSet::new();
                 set.insert(GroupDetails {
@@ -756,81 +1675,109 @@ mod tests {
                     display_name: "lldap_admin".to_string(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    gid_number: None,
                 });
                 Ok(set)
             });
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
-
-        let request = LdapBindRequest {
-            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::Success
-        );
-    }
-
-    #[tokio::test]
-    async fn test_search_regular_user() {
-        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_password_policy()
+            .returning(PasswordPolicyOptions::default);
+        mock.expect_get_user_attributes_schema()
+            .returning(|| Ok(vec![]));
         mock.expect_list_users()
             .with(
-                eq(Some(UserRequestFilter::And(vec![
-                    UserRequestFilter::And(vec![]),
-                    UserRequestFilter::UserId(UserId::new("test")),
-                ]))),
+                eq(Some(UserRequestFilter::MemberOf("group_1".to_string()))),
                 eq(false),
             )
             .times(1)
             .return_once(|_, _| {
                 Ok(vec![UserAndGroups {
                     user: User {
-                        user_id: UserId::new("test"),
+                        user_id: UserId::new("bob"),
                         ..Default::default()
                     },
                     groups: None,
                 }])
             });
-        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
-
-        let request =
-            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec!["dc=Corp".to_string()],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=corp".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        let request = make_search_request(
+            "ou=People,dc=corp",
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "cn=group_1,ou=groups,dc=corp".to_string(),
+            ),
+            vec!["uid"],
+        );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![],
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"bob".to_vec()],
+                    }],
                 }),
                 make_search_success()
-            ]),
-        );
-    }
-
-    #[tokio::test]
-    async fn test_search_readonly_user() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
-            .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request =
-            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
-        assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_search_member_of() {
+    async fn test_active_directory_compatibility() {
         let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(2)
+            .returning(|_| Ok(()));
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details().returning(|_| {
+            Ok(User {
+                user_id: UserId::new("bob"),
+                ..Default::default()
+            })
+        });
+        mock.expect_get_user_groups().returning(|_| {
+            let mut set = HashSet::new();
+            set.insert(GroupDetails {
+                group_id: GroupId(1),
+                display_name: "lldap_admin".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                gid_number: None,
+            });
+            Ok(set)
+        });
+        mock.expect_get_password_policy()
+            .returning(PasswordPolicyOptions::default);
+        mock.expect_get_user_attributes_schema()
+            .returning(|| Ok(vec![]));
         mock.expect_list_users()
-            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(true))
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(true),
+            )
             .times(1)
             .return_once(|_, _| {
                 Ok(vec![UserAndGroups {
@@ -843,279 +1790,1784 @@ mod tests {
                         display_name: "rockstars".to_string(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
                         uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        gid_number: None,
                     }]),
                 }])
             });
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = make_user_search_request::<String>(
-            LdapFilter::And(vec![]),
-            vec!["memberOf".to_string()],
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        )
+        .with_active_directory(&ActiveDirectoryOptions {
+            enabled: true,
+            netbios_domain: Some("EXAMPLE".to_string()),
+            ..Default::default()
+        });
+        for (name, code) in [
+            ("OTHER\\bob", LdapResultCode::NamingViolation),
+            ("bob@other.com", LdapResultCode::NamingViolation),
+            ("example\\bob", LdapResultCode::Success),
+            ("bob@Example.com", LdapResultCode::Success),
+        ] {
+            let request = LdapBindRequest {
+                dn: name.to_string(),
+                cred: LdapBindCred::Simple("pass".to_string()),
+            };
+            assert_eq!(ldap_handler.do_bind(&request).await.0, code, "{}", name);
+        }
+        let request = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![
+                LdapFilter::Equality("objectCategory".to_string(), "person".to_string()),
+                LdapFilter::Equality("sAMAccountName".to_string(), "bob".to_string()),
+            ]),
+            vec!["sAMAccountName", "userPrincipalName", "memberOf"],
         );
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
+            results[0],
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                attributes: vec![
+                    LdapPartialAttribute {
+                        atype: "sAMAccountName".to_string(),
+                        vals: vec![b"bob".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "userPrincipalName".to_string(),
+                        vals: vec![b"bob@example.com".to_vec()],
+                    },
+                    LdapPartialAttribute {
                         atype: "memberOf".to_string(),
-                        vals: vec![b"uid=rockstars,ou=groups,dc=example,dc=com".to_vec()]
-                    }],
-                }),
-                make_search_success(),
-            ]),
+                        vals: vec![b"cn=rockstars,ou=groups,dc=example,dc=com".to_vec()],
+                    },
+                ],
+            })
         );
     }
 
     #[tokio::test]
-    async fn test_search_user_as_scope() {
+    async fn test_bind() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![
-                    UserRequestFilter::And(vec![]),
-                    UserRequestFilter::UserId(UserId::new("bob")),
-                ]))),
-                eq(false),
-            )
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
             .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+            .return_once(|_| Ok(()));
+        mock.expect_record_login()
+            .with(eq(UserId::new("bob")), eq(LoginKind::Ldap))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        mock.expect_get_user_capabilities()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(Capabilities::default()));
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=eXample,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        );
 
-        let request = LdapSearchRequest {
-            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
-        };
+        let request = LdapOp::BindRequest(LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        });
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![LdapOp::BindResponse(LdapBindResponse {
+                res: LdapResultOp {
+                    code: LdapResultCode::Success,
+                    matcheddn: "".to_string(),
+                    message: "".to_string(),
+                    referral: vec![],
+                },
+                saslcreds: None,
+            })]),
         );
     }
 
     #[tokio::test]
-    async fn test_bind_invalid_dn() {
-        let mock = MockTestBackendHandler::new();
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+    async fn test_bind_locked_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().times(1).return_once(|_| {
+            Err(DomainError::AccountLocked(
+                "Too many failed logins".to_string(),
+            ))
+        });
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        );
 
         let request = LdapBindRequest {
-            dn: "cn=bob,dc=example,dc=com".to_string(),
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
         };
         assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            ldap_handler.do_bind(&request).await,
+            (
+                LdapResultCode::ConstraintViolation,
+                "Exceed password retry limit. Please try later.".to_string()
+            )
         );
-        let request = LdapBindRequest {
-            dn: "uid=bob,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+    }
+
+    #[tokio::test]
+    async fn test_bind_disabled_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().times(1).return_once(|_| Ok(()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    enabled: false,
+                    ..Default::default()
+                })
+            });
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
         );
+
         let request = LdapBindRequest {
-            dn: "uid=bob,ou=groups,dc=example,dc=com".to_string(),
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
         };
         assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            ldap_handler.do_bind(&request).await,
+            (
+                LdapResultCode::UnwillingToPerform,
+                "Account is disabled or expired".to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_expired_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().times(1).return_once(|_| Ok(()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    valid_until: Some(chrono::Utc.timestamp_opt(42, 0).unwrap()),
+                    ..Default::default()
+                })
+            });
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
         );
+
         let request = LdapBindRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=fr".to_string(),
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
         };
         assert_eq!(
             ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            LdapResultCode::UnwillingToPerform
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_bind() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("test"),
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                let mut set = HashSet::new();
+                set.insert(GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "lldap_admin".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    gid_number: None,
+                });
+                Ok(set)
+            });
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
         );
+
         let request = LdapBindRequest {
-            dn: "uid=bob=test,ou=people,dc=example,dc=com".to_string(),
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
         };
         assert_eq!(
             ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            LdapResultCode::Success
         );
     }
 
-    #[test]
-    fn test_is_subtree() {
-        let subtree1 = &[
-            ("ou".to_string(), "people".to_string()),
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
-        let root = &[
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
-        assert!(is_subtree(subtree1, root));
-        assert!(!is_subtree(&[], root));
-    }
+    #[tokio::test]
+    async fn test_search_regular_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("test")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("test"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
 
-    #[test]
-    fn test_parse_distinguished_name() {
-        let parsed_dn = &[
-            ("ou".to_string(), "people".to_string()),
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
         assert_eq!(
-            parse_distinguished_name("ou=people,dc=example,dc=com").expect("parsing failed"),
-            parsed_dn
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success()
+            ]),
         );
+    }
+
+    #[tokio::test]
+    async fn test_search_regular_user_hidden_entries() {
+        // The backend is not queried at all.
+        let mut ldap_handler = setup_bound_handler_with_visibility(
+            MockTestBackendHandler::new(),
+            "regular",
+            RegularUserVisibility::Nothing,
+        )
+        .await;
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
         assert_eq!(
-            parse_distinguished_name(" ou  = people , dc = example , dc =  com ")
-                .expect("parsing failed"),
-            parsed_dn
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_users() {
-        use chrono::prelude::*;
+    async fn test_search_custom_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users().times(1).return_once(|_, _| {
-            Ok(vec![
-                UserAndGroups {
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
                     user: User {
-                        user_id: UserId::new("bob_1"),
-                        email: "bob@bobmail.bob".to_string(),
-                        display_name: Some("Bôb Böbberson".to_string()),
-                        first_name: Some("Bôb".to_string()),
-                        last_name: Some("Böbberson".to_string()),
-                        uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                        user_id: UserId::new("bob"),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "department".to_owned(),
+                                value: vec!["R&D".to_owned()],
+                            },
+                            AttributeValue {
+                                name: "employeenumber".to_owned(),
+                                value: vec!["42".to_owned()],
+                            },
+                        ],
                         ..Default::default()
                     },
                     groups: None,
-                },
-                UserAndGroups {
-                    user: User {
-                        user_id: UserId::new("jim"),
-                        email: "jim@cricket.jim".to_string(),
-                        display_name: Some("Jimminy Cricket".to_string()),
-                        first_name: Some("Jim".to_string()),
-                        last_name: Some("Cricket".to_string()),
-                        avatar: Some(JpegPhoto::for_tests()),
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                        creation_date: Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
-                    },
-                    groups: None,
-                },
-            ])
+                }])
+            });
+        mock.expect_bind().return_once(|_| Ok(()));
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details()
+            .return_once(|_| Ok(User::default()));
+        mock.expect_get_user_groups().return_once(|_| {
+            let mut set = HashSet::new();
+            set.insert(GroupDetails {
+                group_id: GroupId(1),
+                display_name: "lldap_admin".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                gid_number: None,
+            });
+            Ok(set)
         });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::And(vec![]),
-            vec![
-                "objectClass",
-                "dn",
-                "uid",
-                "mail",
-                "givenName",
-                "sn",
-                "cn",
-                "createTimestamp",
-                "entryUuid",
-                "jpegPhoto",
-            ],
+        mock.expect_get_password_policy()
+            .returning(PasswordPolicyOptions::default);
+        mock.expect_get_user_attributes_schema()
+            .times(1)
+            .return_once(|| {
+                Ok(vec![
+                    AttributeSchema {
+                        name: "department".to_owned(),
+                        attribute_type: AttributeType::String,
+                        is_list: false,
+                        is_visible: true,
+                    },
+                    AttributeSchema {
+                        name: "employeenumber".to_owned(),
+                        attribute_type: AttributeType::Integer,
+                        is_list: false,
+                        is_visible: false,
+                    },
+                ])
+            });
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        );
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        let request = make_user_search_request::<String>(
+            LdapFilter::And(vec![]),
+            vec!["department".to_string(), "employeeNumber".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "department".to_string(),
+                            vals: vec![b"R&D".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "employeeNumber".to_string(),
+                            vals: vec![b"42".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success()
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_readonly_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_member_of() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(true))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![GroupDetails {
+                        group_id: GroupId(42),
+                        display_name: "rockstars".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        gid_number: None,
+                    }]),
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request::<String>(
+            LdapFilter::And(vec![]),
+            vec!["memberOf".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberOf".to_string(),
+                        vals: vec![b"cn=rockstars,ou=groups,dc=example,dc=com".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_custom_membership_options() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().return_once(|_| Ok(()));
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details()
+            .return_once(|_| Ok(User::default()));
+        mock.expect_get_user_groups().return_once(|_| {
+            let mut set = HashSet::new();
+            set.insert(GroupDetails {
+                group_id: GroupId(1),
+                display_name: "lldap_admin".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                gid_number: None,
+            });
+            Ok(set)
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::MemberOf("rockstars".to_string()))),
+                eq(true),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![GroupDetails {
+                        group_id: GroupId(42),
+                        display_name: "rockstars".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        gid_number: None,
+                    }]),
+                }])
+            });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Member(UserId::new("bob")))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(42),
+                    display_name: "rockstars".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![UserId::new("bob")],
+                    attributes: vec![],
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                }])
+            });
+        mock.expect_get_password_policy()
+            .returning(PasswordPolicyOptions::default);
+        mock.expect_get_user_attributes_schema()
+            .returning(|| Ok(vec![]));
+        mock.expect_get_group_attributes_schema()
+            .returning(|| Ok(vec![]));
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions {
+                member_of_attribute: "isMemberOf".to_string(),
+                member_attributes: vec!["memberUid".to_string()],
+                group_dn_attribute: "uid".to_string(),
+                value_format: MembershipValueFormat::Id,
+                ..Default::default()
+            },
+            RegularUserVisibility::default(),
+        );
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+
+        let request = make_user_search_request(
+            LdapFilter::Equality("isMemberOf".to_string(), "rockstars".to_string()),
+            vec!["isMemberOf"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "isMemberOf".to_string(),
+                        vals: vec![b"rockstars".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Equality("memberUid".to_string(), "bob".to_string()),
+            vec!["memberUid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=rockstars,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberUid".to_string(),
+                        vals: vec![b"bob".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_named_by_uuid() {
+        let rockstars = uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8");
+        let rockstars_dn =
+            "entryUUID=a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8,ou=groups,dc=example,dc=com";
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().return_once(|_| Ok(()));
+        mock.expect_record_login().returning(|_, _| Ok(()));
+        mock.expect_get_user_details()
+            .return_once(|_| Ok(User::default()));
+        mock.expect_get_user_groups().return_once(|_| {
+            let mut set = HashSet::new();
+            set.insert(GroupDetails {
+                group_id: GroupId(1),
+                display_name: "lldap_admin".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                gid_number: None,
+            });
+            Ok(set)
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::MemberOfUuid(rockstars.clone()))),
+                eq(true),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![GroupDetails {
+                        group_id: GroupId(42),
+                        display_name: "rockstars".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        uuid: uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                        gid_number: None,
+                    }]),
+                }])
+            });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::Uuid(rockstars.clone()),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(42),
+                    display_name: "rockstars".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![UserId::new("bob")],
+                    attributes: vec![],
+                    uuid: uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                }])
+            });
+        mock.expect_get_password_policy()
+            .returning(PasswordPolicyOptions::default);
+        mock.expect_get_user_attributes_schema()
+            .returning(|| Ok(vec![]));
+        mock.expect_get_group_attributes_schema()
+            .returning(|| Ok(vec![]));
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions {
+                group_dn_attribute: "entryUUID".to_string(),
+                ..Default::default()
+            },
+            RegularUserVisibility::default(),
+        );
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+
+        let request = make_user_search_request(
+            LdapFilter::Equality("memberOf".to_string(), rockstars_dn.to_string()),
+            vec!["memberOf"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberOf".to_string(),
+                        vals: vec![rockstars_dn.as_bytes().to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+
+        // The group can be found by its DN, whatever its name.
+        let request = make_search_request(rockstars_dn, LdapFilter::And(vec![]), vec!["cn"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: rockstars_dn.to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"rockstars".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_as_scope() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_service_account() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind_service_account()
+            .withf(|service_id, password| service_id == "nextcloud" && password == "pass")
+            .times(1)
+            .return_once(|_, _| {
+                Ok(ServiceAccount {
+                    service_id: "nextcloud".to_owned(),
+                    description: None,
+                    read_all: true,
+                    creation_date: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+                })
+            });
+        mock.expect_bind_service_account()
+            .times(1)
+            .return_once(|_, _| Err(DomainError::AuthenticationError("Invalid".to_owned())));
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        );
+
+        let request = LdapBindRequest {
+            dn: "uid=NextCloud,ou=services,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        let user_info = ldap_handler.user_info.clone().unwrap();
+        assert_eq!(user_info.user, UserId::new("service:nextcloud"));
+        assert!(user_info.is_admin_or_readonly());
+        let request = LdapBindRequest {
+            dn: "cn=nextcloud,ou=services,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("wrong".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::InvalidCredentials,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_invalid_dn() {
+        let mock = MockTestBackendHandler::new();
+        let mut ldap_handler = LdapHandler::new(
+            mock,
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            MembershipOptions::default(),
+            RegularUserVisibility::default(),
+        );
+
+        let request = LdapBindRequest {
+            dn: "cn=bob,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=groups,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=fr".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+    }
+
+    #[test]
+    fn test_is_subtree() {
+        let subtree1 = &[
+            ("ou".to_string(), "people".to_string()),
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        let root = &[
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        assert!(is_subtree(subtree1, root));
+        assert!(!is_subtree(&[], root));
+    }
+
+    #[test]
+    fn test_parse_distinguished_name() {
+        let parsed_dn = &[
+            ("ou".to_string(), "people".to_string()),
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        assert_eq!(
+            parse_distinguished_name("ou=people,dc=example,dc=com").expect("parsing failed"),
+            parsed_dn
+        );
+        assert_eq!(
+            parse_distinguished_name(" ou  = people , dc = example , dc =  com ")
+                .expect("parsing failed"),
+            parsed_dn
+        );
+    }
+
+    #[test]
+    fn test_custom_dn_structure() {
+        let ldap_info = LdapInfo::new(
+            "dc=example,dc=com".to_owned(),
+            &[],
+            vec![],
+            vec![],
+            MembershipOptions {
+                user_dn_attribute: "CN".to_owned(),
+                user_ou: "Users".to_owned(),
+                group_ou: "roles".to_owned(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            ldap_info.user_dn("bob"),
+            "cn=bob,ou=users,dc=example,dc=com"
+        );
+        assert_eq!(
+            ldap_info.group_dn("admins", &uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8")),
+            "cn=admins,ou=roles,dc=example,dc=com"
+        );
+        assert_eq!(
+            ldap_info.get_user_id_from_dn("cn=bob,ou=users,dc=example,dc=com"),
+            Ok(UserId::new("bob"))
+        );
+        assert_eq!(
+            ldap_info.get_user_id_from_dn("uid=bob,ou=users,dc=example,dc=com"),
+            Ok(UserId::new("bob"))
+        );
+        ldap_info
+            .get_user_id_from_dn("uid=bob,ou=people,dc=example,dc=com")
+            .unwrap_err();
+        let scope = |dn: &str| get_search_scope(&ldap_info, &parse_distinguished_name(dn).unwrap());
+        assert!(matches!(
+            scope("ou=users,dc=example,dc=com"),
+            SearchScope::Users
+        ));
+        assert!(matches!(
+            scope("ou=roles,dc=example,dc=com"),
+            SearchScope::Groups
+        ));
+        assert!(matches!(
+            scope("ou=people,dc=example,dc=com"),
+            SearchScope::Unknown
+        ));
+        assert!(matches!(
+            scope("cn=bob,ou=users,dc=example,dc=com"),
+            SearchScope::User(LdapFilter::Equality(attribute, value))
+                if attribute == "uid" && value == "bob"
+        ));
+    }
+
+    #[test]
+    fn test_custom_group_dn_attribute() {
+        let ldap_info = LdapInfo::new(
+            "dc=example,dc=com".to_owned(),
+            &[],
+            vec![],
+            vec![],
+            MembershipOptions {
+                group_dn_attribute: "displayName".to_owned(),
+                ..Default::default()
+            },
+        );
+        let admins_dn = ldap_info.group_dn("admins", &uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"));
+        assert_eq!(admins_dn, "displayName=admins,ou=groups,dc=example,dc=com");
+        // The memberOf values served to the clients are understood in their filters.
+        assert_eq!(
+            ldap_info.parse_member_of_dn(&admins_dn),
+            Ok(UserRequestFilter::MemberOf("admins".to_owned()))
+        );
+        assert_eq!(
+            ldap_info.parse_group_dn(&admins_dn),
+            Ok(GroupRequestFilter::DisplayName("admins".to_owned()))
+        );
+        assert_eq!(
+            ldap_info.parse_member_of_dn("cn=admins,ou=groups,dc=example,dc=com"),
+            Ok(UserRequestFilter::MemberOf("admins".to_owned()))
+        );
+        ldap_info
+            .parse_member_of_dn("o=admins,ou=groups,dc=example,dc=com")
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_search_users() {
+        use chrono::prelude::*;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        email: "bob@bobmail.bob".to_string(),
+                        display_name: Some("Bôb Böbberson".to_string()),
+                        first_name: Some("Bôb".to_string()),
+                        last_name: Some("Böbberson".to_string()),
+                        uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("jim"),
+                        email: "jim@cricket.jim".to_string(),
+                        display_name: Some("Jimminy Cricket".to_string()),
+                        first_name: Some("Jim".to_string()),
+                        last_name: Some("Cricket".to_string()),
+                        avatar: Some(JpegPhoto::for_tests()),
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        creation_date: Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec![
+                "objectClass",
+                "dn",
+                "uid",
+                "mail",
+                "givenName",
+                "sn",
+                "cn",
+                "createTimestamp",
+                "entryUuid",
+                "jpegPhoto",
+            ],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "givenName".to_string(),
+                            vals: vec!["Bôb".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sn".to_string(),
+                            vals: vec!["Böbberson".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec!["Bôb Böbberson".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"1970-01-01T00:00:00+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"jim@cricket.jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "givenName".to_string(),
+                            vals: vec![b"Jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sn".to_string(),
+                            vals: vec![b"Cricket".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"Jimminy Cricket".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"2014-07-08T09:10:11+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "jpegPhoto".to_string(),
+                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_posix_users() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        uid_number: Some(1000),
+                        gid_number: Some(100),
+                        home_directory: Some("/home/bob".to_string()),
+                        login_shell: Some("/bin/bash".to_string()),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("jim"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality("objectClass".to_string(), "posixAccount".to_string()),
+            vec![
+                "objectClass",
+                "uid",
+                "uidNumber",
+                "gidNumber",
+                "homeDirectory",
+                "loginShell",
+            ],
+        );
+        let object_classes = || LdapPartialAttribute {
+            atype: "objectClass".to_string(),
+            vals: vec![
+                b"inetOrgPerson".to_vec(),
+                b"posixAccount".to_vec(),
+                b"mailAccount".to_vec(),
+                b"person".to_vec(),
+            ],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        object_classes(),
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uidNumber".to_string(),
+                            vals: vec![b"1000".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "gidNumber".to_string(),
+                            vals: vec![b"100".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "homeDirectory".to_string(),
+                            vals: vec![b"/home/bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "loginShell".to_string(),
+                            vals: vec![b"/bin/bash".to_vec()]
+                        },
+                    ],
+                }),
+                // The POSIX attributes that are not set are left out.
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        object_classes(),
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"jim".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_posix_groups() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups().times(1).return_once(|_| {
+            Ok(vec![
+                Group {
+                    id: GroupId(2),
+                    display_name: "developers".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![UserId::new("bob")],
+                    attributes: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    gid_number: Some(100),
+                    description: None,
+                    mail: None,
+                },
+                Group {
+                    id: GroupId(3),
+                    display_name: "guests".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    attributes: vec![],
+                    uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectClass", "cn", "gidNumber"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=developers,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(), b"posixGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"developers".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "gidNumber".to_string(),
+                            vals: vec![b"100".to_vec()]
+                        },
+                    ],
+                }),
+                // Only the groups with a GID are POSIX groups.
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=guests,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"guests".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![
+                    Group {
+                        id: GroupId(1),
+                        display_name: "group_1".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        users: vec![UserId::new("bob"), UserId::new("john")],
+                        attributes: vec![],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        gid_number: None,
+                        description: None,
+                        mail: None,
+                    },
+                    Group {
+                        id: GroupId(3),
+                        display_name: "BestGroup".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        users: vec![UserId::new("john")],
+                        attributes: vec![],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        gid_number: None,
+                        description: None,
+                        mail: None,
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=cOm",
+            LdapFilter::And(vec![]),
+            vec!["objectClass", "dn", "cn", "uniqueMember", "entryUuid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"group_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![
+                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"BestGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::DisplayName("group_1".to_string()),
+                GroupRequestFilter::Member(UserId::new("bob")),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::Not(Box::new(
+                    GroupRequestFilter::And(vec![]),
+                )))),
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![]))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "group_1".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    attributes: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![
+                LdapFilter::Equality("cN".to_string(), "Group_1".to_string()),
+                LdapFilter::Equality(
+                    "uniqueMember".to_string(),
+                    "uid=bob,ou=peopLe,Dc=eXample,dc=com".to_string(),
+                ),
+                LdapFilter::Equality("obJEctclass".to_string(), "groupofUniqueNames".to_string()),
+                LdapFilter::Equality("objectclass".to_string(), "groupOfNames".to_string()),
+                LdapFilter::Present("objectclass".to_string()),
+                LdapFilter::Present("dn".to_string()),
+                LdapFilter::Not(Box::new(LdapFilter::Present(
+                    "random_attribUte".to_string(),
+                ))),
+                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
+            ]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter_2() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
+                    "group_2".to_string(),
+                ))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "group_1".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    attributes: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
+                "displayname".to_string(),
+                "group_2".to_string(),
+            )))]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"group_1".to_vec()]
+                    },],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_group_as_scope() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::DisplayName("rockstars".to_string()),
+            ]))))
+            .times(1)
+            .return_once(|_| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=rockstars,ou=groups,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_error() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
+                    "group_2".to_string(),
+                ))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::InternalError(
+                    "Error getting groups".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
+                "displayname".to_string(),
+                "group_2".to_string(),
+            )))]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError{
+                code: LdapResultCode::Other,
+                message: r#"Error while listing groups "ou=groups,dc=example,dc=com": Internal error: `Error getting groups`"#.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter_error() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![LdapFilter::Substring(
+                "entryUuid".to_string(),
+                ldap3_proto::proto::LdapSubstringFilter::default(),
+            )]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: r#"Unsupported group attribute for substring filter: "entryuuid""#
+                    .to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
+                    vec![
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new(
+                            "bob",
+                        )))),
+                        UserRequestFilter::And(vec![]),
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                        UserRequestFilter::And(vec![]),
+                        UserRequestFilter::Present(UserColumn::UserId),
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                        UserRequestFilter::SubString(
+                            UserColumn::Email,
+                            SubStringFilter {
+                                initial: Some("bob".to_owned()),
+                                any: vec!["example".to_owned()],
+                                final_: None,
+                            },
+                        ),
+                    ],
+                )]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![LdapFilter::Or(vec![
+                LdapFilter::Not(Box::new(LdapFilter::Equality(
+                    "uid".to_string(),
+                    "bob".to_string(),
+                ))),
+                LdapFilter::Equality("objectclass".to_string(), "persOn".to_string()),
+                LdapFilter::Equality("objectclass".to_string(), "other".to_string()),
+                LdapFilter::Present("objectClass".to_string()),
+                LdapFilter::Present("uid".to_string()),
+                LdapFilter::Present("unknown".to_string()),
+                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
+                LdapFilter::Substring(
+                    "mail".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter {
+                        initial: Some("bob".to_string()),
+                        any: vec!["example".to_string()],
+                        final_: None,
+                    },
+                ),
+            ])]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_extensible_and_approx_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::Equality(UserColumn::FirstName, "bob".to_string()),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::MemberOf("group_1".to_string()),
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let extensible = |matching_rule: Option<&str>, type_: &str, value: &str, dn| {
+            LdapFilter::Extensible(ldap3_proto::proto::LdapMatchingRuleAssertion {
+                matching_rule: matching_rule.map(str::to_string),
+                type_: Some(type_.to_string()),
+                match_value: value.to_string(),
+                dn_attributes: dn,
+            })
+        };
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Approx("givenName".to_string(), "bob".to_string()),
+                extensible(Some("caseIgnoreMatch"), "uid", "bob", false),
+                extensible(
+                    Some("1.2.840.113556.1.4.1941"),
+                    "memberOf",
+                    "cn=group_1,ou=groups,dc=example,dc=com",
+                    false,
+                ),
+                extensible(None, "ou", "People", true),
+                extensible(None, "uid", "bob", true),
+                extensible(Some("1.2.840.113556.1.4.803"), "uid", "2", false),
+            ]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_entry_uuid_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Uuid,
+                    "698e1d5f-7a40-3151-8745-b9b8a37839da".to_string(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "entryUUID".to_string(),
+                "698E1D5F-7A40-3151-8745-B9B8A37839DA".to_string(),
+            ),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality("entryUUID".to_string(), "not-a-uuid".to_string()),
+            vec!["objectClass"],
+        );
+        assert!(matches!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InappropriateMatching,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search_users_by_mail_alias() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::Equality(UserColumn::Email, "alias@example.com".to_string()),
+                    UserRequestFilter::SecondaryEmail("alias@example.com".to_string()),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@example.com".to_string(),
+                        secondary_emails: vec![
+                            "alias@example.com".to_string(),
+                            "bob.alias@example.com".to_string(),
+                        ],
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Equality("mail".to_string(), "alias@example.com".to_string()),
+                LdapFilter::Equality("mailAlias".to_string(), "alias@example.com".to_string()),
+            ]),
+            vec!["mail", "otherMailbox"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
                     attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![
-                                b"inetOrgPerson".to_vec(),
-                                b"posixAccount".to_vec(),
-                                b"mailAccount".to_vec(),
-                                b"person".to_vec()
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uid".to_string(),
-                            vals: vec![b"bob_1".to_vec()]
-                        },
                         LdapPartialAttribute {
                             atype: "mail".to_string(),
-                            vals: vec![b"bob@bobmail.bob".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "givenName".to_string(),
-                            vals: vec!["Bôb".to_string().into_bytes()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "sn".to_string(),
-                            vals: vec!["Böbberson".to_string().into_bytes()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec!["Bôb Böbberson".to_string().into_bytes()]
+                            vals: vec![b"bob@example.com".to_vec()],
                         },
                         LdapPartialAttribute {
-                            atype: "createTimestamp".to_string(),
-                            vals: vec![b"1970-01-01T00:00:00+00:00".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
-                        },
-                    ],
-                }),
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
+                            atype: "otherMailbox".to_string(),
                             vals: vec![
-                                b"inetOrgPerson".to_vec(),
-                                b"posixAccount".to_vec(),
-                                b"mailAccount".to_vec(),
-                                b"person".to_vec()
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uid".to_string(),
-                            vals: vec![b"jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "mail".to_string(),
-                            vals: vec![b"jim@cricket.jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "givenName".to_string(),
-                            vals: vec![b"Jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "sn".to_string(),
-                            vals: vec![b"Cricket".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"Jimminy Cricket".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "createTimestamp".to_string(),
-                            vals: vec![b"2014-07-08T09:10:11+00:00".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "jpegPhoto".to_string(),
-                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                                b"alias@example.com".to_vec(),
+                                b"bob.alias@example.com".to_vec(),
+                            ],
                         },
                     ],
                 }),
@@ -1125,80 +3577,56 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_groups() {
+    async fn test_search_users_profile_fields() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Department,
+                    "Sales".to_string(),
+                ))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| {
-                Ok(vec![
-                    Group {
-                        id: GroupId(1),
-                        display_name: "group_1".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                        users: vec![UserId::new("bob"), UserId::new("john")],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                    },
-                    Group {
-                        id: GroupId(3),
-                        display_name: "BestGroup".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                        users: vec![UserId::new("john")],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        telephone_number: Some("+1 555 0100".to_string()),
+                        title: Some("Manager".to_string()),
+                        department: Some("Sales".to_string()),
+                        locality: Some("Springfield".to_string()),
+                        ..Default::default()
                     },
-                ])
+                    groups: None,
+                }])
             });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=cOm",
-            LdapFilter::And(vec![]),
-            vec!["objectClass", "dn", "cn", "uniqueMember", "entryUuid"],
+        let request = make_user_search_request(
+            LdapFilter::Equality("ou".to_string(), "Sales".to_string()),
+            vec!["telephoneNumber", "mobile", "title", "ou", "l"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"group_1".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![
-                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
-                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
-                        },
-                    ],
-                }),
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
                     attributes: vec![
                         LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                            atype: "telephoneNumber".to_string(),
+                            vals: vec![b"+1 555 0100".to_vec()],
                         },
                         LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"BestGroup".to_vec()]
+                            atype: "title".to_string(),
+                            vals: vec![b"Manager".to_vec()],
                         },
                         LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                            atype: "ou".to_string(),
+                            vals: vec![b"Sales".to_vec()],
                         },
                         LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                            atype: "l".to_string(),
+                            vals: vec![b"Springfield".to_vec()],
                         },
                     ],
                 }),
@@ -1208,57 +3636,42 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter() {
+    async fn test_search_users_shadow_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![
-                GroupRequestFilter::DisplayName("group_1".to_string()),
-                GroupRequestFilter::Member(UserId::new("bob")),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::Not(Box::new(
-                    GroupRequestFilter::And(vec![]),
-                )))),
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![]))),
-            ]))))
-            .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    display_name: "group_1".to_string(),
-                    id: GroupId(1),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    users: vec![],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                }])
-            });
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    password_modified_date: Some(
+                        Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap(),
+                    ),
+                    valid_until: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::And(vec![
-                LdapFilter::Equality("cN".to_string(), "Group_1".to_string()),
-                LdapFilter::Equality(
-                    "uniqueMember".to_string(),
-                    "uid=bob,ou=peopLe,Dc=eXample,dc=com".to_string(),
-                ),
-                LdapFilter::Equality("obJEctclass".to_string(), "groupofUniqueNames".to_string()),
-                LdapFilter::Equality("objectclass".to_string(), "groupOfNames".to_string()),
-                LdapFilter::Present("objectclass".to_string()),
-                LdapFilter::Present("dn".to_string()),
-                LdapFilter::Not(Box::new(LdapFilter::Present(
-                    "random_attribUte".to_string(),
-                ))),
-                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
-            ]),
-            vec!["1.1"],
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["shadowLastChange", "shadowMax", "shadowExpire"],
         );
+        // The passwords don't expire with the default policy.
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![],
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "shadowLastChange".to_string(),
+                            vals: vec![b"19359".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "shadowExpire".to_string(),
+                            vals: vec![b"19723".to_vec()],
+                        },
+                    ],
                 }),
                 make_search_success(),
             ])
@@ -1266,42 +3679,43 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter_2() {
+    async fn test_search_users_auth_timestamp() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::Or(vec![
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
-                    "group_2".to_string(),
-                ))),
-            ]))))
-            .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    display_name: "group_1".to_string(),
-                    id: GroupId(1),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    users: vec![],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                }])
-            });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
-                "displayname".to_string(),
-                "group_2".to_string(),
-            )))]),
-            vec!["cn"],
-        );
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        last_ldap_login: Some(Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap()),
+                        last_web_login: Some(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("john"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["authTimestamp"]);
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
                     attributes: vec![LdapPartialAttribute {
-                        atype: "cn".to_string(),
-                        vals: vec![b"group_1".to_vec()]
-                    },],
+                        atype: "authTimestamp".to_string(),
+                        vals: vec![b"2023-01-02T03:04:05+00:00".to_vec()],
+                    }],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
                 }),
                 make_search_success(),
             ])
@@ -1309,128 +3723,201 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_group_as_scope() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::DisplayName("rockstars".to_string()),
-            ]))))
-            .times(1)
-            .return_once(|_| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = LdapSearchRequest {
-            base: "uid=rockstars,ou=groups,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
-        };
-        assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
-        );
-    }
-
-    #[tokio::test]
-    async fn test_search_groups_error() {
+    async fn test_search_users_modified_since() {
+        let modified_date = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::Or(vec![
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
-                    "group_2".to_string(),
-                ))),
-            ]))))
-            .times(1)
-            .return_once(|_| {
-                Err(crate::domain::error::DomainError::InternalError(
-                    "Error getting groups".to_string(),
-                ))
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::ModifiedSince(modified_date))),
+                eq(false),
+            )
+            .times(2)
+            .returning(move |_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        modified_date,
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
             });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
-                "displayname".to_string(),
-                "group_2".to_string(),
-            )))]),
-            vec!["cn"],
+        let expected_result = Ok(vec![
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "modifyTimestamp".to_string(),
+                    vals: vec![b"2023-01-02T03:04:05+00:00".to_vec()],
+                }],
+            }),
+            make_search_success(),
+        ]);
+        for timestamp in ["20230102030405Z", "2023-01-02T03:04:05+00:00"] {
+            let request = make_user_search_request(
+                LdapFilter::GreaterOrEqual("modifyTimestamp".to_string(), timestamp.to_string()),
+                vec!["modifyTimestamp"],
+            );
+            assert_eq!(
+                ldap_handler.do_search_or_dse(&request).await,
+                expected_result
+            );
+        }
+        let request = make_user_search_request(
+            LdapFilter::GreaterOrEqual("modifyTimestamp".to_string(), "yesterday".to_string()),
+            vec!["modifyTimestamp"],
         );
-        assert_eq!(
+        assert!(matches!(
             ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::Other,
-                message: r#"Error while listing groups "ou=groups,dc=example,dc=com": Internal error: `Error getting groups`"#.to_string()
+            Err(LdapError {
+                code: LdapResultCode::InvalidAttributeSyntax,
+                ..
             })
+        ));
+    }
+
+    fn make_change(
+        change_id: i32,
+        entry_id: &str,
+        entry_uuid: Uuid,
+        change_type: ChangeType,
+    ) -> Change {
+        Change {
+            change_id,
+            entry_type: ChangeEntryType::User,
+            entry_id: entry_id.to_string(),
+            entry_uuid,
+            change_type,
+            change_date: Utc.timestamp_opt(0, 0).unwrap(),
+            diff: None,
+        }
+    }
+
+    #[test]
+    fn test_compact_changes() {
+        let bob = uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da");
+        let john = uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8");
+        let changes = vec![
+            make_change(1, "bob", bob.clone(), ChangeType::Add),
+            make_change(2, "john", john.clone(), ChangeType::Modify),
+            make_change(3, "bob", bob.clone(), ChangeType::Modify),
+            make_change(4, "john", john.clone(), ChangeType::Delete),
+        ];
+        assert_eq!(
+            compact_changes(changes),
+            vec![
+                (make_change(3, "bob", bob, ChangeType::Modify), true),
+                (make_change(4, "john", john, ChangeType::Delete), false),
+            ]
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter_error() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::And(vec![LdapFilter::Substring(
-                "whatever".to_string(),
-                ldap3_proto::proto::LdapSubstringFilter::default(),
-            )]),
-            vec!["cn"],
+    async fn test_sync_search_refresh_only() {
+        let bob_uuid = uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da");
+        let john_uuid = uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8");
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_last_change_id()
+            .times(2)
+            .returning(|| Ok(3));
+        let user_uuid = bob_uuid.clone();
+        mock.expect_list_users().times(2).returning(move |_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    display_name: Some("Bôb Böbberson".to_string()),
+                    uuid: user_uuid.clone(),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        let changes = vec![
+            make_change(2, "bob", bob_uuid, ChangeType::Modify),
+            make_change(3, "john", john_uuid, ChangeType::Delete),
+        ];
+        mock.expect_list_changes_since()
+            .with(eq(1))
+            .times(1)
+            .return_once(move |_| Ok(changes));
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["cn"]);
+        let bob_entry = LdapOp::SearchResultEntry(LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "cn".to_string(),
+                vals: vec!["Bôb Böbberson".to_string().into_bytes()],
+            }],
+        });
+        let bob_uuid = uuid::Uuid::parse_str("698e1d5f-7a40-3151-8745-b9b8a37839da").unwrap();
+        let john_uuid = uuid::Uuid::parse_str("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8").unwrap();
+        // Without a cookie, all the entries are sent.
+        assert_eq!(
+            ldap_handler
+                .do_sync_search(&request, SyncRequestMode::RefreshOnly, None)
+                .collect::<Vec<_>>()
+                .await,
+            vec![
+                (
+                    bob_entry.clone(),
+                    make_sync_state(SyncStateValue::Add, bob_uuid, None)
+                ),
+                (
+                    make_search_success(),
+                    vec![LdapControl::SyncDone {
+                        cookie: Some(b"3".to_vec()),
+                        refresh_deletes: false,
+                    }]
+                ),
+            ]
         );
+        // With a cookie, only the changes since are sent.
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported group filter: Substring("whatever", LdapSubstringFilter { initial: None, any: [], final_: None })"#
-                    .to_string()
-            })
+            ldap_handler
+                .do_sync_search(&request, SyncRequestMode::RefreshOnly, Some(b"1".to_vec()))
+                .collect::<Vec<_>>()
+                .await,
+            vec![
+                (
+                    bob_entry,
+                    make_sync_state(SyncStateValue::Modify, bob_uuid, None)
+                ),
+                (
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                        attributes: vec![],
+                    }),
+                    make_sync_state(SyncStateValue::Delete, john_uuid, None)
+                ),
+                (
+                    make_search_success(),
+                    vec![LdapControl::SyncDone {
+                        cookie: Some(b"3".to_vec()),
+                        refresh_deletes: true,
+                    }]
+                ),
+            ]
         );
     }
 
     #[tokio::test]
-    async fn test_search_filters() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
-                    vec![
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new(
-                            "bob",
-                        )))),
-                        UserRequestFilter::And(vec![]),
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
-                        UserRequestFilter::And(vec![]),
-                        UserRequestFilter::And(vec![]),
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
-                    ],
-                )]))),
-                eq(false),
-            )
-            .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+    async fn test_search_extensible_filter_without_attribute() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
         let request = make_user_search_request(
-            LdapFilter::And(vec![LdapFilter::Or(vec![
-                LdapFilter::Not(Box::new(LdapFilter::Equality(
-                    "uid".to_string(),
-                    "bob".to_string(),
-                ))),
-                LdapFilter::Equality("objectclass".to_string(), "persOn".to_string()),
-                LdapFilter::Equality("objectclass".to_string(), "other".to_string()),
-                LdapFilter::Present("objectClass".to_string()),
-                LdapFilter::Present("uid".to_string()),
-                LdapFilter::Present("unknown".to_string()),
-                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
-            ])]),
+            LdapFilter::Extensible(ldap3_proto::proto::LdapMatchingRuleAssertion {
+                matching_rule: Some("caseIgnoreMatch".to_string()),
+                type_: None,
+                match_value: "bob".to_string(),
+                dn_attributes: false,
+            }),
             vec!["objectClass"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
+            Ok(vec![make_search_error(
+                LdapResultCode::UnwillingToPerform,
+                "Extensible match filters without an attribute are not supported".to_string()
+            )])
         );
     }
 
@@ -1556,8 +4043,13 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".to_string(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
+                    attributes: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
                 }])
             });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
@@ -1630,8 +4122,13 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".to_string(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
+                    attributes: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
                 }])
             });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
@@ -1797,16 +4294,17 @@ mod tests {
         let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
         let request = make_user_search_request(
             LdapFilter::Substring(
-                "uid".to_string(),
+                "uidNumber".to_string(),
                 ldap3_proto::proto::LdapSubstringFilter::default(),
             ),
             vec!["objectClass"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
+            Err(LdapError {
                 code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported user filter: Substring("uid", LdapSubstringFilter { initial: None, any: [], final_: None })"#.to_string()
+                message: r#"Unsupported user attribute for substring filter: "uidnumber""#
+                    .to_string()
             })
         );
     }
@@ -1837,9 +4335,16 @@ mod tests {
                 registration_response: start_response.message,
             })
         });
+        mock.expect_check_password_policy()
+            .withf(|user_id, password| user_id.as_str() == "bob" && *password == "password")
+            .times(1)
+            .return_once(|_, _| Ok(()));
         mock.expect_registration_finish()
             .times(1)
             .return_once(|_| Ok(()));
+        mock.expect_add_password_to_history()
+            .times(1)
+            .return_once(|_, _| Ok(()));
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
         let request = LdapOp::ExtendedRequest(
             LdapPasswordModifyRequest {
@@ -1884,9 +4389,16 @@ mod tests {
                 registration_response: start_response.message,
             })
         });
+        mock.expect_check_password_policy()
+            .withf(|user_id, password| user_id.as_str() == "bob" && *password == "password")
+            .times(1)
+            .return_once(|_, _| Ok(()));
         mock.expect_registration_finish()
             .times(1)
             .return_once(|_| Ok(()));
+        mock.expect_add_password_to_history()
+            .times(1)
+            .return_once(|_, _| Ok(()));
         let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
         let request = LdapOp::ExtendedRequest(
             LdapPasswordModifyRequest {
@@ -1905,6 +4417,37 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_password_change_policy_violation() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_check_password_policy()
+            .times(1)
+            .return_once(|_, _| {
+                Err(DomainError::InvalidRequest(
+                    "The password must contain a digit".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::ConstraintViolation,
+                "Invalid request: `The password must contain a digit`".to_string(),
+            )])
+        );
+    }
+
     #[tokio::test]
     async fn test_password_change_errors() {
         let mut mock = MockTestBackendHandler::new();
@@ -1964,6 +4507,7 @@ mod tests {
             display_name: "lldap_admin".to_string(),
             creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
             uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            gid_number: None,
         });
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
@@ -2037,6 +4581,11 @@ mod tests {
     #[tokio::test]
     async fn test_create_user() {
         let mut mock = MockTestBackendHandler::new();
+        // The policy is checked on the user ID as written in the DN.
+        mock.expect_check_user_id()
+            .withf(|user_id| user_id == "Bob")
+            .times(1)
+            .return_once(|_| Ok(()));
         mock.expect_create_user()
             .with(eq(CreateUserRequest {
                 user_id: UserId::new("bob"),
@@ -2048,7 +4597,7 @@ mod tests {
             .return_once(|_| Ok(()));
         let ldap_handler = setup_bound_admin_handler(mock).await;
         let request = LdapAddRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            dn: "uid=Bob,ou=people,dc=example,dc=com".to_owned(),
             attributes: vec![LdapPartialAttribute {
                 atype: "cn".to_owned(),
                 vals: vec![b"Bob".to_vec()],
@@ -2060,6 +4609,26 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_create_user_read_only() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new())
+            .await
+            .with_read_only_mode(ReadOnlyMode::new(true));
+        let request = LdapAddRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![],
+        };
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(LdapOp::AddRequest(request))
+                .await,
+            Some(vec![make_add_error(
+                LdapResultCode::UnwillingToPerform,
+                READ_ONLY_ERROR.to_owned()
+            )])
+        );
+    }
+
     #[tokio::test]
     async fn test_create_user_wrong_ou() {
         let ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
@@ -2093,4 +4662,82 @@ mod tests {
             Ok(vec![make_search_success()])
         );
     }
+
+    fn expect_group_1(mock: &mut MockTestBackendHandler) {
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName(
+                "group_1".to_string(),
+            ))))
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(2),
+                    display_name: "group_1".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    attributes: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                }])
+            });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+    }
+
+    fn make_add_member_request() -> LdapModifyRequest {
+        LdapModifyRequest {
+            dn: "cn=group_1,ou=groups,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Add,
+                modification: LdapPartialAttribute {
+                    atype: "member".to_owned(),
+                    vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+                },
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members_as_manager() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_group_1(&mut mock);
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(2)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        ldap_handler
+            .user_info
+            .as_mut()
+            .unwrap()
+            .capabilities
+            .managed_memberships = vec![GroupId(2)];
+        assert_eq!(
+            ldap_handler
+                .do_modify_request(make_add_member_request())
+                .await,
+            Ok(vec![make_modify_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members_unauthorized() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_group_1(&mut mock);
+        let ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        assert_eq!(
+            ldap_handler
+                .do_modify_request(make_add_member_request())
+                .await
+                .unwrap_err()
+                .code,
+            LdapResultCode::InsufficentAccessRights
+        );
+    }
 }
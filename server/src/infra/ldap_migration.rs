@@ -0,0 +1,199 @@
+use crate::infra::{
+    import::{read_entries, ImportData},
+    ldif::LdifEntry,
+};
+use anyhow::{Context, Result};
+use ldap3::{
+    adapters::{Adapter, EntriesOnly, PagedResults},
+    LdapConnAsync, LdapConnSettings, Scope, SearchEntry,
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+// Entries per page, below the 1000 results limit of Active Directory.
+const PAGE_SIZE: i32 = 500;
+
+const USER_ATTRIBUTES: &[&str] = &[
+    "objectClass",
+    "uid",
+    "sAMAccountName",
+    "mail",
+    "displayName",
+    "cn",
+    "givenName",
+    "sn",
+    "uidNumber",
+    "gidNumber",
+    "homeDirectory",
+    "loginShell",
+    // Only readable with enough privileges, and never by Active Directory.
+    "userPassword",
+];
+const GROUP_ATTRIBUTES: &[&str] = &["objectClass", "cn", "member", "uniqueMember", "memberUid"];
+
+/// The remote directory to migrate from, OpenLDAP, Active Directory or any LDAP server.
+#[derive(Debug, Clone)]
+pub struct LdapMigrationOptions {
+    /// "ldap://" or "ldaps://" URL.
+    pub url: String,
+    pub bind_dn: String,
+    pub bind_password: String,
+    pub base_dn: String,
+    pub user_filter: String,
+    pub group_filter: String,
+    pub timeout_seconds: u64,
+}
+
+fn into_ldif_entry(entry: SearchEntry) -> LdifEntry {
+    let text_values = entry.attrs.into_iter().flat_map(|(name, values)| {
+        let name = name.to_ascii_lowercase();
+        values
+            .into_iter()
+            .map(move |v| (name.clone(), v.into_bytes()))
+    });
+    let binary_values = entry.bin_attrs.into_iter().flat_map(|(name, values)| {
+        let name = name.to_ascii_lowercase();
+        values.into_iter().map(move |v| (name.clone(), v))
+    });
+    LdifEntry {
+        line: 0,
+        dn: entry.dn,
+        attributes: text_values.chain(binary_values).collect(),
+    }
+}
+
+async fn search(
+    ldap: &mut ldap3::Ldap,
+    base_dn: &str,
+    filter: &str,
+    attributes: &[&str],
+) -> Result<Vec<LdifEntry>> {
+    let adapters: Vec<Box<dyn Adapter<_, _>>> = vec![
+        Box::new(EntriesOnly::new()),
+        Box::new(PagedResults::new(PAGE_SIZE)),
+    ];
+    let mut stream = ldap
+        .streaming_search_with(
+            adapters,
+            base_dn,
+            Scope::Subtree,
+            filter,
+            attributes.to_vec(),
+        )
+        .await?;
+    let mut entries = Vec::new();
+    while let Some(entry) = stream.next().await? {
+        entries.push(into_ldif_entry(SearchEntry::construct(entry)));
+    }
+    stream.finish().await.success()?;
+    Ok(entries)
+}
+
+/// Reads the users and groups of the remote directory, mapped like in an LDIF import.
+pub async fn fetch_directory(options: &LdapMigrationOptions) -> Result<ImportData> {
+    let settings =
+        LdapConnSettings::new().set_conn_timeout(Duration::from_secs(options.timeout_seconds));
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &options.url)
+        .await
+        .with_context(|| format!("while connecting to {}", options.url))?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.drive().await {
+            warn!("Remote LDAP connection error: {}", e);
+        }
+    });
+    ldap.simple_bind(&options.bind_dn, &options.bind_password)
+        .await
+        .and_then(|r| r.success())
+        .with_context(|| format!("while binding as {}", options.bind_dn))?;
+    let users = search(
+        &mut ldap,
+        &options.base_dn,
+        &options.user_filter,
+        USER_ATTRIBUTES,
+    )
+    .await
+    .context("while searching the users")?;
+    let groups = search(
+        &mut ldap,
+        &options.base_dn,
+        &options.group_filter,
+        GROUP_ATTRIBUTES,
+    )
+    .await
+    .context("while searching the groups")?;
+    let _ = ldap.unbind().await;
+    info!(
+        "Found {} user and {} group entries in {}",
+        users.len(),
+        groups.len(),
+        options.url
+    );
+    Ok(read_entries(
+        &users.into_iter().chain(groups).collect::<Vec<_>>(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::UserId;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_active_directory_entries() {
+        let entry = |dn: &str, attrs: &[(&str, &[&str])]| {
+            into_ldif_entry(SearchEntry {
+                dn: dn.to_owned(),
+                attrs: attrs
+                    .iter()
+                    .map(|(name, values)| {
+                        (
+                            name.to_string(),
+                            values.iter().map(|v| v.to_string()).collect(),
+                        )
+                    })
+                    .collect(),
+                bin_attrs: HashMap::new(),
+            })
+        };
+        let entries = vec![
+            entry(
+                "CN=John Doe,OU=Staff,DC=corp,DC=example,DC=com",
+                &[
+                    ("objectClass", &["top", "person", "user"][..]),
+                    ("sAMAccountName", &["jdoe"][..]),
+                    ("mail", &["jdoe@example.com"][..]),
+                    ("displayName", &["John Doe"][..]),
+                ],
+            ),
+            entry(
+                "CN=WORKSTATION,OU=Computers,DC=corp,DC=example,DC=com",
+                &[
+                    ("objectClass", &["top", "person", "user", "computer"][..]),
+                    ("sAMAccountName", &["WORKSTATION$"][..]),
+                ],
+            ),
+            entry(
+                "CN=Staff,OU=Groups,DC=corp,DC=example,DC=com",
+                &[
+                    ("objectClass", &["top", "group"][..]),
+                    ("cn", &["Staff"][..]),
+                    (
+                        "member",
+                        &["CN=John Doe,OU=Staff,DC=corp,DC=example,DC=com"][..],
+                    ),
+                ],
+            ),
+        ];
+        let data = read_entries(&entries);
+        assert!(data.errors.is_empty());
+        assert_eq!(data.users.len(), 1);
+        assert_eq!(data.users[0].request.user_id, UserId::new("jdoe"));
+        assert_eq!(
+            data.users[0].request.display_name.as_deref(),
+            Some("John Doe")
+        );
+        assert_eq!(data.groups.len(), 1);
+        assert_eq!(data.groups[0].members, vec![UserId::new("jdoe")]);
+    }
+}
@@ -0,0 +1,242 @@
+//! Forwarding of the binds and the writes of a replica to the LDAP server of the primary, which
+//! owns the database: the replica only serves the searches from its replicated copy.
+
+use crate::{
+    domain::error::{DomainError, Result},
+    infra::configuration::ReplicaOptions,
+};
+use ldap3::{exop::Exop, Ldap, LdapConnAsync, LdapConnSettings, LdapError, Mod};
+use ldap3_proto::proto::{
+    LdapAddRequest, LdapExtendedRequest, LdapExtendedResponse, LdapModifyRequest, LdapModifyType,
+    LdapResult as LdapResultOp, LdapResultCode,
+};
+use secstr::SecUtf8;
+use std::{collections::HashSet, time::Duration};
+use tracing::{debug, instrument, warn};
+
+/// LDAP result code of a bind to a locked account, see `LdapHandler::do_bind`.
+const CONSTRAINT_VIOLATION: u32 = 19;
+
+fn result_code(rc: u32) -> LdapResultCode {
+    LdapResultCode::try_from(i64::from(rc)).unwrap_or(LdapResultCode::Other)
+}
+
+fn to_ldap_result(result: std::result::Result<ldap3::LdapResult, LdapError>) -> LdapResultOp {
+    match result {
+        Ok(result) | Err(LdapError::LdapResult { result }) => LdapResultOp {
+            code: result_code(result.rc),
+            matcheddn: result.matched,
+            message: result.text,
+            referral: vec![],
+        },
+        Err(e) => {
+            warn!("Could not forward the request to the primary: {}", e);
+            LdapResultOp {
+                code: LdapResultCode::Unavailable,
+                matcheddn: "".to_string(),
+                message: format!("The primary server is unavailable: {}", e),
+                referral: vec![],
+            }
+        }
+    }
+}
+
+fn to_mods(request: LdapModifyRequest) -> Vec<Mod<Vec<u8>>> {
+    request
+        .changes
+        .into_iter()
+        .map(|change| {
+            let attribute = change.modification.atype.into_bytes();
+            let values = change.modification.vals.into_iter().collect::<HashSet<_>>();
+            match change.operation {
+                LdapModifyType::Add => Mod::Add(attribute, values),
+                LdapModifyType::Delete => Mod::Delete(attribute, values),
+                LdapModifyType::Replace => Mod::Replace(attribute, values),
+            }
+        })
+        .collect()
+}
+
+/// The primary, as seen from one LDAP session of the replica.
+pub struct PrimaryLdap {
+    options: ReplicaOptions,
+    /// The DN and password of the last successful bind of the session, to send its writes as
+    /// the same user.
+    credentials: Option<(String, SecUtf8)>,
+}
+
+impl PrimaryLdap {
+    pub fn new(options: ReplicaOptions) -> Self {
+        Self {
+            options,
+            credentials: None,
+        }
+    }
+
+    async fn connect(&self) -> std::result::Result<Ldap, LdapError> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(self.options.timeout_seconds));
+        let (conn, ldap) =
+            LdapConnAsync::with_settings(settings, &self.options.primary_ldap_url).await?;
+        tokio::spawn(async move {
+            if let Err(e) = conn.drive().await {
+                warn!("Primary LDAP connection error: {}", e);
+            }
+        });
+        Ok(ldap)
+    }
+
+    /// A connection to the primary, bound as the client of the session if it is bound.
+    async fn connect_as_client(&self) -> std::result::Result<Ldap, LdapResultOp> {
+        let mut ldap = self.connect().await.map_err(|e| to_ldap_result(Err(e)))?;
+        if let Some((dn, password)) = &self.credentials {
+            ldap.simple_bind(dn, password.unsecure())
+                .await
+                .and_then(|r| r.success())
+                .map_err(|e| to_ldap_result(Err(e)))?;
+        }
+        Ok(ldap)
+    }
+
+    /// Checks the password on the primary, which keeps track of the failed attempts. On success,
+    /// the credentials are kept for the writes of the session.
+    #[instrument(skip_all, level = "debug", err)]
+    pub async fn bind(&mut self, dn: &str, password: &str) -> Result<()> {
+        self.credentials = None;
+        // An empty password would be an unauthenticated bind, which succeeds.
+        if password.is_empty() {
+            return Err(DomainError::AuthenticationError(
+                "Empty password".to_owned(),
+            ));
+        }
+        let to_domain_error = |e: LdapError| {
+            DomainError::InternalError(format!("Could not reach the primary: {}", e))
+        };
+        let mut ldap = self.connect().await.map_err(to_domain_error)?;
+        let result = ldap
+            .simple_bind(dn, password)
+            .await
+            .map_err(to_domain_error)?;
+        let _ = ldap.unbind().await;
+        match result.rc {
+            0 => {
+                debug!("Bound on the primary");
+                self.set_credentials(Some((dn, password)));
+                Ok(())
+            }
+            CONSTRAINT_VIOLATION => Err(DomainError::AccountLocked(result.text)),
+            _ => Err(DomainError::AuthenticationError(result.text)),
+        }
+    }
+
+    /// Sets the credentials of a bind checked locally, e.g. of a service account.
+    pub fn set_credentials(&mut self, credentials: Option<(&str, &str)>) {
+        self.credentials =
+            credentials.map(|(dn, password)| (dn.to_owned(), SecUtf8::from(password)));
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    pub async fn add(&self, request: LdapAddRequest) -> LdapResultOp {
+        debug!(?request.dn);
+        let mut ldap = match self.connect_as_client().await {
+            Ok(ldap) => ldap,
+            Err(result) => return result,
+        };
+        let attributes = request
+            .attributes
+            .into_iter()
+            .map(|a| (a.atype.into_bytes(), a.vals.into_iter().collect()))
+            .collect::<Vec<(Vec<u8>, HashSet<Vec<u8>>)>>();
+        let result = ldap.add(&request.dn, attributes).await;
+        let _ = ldap.unbind().await;
+        to_ldap_result(result)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    pub async fn modify(&self, request: LdapModifyRequest) -> LdapResultOp {
+        debug!(?request.dn);
+        let mut ldap = match self.connect_as_client().await {
+            Ok(ldap) => ldap,
+            Err(result) => return result,
+        };
+        let dn = request.dn.clone();
+        let result = ldap.modify(&dn, to_mods(request)).await;
+        let _ = ldap.unbind().await;
+        to_ldap_result(result)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    pub async fn extended(&self, request: LdapExtendedRequest) -> LdapExtendedResponse {
+        debug!(?request.name);
+        let error_response = |res| LdapExtendedResponse {
+            res,
+            name: None,
+            value: None,
+        };
+        let mut ldap = match self.connect_as_client().await {
+            Ok(ldap) => ldap,
+            Err(result) => return error_response(result),
+        };
+        let result = ldap
+            .extended(Exop {
+                name: Some(request.name),
+                val: request.value,
+            })
+            .await;
+        let _ = ldap.unbind().await;
+        match result {
+            Ok(ldap3::exop::ExopResult(exop, result)) => LdapExtendedResponse {
+                res: to_ldap_result(Ok(result)),
+                name: exop.name,
+                value: exop.val,
+            },
+            Err(e) => error_response(to_ldap_result(Err(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::{LdapModify, LdapPartialAttribute};
+
+    #[test]
+    fn test_result_code() {
+        assert_eq!(result_code(0), LdapResultCode::Success);
+        assert_eq!(result_code(49), LdapResultCode::InvalidCredentials);
+        assert_eq!(result_code(12345), LdapResultCode::Other);
+    }
+
+    #[test]
+    fn test_to_mods() {
+        let request = LdapModifyRequest {
+            dn: "cn=group,ou=groups,dc=example,dc=com".to_owned(),
+            changes: vec![
+                LdapModify {
+                    operation: LdapModifyType::Add,
+                    modification: LdapPartialAttribute {
+                        atype: "member".to_owned(),
+                        vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+                    },
+                },
+                LdapModify {
+                    operation: LdapModifyType::Delete,
+                    modification: LdapPartialAttribute {
+                        atype: "member".to_owned(),
+                        vals: vec![],
+                    },
+                },
+            ],
+        };
+        assert_eq!(
+            to_mods(request),
+            vec![
+                Mod::Add(
+                    b"member".to_vec(),
+                    HashSet::from([b"uid=bob,ou=people,dc=example,dc=com".to_vec()])
+                ),
+                Mod::Delete(b"member".to_vec(), HashSet::new()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use crate::infra::configuration::LdapRateLimitOptions;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+const BIND_ATTEMPTS_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct State {
+    total_connections: usize,
+    connections: HashMap<IpAddr, usize>,
+    bind_attempts: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+/// Limits the number of concurrent connections, globally and per client IP, and the rate of bind
+/// attempts per client IP, shared between all the LDAP connections.
+#[derive(Clone)]
+pub struct LdapRateLimiter {
+    options: Arc<RwLock<LdapRateLimitOptions>>,
+    state: Arc<Mutex<State>>,
+}
+
+/// Holds a connection slot, released when dropped.
+pub struct ConnectionGuard {
+    ip: IpAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.total_connections -= 1;
+        if let Some(count) = state.connections.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                state.connections.remove(&self.ip);
+            }
+        }
+    }
+}
+
+impl LdapRateLimiter {
+    pub fn new(options: LdapRateLimitOptions) -> Self {
+        Self {
+            options: Arc::new(RwLock::new(options)),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    fn options(&self) -> LdapRateLimitOptions {
+        self.options.read().unwrap().clone()
+    }
+
+    /// Replaces the limits, keeping the current connections and counts.
+    pub fn set_options(&self, options: LdapRateLimitOptions) {
+        *self.options.write().unwrap() = options;
+    }
+
+    /// Registers a new connection from the IP, or returns `None` if the server or the IP already
+    /// have the maximum number of concurrent connections.
+    pub fn try_connect(&self, ip: IpAddr) -> Option<ConnectionGuard> {
+        let options = self.options();
+        let mut state = self.state.lock().unwrap();
+        if options.max_connections != 0 && state.total_connections >= options.max_connections {
+            return None;
+        }
+        let count = state.connections.entry(ip).or_default();
+        if options.max_connections_per_ip != 0 && *count >= options.max_connections_per_ip {
+            return None;
+        }
+        *count += 1;
+        state.total_connections += 1;
+        Some(ConnectionGuard {
+            ip,
+            state: self.state.clone(),
+        })
+    }
+
+    /// Records a bind attempt from the IP, and returns whether it is allowed.
+    pub fn try_bind(&self, ip: IpAddr) -> bool {
+        self.try_bind_at(ip, Instant::now())
+    }
+
+    fn try_bind_at(&self, ip: IpAddr, now: Instant) -> bool {
+        let max_attempts = self.options().max_bind_attempts_per_minute;
+        if max_attempts == 0 {
+            return true;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.bind_attempts.retain(|_, attempts| {
+            while matches!(attempts.front(), Some(t) if now.duration_since(*t) >= BIND_ATTEMPTS_WINDOW)
+            {
+                attempts.pop_front();
+            }
+            !attempts.is_empty()
+        });
+        let attempts = state.bind_attempts.entry(ip).or_default();
+        if attempts.len() >= max_attempts {
+            return false;
+        }
+        attempts.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_rate_limiter(
+        max_connections_per_ip: usize,
+        max_bind_attempts_per_minute: usize,
+    ) -> LdapRateLimiter {
+        LdapRateLimiter::new(LdapRateLimitOptions {
+            max_connections: 0,
+            max_connections_per_ip,
+            max_bind_attempts_per_minute,
+        })
+    }
+
+    #[test]
+    fn test_connections_per_ip() {
+        let rate_limiter = make_rate_limiter(2, 0);
+        let ip: IpAddr = "192.168.1.2".parse().unwrap();
+        let other_ip: IpAddr = "192.168.1.3".parse().unwrap();
+        let first = rate_limiter.try_connect(ip).unwrap();
+        let _second = rate_limiter.try_connect(ip).unwrap();
+        assert!(rate_limiter.try_connect(ip).is_none());
+        assert!(rate_limiter.try_connect(other_ip).is_some());
+        drop(first);
+        assert!(rate_limiter.try_connect(ip).is_some());
+    }
+
+    #[test]
+    fn test_set_options() {
+        let rate_limiter = make_rate_limiter(1, 0);
+        let ip: IpAddr = "192.168.1.2".parse().unwrap();
+        let _first = rate_limiter.try_connect(ip).unwrap();
+        assert!(rate_limiter.try_connect(ip).is_none());
+        rate_limiter.set_options(LdapRateLimitOptions {
+            max_connections: 0,
+            max_connections_per_ip: 2,
+            max_bind_attempts_per_minute: 0,
+        });
+        // The existing connection still counts.
+        let _second = rate_limiter.try_connect(ip).unwrap();
+        assert!(rate_limiter.try_connect(ip).is_none());
+    }
+
+    #[test]
+    fn test_total_connections() {
+        let rate_limiter = LdapRateLimiter::new(LdapRateLimitOptions {
+            max_connections: 2,
+            max_connections_per_ip: 0,
+            max_bind_attempts_per_minute: 0,
+        });
+        let first = rate_limiter
+            .try_connect("192.168.1.2".parse().unwrap())
+            .unwrap();
+        let _second = rate_limiter
+            .try_connect("192.168.1.3".parse().unwrap())
+            .unwrap();
+        assert!(rate_limiter
+            .try_connect("192.168.1.4".parse().unwrap())
+            .is_none());
+        drop(first);
+        assert!(rate_limiter
+            .try_connect("192.168.1.4".parse().unwrap())
+            .is_some());
+    }
+
+    #[test]
+    fn test_bind_attempts_per_ip() {
+        let rate_limiter = make_rate_limiter(0, 2);
+        let ip: IpAddr = "192.168.1.2".parse().unwrap();
+        let other_ip: IpAddr = "192.168.1.3".parse().unwrap();
+        let start = Instant::now();
+        assert!(rate_limiter.try_bind_at(ip, start));
+        assert!(rate_limiter.try_bind_at(ip, start + Duration::from_secs(10)));
+        assert!(!rate_limiter.try_bind_at(ip, start + Duration::from_secs(20)));
+        assert!(rate_limiter.try_bind_at(other_ip, start + Duration::from_secs(20)));
+        assert!(rate_limiter.try_bind_at(ip, start + Duration::from_secs(61)));
+        assert!(!rate_limiter.try_bind_at(ip, start + Duration::from_secs(62)));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let rate_limiter = make_rate_limiter(0, 0);
+        let ip: IpAddr = "192.168.1.2".parse().unwrap();
+        let _guards: Vec<_> = (0..100)
+            .map(|_| rate_limiter.try_connect(ip).unwrap())
+            .collect();
+        assert!((0..100).all(|_| rate_limiter.try_bind(ip)));
+    }
+}
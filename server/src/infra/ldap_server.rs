@@ -3,64 +3,294 @@ use crate::{
         handler::{BackendHandler, LoginHandler},
         opaque_handler::OpaqueHandler,
     },
-    infra::{configuration::Configuration, ldap_handler::LdapHandler},
+    infra::{
+        configuration::{
+            ActiveDirectoryOptions, Configuration, MembershipOptions, RegularUserVisibility,
+            ReplicaOptions,
+        },
+        correlation_id::CorrelationId,
+        ldap_client_profiles::ClientProfiles,
+        ldap_handler::LdapHandler,
+        ldap_rate_limiter::LdapRateLimiter,
+        ldap_wire_log::{ConnectionWireLog, Direction, LdapWireLog},
+        listeners,
+        metrics::Metrics,
+        proxy_protocol::read_proxy_header,
+        read_only::ReadOnlyMode,
+        systemd::ActivatedSockets,
+        tls_certificate::ReloadableCertificate,
+    },
 };
 use actix_rt::net::TcpStream;
 use actix_server::ServerBuilder;
 use actix_service::{fn_service, ServiceFactoryExt};
 use anyhow::{anyhow, Context, Result};
-use ldap3_proto::{proto::LdapMsg, LdapCodec};
-use rustls::PrivateKey;
+use futures_util::stream;
+use ldap3_proto::{
+    proto::{
+        LdapBindResponse, LdapControl, LdapExtendedResponse, LdapMsg, LdapOp,
+        LdapResult as LdapResultOp, SyncRequestMode,
+    },
+    LdapCodec, LdapResultCode,
+};
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio_rustls::TlsAcceptor as RustlsTlsAcceptor;
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{debug, error, info, instrument};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, instrument, warn};
 
-#[instrument(skip_all, level = "info", name = "LDAP request")]
+/// Returns the mode and cookie of the content synchronization control, if present.
+fn get_sync_request(controls: &[LdapControl]) -> Option<(SyncRequestMode, Option<Vec<u8>>)> {
+    controls.iter().find_map(|control| match control {
+        LdapControl::SyncRequest { mode, cookie, .. } => Some((mode.clone(), cookie.clone())),
+        _ => None,
+    })
+}
+
+/// The limits on the duration of the operations, `None` for no limit.
+#[derive(Clone, Copy, Debug)]
+struct OperationTimeouts {
+    search: Option<Duration>,
+    bind: Option<Duration>,
+}
+
+async fn with_timeout<F: Future>(
+    timeout: Option<Duration>,
+    future: F,
+) -> Result<F::Output, tokio::time::error::Elapsed> {
+    match timeout {
+        None => Ok(future.await),
+        Some(timeout) => tokio::time::timeout(timeout, future).await,
+    }
+}
+
+fn make_bind_error(code: LdapResultCode, message: &str) -> LdapOp {
+    LdapOp::BindResponse(LdapBindResponse {
+        res: LdapResultOp {
+            code,
+            matcheddn: "".to_string(),
+            message: message.to_string(),
+            referral: vec![],
+        },
+        saslcreds: None,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    skip_all,
+    level = "info",
+    name = "LDAP request",
+    fields(correlation_id = tracing::field::Empty)
+)]
 async fn handle_ldap_message<Backend, Writer>(
     msg: Result<LdapMsg, std::io::Error>,
     resp: &mut Writer,
     session: &mut LdapHandler<Backend>,
+    rate_limiter: &LdapRateLimiter,
+    metrics: &Metrics,
+    client_ip: IpAddr,
+    timeouts: OperationTimeouts,
+    wire_log: &ConnectionWireLog,
+    correlation_id: &CorrelationId,
 ) -> Result<bool>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler,
     Writer: futures_util::Sink<LdapMsg> + Unpin,
     <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
 {
-    use futures_util::SinkExt;
+    use futures_util::{SinkExt, StreamExt};
     let msg = msg.context("while receiving LDAP op")?;
+    tracing::Span::current().record(
+        "correlation_id",
+        &correlation_id.for_operation(msg.msgid).as_str(),
+    );
     debug!(?msg);
-    match session.handle_ldap_message(msg.op).await {
-        None => return Ok(false),
-        Some(result) => {
-            if result.is_empty() {
-                debug!("No response");
-            }
-            for response in result.into_iter() {
-                debug!(?response);
-                resp.send(LdapMsg {
-                    msgid: msg.msgid,
-                    op: response,
-                    ctrl: vec![],
-                })
-                .await
-                .context("while sending a response: {:#}")?
+    wire_log.log(Direction::Request, &msg).await;
+    let search_request;
+    // Time left to produce the search results. The persistent searches of the content
+    // synchronization are not limited.
+    let mut search_time_left = None;
+    let mut responses = match msg.op {
+        LdapOp::BindRequest(_) if !rate_limiter.try_bind(client_ip) => {
+            warn!("Too many bind attempts, rejecting the bind");
+            stream::iter(vec![(
+                make_bind_error(
+                    LdapResultCode::UnwillingToPerform,
+                    "Too many bind attempts, try again later",
+                ),
+                vec![],
+            )])
+            .boxed_local()
+        }
+        op @ LdapOp::BindRequest(_) => {
+            let result = match with_timeout(timeouts.bind, session.handle_ldap_message(op)).await {
+                Ok(result) => result.unwrap_or_default(),
+                Err(_) => {
+                    warn!(
+                        "The bind took longer than {:?}, interrupting it",
+                        timeouts.bind
+                    );
+                    vec![make_bind_error(
+                        LdapResultCode::Unavailable,
+                        "The bind timed out",
+                    )]
+                }
+            };
+            stream::iter(result.into_iter().map(|op| (op, vec![]))).boxed_local()
+        }
+        // Search results are sent as they are produced, to avoid holding them all in memory.
+        LdapOp::SearchRequest(request) => {
+            search_request = request;
+            match get_sync_request(&msg.ctrl) {
+                Some((mode, cookie)) => session.do_sync_search(&search_request, mode, cookie),
+                None => {
+                    search_time_left = timeouts.search;
+                    session
+                        .do_search_or_dse_streaming(&search_request)
+                        .map(|op| (op, vec![]))
+                        .boxed_local()
+                }
             }
-
-            resp.flush()
-                .await
-                .context("while flushing responses: {:#}")?
         }
+        op => match session.handle_ldap_message(op).await {
+            None => return Ok(false),
+            Some(result) => stream::iter(result.into_iter().map(|op| (op, vec![]))).boxed_local(),
+        },
+    };
+    let mut has_responses = false;
+    loop {
+        let started = Instant::now();
+        let (op, ctrl) = match with_timeout(search_time_left, responses.next()).await {
+            Ok(Some(response)) => response,
+            Ok(None) => break,
+            Err(_) => {
+                warn!(
+                    "The search took longer than {:?}, interrupting it",
+                    timeouts.search
+                );
+                search_time_left = None;
+                responses = stream::iter(vec![(
+                    LdapOp::SearchResultDone(LdapResultOp {
+                        code: LdapResultCode::TimeLimitExceeded,
+                        matcheddn: "".to_string(),
+                        message: "The search timed out".to_string(),
+                        referral: vec![],
+                    }),
+                    vec![],
+                )])
+                .boxed_local();
+                continue;
+            }
+        };
+        // Only the time spent producing the results counts, not the time spent sending them.
+        search_time_left = search_time_left.map(|t| t.saturating_sub(started.elapsed()));
+        has_responses = true;
+        debug!(?op, ?ctrl);
+        metrics.record_ldap_response(&op);
+        let response = LdapMsg {
+            msgid: msg.msgid,
+            op,
+            ctrl,
+        };
+        wire_log.log(Direction::Response, &response).await;
+        resp.send(response)
+            .await
+            .context("while sending a response: {:#}")?
+    }
+    if !has_responses {
+        debug!("No response");
     }
+    resp.flush()
+        .await
+        .context("while flushing responses: {:#}")?;
     Ok(true)
 }
 
-#[instrument(skip_all, level = "info", name = "LDAP session")]
+/// Sends an unsolicited notification telling the client that the server is closing the
+/// connection (RFC 4511, section 4.4.1).
+async fn send_notice_of_disconnection<Writer>(resp: &mut Writer, message: &str) -> Result<()>
+where
+    Writer: futures_util::Sink<LdapMsg> + Unpin,
+    <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
+{
+    use futures_util::SinkExt;
+    resp.send(LdapMsg {
+        msgid: 0,
+        op: LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code: LdapResultCode::Unavailable,
+                matcheddn: "".to_string(),
+                message: message.to_string(),
+                referral: vec![],
+            },
+            name: Some("1.3.6.1.4.1.1466.20036".to_string()),
+            value: None,
+        }),
+        ctrl: vec![],
+    })
+    .await
+    .context("while sending the notice of disconnection")?;
+    resp.flush()
+        .await
+        .context("while flushing the notice of disconnection")
+}
+
+/// Returns the address of the client, read from the PROXY protocol header if it is enabled.
+async fn get_client_address(stream: &mut TcpStream, proxy_protocol: bool) -> Result<SocketAddr> {
+    let peer_address = stream
+        .peer_addr()
+        .context("while getting the peer address")?;
+    if !proxy_protocol {
+        return Ok(peer_address);
+    }
+    Ok(read_proxy_header(stream)
+        .await
+        .with_context(|| {
+            format!(
+                "while reading the PROXY protocol header from {}",
+                peer_address
+            )
+        })?
+        .unwrap_or(peer_address))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    skip_all,
+    level = "info",
+    name = "LDAP session",
+    fields(
+        client = %client_address,
+        connection = tracing::field::Empty,
+        correlation_id = tracing::field::Empty
+    )
+)]
 async fn handle_ldap_stream<Stream, Backend>(
     stream: Stream,
+    client_address: SocketAddr,
+    rate_limiter: LdapRateLimiter,
+    metrics: Metrics,
+    idle_timeout: Option<Duration>,
+    timeouts: OperationTimeouts,
+    shutdown: CancellationToken,
     backend_handler: Backend,
     ldap_base_dn: String,
+    ldap_base_dn_aliases: Vec<String>,
     ignored_user_attributes: Vec<String>,
     ignored_group_attributes: Vec<String>,
+    membership_options: MembershipOptions,
+    active_directory_options: ActiveDirectoryOptions,
+    regular_user_visibility: RegularUserVisibility,
+    replica_options: Option<ReplicaOptions>,
+    read_only: ReadOnlyMode,
+    client_profiles: ClientProfiles,
+    wire_log: LdapWireLog,
 ) -> Result<Stream>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
@@ -71,18 +301,69 @@ where
     // Configure the codec etc.
     let mut requests = FramedRead::new(r, LdapCodec);
     let mut resp = FramedWrite::new(w, LdapCodec);
+    let wire_log = wire_log.for_connection();
+    tracing::Span::current().record("connection", &wire_log.connection_id);
+    // The operations get their own ID, derived from this one and their message ID.
+    let correlation_id = CorrelationId::new();
+    tracing::Span::current().record("correlation_id", &correlation_id.as_str());
 
     let mut session = LdapHandler::new(
         backend_handler,
         ldap_base_dn,
+        ldap_base_dn_aliases,
         ignored_user_attributes,
         ignored_group_attributes,
-    );
+        membership_options,
+        regular_user_visibility,
+    )
+    .with_active_directory(&active_directory_options)
+    .with_read_only_mode(read_only)
+    .with_client_profiles(client_profiles, client_address.ip());
+    if let Some(options) = replica_options {
+        session = session.with_primary(options);
+    }
 
-    while let Some(msg) = requests.next().await {
-        if !handle_ldap_message(msg, &mut resp, &mut session)
-            .await
-            .context("while handling incoming messages")?
+    loop {
+        let next_message = async {
+            match idle_timeout {
+                None => Ok(requests.next().await),
+                Some(idle_timeout) => tokio::time::timeout(idle_timeout, requests.next()).await,
+            }
+        };
+        // The operations in progress are not interrupted by the shutdown, it only closes the
+        // connection between two requests.
+        let msg = tokio::select! {
+            _ = shutdown.cancelled() => {
+                info!("Closing the connection, the server is shutting down");
+                send_notice_of_disconnection(&mut resp, "The server is shutting down").await?;
+                break;
+            }
+            msg = next_message => match msg {
+                Ok(msg) => msg,
+                Err(_) => {
+                    info!("Closing idle connection");
+                    send_notice_of_disconnection(&mut resp, "Idle connection timed out").await?;
+                    break;
+                }
+            },
+        };
+        let msg = match msg {
+            Some(msg) => msg,
+            None => break,
+        };
+        if !handle_ldap_message(
+            msg,
+            &mut resp,
+            &mut session,
+            &rate_limiter,
+            &metrics,
+            client_address.ip(),
+            timeouts,
+            &wire_log,
+            &correlation_id,
+        )
+        .await
+        .context("while handling incoming messages")?
         {
             break;
         }
@@ -90,58 +371,25 @@ where
     Ok(requests.into_inner().unsplit(resp.into_inner()))
 }
 
-fn read_private_key(key_file: &str) -> Result<PrivateKey> {
-    use rustls_pemfile::{pkcs8_private_keys, rsa_private_keys};
-    use std::{fs::File, io::BufReader};
-    pkcs8_private_keys(&mut BufReader::new(File::open(key_file)?))
-        .map_err(anyhow::Error::from)
-        .and_then(|keys| {
-            keys.into_iter()
-                .next()
-                .ok_or_else(|| anyhow!("No PKCS8 key"))
-        })
-        .or_else(|_| {
-            rsa_private_keys(&mut BufReader::new(File::open(key_file)?))
-                .map_err(anyhow::Error::from)
-                .and_then(|keys| {
-                    keys.into_iter()
-                        .next()
-                        .ok_or_else(|| anyhow!("No PKCS1 key"))
-                })
-        })
-        .with_context(|| {
-            format!(
-                "Cannot read either PKCS1 or PKCS8 private key from {}",
-                key_file
-            )
-        })
-        .map(rustls::PrivateKey)
-}
-
-fn get_tls_acceptor(config: &Configuration) -> Result<RustlsTlsAcceptor> {
-    use rustls::{Certificate, ServerConfig};
-    use rustls_pemfile::certs;
-    use std::{fs::File, io::BufReader};
-    // Load TLS key and cert files
-    let certs = certs(&mut BufReader::new(File::open(
-        &config.ldaps_options.cert_file,
-    )?))?
-    .into_iter()
-    .map(Certificate)
-    .collect::<Vec<_>>();
-    let private_key = read_private_key(&config.ldaps_options.key_file)?;
-    let server_config = std::sync::Arc::new(
-        ServerConfig::builder()
+fn get_tls_acceptor(certificate: Arc<ReloadableCertificate>) -> RustlsTlsAcceptor {
+    let server_config = Arc::new(
+        rustls::ServerConfig::builder()
             .with_safe_defaults()
             .with_no_client_auth()
-            .with_single_cert(certs, private_key)?,
+            .with_cert_resolver(certificate),
     );
-    Ok(server_config.into())
+    server_config.into()
 }
 
 pub fn build_ldap_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    rate_limiter: LdapRateLimiter,
+    metrics: Metrics,
+    read_only: ReadOnlyMode,
+    ldaps_certificate: Option<Arc<ReloadableCertificate>>,
+    shutdown: CancellationToken,
+    sockets: &mut ActivatedSockets,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
@@ -150,24 +398,85 @@ where
     let context = (
         backend_handler,
         config.ldap_base_dn.clone(),
+        config.ldap_base_dn_aliases.clone(),
         config.ignored_user_attributes.clone(),
         config.ignored_group_attributes.clone(),
+        config.membership_options.clone(),
+        config.active_directory_options.clone(),
+        config.regular_user_visibility,
+        config
+            .replica_options
+            .enabled
+            .then(|| config.replica_options.clone()),
+        read_only,
+        ClientProfiles::new(&config.client_profiles)?,
+        LdapWireLog::new(&config.ldap_wire_log)?,
     );
 
     let context_for_tls = context.clone();
+    let proxy_protocol = config.ldap_proxy_protocol;
+    let rate_limiter_for_tls = rate_limiter.clone();
+    let metrics_for_tls = metrics.clone();
+    let shutdown_for_tls = shutdown.clone();
+    let idle_timeout = match config.ldap_idle_timeout_seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
+    let timeouts = OperationTimeouts {
+        search: config.timeout_options.ldap_search_timeout(),
+        bind: config.timeout_options.ldap_bind_timeout(),
+    };
 
     let binder = move || {
         let context = context.clone();
-        fn_service(move |stream: TcpStream| {
+        let rate_limiter = rate_limiter.clone();
+        let metrics = metrics.clone();
+        let shutdown = shutdown.clone();
+        fn_service(move |mut stream: TcpStream| {
             let context = context.clone();
+            let rate_limiter = rate_limiter.clone();
+            let metrics = metrics.clone();
+            let shutdown = shutdown.clone();
             async move {
-                let (handler, base_dn, ignored_user_attributes, ignored_group_attributes) = context;
+                let client_address = get_client_address(&mut stream, proxy_protocol).await?;
+                let _connection = rate_limiter
+                    .try_connect(client_address.ip())
+                    .ok_or_else(|| anyhow!("Too many connections from {}", client_address.ip()))?;
+                let _active_connection = metrics.track_ldap_connection();
+                let (
+                    handler,
+                    base_dn,
+                    base_dn_aliases,
+                    ignored_user_attributes,
+                    ignored_group_attributes,
+                    membership_options,
+                    active_directory_options,
+                    regular_user_visibility,
+                    replica_options,
+                    read_only,
+                    client_profiles,
+                    wire_log,
+                ) = context;
                 handle_ldap_stream(
                     stream,
+                    client_address,
+                    rate_limiter,
+                    metrics,
+                    idle_timeout,
+                    timeouts,
+                    shutdown,
                     handler,
                     base_dn,
+                    base_dn_aliases,
                     ignored_user_attributes,
                     ignored_group_attributes,
+                    membership_options,
+                    active_directory_options,
+                    regular_user_visibility,
+                    replica_options,
+                    read_only,
+                    client_profiles,
+                    wire_log,
                 )
                 .await
             }
@@ -176,30 +485,76 @@ where
     };
 
     info!("Starting the LDAP server on port {}", config.ldap_port);
-    let server_builder = server_builder
-        .bind("ldap", (config.ldap_host.clone(), config.ldap_port), binder)
-        .with_context(|| format!("while binding to the port {}", config.ldap_port));
-    if config.ldaps_options.enabled {
-        let tls_context = (
-            context_for_tls,
-            get_tls_acceptor(config).context("while setting up the SSL certificate")?,
-        );
+    let backlog = config.runtime_options.backlog;
+    let server_builder = listeners::listen(
+        server_builder,
+        sockets,
+        "ldap",
+        &config.ldap_host,
+        config.ldap_port,
+        backlog,
+        binder,
+    )
+    .with_context(|| format!("while binding to the port {}", config.ldap_port));
+    if let Some(certificate) = ldaps_certificate {
+        let tls_context = (context_for_tls, get_tls_acceptor(certificate));
         let tls_binder = move || {
             let tls_context = tls_context.clone();
-            fn_service(move |stream: TcpStream| {
+            let rate_limiter = rate_limiter_for_tls.clone();
+            let metrics = metrics_for_tls.clone();
+            let shutdown = shutdown_for_tls.clone();
+            fn_service(move |mut stream: TcpStream| {
                 let tls_context = tls_context.clone();
+                let rate_limiter = rate_limiter.clone();
+                let metrics = metrics.clone();
+                let shutdown = shutdown.clone();
                 async move {
+                    let client_address = get_client_address(&mut stream, proxy_protocol).await?;
+                    let _connection =
+                        rate_limiter
+                            .try_connect(client_address.ip())
+                            .ok_or_else(|| {
+                                anyhow!("Too many connections from {}", client_address.ip())
+                            })?;
+                    let _active_connection = metrics.track_ldap_connection();
                     let (
-                        (handler, base_dn, ignored_user_attributes, ignored_group_attributes),
+                        (
+                            handler,
+                            base_dn,
+                            base_dn_aliases,
+                            ignored_user_attributes,
+                            ignored_group_attributes,
+                            membership_options,
+                            active_directory_options,
+                            regular_user_visibility,
+                            replica_options,
+                            read_only,
+                            client_profiles,
+                            wire_log,
+                        ),
                         tls_acceptor,
                     ) = tls_context;
                     let tls_stream = tls_acceptor.accept(stream).await?;
                     handle_ldap_stream(
                         tls_stream,
+                        client_address,
+                        rate_limiter,
+                        metrics,
+                        idle_timeout,
+                        timeouts,
+                        shutdown,
                         handler,
                         base_dn,
+                        base_dn_aliases,
                         ignored_user_attributes,
                         ignored_group_attributes,
+                        membership_options,
+                        active_directory_options,
+                        regular_user_visibility,
+                        replica_options,
+                        read_only,
+                        client_profiles,
+                        wire_log,
                     )
                     .await
                 }
@@ -212,9 +567,17 @@ where
             config.ldaps_options.port
         );
         server_builder.and_then(|s| {
-            s.bind(
+            listeners::listen(
+                s,
+                sockets,
                 "ldaps",
-                (config.ldap_host.clone(), config.ldaps_options.port),
+                config
+                    .ldaps_options
+                    .host
+                    .as_ref()
+                    .unwrap_or(&config.ldap_host),
+                config.ldaps_options.port,
+                backlog,
                 tls_binder,
             )
             .with_context(|| format!("while binding to the port {}", config.ldaps_options.port))
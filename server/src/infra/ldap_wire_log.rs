@@ -0,0 +1,365 @@
+//! Opt-in logging of the decoded LDAP operations, to debug the clients that don't find what they
+//! expect. The passwords are redacted. The operations can also be written to a capture file in the
+//! pcap format, with one LDAP message per packet (link type `DLT_USER0`, to decode as LDAP in
+//! Wireshark).
+
+use crate::infra::configuration::LdapWireLogOptions;
+use anyhow::{Context, Result};
+use futures_util::SinkExt;
+use ldap3_proto::{
+    proto::{LdapBindCred, LdapMsg, LdapOp, LdapPartialAttribute},
+    LdapCodec,
+};
+use std::{
+    collections::HashSet,
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+use tokio_util::codec::FramedWrite;
+use tracing::{info, warn};
+
+const PASSWORD_MODIFY_OID: &str = "1.3.6.1.4.1.4203.1.11.1";
+const PASSWORD_ATTRIBUTES: &[&str] = &["userpassword", "unicodepwd"];
+const REDACTED: &[u8] = b"<redacted>";
+
+/// The pcap global header: little-endian magic number, version 2.4, UTC, maximum snapshot length
+/// and link type 147 (`DLT_USER0`).
+const PCAP_HEADER: [u8; 24] = [
+    0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 147, 0, 0, 0,
+];
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Response => "response",
+        }
+    }
+}
+
+fn redact_attributes(attributes: &mut [LdapPartialAttribute]) {
+    for attribute in attributes {
+        if PASSWORD_ATTRIBUTES.contains(&attribute.atype.to_ascii_lowercase().as_str()) {
+            attribute.vals = vec![REDACTED.to_vec()];
+        }
+    }
+}
+
+/// A copy of the message without the passwords. The response to a password modify request can
+/// carry a generated password: the caller tells which extended response answers one.
+pub fn sanitize(msg: &LdapMsg, is_password_modify_response: bool) -> LdapMsg {
+    let mut msg = msg.clone();
+    match &mut msg.op {
+        LdapOp::BindRequest(request) => {
+            let LdapBindCred::Simple(password) = &mut request.cred;
+            if !password.is_empty() {
+                *password = String::from_utf8_lossy(REDACTED).into_owned();
+            }
+        }
+        LdapOp::AddRequest(request) => redact_attributes(&mut request.attributes),
+        LdapOp::ModifyRequest(request) => {
+            for change in &mut request.changes {
+                redact_attributes(std::slice::from_mut(&mut change.modification));
+            }
+        }
+        LdapOp::ExtendedRequest(request) if request.name == PASSWORD_MODIFY_OID => {
+            request.value = request.value.as_ref().map(|_| REDACTED.to_vec());
+        }
+        LdapOp::ExtendedResponse(response)
+            if is_password_modify_response
+                || response.name.as_deref() == Some(PASSWORD_MODIFY_OID) =>
+        {
+            response.value = response.value.as_ref().map(|_| REDACTED.to_vec());
+        }
+        _ => {}
+    }
+    msg
+}
+
+async fn encode(msg: LdapMsg) -> Result<Vec<u8>> {
+    let mut framed = FramedWrite::new(Vec::new(), LdapCodec);
+    framed.send(msg).await?;
+    Ok(framed.into_inner())
+}
+
+fn pcap_record(data: &[u8]) -> Vec<u8> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let length = data.len() as u32;
+    let mut record = Vec::with_capacity(16 + data.len());
+    record.extend_from_slice(&(timestamp.as_secs() as u32).to_le_bytes());
+    record.extend_from_slice(&timestamp.subsec_micros().to_le_bytes());
+    record.extend_from_slice(&length.to_le_bytes());
+    record.extend_from_slice(&length.to_le_bytes());
+    record.extend_from_slice(data);
+    record
+}
+
+/// Shared by the LDAP connections.
+#[derive(Clone, Default)]
+pub struct LdapWireLog {
+    log_operations: bool,
+    capture: Option<Arc<Mutex<tokio::fs::File>>>,
+}
+
+impl LdapWireLog {
+    pub fn new(options: &LdapWireLogOptions) -> Result<Self> {
+        let capture = match &options.capture_file {
+            None => None,
+            Some(file_name) => {
+                let mut file = std::fs::File::create(file_name)
+                    .with_context(|| format!("while creating the capture file {}", file_name))?;
+                file.write_all(&PCAP_HEADER)
+                    .with_context(|| format!("while writing to {}", file_name))?;
+                Some(Arc::new(Mutex::new(tokio::fs::File::from_std(file))))
+            }
+        };
+        Ok(Self {
+            log_operations: options.enabled,
+            capture,
+        })
+    }
+
+    /// The log of a new connection, with its own ID.
+    pub fn for_connection(&self) -> ConnectionWireLog {
+        ConnectionWireLog {
+            log: self.clone(),
+            connection_id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            password_modify_ids: Default::default(),
+        }
+    }
+}
+
+pub struct ConnectionWireLog {
+    log: LdapWireLog,
+    pub connection_id: u64,
+    // The IDs of the password modify requests that haven't been answered yet. The responses don't
+    // always name the operation they answer.
+    password_modify_ids: std::sync::Mutex<HashSet<i32>>,
+}
+
+impl ConnectionWireLog {
+    fn is_password_modify_response(&self, direction: Direction, msg: &LdapMsg) -> bool {
+        let mut password_modify_ids = self.password_modify_ids.lock().unwrap();
+        match (direction, &msg.op) {
+            (Direction::Request, LdapOp::ExtendedRequest(request))
+                if request.name == PASSWORD_MODIFY_OID =>
+            {
+                password_modify_ids.insert(msg.msgid);
+                false
+            }
+            (Direction::Response, LdapOp::ExtendedResponse(_)) => {
+                password_modify_ids.remove(&msg.msgid)
+            }
+            _ => false,
+        }
+    }
+
+    pub async fn log(&self, direction: Direction, msg: &LdapMsg) {
+        if !self.log.log_operations && self.log.capture.is_none() {
+            return;
+        }
+        let is_password_modify_response = self.is_password_modify_response(direction, msg);
+        let msg = sanitize(msg, is_password_modify_response);
+        if self.log.log_operations {
+            info!(
+                connection = self.connection_id,
+                direction = direction.as_str(),
+                msgid = msg.msgid,
+                "{:?}",
+                msg.op
+            );
+        }
+        if let Some(capture) = &self.log.capture {
+            let result = async {
+                let record = pcap_record(&encode(msg).await?);
+                let mut capture = capture.lock().await;
+                capture.write_all(&record).await?;
+                capture.flush().await?;
+                Ok::<_, anyhow::Error>(())
+            }
+            .await;
+            if let Err(e) = result {
+                warn!("Could not write to the LDAP capture file: {:#}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::{
+        LdapBindRequest, LdapExtendedRequest, LdapExtendedResponse, LdapModify, LdapModifyRequest,
+        LdapModifyType, LdapResult, LdapResultCode,
+    };
+
+    #[test]
+    fn test_sanitize() {
+        let bind = LdapMsg {
+            msgid: 1,
+            op: LdapOp::BindRequest(LdapBindRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                cred: LdapBindCred::Simple("secret".to_owned()),
+            }),
+            ctrl: vec![],
+        };
+        assert_eq!(
+            sanitize(&bind, false).op,
+            LdapOp::BindRequest(LdapBindRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                cred: LdapBindCred::Simple("<redacted>".to_owned()),
+            })
+        );
+        let modify = LdapMsg {
+            msgid: 2,
+            op: LdapOp::ModifyRequest(LdapModifyRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                changes: vec![
+                    LdapModify {
+                        operation: LdapModifyType::Replace,
+                        modification: LdapPartialAttribute {
+                            atype: "userPassword".to_owned(),
+                            vals: vec![b"secret".to_vec()],
+                        },
+                    },
+                    LdapModify {
+                        operation: LdapModifyType::Replace,
+                        modification: LdapPartialAttribute {
+                            atype: "mail".to_owned(),
+                            vals: vec![b"bob@example.com".to_vec()],
+                        },
+                    },
+                ],
+            }),
+            ctrl: vec![],
+        };
+        match sanitize(&modify, false).op {
+            LdapOp::ModifyRequest(request) => {
+                assert_eq!(
+                    request.changes[0].modification.vals,
+                    vec![REDACTED.to_vec()]
+                );
+                assert_eq!(
+                    request.changes[1].modification.vals,
+                    vec![b"bob@example.com".to_vec()]
+                );
+            }
+            op => panic!("Unexpected operation: {:?}", op),
+        }
+        let password_modify = LdapMsg {
+            msgid: 3,
+            op: LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: PASSWORD_MODIFY_OID.to_owned(),
+                value: Some(b"secret".to_vec()),
+            }),
+            ctrl: vec![],
+        };
+        assert_eq!(
+            sanitize(&password_modify, false).op,
+            LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: PASSWORD_MODIFY_OID.to_owned(),
+                value: Some(REDACTED.to_vec()),
+            })
+        );
+    }
+
+    fn extended_response(msgid: i32, value: &[u8]) -> LdapMsg {
+        LdapMsg {
+            msgid,
+            op: LdapOp::ExtendedResponse(LdapExtendedResponse {
+                res: LdapResult {
+                    code: LdapResultCode::Success,
+                    matcheddn: "".to_owned(),
+                    message: "".to_owned(),
+                    referral: vec![],
+                },
+                name: None,
+                value: Some(value.to_vec()),
+            }),
+            ctrl: vec![],
+        }
+    }
+
+    fn extended_response_value(msg: LdapMsg) -> Option<Vec<u8>> {
+        match msg.op {
+            LdapOp::ExtendedResponse(response) => response.value,
+            op => panic!("Unexpected operation: {:?}", op),
+        }
+    }
+
+    #[test]
+    fn test_password_modify_response() {
+        let log = LdapWireLog::default().for_connection();
+        let password_modify = LdapMsg {
+            msgid: 3,
+            op: LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: PASSWORD_MODIFY_OID.to_owned(),
+                value: None,
+            }),
+            ctrl: vec![],
+        };
+        let who_am_i = LdapMsg {
+            msgid: 4,
+            op: LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: "1.3.6.1.4.1.4203.1.11.3".to_owned(),
+                value: None,
+            }),
+            ctrl: vec![],
+        };
+        assert!(!log.is_password_modify_response(Direction::Request, &password_modify));
+        assert!(!log.is_password_modify_response(Direction::Request, &who_am_i));
+        // The generated password is redacted, but not the other extended responses.
+        let generated_password = extended_response(3, b"generated");
+        assert!(log.is_password_modify_response(Direction::Response, &generated_password));
+        assert_eq!(
+            extended_response_value(sanitize(&generated_password, true)),
+            Some(REDACTED.to_vec())
+        );
+        let authz_id = extended_response(4, b"dn:uid=bob,ou=people,dc=example,dc=com");
+        assert!(!log.is_password_modify_response(Direction::Response, &authz_id));
+        assert_eq!(
+            extended_response_value(sanitize(&authz_id, false)),
+            Some(b"dn:uid=bob,ou=people,dc=example,dc=com".to_vec())
+        );
+        // Each request has a single response.
+        assert!(!log.is_password_modify_response(Direction::Response, &generated_password));
+    }
+
+    #[tokio::test]
+    async fn test_capture() {
+        let file_name =
+            std::env::temp_dir().join(format!("lldap_capture_test_{}.pcap", std::process::id()));
+        let options = LdapWireLogOptions {
+            enabled: true,
+            capture_file: Some(file_name.to_str().unwrap().to_owned()),
+        };
+        let log = LdapWireLog::new(&options).unwrap().for_connection();
+        let msg = LdapMsg {
+            msgid: 1,
+            op: LdapOp::UnbindRequest,
+            ctrl: vec![],
+        };
+        log.log(Direction::Request, &msg).await;
+        let content = std::fs::read(&file_name).unwrap();
+        let encoded = encode(msg).await.unwrap();
+        assert_eq!(content[..24], PCAP_HEADER);
+        assert_eq!(content.len(), 24 + 16 + encoded.len());
+        assert_eq!(content[40..], encoded[..]);
+        std::fs::remove_file(&file_name).unwrap();
+    }
+}
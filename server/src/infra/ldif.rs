@@ -0,0 +1,376 @@
+use crate::domain::{
+    handler::BackendHandler,
+    ldap::{group::get_groups_list, user::get_user_list, utils::LdapInfo},
+};
+use anyhow::{Context, Result};
+use futures_util::TryStreamExt;
+use ldap3_proto::{proto::LdapOp, LdapFilter, LdapPartialAttribute, LdapSearchResultEntry};
+use std::io::Write;
+
+// Maximum length of the lines, longer ones are folded.
+const MAX_LINE_LENGTH: usize = 76;
+
+/// The attributes maintained by the server, which can't be set when adding entries with
+/// `ldapadd`.
+const OPERATIONAL_ATTRIBUTES: &[&str] = &["createtimestamp", "modifytimestamp", "entryuuid"];
+
+// Whether the value can be written as is, rather than base64-encoded (SAFE-STRING in RFC 2849).
+fn is_safe_string(value: &[u8]) -> bool {
+    let safe_char = |c: &u8| c.is_ascii() && !matches!(c, b'\0' | b'\n' | b'\r');
+    match value {
+        [] => true,
+        [first, ..] if matches!(first, b' ' | b':' | b'<') => false,
+        [.., b' '] => false,
+        _ => value.iter().all(safe_char),
+    }
+}
+
+fn write_folded(out: &mut impl Write, line: &str) -> std::io::Result<()> {
+    // The line is ASCII, it can be split anywhere.
+    let (first, mut rest) = line.split_at(line.len().min(MAX_LINE_LENGTH));
+    writeln!(out, "{}", first)?;
+    while !rest.is_empty() {
+        let (next, remaining) = rest.split_at(rest.len().min(MAX_LINE_LENGTH - 1));
+        writeln!(out, " {}", next)?;
+        rest = remaining;
+    }
+    Ok(())
+}
+
+fn write_value(out: &mut impl Write, name: &str, value: &[u8]) -> std::io::Result<()> {
+    if is_safe_string(value) {
+        // Checked to be ASCII.
+        write_folded(
+            out,
+            &format!("{}: {}", name, String::from_utf8_lossy(value)),
+        )
+    } else {
+        write_folded(out, &format!("{}:: {}", name, base64::encode(value)))
+    }
+}
+
+/// Writes the entry in the LDIF format, followed by an empty line.
+pub fn write_entry(out: &mut impl Write, entry: &LdapSearchResultEntry) -> std::io::Result<()> {
+    write_value(out, "dn", entry.dn.as_bytes())?;
+    for attribute in &entry.attributes {
+        for value in &attribute.vals {
+            write_value(out, &attribute.atype, value)?;
+        }
+    }
+    writeln!(out)
+}
+
+fn organizational_unit(ldap_info: &LdapInfo, ou: &str) -> LdapSearchResultEntry {
+    LdapSearchResultEntry {
+        dn: format!("ou={},{}", ou, ldap_info.base_dn_str),
+        attributes: vec![
+            LdapPartialAttribute {
+                atype: "objectClass".to_owned(),
+                vals: vec![b"organizationalUnit".to_vec()],
+            },
+            LdapPartialAttribute {
+                atype: "ou".to_owned(),
+                vals: vec![ou.as_bytes().to_vec()],
+            },
+        ],
+    }
+}
+
+fn into_entry(op: LdapOp, operational_attributes: bool) -> Option<LdapSearchResultEntry> {
+    match op {
+        LdapOp::SearchResultEntry(mut entry) => {
+            if !operational_attributes {
+                entry.attributes.retain(|attribute| {
+                    !OPERATIONAL_ATTRIBUTES.contains(&attribute.atype.to_ascii_lowercase().as_str())
+                });
+            }
+            Some(entry)
+        }
+        _ => None,
+    }
+}
+
+/// Writes all the users and groups in the LDIF format, as they are served over LDAP, after the
+/// "people" and "groups" organizational units. Returns the number of users and groups.
+pub async fn export_ldif<Backend: BackendHandler>(
+    backend: &Backend,
+    ldap_info: &LdapInfo,
+    operational_attributes: bool,
+    out: &mut impl Write,
+) -> Result<(usize, usize)> {
+    let all = LdapFilter::Present("objectClass".to_owned());
+    let attributes = ["*".to_owned()];
+    let users = get_user_list(
+        ldap_info,
+        all.clone(),
+        &attributes,
+        &ldap_info.base_dn_str,
+        None,
+        backend,
+    )
+    .try_collect::<Vec<_>>()
+    .await?;
+    let groups = get_groups_list(
+        ldap_info,
+        &all,
+        &attributes,
+        &ldap_info.base_dn_str,
+        &None,
+        backend,
+    )
+    .await?;
+    writeln!(out, "version: 1")?;
+    writeln!(out)?;
+    let options = &ldap_info.membership_options;
+    write_entry(out, &organizational_unit(ldap_info, &options.user_ou))?;
+    write_entry(out, &organizational_unit(ldap_info, &options.group_ou))?;
+    let users = users
+        .into_iter()
+        .filter_map(|op| into_entry(op, operational_attributes))
+        .collect::<Vec<_>>();
+    let groups = groups
+        .into_iter()
+        .filter_map(|op| into_entry(op, operational_attributes))
+        .collect::<Vec<_>>();
+    for entry in users.iter().chain(groups.iter()) {
+        write_entry(out, entry)?;
+    }
+    Ok((users.len(), groups.len()))
+}
+
+/// An entry read from an LDIF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdifEntry {
+    /// Line of the file where the entry starts, to report errors.
+    pub line: usize,
+    pub dn: String,
+    /// The values in the order of the file. The names are lowercased, without the options
+    /// ("cn;lang-fr" is read as "cn").
+    pub attributes: Vec<(String, Vec<u8>)>,
+}
+
+impl LdifEntry {
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.attributes
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// The first value of the attribute, if it is valid UTF-8.
+    pub fn first_value(&self, name: &str) -> Option<&str> {
+        self.values(name)
+            .next()
+            .and_then(|v| std::str::from_utf8(v).ok())
+    }
+}
+
+// Splits the content into its logical lines, unfolding the continuation lines and dropping the
+// comments. Returns the line number of the start of each one, and the empty lines separating
+// the entries as `None`.
+fn unfold(content: &str) -> Vec<(usize, Option<String>)> {
+    let mut lines: Vec<(usize, Option<String>)> = Vec::new();
+    let mut in_comment = false;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(continuation) = line.strip_prefix(' ') {
+            if !in_comment {
+                if let Some((_, Some(previous))) = lines.last_mut() {
+                    previous.push_str(continuation);
+                }
+            }
+            continue;
+        }
+        in_comment = line.starts_with('#');
+        if in_comment {
+            continue;
+        }
+        lines.push((number + 1, (!line.is_empty()).then(|| line.to_owned())));
+    }
+    lines
+}
+
+fn parse_line(line: &str) -> Result<(String, Vec<u8>)> {
+    let (name, value) = line
+        .split_once(':')
+        .with_context(|| format!("Missing ':' in \"{}\"", line))?;
+    let name = name
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let value = if let Some(encoded) = value.strip_prefix(':') {
+        base64::decode(encoded.trim())
+            .with_context(|| format!("Invalid base64 value of {}", name))?
+    } else if value.starts_with('<') {
+        anyhow::bail!("Values read from URLs are not supported ({})", name)
+    } else {
+        value.trim_start_matches(' ').as_bytes().to_vec()
+    };
+    Ok((name, value))
+}
+
+/// Parses the content of an LDIF file (RFC 2849). Change records are rejected, except for
+/// `changetype: add`.
+pub fn parse_ldif(content: &str) -> Result<Vec<LdifEntry>> {
+    let mut entries = Vec::new();
+    let mut current: Option<LdifEntry> = None;
+    for (number, line) in unfold(content) {
+        let line = match line {
+            None => {
+                entries.extend(current.take());
+                continue;
+            }
+            Some(line) => line,
+        };
+        let (name, value) = parse_line(&line).with_context(|| format!("line {}", number))?;
+        match current.as_mut() {
+            None if name == "version" && entries.is_empty() => {}
+            None if name == "dn" => {
+                current = Some(LdifEntry {
+                    line: number,
+                    dn: String::from_utf8(value)
+                        .with_context(|| format!("line {}: invalid DN", number))?,
+                    attributes: Vec::new(),
+                })
+            }
+            None => anyhow::bail!("line {}: expected a \"dn\", got \"{}\"", number, name),
+            Some(_) if name == "changetype" => {
+                if value != b"add" {
+                    anyhow::bail!(
+                        "line {}: only additions are supported, got \"changetype: {}\"",
+                        number,
+                        String::from_utf8_lossy(&value)
+                    );
+                }
+            }
+            Some(entry) => entry.attributes.push((name, value)),
+        }
+    }
+    entries.extend(current);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_ldif(entry: &LdapSearchResultEntry) -> String {
+        let mut out = Vec::new();
+        write_entry(&mut out, entry).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_is_safe_string() {
+        assert!(is_safe_string(b"bob"));
+        assert!(is_safe_string(b""));
+        assert!(is_safe_string(b"bob: the builder"));
+        assert!(!is_safe_string(b" bob"));
+        assert!(!is_safe_string(b"bob "));
+        assert!(!is_safe_string(b":bob"));
+        assert!(!is_safe_string(b"<bob"));
+        assert!(!is_safe_string(b"bob\nbob"));
+        assert!(!is_safe_string("böb".as_bytes()));
+    }
+
+    #[test]
+    fn test_write_entry() {
+        let entry = LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![
+                LdapPartialAttribute {
+                    atype: "objectClass".to_owned(),
+                    vals: vec![b"inetOrgPerson".to_vec(), b"person".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "cn".to_owned(),
+                    vals: vec!["Böb".as_bytes().to_vec()],
+                },
+            ],
+        };
+        assert_eq!(
+            to_ldif(&entry),
+            "dn: uid=bob,ou=people,dc=example,dc=com\n\
+             objectClass: inetOrgPerson\n\
+             objectClass: person\n\
+             cn:: QsO2Yg==\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn test_long_lines_are_folded() {
+        let entry = LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "description".to_owned(),
+                vals: vec![vec![b'a'; 200]],
+            }],
+        };
+        let ldif = to_ldif(&entry);
+        let lines = ldif.lines().collect::<Vec<_>>();
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_LENGTH));
+        assert_eq!(lines[2], format!(" {}", "a".repeat(75)));
+        // Unfolding gives back the value.
+        let unfolded = ldif.replace("\n ", "");
+        assert!(unfolded.contains(&format!("description: {}\n", "a".repeat(200))));
+    }
+
+    #[test]
+    fn test_parse_ldif() {
+        let ldif = "version: 1\n\
+                    \n\
+                    # A comment\n\
+                    #  continued\n\
+                    dn: uid=bob,ou=people,dc=example,dc=com\n\
+                    objectClass: inetOrgPerson\n\
+                    cn;lang-fr:: QsO2Yg==\n\
+                    description: a long\n\
+                    \x20description\n\
+                    \n\
+                    \n\
+                    dn: cn=group,ou=groups,dc=example,dc=com\n\
+                    changetype: add\n\
+                    member: uid=bob,ou=people,dc=example,dc=com\n";
+        let entries = parse_ldif(ldif).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].line, 5);
+        assert_eq!(entries[0].dn, "uid=bob,ou=people,dc=example,dc=com");
+        assert_eq!(entries[0].first_value("CN"), Some("Böb"));
+        assert_eq!(
+            entries[0].first_value("description"),
+            Some("a longdescription")
+        );
+        assert_eq!(entries[1].line, 12);
+        assert_eq!(
+            entries[1].values("member").collect::<Vec<_>>(),
+            vec![b"uid=bob,ou=people,dc=example,dc=com".as_slice()]
+        );
+    }
+
+    #[test]
+    fn test_parse_ldif_errors() {
+        assert!(parse_ldif("cn: bob\n").is_err());
+        assert!(parse_ldif("dn: cn=bob\nchangetype: delete\n").is_err());
+        assert!(parse_ldif("dn: cn=bob\njpegPhoto:< file:///photo.jpg\n").is_err());
+        assert!(parse_ldif("dn: cn=bob\ncn:: not base64!\n").is_err());
+    }
+
+    #[test]
+    fn test_export_can_be_parsed() {
+        let entry = LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "cn".to_owned(),
+                vals: vec!["Böb".as_bytes().to_vec(), vec![b'a'; 200]],
+            }],
+        };
+        let entries = parse_ldif(&to_ldif(&entry)).unwrap();
+        assert_eq!(entries[0].dn, entry.dn);
+        assert_eq!(
+            entries[0].values("cn").collect::<Vec<_>>(),
+            vec!["Böb".as_bytes(), &[b'a'; 200][..]]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Binding of the listening sockets of the LDAP, LDAPS, HTTP and HTTPS servers, on each of their
+//! addresses.
+
+use crate::infra::{configuration::ListenAddresses, systemd::ActivatedSockets};
+use actix_rt::net::TcpStream;
+use actix_server::{ServerBuilder, ServiceFactory};
+use anyhow::{Context, Result};
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs},
+};
+use tracing::info;
+
+fn bind_address(address: SocketAddr, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = if address.is_ipv4() {
+        tokio::net::TcpSocket::new_v4()?
+    } else {
+        tokio::net::TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(address)?;
+    socket.listen(backlog)?.into_std()
+}
+
+/// Binds a socket for each of the addresses, and each of the IPs a host name resolves to.
+///
+/// The IPv6 addresses are bound first: where the system is dual-stack (the default on Linux), the
+/// socket of "::" accepts the IPv4 connections as well, and "0.0.0.0" cannot be bound anymore. It
+/// is skipped then, so that listing both works on all the systems.
+pub fn bind_addresses(
+    addresses: &ListenAddresses,
+    port: u16,
+    backlog: u32,
+) -> Result<Vec<TcpListener>> {
+    let mut socket_addresses = Vec::<SocketAddr>::new();
+    for address in addresses.iter() {
+        let host = address.trim_start_matches('[').trim_end_matches(']');
+        for socket_address in (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("while resolving {}", address))?
+        {
+            if !socket_addresses.contains(&socket_address) {
+                socket_addresses.push(socket_address);
+            }
+        }
+    }
+    socket_addresses.sort_by_key(SocketAddr::is_ipv4);
+    let ipv6_wildcard = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    let mut listeners = Vec::new();
+    for address in socket_addresses {
+        match bind_address(address, backlog) {
+            Ok(listener) => listeners.push(listener),
+            Err(e)
+                if e.kind() == ErrorKind::AddrInUse
+                    && address.ip() == Ipv4Addr::UNSPECIFIED
+                    && has_listener_on(&listeners, ipv6_wildcard) =>
+            {
+                info!(
+                    "The IPv4 connections on port {} are accepted by the dual-stack socket of [::]",
+                    port
+                );
+            }
+            Err(e) => return Err(e).with_context(|| format!("while binding to {}", address)),
+        }
+    }
+    Ok(listeners)
+}
+
+fn has_listener_on(listeners: &[TcpListener], address: SocketAddr) -> bool {
+    listeners
+        .iter()
+        .any(|listener| listener.local_addr().ok() == Some(address))
+}
+
+/// Adds a server listening on the sockets passed by systemd with its name if there are any, or on
+/// each of its addresses otherwise.
+pub fn listen<F>(
+    server_builder: ServerBuilder,
+    sockets: &mut ActivatedSockets,
+    name: &str,
+    addresses: &ListenAddresses,
+    port: u16,
+    backlog: u32,
+    factory: F,
+) -> Result<ServerBuilder>
+where
+    F: ServiceFactory<TcpStream>,
+{
+    let mut listeners = sockets.take(name);
+    if listeners.is_empty() {
+        listeners = bind_addresses(addresses, port, backlog)?;
+    }
+    listeners
+        .into_iter()
+        .try_fold(server_builder, |builder, listener| {
+            builder.listen(name, listener, factory.clone())
+        })
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_addresses() {
+        let listeners =
+            bind_addresses(&ListenAddresses::from("127.0.0.1, 127.0.0.1"), 0, 16).unwrap();
+        assert_eq!(listeners.len(), 1);
+        assert!(listeners[0].local_addr().unwrap().ip().is_loopback());
+    }
+
+    #[test]
+    fn test_listen_addresses_from_str() {
+        assert_eq!(
+            ListenAddresses::from(r#"["0.0.0.0", "::"]"#)
+                .iter()
+                .collect::<Vec<_>>(),
+            vec!["0.0.0.0", "::"]
+        );
+        assert_eq!(
+            ListenAddresses::from("[::1],127.0.0.1")
+                .iter()
+                .collect::<Vec<_>>(),
+            vec!["::1", "127.0.0.1"]
+        );
+        assert!(ListenAddresses::from(" ").is_empty());
+    }
+}
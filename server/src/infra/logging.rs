@@ -1,11 +1,26 @@
-use crate::infra::configuration::Configuration;
+use crate::infra::{
+    configuration::{Configuration, LogFormat, OpenTelemetryOptions},
+    correlation_id::{CorrelationId, CORRELATION_ID_HEADER},
+    trusted_proxies::{client_ip, scheme},
+};
 use actix_web::{
     dev::{ServiceRequest, ServiceResponse},
-    Error,
+    Error, HttpMessage,
+};
+use anyhow::Context;
+use opentelemetry::{
+    sdk::{
+        trace::{self, Sampler},
+        Resource,
+    },
+    KeyValue,
 };
+use opentelemetry_otlp::WithExportConfig;
 use tracing::{error, info, Span};
 use tracing_actix_web::{root_span, RootSpanBuilder};
-use tracing_subscriber::{filter::EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{
+    filter::EnvFilter, layer::SubscriberExt, reload, util::SubscriberInitExt, Registry,
+};
 
 /// We will define a custom root span builder to capture additional fields, specific
 /// to our application, on top of the ones provided by `DefaultRootSpanBuilder` out of the box.
@@ -13,9 +28,21 @@ pub struct CustomRootSpanBuilder;
 
 impl RootSpanBuilder for CustomRootSpanBuilder {
     fn on_request_start(request: &ServiceRequest) -> Span {
-        let span = root_span!(request);
+        // Kept in the request for the handlers to return it, e.g. with the GraphQL errors.
+        let correlation_id = request
+            .headers()
+            .get(CORRELATION_ID_HEADER)
+            .and_then(|header| header.to_str().ok())
+            .and_then(CorrelationId::from_client)
+            .unwrap_or_default();
+        let span = root_span!(request, correlation_id = %correlation_id);
+        request.extensions_mut().insert(correlation_id);
         span.in_scope(|| {
-            info!(uri = %request.uri());
+            info!(
+                uri = %request.uri(),
+                client_ip = ?client_ip(request.request()),
+                scheme = %scheme(request.request()),
+            );
         });
         span
     }
@@ -34,19 +61,81 @@ impl RootSpanBuilder for CustomRootSpanBuilder {
     }
 }
 
-pub fn init(config: &Configuration) -> anyhow::Result<()> {
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-        EnvFilter::new(if config.verbose {
+fn build_tracer(
+    options: &OpenTelemetryOptions,
+) -> anyhow::Result<opentelemetry::sdk::trace::Tracer> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(&options.endpoint)
+                .with_headers(options.headers.clone()),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(Sampler::TraceIdRatioBased(options.sampling_ratio))
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", "lldap")])),
+        )
+        // The exports run on their own thread: there is no runtime yet when the logging starts.
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)
+        .context("while setting up the OpenTelemetry exporter")
+}
+
+fn env_filter(verbose: bool) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(if verbose {
             "sqlx=warn,reqwest=warn,debug"
         } else {
             "sqlx=warn,reqwest=warn,info"
         })
-    });
+    })
+}
+
+/// Changes the log level of the running server.
+#[derive(Clone)]
+pub struct LogLevelHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogLevelHandle {
+    pub fn set_verbose(&self, verbose: bool) -> anyhow::Result<()> {
+        self.0
+            .reload(env_filter(verbose))
+            .context("while changing the log level")
+    }
+}
+
+pub fn init(config: &Configuration) -> anyhow::Result<LogLevelHandle> {
+    let (env_filter, log_level_handle) = reload::Layer::new(env_filter(config.verbose));
+    let opentelemetry_layer = if config.opentelemetry_options.enabled {
+        let tracer = build_tracer(&config.opentelemetry_options)?;
+        Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    } else {
+        None
+    };
+    let (forest_layer, json_layer) = match config.logging.format {
+        LogFormat::Text => (Some(tracing_forest::ForestLayer::default()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            ),
+        ),
+    };
     tracing_subscriber::registry()
         .with(env_filter)
-        .with(tracing_forest::ForestLayer::default())
+        .with(forest_layer)
+        .with(json_layer)
+        .with(opentelemetry_layer)
         .init();
-    Ok(())
+    Ok(LogLevelHandle(log_level_handle))
+}
+
+/// Sends the spans that are not exported yet, if any.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
 }
 
 #[cfg(test)]
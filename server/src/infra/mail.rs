@@ -1,68 +1,760 @@
-use crate::infra::{cli::SmtpEncryption, configuration::MailOptions};
-use anyhow::{Ok, Result};
+//! The emails sent by LLDAP.
+//!
+//! Each email has a built-in plain text body and subject, which can be replaced for branding by
+//! the files `<name>.txt` and `<name>.subject` of `templates_dir`. A `<name>.html` file there adds
+//! an HTML version, sent along with the plain text one. The files are Tera templates, read when
+//! sending each email: see `EmailTemplate` for the names and the variables of each email.
+//!
+//! The emails sent to a user with a preferred language look first for the translated files, named
+//! after the language tag: `<name>.fr-CA.txt`, then `<name>.fr.txt`, then `<name>.txt`.
+
+use crate::infra::{
+    cli::SmtpEncryption,
+    configuration::{DkimAlgorithm, MailOptions},
+    smtp_oauth2,
+};
+use anyhow::{Context, Ok, Result};
 use lettre::{
-    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
-    AsyncTransport, Message, Tokio1Executor,
+    message::{
+        dkim::{DkimConfig, DkimSigningAlgorithm, DkimSigningKey},
+        Mailbox, MultiPart, SinglePart,
+    },
+    transport::smtp::authentication::{Credentials, Mechanism},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// The built-in version of an email.
+struct EmailTemplate {
+    name: &'static str,
+    subject: &'static str,
+    text: &'static str,
+}
+
+/// Variables: `username`, `url`, `base_url`.
+const PASSWORD_RESET: EmailTemplate = EmailTemplate {
+    name: "password_reset",
+    subject: "[LLDAP] Password reset requested",
+    text: "Hello {{ username }},
+This email has been sent to you in order to validate your identity.
+If you did not initiate the process your credentials might have been
+compromised. You should reset your password and contact an administrator.
+
+To reset your password please visit the following URL: {{ url }}
+
+Please contact an administrator if you did not initiate the process.",
+};
+
+/// Variables: `username`, `url`, `base_url`, `validity_days`.
+const PASSWORD_SETUP: EmailTemplate = EmailTemplate {
+    name: "password_setup",
+    subject: "[LLDAP] Choose your password",
+    text: "Hello {{ username }},
+Your account was moved to LLDAP. Your previous password cannot be transferred,
+you need to choose a new one.
+
+To choose your password please visit the following URL: {{ url }}
+
+The link expires in {{ validity_days }} days. Afterwards, you can still request a new one from
+the login page with \"Forgot your password?\".",
+};
+
+/// Variables: `username`, `url`, `base_url`, `validity_days`.
+const ADMIN_PASSWORD_RESET: EmailTemplate = EmailTemplate {
+    name: "admin_password_reset",
+    subject: "[LLDAP] Choose a new password",
+    text: "Hello {{ username }},
+An administrator requested that you choose a new password for your LLDAP account.
+
+To choose your password please visit the following URL: {{ url }}
+
+The link expires in {{ validity_days }} days. Afterwards, you can still request a new one from
+the login page with \"Forgot your password?\".",
+};
+
+/// Variables: `url`, `base_url`.
+const INVITATION: EmailTemplate = EmailTemplate {
+    name: "invitation",
+    subject: "[LLDAP] Invitation to create an account",
+    text: "Hello,
+An administrator invited you to create an account on LLDAP.
+
+To choose your username and password please visit the following URL: {{ url }}
+
+The invitation expires in a week. You can ignore this email if you were not
+expecting it.",
+};
+
+/// Variables: `user_id`, `email`, `url`, `base_url`.
+const SIGN_UP_NOTIFICATION: EmailTemplate = EmailTemplate {
+    name: "sign_up_notification",
+    subject: "[LLDAP] New account request",
+    text: "Hello,
+Someone requested an account on LLDAP, with the user ID '{{ user_id }}' and the email '{{ email }}'.
+
+To approve or reject the request please visit the following URL: {{ url }}",
+};
+
+/// Variables: `username`, `url`, `base_url`.
+const SIGN_UP_APPROVED: EmailTemplate = EmailTemplate {
+    name: "sign_up_approved",
+    subject: "[LLDAP] Account request approved",
+    text: "Hello {{ username }},
+Your request for an account on LLDAP was approved.
+
+To choose your password please visit the following URL: {{ url }}",
+};
+
+/// Variables: `username`.
+const SIGN_UP_REJECTED: EmailTemplate = EmailTemplate {
+    name: "sign_up_rejected",
+    subject: "[LLDAP] Account request rejected",
+    text: "Hello {{ username }},
+Your request for an account on LLDAP was rejected by an administrator.",
+};
+
+/// Variables: `username`, `base_url`.
+const PASSWORD_CHANGED: EmailTemplate = EmailTemplate {
+    name: "password_changed",
+    subject: "[LLDAP] Your password was changed",
+    text: "Hello {{ username }},
+The password of your LLDAP account was just changed.
+
+If you did not change it, please reset your password at {{ base_url }}
+and contact an administrator.",
+};
+
+/// Variables: `username`, `base_url`.
+const WEBAUTHN_CREDENTIAL_REMOVED: EmailTemplate = EmailTemplate {
+    name: "webauthn_credential_removed",
+    subject: "[LLDAP] A security key was removed",
+    text: "Hello {{ username }},
+A security key or passkey was just removed from your LLDAP account.
+
+If you did not remove it, please check your account at {{ base_url }}
+and contact an administrator.",
+};
+
+/// Variables: `username`, `group`, `added`, `base_url`.
+const ADMIN_GROUP_CHANGED: EmailTemplate = EmailTemplate {
+    name: "admin_group_changed",
+    subject: "[LLDAP] Your administrative rights changed",
+    text: "Hello {{ username }},
+Your LLDAP account was just {% if added %}added to{% else %}removed from{% endif %} the group '{{ group }}'.
+
+Please contact an administrator if you were not expecting this change.",
+};
+
+/// Variables: `username`, `expiry_date`, `days_left`, `base_url`.
+const PASSWORD_EXPIRY_WARNING: EmailTemplate = EmailTemplate {
+    name: "password_expiry_warning",
+    subject: "[LLDAP] Your password expires soon",
+    text: "Hello {{ username }},
+The password of your LLDAP account expires on {{ expiry_date }}, in {{ days_left }} day(s).
+
+Please change it at {{ base_url }} before then. Afterwards, you will have to change it
+at your next login before using your account.",
+};
+
+/// Variables: `username`, `disable_date`, `days_left`, `base_url`.
+const STALE_ACCOUNT_WARNING: EmailTemplate = EmailTemplate {
+    name: "stale_account_warning",
+    subject: "[LLDAP] Your account will be disabled",
+    text: "Hello {{ username }},
+You have not used your LLDAP account for a while. It will be disabled on {{ disable_date }},
+in {{ days_left }} day(s).
+
+To keep it, please log in at {{ base_url }} before then.",
 };
-use tracing::debug;
 
-async fn send_email(to: Mailbox, subject: &str, body: String, options: &MailOptions) -> Result<()> {
-    let from = options
-        .from
-        .clone()
-        .unwrap_or_else(|| "LLDAP <nobody@lldap>".parse().unwrap());
+/// No variables.
+const TEST: EmailTemplate = EmailTemplate {
+    name: "test",
+    subject: "LLDAP test email",
+    text: "The test is successful! You can send emails from LLDAP",
+};
+
+const ALL_TEMPLATES: [&EmailTemplate; 13] = [
+    &PASSWORD_RESET,
+    &PASSWORD_SETUP,
+    &ADMIN_PASSWORD_RESET,
+    &INVITATION,
+    &SIGN_UP_NOTIFICATION,
+    &SIGN_UP_APPROVED,
+    &SIGN_UP_REJECTED,
+    &PASSWORD_CHANGED,
+    &WEBAUTHN_CREDENTIAL_REMOVED,
+    &ADMIN_GROUP_CHANGED,
+    &PASSWORD_EXPIRY_WARNING,
+    &STALE_ACCOUNT_WARNING,
+    &TEST,
+];
+
+struct RenderedEmail {
+    subject: String,
+    text: String,
+    html: Option<String>,
+}
+
+/// Reads the template file from the directory, if there is one and the file exists.
+fn read_template_file(templates_dir: Option<&Path>, file_name: &str) -> Result<Option<String>> {
+    let path = match templates_dir {
+        None => return Ok(None),
+        Some(dir) => dir.join(file_name),
+    };
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::read_to_string(&path).with_context(|| {
+        format!("while reading the email template {}", path.display())
+    })?))
+}
+
+/// The language tag and its less specific versions, from the most specific one: "zh-Hant-TW",
+/// "zh-Hant", "zh".
+fn locale_fallbacks(locale: Option<&str>) -> Vec<&str> {
+    let mut fallbacks = Vec::new();
+    let mut locale = locale.unwrap_or_default();
+    while !locale.is_empty() {
+        fallbacks.push(locale);
+        locale = locale
+            .rsplit_once('-')
+            .map(|(prefix, _)| prefix)
+            .unwrap_or("");
+    }
+    fallbacks
+}
+
+/// Reads the most specific template file for the locale, if any.
+fn read_localized_template_file(
+    templates_dir: Option<&Path>,
+    name: &str,
+    extension: &str,
+    locale: Option<&str>,
+) -> Result<Option<(String, String)>> {
+    let file_names = locale_fallbacks(locale)
+        .into_iter()
+        .map(|locale| format!("{}.{}.{}", name, locale, extension))
+        .chain(std::iter::once(format!("{}.{}", name, extension)));
+    for file_name in file_names {
+        if let Some(source) = read_template_file(templates_dir, &file_name)? {
+            return Ok(Some((file_name, source)));
+        }
+    }
+    Ok(None)
+}
+
+fn render_email(
+    template: &EmailTemplate,
+    variables: &tera::Context,
+    locale: Option<&str>,
+    options: &MailOptions,
+) -> Result<RenderedEmail> {
+    let templates_dir = options.templates_dir.as_deref().map(Path::new);
+    let render = |extension: &str, default: Option<&str>| -> Result<Option<String>> {
+        let (file_name, source) =
+            match read_localized_template_file(templates_dir, template.name, extension, locale)? {
+                Some(file) => file,
+                None => match default {
+                    Some(default) => (
+                        format!("{}.{}", template.name, extension),
+                        default.to_owned(),
+                    ),
+                    None => return Ok(None),
+                },
+            };
+        Ok(Some(
+            tera::Tera::one_off(&source, variables, extension == "html")
+                .with_context(|| format!("while rendering the email template {}", file_name))?,
+        ))
+    };
+    Ok(RenderedEmail {
+        // A trailing newline in the file would break the header.
+        subject: render("subject", Some(template.subject))?
+            .unwrap_or_default()
+            .trim()
+            .to_owned(),
+        text: render("txt", Some(template.text))?.unwrap_or_default(),
+        html: render("html", None)?,
+    })
+}
+
+/// The locales of the translated template files of `templates_dir`.
+fn template_locales(options: &MailOptions) -> Result<Vec<String>> {
+    let templates_dir = match &options.templates_dir {
+        None => return Ok(Vec::new()),
+        Some(dir) => Path::new(dir),
+    };
+    if !templates_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut locales = Vec::new();
+    for entry in std::fs::read_dir(templates_dir)
+        .with_context(|| format!("while listing {}", templates_dir.display()))?
+    {
+        let file_name = entry?.file_name();
+        let parts: Vec<_> = file_name
+            .to_string_lossy()
+            .splitn(3, '.')
+            .map(str::to_owned)
+            .collect();
+        if let [name, locale, _] = parts.as_slice() {
+            if ALL_TEMPLATES.iter().any(|t| t.name == name.as_str()) {
+                locales.push(locale.clone());
+            }
+        }
+    }
+    locales.sort();
+    locales.dedup();
+    Ok(locales)
+}
+
+/// Renders all the emails with placeholder values, to check the templates of `templates_dir`,
+/// including the translated ones.
+pub fn check_templates(options: &MailOptions) -> Result<()> {
+    let mut variables = tera::Context::new();
+    for name in [
+        "username",
+        "user_id",
+        "email",
+        "url",
+        "base_url",
+        "group",
+        "expiry_date",
+    ] {
+        variables.insert(name, name);
+    }
+    variables.insert("validity_days", &7);
+    variables.insert("days_left", &7);
+    variables.insert("added", &true);
+    let locales = template_locales(options)?;
+    for locale in std::iter::once(None).chain(locales.iter().map(|l| Some(l.as_str()))) {
+        for template in ALL_TEMPLATES {
+            render_email(template, &variables, locale, options)?;
+        }
+    }
+    Ok(())
+}
+
+/// The configured sender, or the SMTP user if it is an email address: the providers reject or
+/// flag the emails from another domain than the authenticated one.
+fn sender(options: &MailOptions) -> Mailbox {
+    options.from.clone().unwrap_or_else(|| {
+        options
+            .user
+            .parse()
+            .map(|address| Mailbox::new(Some("LLDAP".to_owned()), address))
+            .unwrap_or_else(|_| "LLDAP <nobody@lldap>".parse().unwrap())
+    })
+}
+
+/// A unique Message-ID, in the domain of the sender rather than the default "localhost" that the
+/// spam filters penalize.
+fn message_id(options: &MailOptions, from: &Mailbox) -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+    let domain = options
+        .message_id_domain
+        .as_deref()
+        .unwrap_or_else(|| from.email.domain());
+    format!("<{}.{}@{}>", chrono::Utc::now().timestamp(), random, domain)
+}
+
+fn dkim_config(options: &MailOptions, from: &Mailbox) -> Result<Option<DkimConfig>> {
+    let dkim = &options.dkim;
+    if !dkim.enabled {
+        return Ok(None);
+    }
+    let algorithm = match dkim.algorithm {
+        DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+        DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+    };
+    let key = DkimSigningKey::new(dkim.private_key.unsecure(), algorithm)
+        .map_err(|e| anyhow::anyhow!("Invalid DKIM private key: {}", e))?;
+    Ok(Some(DkimConfig::default_config(
+        dkim.selector.clone(),
+        dkim.domain
+            .clone()
+            .unwrap_or_else(|| from.email.domain().to_owned()),
+        key,
+    )))
+}
+
+/// Checks that the DKIM private key can be loaded, if the signature is enabled.
+pub fn check_dkim_key(options: &MailOptions) -> Result<()> {
+    dkim_config(options, &sender(options))?;
+    Ok(())
+}
+
+async fn send_email(
+    to: Mailbox,
+    template: &EmailTemplate,
+    variables: tera::Context,
+    locale: Option<&str>,
+    options: &MailOptions,
+) -> Result<()> {
+    let email = render_email(template, &variables, locale, options)?;
+    let from = sender(options);
     let reply_to = options.reply_to.clone().unwrap_or_else(|| from.clone());
+    let dkim_config = dkim_config(options, &from)?;
     debug!(
         "Sending email to '{}' as '{}' via '{}'@'{}':'{}'",
         &to, &from, &options.user, &options.server, options.port
     );
-    let email = Message::builder()
+    let builder = Message::builder()
+        .message_id(Some(message_id(options, &from)))
         .from(from)
         .reply_to(reply_to)
         .to(to)
-        .subject(subject)
-        .body(body)?;
-    let creds = Credentials::new(
-        options.user.clone(),
-        options.password.unsecure().to_string(),
-    );
+        .subject(email.subject);
+    let mut message = match email.html {
+        None => builder.body(email.text)?,
+        Some(html) => builder.multipart(
+            MultiPart::alternative()
+                .singlepart(SinglePart::plain(email.text))
+                .singlepart(SinglePart::html(html)),
+        )?,
+    };
+    if let Some(dkim_config) = dkim_config {
+        message.sign(&dkim_config);
+    }
+    if let Err(e) = get_mailer(options).await?.send(message.clone()).await {
+        if !(options.oauth2.enabled && e.is_permanent()) {
+            return Err(e.into());
+        }
+        // The access token may have been revoked before its expiry: retry with a new one.
+        warn!(
+            "Sending the email failed, retrying with a new access token: {}",
+            e
+        );
+        smtp_oauth2::invalidate_access_token();
+        get_mailer(options).await?.send(message).await?;
+    }
+    Ok(())
+}
+
+async fn get_mailer(options: &MailOptions) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
     let relay_factory = match options.smtp_encryption {
         SmtpEncryption::TLS => AsyncSmtpTransport::<Tokio1Executor>::relay,
         SmtpEncryption::STARTTLS => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay,
     };
-    let mailer = relay_factory(&options.server)?.credentials(creds).build();
-    mailer.send(email).await?;
+    let mailer = relay_factory(&options.server)?;
+    Ok(if options.oauth2.enabled {
+        let access_token = smtp_oauth2::get_access_token(&options.oauth2).await?;
+        mailer
+            .credentials(Credentials::new(
+                options.user.clone(),
+                access_token.unsecure().to_string(),
+            ))
+            .authentication(vec![Mechanism::Xoauth2])
+            .build()
+    } else {
+        mailer
+            .credentials(Credentials::new(
+                options.user.clone(),
+                options.password.unsecure().to_string(),
+            ))
+            .build()
+    })
+}
+
+/// Connects to the SMTP server and logs in, without sending anything.
+pub async fn check_smtp_connection(options: &MailOptions) -> Result<()> {
+    if !get_mailer(options).await?.test_connection().await? {
+        anyhow::bail!(
+            "The SMTP server {} did not accept the connection",
+            options.server
+        );
+    }
     Ok(())
 }
 
+fn base_variables(domain: &str) -> tera::Context {
+    let mut variables = tera::Context::new();
+    variables.insert("base_url", domain);
+    variables
+}
+
 pub async fn send_password_reset_email(
     username: &str,
     to: &str,
+    locale: Option<&str>,
     token: &str,
     domain: &str,
     options: &MailOptions,
 ) -> Result<()> {
     let to = to.parse()?;
-    let body = format!(
-        "Hello {},
-This email has been sent to you in order to validate your identity.
-If you did not initiate the process your credentials might have been
-compromised. You should reset your password and contact an administrator.
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("url", &format!("{}/reset-password/step2/{}", domain, token));
+    send_email(to, &PASSWORD_RESET, variables, locale, options).await
+}
 
-To reset your password please visit the following URL: {}/reset-password/step2/{}
+pub async fn send_password_setup_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    token: &str,
+    validity_days: i64,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("url", &format!("{}/reset-password/step2/{}", domain, token));
+    variables.insert("validity_days", &validity_days);
+    send_email(to, &PASSWORD_SETUP, variables, locale, options).await
+}
 
-Please contact an administrator if you did not initiate the process.",
-        username, domain, token
-    );
-    send_email(to, "[LLDAP] Password reset requested", body, options).await
+pub async fn send_admin_password_reset_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    token: &str,
+    validity_days: i64,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("url", &format!("{}/reset-password/step2/{}", domain, token));
+    variables.insert("validity_days", &validity_days);
+    send_email(to, &ADMIN_PASSWORD_RESET, variables, locale, options).await
+}
+
+pub async fn send_invitation_email(
+    to: &str,
+    token: &str,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("url", &format!("{}/invitation/{}", domain, token));
+    send_email(to, &INVITATION, variables, None, options).await
+}
+
+pub async fn send_sign_up_notification_email(
+    to: &str,
+    user_id: &str,
+    email: &str,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("user_id", user_id);
+    variables.insert("email", email);
+    variables.insert("url", domain);
+    send_email(to, &SIGN_UP_NOTIFICATION, variables, None, options).await
+}
+
+pub async fn send_sign_up_approved_email(
+    username: &str,
+    to: &str,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("url", &format!("{}/reset-password/step1", domain));
+    send_email(to, &SIGN_UP_APPROVED, variables, None, options).await
+}
+
+pub async fn send_sign_up_rejected_email(
+    username: &str,
+    to: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = tera::Context::new();
+    variables.insert("username", username);
+    send_email(to, &SIGN_UP_REJECTED, variables, None, options).await
+}
+
+pub async fn send_password_changed_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    send_email(to, &PASSWORD_CHANGED, variables, locale, options).await
+}
+
+pub async fn send_webauthn_credential_removed_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    send_email(to, &WEBAUTHN_CREDENTIAL_REMOVED, variables, locale, options).await
+}
+
+pub async fn send_admin_group_changed_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    group: &str,
+    added: bool,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("group", group);
+    variables.insert("added", &added);
+    send_email(to, &ADMIN_GROUP_CHANGED, variables, locale, options).await
+}
+
+pub async fn send_password_expiry_warning_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    expiry_date: &chrono::DateTime<chrono::Utc>,
+    days_left: i64,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("expiry_date", &expiry_date.format("%Y-%m-%d").to_string());
+    variables.insert("days_left", &days_left);
+    send_email(to, &PASSWORD_EXPIRY_WARNING, variables, locale, options).await
+}
+
+pub async fn send_stale_account_warning_email(
+    username: &str,
+    to: &str,
+    locale: Option<&str>,
+    disable_date: &chrono::DateTime<chrono::Utc>,
+    days_left: i64,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut variables = base_variables(domain);
+    variables.insert("username", username);
+    variables.insert("disable_date", &disable_date.format("%Y-%m-%d").to_string());
+    variables.insert("days_left", &days_left);
+    send_email(to, &STALE_ACCOUNT_WARNING, variables, locale, options).await
 }
 
 pub async fn send_test_email(to: Mailbox, options: &MailOptions) -> Result<()> {
-    send_email(
-        to,
-        "LLDAP test email",
-        "The test is successful! You can send emails from LLDAP".to_string(),
-        options,
-    )
-    .await
+    send_email(to, &TEST, tera::Context::new(), None, options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> tera::Context {
+        let mut variables = base_variables("https://ldap.example.com");
+        variables.insert("username", "<bob>");
+        variables.insert("url", "https://ldap.example.com/reset-password/step2/token");
+        variables
+    }
+
+    #[test]
+    fn test_render_built_in_email() {
+        let email =
+            render_email(&PASSWORD_RESET, &variables(), None, &MailOptions::default()).unwrap();
+        assert_eq!(email.subject, "[LLDAP] Password reset requested");
+        assert!(email.text.starts_with("Hello <bob>,\n"));
+        assert!(email
+            .text
+            .contains("URL: https://ldap.example.com/reset-password/step2/token\n"));
+        assert!(email.html.is_none());
+        assert!(check_templates(&MailOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_render_custom_email() {
+        let dir = std::env::temp_dir().join(format!("lldap_mail_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("password_reset.subject"),
+            "Reset for {{ username }}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("password_reset.html"),
+            "<p>Hi {{ username }}</p><a href=\"{{ url }}\">Reset</a>",
+        )
+        .unwrap();
+        let options = MailOptions {
+            templates_dir: Some(dir.to_str().unwrap().to_owned()),
+            ..Default::default()
+        };
+        let email = render_email(&PASSWORD_RESET, &variables(), None, &options).unwrap();
+        assert_eq!(email.subject, "Reset for <bob>");
+        // The plain text version falls back to the built-in one.
+        assert!(email.text.starts_with("Hello <bob>,\n"));
+        assert_eq!(
+            email.html.unwrap(),
+            "<p>Hi &lt;bob&gt;</p><a href=\"https:&#x2F;&#x2F;ldap.example.com&#x2F;reset-password&#x2F;step2&#x2F;token\">Reset</a>"
+        );
+        std::fs::write(
+            dir.join("password_reset.fr.subject"),
+            "Réinitialisation pour {{ username }}",
+        )
+        .unwrap();
+        let email = render_email(&PASSWORD_RESET, &variables(), Some("fr-CA"), &options).unwrap();
+        assert_eq!(email.subject, "Réinitialisation pour <bob>");
+        let email = render_email(&PASSWORD_RESET, &variables(), Some("de"), &options).unwrap();
+        assert_eq!(email.subject, "Reset for <bob>");
+        assert!(check_templates(&options).is_ok());
+        std::fs::write(dir.join("invitation.fr.txt"), "{{ unclosed").unwrap();
+        assert!(check_templates(&options).is_err());
+        std::fs::remove_file(dir.join("invitation.fr.txt")).unwrap();
+        std::fs::write(dir.join("invitation.txt"), "{{ unclosed").unwrap();
+        assert!(check_templates(&options).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_locale_fallbacks() {
+        assert_eq!(
+            locale_fallbacks(Some("zh-Hant-TW")),
+            vec!["zh-Hant-TW", "zh-Hant", "zh"]
+        );
+        assert!(locale_fallbacks(None).is_empty());
+    }
+
+    #[test]
+    fn test_sender_and_message_id() {
+        let options = MailOptions {
+            user: "sender@example.com".to_owned(),
+            ..Default::default()
+        };
+        let from = sender(&options);
+        assert_eq!(from.to_string(), "LLDAP <sender@example.com>");
+        assert!(message_id(&options, &from).ends_with("@example.com>"));
+        let options = MailOptions {
+            user: "admin".to_owned(),
+            message_id_domain: Some("mail.example.com".to_owned()),
+            ..Default::default()
+        };
+        let from = sender(&options);
+        assert_eq!(from.to_string(), "LLDAP <nobody@lldap>");
+        assert!(message_id(&options, &from).ends_with("@mail.example.com>"));
+        assert_ne!(message_id(&options, &from), message_id(&options, &from));
+    }
 }
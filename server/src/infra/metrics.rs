@@ -0,0 +1,166 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Result;
+use ldap3_proto::{proto::LdapOp, LdapResultCode};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use tracing::error;
+
+use crate::infra::tcp_server::AppState;
+
+/// The metrics exported on `/metrics`, in the Prometheus format. Cloning it gives a handle on the
+/// same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    ldap_operations: IntCounterVec,
+    ldap_connections: IntGauge,
+    graphql_duration: Histogram,
+    db_query_duration: Histogram,
+}
+
+/// Counts an LDAP connection as active until it is dropped.
+pub struct LdapConnectionGuard(IntGauge);
+
+impl Drop for LdapConnectionGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("lldap".to_owned()), None)?;
+        let ldap_operations = IntCounterVec::new(
+            Opts::new(
+                "ldap_operations_total",
+                "Number of LDAP binds and searches.",
+            ),
+            &["operation", "result"],
+        )?;
+        let ldap_connections = IntGauge::new(
+            "ldap_active_connections",
+            "Number of open LDAP connections.",
+        )?;
+        let graphql_duration = Histogram::with_opts(HistogramOpts::new(
+            "graphql_request_duration_seconds",
+            "Time to answer the GraphQL requests.",
+        ))?;
+        let db_query_duration = Histogram::with_opts(HistogramOpts::new(
+            "db_query_duration_seconds",
+            "Time spent executing the database queries.",
+        ))?;
+        registry.register(Box::new(ldap_operations.clone()))?;
+        registry.register(Box::new(ldap_connections.clone()))?;
+        registry.register(Box::new(graphql_duration.clone()))?;
+        registry.register(Box::new(db_query_duration.clone()))?;
+        Ok(Self {
+            registry,
+            ldap_operations,
+            ldap_connections,
+            graphql_duration,
+            db_query_duration,
+        })
+    }
+
+    /// Counts the binds and searches, by result, from the response sent to the client.
+    pub fn record_ldap_response(&self, op: &LdapOp) {
+        let (operation, code) = match op {
+            LdapOp::BindResponse(response) => ("bind", &response.res.code),
+            LdapOp::SearchResultDone(result) => ("search", &result.code),
+            _ => return,
+        };
+        let result = match code {
+            LdapResultCode::Success => "success",
+            LdapResultCode::InvalidCredentials => "invalid_credentials",
+            LdapResultCode::InsufficentAccessRights => "insufficient_access_rights",
+            LdapResultCode::NoSuchObject => "no_such_object",
+            LdapResultCode::UnwillingToPerform => "unwilling_to_perform",
+            _ => "other_error",
+        };
+        self.ldap_operations
+            .with_label_values(&[operation, result])
+            .inc();
+    }
+
+    pub fn track_ldap_connection(&self) -> LdapConnectionGuard {
+        self.ldap_connections.inc();
+        LdapConnectionGuard(self.ldap_connections.clone())
+    }
+
+    pub fn graphql_duration(&self) -> &Histogram {
+        &self.graphql_duration
+    }
+
+    pub fn db_query_duration(&self) -> &Histogram {
+        &self.db_query_duration
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+async fn metrics_handler<Backend: 'static>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    match data.metrics.encode() {
+        Ok(buffer) => HttpResponse::Ok()
+            .content_type(TextEncoder::new().format_type())
+            .body(buffer),
+        Err(e) => {
+            error!("Could not encode the metrics: {:#}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub fn configure_endpoint<Backend: 'static>(cfg: &mut web::ServiceConfig) {
+    cfg.route("/metrics", web::get().to(metrics_handler::<Backend>));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::{LdapBindResponse, LdapResult};
+
+    fn bind_response(code: LdapResultCode) -> LdapOp {
+        LdapOp::BindResponse(LdapBindResponse {
+            res: LdapResult {
+                code,
+                matcheddn: "".to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            },
+            saslcreds: None,
+        })
+    }
+
+    #[test]
+    fn test_record_ldap_responses() {
+        let metrics = Metrics::new().unwrap();
+        metrics.record_ldap_response(&bind_response(LdapResultCode::Success));
+        metrics.record_ldap_response(&bind_response(LdapResultCode::InvalidCredentials));
+        metrics.record_ldap_response(&bind_response(LdapResultCode::InvalidCredentials));
+        metrics.record_ldap_response(&LdapOp::SearchResultDone(LdapResult {
+            code: LdapResultCode::Success,
+            matcheddn: "".to_string(),
+            message: "".to_string(),
+            referral: vec![],
+        }));
+        {
+            let _connection = metrics.track_ldap_connection();
+            assert_eq!(metrics.ldap_connections.get(), 1);
+        }
+        assert_eq!(metrics.ldap_connections.get(), 0);
+        let output = String::from_utf8(metrics.encode().unwrap()).unwrap();
+        assert!(output.contains(
+            r#"lldap_ldap_operations_total{operation="bind",result="invalid_credentials"} 2"#
+        ));
+        assert!(
+            output.contains(r#"lldap_ldap_operations_total{operation="bind",result="success"} 1"#)
+        );
+        assert!(output
+            .contains(r#"lldap_ldap_operations_total{operation="search",result="success"} 1"#));
+    }
+}
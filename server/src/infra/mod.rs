@@ -1,14 +1,46 @@
+pub mod acme;
+pub mod api_client;
 pub mod auth_service;
+pub mod avatar;
+pub mod bootstrap;
+pub mod change_events;
 pub mod cli;
+pub mod config_check;
+pub mod config_reload;
 pub mod configuration;
+pub mod correlation_id;
+pub mod database;
 pub mod db_cleaner;
 pub mod graphql;
 pub mod healthcheck;
+pub mod http_rate_limiter;
+pub mod import;
 pub mod jwt_sql_tables;
+pub mod ldap_client_profiles;
 pub mod ldap_handler;
+pub mod ldap_migration;
+pub mod ldap_primary;
+pub mod ldap_rate_limiter;
 pub mod ldap_server;
+pub mod ldap_wire_log;
+pub mod ldif;
+pub mod listeners;
 pub mod logging;
 pub mod mail;
+pub mod metrics;
+pub mod oidc_service;
+pub mod proxy_protocol;
+pub mod pwned_passwords;
+pub mod read_only;
+pub mod rest_api;
+pub mod scim_service;
+pub mod secrets_provider;
+pub mod security_notifications;
+pub mod smtp_oauth2;
 pub mod sql_backend_handler;
+pub mod systemd;
 pub mod tcp_backend_handler;
 pub mod tcp_server;
+pub mod tls_certificate;
+pub mod trusted_proxies;
+pub mod webhook;
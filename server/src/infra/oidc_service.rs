@@ -0,0 +1,893 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use chrono::prelude::*;
+use hmac::{Hmac, NewMac};
+use jwt::SignWithKey;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument};
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{BackendHandler, BindRequest, LoginHandler},
+        types::{GroupDetails, OidcAuthorizationCode, OidcClient, OidcGroupClaim, User, UserId},
+    },
+    infra::{
+        auth_service::{check_if_token_is_valid, check_user_can_log_in, has_password_expired},
+        configuration::OidcOptions,
+        tcp_backend_handler::*,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+
+/// How long the client has to exchange the authorization code for the tokens.
+const CODE_VALIDITY_SECONDS: i64 = 60;
+/// Distinguishes the access tokens from the other JWTs signed with the same keys.
+const ACCESS_TOKEN_USE: &str = "oidc_access";
+
+/// The parameters of the authorization request, passed along by the login form.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct AuthorizationRequest {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    #[serde(default)]
+    scope: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginForm {
+    #[serde(flatten)]
+    request: AuthorizationRequest,
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenRequest {
+    grant_type: String,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    redirect_uri: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    code_verifier: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AccessTokenClaims {
+    iss: String,
+    sub: String,
+    /// The client the token was issued to.
+    aud: String,
+    exp: i64,
+    iat: i64,
+    scope: String,
+    token_use: String,
+}
+
+fn check_oidc_enabled(oidc_options: &OidcOptions) -> TcpResult<()> {
+    if !oidc_options.enabled {
+        return Err(TcpError::BadRequest(
+            "The OpenID Connect provider is not enabled".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn get_issuer<Backend>(data: &AppState<Backend>) -> String {
+    data.server_url.trim_end_matches('/').to_owned()
+}
+
+async fn get_discovery<Backend>(data: web::Data<AppState<Backend>>) -> TcpResult<HttpResponse> {
+    check_oidc_enabled(&data.oidc_options)?;
+    let issuer = get_issuer(&data);
+    Ok(HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/oidc/authorize", issuer),
+        "token_endpoint": format!("{}/oidc/token", issuer),
+        "userinfo_endpoint": format!("{}/oidc/userinfo", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["HS256"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+        "code_challenge_methods_supported": ["S256", "plain"],
+        "scopes_supported": ["openid", "profile", "email", "groups"],
+        "claims_supported": [
+            "sub", "iss", "aud", "exp", "iat", "nonce", "name", "preferred_username",
+            "given_name", "family_name", "email", "groups",
+        ],
+    })))
+}
+
+async fn get_discovery_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    get_discovery(data)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+// An unknown client or redirect URI is reported to the user: redirecting them could send them
+// anywhere.
+async fn get_client<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    request: &AuthorizationRequest,
+) -> TcpResult<OidcClient> {
+    let client = match backend_handler.get_oidc_client(&request.client_id).await {
+        Err(DomainError::EntityNotFound(_)) => {
+            return Err(TcpError::BadRequest(format!(
+                "Unknown client '{}'",
+                request.client_id
+            )))
+        }
+        client => client?,
+    };
+    if !client.redirect_uris.contains(&request.redirect_uri) {
+        return Err(TcpError::BadRequest(format!(
+            "Invalid redirect URI '{}'",
+            request.redirect_uri
+        )));
+    }
+    Ok(client)
+}
+
+/// Checks the rest of the request, returning the error code and description sent to the client.
+fn check_authorization_request(
+    request: &AuthorizationRequest,
+) -> std::result::Result<(), (&'static str, &'static str)> {
+    if request.response_type != "code" {
+        return Err((
+            "unsupported_response_type",
+            "Only the authorization code flow is supported",
+        ));
+    }
+    if !request.scope.split_whitespace().any(|s| s == "openid") {
+        return Err(("invalid_scope", "The \"openid\" scope is required"));
+    }
+    if !matches!(
+        request.code_challenge_method.as_deref(),
+        None | Some("S256") | Some("plain")
+    ) {
+        return Err(("invalid_request", "Unsupported code challenge method"));
+    }
+    Ok(())
+}
+
+fn redirect_to_client(request: &AuthorizationRequest, params: &[(&str, &str)]) -> HttpResponse {
+    let mut params = params.to_vec();
+    if let Some(state) = &request.state {
+        params.push(("state", state));
+    }
+    let separator = if request.redirect_uri.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    HttpResponse::Found()
+        .insert_header((
+            header::LOCATION,
+            format!(
+                "{}{}{}",
+                request.redirect_uri,
+                separator,
+                serde_urlencoded::to_string(&params).unwrap()
+            ),
+        ))
+        .finish()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// The session cookie is not sent along with the redirection from another site, so the users
+// usually log in again through this form. It posts the authorization request back with the
+// credentials.
+fn login_page(
+    request: &AuthorizationRequest,
+    client: &OidcClient,
+    error: Option<&str>,
+) -> HttpResponse {
+    let hidden_fields: String = match serde_json::to_value(request).unwrap() {
+        Value::Object(fields) => fields
+            .iter()
+            .filter_map(|(name, value)| value.as_str().map(|value| (name, value)))
+            .map(|(name, value)| {
+                format!(
+                    r#"<input type="hidden" name="{}" value="{}">"#,
+                    html_escape(name),
+                    html_escape(value)
+                )
+            })
+            .collect(),
+        _ => unreachable!(),
+    };
+    let error = error
+        .map(|e| format!(r#"<p class="error">{}</p>"#, html_escape(e)))
+        .unwrap_or_default();
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>LLDAP login</title>
+<style>
+body {{ font-family: sans-serif; max-width: 20em; margin: 4em auto; }}
+input {{ display: block; width: 100%; margin-bottom: 1em; }}
+.error {{ color: #b00; }}
+</style>
+</head>
+<body>
+<h1>Log in to {}</h1>
+{}
+<form method="post" action="authorize">
+{}
+<label for="username">Username</label>
+<input type="text" id="username" name="username" autocomplete="username" required autofocus>
+<label for="password">Password</label>
+<input type="password" id="password" name="password" autocomplete="current-password" required>
+<input type="submit" value="Log in">
+</form>
+</body>
+</html>
+"#,
+        html_escape(&client.display_name),
+        error,
+        hidden_fields
+    );
+    let status = if error.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::UNAUTHORIZED
+    };
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::X_FRAME_OPTIONS, "DENY"))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(body)
+}
+
+async fn redirect_with_code<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    request: &AuthorizationRequest,
+    user_id: UserId,
+) -> TcpResult<HttpResponse> {
+    debug!(?user_id, client_id = ?request.client_id, "Issuing an authorization code");
+    let code = backend_handler
+        .create_oidc_authorization_code(OidcAuthorizationCode {
+            client_id: request.client_id.clone(),
+            user_id,
+            redirect_uri: request.redirect_uri.clone(),
+            scope: request.scope.clone(),
+            nonce: request.nonce.clone(),
+            code_challenge: request.code_challenge.clone(),
+            code_challenge_method: request.code_challenge_method.clone(),
+            expiry_date: Utc::now() + chrono::Duration::seconds(CODE_VALIDITY_SECONDS),
+        })
+        .await?;
+    Ok(redirect_to_client(request, &[("code", &code)]))
+}
+
+/// Why the authenticated user cannot log in to the client, if they cannot.
+async fn login_refusal<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user_id: &UserId,
+) -> TcpResult<Option<&'static str>> {
+    if check_user_can_log_in(backend_handler, user_id)
+        .await
+        .is_err()
+    {
+        return Ok(Some("The account is disabled or expired"));
+    }
+    // The new password is chosen in the LLDAP web UI.
+    if has_password_expired(backend_handler, user_id).await? {
+        return Ok(Some(
+            "The password has expired, log in to LLDAP to choose a new one",
+        ));
+    }
+    Ok(None)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_authorize<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Query<AuthorizationRequest>,
+    http_request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_oidc_enabled(&data.oidc_options)?;
+    let client = get_client(&data.backend_handler, &request).await?;
+    if let Err((error, description)) = check_authorization_request(&request) {
+        return Ok(redirect_to_client(
+            &request,
+            &[("error", error), ("error_description", description)],
+        ));
+    }
+    // Skip the login form if the user is already logged in to LLDAP.
+    if let Some(validation_result) = http_request
+        .cookie("token")
+        .and_then(|token| check_if_token_is_valid(&data, token.value()).ok())
+    {
+        if login_refusal(&data.backend_handler, &validation_result.user)
+            .await?
+            .is_none()
+        {
+            return redirect_with_code(&data.backend_handler, &request, validation_result.user)
+                .await;
+        }
+    }
+    Ok(login_page(&request, &client, None))
+}
+
+async fn get_authorize_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Query<AuthorizationRequest>,
+    http_request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_authorize(data, request, http_request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_authorize<Backend>(
+    data: web::Data<AppState<Backend>>,
+    form: web::Form<LoginForm>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
+{
+    check_oidc_enabled(&data.oidc_options)?;
+    let LoginForm {
+        request,
+        username,
+        password,
+    } = form.into_inner();
+    let client = get_client(&data.backend_handler, &request).await?;
+    if let Err((error, description)) = check_authorization_request(&request) {
+        return Ok(redirect_to_client(
+            &request,
+            &[("error", error), ("error_description", description)],
+        ));
+    }
+    let user_id = UserId::new(&username);
+    debug!(?user_id);
+    let login = data
+        .backend_handler
+        .bind(BindRequest {
+            name: user_id.clone(),
+            password,
+        })
+        .await;
+    match login {
+        Ok(()) => {}
+        Err(DomainError::AuthenticationError(_)) => {
+            return Ok(login_page(
+                &request,
+                &client,
+                Some("Invalid username or password"),
+            ))
+        }
+        Err(DomainError::AccountLocked(_)) => {
+            return Ok(login_page(
+                &request,
+                &client,
+                Some("The account is temporarily locked"),
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    }
+    if let Some(refusal) = login_refusal(&data.backend_handler, &user_id).await? {
+        return Ok(login_page(&request, &client, Some(refusal)));
+    }
+    redirect_with_code(&data.backend_handler, &request, user_id).await
+}
+
+async fn post_authorize_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    form: web::Form<LoginForm>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
+{
+    post_authorize(data, form)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+fn verify_pkce(
+    code_challenge: Option<&str>,
+    code_challenge_method: Option<&str>,
+    code_verifier: Option<&str>,
+) -> bool {
+    match (code_challenge, code_verifier) {
+        (None, None) => true,
+        (Some(challenge), Some(verifier)) => match code_challenge_method.unwrap_or("plain") {
+            "S256" => {
+                base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+                    == challenge
+            }
+            "plain" => verifier == challenge,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The client credentials, either from the basic authentication header or from the form.
+fn get_client_credentials(
+    http_request: &HttpRequest,
+    request: &TokenRequest,
+) -> Option<(String, String)> {
+    if let Some(basic) = http_request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+    {
+        let decoded = String::from_utf8(base64::decode(basic).ok()?).ok()?;
+        let (client_id, client_secret) = decoded.split_once(':')?;
+        return Some((client_id.to_owned(), client_secret.to_owned()));
+    }
+    Some((request.client_id.clone()?, request.client_secret.clone()?))
+}
+
+fn token_error(status: StatusCode, error: &str, description: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(json!({
+            "error": error,
+            "error_description": description,
+        }))
+}
+
+fn invalid_client() -> HttpResponse {
+    token_error(
+        StatusCode::UNAUTHORIZED,
+        "invalid_client",
+        "Invalid client credentials",
+    )
+}
+
+fn invalid_grant(description: &str) -> HttpResponse {
+    token_error(StatusCode::BAD_REQUEST, "invalid_grant", description)
+}
+
+/// The claims about the user, depending on the requested scopes. The claims mapped from the
+/// groups are single values, or arrays if several groups of the user set the same claim.
+fn build_claims(
+    user: &User,
+    groups: &HashSet<GroupDetails>,
+    group_claims: &[OidcGroupClaim],
+    scope: &str,
+) -> Map<String, Value> {
+    let scopes: HashSet<&str> = scope.split_whitespace().collect();
+    let mut claims = Map::new();
+    claims.insert("sub".to_owned(), json!(user.user_id.as_str()));
+    if scopes.contains("profile") {
+        claims.insert(
+            "preferred_username".to_owned(),
+            json!(user.user_id.as_str()),
+        );
+        claims.insert(
+            "name".to_owned(),
+            json!(user
+                .display_name
+                .as_deref()
+                .unwrap_or_else(|| user.user_id.as_str())),
+        );
+        if let Some(first_name) = user.first_name.as_deref().filter(|n| !n.is_empty()) {
+            claims.insert("given_name".to_owned(), json!(first_name));
+        }
+        if let Some(last_name) = user.last_name.as_deref().filter(|n| !n.is_empty()) {
+            claims.insert("family_name".to_owned(), json!(last_name));
+        }
+    }
+    if scopes.contains("email") {
+        claims.insert("email".to_owned(), json!(user.email));
+    }
+    if scopes.contains("groups") {
+        let group_names: BTreeSet<&str> = groups.iter().map(|g| g.display_name.as_str()).collect();
+        claims.insert("groups".to_owned(), json!(group_names));
+    }
+    let group_ids: HashSet<_> = groups.iter().map(|g| g.group_id).collect();
+    let mut mapped_claims = BTreeMap::<&str, BTreeSet<&str>>::new();
+    for group_claim in group_claims
+        .iter()
+        .filter(|c| group_ids.contains(&c.group_id))
+    {
+        mapped_claims
+            .entry(&group_claim.claim)
+            .or_default()
+            .insert(&group_claim.value);
+    }
+    for (claim, values) in mapped_claims {
+        // The standard claims cannot be overridden.
+        if claims.contains_key(claim) {
+            continue;
+        }
+        let value = match values.len() {
+            1 => json!(values.into_iter().next().unwrap()),
+            _ => json!(values),
+        };
+        claims.insert(claim.to_owned(), value);
+    }
+    claims
+}
+
+async fn get_user_claims<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    client_id: &str,
+    user_id: &UserId,
+    scope: &str,
+) -> TcpResult<Map<String, Value>> {
+    let user = backend_handler
+        .get_user_details_from_primary(user_id)
+        .await?;
+    let groups = backend_handler
+        .get_user_groups_from_primary(user_id)
+        .await?;
+    let group_claims = backend_handler.list_oidc_group_claims(client_id).await?;
+    Ok(build_claims(&user, &groups, &group_claims, scope))
+}
+
+// The ID tokens are signed with the client secret, so that the clients can verify them without
+// fetching any key.
+fn create_id_token(
+    client: &OidcClient,
+    mut claims: Map<String, Value>,
+    issuer: &str,
+    issued_at: i64,
+    expiry: i64,
+    nonce: Option<String>,
+) -> String {
+    claims.insert("iss".to_owned(), json!(issuer));
+    claims.insert("aud".to_owned(), json!(client.client_id));
+    claims.insert("iat".to_owned(), json!(issued_at));
+    claims.insert("exp".to_owned(), json!(expiry));
+    if let Some(nonce) = nonce {
+        claims.insert("nonce".to_owned(), json!(nonce));
+    }
+    let key: Hmac<Sha256> = Hmac::new_varkey(client.client_secret.as_bytes()).unwrap();
+    claims.sign_with_key(&key).unwrap()
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_token<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Form<TokenRequest>,
+    http_request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_oidc_enabled(&data.oidc_options)?;
+    if request.grant_type != "authorization_code" {
+        return Ok(token_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "Only the authorization code grant is supported",
+        ));
+    }
+    let (client_id, client_secret) = match get_client_credentials(&http_request, &request) {
+        Some(credentials) => credentials,
+        None => return Ok(invalid_client()),
+    };
+    debug!(?client_id);
+    let client = match data.backend_handler.get_oidc_client(&client_id).await {
+        Err(DomainError::EntityNotFound(_)) => return Ok(invalid_client()),
+        client => client?,
+    };
+    if orion::util::secure_cmp(client.client_secret.as_bytes(), client_secret.as_bytes()).is_err() {
+        return Ok(invalid_client());
+    }
+    let code = match data
+        .backend_handler
+        .consume_oidc_authorization_code(&request.code)
+        .await
+    {
+        Err(DomainError::EntityNotFound(_)) => {
+            return Ok(invalid_grant("Invalid or expired authorization code"))
+        }
+        code => code?,
+    };
+    if code.client_id != client.client_id || code.redirect_uri != request.redirect_uri {
+        return Ok(invalid_grant(
+            "The authorization code was issued to another client or redirect URI",
+        ));
+    }
+    if !verify_pkce(
+        code.code_challenge.as_deref(),
+        code.code_challenge_method.as_deref(),
+        request.code_verifier.as_deref(),
+    ) {
+        return Ok(invalid_grant("Invalid code verifier"));
+    }
+    if check_user_can_log_in(&data.backend_handler, &code.user_id)
+        .await
+        .is_err()
+    {
+        return Ok(invalid_grant("The account is disabled or expired"));
+    }
+    let claims = get_user_claims(
+        &data.backend_handler,
+        &client.client_id,
+        &code.user_id,
+        &code.scope,
+    )
+    .await?;
+    let issuer = get_issuer(&data);
+    let issued_at = Utc::now().timestamp();
+    let lifetime = data.oidc_options.token_lifetime_seconds as i64;
+    let access_token = data.jwt_keys.sign_claims(AccessTokenClaims {
+        iss: issuer.clone(),
+        sub: code.user_id.to_string(),
+        aud: client.client_id.clone(),
+        exp: issued_at + lifetime,
+        iat: issued_at,
+        scope: code.scope.clone(),
+        token_use: ACCESS_TOKEN_USE.to_owned(),
+    });
+    let id_token = create_id_token(
+        &client,
+        claims,
+        &issuer,
+        issued_at,
+        issued_at + lifetime,
+        code.nonce,
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": lifetime,
+            "id_token": id_token,
+            "scope": code.scope,
+        })))
+}
+
+async fn post_token_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Form<TokenRequest>,
+    http_request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    post_token(data, request, http_request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_userinfo<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_oidc_enabled(&data.oidc_options)?;
+    let token = data
+        .jwt_keys
+        .verify_claims::<AccessTokenClaims>(credentials.token())
+        .filter(|token| token.claims().token_use == ACCESS_TOKEN_USE)
+        .ok_or_else(|| TcpError::UnauthorizedError("Invalid access token".to_string()))?;
+    let claims = token.claims();
+    if claims.exp < Utc::now().timestamp() {
+        return Err(TcpError::UnauthorizedError(
+            "Expired access token".to_string(),
+        ));
+    }
+    let user_id = UserId::new(&claims.sub);
+    debug!(?user_id, client_id = ?claims.aud);
+    check_user_can_log_in(&data.backend_handler, &user_id).await?;
+    Ok(HttpResponse::Ok()
+        .json(get_user_claims(&data.backend_handler, &claims.aud, &user_id, &claims.scope).await?))
+}
+
+async fn get_userinfo_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_userinfo(data, credentials)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// The discovery document, served at the root of the server.
+pub fn configure_discovery<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    cfg.service(
+        web::resource("/.well-known/openid-configuration")
+            .route(web::get().to(get_discovery_handler::<Backend>)),
+    );
+}
+
+/// The endpoints of the OpenID Connect provider, under "/oidc".
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
+{
+    cfg.service(
+        web::resource("/authorize")
+            .route(web::get().to(get_authorize_handler::<Backend>))
+            .route(web::post().to(post_authorize_handler::<Backend>)),
+    )
+    .service(web::resource("/token").route(web::post().to(post_token_handler::<Backend>)))
+    .service(
+        web::resource("/userinfo")
+            .route(web::get().to(get_userinfo_handler::<Backend>))
+            .route(web::post().to(get_userinfo_handler::<Backend>)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::GroupId;
+
+    #[test]
+    fn test_verify_pkce() {
+        // From RFC 7636, appendix B.
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        assert!(verify_pkce(Some(challenge), Some("S256"), Some(verifier)));
+        assert!(!verify_pkce(Some(challenge), Some("S256"), Some(challenge)));
+        assert!(verify_pkce(Some(verifier), None, Some(verifier)));
+        assert!(!verify_pkce(Some(challenge), Some("S256"), None));
+        assert!(!verify_pkce(None, None, Some(verifier)));
+        assert!(verify_pkce(None, None, None));
+    }
+
+    #[test]
+    fn test_build_claims() {
+        let user = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".to_owned(),
+            display_name: Some("Bob Bobbersson".to_owned()),
+            first_name: Some("Bob".to_owned()),
+            last_name: Some(String::new()),
+            ..Default::default()
+        };
+        let make_group = |id, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.to_owned(),
+            creation_date: Utc::now(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            gid_number: None,
+        };
+        let groups = HashSet::from([make_group(1, "admins"), make_group(2, "editors")]);
+        let make_claim = |group_id, claim: &str, value: &str| OidcGroupClaim {
+            client_id: "wiki".to_owned(),
+            group_id: GroupId(group_id),
+            claim: claim.to_owned(),
+            value: value.to_owned(),
+        };
+        let group_claims = vec![
+            make_claim(1, "role", "admin"),
+            make_claim(2, "role", "editor"),
+            make_claim(2, "team", "docs"),
+            make_claim(3, "team", "ops"),
+            make_claim(1, "sub", "root"),
+        ];
+        let claims = build_claims(&user, &groups, &group_claims, "openid profile groups");
+        assert_eq!(
+            Value::Object(claims),
+            json!({
+                "sub": "bob",
+                "preferred_username": "bob",
+                "name": "Bob Bobbersson",
+                "given_name": "Bob",
+                "groups": ["admins", "editors"],
+                "role": ["admin", "editor"],
+                "team": "docs",
+            })
+        );
+        let claims = build_claims(&user, &HashSet::new(), &group_claims, "openid email");
+        assert_eq!(
+            Value::Object(claims),
+            json!({"sub": "bob", "email": "bob@example.com"})
+        );
+    }
+
+    #[test]
+    fn test_check_authorization_request() {
+        let request = AuthorizationRequest {
+            response_type: "code".to_owned(),
+            client_id: "wiki".to_owned(),
+            redirect_uri: "https://wiki.example.com/callback".to_owned(),
+            scope: "openid profile".to_owned(),
+            state: Some("xyz".to_owned()),
+            nonce: None,
+            code_challenge: None,
+            code_challenge_method: None,
+        };
+        assert!(check_authorization_request(&request).is_ok());
+        assert_eq!(
+            check_authorization_request(&AuthorizationRequest {
+                scope: "profile".to_owned(),
+                ..request.clone()
+            })
+            .unwrap_err()
+            .0,
+            "invalid_scope"
+        );
+        assert_eq!(
+            check_authorization_request(&AuthorizationRequest {
+                response_type: "token".to_owned(),
+                ..request.clone()
+            })
+            .unwrap_err()
+            .0,
+            "unsupported_response_type"
+        );
+        let response = redirect_to_client(&request, &[("code", "a b")]);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "https://wiki.example.com/callback?code=a+b&state=xyz"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_refusal() {
+        use crate::{
+            domain::handler::MockTestBackendHandler, infra::configuration::PasswordPolicyOptions,
+        };
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_password_policy()
+            .returning(|| PasswordPolicyOptions {
+                max_age_days: 30,
+                ..Default::default()
+            });
+        mock.expect_get_user_details().returning(|user_id| {
+            let days = if user_id.as_str() == "bob" { 10 } else { 31 };
+            Ok(User {
+                user_id: user_id.clone(),
+                enabled: user_id.as_str() != "john",
+                password_modified_date: Some(Utc::now() - chrono::Duration::days(days)),
+                ..Default::default()
+            })
+        });
+        assert_eq!(
+            login_refusal(&mock, &UserId::new("bob")).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            login_refusal(&mock, &UserId::new("patrick")).await.unwrap(),
+            Some("The password has expired, log in to LLDAP to choose a new one")
+        );
+        assert_eq!(
+            login_refusal(&mock, &UserId::new("john")).await.unwrap(),
+            Some("The account is disabled or expired")
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Parsing of the HAProxy PROXY protocol (version 2) header, that TCP load balancers send at the
+//! start of the connection to forward the address of the original client.
+
+use anyhow::{bail, Context, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+const COMMAND_LOCAL: u8 = 0x0;
+const COMMAND_PROXY: u8 = 0x1;
+
+const FAMILY_UNSPEC: u8 = 0x0;
+const FAMILY_INET: u8 = 0x1;
+const FAMILY_INET6: u8 = 0x2;
+const FAMILY_UNIX: u8 = 0x3;
+
+/// Reads the PROXY protocol header at the start of the stream, leaving the stream positioned at
+/// the first byte of the proxied data.
+///
+/// Returns the address of the original client, or `None` if the proxy didn't forward one (e.g.
+/// for its own health checks).
+pub async fn read_proxy_header<Stream: AsyncRead + Unpin>(
+    stream: &mut Stream,
+) -> Result<Option<SocketAddr>> {
+    let mut header = [0u8; 16];
+    stream
+        .read_exact(&mut header)
+        .await
+        .context("while reading the header")?;
+    if &header[..12] != SIGNATURE {
+        bail!("Missing PROXY protocol v2 signature");
+    }
+    let version = header[12] >> 4;
+    if version != 2 {
+        bail!("Unsupported PROXY protocol version: {}", version);
+    }
+    let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+    let mut payload = vec![0u8; length];
+    stream
+        .read_exact(&mut payload)
+        .await
+        .context("while reading the addresses")?;
+    match header[12] & 0x0F {
+        COMMAND_LOCAL => Ok(None),
+        COMMAND_PROXY => parse_source_address(header[13] >> 4, &payload),
+        command => bail!("Unsupported PROXY protocol command: {}", command),
+    }
+}
+
+fn parse_source_address(family: u8, payload: &[u8]) -> Result<Option<SocketAddr>> {
+    let port = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+    match family {
+        FAMILY_INET => {
+            if payload.len() < 12 {
+                bail!("Truncated PROXY protocol IPv4 addresses");
+            }
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port(&payload[8..10]))))
+        }
+        FAMILY_INET6 => {
+            if payload.len() < 36 {
+                bail!("Truncated PROXY protocol IPv6 addresses");
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&payload[..16]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                port(&payload[32..34]),
+            )))
+        }
+        // Unix socket paths are not useful to identify the client.
+        FAMILY_UNSPEC | FAMILY_UNIX => Ok(None),
+        family => bail!("Unsupported PROXY protocol address family: {}", family),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push((family << 4) | 0x1);
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn test_ipv4() {
+        let mut data = make_header(
+            COMMAND_PROXY,
+            FAMILY_INET,
+            &[192, 168, 1, 2, 10, 0, 0, 1, 0x30, 0x39, 0x0F, 0x32],
+        );
+        data.extend_from_slice(b"ldap");
+        let mut stream = data.as_slice();
+        assert_eq!(
+            read_proxy_header(&mut stream).await.unwrap(),
+            Some("192.168.1.2:12345".parse().unwrap())
+        );
+        assert_eq!(stream, b"ldap");
+    }
+
+    #[tokio::test]
+    async fn test_ipv6() {
+        let mut addresses = vec![0u8; 36];
+        addresses[15] = 1;
+        addresses[31] = 2;
+        addresses[32..34].copy_from_slice(&389u16.to_be_bytes());
+        let data = make_header(COMMAND_PROXY, FAMILY_INET6, &addresses);
+        assert_eq!(
+            read_proxy_header(&mut data.as_slice()).await.unwrap(),
+            Some("[::1]:389".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_command() {
+        let data = make_header(COMMAND_LOCAL, FAMILY_UNSPEC, &[]);
+        assert_eq!(read_proxy_header(&mut data.as_slice()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_header() {
+        let mut data = b"PROXY TCP4 192.168.1.2 10.0.0.1 12345 389\r\n".to_vec();
+        assert!(read_proxy_header(&mut data.as_slice()).await.is_err());
+        data = make_header(COMMAND_PROXY, FAMILY_INET, &[192, 168, 1, 2]);
+        assert!(read_proxy_header(&mut data.as_slice()).await.is_err());
+    }
+}
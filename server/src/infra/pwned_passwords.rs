@@ -0,0 +1,77 @@
+//! Check of the passwords against the HaveIBeenPwned corpus of breached passwords, through its
+//! k-anonymity range API: only the first 5 characters of the SHA-1 of the password are sent.
+
+use anyhow::Result;
+use sha1::{Digest, Sha1};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The uppercase hex SHA-1 of the password, split into the prefix sent to the API and the suffix
+// looked up in the response.
+fn split_hash(password: &str) -> (String, String) {
+    let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(5);
+    (prefix.to_owned(), suffix.to_owned())
+}
+
+// The response lists the "SUFFIX:COUNT" of the breached passwords with the prefix. The padding
+// entries added to hide the size of the response have a count of 0.
+fn response_contains(body: &str, suffix: &str) -> bool {
+    body.lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .any(|(candidate, count)| {
+            candidate.eq_ignore_ascii_case(suffix) && count.trim().parse::<u64>().unwrap_or(0) > 0
+        })
+}
+
+/// Whether the password appears in a known data breach. The URL is the range endpoint, e.g.
+/// "https://api.pwnedpasswords.com/range/", to which the hash prefix is appended.
+pub async fn is_pwned(url: &str, password: &str) -> Result<bool> {
+    let (prefix, suffix) = split_hash(password);
+    let body = reqwest::Client::new()
+        .get(format!("{}{}", url, prefix))
+        .header("Add-Padding", "true")
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(response_contains(&body, &suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_hash() {
+        assert_eq!(
+            split_hash("password"),
+            (
+                "5BAA6".to_owned(),
+                "1E4C9B93F3F0682250B6CF8331B7EE68FD8".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_response_contains() {
+        let body = "1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n\
+                    0018A45C4D1DEF81644B54AB7F969B88D65:0\r\n";
+        assert!(response_contains(
+            body,
+            "1E4C9B93F3F0682250B6CF8331B7EE68FD8"
+        ));
+        // Padding entry.
+        assert!(!response_contains(
+            body,
+            "0018A45C4D1DEF81644B54AB7F969B88D65"
+        ));
+        assert!(!response_contains(
+            body,
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"
+        ));
+    }
+}
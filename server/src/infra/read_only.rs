@@ -0,0 +1,158 @@
+//! The read-only mode, for the migrations and the backups: the searches and the logins still
+//! work, but the changes to the directory are rejected until an admin turns it off.
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    web, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use std::{
+    pin::Pin,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+/// The error returned for the changes rejected in read-only mode.
+pub const READ_ONLY_ERROR: &str = "The server is in read-only mode for maintenance";
+
+/// The GraphQL mutations still allowed in read-only mode.
+pub const ALLOWED_MUTATIONS: &[&str] = &["setReadOnlyMode"];
+
+/// The methods that don't change anything, still allowed by [`ReadOnlyGuardFactory`].
+static READ_METHODS: [Method; 2] = [Method::GET, Method::HEAD];
+
+/// Shared by the LDAP and HTTP servers, and toggled at runtime.
+#[derive(Clone, Debug, Default)]
+pub struct ReadOnlyMode(Arc<AtomicBool>);
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed)
+    }
+}
+
+/// Rejects the requests other than GET and HEAD in read-only mode, for the endpoints that change
+/// the directory. The mode is read from the `web::Data<ReadOnlyMode>` of the application.
+pub struct ReadOnlyGuardFactory;
+
+/// Like [`ReadOnlyGuardFactory`], but also rejects the GET requests, for the endpoints that change
+/// the directory despite their method.
+pub struct ReadOnlyStrictGuardFactory;
+
+impl<S> Transform<S, ServiceRequest> for ReadOnlyGuardFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = ReadOnlyGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ReadOnlyGuardMiddleware {
+            service: Rc::new(service),
+            allowed_methods: &READ_METHODS,
+        })
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for ReadOnlyStrictGuardFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = ReadOnlyGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ReadOnlyGuardMiddleware {
+            service: Rc::new(service),
+            allowed_methods: &[],
+        })
+    }
+}
+
+pub struct ReadOnlyGuardMiddleware<S> {
+    service: Rc<S>,
+    /// The methods still allowed in read-only mode.
+    allowed_methods: &'static [Method],
+}
+
+impl<S> Service<ServiceRequest> for ReadOnlyGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn core::future::Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let read_only = req
+            .app_data::<web::Data<ReadOnlyMode>>()
+            .map(|mode| mode.is_enabled())
+            .unwrap_or(false);
+        if read_only && !self.allowed_methods.contains(req.method()) {
+            return Box::pin(async move {
+                Ok(req.into_response(HttpResponse::ServiceUnavailable().body(READ_ONLY_ERROR)))
+            });
+        }
+        let service = self.service.clone();
+        Box::pin(async move { service.call(req).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, App};
+
+    #[actix_rt::test]
+    async fn test_read_only_guard() {
+        let mode = ReadOnlyMode::new(true);
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(mode.clone())).service(
+                web::resource("/users")
+                    .wrap(ReadOnlyGuardFactory)
+                    .to(|| async { HttpResponse::Ok().finish() }),
+            ),
+        )
+        .await;
+        let get = test::TestRequest::get().uri("/users").to_request();
+        assert_eq!(test::call_service(&app, get).await.status(), StatusCode::OK);
+        let post = test::TestRequest::post().uri("/users").to_request();
+        assert_eq!(
+            test::call_service(&app, post).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        mode.set(false);
+        let post = test::TestRequest::post().uri("/users").to_request();
+        assert_eq!(
+            test::call_service(&app, post).await.status(),
+            StatusCode::OK
+        );
+    }
+}
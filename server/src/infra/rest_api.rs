@@ -0,0 +1,630 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{
+            BackendHandler, CreateUserRequest, GroupOrder, GroupRequestFilter, UpdateGroupRequest,
+            UpdateUserRequest,
+        },
+        types::{AuditSource, DateTime, Group, GroupDetails, GroupId, User, UserId},
+    },
+    infra::{
+        auth_service::{check_if_bearer_is_valid, ValidationResults},
+        read_only::ReadOnlyGuardFactory,
+        tcp_server::{error_to_http_response, AppState, TcpError},
+    },
+};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UserResponse {
+    id: String,
+    email: String,
+    display_name: String,
+    first_name: String,
+    last_name: String,
+    creation_date: DateTime,
+    modified_date: DateTime,
+    uuid: String,
+    enabled: bool,
+    /// Only set when the caller can see the groups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<Vec<GroupSummary>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GroupSummary {
+    id: i32,
+    display_name: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GroupResponse {
+    id: i32,
+    display_name: String,
+    creation_date: DateTime,
+    modified_date: DateTime,
+    uuid: String,
+    /// The IDs of the members.
+    users: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CreateUserBody {
+    id: String,
+    email: String,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UpdateUserBody {
+    email: Option<String>,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GroupBody {
+    display_name: String,
+}
+
+impl UserResponse {
+    fn new(user: User, groups: Option<Vec<GroupDetails>>) -> Self {
+        Self {
+            id: user.user_id.to_string(),
+            email: user.email,
+            display_name: user.display_name.unwrap_or_default(),
+            first_name: user.first_name.unwrap_or_default(),
+            last_name: user.last_name.unwrap_or_default(),
+            creation_date: user.creation_date,
+            modified_date: user.modified_date,
+            uuid: user.uuid.into_string(),
+            enabled: user.enabled,
+            groups: groups.map(|groups| {
+                let mut groups: Vec<_> = groups
+                    .into_iter()
+                    .map(|g| GroupSummary {
+                        id: g.group_id.0,
+                        display_name: g.display_name,
+                    })
+                    .collect();
+                groups.sort_by_key(|g| g.id);
+                groups
+            }),
+        }
+    }
+}
+
+impl From<Group> for GroupResponse {
+    fn from(group: Group) -> Self {
+        Self {
+            id: group.id.0,
+            display_name: group.display_name,
+            creation_date: group.creation_date,
+            modified_date: group.modified_date,
+            uuid: group.uuid.into_string(),
+            users: group.users.into_iter().map(UserId::into_string).collect(),
+        }
+    }
+}
+
+/// Responds to the errors like the other endpoints, except that missing entries are reported as
+/// such.
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+struct RestError {
+    status: StatusCode,
+    message: String,
+}
+
+impl From<TcpError> for RestError {
+    fn from(error: TcpError) -> Self {
+        let message = error.to_string();
+        let status = match error {
+            TcpError::DomainError(DomainError::EntityNotFound(_)) => StatusCode::NOT_FOUND,
+            error => error_to_http_response(error).status(),
+        };
+        Self { status, message }
+    }
+}
+
+impl From<DomainError> for RestError {
+    fn from(error: DomainError) -> Self {
+        TcpError::DomainError(error).into()
+    }
+}
+
+impl ResponseError for RestError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).body(self.message.clone())
+    }
+}
+
+type RestResult<T> = std::result::Result<T, RestError>;
+
+fn unauthorized(message: &str) -> RestError {
+    TcpError::UnauthorizedError(message.to_owned()).into()
+}
+
+fn bad_request(message: &str) -> RestError {
+    TcpError::BadRequest(message.to_owned()).into()
+}
+
+async fn validate<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    credentials: &BearerAuth,
+) -> RestResult<ValidationResults> {
+    check_if_bearer_is_valid(data, credentials.token())
+        .await
+        .map_err(|e| unauthorized(&e.to_string()))
+}
+
+// Same rules as the GraphQL API: admins can manage all the users, user managers and the roles
+// managing a group only the ones that are not admins.
+async fn can_manage_user<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    user_id: &UserId,
+) -> RestResult<bool> {
+    if validation_result.is_admin() {
+        return Ok(true);
+    }
+    if !validation_result.can_manage_any_user() {
+        return Ok(false);
+    }
+    let user_groups = backend_handler
+        .get_user_groups_from_primary(user_id)
+        .await?;
+    Ok(validation_result.can_manage_user(&user_groups))
+}
+
+async fn can_manage_membership<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    user_id: &UserId,
+    group_id: GroupId,
+) -> RestResult<bool> {
+    if validation_result.is_admin() {
+        return Ok(true);
+    }
+    if group_id == GroupId(1) || !validation_result.can_manage_any_user() {
+        return Ok(false);
+    }
+    let user_groups = backend_handler
+        .get_user_groups_from_primary(user_id)
+        .await?;
+    Ok(validation_result.can_manage_membership(group_id, &user_groups))
+}
+
+async fn get_user_response<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    user_id: &UserId,
+) -> RestResult<UserResponse> {
+    let user = backend_handler.get_user_details(user_id).await?;
+    let groups = if validation_result.can_read_own_entries() {
+        Some(
+            backend_handler
+                .get_user_groups(user_id)
+                .await?
+                .into_iter()
+                .collect(),
+        )
+    } else {
+        None
+    };
+    Ok(UserResponse::new(user, groups))
+}
+
+async fn get_group_response<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    group_id: GroupId,
+) -> RestResult<GroupResponse> {
+    backend_handler
+        .list_groups(
+            Some(GroupRequestFilter::GroupId(group_id)),
+            GroupOrder::default(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .map(Into::into)
+        .ok_or_else(|| {
+            DomainError::EntityNotFound(format!("No group with ID {}", group_id.0)).into()
+        })
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_users<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    if !validation_result.is_admin_or_readonly() {
+        return Err(unauthorized("Unauthorized access to user list"));
+    }
+    let users: Vec<_> = data
+        .backend_handler
+        .list_users(None, true)
+        .await?
+        .into_iter()
+        .map(|u| UserResponse::new(u.user, u.groups))
+        .collect();
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    if !validation_result.can_read(&user_id) {
+        return Err(unauthorized("Unauthorized access to user data"));
+    }
+    Ok(HttpResponse::Ok()
+        .json(get_user_response(&data.backend_handler, &validation_result, &user_id).await?))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    body: web::Json<CreateUserBody>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let body = body.into_inner();
+    let user_id = UserId::new(&body.id);
+    debug!(?user_id);
+    if !validation_result.can_manage_users() {
+        return Err(unauthorized("Unauthorized user creation"));
+    }
+    data.backend_handler.check_user_id(&body.id)?;
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .create_user(CreateUserRequest {
+            user_id: user_id.clone(),
+            email: body.email,
+            display_name: body.display_name,
+            first_name: body.first_name,
+            last_name: body.last_name,
+            ..Default::default()
+        })
+        .await?;
+    Ok(HttpResponse::Created()
+        .json(get_user_response(&data.backend_handler, &validation_result, &user_id).await?))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn update_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+    body: web::Json<UpdateUserBody>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    if !validation_result.can_write(&user_id)
+        && !can_manage_user(&data.backend_handler, &validation_result, &user_id).await?
+    {
+        return Err(unauthorized("Unauthorized user update"));
+    }
+    let body = body.into_inner();
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .update_user(UpdateUserRequest {
+            user_id: user_id.clone(),
+            email: body.email,
+            display_name: body.display_name,
+            first_name: body.first_name,
+            last_name: body.last_name,
+            ..Default::default()
+        })
+        .await?;
+    Ok(HttpResponse::Ok()
+        .json(get_user_response(&data.backend_handler, &validation_result, &user_id).await?))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn delete_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    if !can_manage_user(&data.backend_handler, &validation_result, &user_id).await? {
+        return Err(unauthorized("Unauthorized user deletion"));
+    }
+    if validation_result.user == user_id {
+        return Err(bad_request("Cannot delete current user"));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .delete_user(&user_id)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn add_user_to_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    path: web::Path<(String, i32)>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let (user_id, group_id) = path.into_inner();
+    let (user_id, group_id) = (UserId::new(&user_id), GroupId(group_id));
+    debug!(?user_id, ?group_id);
+    if !can_manage_membership(
+        &data.backend_handler,
+        &validation_result,
+        &user_id,
+        group_id,
+    )
+    .await?
+    {
+        return Err(unauthorized("Unauthorized group membership modification"));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .add_user_to_group(&user_id, group_id)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn remove_user_from_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    path: web::Path<(String, i32)>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let (user_id, group_id) = path.into_inner();
+    let (user_id, group_id) = (UserId::new(&user_id), GroupId(group_id));
+    debug!(?user_id, ?group_id);
+    if !can_manage_membership(
+        &data.backend_handler,
+        &validation_result,
+        &user_id,
+        group_id,
+    )
+    .await?
+    {
+        return Err(unauthorized("Unauthorized group membership modification"));
+    }
+    if validation_result.user == user_id && group_id == GroupId(1) {
+        return Err(bad_request("Cannot remove admin rights for current user"));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .remove_user_from_group(&user_id, group_id)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_groups<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    if !validation_result.is_admin_or_readonly() {
+        return Err(unauthorized("Unauthorized access to group list"));
+    }
+    let groups: Vec<GroupResponse> = data
+        .backend_handler
+        .list_groups(None, GroupOrder::default())
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(HttpResponse::Ok().json(groups))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<i32>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let group_id = GroupId(group_id.into_inner());
+    debug!(?group_id);
+    if !validation_result.is_admin_or_readonly() {
+        return Err(unauthorized("Unauthorized access to group data"));
+    }
+    Ok(HttpResponse::Ok().json(get_group_response(&data.backend_handler, group_id).await?))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    body: web::Json<GroupBody>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    debug!(?body.display_name);
+    if !validation_result.is_admin() {
+        return Err(unauthorized("Unauthorized group creation"));
+    }
+    let group_id = data
+        .backend_handler_for(&validation_result, AuditSource::Rest)
+        .create_group(&body.display_name)
+        .await?;
+    Ok(HttpResponse::Created().json(get_group_response(&data.backend_handler, group_id).await?))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn update_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<i32>,
+    body: web::Json<GroupBody>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let group_id = GroupId(group_id.into_inner());
+    debug!(?group_id, ?body.display_name);
+    if !validation_result.is_admin() {
+        return Err(unauthorized("Unauthorized group update"));
+    }
+    if group_id == GroupId(1) {
+        return Err(bad_request("Cannot change admin group details"));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .update_group(UpdateGroupRequest {
+            group_id,
+            display_name: Some(body.display_name.clone()),
+            ..Default::default()
+        })
+        .await?;
+    Ok(HttpResponse::Ok().json(get_group_response(&data.backend_handler, group_id).await?))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn delete_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<i32>,
+) -> RestResult<HttpResponse> {
+    let validation_result = validate(&data, &credentials).await?;
+    let group_id = GroupId(group_id.into_inner());
+    debug!(?group_id);
+    if !validation_result.is_admin() {
+        return Err(unauthorized("Unauthorized group deletion"));
+    }
+    if group_id == GroupId(1) {
+        return Err(bad_request("Cannot delete admin group"));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Rest)
+        .delete_group(group_id)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// A plain REST API mirroring the main GraphQL queries and mutations, under "/api/v1", for the
+/// scripts and tools where GraphQL is awkward. It uses the same authentication and permissions.
+pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + 'static,
+{
+    cfg.service(
+        web::scope("/v1")
+            .wrap(ReadOnlyGuardFactory)
+            .service(
+                web::resource("/users")
+                    .route(web::get().to(list_users::<Backend>))
+                    .route(web::post().to(create_user::<Backend>)),
+            )
+            .service(
+                web::resource("/users/{user_id}")
+                    .route(web::get().to(get_user::<Backend>))
+                    .route(web::patch().to(update_user::<Backend>))
+                    .route(web::delete().to(delete_user::<Backend>)),
+            )
+            .service(
+                web::resource("/users/{user_id}/groups/{group_id}")
+                    .route(web::put().to(add_user_to_group::<Backend>))
+                    .route(web::delete().to(remove_user_from_group::<Backend>)),
+            )
+            .service(
+                web::resource("/groups")
+                    .route(web::get().to(list_groups::<Backend>))
+                    .route(web::post().to(create_group::<Backend>)),
+            )
+            .service(
+                web::resource("/groups/{group_id}")
+                    .route(web::get().to(get_group::<Backend>))
+                    .route(web::patch().to(update_group::<Backend>))
+                    .route(web::delete().to(delete_group::<Backend>)),
+            ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_user_response() {
+        let user = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".to_owned(),
+            display_name: Some("Bob".to_owned()),
+            creation_date: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+            modified_date: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+            uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+            enabled: true,
+            ..Default::default()
+        };
+        let groups = vec![
+            GroupDetails {
+                group_id: GroupId(3),
+                display_name: "ops".to_owned(),
+                creation_date: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+                uuid: uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                gid_number: None,
+            },
+            GroupDetails {
+                group_id: GroupId(2),
+                display_name: "dev".to_owned(),
+                creation_date: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+                uuid: uuid!("b1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                gid_number: None,
+            },
+        ];
+        assert_eq!(
+            serde_json::to_value(UserResponse::new(user.clone(), Some(groups))).unwrap(),
+            serde_json::json!({
+                "id": "bob",
+                "email": "bob@example.com",
+                "displayName": "Bob",
+                "firstName": "",
+                "lastName": "",
+                "creationDate": "1970-01-01T00:00:00Z",
+                "modifiedDate": "1970-01-01T00:00:00Z",
+                "uuid": "698e1d5f-7a40-3151-8745-b9b8a37839da",
+                "enabled": true,
+                "groups": [
+                    {"id": 2, "displayName": "dev"},
+                    {"id": 3, "displayName": "ops"},
+                ],
+            })
+        );
+        let response = serde_json::to_value(UserResponse::new(user, None)).unwrap();
+        assert!(response.get("groups").is_none());
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(
+            RestError::from(DomainError::EntityNotFound("bob".to_owned())).status_code(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            RestError::from(DomainError::InvalidRequest("bob".to_owned())).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            unauthorized("Unauthorized user creation").status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
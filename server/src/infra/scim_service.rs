@@ -0,0 +1,1420 @@
+use std::collections::HashSet;
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use secstr::SecUtf8;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{
+            BackendHandler, CreateUserRequest, GroupOrder, GroupRequestFilter, UpdateGroupRequest,
+            UpdateUserRequest, UserOrder, UserRequestFilter,
+        },
+        opaque_handler::OpaqueHandler,
+        sql_opaque_handler::register_password,
+        types::{AuditSource, DateTime, Group, GroupDetails, GroupId, User, UserColumn, UserId},
+    },
+    infra::{
+        auth_service::{check_if_bearer_is_valid, ValidationResults},
+        tcp_server::AppState,
+    },
+};
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+/// Page size when the client doesn't ask for one.
+const DEFAULT_COUNT: u64 = 100;
+
+/// An error, in the format of RFC 7644 section 3.12.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("{detail}")]
+struct ScimError {
+    status: StatusCode,
+    scim_type: Option<&'static str>,
+    detail: String,
+}
+
+impl ScimError {
+    fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            scim_type: None,
+            detail: detail.into(),
+        }
+    }
+
+    fn bad_request(scim_type: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            scim_type: Some(scim_type),
+            detail: detail.into(),
+        }
+    }
+
+    fn conflict(detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            scim_type: Some("uniqueness"),
+            detail: detail.into(),
+        }
+    }
+}
+
+impl ResponseError for ScimError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = json!(scim_type);
+        }
+        HttpResponse::build(self.status)
+            .content_type(SCIM_CONTENT_TYPE)
+            .body(body.to_string())
+    }
+}
+
+impl From<DomainError> for ScimError {
+    fn from(error: DomainError) -> Self {
+        let status = match error {
+            DomainError::EntityNotFound(_) => StatusCode::NOT_FOUND,
+            DomainError::InvalidRequest(_) | DomainError::WeakPassword { .. } => {
+                StatusCode::BAD_REQUEST
+            }
+            DomainError::LastAdmin(_) => StatusCode::CONFLICT,
+            DomainError::AuthenticationError(_)
+            | DomainError::AccountLocked(_)
+            | DomainError::AuthenticationProtocolError(_) => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+type ScimResult<T> = std::result::Result<T, ScimError>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimName {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    formatted: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    family_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ScimEmail {
+    value: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    type_: Option<String>,
+    #[serde(default)]
+    primary: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ScimMember {
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimMeta {
+    resource_type: &'static str,
+    created: DateTime,
+    last_modified: DateTime,
+    location: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimUser {
+    #[serde(default)]
+    schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    user_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<ScimName>,
+    #[serde(default)]
+    emails: Vec<ScimEmail>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    active: Option<bool>,
+    /// Only accepted on input, the passwords are never returned.
+    #[serde(default, skip_serializing)]
+    password: Option<String>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<ScimMember>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    meta: Option<ScimMeta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimGroup {
+    #[serde(default)]
+    schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    display_name: String,
+    #[serde(default)]
+    members: Vec<ScimMember>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    meta: Option<ScimMeta>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ListResponse<T> {
+    schemas: Vec<&'static str>,
+    total_results: u64,
+    start_index: u64,
+    items_per_page: u64,
+    #[serde(rename = "Resources")]
+    resources: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ListQuery {
+    filter: Option<String>,
+    start_index: Option<u64>,
+    count: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct PatchOperation {
+    op: String,
+    path: Option<String>,
+    value: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PatchRequest {
+    operations: Vec<PatchOperation>,
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> ScimResult<T> {
+    serde_json::from_slice(body)
+        .map_err(|e| ScimError::bad_request("invalidSyntax", format!("Invalid request: {}", e)))
+}
+
+fn scim_response<T: Serialize>(status: StatusCode, body: &T) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(SCIM_CONTENT_TYPE)
+        .body(serde_json::to_string(body).unwrap())
+}
+
+fn base_url<Backend>(data: &AppState<Backend>) -> String {
+    format!("{}/scim/v2", data.server_url.trim_end_matches('/'))
+}
+
+/// The provisioning clients read everything, and only the admins can modify the directory.
+async fn authorize<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    credentials: &BearerAuth,
+    write: bool,
+) -> ScimResult<ValidationResults> {
+    let validation_result = check_if_bearer_is_valid(data, credentials.token())
+        .await
+        .map_err(|e| ScimError::new(StatusCode::UNAUTHORIZED, e.to_string()))?;
+    let allowed = if write {
+        validation_result.is_admin()
+    } else {
+        validation_result.is_admin_or_readonly()
+    };
+    if !allowed {
+        return Err(ScimError::new(
+            StatusCode::FORBIDDEN,
+            "Insufficient permissions",
+        ));
+    }
+    Ok(validation_result)
+}
+
+/// Parses the `<attribute> eq "<value>"` filters, the only ones the provisioning clients use to
+/// find existing entries.
+fn parse_equality_filter(filter: &str) -> ScimResult<(String, String)> {
+    let invalid_filter =
+        || ScimError::bad_request("invalidFilter", format!("Unsupported filter: '{}'", filter));
+    let mut parts = filter.trim().splitn(3, ' ');
+    let attribute = parts.next().ok_or_else(invalid_filter)?;
+    let operator = parts.next().ok_or_else(invalid_filter)?;
+    let value = parts.next().ok_or_else(invalid_filter)?.trim();
+    if !operator.eq_ignore_ascii_case("eq") {
+        return Err(invalid_filter());
+    }
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(invalid_filter)?
+        .replace("\\\"", "\"");
+    Ok((attribute.to_ascii_lowercase(), value))
+}
+
+fn to_user_filter(filter: &str) -> ScimResult<UserRequestFilter> {
+    let (attribute, value) = parse_equality_filter(filter)?;
+    Ok(match attribute.as_str() {
+        "username" | "id" => UserRequestFilter::UserId(UserId::new(&value)),
+        "emails" | "emails.value" => UserRequestFilter::Equality(UserColumn::Email, value),
+        "displayname" => UserRequestFilter::Equality(UserColumn::DisplayName, value),
+        _ => {
+            return Err(ScimError::bad_request(
+                "invalidFilter",
+                format!("Unsupported filter attribute: '{}'", attribute),
+            ))
+        }
+    })
+}
+
+fn to_group_filter(filter: &str) -> ScimResult<GroupRequestFilter> {
+    let (attribute, value) = parse_equality_filter(filter)?;
+    Ok(match attribute.as_str() {
+        "displayname" => GroupRequestFilter::DisplayName(value),
+        "id" => GroupRequestFilter::GroupId(GroupId(value.parse().map_err(|_| {
+            ScimError::bad_request("invalidFilter", format!("Invalid group ID: '{}'", value))
+        })?)),
+        _ => {
+            return Err(ScimError::bad_request(
+                "invalidFilter",
+                format!("Unsupported filter attribute: '{}'", attribute),
+            ))
+        }
+    })
+}
+
+fn to_scim_user(user: User, groups: Vec<GroupDetails>, base_url: &str) -> ScimUser {
+    let name = ScimName {
+        formatted: None,
+        given_name: user.first_name.filter(|n| !n.is_empty()),
+        family_name: user.last_name.filter(|n| !n.is_empty()),
+    };
+    ScimUser {
+        schemas: vec![USER_SCHEMA.to_owned()],
+        id: Some(user.user_id.to_string()),
+        user_name: user.user_id.to_string(),
+        display_name: user.display_name.filter(|n| !n.is_empty()),
+        name: if name == ScimName::default() {
+            None
+        } else {
+            Some(name)
+        },
+        // The secondary addresses are only listed: they are managed through GraphQL.
+        emails: std::iter::once(ScimEmail {
+            value: user.email,
+            type_: Some("work".to_owned()),
+            primary: true,
+        })
+        .chain(user.secondary_emails.into_iter().map(|value| ScimEmail {
+            value,
+            type_: Some("other".to_owned()),
+            primary: false,
+        }))
+        .collect(),
+        active: Some(user.enabled),
+        password: None,
+        groups: groups
+            .into_iter()
+            .map(|g| ScimMember {
+                value: g.group_id.0.to_string(),
+                display: Some(g.display_name),
+            })
+            .collect(),
+        meta: Some(ScimMeta {
+            resource_type: "User",
+            created: user.creation_date,
+            last_modified: user.modified_date,
+            location: format!("{}/Users/{}", base_url, user.user_id),
+        }),
+    }
+}
+
+fn to_scim_group(group: Group, base_url: &str) -> ScimGroup {
+    ScimGroup {
+        schemas: vec![GROUP_SCHEMA.to_owned()],
+        id: Some(group.id.0.to_string()),
+        display_name: group.display_name,
+        members: group
+            .users
+            .into_iter()
+            .map(|u| ScimMember {
+                value: u.to_string(),
+                display: None,
+            })
+            .collect(),
+        meta: Some(ScimMeta {
+            resource_type: "Group",
+            created: group.creation_date,
+            last_modified: group.modified_date,
+            location: format!("{}/Groups/{}", base_url, group.id.0),
+        }),
+    }
+}
+
+fn primary_email(emails: &[ScimEmail]) -> Option<&str> {
+    emails
+        .iter()
+        .find(|e| e.primary)
+        .or_else(|| emails.first())
+        .map(|e| e.value.as_str())
+}
+
+fn parse_group_id(id: &str) -> ScimResult<GroupId> {
+    id.parse()
+        .map(GroupId)
+        .map_err(|_| ScimError::new(StatusCode::NOT_FOUND, format!("No group '{}'", id)))
+}
+
+/// The modifications of a user, from a replacement or a patch.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct UserModification {
+    request: UpdateUserRequest,
+    new_user_id: Option<UserId>,
+    password: Option<String>,
+}
+
+fn as_string(value: &Value) -> ScimResult<String> {
+    value.as_str().map(str::to_owned).ok_or_else(|| {
+        ScimError::bad_request("invalidValue", format!("Expected a string: {}", value))
+    })
+}
+
+// Some clients send the booleans as strings, e.g. "False".
+fn as_bool(value: &Value) -> ScimResult<bool> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::bad_request(
+            "invalidValue",
+            format!("Expected a boolean: {}", value),
+        )),
+    }
+}
+
+fn as_email(value: &Value) -> ScimResult<String> {
+    let emails: Vec<ScimEmail> = match value {
+        Value::Array(_) => serde_json::from_value(value.clone()),
+        _ => serde_json::from_value(Value::Array(vec![value.clone()])),
+    }
+    .map_err(|e| ScimError::bad_request("invalidValue", format!("Invalid emails: {}", e)))?;
+    primary_email(&emails)
+        .map(str::to_owned)
+        .ok_or_else(|| ScimError::bad_request("invalidValue", "Missing email"))
+}
+
+impl UserModification {
+    fn new(user_id: UserId) -> Self {
+        Self {
+            request: UpdateUserRequest {
+                user_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Replaces all the attributes of the user, like a PUT: the missing ones are cleared.
+    fn replace(user_id: UserId, user: ScimUser) -> ScimResult<Self> {
+        let mut modification = Self::new(user_id);
+        let email = primary_email(&user.emails)
+            .ok_or_else(|| ScimError::bad_request("invalidValue", "Missing email"))?;
+        let name = user.name.unwrap_or_default();
+        modification.request.email = Some(email.to_owned());
+        modification.request.display_name = Some(user.display_name.unwrap_or_default());
+        modification.request.first_name = Some(name.given_name.unwrap_or_default());
+        modification.request.last_name = Some(name.family_name.unwrap_or_default());
+        modification.request.enabled = user.active;
+        modification.set_user_name(user.user_name);
+        modification.password = user.password;
+        Ok(modification)
+    }
+
+    fn set_user_name(&mut self, user_name: String) {
+        let new_user_id = UserId::new(&user_name);
+        if new_user_id != self.request.user_id {
+            self.new_user_id = Some(new_user_id);
+        }
+    }
+
+    fn apply(&mut self, operation: &PatchOperation) -> ScimResult<()> {
+        let op = operation.op.to_ascii_lowercase();
+        let path = operation.path.as_deref().map(normalize_path);
+        match (op.as_str(), path, &operation.value) {
+            ("add" | "replace", None, Some(Value::Object(values))) => {
+                for (attribute, value) in values {
+                    self.set(&normalize_path(attribute), value)?;
+                }
+                Ok(())
+            }
+            ("add" | "replace", Some(path), Some(value)) => self.set(&path, value),
+            ("remove", Some(path), _) => self.remove(&path),
+            _ => Err(ScimError::bad_request(
+                "invalidSyntax",
+                format!("Unsupported operation: {:?}", operation),
+            )),
+        }
+    }
+
+    fn set(&mut self, path: &str, value: &Value) -> ScimResult<()> {
+        match path {
+            "username" => self.set_user_name(as_string(value)?),
+            "displayname" => self.request.display_name = Some(as_string(value)?),
+            "name.givenname" => self.request.first_name = Some(as_string(value)?),
+            "name.familyname" => self.request.last_name = Some(as_string(value)?),
+            "name" => {
+                let values = value.as_object().ok_or_else(|| {
+                    ScimError::bad_request("invalidValue", format!("Invalid name: {}", value))
+                })?;
+                for (attribute, value) in values {
+                    let path = format!("name.{}", attribute.to_ascii_lowercase());
+                    if path != "name.formatted" {
+                        self.set(&path, value)?;
+                    }
+                }
+            }
+            "emails" | "emails.value" => self.request.email = Some(as_email(value)?),
+            path if path.starts_with("emails[") => self.request.email = Some(as_string(value)?),
+            "active" => self.request.enabled = Some(as_bool(value)?),
+            "password" => self.password = Some(as_string(value)?),
+            // Read-only or unsupported attributes.
+            "id" | "schemas" | "meta" | "groups" | "externalid" => {}
+            _ => {
+                return Err(ScimError::bad_request(
+                    "invalidPath",
+                    format!("Unsupported attribute: '{}'", path),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str) -> ScimResult<()> {
+        match path {
+            "displayname" => self.request.display_name = Some(String::new()),
+            "name.givenname" => self.request.first_name = Some(String::new()),
+            "name.familyname" => self.request.last_name = Some(String::new()),
+            "name" => {
+                self.request.first_name = Some(String::new());
+                self.request.last_name = Some(String::new());
+            }
+            _ => {
+                return Err(ScimError::bad_request(
+                    "mutability",
+                    format!("Cannot remove the attribute '{}'", path),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+// Paths are case-insensitive, and can be prefixed by the schema.
+fn normalize_path(path: &str) -> String {
+    let path = path.to_ascii_lowercase();
+    path.strip_prefix(&format!("{}:", USER_SCHEMA.to_ascii_lowercase()))
+        .map(str::to_owned)
+        .unwrap_or(path)
+}
+
+async fn set_password<Backend: BackendHandler + OpaqueHandler>(
+    backend_handler: &Backend,
+    user_id: &UserId,
+    password: &str,
+) -> ScimResult<()> {
+    backend_handler
+        .check_password_policy(user_id, password)
+        .await?;
+    register_password(backend_handler, user_id, &SecUtf8::from(password)).await?;
+    backend_handler
+        .add_password_to_history(user_id, password)
+        .await?;
+    Ok(())
+}
+
+async fn apply_user_modification<Backend: BackendHandler + OpaqueHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    modification: UserModification,
+) -> ScimResult<UserId> {
+    let UserModification {
+        mut request,
+        new_user_id,
+        password,
+    } = modification;
+    let mut user_id = request.user_id.clone();
+    if user_id == validation_result.user && request.enabled == Some(false) {
+        return Err(ScimError::bad_request(
+            "mutability",
+            "Cannot disable the current user",
+        ));
+    }
+    if let Some(new_user_id) = new_user_id {
+        backend_handler.rename_user(&user_id, &new_user_id).await?;
+        user_id = new_user_id;
+        request.user_id = user_id.clone();
+    }
+    if request
+        != (UpdateUserRequest {
+            user_id: user_id.clone(),
+            ..Default::default()
+        })
+    {
+        backend_handler.update_user(request).await?;
+    }
+    if let Some(password) = password {
+        set_password(backend_handler, &user_id, &password).await?;
+    }
+    Ok(user_id)
+}
+
+async fn get_scim_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    user_id: &UserId,
+) -> ScimResult<ScimUser> {
+    let user = data.backend_handler.get_user_details(user_id).await?;
+    let mut groups: Vec<_> = data
+        .backend_handler
+        .get_user_groups(user_id)
+        .await?
+        .into_iter()
+        .collect();
+    groups.sort_by_key(|g| g.group_id);
+    Ok(to_scim_user(user, groups, &base_url(data)))
+}
+
+async fn get_scim_group<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    group_id: GroupId,
+) -> ScimResult<ScimGroup> {
+    data.backend_handler
+        .list_groups(
+            Some(GroupRequestFilter::GroupId(group_id)),
+            GroupOrder::default(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .map(|group| to_scim_group(group, &base_url(data)))
+        .ok_or_else(|| ScimError::new(StatusCode::NOT_FOUND, format!("No group '{}'", group_id.0)))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_users<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    query: web::Query<ListQuery>,
+) -> ScimResult<HttpResponse> {
+    authorize(&data, &credentials, false).await?;
+    debug!(?query);
+    let filter = query.filter.as_deref().map(to_user_filter).transpose()?;
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let page = data
+        .backend_handler
+        .list_users_page(
+            filter,
+            true,
+            UserOrder::default(),
+            start_index - 1,
+            Some(query.count.unwrap_or(DEFAULT_COUNT)),
+        )
+        .await?;
+    let base_url = base_url(&data);
+    let resources: Vec<_> = page
+        .users
+        .into_iter()
+        .map(|u| to_scim_user(u.user, u.groups.unwrap_or_default(), &base_url))
+        .collect();
+    Ok(scim_response(
+        StatusCode::OK,
+        &ListResponse {
+            schemas: vec![LIST_RESPONSE_SCHEMA],
+            total_results: page.total_count,
+            start_index,
+            items_per_page: resources.len() as u64,
+            resources,
+        },
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    authorize(&data, &credentials, false).await?;
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    Ok(scim_response(
+        StatusCode::OK,
+        &get_scim_user(&data, &user_id).await?,
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_user<Backend: BackendHandler + OpaqueHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let backend_handler = data.backend_handler_for(&validation_result, AuditSource::Scim);
+    let user: ScimUser = parse_body(&body)?;
+    let user_id = create_scim_user(&backend_handler, &validation_result, user).await?;
+    let user = get_scim_user(&data, &user_id).await?;
+    Ok(HttpResponse::Created()
+        .content_type(SCIM_CONTENT_TYPE)
+        .insert_header((
+            header::LOCATION,
+            format!("{}/Users/{}", base_url(&data), user_id),
+        ))
+        .body(serde_json::to_string(&user).unwrap()))
+}
+
+/// Creates the user, then sets their password and status. The user is deleted if that fails, so
+/// that the client can retry the creation.
+async fn create_scim_user<Backend: BackendHandler + OpaqueHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    user: ScimUser,
+) -> ScimResult<UserId> {
+    let user_id = UserId::new(&user.user_name);
+    debug!(?user_id);
+    backend_handler.check_user_id(&user.user_name)?;
+    match backend_handler.get_user_details(&user_id).await {
+        Ok(_) => {
+            return Err(ScimError::conflict(format!(
+                "User '{}' already exists",
+                user_id
+            )))
+        }
+        Err(DomainError::EntityNotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+    let email = primary_email(&user.emails)
+        .ok_or_else(|| ScimError::bad_request("invalidValue", "Missing email"))?;
+    let name = user.name.clone().unwrap_or_default();
+    // Checked before the creation, to reject a weak password without a round trip.
+    if let Some(password) = &user.password {
+        backend_handler
+            .check_password_policy(&user_id, password)
+            .await?;
+    }
+    backend_handler
+        .create_user(CreateUserRequest {
+            user_id: user_id.clone(),
+            email: email.to_owned(),
+            display_name: user.display_name.clone(),
+            first_name: name.given_name,
+            last_name: name.family_name,
+            ..Default::default()
+        })
+        .await?;
+    let mut modification = UserModification::new(user_id.clone());
+    if user.active == Some(false) {
+        modification.request.enabled = Some(false);
+    }
+    modification.password = user.password;
+    if let Err(e) = apply_user_modification(backend_handler, validation_result, modification).await
+    {
+        if let Err(delete_error) = backend_handler.delete_user(&user_id).await {
+            warn!(
+                "Could not delete the user {} after a failed creation: {}",
+                user_id, delete_error
+            );
+        }
+        return Err(e);
+    }
+    Ok(user_id)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn replace_user<Backend: BackendHandler + OpaqueHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let backend_handler = data.backend_handler_for(&validation_result, AuditSource::Scim);
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    // Fail early if the user doesn't exist.
+    data.backend_handler.get_user_details(&user_id).await?;
+    let modification = UserModification::replace(user_id, parse_body(&body)?)?;
+    let user_id =
+        apply_user_modification(&backend_handler, &validation_result, modification).await?;
+    Ok(scim_response(
+        StatusCode::OK,
+        &get_scim_user(&data, &user_id).await?,
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn patch_user<Backend: BackendHandler + OpaqueHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let backend_handler = data.backend_handler_for(&validation_result, AuditSource::Scim);
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    data.backend_handler.get_user_details(&user_id).await?;
+    let patch: PatchRequest = parse_body(&body)?;
+    let mut modification = UserModification::new(user_id);
+    for operation in &patch.operations {
+        modification.apply(operation)?;
+    }
+    let user_id =
+        apply_user_modification(&backend_handler, &validation_result, modification).await?;
+    Ok(scim_response(
+        StatusCode::OK,
+        &get_scim_user(&data, &user_id).await?,
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn delete_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    user_id: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let user_id = UserId::new(&user_id);
+    debug!(?user_id);
+    if validation_result.user == user_id {
+        return Err(ScimError::bad_request(
+            "mutability",
+            "Cannot delete the current user",
+        ));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Scim)
+        .delete_user(&user_id)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_groups<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    query: web::Query<ListQuery>,
+) -> ScimResult<HttpResponse> {
+    authorize(&data, &credentials, false).await?;
+    debug!(?query);
+    let filter = query.filter.as_deref().map(to_group_filter).transpose()?;
+    let groups = data
+        .backend_handler
+        .list_groups(filter, GroupOrder::default())
+        .await?;
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let total_results = groups.len() as u64;
+    let base_url = base_url(&data);
+    let resources: Vec<_> = groups
+        .into_iter()
+        .skip((start_index - 1) as usize)
+        .take(query.count.unwrap_or(DEFAULT_COUNT) as usize)
+        .map(|g| to_scim_group(g, &base_url))
+        .collect();
+    Ok(scim_response(
+        StatusCode::OK,
+        &ListResponse {
+            schemas: vec![LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index,
+            items_per_page: resources.len() as u64,
+            resources,
+        },
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    authorize(&data, &credentials, false).await?;
+    let group_id = parse_group_id(&group_id)?;
+    debug!(?group_id);
+    Ok(scim_response(
+        StatusCode::OK,
+        &get_scim_group(&data, group_id).await?,
+    ))
+}
+
+/// Adds and removes members so that the group has exactly the given ones.
+async fn set_group_members<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    group_id: GroupId,
+    current_members: &[UserId],
+    members: &[UserId],
+) -> ScimResult<()> {
+    let current: HashSet<_> = current_members.iter().collect();
+    let wanted: HashSet<_> = members.iter().collect();
+    let to_add: Vec<_> = members
+        .iter()
+        .filter(|u| !current.contains(u))
+        .cloned()
+        .collect();
+    let to_remove: Vec<_> = current_members
+        .iter()
+        .filter(|u| !wanted.contains(u))
+        .cloned()
+        .collect();
+    modify_group_members(backend_handler, validation_result, group_id, &to_add, true).await?;
+    modify_group_members(
+        backend_handler,
+        validation_result,
+        group_id,
+        &to_remove,
+        false,
+    )
+    .await
+}
+
+async fn modify_group_members<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    validation_result: &ValidationResults,
+    group_id: GroupId,
+    user_ids: &[UserId],
+    add: bool,
+) -> ScimResult<()> {
+    if user_ids.is_empty() {
+        return Ok(());
+    }
+    if add {
+        backend_handler
+            .add_users_to_group(user_ids, group_id)
+            .await?;
+    } else {
+        if group_id == GroupId(1) && user_ids.contains(&validation_result.user) {
+            return Err(ScimError::bad_request(
+                "mutability",
+                "Cannot remove the current user from the admin group",
+            ));
+        }
+        backend_handler
+            .remove_users_from_group(user_ids, group_id)
+            .await?;
+    }
+    Ok(())
+}
+
+fn member_ids(members: &[ScimMember]) -> Vec<UserId> {
+    members.iter().map(|m| UserId::new(&m.value)).collect()
+}
+
+fn parse_members(value: &Value) -> ScimResult<Vec<UserId>> {
+    let members: Vec<ScimMember> = match value {
+        Value::Array(_) => serde_json::from_value(value.clone()),
+        _ => serde_json::from_value(Value::Array(vec![value.clone()])),
+    }
+    .map_err(|e| ScimError::bad_request("invalidValue", format!("Invalid members: {}", e)))?;
+    Ok(member_ids(&members))
+}
+
+/// Parses the `members[value eq "bob"]` paths of the removals.
+fn parse_member_path(path: &str) -> Option<UserId> {
+    let filter = path.strip_prefix("members[")?.strip_suffix(']')?;
+    match parse_equality_filter(filter) {
+        Ok((attribute, value)) if attribute == "value" => Some(UserId::new(&value)),
+        _ => None,
+    }
+}
+
+async fn rename_group<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    group: &ScimGroup,
+    group_id: GroupId,
+    display_name: String,
+) -> ScimResult<()> {
+    if display_name == group.display_name {
+        return Ok(());
+    }
+    backend_handler
+        .update_group(UpdateGroupRequest {
+            group_id,
+            display_name: Some(display_name),
+            ..Default::default()
+        })
+        .await?;
+    Ok(())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let backend_handler = data.backend_handler_for(&validation_result, AuditSource::Scim);
+    let group: ScimGroup = parse_body(&body)?;
+    debug!(?group.display_name);
+    if !data
+        .backend_handler
+        .list_groups(
+            Some(GroupRequestFilter::DisplayName(group.display_name.clone())),
+            GroupOrder::default(),
+        )
+        .await?
+        .is_empty()
+    {
+        return Err(ScimError::conflict(format!(
+            "Group '{}' already exists",
+            group.display_name
+        )));
+    }
+    let group_id = backend_handler.create_group(&group.display_name).await?;
+    modify_group_members(
+        &backend_handler,
+        &validation_result,
+        group_id,
+        &member_ids(&group.members),
+        true,
+    )
+    .await?;
+    Ok(HttpResponse::Created()
+        .content_type(SCIM_CONTENT_TYPE)
+        .insert_header((
+            header::LOCATION,
+            format!("{}/Groups/{}", base_url(&data), group_id.0),
+        ))
+        .body(serde_json::to_string(&get_scim_group(&data, group_id).await?).unwrap()))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn replace_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let backend_handler = data.backend_handler_for(&validation_result, AuditSource::Scim);
+    let group_id = parse_group_id(&group_id)?;
+    debug!(?group_id);
+    let current = get_scim_group(&data, group_id).await?;
+    let group: ScimGroup = parse_body(&body)?;
+    rename_group(&backend_handler, &current, group_id, group.display_name).await?;
+    set_group_members(
+        &backend_handler,
+        &validation_result,
+        group_id,
+        &member_ids(&current.members),
+        &member_ids(&group.members),
+    )
+    .await?;
+    Ok(scim_response(
+        StatusCode::OK,
+        &get_scim_group(&data, group_id).await?,
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn patch_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let group_id = parse_group_id(&group_id)?;
+    debug!(?group_id);
+    let patch: PatchRequest = parse_body(&body)?;
+    let backend_handler = &data.backend_handler_for(&validation_result, AuditSource::Scim);
+    for operation in patch.operations {
+        // Each operation sees the result of the previous ones.
+        let current = get_scim_group(&data, group_id).await?;
+        let current_members = member_ids(&current.members);
+        let op = operation.op.to_ascii_lowercase();
+        let path = operation.path.as_deref().map(str::to_ascii_lowercase);
+        match (op.as_str(), path.as_deref(), operation.value) {
+            ("add", Some("members"), Some(value)) => {
+                let members: Vec<_> = parse_members(&value)?
+                    .into_iter()
+                    .filter(|u| !current_members.contains(u))
+                    .collect();
+                modify_group_members(
+                    backend_handler,
+                    &validation_result,
+                    group_id,
+                    &members,
+                    true,
+                )
+                .await?
+            }
+            ("replace", Some("members"), Some(value)) => {
+                set_group_members(
+                    backend_handler,
+                    &validation_result,
+                    group_id,
+                    &current_members,
+                    &parse_members(&value)?,
+                )
+                .await?
+            }
+            ("remove", Some("members"), value) => {
+                let members = match value {
+                    Some(value) => parse_members(&value)?,
+                    None => current_members.clone(),
+                };
+                let members: Vec<_> = members
+                    .into_iter()
+                    .filter(|u| current_members.contains(u))
+                    .collect();
+                modify_group_members(
+                    backend_handler,
+                    &validation_result,
+                    group_id,
+                    &members,
+                    false,
+                )
+                .await?
+            }
+            ("remove", Some(path), None) if parse_member_path(path).is_some() => {
+                let user_id = parse_member_path(path).unwrap();
+                if current_members.contains(&user_id) {
+                    modify_group_members(
+                        backend_handler,
+                        &validation_result,
+                        group_id,
+                        &[user_id],
+                        false,
+                    )
+                    .await?
+                }
+            }
+            ("add" | "replace", Some("displayname"), Some(value)) => {
+                rename_group(backend_handler, &current, group_id, as_string(&value)?).await?
+            }
+            ("add" | "replace", None, Some(Value::Object(values))) => {
+                if let Some(display_name) = values
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("displayName"))
+                    .map(|(_, v)| as_string(v))
+                    .transpose()?
+                {
+                    rename_group(backend_handler, &current, group_id, display_name).await?;
+                }
+                if let Some(members) = values
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("members"))
+                    .map(|(_, v)| parse_members(v))
+                    .transpose()?
+                {
+                    set_group_members(
+                        backend_handler,
+                        &validation_result,
+                        group_id,
+                        &current_members,
+                        &members,
+                    )
+                    .await?;
+                }
+            }
+            (op, path, _) => {
+                return Err(ScimError::bad_request(
+                    "invalidSyntax",
+                    format!("Unsupported operation '{}' on {:?}", op, path),
+                ))
+            }
+        }
+    }
+    Ok(scim_response(
+        StatusCode::OK,
+        &get_scim_group(&data, group_id).await?,
+    ))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn delete_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    credentials: BearerAuth,
+    group_id: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authorize(&data, &credentials, true).await?;
+    let group_id = parse_group_id(&group_id)?;
+    debug!(?group_id);
+    if group_id == GroupId(1) {
+        return Err(ScimError::bad_request(
+            "mutability",
+            "Cannot delete the admin group",
+        ));
+    }
+    data.backend_handler_for(&validation_result, AuditSource::Scim)
+        .delete_group(group_id)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn get_service_provider_config() -> HttpResponse {
+    scim_response(
+        StatusCode::OK,
+        &json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+            "patch": {"supported": true},
+            "bulk": {"supported": false, "maxOperations": 0, "maxPayloadSize": 0},
+            "filter": {"supported": true, "maxResults": DEFAULT_COUNT},
+            "changePassword": {"supported": true},
+            "sort": {"supported": false},
+            "etag": {"supported": false},
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "Bearer token",
+                "description": "An LLDAP API token, or the JWT of a logged-in admin",
+            }],
+        }),
+    )
+}
+
+async fn get_resource_types<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    let base_url = base_url(&data);
+    let resources = vec![
+        json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ResourceType"],
+            "id": "User",
+            "name": "User",
+            "endpoint": "/Users",
+            "schema": USER_SCHEMA,
+            "meta": {"resourceType": "ResourceType", "location": format!("{}/ResourceTypes/User", base_url)},
+        }),
+        json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ResourceType"],
+            "id": "Group",
+            "name": "Group",
+            "endpoint": "/Groups",
+            "schema": GROUP_SCHEMA,
+            "meta": {"resourceType": "ResourceType", "location": format!("{}/ResourceTypes/Group", base_url)},
+        }),
+    ];
+    scim_response(
+        StatusCode::OK,
+        &ListResponse {
+            schemas: vec![LIST_RESPONSE_SCHEMA],
+            total_results: 2,
+            start_index: 1,
+            items_per_page: 2,
+            resources,
+        },
+    )
+}
+
+/// The SCIM 2.0 API (RFC 7644), under "/scim/v2", for the identity providers that provision the
+/// users and groups. It authenticates with the same bearer tokens as the GraphQL API.
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + OpaqueHandler + Sync + 'static,
+{
+    cfg.service(
+        web::resource("/ServiceProviderConfig").route(web::get().to(get_service_provider_config)),
+    )
+    .service(web::resource("/ResourceTypes").route(web::get().to(get_resource_types::<Backend>)))
+    .service(
+        web::resource("/Users")
+            .route(web::get().to(list_users::<Backend>))
+            .route(web::post().to(create_user::<Backend>)),
+    )
+    .service(
+        web::resource("/Users/{user_id}")
+            .route(web::get().to(get_user::<Backend>))
+            .route(web::put().to(replace_user::<Backend>))
+            .route(web::patch().to(patch_user::<Backend>))
+            .route(web::delete().to(delete_user::<Backend>)),
+    )
+    .service(
+        web::resource("/Groups")
+            .route(web::get().to(list_groups::<Backend>))
+            .route(web::post().to(create_group::<Backend>)),
+    )
+    .service(
+        web::resource("/Groups/{group_id}")
+            .route(web::get().to(get_group::<Backend>))
+            .route(web::put().to(replace_group::<Backend>))
+            .route(web::patch().to(patch_group::<Backend>))
+            .route(web::delete().to(delete_group::<Backend>)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filters() {
+        assert_eq!(
+            to_user_filter(r#"userName eq "bob""#).unwrap(),
+            UserRequestFilter::UserId(UserId::new("bob"))
+        );
+        assert_eq!(
+            to_user_filter(r#"emails.value EQ "bob@example.com""#).unwrap(),
+            UserRequestFilter::Equality(UserColumn::Email, "bob@example.com".to_owned())
+        );
+        assert_eq!(
+            to_group_filter(r#"displayName eq "Team \"A\"""#).unwrap(),
+            GroupRequestFilter::DisplayName(r#"Team "A""#.to_owned())
+        );
+        assert_eq!(
+            to_user_filter(r#"userName sw "b""#).unwrap_err().scim_type,
+            Some("invalidFilter")
+        );
+        assert!(to_user_filter(r#"title eq "boss""#).is_err());
+        assert!(to_user_filter("userName eq bob").is_err());
+        assert_eq!(
+            parse_member_path(r#"members[value eq "bob"]"#),
+            Some(UserId::new("bob"))
+        );
+    }
+
+    #[test]
+    fn test_patch_user() {
+        let patch: PatchRequest = serde_json::from_value(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [
+                {"op": "Replace", "path": "active", "value": "False"},
+                {"op": "replace", "path": "emails[type eq \"work\"].value", "value": "bob@example.com"},
+                {"op": "add", "value": {"displayName": "Bob", "name": {"givenName": "Bobby"}}},
+                {"op": "remove", "path": "name.familyName"},
+            ],
+        }))
+        .unwrap();
+        let mut modification = UserModification::new(UserId::new("bob"));
+        for operation in &patch.operations {
+            modification.apply(operation).unwrap();
+        }
+        assert_eq!(
+            modification,
+            UserModification {
+                request: UpdateUserRequest {
+                    user_id: UserId::new("bob"),
+                    email: Some("bob@example.com".to_owned()),
+                    display_name: Some("Bob".to_owned()),
+                    first_name: Some("Bobby".to_owned()),
+                    last_name: Some(String::new()),
+                    enabled: Some(false),
+                    ..Default::default()
+                },
+                new_user_id: None,
+                password: None,
+            }
+        );
+        assert!(modification
+            .apply(&PatchOperation {
+                op: "remove".to_owned(),
+                path: Some("emails".to_owned()),
+                value: None,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_replace_user() {
+        let user: ScimUser = serde_json::from_value(json!({
+            "schemas": [USER_SCHEMA],
+            "userName": "Robert",
+            "emails": [
+                {"value": "bob@home.com", "type": "home"},
+                {"value": "bob@example.com", "type": "work", "primary": true},
+            ],
+            "password": "hunter2",
+        }))
+        .unwrap();
+        let modification = UserModification::replace(UserId::new("bob"), user).unwrap();
+        assert_eq!(modification.new_user_id, Some(UserId::new("robert")));
+        assert_eq!(modification.password.as_deref(), Some("hunter2"));
+        assert_eq!(
+            modification.request.email.as_deref(),
+            Some("bob@example.com")
+        );
+        assert_eq!(modification.request.display_name.as_deref(), Some(""));
+        assert_eq!(modification.request.enabled, None);
+    }
+
+    #[tokio::test]
+    async fn test_create_user_with_weak_password() {
+        use crate::domain::sql_backend_handler::tests::*;
+        let fixture = TestFixture::new().await;
+        let user: ScimUser = serde_json::from_value(json!({
+            "schemas": [USER_SCHEMA],
+            "userName": "alice",
+            "emails": [{"value": "alice@example.com", "primary": true}],
+            "password": "abc",
+        }))
+        .unwrap();
+        let error = create_scim_user(&fixture.handler, &ValidationResults::admin(), user.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        // Nothing was created, the client can try again with a stronger password.
+        assert!(matches!(
+            fixture
+                .handler
+                .get_user_details(&UserId::new("alice"))
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        let user = ScimUser {
+            password: Some("correct horse battery staple".to_owned()),
+            ..user
+        };
+        assert_eq!(
+            create_scim_user(&fixture.handler, &ValidationResults::admin(), user)
+                .await
+                .unwrap(),
+            UserId::new("alice")
+        );
+    }
+
+    #[test]
+    fn test_to_scim_user() {
+        let user = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".to_owned(),
+            display_name: Some("Bob".to_owned()),
+            first_name: Some(String::new()),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(to_scim_user(
+            user,
+            Vec::new(),
+            "https://lldap.example.com/scim/v2",
+        ))
+        .unwrap();
+        assert_eq!(value["userName"], "bob");
+        assert_eq!(value["emails"][0]["value"], "bob@example.com");
+        assert_eq!(value["active"], true);
+        assert!(value.get("name").is_none());
+        assert!(value.get("password").is_none());
+        assert_eq!(
+            value["meta"]["location"],
+            "https://lldap.example.com/scim/v2/Users/bob"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::infra::{
+    config_reload::SharedMailOptions,
+    configuration::{Configuration, MailOptions, SecretsProviderOptions, SecretsProviderType},
+};
+use anyhow::{bail, Context, Result};
+use secstr::SecUtf8;
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{error, info, instrument, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The secrets returned by the provider. The missing ones are taken from the configuration, and
+/// the other keys are ignored.
+#[derive(Debug, Default, Deserialize)]
+pub struct Secrets {
+    pub jwt_secret: Option<SecUtf8>,
+    pub ldap_user_pass: Option<SecUtf8>,
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<SecUtf8>,
+}
+
+impl Secrets {
+    fn apply_to_mail_options(&self, options: &mut MailOptions) {
+        if let Some(user) = &self.smtp_user {
+            options.user = user.clone();
+        }
+        if let Some(password) = &self.smtp_password {
+            options.password = password.clone();
+        }
+    }
+
+    pub fn apply(self, config: &mut Configuration) {
+        self.apply_to_mail_options(&mut config.smtp_options);
+        if let Some(jwt_secret) = self.jwt_secret {
+            config.jwt_secret = jwt_secret;
+        }
+        if let Some(ldap_user_pass) = self.ldap_user_pass {
+            config.ldap_user_pass = ldap_user_pass;
+        }
+    }
+}
+
+// Both versions of the KV engine are supported: the version 2 nests the secret in a second "data"
+// object, next to its "metadata".
+fn parse_vault_response(mut response: serde_json::Value) -> Result<Secrets> {
+    let mut data = response
+        .get_mut("data")
+        .map(serde_json::Value::take)
+        .context("Missing \"data\" in the Vault response")?;
+    if data.get("metadata").is_some() {
+        data = data
+            .get_mut("data")
+            .map(serde_json::Value::take)
+            .context("Missing \"data\" in the Vault secret")?;
+    }
+    Ok(serde_json::from_value(data)?)
+}
+
+async fn fetch_from_vault(options: &SecretsProviderOptions) -> Result<Secrets> {
+    let url = format!(
+        "{}/v1/{}",
+        options.vault_url.trim_end_matches('/'),
+        options.vault_path.trim_start_matches('/')
+    );
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header("X-Vault-Token", options.vault_token.unsecure())
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("while connecting to {}", options.vault_url))?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    parse_vault_response(response)
+}
+
+async fn fetch_from_command(command: &str) -> Result<Secrets> {
+    let output = tokio::process::Command::new(command)
+        .output()
+        .await
+        .with_context(|| format!("while running {}", command))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("{} did not print a JSON object", command))
+}
+
+/// Fetches the secrets from the configured provider, if any.
+#[instrument(skip_all, level = "debug", err)]
+pub async fn fetch_secrets(options: &SecretsProviderOptions) -> Result<Option<Secrets>> {
+    Ok(Some(match options.provider {
+        SecretsProviderType::None => return Ok(None),
+        SecretsProviderType::Vault => fetch_from_vault(options)
+            .await
+            .context("while fetching the secrets from Vault")?,
+        SecretsProviderType::Exec => {
+            let command = options
+                .command
+                .as_deref()
+                .context("secrets_provider.command is not set")?;
+            fetch_from_command(command)
+                .await
+                .context("while fetching the secrets")?
+        }
+    }))
+}
+
+/// Replaces the secrets of the configuration with the ones of the provider.
+pub async fn apply_secrets(config: &mut Configuration) -> Result<()> {
+    if let Some(secrets) = fetch_secrets(&config.secrets_provider).await? {
+        secrets.apply(config);
+        if config.jwt_secret == SecUtf8::from("secretjwtsecret") {
+            warn!("Default JWT secret used! This is highly unsafe and can allow attackers to log in as admin.");
+        }
+        if config.ldap_user_pass == SecUtf8::from("password") {
+            warn!("Unsecure default admin password is used.");
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the secrets periodically, to pick up the rotated SMTP credentials. The other secrets
+/// are only read at startup.
+pub async fn refresh_secrets(options: SecretsProviderOptions, mail_options: SharedMailOptions) {
+    if options.provider == SecretsProviderType::None || options.refresh_interval_seconds == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(options.refresh_interval_seconds));
+    // The first tick is immediate, and the secrets were just fetched at startup.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match fetch_secrets(&options).await {
+            Ok(Some(secrets)) => {
+                secrets.apply_to_mail_options(&mut mail_options.write().unwrap());
+                info!("Refreshed the SMTP credentials");
+            }
+            Ok(None) => {}
+            // Keeps the current credentials.
+            Err(e) => error!("Could not refresh the secrets: {:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_vault_response() {
+        let kv2 = json!({
+            "data": {
+                "data": {"jwt_secret": "from vault", "other": "ignored"},
+                "metadata": {"version": 3}
+            }
+        });
+        let secrets = parse_vault_response(kv2).unwrap();
+        assert_eq!(secrets.jwt_secret.unwrap().unsecure(), "from vault");
+        assert!(secrets.ldap_user_pass.is_none());
+        let kv1 = json!({"data": {"smtp_password": "smtp"}});
+        let secrets = parse_vault_response(kv1).unwrap();
+        assert_eq!(secrets.smtp_password.unwrap().unsecure(), "smtp");
+        assert!(parse_vault_response(json!({"errors": []})).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut config = crate::infra::configuration::ConfigurationBuilder::for_tests();
+        Secrets {
+            ldap_user_pass: Some(SecUtf8::from("new admin password")),
+            smtp_user: Some("mailer".to_owned()),
+            ..Default::default()
+        }
+        .apply(&mut config);
+        assert_eq!(config.ldap_user_pass.unsecure(), "new admin password");
+        assert_eq!(config.smtp_options.user, "mailer");
+        assert_eq!(config.jwt_secret.unsecure(), "secretjwtsecret");
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{
+    domain::{
+        handler::{GroupBackendHandler, GroupRequestFilter, UserBackendHandler},
+        security_events::SecurityEvent,
+        types::{GroupId, UserId},
+    },
+    infra::{config_reload::SharedMailOptions, configuration::SecurityNotificationOptions, mail},
+};
+use actix::prelude::{Actor, AsyncContext, Context};
+use anyhow::Result;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Emails the users about the security events of their account, as enabled in the options.
+pub struct SecurityNotifier<Backend> {
+    backend_handler: Backend,
+    events: Option<broadcast::Receiver<SecurityEvent>>,
+    options: SecurityNotificationOptions,
+    mail_options: SharedMailOptions,
+    server_url: String,
+}
+
+impl<Backend> Actor for SecurityNotifier<Backend>
+where
+    Backend: UserBackendHandler + GroupBackendHandler + Clone + Unpin + 'static,
+{
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Security notifier started");
+        let events = self
+            .events
+            .take()
+            .expect("The notifier can only be started once");
+        let future = actix::fut::wrap_future::<_, Self>(Self::run(
+            self.backend_handler.clone(),
+            events,
+            self.options.clone(),
+            self.mail_options.clone(),
+            self.server_url.clone(),
+        ));
+        context.spawn(future);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        info!("Security notifier stopped");
+    }
+}
+
+/// The name of the group if it is one of the admin groups.
+async fn admin_group_name<Backend: GroupBackendHandler>(
+    backend_handler: &Backend,
+    options: &SecurityNotificationOptions,
+    group_id: GroupId,
+) -> Result<Option<String>> {
+    Ok(backend_handler
+        .list_groups(Some(GroupRequestFilter::GroupId(group_id)))
+        .await?
+        .into_iter()
+        .next()
+        .map(|group| group.display_name)
+        .filter(|name| {
+            options
+                .admin_groups
+                .iter()
+                .any(|admin_group| admin_group.eq_ignore_ascii_case(name))
+        }))
+}
+
+impl<Backend> SecurityNotifier<Backend>
+where
+    Backend: UserBackendHandler + GroupBackendHandler + Clone + Unpin + 'static,
+{
+    pub fn new(
+        backend_handler: Backend,
+        events: broadcast::Receiver<SecurityEvent>,
+        options: SecurityNotificationOptions,
+        mail_options: SharedMailOptions,
+        server_url: String,
+    ) -> Self {
+        Self {
+            backend_handler,
+            events: Some(events),
+            options,
+            mail_options,
+            server_url,
+        }
+    }
+
+    async fn notify(
+        backend_handler: &Backend,
+        event: SecurityEvent,
+        options: &SecurityNotificationOptions,
+        mail_options: &SharedMailOptions,
+        server_url: &str,
+    ) -> Result<()> {
+        let user_id: &UserId = match &event {
+            SecurityEvent::PasswordChanged(user_id) if options.password_changed => user_id,
+            SecurityEvent::WebauthnCredentialRemoved(user_id)
+                if options.webauthn_credential_removed =>
+            {
+                user_id
+            }
+            SecurityEvent::GroupMembershipChanged { user_id, .. }
+                if options.admin_group_changed =>
+            {
+                user_id
+            }
+            _ => return Ok(()),
+        };
+        let group = match &event {
+            SecurityEvent::GroupMembershipChanged { group_id, .. } => {
+                match admin_group_name(backend_handler, options, *group_id).await? {
+                    None => return Ok(()),
+                    group => group,
+                }
+            }
+            _ => None,
+        };
+        let user = backend_handler.get_user_details(user_id).await?;
+        let username = user
+            .display_name
+            .as_deref()
+            .unwrap_or_else(|| user.user_id.as_str());
+        debug!(?event, "Notifying {}", &user.email);
+        let mail_options = mail_options.read().unwrap().clone();
+        match event {
+            SecurityEvent::PasswordChanged(_) => {
+                mail::send_password_changed_email(
+                    username,
+                    &user.email,
+                    user.locale.as_deref(),
+                    server_url,
+                    &mail_options,
+                )
+                .await
+            }
+            SecurityEvent::WebauthnCredentialRemoved(_) => {
+                mail::send_webauthn_credential_removed_email(
+                    username,
+                    &user.email,
+                    user.locale.as_deref(),
+                    server_url,
+                    &mail_options,
+                )
+                .await
+            }
+            SecurityEvent::GroupMembershipChanged { added, .. } => {
+                mail::send_admin_group_changed_email(
+                    username,
+                    &user.email,
+                    user.locale.as_deref(),
+                    &group.unwrap_or_default(),
+                    added,
+                    server_url,
+                    &mail_options,
+                )
+                .await
+            }
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn run(
+        backend_handler: Backend,
+        mut events: broadcast::Receiver<SecurityEvent>,
+        options: SecurityNotificationOptions,
+        mail_options: SharedMailOptions,
+        server_url: String,
+    ) {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    error!(
+                        "The notifications are too slow, {} events were dropped",
+                        count
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if let Err(e) = Self::notify(
+                &backend_handler,
+                event.clone(),
+                &options,
+                &mail_options,
+                &server_url,
+            )
+            .await
+            {
+                warn!("Could not send the notification of {:?}: {:#}", event, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::MockTestBackendHandler, types::Group};
+    use mockall::predicate::eq;
+
+    fn expect_group(mock: &mut MockTestBackendHandler, name: &'static str) {
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::GroupId(GroupId(3)))))
+            .times(1)
+            .return_once(move |_| {
+                Ok(vec![Group {
+                    id: GroupId(3),
+                    display_name: name.to_owned(),
+                    creation_date: chrono::Utc::now(),
+                    modified_date: chrono::Utc::now(),
+                    uuid: crate::uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                    gid_number: None,
+                    description: None,
+                    mail: None,
+                    users: Vec::new(),
+                    attributes: Vec::new(),
+                }])
+            });
+    }
+
+    #[tokio::test]
+    async fn test_admin_group_name() {
+        let options = SecurityNotificationOptions::default();
+        let mut mock = MockTestBackendHandler::new();
+        expect_group(&mut mock, "LLDAP_Admin");
+        assert_eq!(
+            admin_group_name(&mock, &options, GroupId(3)).await.unwrap(),
+            Some("LLDAP_Admin".to_owned())
+        );
+        let mut mock = MockTestBackendHandler::new();
+        expect_group(&mut mock, "developers");
+        assert_eq!(
+            admin_group_name(&mock, &options, GroupId(3)).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_notifications_are_ignored() {
+        // No call to the backend is expected.
+        let mock = MockTestBackendHandler::new();
+        let mail_options = SharedMailOptions::default();
+        SecurityNotifier::notify(
+            &mock,
+            SecurityEvent::PasswordChanged(UserId::new("bob")),
+            &SecurityNotificationOptions::default(),
+            &mail_options,
+            "http://localhost",
+        )
+        .await
+        .unwrap();
+    }
+}
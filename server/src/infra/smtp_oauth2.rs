@@ -0,0 +1,194 @@
+//! OAuth2 access tokens for the SMTP authentication (XOAUTH2), needed by the providers that
+//! disabled the password authentication, like Microsoft 365 and Gmail.
+//!
+//! The token is cached until shortly before its expiry. When the provider rotates the refresh
+//! token, the new one replaces the configured one until the configuration changes.
+
+use crate::infra::configuration::SmtpOAuth2Options;
+use anyhow::{Context, Result};
+use secstr::SecUtf8;
+use serde::Deserialize;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, instrument};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The tokens are renewed this long before their expiry, to cover the time to send the email.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+/// Used when the provider doesn't return the lifetime of the token.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(3600);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: SecUtf8,
+    expires_in: Option<u64>,
+    refresh_token: Option<SecUtf8>,
+}
+
+struct CachedToken {
+    /// The options the token was obtained with: a configuration reload discards it.
+    options: SmtpOAuth2Options,
+    access_token: SecUtf8,
+    expiry: Instant,
+    /// The latest refresh token returned by the provider, if it rotated the configured one.
+    refresh_token: Option<SecUtf8>,
+}
+
+static CACHED_TOKEN: Mutex<Option<CachedToken>> = Mutex::new(None);
+
+fn same_client(a: &SmtpOAuth2Options, b: &SmtpOAuth2Options) -> bool {
+    a.token_url == b.token_url
+        && a.client_id == b.client_id
+        && a.scope == b.scope
+        && a.client_secret.unsecure() == b.client_secret.unsecure()
+        && a.refresh_token.unsecure() == b.refresh_token.unsecure()
+}
+
+fn token_request_params<'a>(
+    options: &'a SmtpOAuth2Options,
+    refresh_token: &'a SecUtf8,
+) -> Vec<(&'static str, &'a str)> {
+    let mut params = vec![("client_id", options.client_id.as_str())];
+    if refresh_token.unsecure().is_empty() {
+        params.push(("grant_type", "client_credentials"));
+    } else {
+        params.push(("grant_type", "refresh_token"));
+        params.push(("refresh_token", refresh_token.unsecure()));
+    }
+    // Public clients, e.g. for a refresh token obtained with a device code, have no secret.
+    if !options.client_secret.unsecure().is_empty() {
+        params.push(("client_secret", options.client_secret.unsecure()));
+    }
+    if !options.scope.is_empty() {
+        params.push(("scope", options.scope.as_str()));
+    }
+    params
+}
+
+async fn request_token(
+    options: &SmtpOAuth2Options,
+    refresh_token: &SecUtf8,
+) -> Result<TokenResponse> {
+    let response = reqwest::Client::new()
+        .post(&options.token_url)
+        .form(&token_request_params(options, refresh_token))
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("while connecting to {}", options.token_url))?;
+    let status = response.status();
+    if !status.is_success() {
+        // The body explains the error, e.g. {"error": "invalid_grant", ...}.
+        anyhow::bail!(
+            "The token request failed with {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        );
+    }
+    Ok(response.json().await?)
+}
+
+/// Returns a valid access token, from the cache or from the provider.
+#[instrument(skip_all, level = "debug", err)]
+pub async fn get_access_token(options: &SmtpOAuth2Options) -> Result<SecUtf8> {
+    let refresh_token = {
+        let mut cached_token = CACHED_TOKEN.lock().unwrap();
+        match cached_token.as_ref() {
+            Some(cached) if same_client(&cached.options, options) => {
+                if cached.expiry > Instant::now() {
+                    return Ok(cached.access_token.clone());
+                }
+                cached
+                    .refresh_token
+                    .clone()
+                    .unwrap_or_else(|| options.refresh_token.clone())
+            }
+            _ => {
+                *cached_token = None;
+                options.refresh_token.clone()
+            }
+        }
+    };
+    debug!("Requesting an SMTP access token from {}", options.token_url);
+    let response = request_token(options, &refresh_token)
+        .await
+        .context("while fetching the SMTP access token")?;
+    let lifetime = response
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_LIFETIME);
+    let access_token = response.access_token.clone();
+    *CACHED_TOKEN.lock().unwrap() = Some(CachedToken {
+        options: options.clone(),
+        access_token: response.access_token,
+        expiry: Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN),
+        refresh_token: response.refresh_token.or_else(|| {
+            // Keep the previously rotated refresh token, if any.
+            (refresh_token.unsecure() != options.refresh_token.unsecure()).then_some(refresh_token)
+        }),
+    });
+    Ok(access_token)
+}
+
+/// Forces the next `get_access_token` to request a new token, e.g. when the server rejected the
+/// cached one because it was revoked.
+pub fn invalidate_access_token() {
+    if let Some(cached) = CACHED_TOKEN.lock().unwrap().as_mut() {
+        cached.expiry = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::SmtpOAuth2OptionsBuilder;
+
+    #[test]
+    fn test_token_request_params() {
+        let options = SmtpOAuth2OptionsBuilder::default()
+            .client_id("lldap".to_owned())
+            .client_secret(SecUtf8::from("secret"))
+            .scope("https://outlook.office365.com/.default".to_owned())
+            .build()
+            .unwrap();
+        assert_eq!(
+            token_request_params(&options, &SecUtf8::from("")),
+            vec![
+                ("client_id", "lldap"),
+                ("grant_type", "client_credentials"),
+                ("client_secret", "secret"),
+                ("scope", "https://outlook.office365.com/.default"),
+            ]
+        );
+        let options = SmtpOAuth2Options {
+            client_secret: SecUtf8::from(""),
+            scope: String::new(),
+            ..options
+        };
+        assert_eq!(
+            token_request_params(&options, &SecUtf8::from("refresh")),
+            vec![
+                ("client_id", "lldap"),
+                ("grant_type", "refresh_token"),
+                ("refresh_token", "refresh"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_client() {
+        let options = SmtpOAuth2OptionsBuilder::default()
+            .client_id("lldap".to_owned())
+            .refresh_token(SecUtf8::from("refresh"))
+            .build()
+            .unwrap();
+        assert!(same_client(&options, &options.clone()));
+        let rotated = SmtpOAuth2Options {
+            refresh_token: SecUtf8::from("other"),
+            ..options.clone()
+        };
+        assert!(!same_client(&options, &rotated));
+    }
+}
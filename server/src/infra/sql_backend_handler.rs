@@ -1,6 +1,7 @@
 use super::tcp_backend_handler::TcpBackendHandler;
 use crate::domain::{
     error::*,
+    handler::PasswordResetBackendHandler,
     model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
     sql_backend_handler::SqlBackendHandler,
     types::UserId,
@@ -46,8 +47,13 @@ impl TcpBackendHandler for SqlBackendHandler {
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)> {
-        debug!(?user);
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        user_agent: Option<String>,
+        ip_address: Option<String>,
+    ) -> Result<(String, chrono::Duration)> {
+        debug!(?user, ?user_agent, ?ip_address);
         // TODO: Initialize the rng only once. Maybe Arc<Cell>?
         let refresh_token = gen_random_string(100);
         let refresh_token_hash = {
@@ -58,10 +64,15 @@ impl TcpBackendHandler for SqlBackendHandler {
             s.finish()
         };
         let duration = chrono::Duration::days(30);
+        let now = chrono::Utc::now();
         let new_token = model::jwt_refresh_storage::Model {
             refresh_token_hash: refresh_token_hash as i64,
             user_id: user.clone(),
-            expiry_date: chrono::Utc::now() + duration,
+            expiry_date: now + duration,
+            creation_date: Some(now),
+            last_used: Some(now),
+            user_agent,
+            ip_address,
         }
         .into_active_model();
         new_token.insert(&self.sql_pool).await?;
@@ -71,13 +82,16 @@ impl TcpBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug")]
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool> {
         debug!(?user);
-        Ok(
-            model::JwtRefreshStorage::find_by_id(refresh_token_hash as i64)
-                .filter(JwtRefreshStorageColumn::UserId.eq(user))
-                .one(&self.sql_pool)
-                .await?
-                .is_some(),
-        )
+        let res = model::JwtRefreshStorage::update_many()
+            .col_expr(
+                JwtRefreshStorageColumn::LastUsed,
+                Expr::value(chrono::Utc::now()),
+            )
+            .filter(JwtRefreshStorageColumn::RefreshTokenHash.eq(refresh_token_hash as i64))
+            .filter(JwtRefreshStorageColumn::UserId.eq(user))
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(res.rows_affected > 0)
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -115,27 +129,25 @@ impl TcpBackendHandler for SqlBackendHandler {
 
     #[instrument(skip_all, level = "debug")]
     async fn start_password_reset(&self, user: &UserId) -> Result<Option<String>> {
-        debug!(?user);
-        if model::User::find_by_id(user.clone())
-            .one(&self.sql_pool)
-            .await?
-            .is_none()
-        {
-            debug!("User not found");
-            return Ok(None);
-        }
-
-        let token = gen_random_string(100);
-        let duration = chrono::Duration::minutes(10);
+        self.start_password_setup(user, chrono::Duration::minutes(10))
+            .await
+    }
 
-        let new_token = model::password_reset_tokens::Model {
-            token: token.clone(),
-            user_id: user.clone(),
-            expiry_date: chrono::Utc::now() + duration,
+    #[instrument(skip_all, level = "debug")]
+    async fn start_password_setup(
+        &self,
+        user: &UserId,
+        validity: chrono::Duration,
+    ) -> Result<Option<String>> {
+        debug!(?user);
+        match self.create_password_reset_token(user, validity).await {
+            Ok(token) => Ok(Some(token)),
+            Err(DomainError::EntityNotFound(_)) => {
+                debug!("User not found");
+                Ok(None)
+            }
+            Err(e) => Err(e),
         }
-        .into_active_model();
-        new_token.insert(&self.sql_pool).await?;
-        Ok(Some(token))
     }
 
     #[instrument(skip_all, level = "debug", ret)]
@@ -162,3 +174,62 @@ impl TcpBackendHandler for SqlBackendHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::sql_backend_handler::tests::*, infra::jwt_sql_tables};
+
+    #[tokio::test]
+    async fn test_password_reset_tokens() {
+        let fixture = TestFixture::new().await;
+        jwt_sql_tables::init_table(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        let bob = UserId::new("bob");
+        assert_eq!(
+            fixture
+                .handler
+                .start_password_reset(&UserId::new("unknown"))
+                .await
+                .unwrap(),
+            None
+        );
+        let first_token = fixture
+            .handler
+            .start_password_reset(&bob)
+            .await
+            .unwrap()
+            .unwrap();
+        let second_token = fixture
+            .handler
+            .start_password_reset(&bob)
+            .await
+            .unwrap()
+            .unwrap();
+        // Requesting a new link invalidates the previous one.
+        assert!(fixture
+            .handler
+            .get_user_id_for_password_reset_token(&first_token)
+            .await
+            .is_err());
+        assert_eq!(
+            fixture
+                .handler
+                .get_user_id_for_password_reset_token(&second_token)
+                .await
+                .unwrap(),
+            bob
+        );
+        fixture
+            .handler
+            .delete_password_reset_token(&second_token)
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .get_user_id_for_password_reset_token(&second_token)
+            .await
+            .is_err());
+    }
+}
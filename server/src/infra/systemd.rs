@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use sd_notify::NotifyState;
+use std::{collections::HashMap, net::TcpListener, time::Duration};
+use tracing::{debug, info, warn};
+
+/// The listening sockets passed by systemd with socket activation, by name: the
+/// `FileDescriptorName=` of the socket units, "ldap", "ldaps", "http" or "https". Several sockets
+/// can have the same name, e.g. one per address. The servers without one bind their own sockets.
+#[derive(Default)]
+pub struct ActivatedSockets(HashMap<String, Vec<TcpListener>>);
+
+impl ActivatedSockets {
+    pub fn from_env() -> Result<Self> {
+        let mut listen_fds = listenfd::ListenFd::from_env();
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+        let mut sockets = HashMap::new();
+        for (index, name) in names.split(':').enumerate().take(listen_fds.len()) {
+            if let Some(listener) = listen_fds
+                .take_tcp_listener(index)
+                .with_context(|| format!("while reading the systemd socket {}", name))?
+            {
+                listener.set_nonblocking(true)?;
+                info!("Using the {} socket passed by systemd", name);
+                sockets
+                    .entry(name.to_owned())
+                    .or_insert_with(Vec::new)
+                    .push(listener);
+            }
+        }
+        Ok(Self(sockets))
+    }
+
+    /// The sockets with this name, if any.
+    pub fn take(&mut self, name: &str) -> Vec<TcpListener> {
+        self.0.remove(name).unwrap_or_default()
+    }
+
+    /// Logs the sockets that no server used, most likely because of a typo in their name.
+    pub fn warn_unused(&self) {
+        for name in self.0.keys() {
+            warn!("Ignoring the {} socket passed by systemd", name);
+        }
+    }
+}
+
+/// Tells systemd that the server is ready, and keeps its watchdog happy if it is enabled. This is
+/// a no-op when not started by systemd.
+pub fn notify_ready() {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        warn!("Could not notify systemd: {:#}", e);
+        return;
+    }
+    let mut watchdog_usec = 0;
+    if sd_notify::watchdog_enabled(false, &mut watchdog_usec) {
+        // Pinged twice per period, to not be late.
+        let period = Duration::from_micros(watchdog_usec) / 2;
+        debug!(?period, "Enabling the systemd watchdog");
+        actix_rt::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+                    warn!("Could not ping the systemd watchdog: {:#}", e);
+                }
+            }
+        });
+    }
+}
+
+pub fn notify_stopping() {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Stopping]) {
+        warn!("Could not notify systemd: {:#}", e);
+    }
+}
@@ -6,7 +6,14 @@ use crate::domain::{error::Result, types::UserId};
 #[async_trait]
 pub trait TcpBackendHandler {
     async fn get_jwt_blacklist(&self) -> anyhow::Result<HashSet<u64>>;
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)>;
+    /// Creates a refresh token for a new login session, recording the client details when known.
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        user_agent: Option<String>,
+        ip_address: Option<String>,
+    ) -> Result<(String, chrono::Duration)>;
+    /// Checks that the refresh token is valid for the user, and marks the session as used.
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool>;
     async fn blacklist_jwts(&self, user: &UserId) -> Result<HashSet<u64>>;
     async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()>;
@@ -15,6 +22,14 @@ pub trait TcpBackendHandler {
     /// If the user doesn't exist, returns `Ok(None)`, otherwise `Ok(Some(token))`.
     async fn start_password_reset(&self, user: &UserId) -> Result<Option<String>>;
 
+    /// Same as `start_password_reset`, with a token valid for longer, for the users that don't
+    /// have a password yet.
+    async fn start_password_setup(
+        &self,
+        user: &UserId,
+        validity: chrono::Duration,
+    ) -> Result<Option<String>>;
+
     /// Get the user ID associated with a password reset token.
     async fn get_user_id_for_password_reset_token(&self, token: &str) -> Result<UserId>;
 
@@ -36,21 +51,155 @@ mockall::mock! {
     #[async_trait]
     impl GroupBackendHandler for TestTcpBackendHandler {
         async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+        async fn list_groups_sorted(&self, filters: Option<GroupRequestFilter>, order: GroupOrder) -> Result<Vec<Group>>;
         async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, group_name: &str) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        async fn add_group_to_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, parent_id: GroupId, child_id: GroupId) -> Result<()>;
     }
     #[async_trait]
     impl UserBackendHandler for TestBackendHandler {
         async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_page(&self, filters: Option<UserRequestFilter>, get_groups: bool, order: UserOrder, offset: u64, limit: Option<u64>) -> Result<UserPage>;
         async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
         async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+        async fn create_users(&self, requests: Vec<CreateUserRequest>) -> Result<Vec<Result<()>>>;
+        fn check_user_id(&self, user_id: &str) -> Result<()>;
         async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+        async fn add_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+        async fn remove_ssh_public_key(&self, user_id: &UserId, key: &str) -> Result<()>;
+        async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<()>;
         async fn delete_user(&self, user_id: &UserId) -> Result<()>;
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+        async fn get_users_groups(&self, user_ids: &[UserId]) -> Result<std::collections::HashMap<UserId, HashSet<GroupDetails>>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn add_user_to_group_until(&self, user_id: &UserId, group_id: GroupId, expiry_date: DateTime) -> Result<()>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+        async fn remove_users_from_group(&self, user_ids: &[UserId], group_id: GroupId) -> Result<()>;
+        async fn record_login(&self, user_id: &UserId, kind: LoginKind) -> Result<()>;
+    }
+    #[async_trait]
+    impl SchemaBackendHandler for TestTcpBackendHandler {
+        async fn get_user_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_user_attribute(&self, name: &str) -> Result<()>;
+        async fn get_group_attributes_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_group_attribute(&self, name: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl ChangeLogBackendHandler for TestTcpBackendHandler {
+        async fn list_changes_since(&self, change_id: i32) -> Result<Vec<Change>>;
+        async fn list_changes_page(&self, change_id: i32, limit: u64) -> Result<Vec<Change>>;
+        async fn get_last_change_id(&self) -> Result<i32>;
+    }
+    #[async_trait]
+    impl AuditLogBackendHandler for TestTcpBackendHandler {
+        async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+        async fn list_audit_logs(&self, filter: AuditLogFilter, offset: u64, limit: Option<u64>) -> Result<AuditLogPage>;
+    }
+    #[async_trait]
+    impl ApiTokenBackendHandler for TestTcpBackendHandler {
+        async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+        async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+        async fn delete_api_token(&self, token_id: i32) -> Result<()>;
+        async fn validate_api_token(&self, token: &str) -> Result<ApiToken>;
+    }
+    #[async_trait]
+    impl ServiceAccountBackendHandler for TestTcpBackendHandler {
+        async fn create_service_account(&self, request: CreateServiceAccountRequest) -> Result<(ServiceAccount, String)>;
+        async fn list_service_accounts(&self) -> Result<Vec<ServiceAccount>>;
+        async fn update_service_account(&self, request: UpdateServiceAccountRequest) -> Result<()>;
+        async fn reset_service_account_password(&self, service_id: &str) -> Result<String>;
+        async fn delete_service_account(&self, service_id: &str) -> Result<()>;
+        async fn bind_service_account(&self, service_id: &str, password: &str) -> Result<ServiceAccount>;
+    }
+    #[async_trait]
+    impl SessionBackendHandler for TestTcpBackendHandler {
+        async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+        async fn delete_user_session(&self, user_id: &UserId, session_id: i64) -> Result<()>;
+        async fn delete_all_user_sessions(&self, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl PasswordPolicyBackendHandler for TestTcpBackendHandler {
+        fn get_password_policy(&self) -> crate::infra::configuration::PasswordPolicyOptions;
+        async fn check_password_policy(&self, user_id: &UserId, password: &str) -> Result<()>;
+        async fn add_password_to_history(&self, user_id: &UserId, password: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl AccountLockoutBackendHandler for TestTcpBackendHandler {
+        async fn get_account_lockout(&self, user_id: &UserId) -> Result<Option<DateTime>>;
+        async fn unlock_account(&self, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl PasswordResetBackendHandler for TestTcpBackendHandler {
+        async fn create_password_reset_token(&self, user_id: &UserId, validity: chrono::Duration) -> Result<String>;
+    }
+    #[async_trait]
+    impl WebauthnCredentialBackendHandler for TestTcpBackendHandler {
+        async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+        async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl InvitationBackendHandler for TestTcpBackendHandler {
+        async fn create_invitation(&self, request: CreateInvitationRequest) -> Result<String>;
+        async fn get_invitation(&self, token: &str) -> Result<Invitation>;
+        async fn accept_invitation(&self, token: &str, user_id: &UserId) -> Result<()>;
+        async fn delete_invitation(&self, token: &str) -> Result<()>;
+    }
+    #[async_trait]
+    impl PendingUserBackendHandler for TestTcpBackendHandler {
+        async fn create_pending_user(&self, request: CreatePendingUserRequest) -> Result<()>;
+        async fn list_pending_users(&self) -> Result<Vec<PendingUser>>;
+        async fn approve_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+        async fn reject_pending_user(&self, user_id: &UserId) -> Result<PendingUser>;
+    }
+    #[async_trait]
+    impl RecycleBinBackendHandler for TestTcpBackendHandler {
+        async fn list_deleted_users(&self) -> Result<Vec<DeletedUser>>;
+        async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+        async fn purge_user(&self, user_id: &UserId) -> Result<()>;
+        async fn list_deleted_groups(&self) -> Result<Vec<DeletedGroup>>;
+        async fn restore_group(&self, group_id: GroupId) -> Result<()>;
+        async fn purge_group(&self, group_id: GroupId) -> Result<()>;
+    }
+    #[async_trait]
+    impl RoleBackendHandler for TestTcpBackendHandler {
+        async fn list_roles(&self) -> Result<Vec<Role>>;
+        async fn create_role(&self, request: CreateRoleRequest) -> Result<i32>;
+        async fn delete_role(&self, role_id: i32) -> Result<()>;
+        async fn add_role_to_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+        async fn remove_role_from_group(&self, role_id: i32, group_id: GroupId) -> Result<()>;
+        async fn get_user_capabilities(&self, user_id: &UserId) -> Result<Capabilities>;
+    }
+    #[async_trait]
+    impl GroupManagerBackendHandler for TestTcpBackendHandler {
+        async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+        async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl OidcBackendHandler for TestTcpBackendHandler {
+        async fn create_oidc_client(&self, request: CreateOidcClientRequest) -> Result<String>;
+        async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>>;
+        async fn get_oidc_client(&self, client_id: &str) -> Result<OidcClient>;
+        async fn delete_oidc_client(&self, client_id: &str) -> Result<()>;
+        async fn list_oidc_group_claims(&self, client_id: &str) -> Result<Vec<OidcGroupClaim>>;
+        async fn add_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+        async fn remove_oidc_group_claim(&self, claim: OidcGroupClaim) -> Result<()>;
+        async fn create_oidc_authorization_code(&self, code: OidcAuthorizationCode) -> Result<String>;
+        async fn consume_oidc_authorization_code(&self, code: &str) -> Result<OidcAuthorizationCode>;
+    }
+    #[async_trait]
+    impl StatisticsBackendHandler for TestTcpBackendHandler {
+        async fn get_statistics(&self) -> Result<DirectoryStatistics>;
+    }
+    #[async_trait]
+    impl DirectoryChangesBackendHandler for TestTcpBackendHandler {
+        async fn apply_changes(&self, operations: Vec<DirectoryOperation>) -> Result<Vec<GroupId>>;
     }
     #[async_trait]
     impl BackendHandler for TestTcpBackendHandler {}
@@ -1,27 +1,50 @@
 use crate::{
     domain::{
         error::DomainError,
-        handler::{BackendHandler, LoginHandler},
+        handler::{AuditActor, BackendHandler, LoginHandler},
         opaque_handler::OpaqueHandler,
+        types::AuditSource,
+        webauthn_handler::WebauthnHandler,
     },
     infra::{
-        auth_service,
-        configuration::{Configuration, MailOptions},
+        acme::{self, AcmeChallenges},
+        auth_service::{self, JwtKeys, ValidationResults},
+        change_events::ChangeEventBus,
+        config_reload::{ConfigReloader, SharedMailOptions},
+        configuration::{
+            AvatarOptions, Configuration, CorsOptions, GraphqlOptions, MailOptions, OidcOptions,
+            RegularUserVisibility,
+        },
+        db_cleaner::SchedulerStatus,
+        graphql::persisted_queries::PersistedQueries,
+        healthcheck::{self, HealthChecker},
+        http_rate_limiter::{HttpRateLimiter, HttpRateLimiterFactory},
+        listeners,
         logging::CustomRootSpanBuilder,
+        metrics::{self, Metrics},
+        oidc_service,
+        read_only::{ReadOnlyGuardFactory, ReadOnlyMode},
+        scim_service,
+        systemd::ActivatedSockets,
         tcp_backend_handler::*,
+        tls_certificate::{https_server_config, ReloadableCertificate},
+        trusted_proxies::TrustedProxies,
     },
 };
+use actix_cors::Cors;
 use actix_files::{Files, NamedFile};
-use actix_http::HttpServiceBuilder;
+use actix_http::{error::DispatchError, HttpServiceBuilder, Protocol};
+use actix_rt::net::{TcpStream, UnixStream};
 use actix_server::ServerBuilder;
-use actix_service::map_config;
-use actix_web::{dev::AppConfig, web, App, HttpResponse};
+use actix_service::{fn_service, map_config, ServiceFactoryExt};
+use actix_web::{dev::AppConfig, middleware::Condition, web, App, HttpResponse};
 use anyhow::{Context, Result};
-use hmac::{Hmac, NewMac};
-use sha2::Sha512;
 use std::collections::HashSet;
+use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_rustls::TlsAcceptor;
 use tracing::info;
 
 async fn index() -> actix_web::Result<NamedFile> {
@@ -46,15 +69,18 @@ pub type TcpResult<T> = std::result::Result<T, TcpError>;
 pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
     match error {
         TcpError::DomainError(ref de) => match de {
-            DomainError::AuthenticationError(_) | DomainError::AuthenticationProtocolError(_) => {
-                HttpResponse::Unauthorized()
-            }
+            DomainError::AuthenticationError(_)
+            | DomainError::AccountLocked(_)
+            | DomainError::AuthenticationProtocolError(_) => HttpResponse::Unauthorized(),
             DomainError::DatabaseError(_)
             | DomainError::InternalError(_)
             | DomainError::UnknownCryptoError(_) => HttpResponse::InternalServerError(),
             DomainError::Base64DecodeError(_)
             | DomainError::BinarySerializationError(_)
-            | DomainError::EntityNotFound(_) => HttpResponse::BadRequest(),
+            | DomainError::EntityNotFound(_)
+            | DomainError::InvalidRequest(_)
+            | DomainError::WeakPassword { .. } => HttpResponse::BadRequest(),
+            DomainError::LastAdmin(_) => HttpResponse::Conflict(),
         },
         TcpError::BadRequest(_) => HttpResponse::BadRequest(),
         TcpError::InternalServerError(_) => HttpResponse::InternalServerError(),
@@ -63,30 +89,124 @@ pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
     .body(error.to_string())
 }
 
+// The scheme, host and port of a URL.
+fn url_origin(url: &str) -> &str {
+    let host_start = url.find("://").map(|i| i + 3).unwrap_or(0);
+    match url[host_start..].find('/') {
+        Some(i) => &url[..host_start + i],
+        None => url,
+    }
+}
+
+fn cors(options: &CorsOptions, server_url: &str) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+        .allowed_headers(options.allowed_headers.iter().map(String::as_str))
+        .max_age(options.max_age_seconds);
+    if options.allowed_origins.iter().any(|origin| origin == "*") {
+        cors = cors.allow_any_origin();
+    } else {
+        cors = cors.allowed_origin(url_origin(server_url));
+        for origin in &options.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+    if options.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+#[allow(clippy::too_many_arguments)]
 fn http_config<Backend>(
     cfg: &mut web::ServiceConfig,
     backend_handler: Backend,
-    jwt_secret: secstr::SecUtf8,
-    jwt_blacklist: HashSet<u64>,
+    jwt_keys: JwtKeys,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
     server_url: String,
-    mail_options: MailOptions,
+    mail_options: SharedMailOptions,
+    enable_self_registration: bool,
+    regular_user_visibility: RegularUserVisibility,
+    oidc_options: OidcOptions,
+    cors_options: CorsOptions,
+    change_events: ChangeEventBus,
+    rate_limiter: HttpRateLimiter,
+    metrics: Metrics,
+    enable_metrics: bool,
+    config_reloader: ConfigReloader,
+    acme_challenges: AcmeChallenges,
+    health_checker: HealthChecker,
+    scheduler_status: SchedulerStatus,
+    graphql_timeout: Option<Duration>,
+    graphql_options: GraphqlOptions,
+    persisted_queries: PersistedQueries,
+    avatar_options: AvatarOptions,
+    read_only: ReadOnlyMode,
 ) where
-    Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Sync + 'static,
+    Backend: TcpBackendHandler
+        + BackendHandler
+        + LoginHandler
+        + OpaqueHandler
+        + WebauthnHandler
+        + Sync
+        + 'static,
 {
+    let cors = Condition::new(
+        !cors_options.allowed_origins.is_empty(),
+        cors(&cors_options, &server_url),
+    );
     cfg.app_data(web::Data::new(AppState::<Backend> {
         backend_handler,
-        jwt_key: Hmac::new_varkey(jwt_secret.unsecure().as_bytes()).unwrap(),
-        jwt_blacklist: RwLock::new(jwt_blacklist),
+        jwt_keys,
+        jwt_blacklist,
         server_url,
         mail_options,
+        enable_self_registration,
+        regular_user_visibility,
+        oidc_options,
+        change_events,
+        metrics,
+        config_reloader,
+        acme_challenges,
+        health_checker,
+        scheduler_status,
+        graphql_timeout,
+        graphql_options,
+        persisted_queries,
+        avatar_options,
     }))
-    .route("/health", web::get().to(|| HttpResponse::Ok().finish()))
-    .service(web::scope("/auth").configure(auth_service::configure_server::<Backend>))
+    .app_data(web::Data::new(read_only))
+    .configure(healthcheck::configure_endpoint::<Backend>)
+    .configure(acme::configure_endpoint::<Backend>);
+    if enable_metrics {
+        cfg.configure(metrics::configure_endpoint::<Backend>);
+    }
+    cfg.service(
+        web::scope("/auth")
+            .wrap(HttpRateLimiterFactory::new(rate_limiter.clone()))
+            .configure(auth_service::configure_server::<Backend>),
+    )
+    // OpenID Connect provider.
+    .configure(oidc_service::configure_discovery::<Backend>)
+    .service(
+        web::scope("/oidc")
+            .wrap(HttpRateLimiterFactory::new(rate_limiter))
+            .configure(oidc_service::configure_server::<Backend>),
+    )
     // API endpoint.
     .service(
         web::scope("/api")
             .wrap(auth_service::CookieToHeaderTranslatorFactory)
-            .configure(super::graphql::api::configure_endpoint::<Backend>),
+            .wrap(cors)
+            .configure(super::graphql::api::configure_endpoint::<Backend>)
+            .configure(super::rest_api::configure_endpoint::<Backend>)
+            .configure(super::config_reload::configure_endpoint::<Backend>),
+    )
+    // SCIM provisioning endpoint.
+    .service(
+        web::scope("/scim/v2")
+            .wrap(ReadOnlyGuardFactory)
+            .configure(scim_service::configure_server::<Backend>),
     )
     // Serve the /pkg path with the compiled WASM app.
     .service(Files::new("/pkg", "./app/pkg"))
@@ -104,61 +224,243 @@ fn http_config<Backend>(
 
 pub(crate) struct AppState<Backend> {
     pub backend_handler: Backend,
-    pub jwt_key: Hmac<Sha512>,
-    pub jwt_blacklist: RwLock<HashSet<u64>>,
+    pub jwt_keys: JwtKeys,
+    /// Shared by all the workers and servers, so that a logout applies everywhere.
+    pub jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
     pub server_url: String,
-    pub mail_options: MailOptions,
+    pub mail_options: SharedMailOptions,
+    pub enable_self_registration: bool,
+    pub regular_user_visibility: RegularUserVisibility,
+    pub oidc_options: OidcOptions,
+    pub change_events: ChangeEventBus,
+    pub metrics: Metrics,
+    pub config_reloader: ConfigReloader,
+    pub acme_challenges: AcmeChallenges,
+    pub health_checker: HealthChecker,
+    pub scheduler_status: SchedulerStatus,
+    /// The limit on the duration of a GraphQL request, `None` for no limit.
+    pub graphql_timeout: Option<Duration>,
+    /// The limits on the depth and complexity of the GraphQL queries.
+    pub graphql_options: GraphqlOptions,
+    pub persisted_queries: PersistedQueries,
+    pub avatar_options: AvatarOptions,
+}
+
+impl<Backend> AppState<Backend> {
+    /// The current SMTP options, which can change when the configuration is reloaded.
+    pub fn mail_options(&self) -> MailOptions {
+        self.mail_options.read().unwrap().clone()
+    }
 }
 
+impl<Backend: BackendHandler> AppState<Backend> {
+    /// A copy of the backend handler, recording its mutations in the audit log on behalf of the
+    /// user.
+    pub fn backend_handler_for(
+        &self,
+        validation_result: &ValidationResults,
+        source: AuditSource,
+    ) -> Backend {
+        let mut backend_handler = self.backend_handler.clone();
+        backend_handler.set_audit_actor(AuditActor::new(validation_result.user.clone(), source));
+        backend_handler
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn build_tcp_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    change_events: ChangeEventBus,
+    metrics: Metrics,
+    rate_limiter: HttpRateLimiter,
+    mail_options: SharedMailOptions,
+    config_reloader: ConfigReloader,
+    acme_challenges: AcmeChallenges,
+    health_checker: HealthChecker,
+    scheduler_status: SchedulerStatus,
+    read_only: ReadOnlyMode,
+    https_certificate: Option<Arc<ReloadableCertificate>>,
+    sockets: &mut ActivatedSockets,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
-    Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Sync + 'static,
+    Backend: TcpBackendHandler
+        + BackendHandler
+        + LoginHandler
+        + OpaqueHandler
+        + WebauthnHandler
+        + Sync
+        + 'static,
 {
-    let jwt_secret = config.jwt_secret.clone();
-    let jwt_blacklist = backend_handler
-        .get_jwt_blacklist()
-        .await
-        .context("while getting the jwt blacklist")?;
+    let jwt_keys = JwtKeys::new(
+        &config.jwt_key_id,
+        &config.jwt_secret,
+        &config.previous_jwt_secrets,
+    );
+    let jwt_blacklist = Arc::new(RwLock::new(
+        backend_handler
+            .get_jwt_blacklist()
+            .await
+            .context("while getting the jwt blacklist")?,
+    ));
     let server_url = config.http_url.clone();
-    let mail_options = config.smtp_options.clone();
+    let enable_self_registration = config.enable_self_registration;
+    let regular_user_visibility = config.regular_user_visibility;
+    let oidc_options = config.oidc_options.clone();
+    let cors_options = config.cors_options.clone();
+    let enable_metrics = config.enable_metrics;
+    let graphql_timeout = config.timeout_options.graphql_timeout();
+    let graphql_options = config.graphql_options.clone();
+    let persisted_queries = PersistedQueries::new(&config.graphql_options)
+        .context("while reading the persisted GraphQL queries")?;
+    let avatar_options = config.avatar_options.clone();
+    let trusted_proxies =
+        TrustedProxies::new(&config.trusted_proxies).context("while reading trusted_proxies")?;
+    // Builds the HTTP service of a worker, shared by the HTTP and HTTPS servers.
+    let make_http_service = move |trusted_proxies: web::Data<TrustedProxies>| {
+        let backend_handler = backend_handler.clone();
+        let jwt_keys = jwt_keys.clone();
+        let jwt_blacklist = jwt_blacklist.clone();
+        let server_url = server_url.clone();
+        let mail_options = mail_options.clone();
+        let oidc_options = oidc_options.clone();
+        let cors_options = cors_options.clone();
+        let change_events = change_events.clone();
+        let rate_limiter = rate_limiter.clone();
+        let metrics = metrics.clone();
+        let config_reloader = config_reloader.clone();
+        let acme_challenges = acme_challenges.clone();
+        let health_checker = health_checker.clone();
+        let scheduler_status = scheduler_status.clone();
+        let graphql_options = graphql_options.clone();
+        let persisted_queries = persisted_queries.clone();
+        let avatar_options = avatar_options.clone();
+        let read_only = read_only.clone();
+        HttpServiceBuilder::new().finish(map_config(
+            App::new()
+                .app_data(trusted_proxies)
+                .wrap(tracing_actix_web::TracingLogger::<CustomRootSpanBuilder>::new())
+                .configure(move |cfg| {
+                    http_config(
+                        cfg,
+                        backend_handler,
+                        jwt_keys,
+                        jwt_blacklist,
+                        server_url,
+                        mail_options,
+                        enable_self_registration,
+                        regular_user_visibility,
+                        oidc_options,
+                        cors_options,
+                        change_events,
+                        rate_limiter,
+                        metrics,
+                        enable_metrics,
+                        config_reloader,
+                        acme_challenges,
+                        health_checker,
+                        scheduler_status,
+                        graphql_timeout,
+                        graphql_options,
+                        persisted_queries,
+                        avatar_options,
+                        read_only,
+                    )
+                }),
+            |_| AppConfig::default(),
+        ))
+    };
+    let make_https_service = make_http_service.clone();
+    let make_unix_socket_service = make_http_service.clone();
     info!("Starting the API/web server on port {}", config.http_port);
-    server_builder
-        .bind(
-            "http",
-            (config.http_host.clone(), config.http_port),
-            move || {
-                let backend_handler = backend_handler.clone();
-                let jwt_secret = jwt_secret.clone();
-                let jwt_blacklist = jwt_blacklist.clone();
-                let server_url = server_url.clone();
-                let mail_options = mail_options.clone();
-                HttpServiceBuilder::new()
-                    .finish(map_config(
-                        App::new()
-                            .wrap(tracing_actix_web::TracingLogger::<CustomRootSpanBuilder>::new())
-                            .configure(move |cfg| {
-                                http_config(
-                                    cfg,
-                                    backend_handler,
-                                    jwt_secret,
-                                    jwt_blacklist,
-                                    server_url,
-                                    mail_options,
-                                )
-                            }),
-                        |_| AppConfig::default(),
-                    ))
-                    .tcp()
-            },
+    let http_binder = {
+        let trusted_proxies = web::Data::new(trusted_proxies.clone());
+        move || make_http_service(trusted_proxies.clone()).tcp()
+    };
+    let backlog = config.runtime_options.backlog;
+    let mut server_builder = listeners::listen(
+        server_builder,
+        sockets,
+        "http",
+        &config.http_host,
+        config.http_port,
+        backlog,
+        http_binder,
+    )
+    .with_context(|| {
+        format!(
+            "While bringing up the TCP server with port {}",
+            config.http_port
+        )
+    })?;
+    let unix_socket_options = &config.unix_socket_options;
+    if unix_socket_options.enabled {
+        info!(
+            "Starting the API/web server on the socket {}",
+            unix_socket_options.path
+        );
+        let trusted_proxies = web::Data::new(trusted_proxies.for_unix_socket());
+        server_builder = server_builder
+            .bind_uds("unix_socket", &unix_socket_options.path, move || {
+                fn_service(|stream: UnixStream| async {
+                    Ok::<_, DispatchError>((stream, Protocol::Http1, None))
+                })
+                .and_then(make_unix_socket_service(trusted_proxies.clone()))
+            })
+            .with_context(|| format!("while binding to the socket {}", unix_socket_options.path))?;
+        std::fs::set_permissions(
+            &unix_socket_options.path,
+            std::fs::Permissions::from_mode(unix_socket_options.permissions_mode()?),
         )
         .with_context(|| {
             format!(
-                "While bringing up the TCP server with port {}",
-                config.http_port
+                "while setting the permissions of {}",
+                unix_socket_options.path
             )
+        })?;
+    }
+    let certificate = match https_certificate {
+        Some(certificate) => certificate,
+        None => return Ok(server_builder),
+    };
+    let trusted_proxies = web::Data::new(trusted_proxies.for_https());
+    let tls_acceptor: TlsAcceptor =
+        Arc::new(https_server_config(&config.https_options, certificate)?).into();
+    info!(
+        "Starting the HTTPS server on port {}",
+        config.https_options.port
+    );
+    let https_binder = move || {
+        let tls_acceptor = tls_acceptor.clone();
+        fn_service(move |stream: TcpStream| {
+            let tls_acceptor = tls_acceptor.clone();
+            async move {
+                let peer_address = stream.peer_addr().ok();
+                let tls_stream = tls_acceptor.accept(stream).await?;
+                Ok::<_, std::io::Error>((tls_stream, Protocol::Http1, peer_address))
+            }
         })
+        .map_err(DispatchError::Io)
+        .and_then(make_https_service(trusted_proxies.clone()))
+    };
+    listeners::listen(
+        server_builder,
+        sockets,
+        "https",
+        config
+            .https_options
+            .host
+            .as_ref()
+            .unwrap_or(&config.http_host),
+        config.https_options.port,
+        backlog,
+        https_binder,
+    )
+    .with_context(|| {
+        format!(
+            "While bringing up the HTTPS server with port {}",
+            config.https_options.port
+        )
+    })
 }
@@ -0,0 +1,184 @@
+use crate::infra::configuration::{HttpsOptions, TlsVersion};
+use anyhow::{anyhow, Context, Result};
+use chrono::TimeZone;
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    version, Certificate, PrivateKey, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion,
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    sync::{Arc, RwLock},
+};
+use tracing::info;
+
+fn read_private_key(key_file: &str) -> Result<PrivateKey> {
+    use rustls_pemfile::{pkcs8_private_keys, rsa_private_keys};
+    pkcs8_private_keys(&mut BufReader::new(File::open(key_file)?))
+        .map_err(anyhow::Error::from)
+        .and_then(|keys| {
+            keys.into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No PKCS8 key"))
+        })
+        .or_else(|_| {
+            rsa_private_keys(&mut BufReader::new(File::open(key_file)?))
+                .map_err(anyhow::Error::from)
+                .and_then(|keys| {
+                    keys.into_iter()
+                        .next()
+                        .ok_or_else(|| anyhow!("No PKCS1 key"))
+                })
+        })
+        .with_context(|| {
+            format!(
+                "Cannot read either PKCS1 or PKCS8 private key from {}",
+                key_file
+            )
+        })
+        .map(PrivateKey)
+}
+
+fn read_certified_key(cert_file: &str, key_file: &str) -> Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert_file).with_context(|| format!("while opening {}", cert_file))?,
+    ))
+    .with_context(|| format!("while reading the certificates from {}", cert_file))?
+    .into_iter()
+    .map(Certificate)
+    .collect::<Vec<_>>();
+    if certs.is_empty() {
+        anyhow::bail!("No certificate in {}", cert_file);
+    }
+    let private_key = read_private_key(key_file)?;
+    let signing_key = sign::any_supported_type(&private_key)
+        .map_err(|_| anyhow!("Unsupported private key type in {}", key_file))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// The certificate of a TLS server (LDAPS or HTTPS). The files are read again on `reload`, and the new
+/// connections use the new certificate, so that renewals don't require a restart.
+pub struct ReloadableCertificate {
+    cert_file: String,
+    key_file: String,
+    certified_key: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl ReloadableCertificate {
+    pub fn new(cert_file: &str, key_file: &str) -> Result<Self> {
+        Ok(Self {
+            cert_file: cert_file.to_owned(),
+            key_file: key_file.to_owned(),
+            certified_key: RwLock::new(Some(Arc::new(read_certified_key(cert_file, key_file)?))),
+        })
+    }
+
+    /// Starts without a certificate, for when it is not provisioned yet: the TLS handshakes fail
+    /// until the first successful `reload`.
+    pub fn new_pending(cert_file: &str, key_file: &str) -> Self {
+        Self {
+            cert_file: cert_file.to_owned(),
+            key_file: key_file.to_owned(),
+            certified_key: RwLock::new(None),
+        }
+    }
+
+    /// Reads the certificate and key files again. The current certificate is kept if they are
+    /// invalid, e.g. in the middle of a renewal.
+    pub fn reload(&self) -> Result<()> {
+        let certified_key = read_certified_key(&self.cert_file, &self.key_file)
+            .with_context(|| format!("while reloading the certificate from {}", self.cert_file))?;
+        *self.certified_key.write().unwrap() = Some(Arc::new(certified_key));
+        info!("Reloaded the certificate from {}", self.cert_file);
+        Ok(())
+    }
+
+    /// Expiry date of the current certificate, if there is one.
+    pub fn expiry_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        let certified_key = self.certified_key.read().unwrap().clone()?;
+        certificate_expiry_date(certified_key.cert.first()?)
+    }
+
+    pub fn cert_file(&self) -> &str {
+        &self.cert_file
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.certified_key.read().unwrap().clone()
+    }
+}
+
+/// Expiry date of the certificate, if it can be parsed.
+pub fn certificate_expiry_date(certificate: &Certificate) -> Option<chrono::DateTime<chrono::Utc>> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(&certificate.0).ok()?;
+    chrono::Utc
+        .timestamp_opt(certificate.validity().not_after.timestamp(), 0)
+        .single()
+}
+
+fn find_cipher_suite(name: &str) -> Result<SupportedCipherSuite> {
+    rustls::ALL_CIPHER_SUITES
+        .iter()
+        .find(|suite| format!("{:?}", suite.suite()) == name)
+        .copied()
+        .ok_or_else(|| anyhow!("Unknown cipher suite: {}", name))
+}
+
+/// The TLS configuration of the HTTPS server, restricted to the versions and cipher suites of the
+/// options.
+pub fn https_server_config(
+    options: &HttpsOptions,
+    certificate: Arc<ReloadableCertificate>,
+) -> Result<ServerConfig> {
+    let cipher_suites = if options.cipher_suites.is_empty() {
+        rustls::DEFAULT_CIPHER_SUITES.to_vec()
+    } else {
+        options
+            .cipher_suites
+            .iter()
+            .map(|name| find_cipher_suite(name))
+            .collect::<Result<Vec<_>>>()?
+    };
+    let versions: &[&'static SupportedProtocolVersion] = match options.min_tls_version {
+        TlsVersion::Tls12 => &[&version::TLS13, &version::TLS12],
+        TlsVersion::Tls13 => &[&version::TLS13],
+    };
+    Ok(ServerConfig::builder()
+        .with_cipher_suites(&cipher_suites)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .context("while selecting the TLS versions and cipher suites")?
+        .with_no_client_auth()
+        .with_cert_resolver(certificate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_server_config() {
+        let certificate = Arc::new(ReloadableCertificate::new_pending("cert.pem", "key.pem"));
+        let options = HttpsOptions {
+            min_tls_version: TlsVersion::Tls13,
+            cipher_suites: vec!["TLS13_AES_256_GCM_SHA384".to_owned()],
+            ..Default::default()
+        };
+        https_server_config(&options, certificate.clone()).unwrap();
+        // There is no TLS 1.3 cipher suite left.
+        let options = HttpsOptions {
+            min_tls_version: TlsVersion::Tls13,
+            cipher_suites: vec!["TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384".to_owned()],
+            ..Default::default()
+        };
+        assert!(https_server_config(&options, certificate.clone()).is_err());
+        let options = HttpsOptions {
+            cipher_suites: vec!["TLS_RSA_WITH_RC4_128_MD5".to_owned()],
+            ..Default::default()
+        };
+        assert!(https_server_config(&options, certificate).is_err());
+    }
+}
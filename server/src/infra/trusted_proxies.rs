@@ -0,0 +1,277 @@
+use actix_http::header::HeaderMap;
+use actix_web::{web, HttpRequest};
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+/// The reverse proxies allowed to report the client IP and protocol, through the `Forwarded` or
+/// `X-Forwarded-For`/`X-Forwarded-Proto` headers. These headers are ignored when the request
+/// doesn't come from one of them.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+    /// Whether the server listens with TLS, for the requests without a trusted protocol header.
+    https: bool,
+    /// Whether the server listens on a Unix socket, where the peer is a co-located proxy.
+    unix_socket: bool,
+}
+
+fn parse_network(network: &str) -> Result<IpNet> {
+    network
+        .parse::<IpNet>()
+        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| anyhow!("Invalid trusted proxy: {}", network))
+}
+
+// Parses a node of the forwarding headers: an IP, optionally with a port, or an IPv6 in brackets.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| node.parse::<IpAddr>().ok())
+        })
+}
+
+// The values of the `key` parameter in the `Forwarded` headers, from the first proxy to the last.
+fn forwarded_parameters<'a>(headers: &'a HeaderMap, key: &'a str) -> Vec<&'a str> {
+    headers
+        .get_all("forwarded")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case(key)
+                    .then(|| value.trim().trim_matches('"'))
+            })
+        })
+        .collect()
+}
+
+fn x_forwarded_values<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+    headers
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+impl TrustedProxies {
+    /// Each entry is an IP or a network, e.g. "10.0.0.0/8".
+    pub fn new(networks: &[String]) -> Result<Self> {
+        Ok(Self {
+            networks: networks
+                .iter()
+                .map(|network| parse_network(network))
+                .collect::<Result<_>>()?,
+            https: false,
+            unix_socket: false,
+        })
+    }
+
+    /// The same proxies, for the HTTPS server.
+    pub fn for_https(&self) -> Self {
+        Self {
+            https: true,
+            ..self.clone()
+        }
+    }
+
+    /// The same proxies, for the Unix socket server. Its peers are always trusted.
+    pub fn for_unix_socket(&self) -> Self {
+        Self {
+            unix_socket: true,
+            ..self.clone()
+        }
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    fn is_trusted_peer(&self, request: &HttpRequest) -> bool {
+        match request.peer_addr() {
+            Some(address) => self.is_trusted(&address.ip()),
+            None => self.unix_socket,
+        }
+    }
+
+    /// The IP of the client. Each trusted proxy appends the address it received the request from
+    /// to the forwarding headers, so they are read from the end until an untrusted address.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let headers = request.headers();
+        let mut forwarded_for = forwarded_parameters(headers, "for");
+        if forwarded_for.is_empty() {
+            forwarded_for = x_forwarded_values(headers, "x-forwarded-for");
+        }
+        let mut nodes = forwarded_for.into_iter().rev();
+        let mut ip = match request.peer_addr() {
+            Some(address) => address.ip(),
+            None if self.unix_socket => parse_node(nodes.next()?)?,
+            None => return None,
+        };
+        for node in nodes {
+            if !self.is_trusted(&ip) {
+                break;
+            }
+            match parse_node(node) {
+                Some(forwarded_ip) => ip = forwarded_ip,
+                // Obfuscated or unknown node, nothing more can be learnt.
+                None => break,
+            }
+        }
+        Some(ip)
+    }
+
+    /// The protocol the client used, "http" or "https", as reported by a trusted proxy or
+    /// otherwise the one of the server.
+    pub fn scheme(&self, request: &HttpRequest) -> &'static str {
+        let headers = request.headers();
+        let forwarded_scheme = || {
+            forwarded_parameters(headers, "proto")
+                .first()
+                .copied()
+                .or_else(|| {
+                    x_forwarded_values(headers, "x-forwarded-proto")
+                        .first()
+                        .copied()
+                })
+        };
+        match self
+            .is_trusted_peer(request)
+            .then(forwarded_scheme)
+            .flatten()
+        {
+            Some(scheme) if scheme.eq_ignore_ascii_case("https") => "https",
+            Some(_) => "http",
+            None if self.https => "https",
+            None => "http",
+        }
+    }
+}
+
+/// The IP of the client, according to the trusted proxies of the server.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    match request.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted_proxies) => trusted_proxies.client_ip(request),
+        None => request.peer_addr().map(|address| address.ip()),
+    }
+}
+
+/// The protocol the client used, according to the trusted proxies of the server.
+pub fn scheme(request: &HttpRequest) -> &'static str {
+    match request.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted_proxies) => trusted_proxies.scheme(request),
+        None => "http",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn trusted_proxies() -> TrustedProxies {
+        TrustedProxies::new(&["10.0.0.0/8".to_owned(), "192.168.1.1".to_owned()]).unwrap()
+    }
+
+    fn request(peer: &str, headers: &[(&'static str, &'static str)]) -> HttpRequest {
+        let mut request = TestRequest::default().peer_addr(peer.parse().unwrap());
+        for header in headers {
+            request = request.append_header(*header);
+        }
+        request.to_http_request()
+    }
+
+    #[test]
+    fn test_invalid_trusted_proxy() {
+        assert!(TrustedProxies::new(&["10.0.0.0/33".to_owned()]).is_err());
+        assert!(TrustedProxies::new(&["proxy.example.com".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_client_ip_from_untrusted_peer() {
+        let request = request(
+            "203.0.113.5:1234",
+            &[
+                ("X-Forwarded-For", "198.51.100.1"),
+                ("X-Forwarded-Proto", "https"),
+            ],
+        );
+        assert_eq!(
+            trusted_proxies().client_ip(&request),
+            Some("203.0.113.5".parse().unwrap())
+        );
+        assert_eq!(trusted_proxies().scheme(&request), "http");
+    }
+
+    #[test]
+    fn test_client_ip_from_x_forwarded_for() {
+        // The client set a fake address, before going through two trusted proxies.
+        let request = request(
+            "192.168.1.1:1234",
+            &[
+                ("X-Forwarded-For", "1.2.3.4, 198.51.100.1"),
+                ("X-Forwarded-For", "10.1.2.3"),
+                ("X-Forwarded-Proto", "https"),
+            ],
+        );
+        assert_eq!(
+            trusted_proxies().client_ip(&request),
+            Some("198.51.100.1".parse().unwrap())
+        );
+        assert_eq!(trusted_proxies().scheme(&request), "https");
+    }
+
+    #[test]
+    fn test_client_ip_from_forwarded() {
+        let request = request(
+            "10.0.0.2:1234",
+            &[
+                (
+                    "Forwarded",
+                    r#"for="[2001:db8::1]:4711";proto=HTTPS, for=10.0.0.3"#,
+                ),
+                ("X-Forwarded-For", "1.2.3.4"),
+            ],
+        );
+        assert_eq!(
+            trusted_proxies().client_ip(&request),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(trusted_proxies().scheme(&request), "https");
+    }
+
+    #[test]
+    fn test_client_ip_from_unix_socket() {
+        let request = TestRequest::default()
+            .append_header(("X-Forwarded-For", "1.2.3.4, 10.0.0.3"))
+            .append_header(("X-Forwarded-Proto", "https"))
+            .to_http_request();
+        let trusted_proxies = trusted_proxies().for_unix_socket();
+        assert_eq!(
+            trusted_proxies.client_ip(&request),
+            Some("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(trusted_proxies.scheme(&request), "https");
+        assert_eq!(TrustedProxies::default().client_ip(&request), None);
+    }
+
+    #[test]
+    fn test_client_ip_with_obfuscated_node() {
+        let request = request(
+            "10.0.0.2:1234",
+            &[("Forwarded", "for=_hidden, for=10.0.0.3")],
+        );
+        assert_eq!(
+            trusted_proxies().client_ip(&request),
+            Some("10.0.0.3".parse().unwrap())
+        );
+    }
+}
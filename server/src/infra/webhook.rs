@@ -0,0 +1,140 @@
+use crate::infra::{change_events::ChangeEvent, configuration::WebhookOptions};
+use actix::prelude::{Actor, AsyncContext, Context};
+use anyhow::Result;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Header carrying the hex-encoded HMAC-SHA256 of the body, keyed by the webhook secret, in the
+/// form "sha256=<signature>".
+pub const SIGNATURE_HEADER: &str = "X-LLDAP-Signature";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Computes the hex-encoded HMAC-SHA256 of the body.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn post(client: &reqwest::Client, url: &str, signature: &str, body: &str) -> Result<()> {
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+        .timeout(REQUEST_TIMEOUT)
+        .body(body.to_owned())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// Delivers the event to the webhook, retrying with an exponential backoff. Gives up (and drops the
+// event for this webhook) after `max_retries` failed retries.
+async fn deliver(client: &reqwest::Client, webhook: &WebhookOptions, event_id: i32, body: &str) {
+    let signature = sign(webhook.secret.unsecure(), body.as_bytes());
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 0..=webhook.max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+        }
+        match post(client, &webhook.url, &signature, body).await {
+            Ok(()) => {
+                debug!("Delivered event {} to {}", event_id, webhook.url);
+                return;
+            }
+            Err(e) => warn!(
+                "Could not deliver event {} to {} (attempt {}): {:#}",
+                event_id,
+                webhook.url,
+                attempt + 1,
+                e
+            ),
+        }
+    }
+    error!(
+        "Giving up on delivering event {} to {}",
+        event_id, webhook.url
+    );
+}
+
+/// POSTs each event of the bus to all the configured webhooks, in order.
+pub struct WebhookDispatcher {
+    events: Option<broadcast::Receiver<ChangeEvent>>,
+    webhooks: Vec<WebhookOptions>,
+}
+
+impl Actor for WebhookDispatcher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Webhook dispatcher started");
+        let events = self
+            .events
+            .take()
+            .expect("The dispatcher can only be started once");
+        let future = actix::fut::wrap_future::<_, Self>(Self::run(events, self.webhooks.clone()));
+        context.spawn(future);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        info!("Webhook dispatcher stopped");
+    }
+}
+
+impl WebhookDispatcher {
+    pub fn new(events: broadcast::Receiver<ChangeEvent>, webhooks: Vec<WebhookOptions>) -> Self {
+        Self {
+            events: Some(events),
+            webhooks,
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn run(mut events: broadcast::Receiver<ChangeEvent>, webhooks: Vec<WebhookOptions>) {
+        let client = reqwest::Client::new();
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    error!("The webhooks are too slow, {} events were dropped", count);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let body = serde_json::to_string(&event).unwrap();
+            futures::future::join_all(
+                webhooks
+                    .iter()
+                    .map(|webhook| deliver(&client, webhook, event.id, &body)),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // Test case 2 of RFC 4231.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -2,21 +2,62 @@
 #![forbid(non_ascii_idents)]
 #![allow(clippy::nonstandard_macro_braces)]
 
-use std::time::Duration;
+use std::{
+    io::Write,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use crate::{
     domain::{
-        handler::{CreateUserRequest, GroupBackendHandler, GroupRequestFilter, UserBackendHandler},
+        handler::{
+            AccountLockoutBackendHandler, AuditActor, CreateUserRequest, GroupBackendHandler,
+            GroupRequestFilter, UserBackendHandler,
+        },
+        ldap::utils::LdapInfo,
+        security_events,
         sql_backend_handler::SqlBackendHandler,
+        sql_backup,
+        sql_migrations::{self, LAST_SCHEMA_VERSION},
         sql_opaque_handler::register_password,
+        sql_tables::SchemaVersion,
+        types::{AuditSource, UserId},
+    },
+    infra::{
+        acme::{AcmeChallenges, AcmeManager},
+        api_client, bootstrap,
+        change_events::{self, ChangeNotifier},
+        cli::*,
+        config_reload::{self, ConfigReloader},
+        configuration::Configuration,
+        database,
+        db_cleaner::{
+            Flush, PasswordExpiryWarnings, Scheduler, SchedulerStatus, StaleAccountLifecycle,
+        },
+        healthcheck::{self, HealthChecker, HealthStatus},
+        http_rate_limiter::HttpRateLimiter,
+        import, ldap_migration,
+        ldap_rate_limiter::LdapRateLimiter,
+        ldif,
+        logging::LogLevelHandle,
+        mail,
+        metrics::Metrics,
+        read_only::ReadOnlyMode,
+        secrets_provider,
+        security_notifications::SecurityNotifier,
+        systemd::{self, ActivatedSockets},
+        tcp_backend_handler::TcpBackendHandler,
+        tls_certificate::ReloadableCertificate,
+        webhook::WebhookDispatcher,
     },
-    infra::{cli::*, configuration::Configuration, db_cleaner::Scheduler, healthcheck, mail},
 };
-use actix::Actor;
+use actix::{Actor, Addr};
 use actix_server::ServerBuilder;
 use anyhow::{anyhow, Context, Result};
 use futures_util::TryFutureExt;
-use sea_orm::Database;
+use sea_orm::DatabaseConnection;
+use secstr::SecUtf8;
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 mod domain;
@@ -67,21 +108,123 @@ async fn ensure_group_exists(handler: &SqlBackendHandler, group_name: &str) -> R
 }
 
 #[instrument(skip_all)]
-async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
-    info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
+fn load_certificate(
+    config: &Configuration,
+    cert_file: &str,
+    key_file: &str,
+) -> Result<Arc<ReloadableCertificate>> {
+    if config.acme_options.enabled {
+        Ok(Arc::new(
+            ReloadableCertificate::new(cert_file, key_file).unwrap_or_else(|e| {
+                warn!("{:#}, waiting for the ACME certificate", e);
+                ReloadableCertificate::new_pending(cert_file, key_file)
+            }),
+        ))
+    } else {
+        Ok(Arc::new(
+            ReloadableCertificate::new(cert_file, key_file)
+                .context("while setting up the SSL certificate")?,
+        ))
+    }
+}
+
+/// Opens a pool of connections to the database, reporting the query durations to the metrics.
+async fn connect_to_database(
+    config: &Configuration,
+    database_url: &str,
+    metrics: &Metrics,
+) -> Result<DatabaseConnection> {
+    let mut sql_pool = database::connect(&config.database_options, database_url).await?;
+    let db_query_duration = metrics.db_query_duration().clone();
+    sql_pool.set_metric_callback(move |info| db_query_duration.observe(info.elapsed.as_secs_f64()));
+    Ok(sql_pool)
+}
+
+/// A replica only runs the LDAP server, on top of a database replicated from the primary's: it
+/// never writes to it, and forwards the binds and the writes to the primary.
+async fn set_up_replica_server(mut config: Configuration) -> Result<ServerBuilder> {
+    info!(
+        "Starting LLDAP version {} as a replica of {}",
+        env!("CARGO_PKG_VERSION"),
+        config.replica_options.primary_ldap_url
+    );
+
+    secrets_provider::apply_secrets(&mut config).await?;
 
-    let sql_pool = {
-        let mut sql_opt = sea_orm::ConnectOptions::new(config.database_url.clone());
-        sql_opt
-            .max_connections(5)
-            .sqlx_logging(true)
-            .sqlx_logging_level(log::LevelFilter::Debug);
-        Database::connect(sql_opt).await?
+    let metrics = Metrics::new().context("while registering the metrics")?;
+    let sql_pool = connect_to_database(&config, &config.database_url, &metrics).await?;
+    // The migrations are run by the primary, and replicated.
+    match sql_migrations::get_schema_version(&sql_pool).await {
+        Some(version) if version == LAST_SCHEMA_VERSION => {}
+        version => anyhow::bail!(
+            "The replicated database has the schema version {:?} instead of {:?}, the primary \
+             and the replica must run the same version",
+            version,
+            LAST_SCHEMA_VERSION
+        ),
+    }
+    // No cache: it wouldn't see the replicated changes.
+    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool);
+    let ldap_rate_limiter = LdapRateLimiter::new(config.ldap_rate_limit_options.clone());
+    let ldaps_certificate = if config.ldaps_options.enabled {
+        Some(load_certificate(
+            &config,
+            &config.ldaps_options.cert_file,
+            &config.ldaps_options.key_file,
+        )?)
+    } else {
+        None
     };
+    let mut sockets = ActivatedSockets::from_env()?;
+    let shutdown = CancellationToken::new();
+    actix_rt::spawn(close_connections_on_sigterm(shutdown.clone()));
+    let server_builder = infra::ldap_server::build_ldap_server(
+        &config,
+        backend_handler,
+        ldap_rate_limiter,
+        metrics,
+        ReadOnlyMode::new(config.read_only),
+        ldaps_certificate,
+        shutdown,
+        &mut sockets,
+        actix_server::Server::build().backlog(config.runtime_options.backlog),
+    )
+    .context("while binding the LDAP server")?;
+    sockets.warn_unused();
+    Ok(server_builder)
+}
+
+async fn set_up_server(
+    mut config: Configuration,
+    opts: RunOpts,
+    log_level_handle: LogLevelHandle,
+) -> Result<(ServerBuilder, Option<Addr<Scheduler>>)> {
+    if config.replica_options.enabled {
+        return Ok((set_up_replica_server(config).await?, None));
+    }
+    info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
+
+    secrets_provider::apply_secrets(&mut config).await?;
+
+    let metrics = Metrics::new().context("while registering the metrics")?;
+    let sql_pool = connect_to_database(&config, &config.database_url, &metrics).await?;
     domain::sql_tables::init_table(&sql_pool)
         .await
         .context("while creating the tables")?;
-    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+    let mut backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+    if let Some(read_replica_url) = &config.database_read_replica_url {
+        let read_replica = connect_to_database(&config, read_replica_url, &metrics)
+            .await
+            .context("while connecting to the read replica")?;
+        backend_handler = backend_handler.with_read_replica(read_replica);
+        actix_rt::spawn(backend_handler.clone().monitor_read_replica());
+    }
+    let mut backend_handler = backend_handler.with_cache(&config.cache_options);
+    let security_events = security_events::new_bus();
+    if config.security_notifications.any_enabled() {
+        backend_handler = backend_handler.with_security_events(security_events.clone());
+    }
+    let lookup_cache = backend_handler.lookup_cache().cloned();
     ensure_group_exists(&backend_handler, "lldap_admin").await?;
     ensure_group_exists(&backend_handler, "lldap_password_manager").await?;
     ensure_group_exists(&backend_handler, "lldap_strict_readonly").await?;
@@ -92,42 +235,198 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
             .map_err(|e| anyhow!("Error setting up admin login/account: {:#}", e))
             .context("while creating the admin user")?;
     }
+    if let Some(file) = &config.bootstrap_options.file {
+        bootstrap::run(
+            &backend_handler,
+            file,
+            config.bootstrap_options.prune,
+            &config.ldap_user_dn,
+        )
+        .await
+        .context("while bootstrapping the directory")?;
+    }
+    let mail_options = Arc::new(RwLock::new(config.smtp_options.clone()));
+    actix_rt::spawn(secrets_provider::refresh_secrets(
+        config.secrets_provider.clone(),
+        mail_options.clone(),
+    ));
+    if config.security_notifications.any_enabled() {
+        SecurityNotifier::new(
+            backend_handler.clone(),
+            security_events.subscribe(),
+            config.security_notifications.clone(),
+            mail_options.clone(),
+            config.http_url.clone(),
+        )
+        .start();
+    }
+    let ldap_rate_limiter = LdapRateLimiter::new(config.ldap_rate_limit_options.clone());
+    let http_rate_limiter = HttpRateLimiter::new(config.http_rate_limit_options.clone());
+    let ldaps_certificate = if config.ldaps_options.enabled {
+        Some(load_certificate(
+            &config,
+            &config.ldaps_options.cert_file,
+            &config.ldaps_options.key_file,
+        )?)
+    } else {
+        None
+    };
+    let https_certificate = if config.https_options.enabled {
+        Some(load_certificate(
+            &config,
+            &config.https_options.cert_file,
+            &config.https_options.key_file,
+        )?)
+    } else {
+        None
+    };
+    let certificates = ldaps_certificate
+        .iter()
+        .chain(https_certificate.iter())
+        .cloned()
+        .collect::<Vec<_>>();
+    let acme_challenges = AcmeChallenges::default();
+    if config.acme_options.enabled {
+        AcmeManager::new(
+            config.acme_options.clone(),
+            &config.ldaps_options,
+            acme_challenges.clone(),
+            certificates.clone(),
+        )
+        .start();
+    }
+    let scheduler_status = SchedulerStatus::default();
+    let health_checker = HealthChecker::new(
+        sql_pool.clone(),
+        scheduler_status.clone(),
+        certificates.clone(),
+        config.certificate_expiry_warning_days,
+    );
+    let config_reloader = ConfigReloader::new(
+        opts,
+        log_level_handle,
+        mail_options.clone(),
+        ldap_rate_limiter.clone(),
+        http_rate_limiter.clone(),
+        certificates,
+    );
+    actix_rt::spawn(config_reload::reload_on_sighup(config_reloader.clone()));
+    let mut sockets = ActivatedSockets::from_env()?;
+    let shutdown = CancellationToken::new();
+    actix_rt::spawn(close_connections_on_sigterm(shutdown.clone()));
+    let read_only = ReadOnlyMode::new(config.read_only);
     let server_builder = infra::ldap_server::build_ldap_server(
         &config,
         backend_handler.clone(),
-        actix_server::Server::build(),
+        ldap_rate_limiter,
+        metrics.clone(),
+        read_only.clone(),
+        ldaps_certificate,
+        shutdown,
+        &mut sockets,
+        // The backlog applies to the sockets bound afterwards.
+        actix_server::Server::build().backlog(config.runtime_options.backlog),
     )
     .context("while binding the LDAP server")?;
     infra::jwt_sql_tables::init_table(&sql_pool).await?;
-    let server_builder =
-        infra::tcp_server::build_tcp_server(&config, backend_handler, server_builder)
-            .await
-            .context("while binding the TCP server")?;
+    let change_events = change_events::new_bus();
+    if !config.webhooks.is_empty() {
+        WebhookDispatcher::new(change_events.subscribe(), config.webhooks.clone()).start();
+    }
+    ChangeNotifier::new(backend_handler.clone(), change_events.clone()).start();
+    let server_builder = infra::tcp_server::build_tcp_server(
+        &config,
+        backend_handler.clone(),
+        change_events,
+        metrics,
+        http_rate_limiter,
+        mail_options.clone(),
+        config_reloader,
+        acme_challenges,
+        health_checker,
+        scheduler_status.clone(),
+        read_only,
+        https_certificate,
+        &mut sockets,
+        server_builder,
+    )
+    .await
+    .context("while binding the TCP server")?;
+    sockets.warn_unused();
     // Run every hour.
-    let scheduler = Scheduler::new("0 0 * * * * *", sql_pool);
-    scheduler.start();
-    Ok(server_builder)
+    let scheduler = Scheduler::new(
+        &config.maintenance_options,
+        config.avatar_options.clone(),
+        PasswordExpiryWarnings {
+            policy: config.password_policy.clone(),
+            mail_options: mail_options.clone(),
+            server_url: config.http_url.clone(),
+        },
+        StaleAccountLifecycle {
+            options: config.stale_accounts.clone(),
+            mail_options,
+            server_url: config.http_url.clone(),
+            backend_handler,
+        },
+        sql_pool,
+        lookup_cache,
+        scheduler_status,
+    );
+    Ok((server_builder, Some(scheduler.start())))
 }
 
-async fn run_server(config: Configuration) -> Result<()> {
-    set_up_server(config)
-        .await?
-        .workers(1)
-        .run()
-        .await
-        .context("while starting the server")?;
+/// Closes the LDAP connections when the server is asked to stop: the clients usually keep them
+/// open, which would hold the graceful shutdown until the end of the grace period.
+async fn close_connections_on_sigterm(shutdown: CancellationToken) {
+    use actix_rt::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminations) => {
+            terminations.recv().await;
+            shutdown.cancel();
+        }
+        Err(e) => error!("Could not listen to SIGTERM: {:#}", e),
+    }
+}
+
+async fn run_server(
+    config: Configuration,
+    opts: RunOpts,
+    log_level_handle: LogLevelHandle,
+) -> Result<()> {
+    let grace_period = config.shutdown_grace_period_seconds;
+    let runtime_options = config.runtime_options.clone();
+    let (server_builder, scheduler) = set_up_server(config, opts, log_level_handle).await?;
+    let mut server_builder = server_builder
+        .maxconn(runtime_options.max_connections_per_worker)
+        .shutdown_timeout(grace_period);
+    if runtime_options.workers != 0 {
+        server_builder = server_builder.workers(runtime_options.workers);
+    }
+    let server = server_builder.run();
+    systemd::notify_ready();
+    server.await.context("while starting the server")?;
+    systemd::notify_stopping();
+    // Lets the DB cleanup in progress complete.
+    if let Some(scheduler) = scheduler {
+        let flush = tokio::time::timeout(Duration::from_secs(grace_period), scheduler.send(Flush));
+        if !matches!(flush.await, Ok(Ok(()))) {
+            warn!("The database cleanup was interrupted by the shutdown");
+        }
+    }
     Ok(())
 }
 
 fn run_server_command(opts: RunOpts) -> Result<()> {
     debug!("CLI: {:#?}", &opts);
 
-    let config = infra::configuration::init(opts)?;
-    infra::logging::init(&config)?;
+    let config = infra::configuration::init(opts.clone())?;
+    let log_level_handle = infra::logging::init(&config)?;
 
     actix::run(
-        run_server(config).unwrap_or_else(|e| error!("Could not bring up the servers: {:#}", e)),
+        run_server(config, opts, log_level_handle)
+            .unwrap_or_else(|e| error!("Could not bring up the servers: {:#}", e)),
     )?;
+    infra::logging::shutdown();
 
     info!("End.");
     Ok(())
@@ -149,34 +448,344 @@ fn send_test_email_command(opts: TestEmailOpts) -> Result<()> {
     Ok(())
 }
 
-fn run_healthcheck(opts: RunOpts) -> Result<()> {
-    debug!("CLI: {:#?}", &opts);
-    let config = infra::configuration::init(opts)?;
+fn backup_command(opts: BackupOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
     infra::logging::init(&config)?;
 
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()?;
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        let backup = sql_backup::create_backup(&sql_pool).await?;
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&opts.output_file)
+            .with_context(|| format!("while creating {}", opts.output_file))?;
+        serde_json::to_writer(std::io::BufWriter::new(file), &backup)
+            .with_context(|| format!("while writing the backup to {}", opts.output_file))?;
+        info!(
+            "Backed up {} users and {} groups to {}",
+            backup.users.len(),
+            backup.groups.len(),
+            opts.output_file
+        );
+        Ok(())
+    })
+}
 
-    use tokio::time::timeout;
-    let delay = Duration::from_millis(3000);
-    let (ldap, ldaps, api) = runtime.block_on(async {
-        tokio::join!(
-            timeout(delay, healthcheck::check_ldap(config.ldap_port)),
-            timeout(delay, healthcheck::check_ldaps(&config.ldaps_options)),
-            timeout(delay, healthcheck::check_api(config.http_port)),
+fn restore_command(opts: RestoreOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    let content = std::fs::read(&opts.input_file)
+        .with_context(|| format!("while reading {}", opts.input_file))?;
+    let backup: sql_backup::Backup = serde_json::from_slice(&content)
+        .with_context(|| format!("while parsing the backup {}", opts.input_file))?;
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        domain::sql_tables::init_table(&sql_pool)
+            .await
+            .context("while creating the tables")?;
+        sql_backup::restore_backup(&sql_pool, backup, opts.replace).await?;
+        info!("Backup restored from {}", opts.input_file);
+        Ok(())
+    })
+}
+
+fn export_ldif_command(opts: ExportLdifOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool);
+        let ldap_info = LdapInfo::new(
+            config.ldap_base_dn.clone(),
+            &config.ldap_base_dn_aliases,
+            config.ignored_user_attributes.clone(),
+            config.ignored_group_attributes.clone(),
+            config.membership_options.clone(),
+        )
+        .with_active_directory(&config.active_directory_options);
+        let file = std::fs::File::create(&opts.output_file)
+            .with_context(|| format!("while creating {}", opts.output_file))?;
+        let mut out = std::io::BufWriter::new(file);
+        let (users, groups) = ldif::export_ldif(
+            &backend_handler,
+            &ldap_info,
+            opts.operational_attributes,
+            &mut out,
         )
-    });
+        .await?;
+        out.flush()?;
+        info!(
+            "Exported {} users and {} groups to {}",
+            users, groups, opts.output_file
+        );
+        Ok(())
+    })
+}
+
+fn import_command(opts: ImportOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    if opts.batch_size == 0 {
+        anyhow::bail!("The batch size must be positive");
+    }
+    let format = match &opts.format {
+        Some(format) => format.parse::<import::ImportFormat>()?,
+        None => import::ImportFormat::from_file_name(&opts.input_file).ok_or_else(|| {
+            anyhow!(
+                "Cannot guess the format of {}, use --format",
+                opts.input_file
+            )
+        })?,
+    };
+    let columns = import::CsvColumns {
+        user_id: opts.csv_user_id_column.clone(),
+        email: opts.csv_email_column.clone(),
+        display_name: opts.csv_display_name_column.clone(),
+        first_name: opts.csv_first_name_column.clone(),
+        last_name: opts.csv_last_name_column.clone(),
+        groups: opts.csv_groups_column.clone(),
+        password_hash: opts.csv_password_hash_column.clone(),
+    };
+    let content = std::fs::read_to_string(&opts.input_file)
+        .with_context(|| format!("while reading {}", opts.input_file))?;
+    let data = import::read(&content, format, &columns)
+        .with_context(|| format!("while parsing {}", opts.input_file))?;
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool)
+            .with_audit_actor(AuditActor::system(AuditSource::Cli));
+        let report = import::import(&backend_handler, data, opts.batch_size, opts.dry_run).await?;
+        for error in &report.errors {
+            warn!(
+                "{}:{}: {}: {}",
+                opts.input_file, error.line, error.id, error.message
+            );
+        }
+        info!(
+            "{}{} users and {} groups created, {} memberships added, {} errors",
+            if opts.dry_run { "Dry run: " } else { "" },
+            report.created_users.len(),
+            report.created_groups.len(),
+            report.added_memberships,
+            report.errors.len()
+        );
+        Ok(())
+    })
+}
+
+fn migrate_from_ldap_command(opts: MigrateFromLdapOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    let options = ldap_migration::LdapMigrationOptions {
+        url: opts.url.clone(),
+        bind_dn: opts.bind_dn.clone(),
+        bind_password: opts.bind_password.clone(),
+        base_dn: opts.base_dn.clone(),
+        user_filter: opts.user_filter.clone(),
+        group_filter: opts.group_filter.clone(),
+        timeout_seconds: 30,
+    };
+    actix_rt::System::new().block_on(async {
+        let data = ldap_migration::fetch_directory(&options).await?;
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        domain::sql_tables::init_table(&sql_pool)
+            .await
+            .context("while creating the tables")?;
+        infra::jwt_sql_tables::init_table(&sql_pool).await?;
+        let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool)
+            .with_audit_actor(AuditActor::system(AuditSource::Cli));
+        let report = import::import(
+            &backend_handler,
+            data,
+            import::DEFAULT_BATCH_SIZE,
+            opts.dry_run,
+        )
+        .await?;
+        for error in &report.errors {
+            warn!("{}: {}", error.id, error.message);
+        }
+        info!(
+            "{}{} users and {} groups created, {} memberships added, {} errors",
+            if opts.dry_run { "Dry run: " } else { "" },
+            report.created_users.len(),
+            report.created_groups.len(),
+            report.added_memberships,
+            report.errors.len()
+        );
+        if opts.send_password_emails && !opts.dry_run {
+            let validity = chrono::Duration::days(opts.password_link_validity_days);
+            for user_id in &report.created_users {
+                if let Err(e) =
+                    send_password_setup_email(&backend_handler, &config, user_id, validity).await
+                {
+                    error!("Could not email {}: {:#}", user_id, e);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+async fn send_password_setup_email(
+    backend_handler: &SqlBackendHandler,
+    config: &Configuration,
+    user_id: &UserId,
+    validity: chrono::Duration,
+) -> Result<()> {
+    let user = backend_handler.get_user_details(user_id).await?;
+    let token = backend_handler
+        .start_password_setup(user_id, validity)
+        .await?
+        .ok_or_else(|| anyhow!("Unknown user"))?;
+    mail::send_password_setup_email(
+        user.display_name.as_deref().unwrap_or(user_id.as_str()),
+        &user.email,
+        user.locale.as_deref(),
+        &token,
+        validity.num_days(),
+        &config.http_url,
+        &config.smtp_options,
+    )
+    .await
+}
+
+fn reset_admin_password_command(opts: ResetAdminPasswordOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
 
-    let mut failure = false;
-    [ldap, ldaps, api]
-        .into_iter()
-        .filter_map(Result::err)
-        .for_each(|e| {
-            failure = true;
-            error!("{:#}", e)
-        });
-    std::process::exit(i32::from(failure))
+    let user_id = opts
+        .user
+        .as_deref()
+        .map(UserId::new)
+        .unwrap_or_else(|| config.ldap_user_dn.clone());
+    let password = opts
+        .password
+        .clone()
+        .map(SecUtf8::from)
+        .unwrap_or_else(|| config.ldap_user_pass.clone());
+    if password.unsecure().len() < 8 {
+        anyhow::bail!("Minimum password length is 8 characters");
+    }
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool)
+            .with_audit_actor(AuditActor::system(AuditSource::Cli));
+        backend_handler
+            .get_user_details(&user_id)
+            .await
+            .with_context(|| format!("while looking up the user {}", user_id))?;
+        register_password(&backend_handler, &user_id, &password)
+            .await
+            .context("while setting the password")?;
+        // Locked out after too many attempts with the lost password.
+        backend_handler.unlock_account(&user_id).await?;
+        info!("The password of {} was reset", user_id);
+        Ok(())
+    })
+}
+
+fn check_config_command(opts: CheckConfigOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    let failures =
+        actix_rt::System::new().block_on(infra::config_check::check_config(&config, opts.online));
+    if failures != 0 {
+        anyhow::bail!("{} configuration check(s) failed", failures);
+    }
+    Ok(())
+}
+
+fn api_client_command<F: std::future::Future<Output = Result<()>>>(command: F) -> Result<()> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(command)
+}
+
+fn backup_sqlite_command(opts: BackupSqliteOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        database::backup_sqlite(&sql_pool, &opts.output_file).await?;
+        info!("Database copied to {}", opts.output_file);
+        Ok(())
+    })
+}
+
+fn migrate_command(opts: MigrateOpts) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    actix_rt::System::new().block_on(async {
+        let sql_pool = database::connect(&config.database_options, &config.database_url)
+            .await
+            .context("while connecting to the database")?;
+        let current = sql_migrations::get_schema_version(&sql_pool)
+            .await
+            .ok_or_else(|| anyhow!("The database has no schema yet, it is created on startup"))?;
+        let target = opts.to.map(SchemaVersion).unwrap_or(LAST_SCHEMA_VERSION);
+        let steps = sql_migrations::migration_plan(current, target)?;
+        if steps.is_empty() {
+            info!("The schema is already at version {}", current.0);
+        }
+        for step in steps {
+            if opts.dry_run {
+                info!("Would {}", step);
+            } else {
+                info!("Applying the {}", step);
+                sql_migrations::apply_migration_step(&sql_pool, step)
+                    .await
+                    .with_context(|| format!("during the {}", step))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn run_healthcheck(opts: HealthCheckOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let config = infra::configuration::init(opts.clone())?;
+    // The logs would be mixed with the JSON report on the standard output.
+    if opts.output == HealthCheckOutput::Text {
+        infra::logging::init(&config)?;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let report = runtime.block_on(healthcheck::check_endpoints(&config));
+    let exit_code = match opts.output {
+        HealthCheckOutput::Text => {
+            report.log();
+            // A degraded server is still healthy for the container health checks.
+            i32::from(report.status == HealthStatus::Error)
+        }
+        HealthCheckOutput::Json => {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            report.exit_code()
+        }
+    };
+    std::process::exit(exit_code)
 }
 
 fn main() -> Result<()> {
@@ -186,5 +795,16 @@ fn main() -> Result<()> {
         Command::Run(opts) => run_server_command(opts),
         Command::HealthCheck(opts) => run_healthcheck(opts),
         Command::SendTestEmail(opts) => send_test_email_command(opts),
+        Command::Backup(opts) => backup_command(opts),
+        Command::Restore(opts) => restore_command(opts),
+        Command::ExportLdif(opts) => export_ldif_command(opts),
+        Command::Import(opts) => import_command(opts),
+        Command::MigrateFromLdap(opts) => migrate_from_ldap_command(opts),
+        Command::ResetAdminPassword(opts) => reset_admin_password_command(opts),
+        Command::CheckConfig(opts) => check_config_command(opts),
+        Command::User(command) => api_client_command(api_client::run_user_command(&command)),
+        Command::Group(command) => api_client_command(api_client::run_group_command(&command)),
+        Command::BackupSqlite(opts) => backup_sqlite_command(opts),
+        Command::Migrate(opts) => migrate_command(opts),
     }
 }